@@ -0,0 +1,88 @@
+//! `--api-allow-cidr`: answer only clients from the listed networks. The
+//! check wraps the whole server (API, OpenAPI document and web UI) and runs
+//! before any handler, so a rejected request never reaches the database.
+//! It sees the TCP peer; behind a reverse proxy that is the proxy.
+
+use std::net::IpAddr;
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use tracing::warn;
+
+use crate::api::models::ErrorResponse;
+use crate::model::ExcludeList;
+
+/// Networks allowed to call the server, as app data.
+#[derive(Clone)]
+pub struct ClientAllowlist(pub ExcludeList);
+
+impl ClientAllowlist {
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        // A dual-stack listener reports IPv4 clients as ::ffff:a.b.c.d.
+        self.0.contains(ip.to_canonical())
+    }
+}
+
+/// Middleware answering 403 `CLIENT_NOT_ALLOWED` to peers outside the
+/// [`ClientAllowlist`] app data; without it every client passes.
+pub async fn check_client(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    if let Some(allowlist) = req.app_data::<web::Data<ClientAllowlist>>() {
+        let peer = req.peer_addr().map(|addr| addr.ip());
+        if !peer.is_some_and(|ip| allowlist.allows(ip)) {
+            warn!(
+                "Rejected {} {} from {}",
+                req.method(),
+                req.path(),
+                peer.map_or("unknown address".to_string(), |ip| ip.to_string())
+            );
+            let response = HttpResponse::Forbidden().json(ErrorResponse {
+                error: "Client address is not allowed".to_string(),
+                code: Some("CLIENT_NOT_ALLOWED".to_string()),
+            });
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware::from_fn, test, App};
+
+    #[actix_web::test]
+    async fn test_clients_outside_the_allowlist_are_rejected() {
+        let allowlist = ClientAllowlist(ExcludeList::parse("10.0.0.0/8\n192.0.2.7").unwrap());
+        assert!(allowlist.allows("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!allowlist.allows("::1".parse().unwrap()));
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(allowlist))
+                .wrap(from_fn(check_client))
+                .route("/api/v1/healthz", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let status = |peer: &str| {
+            let request = test::TestRequest::get()
+                .uri("/api/v1/healthz")
+                .peer_addr(format!("{}:40000", peer).parse().unwrap())
+                .to_request();
+            let app = &app;
+            async move { test::call_service(app, request).await.status().as_u16() }
+        };
+        assert_eq!(status("10.20.30.40").await, 200);
+        assert_eq!(status("192.0.2.7").await, 200);
+        assert_eq!(status("192.0.2.8").await, 403);
+
+        let no_peer = test::TestRequest::get().uri("/api/v1/healthz").to_request();
+        assert_eq!(test::call_service(&app, no_peer).await.status(), 403);
+    }
+}
@@ -0,0 +1,204 @@
+//! Audit trail of state-changing API calls. Every request other than a
+//! GET, HEAD or OPTIONS is written to `audit_log` once it is answered, with
+//! its query string and body, the caller and the response status; reads
+//! and the `/cluster` worker protocol are not recorded.
+//!
+//! The API has no accounts of its own. The principal is the common name of
+//! the client certificate under `--api-client-ca`, else the user an
+//! authenticating reverse proxy names in `X-Forwarded-User`. The header is
+//! only read from peers listed in `--api-trusted-proxy`; from anyone else it
+//! is ignored, since any client can set it.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpRequest};
+use chrono::Utc;
+use serde_json::{Map, Value};
+use tracing::error;
+
+use crate::api::tls::ClientCertificate;
+use crate::dao::{AuditEntry, SqliteDB};
+use crate::model::ExcludeList;
+
+/// Header an authenticating proxy sets to the user it let through.
+pub const PRINCIPAL_HEADER: &str = "X-Forwarded-User";
+
+/// Proxies whose [`PRINCIPAL_HEADER`] is believed, as app data; without it
+/// the header is ignored.
+#[derive(Clone)]
+pub struct TrustedProxies(pub ExcludeList);
+
+/// Who made the request: the subject of its verified client certificate,
+/// or the user a [`TrustedProxies`] peer named in [`PRINCIPAL_HEADER`].
+pub fn principal(req: &HttpRequest) -> Option<String> {
+    if let Some(cert) = req.conn_data::<ClientCertificate>() {
+        return Some(cert.subject.clone());
+    }
+    let proxies = req.app_data::<web::Data<TrustedProxies>>()?;
+    // A dual-stack listener reports IPv4 peers as ::ffff:a.b.c.d.
+    let peer = req.peer_addr()?.ip().to_canonical();
+    if !proxies.0.contains(peer) {
+        return None;
+    }
+    req.headers()
+        .get(PRINCIPAL_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Bodies longer than this are stored cut short.
+const MAX_BODY_CHARS: usize = 4096;
+
+fn audited(req: &ServiceRequest) -> bool {
+    !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        && !req.path().starts_with("/api/v1/cluster/")
+}
+
+/// The query string and body as one JSON object; a JSON body is kept as
+/// JSON, anything else as text.
+fn params(query: &str, body: &[u8]) -> Option<Value> {
+    let mut params = Map::new();
+    if !query.is_empty() {
+        params.insert("query".to_string(), Value::String(query.to_string()));
+    }
+    if !body.is_empty() {
+        let body = serde_json::from_slice(body).unwrap_or_else(|_| {
+            let text = String::from_utf8_lossy(body);
+            Value::String(text.chars().take(MAX_BODY_CHARS).collect())
+        });
+        params.insert("body".to_string(), body);
+    }
+    (!params.is_empty()).then_some(Value::Object(params))
+}
+
+/// Middleware for the `/api/v1` scope. A failure to write the log is
+/// logged and never fails the call itself.
+pub async fn record(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if !audited(&req) {
+        return next.call(req).await;
+    }
+    let db = req
+        .app_data::<web::Data<SqliteDB>>()
+        .map(|db| db.get_ref().clone());
+    let mut entry = AuditEntry {
+        id: 0,
+        created_at: String::new(),
+        method: req.method().to_string(),
+        path: req.path().to_string(),
+        principal: principal(req.request()),
+        client: req.peer_addr().map(|addr| addr.ip().to_string()),
+        params: None,
+        status: 0,
+    };
+
+    // Read the body here and hand the handler a copy of it.
+    let body = match req.extract::<web::Bytes>().await {
+        Ok(body) => body,
+        Err(e) => {
+            entry.params = params(req.query_string(), &[]);
+            save(
+                db.as_ref(),
+                entry,
+                e.as_response_error().status_code().as_u16(),
+            );
+            return Err(e);
+        }
+    };
+    entry.params = params(req.query_string(), &body);
+    req.set_payload(actix_web::dev::Payload::from(body));
+
+    let response = next.call(req).await;
+    let status = match &response {
+        Ok(response) => response.status().as_u16(),
+        Err(e) => e.as_response_error().status_code().as_u16(),
+    };
+    save(db.as_ref(), entry, status);
+    response
+}
+
+fn save(db: Option<&SqliteDB>, mut entry: AuditEntry, status: u16) {
+    entry.created_at = Utc::now().to_rfc3339();
+    entry.status = status;
+    if let Some(db) = db {
+        if let Err(e) = db.record_audit(&entry) {
+            error!(
+                "Failed to record {} {} in the audit log: {}",
+                entry.method, entry.path, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware::from_fn, test, App, HttpResponse};
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn test_state_changing_calls_are_recorded() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let proxies = TrustedProxies(ExcludeList::parse("127.0.0.1").unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(proxies))
+                .service(
+                    web::scope("/api/v1")
+                        .wrap(from_fn(record))
+                        .route("/stats", web::get().to(HttpResponse::Ok))
+                        .route(
+                            "/templates",
+                            web::post().to(|body: web::Json<Value>| async move {
+                                HttpResponse::Created().json(body.into_inner())
+                            }),
+                        ),
+                ),
+        )
+        .await;
+
+        let read = test::TestRequest::get().uri("/api/v1/stats").to_request();
+        assert!(test::call_service(&app, read).await.status().is_success());
+        let create = test::TestRequest::post()
+            .uri("/api/v1/templates?dry_run=1")
+            .peer_addr("127.0.0.1:40000".parse().unwrap())
+            .insert_header((PRINCIPAL_HEADER, "alice"))
+            .set_json(json!({"name": "weekly"}))
+            .to_request();
+        let response = test::call_service(&app, create).await;
+        assert_eq!(response.status().as_u16(), 201);
+        // The handler still saw the body.
+        let echoed: Value = test::read_body_json(response).await;
+        assert_eq!(echoed["name"], "weekly");
+        // Only a trusted proxy may name the caller.
+        let invalid = test::TestRequest::post()
+            .uri("/api/v1/templates")
+            .peer_addr("192.0.2.9:40000".parse().unwrap())
+            .insert_header((PRINCIPAL_HEADER, "mallory"))
+            .insert_header(("content-type", "application/json"))
+            .set_payload("not json")
+            .to_request();
+        assert_eq!(test::call_service(&app, invalid).await.status(), 400);
+
+        let log = db.get_audit_log(10, None).unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].status, 400);
+        assert_eq!(log[0].params, Some(json!({"body": "not json"})));
+        assert_eq!(log[0].principal, None);
+        assert_eq!(log[0].client.as_deref(), Some("192.0.2.9"));
+        let created = &log[1];
+        assert_eq!(created.method, "POST");
+        assert_eq!(created.path, "/api/v1/templates");
+        assert_eq!(created.principal.as_deref(), Some("alice"));
+        assert_eq!(
+            created.params,
+            Some(json!({"query": "dry_run=1", "body": {"name": "weekly"}}))
+        );
+        assert_eq!(db.get_audit_log(10, Some(log[0].id)).unwrap().len(), 1);
+    }
+}
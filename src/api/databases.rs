@@ -0,0 +1,161 @@
+//! `--attach-db`: result databases of other scanners (one file per region,
+//! say) served read-only next to the main one. Results and stats handlers
+//! take a [`SelectedDb`] instead of the main database, which reads the
+//! request's `db` query parameter.
+
+use std::collections::BTreeMap;
+use std::future::{ready, Ready};
+use std::ops::Deref;
+
+use actix_web::{dev::Payload, error::InternalError, web, FromRequest, HttpRequest, HttpResponse};
+use anyhow::Result;
+use tracing::info;
+
+use crate::api::models::{DbQuery, ErrorResponse};
+use crate::cli::Args;
+use crate::dao::SqliteDB;
+
+/// Name the `db` parameter uses for `--database`.
+pub const MAIN_DATABASE: &str = "main";
+
+#[derive(Clone, Default)]
+pub struct AttachedDatabases {
+    databases: BTreeMap<String, SqliteDB>,
+}
+
+impl AttachedDatabases {
+    /// Open every `--attach-db` file read-only, with `--db-key` when set.
+    pub fn open(args: &Args) -> Result<Self> {
+        let mut databases = BTreeMap::new();
+        for (name, path) in args.attached_databases()? {
+            let db = SqliteDB::open_read_only(&path, args.db_key.as_deref())
+                .map_err(|e| anyhow::anyhow!("--attach-db {}: {}", name, e))?;
+            info!("Attached {} read-only as database {:?}", path, name);
+            databases.insert(name, db);
+        }
+        Ok(Self { databases })
+    }
+
+    /// `main` followed by the attached names, in order.
+    pub fn names(&self) -> Vec<String> {
+        std::iter::once(MAIN_DATABASE.to_string())
+            .chain(self.databases.keys().cloned())
+            .collect()
+    }
+}
+
+/// The database a request reads: the attached one named by its `db` query
+/// parameter, or the main database when the parameter is absent or `main`.
+/// An unknown name is answered with 404 `UNKNOWN_DATABASE`.
+pub struct SelectedDb(SqliteDB);
+
+impl Deref for SelectedDb {
+    type Target = SqliteDB;
+
+    fn deref(&self) -> &SqliteDB {
+        &self.0
+    }
+}
+
+impl SelectedDb {
+    fn select(req: &HttpRequest) -> Result<SqliteDB, actix_web::Error> {
+        // Malformed query strings are left for the handler's own extractor
+        // to reject.
+        let name = web::Query::<DbQuery>::from_query(req.query_string())
+            .ok()
+            .and_then(|query| query.into_inner().db);
+        match name.as_deref() {
+            None | Some(MAIN_DATABASE) => req
+                .app_data::<web::Data<SqliteDB>>()
+                .map(|db| db.get_ref().clone())
+                .ok_or_else(|| {
+                    error_response(
+                        HttpResponse::InternalServerError(),
+                        "Database is not configured".to_string(),
+                        "DATABASE_ERROR",
+                    )
+                }),
+            Some(name) => req
+                .app_data::<web::Data<AttachedDatabases>>()
+                .and_then(|attached| attached.databases.get(name).cloned())
+                .ok_or_else(|| {
+                    error_response(
+                        HttpResponse::NotFound(),
+                        format!("No attached database named {:?}", name),
+                        "UNKNOWN_DATABASE",
+                    )
+                }),
+        }
+    }
+}
+
+fn error_response(
+    mut builder: actix_web::HttpResponseBuilder,
+    error: String,
+    code: &str,
+) -> actix_web::Error {
+    let response = builder.json(ErrorResponse {
+        error,
+        code: Some(code.to_string()),
+    });
+    InternalError::from_response("", response).into()
+}
+
+impl FromRequest for SelectedDb {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Self::select(req).map(SelectedDb))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_db_parameter_selects_the_attached_database() {
+        let main = SqliteDB::new(":memory:").unwrap();
+        main.bulk_update_port_status(vec![("192.0.2.1".parse().unwrap(), 22, true)], 1)
+            .unwrap();
+        let region = SqliteDB::new(":memory:").unwrap();
+        region
+            .bulk_update_port_status(vec![("198.51.100.1".parse().unwrap(), 443, true)], 1)
+            .unwrap();
+        let attached = AttachedDatabases {
+            databases: BTreeMap::from([("eu".to_string(), region)]),
+        };
+        assert_eq!(attached.names(), ["main", "eu"]);
+
+        let request = |uri: &str| {
+            TestRequest::get()
+                .uri(uri)
+                .app_data(web::Data::new(main.clone()))
+                .app_data(web::Data::new(attached.clone()))
+                .to_http_request()
+        };
+        let ips = |uri: &str| {
+            let db = SelectedDb::select(&request(uri)).unwrap();
+            let (results, _) = db
+                .get_scan_results(1, 10, None, None, None, None, None, None, None, None, None)
+                .unwrap();
+            results
+                .into_iter()
+                .map(|result| result.ip_address)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ips("/api/v1/results?page=1"), ["192.0.2.1"]);
+        assert_eq!(ips("/api/v1/results?db=main"), ["192.0.2.1"]);
+        assert_eq!(ips("/api/v1/results?port=443&db=eu"), ["198.51.100.1"]);
+
+        let unknown = SelectedDb::select(&request("/api/v1/results?db=us"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            unknown.error_response().status(),
+            actix_web::http::StatusCode::NOT_FOUND
+        );
+    }
+}
@@ -2,30 +2,34 @@
 //!
 //! This module contains the request handlers for all API endpoints.
 
-use actix_web::{web, HttpResponse, Responder};
-use serde_json::json;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use serde_json::{json, Value};
 use tracing::error;
 
+use crate::api::databases::{AttachedDatabases, SelectedDb};
 use crate::api::models::*;
+use crate::api::quota::ExportQuota;
 use crate::dao::SqliteDB;
 use crate::model::ServiceInfo;
+use crate::service::{
+    csv_row, html_escape, write_results_parquet, Coordinator, LeaseOutcome, LeaseReport,
+    LeaseRequest, ReportOutcome, ResultsFilter, ResultsReport, CSV_HEADER,
+};
 
 /// Get paginated scan results with filtering
 #[utoipa::path(
     get,
     path = "/api/v1/results",
-    params(ResultsQuery),
+    params(ResultsQuery, DbQuery),
     responses(
         (status = 200, description = "Successfully retrieved scan results", body = PaginatedResults),
         (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+        (status = 404, description = "Unknown `db` name", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "Results"
 )]
-pub async fn get_results(
-    db: web::Data<SqliteDB>,
-    query: web::Query<ResultsQuery>,
-) -> impl Responder {
+pub async fn get_results(db: SelectedDb, query: web::Query<ResultsQuery>) -> impl Responder {
     // Validate pagination
     if let Err(err) = query.pagination.validate() {
         return HttpResponse::BadRequest().json(ErrorResponse {
@@ -41,24 +45,16 @@ pub async fn get_results(
         query.filter.port,
         query.filter.round,
         query.filter.ip_type.as_deref(),
+        query.filter.status,
+        query.filter.scan_id.as_deref(),
+        query.filter.hostname.as_deref(),
+        query.filter.has_cves,
+        query.filter.reputation,
     ) {
         Ok((results, total)) => {
             let total_pages = total.div_ceil(query.pagination.page_size);
 
-            let api_results: Vec<ScanResult> = results
-                .into_iter()
-                .map(|r| ScanResult {
-                    ip_address: r.ip_address,
-                    ip_type: r.ip_type,
-                    port: r.port,
-                    scan_round: r.scan_round,
-                    first_seen: r.first_seen,
-                    last_seen: r.last_seen,
-                    country: r.country,
-                    city: r.city,
-                    reverse_dns: r.reverse_dns,
-                })
-                .collect();
+            let api_results: Vec<ScanResult> = results.into_iter().map(ScanResult::from).collect();
 
             HttpResponse::Ok().json(PaginatedResults {
                 results: api_results,
@@ -83,16 +79,22 @@ pub async fn get_results(
     get,
     path = "/api/v1/results/{ip}",
     params(
-        ("ip" = String, Path, description = "IP address")
+        ("ip" = String, Path, description = "IP address"),
+        DbQuery
     ),
     responses(
         (status = 200, description = "Successfully retrieved scan results for IP", body = Vec<ScanResult>),
-        (status = 404, description = "IP not found", body = ErrorResponse),
+        (status = 404, description = "IP not found, or unknown `db` name", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "Results"
 )]
-pub async fn get_results_by_ip(db: web::Data<SqliteDB>, ip: web::Path<String>) -> impl Responder {
+pub async fn get_results_by_ip(db: SelectedDb, ip: web::Path<String>) -> impl Responder {
+    // IPv6 addresses are stored in canonical form, so accept any spelling.
+    let ip = ip
+        .parse::<std::net::IpAddr>()
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|_| ip.into_inner());
     match db.get_results_by_ip(&ip) {
         Ok(results) => {
             if results.is_empty() {
@@ -101,20 +103,8 @@ pub async fn get_results_by_ip(db: web::Data<SqliteDB>, ip: web::Path<String>) -
                     code: Some("IP_NOT_FOUND".to_string()),
                 })
             } else {
-                let api_results: Vec<ScanResult> = results
-                    .into_iter()
-                    .map(|r| ScanResult {
-                        ip_address: r.ip_address,
-                        ip_type: r.ip_type,
-                        port: r.port,
-                        scan_round: r.scan_round,
-                        first_seen: r.first_seen,
-                        last_seen: r.last_seen,
-                        country: r.country,
-                        city: r.city,
-                        reverse_dns: r.reverse_dns,
-                    })
-                    .collect();
+                let api_results: Vec<ScanResult> =
+                    results.into_iter().map(ScanResult::from).collect();
 
                 HttpResponse::Ok().json(api_results)
             }
@@ -129,109 +119,146 @@ pub async fn get_results_by_ip(db: web::Data<SqliteDB>, ip: web::Path<String>) -
     }
 }
 
-/// Get scan results for a specific port
+/// Answer a per-port or per-round lookup with one page of results, or 404
+/// when nothing matches at all.
+fn results_page_response(
+    lookup: anyhow::Result<(Vec<crate::dao::ScanResultDetail>, usize)>,
+    pagination: &PaginationQuery,
+    subject: &str,
+    not_found_code: &str,
+) -> HttpResponse {
+    match lookup {
+        Ok((_, 0)) => HttpResponse::NotFound().json(ErrorResponse {
+            error: format!("No scan results found for {}", subject),
+            code: Some(not_found_code.to_string()),
+        }),
+        Ok((results, total)) => {
+            let api_results: Vec<ScanResult> = results.into_iter().map(ScanResult::from).collect();
+
+            HttpResponse::Ok().json(PaginatedResults {
+                results: api_results,
+                total,
+                page: pagination.page,
+                page_size: pagination.page_size,
+                total_pages: total.div_ceil(pagination.page_size),
+            })
+        }
+        Err(e) => {
+            error!("Failed to get results for {}: {}", subject, e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to retrieve scan results".to_string(),
+                code: Some("DATABASE_ERROR".to_string()),
+            })
+        }
+    }
+}
+
+/// Get when each open port of an IP appeared and disappeared, as runs of
+/// consecutive rounds recorded with `--port-history`
 #[utoipa::path(
     get,
-    path = "/api/v1/results/port/{port}",
+    path = "/api/v1/results/{ip}/history",
     params(
-        ("port" = u16, Path, description = "Port number")
+        ("ip" = String, Path, description = "IP address"),
+        PortHistoryQuery,
+        DbQuery
     ),
     responses(
-        (status = 200, description = "Successfully retrieved scan results for port", body = Vec<ScanResult>),
-        (status = 404, description = "Port not found", body = ErrorResponse),
+        (status = 200, description = "Runs by port, oldest first; empty when none were recorded", body = Vec<crate::dao::PortHistoryRun>),
+        (status = 404, description = "Unknown `db` name", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "Results"
 )]
-pub async fn get_results_by_port(db: web::Data<SqliteDB>, port: web::Path<u16>) -> impl Responder {
-    match db.get_results_by_port(*port) {
-        Ok(results) => {
-            if results.is_empty() {
-                HttpResponse::NotFound().json(ErrorResponse {
-                    error: format!("No scan results found for port: {}", port),
-                    code: Some("PORT_NOT_FOUND".to_string()),
-                })
-            } else {
-                let api_results: Vec<ScanResult> = results
-                    .into_iter()
-                    .map(|r| ScanResult {
-                        ip_address: r.ip_address,
-                        ip_type: r.ip_type,
-                        port: r.port,
-                        scan_round: r.scan_round,
-                        first_seen: r.first_seen,
-                        last_seen: r.last_seen,
-                        country: r.country,
-                        city: r.city,
-                        reverse_dns: r.reverse_dns,
-                    })
-                    .collect();
-
-                HttpResponse::Ok().json(api_results)
-            }
-        }
+pub async fn get_port_history(
+    db: SelectedDb,
+    ip: web::Path<String>,
+    query: web::Query<PortHistoryQuery>,
+) -> impl Responder {
+    match db.get_port_history(&ip, query.port) {
+        Ok(runs) => HttpResponse::Ok().json(runs),
         Err(e) => {
-            error!("Failed to get results for port {}: {}", port, e);
+            error!("Failed to get port history for IP {}: {}", ip, e);
             HttpResponse::InternalServerError().json(ErrorResponse {
-                error: "Failed to retrieve scan results".to_string(),
+                error: "Failed to retrieve port history".to_string(),
                 code: Some("DATABASE_ERROR".to_string()),
             })
         }
     }
 }
 
+/// Get scan results for a specific port
+#[utoipa::path(
+    get,
+    path = "/api/v1/results/port/{port}",
+    params(
+        ("port" = u16, Path, description = "Port number"),
+        PaginationQuery,
+        DbQuery
+    ),
+    responses(
+        (status = 200, description = "One page of results for the port, most recently seen first", body = PaginatedResults),
+        (status = 400, description = "Invalid pagination parameters", body = ErrorResponse),
+        (status = 404, description = "Port not found, or unknown `db` name", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Results"
+)]
+pub async fn get_results_by_port(
+    db: SelectedDb,
+    port: web::Path<u16>,
+    query: web::Query<PaginationQuery>,
+) -> impl Responder {
+    if let Err(err) = query.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: err,
+            code: Some("INVALID_PAGINATION".to_string()),
+        });
+    }
+
+    results_page_response(
+        db.get_results_by_port(*port, query.page, query.page_size),
+        &query,
+        &format!("port: {}", port),
+        "PORT_NOT_FOUND",
+    )
+}
+
 /// Get scan results for a specific round
 #[utoipa::path(
     get,
     path = "/api/v1/results/round/{round}",
     params(
-        ("round" = i64, Path, description = "Scan round number")
+        ("round" = i64, Path, description = "Scan round number"),
+        PaginationQuery,
+        DbQuery
     ),
     responses(
-        (status = 200, description = "Successfully retrieved scan results for round", body = Vec<ScanResult>),
-        (status = 404, description = "Round not found", body = ErrorResponse),
+        (status = 200, description = "One page of results for the round, by address", body = PaginatedResults),
+        (status = 400, description = "Invalid pagination parameters", body = ErrorResponse),
+        (status = 404, description = "Round not found, or unknown `db` name", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "Results"
 )]
 pub async fn get_results_by_round(
-    db: web::Data<SqliteDB>,
+    db: SelectedDb,
     round: web::Path<i64>,
+    query: web::Query<PaginationQuery>,
 ) -> impl Responder {
-    match db.get_results_by_round(*round) {
-        Ok(results) => {
-            if results.is_empty() {
-                HttpResponse::NotFound().json(ErrorResponse {
-                    error: format!("No scan results found for round: {}", round),
-                    code: Some("ROUND_NOT_FOUND".to_string()),
-                })
-            } else {
-                let api_results: Vec<ScanResult> = results
-                    .into_iter()
-                    .map(|r| ScanResult {
-                        ip_address: r.ip_address,
-                        ip_type: r.ip_type,
-                        port: r.port,
-                        scan_round: r.scan_round,
-                        first_seen: r.first_seen,
-                        last_seen: r.last_seen,
-                        country: r.country,
-                        city: r.city,
-                        reverse_dns: r.reverse_dns,
-                    })
-                    .collect();
-
-                HttpResponse::Ok().json(api_results)
-            }
-        }
-        Err(e) => {
-            error!("Failed to get results for round {}: {}", round, e);
-            HttpResponse::InternalServerError().json(ErrorResponse {
-                error: "Failed to retrieve scan results".to_string(),
-                code: Some("DATABASE_ERROR".to_string()),
-            })
-        }
+    if let Err(err) = query.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: err,
+            code: Some("INVALID_PAGINATION".to_string()),
+        });
     }
+
+    results_page_response(
+        db.get_results_by_round(*round, query.page, query.page_size),
+        &query,
+        &format!("round: {}", round),
+        "ROUND_NOT_FOUND",
+    )
 }
 
 /// Lightweight health endpoint for load balancers and orchestration.
@@ -266,12 +293,19 @@ pub async fn get_health(db: web::Data<SqliteDB>) -> impl Responder {
     ),
     tag = "Operations"
 )]
-pub async fn get_system_info(db: web::Data<SqliteDB>) -> impl Responder {
+pub async fn get_system_info(
+    req: HttpRequest,
+    db: web::Data<SqliteDB>,
+    attached: web::Data<AttachedDatabases>,
+    read_only: Option<web::Data<crate::api::ReadOnlyApi>>,
+    quotas: Option<web::Data<crate::api::Quotas>>,
+    geo_jobs: Option<web::Data<crate::service::GeoJobs>>,
+) -> impl Responder {
     let (status, database) = match db.get_current_round() {
         Ok(_) => ("ready", "ok"),
         Err(_) => ("degraded", "error"),
     };
-    let response = SystemInfoResponse {
+    let mut response = SystemInfoResponse {
         protocol: "ip-scan".to_string(),
         api_version: "v1".to_string(),
         service: env!("CARGO_PKG_NAME").to_string(),
@@ -279,14 +313,28 @@ pub async fn get_system_info(db: web::Data<SqliteDB>) -> impl Responder {
         status: status.to_string(),
         database: database.to_string(),
         server_time: chrono::Utc::now().to_rfc3339(),
+        databases: attached.names(),
         capabilities: vec![
             "scan.control".to_string(),
             "scan.status".to_string(),
+            "scan.templates".to_string(),
+            "admin.rounds".to_string(),
+            "admin.db".to_string(),
+            "admin.maintenance".to_string(),
+            "admin.audit".to_string(),
+            "admin.namespaces".to_string(),
             "results.pagination".to_string(),
+            "results.attached_db".to_string(),
+            "results.port_history".to_string(),
             "results.export".to_string(),
+            "results.hostnames".to_string(),
             "services.enrichment".to_string(),
+            "services.vhosts".to_string(),
             "visualization.ip-map".to_string(),
             "observability.prometheus".to_string(),
+            "stats.timeseries".to_string(),
+            "stats.coverage".to_string(),
+            "observability.geo".to_string(),
         ],
         endpoints: vec![
             "/healthz".to_string(),
@@ -295,9 +343,31 @@ pub async fn get_system_info(db: web::Data<SqliteDB>) -> impl Responder {
             "/results".to_string(),
             "/services".to_string(),
             "/scan".to_string(),
+            "/templates".to_string(),
+            "/admin".to_string(),
             "/export".to_string(),
+            "/geo".to_string(),
         ],
     };
+    if read_only.is_some() {
+        response
+            .capabilities
+            .retain(|capability| capability != "scan.control" && !capability.starts_with("admin."));
+        response.capabilities.push("api.read_only".to_string());
+        response.endpoints.retain(|endpoint| endpoint != "/admin");
+    }
+    if quotas.is_some() {
+        response.capabilities.push("api.quotas".to_string());
+    }
+    if geo_jobs.is_some() && read_only.is_none() {
+        response.capabilities.push("geo.enrich".to_string());
+    }
+    if req
+        .conn_data::<crate::api::tls::ClientCertificate>()
+        .is_some()
+    {
+        response.capabilities.push("api.mtls".to_string());
+    }
     if status == "ready" {
         HttpResponse::Ok().json(response)
     } else {
@@ -309,13 +379,15 @@ pub async fn get_system_info(db: web::Data<SqliteDB>) -> impl Responder {
 #[utoipa::path(
     get,
     path = "/api/v1/stats",
+    params(DbQuery),
     responses(
         (status = 200, description = "Successfully retrieved statistics", body = StatsResponse),
+        (status = 404, description = "Unknown `db` name", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "Statistics"
 )]
-pub async fn get_stats(db: web::Data<SqliteDB>) -> impl Responder {
+pub async fn get_stats(db: SelectedDb) -> impl Responder {
     match db.get_stats() {
         Ok((total_open_records, unique_ips)) => {
             let memory_usage_bytes = db.get_memory_usage().unwrap_or(0);
@@ -342,29 +414,216 @@ pub async fn get_stats(db: web::Data<SqliteDB>) -> impl Responder {
     }
 }
 
+/// A JSON snapshot published by the scanner, if it has run.
+fn load_json_metadata(db: &SqliteDB, key: &str) -> Option<Value> {
+    db.get_metadata(key)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+}
+
+/// Render latency percentiles as Prometheus summaries, in seconds.
+fn prometheus_latency(stats: &Value) -> String {
+    let mut body = String::new();
+    for (key, name, help) in [
+        (
+            "connect",
+            "ip_scan_connect_latency_seconds",
+            "TCP connect latency of the current scan",
+        ),
+        (
+            "syn_rtt",
+            "ip_scan_syn_rtt_seconds",
+            "SYN to SYN-ACK round-trip time of the current scan",
+        ),
+        (
+            "db_write",
+            "ip_scan_db_write_seconds",
+            "Duration of database write batches in the current scan",
+        ),
+    ] {
+        let summary = &stats[key];
+        let Some(count) = summary["count"].as_u64().filter(|&c| c > 0) else {
+            continue;
+        };
+        body.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} summary\n",
+            name, help, name
+        ));
+        for (quantile, field) in [("0.5", "p50_ms"), ("0.95", "p95_ms"), ("0.99", "p99_ms")] {
+            let ms = summary[field].as_f64().unwrap_or(0.0);
+            body.push_str(&format!(
+                "{}{{quantile=\"{}\"}} {}\n",
+                name,
+                quantile,
+                ms / 1000.0
+            ));
+        }
+        body.push_str(&format!("{}_count {}\n", name, count));
+    }
+    body
+}
+
+/// Render probe reply ratios as Prometheus gauges.
+fn prometheus_replies(replies: &Value) -> String {
+    if replies["sent"].as_u64().unwrap_or(0) == 0 {
+        return String::new();
+    }
+    let mut body = String::new();
+    for (field, name, help) in [
+        (
+            "no_answer_ratio",
+            "ip_scan_probe_no_answer_ratio",
+            "Fraction of probes in the current scan with neither SYN-ACK nor RST",
+        ),
+        (
+            "rst_ratio",
+            "ip_scan_probe_rst_ratio",
+            "Fraction of probes in the current scan answered with RST",
+        ),
+    ] {
+        body.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} gauge\n{} {}\n",
+            name,
+            help,
+            name,
+            name,
+            replies[field].as_f64().unwrap_or(0.0)
+        ));
+    }
+    body
+}
+
+/// Render pipeline queue depths and DB writer batching as Prometheus gauges.
+fn prometheus_queues(queues: &Value) -> String {
+    let mut body = String::new();
+    for (value, name, help) in [
+        (
+            &queues["pipeline"]["depth"],
+            "ip_scan_pipeline_queue_depth",
+            "Targets waiting for the probe pipeline",
+        ),
+        (
+            &queues["pipeline"]["capacity"],
+            "ip_scan_pipeline_queue_capacity",
+            "Capacity of the probe pipeline queue (--pipeline-buffer)",
+        ),
+        (
+            &queues["results"]["depth"],
+            "ip_scan_result_queue_depth",
+            "Results waiting for the database writer",
+        ),
+        (
+            &queues["results"]["capacity"],
+            "ip_scan_result_queue_capacity",
+            "Capacity of the result queue (--result-buffer)",
+        ),
+        (
+            &queues["db_batch_size"],
+            "ip_scan_db_batch_size",
+            "Rows per database write batch currently used by the writer",
+        ),
+        (
+            &queues["flush_interval_ms"],
+            "ip_scan_db_flush_interval_ms",
+            "Partial batch flush interval currently used by the writer",
+        ),
+    ] {
+        let Some(value) = value.as_u64() else {
+            continue;
+        };
+        body.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} gauge\n{} {}\n",
+            name, help, name, name, value
+        ));
+    }
+    body.push_str(&prometheus_latency(
+        &json!({ "db_write": queues["db_write"].clone() }),
+    ));
+    body
+}
+
+/// Render the geo enrichment worker's counters.
+fn prometheus_geo(geo: &Value) -> String {
+    let mut body = String::from(
+        "# HELP ip_scan_geo_lookups_total Geo lookups finished by the enrichment worker since it started\n# TYPE ip_scan_geo_lookups_total counter\n",
+    );
+    for result in ["found", "failed", "timed_out"] {
+        body.push_str(&format!(
+            "ip_scan_geo_lookups_total{{result=\"{}\"}} {}\n",
+            result,
+            geo[result].as_u64().unwrap_or(0)
+        ));
+    }
+    for (field, name, help) in [
+        (
+            "lookups_per_sec",
+            "ip_scan_geo_lookups_per_second",
+            "Geo lookups finished per second over the last snapshot interval",
+        ),
+        (
+            "in_flight",
+            "ip_scan_geo_in_flight",
+            "Geo lookups in progress",
+        ),
+        (
+            "queued",
+            "ip_scan_geo_queued",
+            "IPs fetched for the current enrichment pass and not looked up yet",
+        ),
+    ] {
+        body.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} gauge\n{} {}\n",
+            name,
+            help,
+            name,
+            name,
+            geo[field].as_f64().unwrap_or(0.0)
+        ));
+    }
+    body
+}
+
+/// The metrics `/stats/prometheus` serves, in Prometheus text format; also
+/// what `[metrics_push]` sends.
+pub fn prometheus_text(db: &SqliteDB) -> anyhow::Result<String> {
+    let (total_open_records, unique_ips) = db.get_stats()?;
+    let memory_bytes = db.get_memory_usage().unwrap_or(0);
+    let round = db.get_current_round().unwrap_or(0);
+    let mut body = format!(
+        "# HELP ip_scan_open_port_records Current open IP/port records\n# TYPE ip_scan_open_port_records gauge\nip_scan_open_port_records {}\n# HELP ip_scan_unique_ips Unique IPs with open ports\n# TYPE ip_scan_unique_ips gauge\nip_scan_unique_ips {}\n# HELP ip_scan_bitmap_bytes Persisted bitmap storage in bytes\n# TYPE ip_scan_bitmap_bytes gauge\nip_scan_bitmap_bytes {}\n# HELP ip_scan_round Current scan round\n# TYPE ip_scan_round gauge\nip_scan_round {}\n",
+        total_open_records, unique_ips, memory_bytes, round
+    );
+    if let Some(stats) = load_json_metadata(db, "latency_stats") {
+        body.push_str(&prometheus_latency(&stats));
+    }
+    if let Some(replies) = load_json_metadata(db, "reply_stats") {
+        body.push_str(&prometheus_replies(&replies));
+    }
+    if let Some(queues) = load_json_metadata(db, "queue_stats") {
+        body.push_str(&prometheus_queues(&queues));
+    }
+    if let Some(geo) = load_json_metadata(db, "geo_stats") {
+        body.push_str(&prometheus_geo(&geo));
+    }
+    Ok(body)
+}
+
 /// Export operational metrics in Prometheus text format.
 #[utoipa::path(
     get,
     path = "/api/v1/stats/prometheus",
     responses(
-        (status = 200, description = "Prometheus metrics", body = String),
+        (status = 200, description = "Prometheus metrics, including latency summaries, probe reply ratios, queue depths and geo enrichment throughput", body = String),
         (status = 500, description = "Failed to collect metrics")
     ),
     tag = "Operations"
 )]
 pub async fn get_prometheus_metrics(db: web::Data<SqliteDB>) -> impl Responder {
-    match db.get_stats() {
-        Ok((total_open_records, unique_ips)) => {
-            let memory_bytes = db.get_memory_usage().unwrap_or(0);
-            let round = db.get_current_round().unwrap_or(0);
-            let body = format!(
-                "# HELP ip_scan_open_port_records Current open IP/port records\n# TYPE ip_scan_open_port_records gauge\nip_scan_open_port_records {}\n# HELP ip_scan_unique_ips Unique IPs with open ports\n# TYPE ip_scan_unique_ips gauge\nip_scan_unique_ips {}\n# HELP ip_scan_bitmap_bytes Persisted bitmap storage in bytes\n# TYPE ip_scan_bitmap_bytes gauge\nip_scan_bitmap_bytes {}\n# HELP ip_scan_round Current scan round\n# TYPE ip_scan_round gauge\nip_scan_round {}\n",
-                total_open_records, unique_ips, memory_bytes, round
-            );
-            HttpResponse::Ok()
-                .content_type("text/plain; version=0.0.4")
-                .body(body)
-        }
+    match prometheus_text(&db) {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(body),
         Err(e) => {
             error!("Failed to export Prometheus metrics: {}", e);
             HttpResponse::InternalServerError().finish()
@@ -372,25 +631,167 @@ pub async fn get_prometheus_metrics(db: web::Data<SqliteDB>) -> impl Responder {
     }
 }
 
+/// Geo enrichment progress: IPs still missing geolocation, the worker's
+/// lookup rate and provider errors, and how long catching up would take.
+#[utoipa::path(
+    get,
+    path = "/api/v1/geo/status",
+    responses(
+        (status = 200, description = "Geo enrichment backlog and worker counters", body = crate::service::GeoStatus),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Operations"
+)]
+pub async fn get_geo_status(db: web::Data<SqliteDB>) -> impl Responder {
+    // Counting the backlog scans open_ports_detail; keep it off the async
+    // workers.
+    let result = web::block(move || crate::service::GeoStatus::collect(&db))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|status| status);
+    match result {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(e) => {
+            error!("Failed to retrieve geo enrichment status: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to retrieve geo enrichment status".to_string(),
+                code: Some("DATABASE_ERROR".to_string()),
+            })
+        }
+    }
+}
+
+fn geo_disabled() -> HttpResponse {
+    HttpResponse::Conflict().json(ErrorResponse {
+        error: "Geo lookups are disabled (--no-geo)".to_string(),
+        code: Some("GEO_DISABLED".to_string()),
+    })
+}
+
+/// Look up geo data for chosen IPs now rather than when the background
+/// enrichment reaches them. Jobs run one at a time; poll the returned job
+/// at `/geo/enrich/{id}`.
+#[utoipa::path(
+    post,
+    path = "/api/v1/geo/enrich",
+    request_body = GeoEnrichRequest,
+    responses(
+        (status = 202, description = "Job queued", body = crate::service::GeoJob),
+        (status = 400, description = "Invalid IP or limit", body = ErrorResponse),
+        (status = 409, description = "Geo lookups are disabled", body = ErrorResponse),
+        (status = 429, description = "Too many jobs waiting", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Operations"
+)]
+pub async fn enrich_geo(
+    db: web::Data<SqliteDB>,
+    jobs: Option<web::Data<crate::service::GeoJobs>>,
+    req: web::Json<GeoEnrichRequest>,
+) -> impl Responder {
+    use crate::service::MAX_JOB_IPS;
+
+    let Some(jobs) = jobs else {
+        return geo_disabled();
+    };
+    let invalid = |error: String| {
+        HttpResponse::BadRequest().json(ErrorResponse {
+            error,
+            code: Some("INVALID_GEO_REQUEST".to_string()),
+        })
+    };
+    let req = req.into_inner();
+    let ips = if req.ips.is_empty() {
+        let limit = req.limit.unwrap_or(MAX_JOB_IPS);
+        if limit == 0 || limit > MAX_JOB_IPS {
+            return invalid(format!("limit must be between 1 and {}", MAX_JOB_IPS));
+        }
+        let missing_only = req.missing_only.unwrap_or(true);
+        let selected = web::block(move || db.get_ips_for_geo(req.port, missing_only, limit))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|ips| ips);
+        match selected {
+            Ok(ips) => ips,
+            Err(e) => {
+                error!("Failed to select IPs for geo enrichment: {}", e);
+                return HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: "Failed to select IPs for geo enrichment".to_string(),
+                    code: Some("DATABASE_ERROR".to_string()),
+                });
+            }
+        }
+    } else {
+        if req.ips.len() > MAX_JOB_IPS {
+            return invalid(format!("At most {} IPs per job", MAX_JOB_IPS));
+        }
+        let mut ips = Vec::with_capacity(req.ips.len());
+        for ip in req.ips {
+            match ip.trim().parse::<std::net::IpAddr>() {
+                Ok(addr) => ips.push(addr.to_string()),
+                Err(_) => return invalid(format!("Invalid IP address: {}", ip)),
+            }
+        }
+        ips.sort();
+        ips.dedup();
+        ips
+    };
+
+    match jobs.submit(ips) {
+        Some(job) => HttpResponse::Accepted().json(job),
+        None => HttpResponse::TooManyRequests().json(ErrorResponse {
+            error: "Too many geo enrichment jobs waiting; try again later".to_string(),
+            code: Some("GEO_QUEUE_FULL".to_string()),
+        }),
+    }
+}
+
+/// Progress of a geo enrichment job
+#[utoipa::path(
+    get,
+    path = "/api/v1/geo/enrich/{id}",
+    params(("id" = u64, Path, description = "Job ID returned by POST /geo/enrich")),
+    responses(
+        (status = 200, description = "Job progress", body = crate::service::GeoJob),
+        (status = 404, description = "Unknown or expired job", body = ErrorResponse),
+        (status = 409, description = "Geo lookups are disabled", body = ErrorResponse)
+    ),
+    tag = "Operations"
+)]
+pub async fn get_geo_job(
+    jobs: Option<web::Data<crate::service::GeoJobs>>,
+    id: web::Path<u64>,
+) -> impl Responder {
+    let Some(jobs) = jobs else {
+        return geo_disabled();
+    };
+    match jobs.get(*id) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().json(ErrorResponse {
+            error: format!("Geo enrichment job {} not found", id),
+            code: Some("GEO_JOB_NOT_FOUND".to_string()),
+        }),
+    }
+}
+
 /// Return bounded open/closed changes between two bitmap rounds.
 #[utoipa::path(
     get,
     path = "/api/v1/stats/changes/{round}/{port}",
     params(
         ("round" = i64, Path, description = "Current scan round"),
-        ("port" = u16, Path, description = "Port to compare")
+        ("port" = u16, Path, description = "Port to compare"),
+        DbQuery
     ),
     responses(
         (status = 200, description = "Changed IP addresses", body = Vec<crate::dao::PortChange>),
         (status = 400, description = "Invalid round or port", body = ErrorResponse),
+        (status = 404, description = "Unknown `db` name", body = ErrorResponse),
         (status = 500, description = "Database error", body = ErrorResponse)
     ),
     tag = "Statistics"
 )]
-pub async fn get_bitmap_changes(
-    db: web::Data<SqliteDB>,
-    path: web::Path<(i64, u16)>,
-) -> impl Responder {
+pub async fn get_bitmap_changes(db: SelectedDb, path: web::Path<(i64, u16)>) -> impl Responder {
     let (round, port) = path.into_inner();
     if round < 1 || port == 0 {
         return HttpResponse::BadRequest().json(ErrorResponse {
@@ -410,143 +811,509 @@ pub async fn get_bitmap_changes(
     }
 }
 
-/// Get top ports statistics
+/// Get scanner counters recorded at the end of each round
 #[utoipa::path(
     get,
-    path = "/api/v1/stats/top-ports",
+    path = "/api/v1/stats/rounds",
     params(
-        ("limit" = Option<usize>, Query, description = "Number of top ports to return (default: 10, max: 100)")
+        ("limit" = Option<usize>, Query, description = "Number of most recent rounds to return (default: 50, max: 500)"),
+        DbQuery
     ),
     responses(
-        (status = 200, description = "Successfully retrieved top ports", body = TopPortsResponse),
+        (status = 200, description = "Per-round metrics, newest first", body = Vec<crate::dao::RoundMetrics>),
         (status = 400, description = "Invalid limit parameter", body = ErrorResponse),
+        (status = 404, description = "Unknown `db` name", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "Statistics"
 )]
-pub async fn get_top_ports(
-    db: web::Data<SqliteDB>,
-    query: web::Query<TopPortsQuery>,
+pub async fn get_round_metrics(
+    db: SelectedDb,
+    query: web::Query<RoundMetricsQuery>,
 ) -> impl Responder {
-    let limit = query.limit.unwrap_or(10);
-
-    if limit == 0 || limit > 100 {
+    let limit = query.limit.unwrap_or(50);
+    if limit == 0 || limit > 500 {
         return HttpResponse::BadRequest().json(ErrorResponse {
-            error: "Limit must be between 1 and 100".to_string(),
+            error: "Limit must be between 1 and 500".to_string(),
             code: Some("INVALID_LIMIT".to_string()),
         });
     }
-
-    // Get total count of all open ports first
-    let total_all_ports = match db.get_total_open_ports_count() {
-        Ok(count) => count,
+    match db.get_round_metrics(limit) {
+        Ok(rounds) => HttpResponse::Ok().json(rounds),
         Err(e) => {
-            error!("Failed to get total open ports count: {}", e);
-            return HttpResponse::InternalServerError().json(ErrorResponse {
-                error: "Failed to retrieve statistics".to_string(),
+            error!("Failed to retrieve round metrics: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to retrieve round metrics".to_string(),
                 code: Some("DATABASE_ERROR".to_string()),
-            });
-        }
-    };
-
-    match db.get_top_ports(limit) {
-        Ok(port_stats) => {
-            let ports: Vec<PortStats> = port_stats
-                .into_iter()
-                .map(|(port, count)| {
-                    let percentage = if total_all_ports > 0 {
-                        (count as f64 / total_all_ports as f64) * 100.0
-                    } else {
-                        0.0
-                    };
-
-                    PortStats {
-                        port,
-                        open_count: count,
-                        percentage,
-                    }
-                })
-                .collect();
-
-            HttpResponse::Ok().json(TopPortsResponse {
-                ports,
-                total_open_ports: total_all_ports,
             })
         }
+    }
+}
+
+/// Get how long ports stay open, from the `--port-history` runs
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats/lifetimes",
+    params(PortLifetimesQuery, DbQuery),
+    responses(
+        (status = 200, description = "Per-port run counts and lifetimes, most runs first", body = Vec<crate::dao::PortLifetime>),
+        (status = 400, description = "Invalid limit parameter", body = ErrorResponse),
+        (status = 404, description = "Unknown `db` name", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Statistics"
+)]
+pub async fn get_port_lifetimes(
+    db: SelectedDb,
+    query: web::Query<PortLifetimesQuery>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(20);
+    if limit == 0 || limit > 100 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Limit must be between 1 and 100".to_string(),
+            code: Some("INVALID_LIMIT".to_string()),
+        });
+    }
+    match db.get_port_lifetimes(limit) {
+        Ok(lifetimes) => HttpResponse::Ok().json(lifetimes),
         Err(e) => {
-            error!("Failed to get top ports: {}", e);
+            error!("Failed to retrieve port lifetimes: {}", e);
             HttpResponse::InternalServerError().json(ErrorResponse {
-                error: "Failed to retrieve top ports".to_string(),
+                error: "Failed to retrieve port lifetimes".to_string(),
                 code: Some("DATABASE_ERROR".to_string()),
             })
         }
     }
 }
 
-/// Start a new scan
-pub async fn start_scan(
-    controller: web::Data<std::sync::Arc<tokio::sync::Mutex<crate::service::ScanController>>>,
-    runtime_scan_state: web::Data<crate::service::RuntimeScanState>,
-    request: web::Json<StartScanRequest>,
+/// Get recent metrics samples (probe rate, queue depths, rate limiter
+/// tokens) of the running or latest scan
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats/timeseries",
+    params(TimeseriesQuery),
+    responses(
+        (status = 200, description = "Samples every 5 seconds, oldest first", body = TimeseriesResponse),
+        (status = 400, description = "Invalid minutes parameter", body = ErrorResponse)
+    ),
+    tag = "Statistics"
+)]
+pub async fn get_metrics_timeseries(
+    controller: web::Data<crate::service::ScanController>,
+    db: web::Data<SqliteDB>,
+    query: web::Query<TimeseriesQuery>,
 ) -> impl Responder {
-    use crate::cli::Args;
-
-    if runtime_scan_state.is_cli_scan_running() {
-        return HttpResponse::Conflict().json(ErrorResponse {
-            error: "A CLI-managed scan is already running".to_string(),
-            code: Some("SCAN_ALREADY_RUNNING".to_string()),
-        });
+    if query
+        .minutes
+        .is_some_and(|minutes| minutes == 0 || minutes > 1440)
+    {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Minutes must be between 1 and 1440".to_string(),
+            code: Some("INVALID_MINUTES".to_string()),
+        });
+    }
+    // A scan run by this process samples in memory; a CLI scan publishes
+    // its samples to the database.
+    let live = controller.timeseries();
+    let (source, mut samples) = if !live.is_empty() {
+        (Some("api"), live)
+    } else {
+        let published: Vec<crate::service::MetricsSample> =
+            load_json_metadata(&db, crate::service::TIMESERIES_KEY)
+                .and_then(|value| serde_json::from_value(value).ok())
+                .unwrap_or_default();
+        ((!published.is_empty()).then_some("cli"), published)
+    };
+    if let Some(minutes) = query.minutes {
+        let since = chrono::Utc::now().timestamp() - (minutes * 60) as i64;
+        samples.retain(|sample| sample.at >= since);
+    }
+    HttpResponse::Ok().json(TimeseriesResponse {
+        source: source.map(str::to_string),
+        interval_secs: crate::service::SAMPLE_EVERY.as_secs(),
+        samples,
+    })
+}
+
+/// Get, per IPv4 prefix, how many addresses were scanned and how many hosts
+/// have an open port in a round, e.g. for a heatmap
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats/coverage",
+    params(CoverageQuery, DbQuery),
+    responses(
+        (status = 200, description = "Scan coverage per prefix", body = crate::dao::Coverage),
+        (status = 400, description = "Invalid prefix_len parameter", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Statistics"
+)]
+pub async fn get_coverage(db: SelectedDb, query: web::Query<CoverageQuery>) -> impl Responder {
+    let prefix_len = query.prefix_len.unwrap_or(8);
+    if !(1..=16).contains(&prefix_len) {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Prefix length must be between 1 and 16".to_string(),
+            code: Some("INVALID_PREFIX_LEN".to_string()),
+        });
+    }
+    let db = SqliteDB::clone(&db);
+    let round = query.round;
+    // Unions every port bitmap of the round; keep it off the async workers.
+    let coverage = web::block(move || {
+        let round = match round {
+            Some(round) => round,
+            None => db.get_current_round()?,
+        };
+        db.get_coverage(round, prefix_len)
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|coverage| coverage);
+    match coverage {
+        Ok(coverage) => HttpResponse::Ok().json(coverage),
+        Err(e) => {
+            error!("Failed to compute scan coverage: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to compute scan coverage".to_string(),
+                code: Some("DATABASE_ERROR".to_string()),
+            })
+        }
+    }
+}
+
+/// Get tags and findings emitted by `--script` hooks
+#[utoipa::path(
+    get,
+    path = "/api/v1/findings",
+    params(
+        ("ip" = Option<String>, Query, description = "Only findings for this IP address"),
+        ("kind" = Option<String>, Query, description = "Only findings of this kind, e.g. tag"),
+        ("limit" = Option<usize>, Query, description = "Number of findings to return (default: 100, max: 1000)")
+    ),
+    responses(
+        (status = 200, description = "Script findings, most recently seen first", body = Vec<crate::dao::ScriptFinding>),
+        (status = 400, description = "Invalid limit parameter", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Results"
+)]
+pub async fn get_script_findings(
+    db: web::Data<SqliteDB>,
+    query: web::Query<FindingsQuery>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(100);
+    if limit == 0 || limit > 1000 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Limit must be between 1 and 1000".to_string(),
+            code: Some("INVALID_LIMIT".to_string()),
+        });
+    }
+    match db.get_script_findings(query.ip.as_deref(), query.kind.as_deref(), limit) {
+        Ok(findings) => HttpResponse::Ok().json(findings),
+        Err(e) => {
+            error!("Failed to retrieve script findings: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to retrieve script findings".to_string(),
+                code: Some("DATABASE_ERROR".to_string()),
+            })
+        }
+    }
+}
+
+/// Full-text search over banners, HTTP titles, server headers, body
+/// previews and TLS names
+#[utoipa::path(
+    get,
+    path = "/api/v1/search",
+    params(
+        ("q" = String, Query, description = "Words that must all appear, e.g. Jenkins; a trailing * matches a prefix"),
+        ("limit" = Option<usize>, Query, description = "Number of results to return (default: 50, max: 500)")
+    ),
+    responses(
+        (status = 200, description = "Matches, best first", body = Vec<SearchResult>),
+        (status = 400, description = "Empty query or invalid limit parameter", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Results"
+)]
+pub async fn search(db: web::Data<SqliteDB>, query: web::Query<SearchQuery>) -> impl Responder {
+    let limit = query.limit.unwrap_or(50);
+    if limit == 0 || limit > 500 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Limit must be between 1 and 500".to_string(),
+            code: Some("INVALID_LIMIT".to_string()),
+        });
+    }
+    if query.q.trim().is_empty() || query.q.len() > 256 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Query must be between 1 and 256 characters".to_string(),
+            code: Some("INVALID_QUERY".to_string()),
+        });
+    }
+    match db.search(&query.q, limit) {
+        Ok(hits) => {
+            let results: Vec<SearchResult> = hits
+                .into_iter()
+                .map(|hit| SearchResult {
+                    ip_address: hit.ip_address,
+                    port: hit.port,
+                    source: hit.source,
+                    hostname: hit.hostname,
+                    snippet: highlight_snippet(&hit.snippet),
+                })
+                .collect();
+            HttpResponse::Ok().json(results)
+        }
+        Err(e) => {
+            error!("Failed to search: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to search".to_string(),
+                code: Some("DATABASE_ERROR".to_string()),
+            })
+        }
+    }
+}
+
+/// Hostnames matching a pattern: reverse DNS names from geo enrichment and
+/// hostname targets, each with the address it belongs to
+#[utoipa::path(
+    get,
+    path = "/api/v1/hostnames/{pattern}",
+    params(
+        ("pattern" = String, Path, description = "Hostname, with `*` matching any run of characters, e.g. *.example.com; case does not matter"),
+        HostnameQuery
+    ),
+    responses(
+        (status = 200, description = "Matches ordered by hostname", body = Vec<HostnameMatch>),
+        (status = 400, description = "Invalid pattern or limit parameter", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Results"
+)]
+pub async fn search_hostnames(
+    db: web::Data<SqliteDB>,
+    pattern: web::Path<String>,
+    query: web::Query<HostnameQuery>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(100);
+    if limit == 0 || limit > 1000 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Limit must be between 1 and 1000".to_string(),
+            code: Some("INVALID_LIMIT".to_string()),
+        });
+    }
+    let pattern = pattern.into_inner();
+    if pattern.trim().is_empty() || pattern.len() > 253 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Pattern must be between 1 and 253 characters".to_string(),
+            code: Some("INVALID_PATTERN".to_string()),
+        });
+    }
+    match db.search_hostnames(&pattern, limit) {
+        Ok(hits) => {
+            let matches: Vec<HostnameMatch> = hits
+                .into_iter()
+                .map(|hit| HostnameMatch {
+                    hostname: hit.hostname,
+                    ip_address: hit.ip_address,
+                    source: hit.source,
+                    open_ports: hit.open_ports,
+                })
+                .collect();
+            HttpResponse::Ok().json(matches)
+        }
+        Err(e) => {
+            error!("Failed to search hostnames: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to search hostnames".to_string(),
+                code: Some("DATABASE_ERROR".to_string()),
+            })
+        }
+    }
+}
+
+/// Escape a search snippet for HTML and turn its match markers into
+/// `<mark>` tags. Banners are attacker-controlled text, so they are never
+/// passed through as markup.
+fn highlight_snippet(snippet: &str) -> String {
+    html_escape(snippet)
+        .replace('\u{2}', "<mark>")
+        .replace('\u{3}', "</mark>")
+}
+
+/// Get top ports statistics
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats/top-ports",
+    params(
+        ("limit" = Option<usize>, Query, description = "Number of top ports to return (default: 10, max: 100)"),
+        DbQuery
+    ),
+    responses(
+        (status = 200, description = "Successfully retrieved top ports", body = TopPortsResponse),
+        (status = 400, description = "Invalid limit parameter", body = ErrorResponse),
+        (status = 404, description = "Unknown `db` name", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Statistics"
+)]
+pub async fn get_top_ports(db: SelectedDb, query: web::Query<TopPortsQuery>) -> impl Responder {
+    let limit = query.limit.unwrap_or(10);
+
+    if limit == 0 || limit > 100 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Limit must be between 1 and 100".to_string(),
+            code: Some("INVALID_LIMIT".to_string()),
+        });
     }
 
-    // Create a minimal base args for scan controller
-    let base_args = Args {
-        config_flag: None,
-        config_pos: None,
-        start_ip: None,
-        end_ip: None,
-        ports: "80".to_string(),
-        timeout: 500,
-        concurrency: 100,
-        database: "scan_results.db".to_string(),
-        verbose: false,
-        dry_run: false,
-        loop_mode: false,
-        ipv4: true,
-        ipv6: false,
-        only_store_open: true,
-        skip_private: true,
-        syn: false,
-        geoip_db: None,
-        no_geo: false,
-        worker_threads: None,
-        pipeline_buffer: 2000,
-        result_buffer: 10000,
-        db_batch_size: 2000,
-        flush_interval_ms: 1000,
-        max_rate: 100000,
-        rate_window_secs: 1,
-        api: false,
-        api_only: false,
-        no_api: false,
-        api_host: "127.0.0.1".to_string(),
-        api_port: 9090,
-        swagger_ui: false,
-        target: None,
-        preset: None,
-        output_format: "text".to_string(),
-        probe_service: false,
-        probe_timeout: 5,
-        probe_concurrency: 50,
-        geo_concurrency: 8,
-        round_delay_ms: 0,
+    // Get total count of all open ports first
+    let total_all_ports = match db.get_total_open_ports_count() {
+        Ok(count) => count,
+        Err(e) => {
+            error!("Failed to get total open ports count: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to retrieve statistics".to_string(),
+                code: Some("DATABASE_ERROR".to_string()),
+            });
+        }
     };
 
-    // Get shared controller with async lock
-    let controller_guard = controller.lock().await;
+    match db.get_top_ports(limit) {
+        Ok(port_stats) => {
+            let ports: Vec<PortStats> = port_stats
+                .into_iter()
+                .map(|(port, count)| {
+                    let percentage = if total_all_ports > 0 {
+                        (count as f64 / total_all_ports as f64) * 100.0
+                    } else {
+                        0.0
+                    };
+
+                    PortStats {
+                        port,
+                        open_count: count,
+                        percentage,
+                    }
+                })
+                .collect();
+
+            HttpResponse::Ok().json(TopPortsResponse {
+                ports,
+                total_open_ports: total_all_ports,
+            })
+        }
+        Err(e) => {
+            error!("Failed to get top ports: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to retrieve top ports".to_string(),
+                code: Some("DATABASE_ERROR".to_string()),
+            })
+        }
+    }
+}
+
+/// Get the hosts with the most open ports
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats/top-ips",
+    params(TopIpsQuery, DbQuery),
+    responses(
+        (status = 200, description = "Successfully retrieved top hosts", body = TopIpsResponse),
+        (status = 400, description = "Invalid limit parameter", body = ErrorResponse),
+        (status = 404, description = "Unknown `db` name", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Statistics"
+)]
+pub async fn get_top_ips(db: SelectedDb, query: web::Query<TopIpsQuery>) -> impl Responder {
+    let limit = query.limit.unwrap_or(10);
+
+    if limit == 0 || limit > 100 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Limit must be between 1 and 100".to_string(),
+            code: Some("INVALID_LIMIT".to_string()),
+        });
+    }
+
+    match db.get_top_ips(limit, query.include_ports) {
+        Ok(hosts) => HttpResponse::Ok().json(TopIpsResponse {
+            ips: hosts
+                .into_iter()
+                .map(|(ip_address, open_ports, ports)| IpStats {
+                    ip_address,
+                    open_ports,
+                    ports,
+                })
+                .collect(),
+        }),
+        Err(e) => {
+            error!("Failed to get top hosts: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to retrieve top hosts".to_string(),
+                code: Some("DATABASE_ERROR".to_string()),
+            })
+        }
+    }
+}
+
+/// Start a new scan. Fields left out of the request come from the
+/// template named by `template_id`, then from the server's configuration.
+#[utoipa::path(
+    post,
+    path = "/api/v1/scan/start",
+    request_body = StartScanRequest,
+    responses(
+        (status = 200, description = "Scan started"),
+        (status = 400, description = "Malformed request, invalid addresses, ports, hostnames or exclusions, or a range larger than --api-max-range", body = ErrorResponse),
+        (status = 404, description = "Template not found", body = ErrorResponse),
+        (status = 409, description = "A scan is already running, or the request is invalid", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 429, description = "Running scan quota reached", body = ErrorResponse)
+    ),
+    tag = "Scan Control"
+)]
+pub async fn start_scan(
+    controller: web::Data<crate::service::ScanController>,
+    runtime_scan_state: web::Data<crate::service::RuntimeScanState>,
+    server_args: web::Data<crate::cli::Args>,
+    db: web::Data<SqliteDB>,
+    req: HttpRequest,
+    body: web::Json<Value>,
+) -> impl Responder {
+    if runtime_scan_state.is_cli_scan_running() {
+        return HttpResponse::Conflict().json(ErrorResponse {
+            error: "A CLI-managed scan is already running".to_string(),
+            code: Some("SCAN_ALREADY_RUNNING".to_string()),
+        });
+    }
+
+    let body = body.into_inner();
+    let template_id = body.get("template_id").and_then(Value::as_i64);
+    let request = match template_id {
+        Some(id) => match db.get_scan_template(id) {
+            Ok(Some(template)) => StartScanRequest::from_template(&template.params, body),
+            Ok(None) => return template_not_found(id),
+            Err(e) => return template_database_error("load template", e),
+        },
+        None => serde_json::from_value(body),
+    };
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: format!("Invalid scan request: {}", e),
+                code: Some("INVALID_SCAN_REQUEST".to_string()),
+            })
+        }
+    };
+    if let Err(e) = crate::api::validation::check_scan_request(&request, &server_args) {
+        return HttpResponse::BadRequest().json(e);
+    }
 
-    // No strict validation - allow empty request, will use defaults
-    match controller_guard
-        .start_scan(request.into_inner(), &base_args)
+    let principal = crate::api::audit::principal(&req);
+    match controller
+        .start_scan(request, &server_args, principal.as_deref())
         .await
     {
         Ok(scan_id) => HttpResponse::Ok().json(json!({
@@ -563,12 +1330,14 @@ pub async fn start_scan(
     }
 }
 
-/// Stop the current scan
+/// Stop the current scan, immediately or once the round in progress
+/// completes
 #[utoipa::path(
     post,
     path = "/api/v1/scan/stop",
+    params(StopScanQuery),
     responses(
-        (status = 200, description = "Scan stopped successfully"),
+        (status = 200, description = "Scan stopped successfully, or will stop after the current round"),
         (status = 404, description = "No scan in progress", body = ErrorResponse),
         (status = 409, description = "CLI-managed scan is not API-controllable", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
@@ -576,8 +1345,9 @@ pub async fn start_scan(
     tag = "Scan Control"
 )]
 pub async fn stop_scan(
-    controller: web::Data<std::sync::Arc<tokio::sync::Mutex<crate::service::ScanController>>>,
+    controller: web::Data<crate::service::ScanController>,
     runtime_scan_state: web::Data<crate::service::RuntimeScanState>,
+    query: web::Query<StopScanQuery>,
 ) -> impl Responder {
     if runtime_scan_state.is_cli_scan_running() {
         return HttpResponse::Conflict().json(ErrorResponse {
@@ -587,10 +1357,23 @@ pub async fn stop_scan(
         });
     }
 
-    // Get shared controller with async lock
-    let controller_guard = controller.lock().await;
+    if query.after_round {
+        return match controller.stop_after_round().await {
+            Ok(round) => HttpResponse::Ok().json(json!({
+                "message": format!("Scan will stop after round {} completes", round),
+                "last_round": round
+            })),
+            Err(e) => {
+                error!("Failed to stop scan: {}", e);
+                HttpResponse::NotFound().json(ErrorResponse {
+                    error: format!("Failed to stop scan: {}", e),
+                    code: Some("SCAN_STOP_FAILED".to_string()),
+                })
+            }
+        };
+    }
 
-    match controller_guard.stop_scan().await {
+    match controller.stop_scan().await {
         Ok(()) => HttpResponse::Ok().json(json!({
             "message": "Scan stopped successfully"
         })),
@@ -609,25 +1392,26 @@ pub async fn stop_scan(
     get,
     path = "/api/v1/scan/status",
     responses(
-        (status = 200, description = "Retrieved API/CLI scan status and controllability"),
+        (status = 200, description = "Retrieved API/CLI scan status, controllability, scan-window wait, latency percentiles, probe reply ratios and per-port/per-prefix breakdown"),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "Scan Control"
 )]
 pub async fn get_scan_status(
-    controller: web::Data<std::sync::Arc<tokio::sync::Mutex<crate::service::ScanController>>>,
+    controller: web::Data<crate::service::ScanController>,
     runtime_scan_state: web::Data<crate::service::RuntimeScanState>,
     db: web::Data<SqliteDB>,
 ) -> impl Responder {
-    // Get shared controller with async lock
-    let controller_guard = controller.lock().await;
-
     // Merge API-controlled and CLI-controlled scanner state. In combined mode
     // the long-running CLI scanner is intentionally not owned by ScanController.
-    let controller_status = controller_guard.get_status();
-    let controller_running = controller_guard.is_running();
+    let controller_status = controller.get_status().await;
+    let controller_running = controller.is_running().await;
     let cli_running = runtime_scan_state.is_cli_scan_running();
-    let scan_id = controller_guard.get_scan_id();
+    let scan_id = controller.get_scan_id().await;
+    // Name, owner and round span of the most recent API scan.
+    let session = scan_id
+        .as_deref()
+        .and_then(|id| db.get_scan_session(id).ok().flatten());
     let (effective_status, is_running, source, controllable) = if controller_running {
         (controller_status, true, Some("api"), true)
     } else if cli_running {
@@ -653,18 +1437,52 @@ pub async fn get_scan_status(
     let start_time = db.get_metadata("last_scan_start_time").ok().flatten();
     let stop_time = db.get_metadata("last_scan_stop_time").ok().flatten();
 
+    // Set by the CLI scanner while it is paused outside --scan-window.
+    let scan_window = db
+        .get_metadata("scan_window")
+        .ok()
+        .flatten()
+        .filter(|v| !v.is_empty());
+    let window_wait_until = db
+        .get_metadata("scan_window_wait_until")
+        .ok()
+        .flatten()
+        .filter(|v| !v.is_empty());
+
+    let latency = load_json_metadata(&db, "latency_stats");
+    let breakdown = load_json_metadata(&db, "metrics_breakdown");
+    let replies = load_json_metadata(&db, "reply_stats");
+    // An API scan runs in this process, so its counters are read directly;
+    // a CLI scan only publishes snapshots to the database.
+    let live = controller
+        .live_metrics()
+        .map(|metrics| metrics.live_stats());
+    let queues = match &live {
+        Some(live) => serde_json::to_value(live.queues).ok(),
+        None => load_json_metadata(&db, "queue_stats"),
+    };
+
     HttpResponse::Ok().json(json!({
         "status": effective_status,
         "is_running": is_running,
         "source": source,
         "controllable": controllable,
         "scan_id": scan_id,
+        "session": session,
         "db_status": db_status,
         "current_round": current_round,
         "last_scan_time": last_scan_time,
         "start_time": start_time,
         "stop_time": stop_time,
-        "next_scheduled_scan": null
+        "scan_window": scan_window,
+        "waiting_for_window": window_wait_until.is_some(),
+        "next_scheduled_scan": window_wait_until,
+        "latency": latency,
+        "breakdown": breakdown,
+        "replies": replies,
+        "queues": queues,
+        "live": live,
+        "rounds": controller.round_progress()
     }))
 }
 
@@ -690,7 +1508,8 @@ pub async fn get_scan_history(db: web::Data<SqliteDB>) -> impl Responder {
                         "start_time": record.start_time,
                         "end_time": record.end_time,
                         "total_open_ports": record.total_open_ports,
-                        "ports_scanned": record.ports_scanned
+                        "ports_scanned": record.ports_scanned,
+                        "session": record.session
                     })
                 })
                 .collect();
@@ -715,11 +1534,16 @@ pub async fn get_scan_history(db: web::Data<SqliteDB>) -> impl Responder {
     params(FilterQuery),
     responses(
         (status = 200, description = "CSV export successful", content_type = "text/csv"),
-        (status = 500, description = "Internal server error", body = ErrorResponse)
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 429, description = "Daily export row quota exhausted", body = ErrorResponse)
     ),
     tag = "Export"
 )]
-pub async fn export_csv(db: web::Data<SqliteDB>, query: web::Query<FilterQuery>) -> impl Responder {
+pub async fn export_csv(
+    db: web::Data<SqliteDB>,
+    query: web::Query<FilterQuery>,
+    quota: Option<web::ReqData<ExportQuota>>,
+) -> impl Responder {
     use futures::stream;
 
     const BATCH_SIZE: usize = 1000;
@@ -728,62 +1552,77 @@ pub async fn export_csv(db: web::Data<SqliteDB>, query: web::Query<FilterQuery>)
     let port_filter = query.port;
     let round_filter = query.round;
     let ip_type_filter = query.ip_type.clone();
+    let status_filter = query.status;
+    let scan_id_filter = query.scan_id.clone();
+    let hostname_filter = query.hostname.clone();
+    let has_cves_filter = query.has_cves;
+    let reputation_filter = query.reputation;
+    // The stream ends once the day's export quota is used up.
+    let quota = quota.map(|quota| quota.into_inner());
+    let left = quota.as_ref().map_or(usize::MAX, ExportQuota::remaining);
+
+    let stream = stream::unfold(
+        (1usize, false, true, left),
+        move |(page, done, is_first, left)| {
+            let db = db_clone.clone();
+            let ip = ip_filter.clone();
+            let ip_type = ip_type_filter.clone();
+            let scan_id = scan_id_filter.clone();
+            let hostname = hostname_filter.clone();
+            let quota = quota.clone();
+
+            async move {
+                if done {
+                    return None;
+                }
 
-    let stream = stream::unfold((1usize, false, true), move |(page, done, is_first)| {
-        let db = db_clone.clone();
-        let ip = ip_filter.clone();
-        let ip_type = ip_type_filter.clone();
-
-        async move {
-            if done {
-                return None;
-            }
-
-            match db.get_scan_results(
-                page,
-                BATCH_SIZE,
-                ip.as_deref(),
-                port_filter,
-                round_filter,
-                ip_type.as_deref(),
-            ) {
-                Ok((results, total)) => {
-                    if results.is_empty() {
-                        return None;
-                    }
-
-                    let mut csv_chunk = String::new();
-
-                    if is_first {
-                        csv_chunk
-                            .push_str("ip_address,ip_type,port,scan_round,first_seen,last_seen\n");
+                match db.get_scan_results(
+                    page,
+                    BATCH_SIZE,
+                    ip.as_deref(),
+                    port_filter,
+                    round_filter,
+                    ip_type.as_deref(),
+                    status_filter,
+                    scan_id.as_deref(),
+                    hostname.as_deref(),
+                    has_cves_filter,
+                    reputation_filter,
+                ) {
+                    Ok((mut results, total)) => {
+                        if results.is_empty() {
+                            return None;
+                        }
+                        results.truncate(left);
+                        if let Some(quota) = &quota {
+                            quota.charge(results.len());
+                        }
+
+                        let mut csv_chunk = String::new();
+
+                        if is_first {
+                            csv_chunk.push_str(CSV_HEADER);
+                        }
+
+                        for result in &results {
+                            csv_chunk.push_str(&csv_row(result));
+                        }
+
+                        let left = left - results.len();
+                        let is_done = page * BATCH_SIZE >= total || left == 0;
+                        Some((
+                            Ok::<_, actix_web::Error>(actix_web::web::Bytes::from(csv_chunk)),
+                            (page + 1, is_done, false, left),
+                        ))
                     }
-
-                    for result in results {
-                        csv_chunk.push_str(&format!(
-                            "{},{},{},{},{},{}\n",
-                            result.ip_address,
-                            result.ip_type,
-                            result.port,
-                            result.scan_round,
-                            result.first_seen,
-                            result.last_seen
-                        ));
+                    Err(e) => {
+                        error!("Failed to export CSV batch: {}", e);
+                        None
                     }
-
-                    let is_done = page * BATCH_SIZE >= total;
-                    Some((
-                        Ok::<_, actix_web::Error>(actix_web::web::Bytes::from(csv_chunk)),
-                        (page + 1, is_done, false),
-                    ))
-                }
-                Err(e) => {
-                    error!("Failed to export CSV batch: {}", e);
-                    None
                 }
             }
-        }
-    });
+        },
+    );
 
     HttpResponse::Ok()
         .content_type("text/csv")
@@ -801,13 +1640,15 @@ pub async fn export_csv(db: web::Data<SqliteDB>, query: web::Query<FilterQuery>)
     params(FilterQuery),
     responses(
         (status = 200, description = "JSON export successful", body = Vec<ScanResult>),
-        (status = 500, description = "Internal server error", body = ErrorResponse)
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 429, description = "Daily export row quota exhausted", body = ErrorResponse)
     ),
     tag = "Export"
 )]
 pub async fn export_json(
     db: web::Data<SqliteDB>,
     query: web::Query<FilterQuery>,
+    quota: Option<web::ReqData<ExportQuota>>,
 ) -> impl Responder {
     // Limit export to prevent OOM
     const MAX_EXPORT_SIZE: usize = 50000;
@@ -819,6 +1660,11 @@ pub async fn export_json(
         query.port,
         query.round,
         query.ip_type.as_deref(),
+        query.status,
+        query.scan_id.as_deref(),
+        query.hostname.as_deref(),
+        query.has_cves,
+        query.reputation,
     ) {
         Ok((results, total)) => {
             if total > MAX_EXPORT_SIZE {
@@ -830,21 +1676,14 @@ pub async fn export_json(
                     code: Some("EXPORT_SIZE_EXCEEDED".to_string()),
                 });
             }
+            if let Some(quota) = &quota {
+                if results.len() > quota.remaining() {
+                    return quota.refuse(results.len());
+                }
+                quota.charge(results.len());
+            }
 
-            let api_results: Vec<ScanResult> = results
-                .into_iter()
-                .map(|r| ScanResult {
-                    ip_address: r.ip_address,
-                    ip_type: r.ip_type,
-                    port: r.port,
-                    scan_round: r.scan_round,
-                    first_seen: r.first_seen,
-                    last_seen: r.last_seen,
-                    country: r.country,
-                    city: r.city,
-                    reverse_dns: r.reverse_dns,
-                })
-                .collect();
+            let api_results: Vec<ScanResult> = results.into_iter().map(ScanResult::from).collect();
 
             HttpResponse::Ok().json(api_results)
         }
@@ -858,20 +1697,161 @@ pub async fn export_json(
     }
 }
 
-/// Export scan results as NDJSON (Newline Delimited JSON)
+/// Rows in the results table of an HTML report.
+pub(crate) const MAX_REPORT_ROWS: usize = 5000;
+
+/// Export a self-contained HTML report: summary stats, top ports and
+/// per-round charts, and the filtered results table (first 5000 rows)
+#[utoipa::path(
+    get,
+    path = "/api/v1/export/html",
+    params(FilterQuery),
+    responses(
+        (status = 200, description = "HTML report generated", content_type = "text/html"),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 429, description = "Daily export row quota exhausted", body = ErrorResponse)
+    ),
+    tag = "Export"
+)]
+pub async fn export_html(
+    db: web::Data<SqliteDB>,
+    query: web::Query<FilterQuery>,
+    quota: Option<web::ReqData<ExportQuota>>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let filter = ResultsFilter {
+        ip: query.ip,
+        port: query.port,
+        round: query.round,
+        ip_type: query.ip_type,
+        status: query.status,
+        scan_id: query.scan_id,
+        hostname: query.hostname,
+        has_cves: query.has_cves,
+        reputation: query.reputation,
+    };
+    match ResultsReport::collect(&db, filter, MAX_REPORT_ROWS) {
+        Ok(report) => {
+            if let Some(quota) = &quota {
+                if report.rows() > quota.remaining() {
+                    return quota.refuse(report.rows());
+                }
+                quota.charge(report.rows());
+            }
+            HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                .append_header((
+                    "Content-Disposition",
+                    "attachment; filename=\"scan_report.html\"",
+                ))
+                .body(report.to_html())
+        }
+        Err(e) => {
+            error!("Failed to build HTML report: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to build HTML report".to_string(),
+                code: Some("DATABASE_ERROR".to_string()),
+            })
+        }
+    }
+}
+
+/// `Write` adapter feeding a streaming response body from a blocking task.
+struct ChannelWriter(tokio::sync::mpsc::Sender<web::Bytes>);
+
+impl std::io::Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .blocking_send(web::Bytes::copy_from_slice(buf))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Export scan results as Parquet
+///
+/// Streams every matching row without the JSON/NDJSON size cap, up to what
+/// is left of the daily export quota; the file is written one row group at a
+/// time on a blocking thread.
+#[utoipa::path(
+    get,
+    path = "/api/v1/export/parquet",
+    params(FilterQuery),
+    responses(
+        (status = 200, description = "Parquet export streaming", content_type = "application/vnd.apache.parquet"),
+        (status = 429, description = "Daily export row quota exhausted", body = ErrorResponse)
+    ),
+    tag = "Export"
+)]
+pub async fn export_parquet(
+    db: web::Data<SqliteDB>,
+    query: web::Query<FilterQuery>,
+    quota: Option<web::ReqData<ExportQuota>>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let filter = ResultsFilter {
+        ip: query.ip,
+        port: query.port,
+        round: query.round,
+        ip_type: query.ip_type,
+        status: query.status,
+        scan_id: query.scan_id,
+        hostname: query.hostname,
+        has_cves: query.has_cves,
+        reputation: query.reputation,
+    };
+    let db = db.get_ref().clone();
+    let quota = quota.map(|quota| quota.into_inner());
+    let max_rows = quota.as_ref().map(ExportQuota::remaining);
+    let (tx, rx) = tokio::sync::mpsc::channel::<web::Bytes>(16);
+    tokio::task::spawn_blocking(move || {
+        let out = std::io::BufWriter::with_capacity(256 * 1024, ChannelWriter(tx));
+        // A failure truncates the body, which readers reject as a file
+        // without a footer, so only a complete file is charged.
+        match write_results_parquet(&db, &filter, max_rows, out) {
+            Ok(rows) => {
+                if let Some(quota) = &quota {
+                    quota.charge(rows);
+                }
+            }
+            Err(e) => error!("Failed to export Parquet: {}", e),
+        }
+    });
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv()
+            .await
+            .map(|chunk| (Ok::<_, actix_web::Error>(chunk), rx))
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/vnd.apache.parquet")
+        .append_header((
+            "Content-Disposition",
+            "attachment; filename=\"scan_results.parquet\"",
+        ))
+        .streaming(stream)
+}
+
+/// Export scan results as NDJSON (Newline Delimited JSON)
 #[utoipa::path(
     get,
     path = "/api/v1/export/ndjson",
     params(FilterQuery),
     responses(
         (status = 200, description = "NDJSON export successful", content_type = "application/x-ndjson"),
-        (status = 500, description = "Internal server error", body = ErrorResponse)
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 429, description = "Daily export row quota exhausted", body = ErrorResponse)
     ),
     tag = "Export"
 )]
 pub async fn export_ndjson(
     db: web::Data<SqliteDB>,
     query: web::Query<FilterQuery>,
+    quota: Option<web::ReqData<ExportQuota>>,
 ) -> impl Responder {
     // Limit export to prevent OOM
     const MAX_EXPORT_SIZE: usize = 50000;
@@ -883,6 +1863,11 @@ pub async fn export_ndjson(
         query.port,
         query.round,
         query.ip_type.as_deref(),
+        query.status,
+        query.scan_id.as_deref(),
+        query.hostname.as_deref(),
+        query.has_cves,
+        query.reputation,
     ) {
         Ok((results, total)) => {
             if total > MAX_EXPORT_SIZE {
@@ -895,6 +1880,13 @@ pub async fn export_ndjson(
                 });
             }
 
+            if let Some(quota) = &quota {
+                if results.len() > quota.remaining() {
+                    return quota.refuse(results.len());
+                }
+                quota.charge(results.len());
+            }
+
             let mut ndjson_content = String::new();
 
             for result in results {
@@ -904,7 +1896,12 @@ pub async fn export_ndjson(
                     "port": result.port,
                     "scan_round": result.scan_round,
                     "first_seen": result.first_seen,
-                    "last_seen": result.last_seen
+                    "last_seen": result.last_seen,
+                    "closed_at": result.closed_at,
+                    "scan_id": result.scan_id,
+                    "cves": result.cves,
+                    "reputation_score": result.reputation_score,
+                    "known_scanner": result.known_scanner
                 });
 
                 ndjson_content.push_str(&serde_json::to_string(&json_line).unwrap_or_default());
@@ -953,8 +1950,11 @@ pub async fn get_service_info_by_ip(
     db: web::Data<SqliteDB>,
     ip: web::Path<String>,
 ) -> impl Responder {
-    match db.get_service_info_by_ip(&ip) {
-        Ok(services) => {
+    let found = db
+        .get_service_info_by_ip(&ip)
+        .and_then(|services| Ok((services, db.get_service_vhosts(&ip)?)));
+    match found {
+        Ok((services, vhosts)) => {
             if services.is_empty() {
                 HttpResponse::NotFound().json(ErrorResponse {
                     error: format!("No service info found for IP: {}", ip),
@@ -966,9 +1966,24 @@ pub async fn get_service_info_by_ip(
                     crate::model::IpServiceSummary::assess_risk(&services);
                 let resp_services: Vec<ServiceInfoResponse> =
                     services.iter().map(service_info_to_response).collect();
+                let vhosts = vhosts
+                    .into_iter()
+                    .map(|v| VirtualHostResponse {
+                        port: v.port,
+                        hostname: v.hostname,
+                        http_status: v.http_status,
+                        http_title: v.http_title,
+                        http_server: v.http_server,
+                        tls_subject: v.tls_subject,
+                        tls_issuer: v.tls_issuer,
+                        tls_version: v.tls_version,
+                        detected_at: v.detected_at,
+                    })
+                    .collect();
                 HttpResponse::Ok().json(IpServiceSummaryResponse {
                     ip: ip.to_string(),
                     services: resp_services,
+                    vhosts,
                     ip_type: None,
                     category,
                     risk_score,
@@ -1010,6 +2025,7 @@ pub async fn get_service_summaries(
                     IpServiceSummaryResponse {
                         ip: s.ip,
                         services: s.services.iter().map(service_info_to_response).collect(),
+                        vhosts: Vec::new(),
                         ip_type: s.ip_type,
                         category: s.category,
                         risk_score,
@@ -1033,3 +2049,776 @@ pub async fn get_service_summaries(
         }
     }
 }
+
+fn not_coordinator() -> HttpResponse {
+    HttpResponse::NotFound().json(ErrorResponse {
+        error: "This instance is not running with --coordinator".to_string(),
+        code: Some("NOT_COORDINATOR".to_string()),
+    })
+}
+
+/// `Some(401)` unless the request carries the cluster token.
+fn cluster_unauthorized(req: &HttpRequest, coordinator: &Coordinator) -> Option<HttpResponse> {
+    let authorization = req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    (!coordinator.authorized(authorization)).then(|| {
+        HttpResponse::Unauthorized().json(ErrorResponse {
+            error: "Missing or wrong cluster token".to_string(),
+            code: Some("UNAUTHORIZED".to_string()),
+        })
+    })
+}
+
+fn invalid_worker_id(worker_id: &str) -> Option<HttpResponse> {
+    (worker_id.is_empty() || worker_id.len() > 128).then(|| {
+        HttpResponse::BadRequest().json(ErrorResponse {
+            error: "worker_id must be 1-128 characters".to_string(),
+            code: Some("INVALID_WORKER_ID".to_string()),
+        })
+    })
+}
+
+fn cluster_database_error(action: &str, e: anyhow::Error) -> HttpResponse {
+    error!("Failed to {}: {}", action, e);
+    HttpResponse::InternalServerError().json(ErrorResponse {
+        error: format!("Failed to {}", action),
+        code: Some("DATABASE_ERROR".to_string()),
+    })
+}
+
+/// Longest accepted template name, in characters.
+const MAX_TEMPLATE_NAME_CHARS: usize = 128;
+
+fn template_not_found(id: i64) -> HttpResponse {
+    HttpResponse::NotFound().json(ErrorResponse {
+        error: format!("Template {} not found", id),
+        code: Some("TEMPLATE_NOT_FOUND".to_string()),
+    })
+}
+
+fn template_name_taken(name: &str) -> HttpResponse {
+    HttpResponse::Conflict().json(ErrorResponse {
+        error: format!("A template named '{}' already exists", name),
+        code: Some("TEMPLATE_NAME_TAKEN".to_string()),
+    })
+}
+
+fn template_database_error(action: &str, e: anyhow::Error) -> HttpResponse {
+    error!("Failed to {}: {}", action, e);
+    HttpResponse::InternalServerError().json(ErrorResponse {
+        error: format!("Failed to {}", action),
+        code: Some("DATABASE_ERROR".to_string()),
+    })
+}
+
+/// Check a template body: a non-empty name and parameters that form a valid
+/// `/scan/start` request on their own, naming only known port groups.
+fn invalid_template(body: &ScanTemplateRequest, args: &crate::cli::Args) -> Option<HttpResponse> {
+    let invalid = |error: String| {
+        Some(HttpResponse::BadRequest().json(ErrorResponse {
+            error,
+            code: Some("INVALID_TEMPLATE".to_string()),
+        }))
+    };
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_TEMPLATE_NAME_CHARS {
+        return invalid(format!(
+            "Template name must be 1 to {} characters",
+            MAX_TEMPLATE_NAME_CHARS
+        ));
+    }
+    if !body.params.is_object() {
+        return invalid("Template params must be a JSON object".to_string());
+    }
+    if body.params.get("template_id").is_some() {
+        return invalid("Template params cannot reference another template".to_string());
+    }
+    match StartScanRequest::from_template(&body.params, json!({})) {
+        Ok(request) => crate::api::validation::check_scan_fields(&request, &args.port_groups)
+            .err()
+            .map(|e| HttpResponse::BadRequest().json(e)),
+        Err(e) => invalid(format!("Invalid template params: {}", e)),
+    }
+}
+
+/// List saved scan templates
+#[utoipa::path(
+    get,
+    path = "/api/v1/templates",
+    responses(
+        (status = 200, description = "Templates ordered by name", body = Vec<crate::dao::ScanTemplate>),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Scan Control"
+)]
+pub async fn list_templates(db: web::Data<SqliteDB>) -> impl Responder {
+    match db.list_scan_templates() {
+        Ok(templates) => HttpResponse::Ok().json(templates),
+        Err(e) => template_database_error("list templates", e),
+    }
+}
+
+/// Get one scan template
+#[utoipa::path(
+    get,
+    path = "/api/v1/templates/{id}",
+    params(("id" = i64, Path, description = "Template ID")),
+    responses(
+        (status = 200, description = "The template", body = crate::dao::ScanTemplate),
+        (status = 404, description = "Template not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Scan Control"
+)]
+pub async fn get_template(db: web::Data<SqliteDB>, id: web::Path<i64>) -> impl Responder {
+    let id = id.into_inner();
+    match db.get_scan_template(id) {
+        Ok(Some(template)) => HttpResponse::Ok().json(template),
+        Ok(None) => template_not_found(id),
+        Err(e) => template_database_error("load template", e),
+    }
+}
+
+/// Save a new scan template
+#[utoipa::path(
+    post,
+    path = "/api/v1/templates",
+    request_body = ScanTemplateRequest,
+    responses(
+        (status = 201, description = "Template created", body = crate::dao::ScanTemplate),
+        (status = 400, description = "Invalid name or parameters", body = ErrorResponse),
+        (status = 409, description = "Name already used by another template", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Scan Control"
+)]
+pub async fn create_template(
+    db: web::Data<SqliteDB>,
+    args: web::Data<crate::cli::Args>,
+    body: web::Json<ScanTemplateRequest>,
+) -> impl Responder {
+    if let Some(response) = invalid_template(&body, &args) {
+        return response;
+    }
+    let name = body.name.trim();
+    match db.create_scan_template(name, body.description.as_deref(), &body.params) {
+        Ok(Some(template)) => HttpResponse::Created().json(template),
+        Ok(None) => template_name_taken(name),
+        Err(e) => template_database_error("create template", e),
+    }
+}
+
+/// Replace a scan template
+#[utoipa::path(
+    put,
+    path = "/api/v1/templates/{id}",
+    params(("id" = i64, Path, description = "Template ID")),
+    request_body = ScanTemplateRequest,
+    responses(
+        (status = 200, description = "Template updated", body = crate::dao::ScanTemplate),
+        (status = 400, description = "Invalid name or parameters", body = ErrorResponse),
+        (status = 404, description = "Template not found", body = ErrorResponse),
+        (status = 409, description = "Name already used by another template", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Scan Control"
+)]
+pub async fn update_template(
+    db: web::Data<SqliteDB>,
+    args: web::Data<crate::cli::Args>,
+    id: web::Path<i64>,
+    body: web::Json<ScanTemplateRequest>,
+) -> impl Responder {
+    let id = id.into_inner();
+    if let Some(response) = invalid_template(&body, &args) {
+        return response;
+    }
+    match db.get_scan_template(id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return template_not_found(id),
+        Err(e) => return template_database_error("load template", e),
+    }
+    let name = body.name.trim();
+    match db.update_scan_template(id, name, body.description.as_deref(), &body.params) {
+        Ok(Some(template)) => HttpResponse::Ok().json(template),
+        Ok(None) => template_name_taken(name),
+        Err(e) => template_database_error("update template", e),
+    }
+}
+
+/// Delete a scan template
+#[utoipa::path(
+    delete,
+    path = "/api/v1/templates/{id}",
+    params(("id" = i64, Path, description = "Template ID")),
+    responses(
+        (status = 204, description = "Template deleted"),
+        (status = 404, description = "Template not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Scan Control"
+)]
+pub async fn delete_template(db: web::Data<SqliteDB>, id: web::Path<i64>) -> impl Responder {
+    let id = id.into_inner();
+    match db.delete_scan_template(id) {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => template_not_found(id),
+        Err(e) => template_database_error("delete template", e),
+    }
+}
+
+/// Round and progress changes would pull the ground from under a running
+/// scan, so they are refused until it ends.
+async fn scan_in_progress(
+    controller: &crate::service::ScanController,
+    runtime_scan_state: &crate::service::RuntimeScanState,
+) -> Option<HttpResponse> {
+    if runtime_scan_state.is_cli_scan_running() || controller.has_active_task().await {
+        return Some(HttpResponse::Conflict().json(ErrorResponse {
+            error: "Stop the running scan before changing rounds or progress".to_string(),
+            code: Some("SCAN_RUNNING".to_string()),
+        }));
+    }
+    None
+}
+
+fn admin_database_error(action: &str, e: anyhow::Error) -> HttpResponse {
+    error!("Failed to {}: {}", action, e);
+    HttpResponse::InternalServerError().json(ErrorResponse {
+        error: format!("Failed to {}", action),
+        code: Some("DATABASE_ERROR".to_string()),
+    })
+}
+
+/// Start a new round: advance the current round by one and drop every
+/// resume point, so the next scan covers the new round from the beginning
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/rounds/increment",
+    responses(
+        (status = 200, description = "Round advanced", body = RoundResponse),
+        (status = 409, description = "A scan is running", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn increment_round(
+    db: web::Data<SqliteDB>,
+    controller: web::Data<crate::service::ScanController>,
+    runtime_scan_state: web::Data<crate::service::RuntimeScanState>,
+) -> impl Responder {
+    if let Some(conflict) = scan_in_progress(&controller, &runtime_scan_state).await {
+        return conflict;
+    }
+    match db.increment_round().and_then(|round| {
+        db.clear_progress()?;
+        Ok(round)
+    }) {
+        Ok(current_round) => HttpResponse::Ok().json(RoundResponse { current_round }),
+        Err(e) => admin_database_error("start a new round", e),
+    }
+}
+
+/// Set the current round and drop every resume point
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/rounds/current",
+    request_body = SetRoundRequest,
+    responses(
+        (status = 200, description = "Current round set", body = RoundResponse),
+        (status = 400, description = "Round below 1", body = ErrorResponse),
+        (status = 409, description = "A scan is running", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn set_current_round(
+    db: web::Data<SqliteDB>,
+    controller: web::Data<crate::service::ScanController>,
+    runtime_scan_state: web::Data<crate::service::RuntimeScanState>,
+    body: web::Json<SetRoundRequest>,
+) -> impl Responder {
+    if body.round < 1 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Round must be at least 1".to_string(),
+            code: Some("INVALID_ROUND".to_string()),
+        });
+    }
+    if let Some(conflict) = scan_in_progress(&controller, &runtime_scan_state).await {
+        return conflict;
+    }
+    match db
+        .set_current_round(body.round)
+        .and_then(|_| db.clear_progress())
+    {
+        Ok(()) => HttpResponse::Ok().json(RoundResponse {
+            current_round: body.round,
+        }),
+        Err(e) => admin_database_error("set the current round", e),
+    }
+}
+
+/// Database file and WAL size, per-table row counts, index sizes and
+/// pragma settings
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/db",
+    responses(
+        (status = 200, description = "Database statistics", body = crate::dao::DatabaseStats),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn get_database_stats(db: web::Data<SqliteDB>) -> impl Responder {
+    // Counting rows scans every table; keep it off the async workers.
+    match web::block(move || db.database_stats()).await {
+        Ok(Ok(stats)) => HttpResponse::Ok().json(stats),
+        Ok(Err(e)) => admin_database_error("read database statistics", e),
+        Err(e) => admin_database_error("read database statistics", e.into()),
+    }
+}
+
+/// The `[maintenance]` schedule: per task, its interval, when it last ran,
+/// what that run did and when it is next due
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/maintenance",
+    responses(
+        (status = 200, description = "Maintenance schedule", body = crate::service::MaintenanceStatus),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn get_maintenance_status(
+    db: web::Data<SqliteDB>,
+    args: web::Data<crate::cli::Args>,
+) -> impl Responder {
+    match crate::service::Maintenance::from_args(&args).status(&db) {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(e) => admin_database_error("read the maintenance schedule", e),
+    }
+}
+
+/// State-changing API calls (scan start/stop, templates, rounds, progress),
+/// newest first, with caller, parameters and response status
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Audit log entries, newest first", body = Vec<crate::dao::AuditEntry>),
+        (status = 400, description = "Invalid limit parameter", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn get_audit_log(
+    db: web::Data<SqliteDB>,
+    query: web::Query<AuditQuery>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(100);
+    if limit == 0 || limit > 1000 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Limit must be between 1 and 1000".to_string(),
+            code: Some("INVALID_LIMIT".to_string()),
+        });
+    }
+    match db.get_audit_log(limit, query.before) {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => admin_database_error("read the audit log", e),
+    }
+}
+
+/// Answer a namespace change: 400 `INVALID_NAMESPACE`, 404
+/// `NAMESPACE_NOT_FOUND` or 409 `NAMESPACE_CONFLICT` when it did not apply.
+fn namespace_response(
+    outcome: anyhow::Result<crate::service::NamespaceOutcome>,
+    name: &str,
+    action: &str,
+    mut done: actix_web::HttpResponseBuilder,
+) -> HttpResponse {
+    use crate::service::NamespaceOutcome;
+    match outcome {
+        Ok(NamespaceOutcome::Done(namespace)) => done.json(namespace),
+        Ok(NamespaceOutcome::Invalid(error)) => HttpResponse::BadRequest().json(ErrorResponse {
+            error,
+            code: Some("INVALID_NAMESPACE".to_string()),
+        }),
+        Ok(NamespaceOutcome::Conflict(error)) => HttpResponse::Conflict().json(ErrorResponse {
+            error,
+            code: Some("NAMESPACE_CONFLICT".to_string()),
+        }),
+        Ok(NamespaceOutcome::NotFound) => HttpResponse::NotFound().json(ErrorResponse {
+            error: format!("Namespace {:?} not found", name),
+            code: Some("NAMESPACE_NOT_FOUND".to_string()),
+        }),
+        Err(e) => admin_database_error(action, e),
+    }
+}
+
+/// Namespaces and the callers confined to each
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/namespaces",
+    responses(
+        (status = 200, description = "Namespaces ordered by name", body = Vec<crate::dao::Namespace>),
+        (status = 403, description = "Called from inside a namespace, or by a caller that is not an operator", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn list_namespaces(namespaces: web::Data<crate::service::Namespaces>) -> impl Responder {
+    match namespaces.list() {
+        Ok(namespaces) => HttpResponse::Ok().json(namespaces),
+        Err(e) => admin_database_error("list namespaces", e),
+    }
+}
+
+/// Create a namespace with its own database; its principals then only see
+/// its scans and results
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/namespaces",
+    request_body = NamespaceRequest,
+    responses(
+        (status = 201, description = "Namespace created", body = crate::dao::Namespace),
+        (status = 400, description = "Invalid name or principals, or no --api-operator is set", body = ErrorResponse),
+        (status = 403, description = "Called from inside a namespace, or by a caller that is not an operator", body = ErrorResponse),
+        (status = 409, description = "Name or a principal already taken", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn create_namespace(
+    namespaces: web::Data<crate::service::Namespaces>,
+    body: web::Json<NamespaceRequest>,
+) -> impl Responder {
+    let NamespaceRequest { name, principals } = body.into_inner();
+    let outcome = namespaces.create(&name, principals);
+    namespace_response(outcome, &name, "create namespace", HttpResponse::Created())
+}
+
+/// Replace the principals of a namespace
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/namespaces/{name}",
+    params(("name" = String, Path, description = "Namespace name")),
+    request_body = NamespacePrincipalsRequest,
+    responses(
+        (status = 200, description = "Namespace updated", body = crate::dao::Namespace),
+        (status = 400, description = "Invalid principals", body = ErrorResponse),
+        (status = 403, description = "Called from inside a namespace, or by a caller that is not an operator", body = ErrorResponse),
+        (status = 404, description = "Namespace not found", body = ErrorResponse),
+        (status = 409, description = "A principal belongs to another namespace", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn update_namespace(
+    namespaces: web::Data<crate::service::Namespaces>,
+    name: web::Path<String>,
+    body: web::Json<NamespacePrincipalsRequest>,
+) -> impl Responder {
+    let outcome = namespaces.update(&name, body.into_inner().principals);
+    namespace_response(outcome, &name, "update namespace", HttpResponse::Ok())
+}
+
+/// Unregister a namespace. Its principals see the main database again; its
+/// database file is kept
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/namespaces/{name}",
+    params(("name" = String, Path, description = "Namespace name")),
+    responses(
+        (status = 200, description = "Namespace deleted", body = crate::dao::Namespace),
+        (status = 403, description = "Called from inside a namespace, or by a caller that is not an operator", body = ErrorResponse),
+        (status = 404, description = "Namespace not found", body = ErrorResponse),
+        (status = 409, description = "The namespace has a scan running", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn delete_namespace(
+    namespaces: web::Data<crate::service::Namespaces>,
+    name: web::Path<String>,
+) -> impl Responder {
+    let outcome = namespaces.delete(&name).await;
+    namespace_response(outcome, &name, "delete namespace", HttpResponse::Ok())
+}
+
+/// Drop every resume point, for the CLI and for API scans
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/progress",
+    responses(
+        (status = 204, description = "Progress cleared"),
+        (status = 409, description = "A scan is running", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn clear_progress(
+    db: web::Data<SqliteDB>,
+    controller: web::Data<crate::service::ScanController>,
+    runtime_scan_state: web::Data<crate::service::RuntimeScanState>,
+) -> impl Responder {
+    if let Some(conflict) = scan_in_progress(&controller, &runtime_scan_state).await {
+        return conflict;
+    }
+    match db.clear_progress() {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => admin_database_error("clear progress", e),
+    }
+}
+
+/// Lease the next slice of the target range
+#[utoipa::path(
+    post,
+    path = "/api/v1/cluster/leases",
+    request_body = LeaseRequest,
+    responses(
+        (status = 200, description = "Slice leased to the worker", body = crate::service::LeaseGrant),
+        (status = 204, description = "No slice available right now; ask again later"),
+        (status = 401, description = "Missing or wrong cluster token", body = ErrorResponse),
+        (status = 404, description = "Not running as coordinator", body = ErrorResponse),
+        (status = 410, description = "Scan finished; the worker should exit")
+    ),
+    tag = "Cluster"
+)]
+pub async fn acquire_lease(
+    req: HttpRequest,
+    coordinator: Option<web::Data<Coordinator>>,
+    body: web::Json<LeaseRequest>,
+) -> impl Responder {
+    let Some(coordinator) = coordinator else {
+        return not_coordinator();
+    };
+    if let Some(response) = cluster_unauthorized(&req, &coordinator) {
+        return response;
+    }
+    if let Some(response) = invalid_worker_id(&body.worker_id) {
+        return response;
+    }
+    match coordinator.lease(&body.worker_id) {
+        Ok(LeaseOutcome::Granted(grant)) => HttpResponse::Ok().json(grant),
+        Ok(LeaseOutcome::Wait) => HttpResponse::NoContent().finish(),
+        Ok(LeaseOutcome::Finished) => HttpResponse::Gone().finish(),
+        Err(e) => cluster_database_error("lease a slice", e),
+    }
+}
+
+/// Extend a lease held by the worker
+#[utoipa::path(
+    post,
+    path = "/api/v1/cluster/leases/{id}/heartbeat",
+    params(("id" = i64, Path, description = "Lease ID")),
+    request_body = LeaseRequest,
+    responses(
+        (status = 200, description = "Lease extended"),
+        (status = 401, description = "Missing or wrong cluster token", body = ErrorResponse),
+        (status = 404, description = "Not running as coordinator", body = ErrorResponse),
+        (status = 409, description = "The lease expired and was given to another worker", body = ErrorResponse)
+    ),
+    tag = "Cluster"
+)]
+pub async fn renew_lease(
+    req: HttpRequest,
+    coordinator: Option<web::Data<Coordinator>>,
+    id: web::Path<i64>,
+    body: web::Json<LeaseRequest>,
+) -> impl Responder {
+    let Some(coordinator) = coordinator else {
+        return not_coordinator();
+    };
+    if let Some(response) = cluster_unauthorized(&req, &coordinator) {
+        return response;
+    }
+    match coordinator.renew(id.into_inner(), &body.worker_id) {
+        Ok(true) => HttpResponse::Ok().json(json!({ "renewed": true })),
+        Ok(false) => HttpResponse::Conflict().json(ErrorResponse {
+            error: "Lease is not held by this worker".to_string(),
+            code: Some("LEASE_NOT_HELD".to_string()),
+        }),
+        Err(e) => cluster_database_error("renew lease", e),
+    }
+}
+
+/// Report the open ports of a finished slice
+#[utoipa::path(
+    post,
+    path = "/api/v1/cluster/leases/{id}/complete",
+    params(("id" = i64, Path, description = "Lease ID")),
+    request_body = LeaseReport,
+    responses(
+        (status = 200, description = "Results stored and lease closed"),
+        (status = 400, description = "A result lies outside the lease", body = ErrorResponse),
+        (status = 401, description = "Missing or wrong cluster token", body = ErrorResponse),
+        (status = 404, description = "Not running as coordinator", body = ErrorResponse),
+        (status = 409, description = "The lease expired and was given to another worker", body = ErrorResponse)
+    ),
+    tag = "Cluster"
+)]
+pub async fn complete_lease(
+    req: HttpRequest,
+    coordinator: Option<web::Data<Coordinator>>,
+    id: web::Path<i64>,
+    body: web::Json<LeaseReport>,
+) -> impl Responder {
+    let Some(coordinator) = coordinator else {
+        return not_coordinator();
+    };
+    if let Some(response) = cluster_unauthorized(&req, &coordinator) {
+        return response;
+    }
+    match coordinator.complete(id.into_inner(), body.into_inner()) {
+        Ok(ReportOutcome::Accepted(open)) => HttpResponse::Ok().json(json!({ "accepted": open })),
+        Ok(ReportOutcome::NotHeld) => HttpResponse::Conflict().json(ErrorResponse {
+            error: "Lease is not held by this worker".to_string(),
+            code: Some("LEASE_NOT_HELD".to_string()),
+        }),
+        Ok(ReportOutcome::Invalid(reason)) => HttpResponse::BadRequest().json(ErrorResponse {
+            error: reason,
+            code: Some("RESULT_OUTSIDE_LEASE".to_string()),
+        }),
+        Err(e) => cluster_database_error("store lease results", e),
+    }
+}
+
+/// Get coordinator progress for the current round
+#[utoipa::path(
+    get,
+    path = "/api/v1/cluster/status",
+    responses(
+        (status = 200, description = "Lease counts, completed totals and the leases workers currently hold", body = crate::service::ClusterStatus),
+        (status = 404, description = "Not running as coordinator", body = ErrorResponse)
+    ),
+    tag = "Cluster"
+)]
+pub async fn get_cluster_status(coordinator: Option<web::Data<Coordinator>>) -> impl Responder {
+    let Some(coordinator) = coordinator else {
+        return not_coordinator();
+    };
+    match coordinator.status() {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(e) => cluster_database_error("read cluster progress", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_snippet_escapes_banner_text() {
+        assert_eq!(
+            highlight_snippet("<title>\u{2}Jenkins\u{3}</title>"),
+            "&lt;title&gt;<mark>Jenkins</mark>&lt;/title&gt;"
+        );
+    }
+
+    #[test]
+    fn test_prometheus_latency_skips_empty_histograms() {
+        let stats = json!({
+            "connect": {"count": 4, "p50_ms": 12.0, "p95_ms": 40.0, "p99_ms": 80.5, "max_ms": 90.0},
+            "syn_rtt": {"count": 0, "p50_ms": 0.0, "p95_ms": 0.0, "p99_ms": 0.0, "max_ms": 0.0}
+        });
+        let body = prometheus_latency(&stats);
+        assert!(body.contains("# TYPE ip_scan_connect_latency_seconds summary\n"));
+        assert!(body.contains("ip_scan_connect_latency_seconds{quantile=\"0.5\"} 0.012\n"));
+        assert!(body.contains("ip_scan_connect_latency_seconds{quantile=\"0.99\"} 0.0805\n"));
+        assert!(body.contains("ip_scan_connect_latency_seconds_count 4\n"));
+        assert!(!body.contains("ip_scan_syn_rtt_seconds"));
+    }
+
+    #[test]
+    fn test_prometheus_replies() {
+        let replies =
+            json!({"sent": 10, "syn_ack": 1, "rst": 2, "no_answer_ratio": 0.7, "rst_ratio": 0.2});
+        let body = prometheus_replies(&replies);
+        assert!(body.contains("ip_scan_probe_no_answer_ratio 0.7\n"));
+        assert!(body.contains("ip_scan_probe_rst_ratio 0.2\n"));
+        assert!(prometheus_replies(&json!({"sent": 0})).is_empty());
+    }
+
+    #[test]
+    fn test_prometheus_geo() {
+        let geo = json!({"found": 40, "failed": 3, "timed_out": 1, "in_flight": 2, "queued": 6, "lookups_per_sec": 1.5});
+        let body = prometheus_geo(&geo);
+        assert!(body.contains("# TYPE ip_scan_geo_lookups_total counter\n"));
+        assert!(body.contains("ip_scan_geo_lookups_total{result=\"found\"} 40\n"));
+        assert!(body.contains("ip_scan_geo_lookups_total{result=\"timed_out\"} 1\n"));
+        assert!(body.contains("ip_scan_geo_lookups_per_second 1.5\n"));
+        assert!(body.contains("ip_scan_geo_in_flight 2\n"));
+    }
+
+    #[test]
+    fn test_prometheus_queues() {
+        let queues = json!({
+            "pipeline": {"depth": 12, "capacity": 2000},
+            "results": {"depth": 300, "capacity": 10000},
+            "db_batch_size": 4000,
+            "flush_interval_ms": 500,
+            "db_write": {"count": 3, "p50_ms": 5.0, "p95_ms": 20.0, "p99_ms": 20.0, "max_ms": 20.0}
+        });
+        let body = prometheus_queues(&queues);
+        assert!(body.contains("ip_scan_pipeline_queue_depth 12\n"));
+        assert!(body.contains("ip_scan_result_queue_depth 300\n"));
+        assert!(body.contains("ip_scan_result_queue_capacity 10000\n"));
+        assert!(body.contains("ip_scan_db_batch_size 4000\n"));
+        assert!(body.contains("ip_scan_db_write_seconds{quantile=\"0.95\"} 0.02\n"));
+    }
+
+    #[actix_web::test]
+    async fn test_scans_and_templates_name_configured_port_groups() {
+        use actix_web::test::{call_service, init_service, TestRequest};
+        use actix_web::App;
+        use clap::Parser;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        let controller = web::Data::new(crate::service::ScanController::new(db.clone()));
+        let mut args = crate::cli::Args::try_parse_from(["ip-scan", "--ipv4"]).unwrap();
+        args.port_groups
+            .insert("edge".to_string(), port.to_string());
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(db))
+                .app_data(controller.clone())
+                .app_data(web::Data::new(crate::service::RuntimeScanState::default()))
+                .app_data(web::Data::new(args))
+                .route("/scan/start", web::post().to(start_scan))
+                .route("/templates", web::post().to(create_template)),
+        )
+        .await;
+        let post =
+            |uri: &str, body: Value| TestRequest::post().uri(uri).set_json(body).to_request();
+        let scan = |ports: &str| json!({"start_ip": "127.0.0.1", "end_ip": "127.0.0.1", "ports": ports, "skip_private": false});
+
+        let template = json!({"name": "edge", "params": {"ports": "Edge"}});
+        assert_eq!(
+            call_service(&app, post("/templates", template))
+                .await
+                .status(),
+            201
+        );
+
+        assert_eq!(
+            call_service(&app, post("/scan/start", scan("nosuchgroup")))
+                .await
+                .status(),
+            400
+        );
+        assert_eq!(
+            call_service(&app, post("/scan/start", scan("edge")))
+                .await
+                .status(),
+            200
+        );
+        let _ = controller.stop_scan().await;
+    }
+}
@@ -3,23 +3,56 @@
 //! This module provides REST API endpoints for accessing scan results,
 //! statistics, and controlling the scanner.
 
+mod allowlist;
+mod audit;
+mod databases;
 mod handlers;
 pub mod models;
+mod namespaces;
+mod quota;
+mod read_only;
 mod routes;
+mod tls;
+mod validation;
 
-use actix_web::web;
+use actix_web::{middleware::from_fn, web};
 
 /// Initialize API routes
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(validation::json_config())
+        .app_data(validation::payload_config())
+        .app_data(validation::query_config());
     cfg.service(
         web::scope("/api/v1")
+            // Innermost, so audit and quotas keep the main database.
+            .wrap(from_fn(namespaces::scope_to_namespace))
+            .wrap(from_fn(read_only::reject_changes))
+            .wrap(from_fn(quota::enforce))
+            // Outside the read-only check, so refused changes are recorded too.
+            .wrap(from_fn(audit::record))
+            // Outermost, so oversized bodies are refused before anything reads them.
+            .wrap(from_fn(validation::limit_body))
             .configure(routes::config_results_routes)
+            .configure(routes::config_findings_routes)
+            .configure(routes::config_search_routes)
             .configure(routes::config_stats_routes)
             .configure(routes::config_scan_routes)
+            .configure(routes::config_template_routes)
+            .configure(routes::config_admin_routes)
             .configure(routes::config_export_routes)
-            .configure(routes::config_service_routes),
+            .configure(routes::config_service_routes)
+            .configure(routes::config_geo_routes)
+            .configure(routes::config_cluster_routes),
     );
 }
 
+pub use allowlist::{check_client, ClientAllowlist};
+pub use audit::TrustedProxies;
+pub use databases::AttachedDatabases;
+pub use handlers::prometheus_text;
+pub use quota::Quotas;
+pub use read_only::ReadOnlyApi;
+pub use tls::{on_connect, server_config as tls_server_config};
+
 /// Re-export ApiDoc for OpenAPI documentation
 pub use routes::ApiDoc;
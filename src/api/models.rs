@@ -2,6 +2,7 @@
 //!
 //! This module defines the data structures used in API requests and responses.
 
+use crate::dao::{PortStatus, ReputationFilter};
 use serde::{Deserialize, Deserializer, Serialize};
 use utoipa::{IntoParams, ToSchema};
 
@@ -38,6 +39,39 @@ where
     }
 }
 
+/// Helper function to deserialize optional bool from strings
+fn deserialize_optional_bool_from_string<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: Option<String> = Option::deserialize(deserializer)?;
+    match s {
+        Some(s) => s
+            .parse::<bool>()
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Helper function to deserialize an optional IP family, case-insensitively,
+/// into the `IPv4`/`IPv6` spelling stored in `ip_type`
+fn deserialize_optional_ip_type<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: Option<String> = Option::deserialize(deserializer)?;
+    match s {
+        Some(s) if s.eq_ignore_ascii_case("ipv4") => Ok(Some("IPv4".to_string())),
+        Some(s) if s.eq_ignore_ascii_case("ipv6") => Ok(Some("IPv6".to_string())),
+        Some(s) => Err(serde::de::Error::custom(format!(
+            "ip_type must be IPv4 or IPv6, got {:?}",
+            s
+        ))),
+        None => Ok(None),
+    }
+}
+
 /// Scan result for a specific IP and port
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ScanResult {
@@ -70,6 +104,54 @@ pub struct ScanResult {
     /// Reverse DNS hostname (optional)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reverse_dns: Option<String>,
+
+    /// Abuse contact mailbox from RDAP/whois, for responsible disclosure (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abuse_email: Option<String>,
+
+    /// When the port was marked gone after going unseen; absent while active
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closed_at: Option<String>,
+
+    /// API scan that last saw the port open; absent for CLI scans
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_id: Option<String>,
+
+    /// Candidate CVE IDs for the detected service version (`--cve-db`);
+    /// absent when none matched
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cves: Vec<String>,
+
+    /// Highest abuse score (0-100) a reputation source gave the host;
+    /// absent when unchecked or unscored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reputation_score: Option<u8>,
+
+    /// A reputation source saw the host scanning the internet
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub known_scanner: bool,
+}
+
+impl From<crate::dao::ScanResultDetail> for ScanResult {
+    fn from(r: crate::dao::ScanResultDetail) -> Self {
+        ScanResult {
+            ip_address: r.ip_address,
+            ip_type: r.ip_type,
+            port: r.port,
+            scan_round: r.scan_round,
+            first_seen: r.first_seen,
+            last_seen: r.last_seen,
+            country: r.country,
+            city: r.city,
+            reverse_dns: r.reverse_dns,
+            abuse_email: r.abuse_email,
+            closed_at: r.closed_at,
+            scan_id: r.scan_id,
+            cves: r.cves,
+            reputation_score: r.reputation_score,
+            known_scanner: r.known_scanner,
+        }
+    }
 }
 
 /// Paginated response for scan results
@@ -101,6 +183,8 @@ pub struct SystemInfoResponse {
     pub status: String,
     pub database: String,
     pub server_time: String,
+    /// Values the `db` parameter accepts: "main" and the `--attach-db` names
+    pub databases: Vec<String>,
     pub capabilities: Vec<String>,
     pub endpoints: Vec<String>,
 }
@@ -147,6 +231,27 @@ pub struct TopPortsResponse {
     pub total_open_ports: usize,
 }
 
+/// Open ports of one host
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct IpStats {
+    /// IP address
+    pub ip_address: String,
+
+    /// Number of active open ports on the host
+    pub open_ports: usize,
+
+    /// The open ports, ascending; only with `include_ports=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ports: Option<Vec<u16>>,
+}
+
+/// Top hosts response
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TopIpsResponse {
+    /// Hosts with the most open ports, most exposed first
+    pub ips: Vec<IpStats>,
+}
+
 /// Error response
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ErrorResponse {
@@ -157,6 +262,14 @@ pub struct ErrorResponse {
     pub code: Option<String>,
 }
 
+/// Database selection for results and stats endpoints
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct DbQuery {
+    /// `--attach-db` name to read instead of the main database ("main" or
+    /// absent for the main one)
+    pub db: Option<String>,
+}
+
 /// Query parameters for pagination
 #[derive(Debug, Deserialize, ToSchema, IntoParams)]
 pub struct PaginationQuery {
@@ -190,9 +303,34 @@ pub struct FilterQuery {
     #[serde(default, deserialize_with = "deserialize_optional_i64_from_string")]
     pub round: Option<i64>,
 
-    /// Filter by IP type (IPv4 or IPv6)
-    #[serde(default)]
+    /// Filter by IP type: IPv4 or IPv6, in any case
+    #[serde(default, deserialize_with = "deserialize_optional_ip_type")]
     pub ip_type: Option<String>,
+
+    /// `active` for ports still being seen, `gone` for ports marked closed
+    /// after going unseen for the configured number of rounds
+    #[serde(default)]
+    pub status: Option<PortStatus>,
+
+    /// Only ports last seen by this API scan
+    #[serde(default)]
+    pub scan_id: Option<String>,
+
+    /// Only IPs whose reverse DNS name matches, or that a hostname target
+    /// resolved to; `*` matches any run of characters, e.g. `*.example.com`
+    #[serde(default)]
+    pub hostname: Option<String>,
+
+    /// `true` for ports with candidate CVEs from the local NVD dataset,
+    /// `false` for ports without
+    #[serde(default, deserialize_with = "deserialize_optional_bool_from_string")]
+    pub has_cves: Option<bool>,
+
+    /// `risky` for hosts with an abuse score of 50 or more (or classified
+    /// malicious), `scanner` for hosts known to scan the internet,
+    /// `not-scanner` to leave those out
+    #[serde(default)]
+    pub reputation: Option<ReputationFilter>,
 }
 
 /// Combined query parameters
@@ -213,8 +351,160 @@ pub struct TopPortsQuery {
     pub limit: Option<usize>,
 }
 
-/// Start scan request
+/// Set the current round
 #[derive(Debug, Deserialize, ToSchema)]
+pub struct SetRoundRequest {
+    /// New current round (at least 1)
+    pub round: i64,
+}
+
+/// Current round after an admin change
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RoundResponse {
+    pub current_round: i64,
+}
+
+/// Query parameters for stopping a scan
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct StopScanQuery {
+    /// Let the round in progress complete, then end the scan (default: false)
+    #[serde(default)]
+    pub after_round: bool,
+}
+
+/// Geo enrichment request. Without `ips`, the job takes IPs with open
+/// ports, optionally only those on `port`.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct GeoEnrichRequest {
+    /// IPs to look up, at most 1000
+    #[serde(default)]
+    pub ips: Vec<String>,
+    /// Only IPs with this port open (when `ips` is empty)
+    pub port: Option<u16>,
+    /// Only IPs without current geo data (when `ips` is empty, default: true)
+    pub missing_only: Option<bool>,
+    /// Most IPs to take (when `ips` is empty, default and max: 1000)
+    pub limit: Option<usize>,
+}
+
+/// Query parameters for top hosts
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct TopIpsQuery {
+    /// Number of hosts to return (default: 10, max: 100)
+    #[serde(default)]
+    pub limit: Option<usize>,
+
+    /// Also list each host's open ports (default: false)
+    #[serde(default)]
+    pub include_ports: bool,
+}
+
+/// Query parameters for per-round metrics
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct RoundMetricsQuery {
+    /// Number of most recent rounds to return (default: 50, max: 500)
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Query parameters for an IP's port history
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct PortHistoryQuery {
+    /// Only this port's runs
+    #[serde(default)]
+    pub port: Option<u16>,
+}
+
+/// Query parameters for port lifetimes
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct PortLifetimesQuery {
+    /// Number of ports to return, most runs first (default: 20, max: 100)
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Query parameters for per-prefix scan coverage
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct CoverageQuery {
+    /// Prefix length to group IPv4 addresses by (default: 8, max: 16)
+    #[serde(default)]
+    pub prefix_len: Option<u8>,
+    /// Scan round (default: the current round)
+    #[serde(default)]
+    pub round: Option<i64>,
+}
+
+/// Query parameters for the metrics timeseries
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct TimeseriesQuery {
+    /// Only samples from the last this many minutes (default: all kept,
+    /// max: 1440)
+    #[serde(default)]
+    pub minutes: Option<u64>,
+}
+
+/// Recent metrics samples of the running or latest scan, for live charts
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimeseriesResponse {
+    /// `api` (a scan started through the API) or `cli` (samples a CLI
+    /// scan published to the database); `None` when there are none
+    pub source: Option<String>,
+    /// Seconds between two samples
+    pub interval_secs: u64,
+    /// Samples, oldest first
+    pub samples: Vec<crate::service::MetricsSample>,
+}
+
+/// Query parameters for the audit log
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct AuditQuery {
+    /// Number of entries to return (default: 100, max: 1000)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Only entries older than this `id`, to page back
+    #[serde(default)]
+    pub before: Option<i64>,
+}
+
+/// Query parameters for script findings
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct FindingsQuery {
+    /// Only findings for this IP address (exact match)
+    #[serde(default)]
+    pub ip: Option<String>,
+
+    /// Only findings of this kind, e.g. `tag`
+    #[serde(default)]
+    pub kind: Option<String>,
+
+    /// Number of most recently seen findings to return (default: 100, max: 1000)
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Query parameters for full-text search
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct SearchQuery {
+    /// Words that must all appear, e.g. `Jenkins` or `MikroTik RouterOS`;
+    /// end a word with `*` to match it as a prefix
+    pub q: String,
+
+    /// Number of best matches to return (default: 50, max: 500)
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Query parameters for hostname search
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct HostnameQuery {
+    /// Number of matches to return (default: 100, max: 1000)
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Start scan request. Optional fields left out use the server's own
+/// configuration.
+#[derive(Debug, Default, Deserialize, ToSchema)]
 #[allow(dead_code)]
 pub struct StartScanRequest {
     /// Start IP address
@@ -223,6 +513,11 @@ pub struct StartScanRequest {
     /// End IP address
     pub end_ip: Option<String>,
 
+    /// Hostnames to scan instead of the IP range, resolved (A/AAAA) at the
+    /// start of every round
+    #[serde(default)]
+    pub hostnames: Vec<String>,
+
     /// Ports to scan (comma-separated or range)
     pub ports: Option<String>,
 
@@ -234,6 +529,10 @@ pub struct StartScanRequest {
     #[serde(default = "default_concurrency")]
     pub concurrency: usize,
 
+    /// Ports probed at once on a single host; defaults to the server's
+    /// `--host-concurrency`
+    pub host_concurrency: Option<usize>,
+
     /// Enable SYN scan mode
     #[serde(default)]
     pub syn: bool,
@@ -241,6 +540,119 @@ pub struct StartScanRequest {
     /// Skip private IP ranges
     #[serde(default)]
     pub skip_private: bool,
+
+    /// Probes per rate window (`--max-rate`)
+    pub max_rate: Option<u64>,
+
+    /// Rate window in seconds (`--rate-window-secs`)
+    pub rate_window_secs: Option<u64>,
+
+    /// Probes sent back to back before the steady pace applies; 0 picks
+    /// 10 ms worth (`--rate-burst`)
+    pub rate_burst: Option<usize>,
+
+    /// Queued IPs between the producer and the scanner (`--pipeline-buffer`)
+    pub pipeline_buffer: Option<usize>,
+
+    /// Queued results waiting for the database writer (`--result-buffer`)
+    pub result_buffer: Option<usize>,
+
+    /// Rows per database write batch (`--db-batch-size`)
+    pub db_batch_size: Option<usize>,
+
+    /// Flush partial batches at least this often, in milliseconds
+    /// (`--flush-interval-ms`)
+    pub flush_interval_ms: Option<u64>,
+
+    /// Tune batch size and flush interval from queue depth and write
+    /// latency (`--adaptive-batching`)
+    pub adaptive_batching: Option<bool>,
+
+    /// Connect scan I/O: `tokio` or `uring` (`--io-backend`)
+    pub io_backend: Option<String>,
+
+    /// Send probes from local ports in this range, e.g. `40000-50000`
+    /// (`--source-port-range`)
+    pub source_port_range: Option<String>,
+
+    /// Addresses never probed, as IPs, `a-b` ranges or CIDRs; applied on top
+    /// of the server's `--excludefile`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    /// Keep scanning in rounds until stopped (default false); also
+    /// accepted as `loop`
+    #[serde(default, alias = "loop")]
+    pub loop_mode: bool,
+
+    /// Number of rounds to scan, then finish; implies looping
+    pub rounds: Option<u64>,
+
+    /// Delay between loop-mode rounds in milliseconds (`--round-delay-ms`)
+    pub round_delay_ms: Option<u64>,
+
+    /// Continue the most recent API scan from its saved progress if it did
+    /// not complete (default: false)
+    #[serde(default)]
+    pub resume: bool,
+
+    /// Short label shown in scan status and history
+    pub name: Option<String>,
+
+    /// What the scan is for
+    pub description: Option<String>,
+
+    /// Team or person responsible for the scan
+    pub owner: Option<String>,
+
+    /// Start from a saved template; the other fields given here override it
+    pub template_id: Option<i64>,
+}
+
+impl StartScanRequest {
+    /// Build a request from template parameters overlaid with the fields of
+    /// `overrides`, both JSON objects shaped like this request.
+    pub fn from_template(
+        params: &serde_json::Value,
+        overrides: serde_json::Value,
+    ) -> serde_json::Result<Self> {
+        let mut merged = params.as_object().cloned().unwrap_or_default();
+        if let serde_json::Value::Object(overrides) = overrides {
+            merged.extend(overrides);
+        }
+        merged.remove("template_id");
+        serde_json::from_value(serde_json::Value::Object(merged))
+    }
+}
+
+/// Create or replace a scan template
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ScanTemplateRequest {
+    /// Unique template name
+    pub name: String,
+
+    pub description: Option<String>,
+
+    /// `/scan/start` request fields, without `template_id`
+    #[schema(value_type = StartScanRequest)]
+    pub params: serde_json::Value,
+}
+
+/// Create a namespace
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NamespaceRequest {
+    /// Lowercase letters, digits, `-` and `_`; also the database file name
+    pub name: String,
+
+    /// Callers confined to the namespace: client certificate common names
+    /// or `X-Forwarded-User` users
+    pub principals: Vec<String>,
+}
+
+/// Replace the callers of a namespace
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NamespacePrincipalsRequest {
+    pub principals: Vec<String>,
 }
 
 /// Export format
@@ -250,6 +662,8 @@ pub enum ExportFormat {
     Csv,
     Json,
     NdJson,
+    Html,
+    Parquet,
 }
 
 /// Scan status enumeration
@@ -327,10 +741,61 @@ pub struct ServiceInfoResponse {
     pub detected_at: String,
 }
 
+/// HTTP(S) results for one hostname probed with SNI and `Host` header
+/// (`--sni-hosts`, `--sni-from-rdns`).
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct VirtualHostResponse {
+    pub port: u16,
+    pub hostname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_server: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_issuer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_version: Option<String>,
+    pub detected_at: String,
+}
+
+/// A banner, HTTP or TLS text match from `/search`.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SearchResult {
+    pub ip_address: String,
+    pub port: u16,
+    /// `service` (probe results), `banner` (connect-scan greeting) or
+    /// `vhost` (per-hostname HTTP/TLS results)
+    pub source: String,
+    /// Virtual host the match was served under, for `vhost` results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// HTML-escaped text around the match, with matched words in `<mark>`
+    pub snippet: String,
+}
+
+/// A hostname from `/hostnames/{pattern}` and an address it belongs to.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct HostnameMatch {
+    pub hostname: String,
+    pub ip_address: String,
+    /// `reverse_dns` (PTR name from geo enrichment) or `target` (a hostname
+    /// target that resolved to the address)
+    pub source: String,
+    /// Ports still open on the address
+    pub open_ports: usize,
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct IpServiceSummaryResponse {
     pub ip: String,
     pub services: Vec<ServiceInfoResponse>,
+    /// Per-hostname results; only filled by `/services/{ip}`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vhosts: Vec<VirtualHostResponse>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ip_type: Option<String>,
     pub category: String,
@@ -345,3 +810,46 @@ pub struct ServiceSummaryListResponse {
     pub page: usize,
     pub page_size: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_request_fields_override_template() {
+        let params = json!({ "ports": "web", "max_rate": 500, "syn": true });
+        let request = StartScanRequest::from_template(
+            &params,
+            json!({ "template_id": 1, "max_rate": 50, "name": "night" }),
+        )
+        .unwrap();
+        assert_eq!(request.ports.as_deref(), Some("web"));
+        assert_eq!(request.max_rate, Some(50));
+        assert!(request.syn);
+        assert_eq!(request.name.as_deref(), Some("night"));
+        assert_eq!(request.template_id, None);
+        assert_eq!(request.timeout, default_timeout());
+
+        assert!(StartScanRequest::from_template(&json!({ "timeout": "slow" }), json!({})).is_err());
+    }
+
+    #[test]
+    fn test_ip_type_filter_is_case_insensitive() {
+        let query: FilterQuery = serde_json::from_value(json!({ "ip_type": "ipv6" })).unwrap();
+        assert_eq!(query.ip_type.as_deref(), Some("IPv6"));
+        let query: FilterQuery = serde_json::from_value(json!({ "ip_type": "IPV4" })).unwrap();
+        assert_eq!(query.ip_type.as_deref(), Some("IPv4"));
+        let query: FilterQuery = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(query.ip_type, None);
+        assert!(serde_json::from_value::<FilterQuery>(json!({ "ip_type": "v6" })).is_err());
+    }
+
+    #[test]
+    fn test_loop_is_an_alias_of_loop_mode() {
+        let request: StartScanRequest =
+            serde_json::from_value(json!({ "loop": true, "rounds": 2 })).unwrap();
+        assert!(request.loop_mode);
+        assert_eq!(request.rounds, Some(2));
+    }
+}
@@ -0,0 +1,205 @@
+//! Namespaced callers. A request from a principal that a namespace lists
+//! (see `service::namespaces`) runs against the namespace's database and
+//! scan controller: the middleware puts them in front of the main ones, so
+//! every handler reading `web::Data<SqliteDB>` or `web::Data<ScanController>`
+//! is scoped without knowing it. `--attach-db` databases are hidden, and the
+//! routes that act on the whole deployment (`/admin`, `/cluster` and
+//! `/geo/enrich`) answer 403 `NAMESPACE_FORBIDDEN`.
+//!
+//! Once any namespace exists, a caller that is neither in one nor an
+//! `--api-operator`, including one with no identity, answers 403
+//! `NAMESPACE_REQUIRED`, apart from the health and system probes and the
+//! token-guarded `/cluster` worker protocol. The caller is the one
+//! `audit::principal` names, so `X-Forwarded-User` only counts from a
+//! `--api-trusted-proxy`.
+//!
+//! It sits inside the audit and quota middlewares, which keep using the main
+//! database: the audit trail and quota usage of every caller stay in one
+//! place.
+
+use std::rc::Rc;
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Extensions, ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+
+use crate::api::audit::principal;
+use crate::api::databases::AttachedDatabases;
+use crate::api::models::ErrorResponse;
+use crate::service::{Namespaces, ScanController};
+
+/// Routes that act on the whole deployment rather than on one database.
+const DEPLOYMENT_ROUTES: [&str; 3] = ["/api/v1/admin/", "/api/v1/cluster/", "/api/v1/geo/enrich"];
+
+/// Probes answered to any caller while namespaces exist.
+const PROBE_ROUTES: [&str; 2] = ["/api/v1/healthz", "/api/v1/system"];
+
+fn forbidden(code: &str, error: String) -> HttpResponse {
+    HttpResponse::Forbidden().json(ErrorResponse {
+        error,
+        code: Some(code.to_string()),
+    })
+}
+
+/// Middleware for the `/api/v1` scope; does nothing until a namespace
+/// exists.
+pub async fn scope_to_namespace(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(namespaces) = req
+        .app_data::<web::Data<Namespaces>>()
+        .filter(|namespaces| namespaces.is_active())
+        .cloned()
+    else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+    let principal = principal(req.request());
+    let tenant = principal
+        .as_deref()
+        .and_then(|principal| namespaces.for_principal(principal));
+    let Some(tenant) = tenant else {
+        let allowed = principal.is_some_and(|principal| namespaces.is_operator(&principal))
+            || PROBE_ROUTES.contains(&req.path())
+            || req.path().starts_with("/api/v1/cluster/");
+        if allowed {
+            return next
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body);
+        }
+        let response = forbidden(
+            "NAMESPACE_REQUIRED",
+            "Caller is neither in a namespace nor an --api-operator".to_string(),
+        );
+        return Ok(req.into_response(response).map_into_right_body());
+    };
+    if DEPLOYMENT_ROUTES
+        .iter()
+        .any(|route| req.path().starts_with(route))
+    {
+        let response = forbidden(
+            "NAMESPACE_FORBIDDEN",
+            format!("Not available to namespace {:?}", tenant.name),
+        );
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    let mut data = Extensions::new();
+    data.insert(web::Data::new(tenant.db));
+    data.insert(web::Data::<ScanController>::from(tenant.controller));
+    data.insert(web::Data::new(tenant.runtime));
+    data.insert(web::Data::new(AttachedDatabases::default()));
+    req.add_data_container(Rc::new(data));
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::audit::TrustedProxies;
+    use crate::cli::Args;
+    use crate::dao::SqliteDB;
+    use crate::model::ExcludeList;
+    use actix_web::{middleware::from_fn, test, App};
+    use clap::Parser;
+
+    async fn open_ports(db: web::Data<SqliteDB>) -> HttpResponse {
+        HttpResponse::Ok().body(db.get_total_open_ports_count().unwrap().to_string())
+    }
+
+    #[actix_web::test]
+    async fn test_namespaced_callers_see_only_their_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut args = Args::try_parse_from(["ip-scan"]).unwrap();
+        args.namespace_dir = dir.path().to_str().unwrap().to_string();
+        args.api_operator = vec!["root".to_string()];
+        let main = SqliteDB::new(":memory:").unwrap();
+        main.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 22, true),
+                ("192.0.2.2".parse().unwrap(), 22, true),
+            ],
+            1,
+        )
+        .unwrap();
+        let namespaces = Namespaces::open(main.clone(), &args).unwrap();
+        namespaces.create("red", vec!["alice".to_string()]).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(main.clone()))
+                .app_data(web::Data::new(namespaces))
+                .app_data(web::Data::new(TrustedProxies(
+                    ExcludeList::parse("127.0.0.1").unwrap(),
+                )))
+                .service(
+                    web::scope("/api/v1")
+                        .wrap(from_fn(scope_to_namespace))
+                        // Audit reads the request body first; it still runs
+                        // against the main database.
+                        .wrap(from_fn(crate::api::audit::record))
+                        .route("/results", web::get().to(open_ports))
+                        .route("/results", web::post().to(open_ports))
+                        .route("/admin/db", web::get().to(HttpResponse::Ok))
+                        .route("/healthz", web::get().to(HttpResponse::Ok)),
+                ),
+        )
+        .await;
+        let call = |method: test::TestRequest, user: Option<&str>| {
+            let app = &app;
+            let method = method.peer_addr("127.0.0.1:40000".parse().unwrap());
+            let method = match user {
+                Some(user) => method.insert_header((crate::api::audit::PRINCIPAL_HEADER, user)),
+                None => method,
+            };
+            async move {
+                let response = test::call_service(app, method.to_request()).await;
+                let status = response.status().as_u16();
+                let body = test::read_body(response).await;
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let results = || test::TestRequest::get().uri("/api/v1/results");
+        assert_eq!(call(results(), Some("root")).await, (200, "2".to_string()));
+        assert_eq!(call(results(), Some("alice")).await, (200, "0".to_string()));
+        // Callers that are neither in a namespace nor operators are refused.
+        for user in [None, Some("bob")] {
+            let (status, body) = call(results(), user).await;
+            assert_eq!(status, 403);
+            assert!(body.contains("NAMESPACE_REQUIRED"));
+        }
+        let healthz = || test::TestRequest::get().uri("/api/v1/healthz");
+        assert_eq!(call(healthz(), None).await.0, 200);
+        // From a peer that is not a trusted proxy the header names nobody.
+        let spoofed = results()
+            .peer_addr("192.0.2.9:40000".parse().unwrap())
+            .insert_header((crate::api::audit::PRINCIPAL_HEADER, "root"))
+            .to_request();
+        assert_eq!(test::call_service(&app, spoofed).await.status(), 403);
+        assert_eq!(
+            call(
+                test::TestRequest::post()
+                    .uri("/api/v1/results")
+                    .set_payload("{}"),
+                Some("alice")
+            )
+            .await,
+            (200, "0".to_string())
+        );
+        let admin = || test::TestRequest::get().uri("/api/v1/admin/db");
+        assert_eq!(call(admin(), Some("root")).await.0, 200);
+        assert_eq!(call(admin(), None).await.0, 403);
+        assert_eq!(call(admin(), Some("alice")).await.0, 403);
+        let audit = main.get_audit_log(10, None).unwrap();
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].principal.as_deref(), Some("alice"));
+    }
+}
@@ -0,0 +1,479 @@
+//! `[quotas]`: per-caller limits on `/api/v1` requests per minute, export
+//! rows per UTC day and running API scans. Usage is counted in the database
+//! (`api_quota_usage`, and `scan_sessions` for scans), so API processes
+//! sharing it enforce one budget, and reported in `X-Quota-*` response
+//! headers. An exhausted quota answers 429 `QUOTA_EXCEEDED`.
+//!
+//! The API has no keys of its own: a caller is the common name of its client
+//! certificate under `--api-client-ca`, the user a `--api-trusted-proxy`
+//! names in `X-Forwarded-User`, or the client address without either.
+//! The `/cluster` worker protocol has its own token and is not counted. A
+//! database error while counting is logged and lets the request through.
+//!
+//! An export reserves all of the caller's rows left for the day before it
+//! runs, in one step, so overlapping exports cannot each spend the same
+//! allowance; while one runs, another by the same caller is refused. The
+//! handlers count the rows they serve on the [`ExportQuota`] this middleware
+//! attaches to the request, so checking the quota never costs a count of the
+//! matching rows, and the unused part goes back once the export is done. A
+//! process that dies mid-export keeps its reservation charged for the day.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpResponse};
+use chrono::{DateTime, Duration, Timelike, Utc};
+use tracing::error;
+
+use crate::api::audit::principal;
+use crate::api::models::ErrorResponse;
+use crate::cli::QuotaConfig;
+use crate::dao::SqliteDB;
+
+/// The `[quotas]` settings, as app data while quotas are enabled.
+#[derive(Clone)]
+pub struct Quotas(pub QuotaConfig);
+
+const REQUESTS: &str = "requests";
+const EXPORT_ROWS: &str = "export_rows";
+
+/// The caller usage is counted against.
+fn caller(req: &ServiceRequest) -> String {
+    principal(req.request())
+        .or_else(|| req.peer_addr().map(|addr| addr.ip().to_string()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The export rows reserved for one request, as request data for the
+/// `/export` handlers. A handler that builds its answer in memory refuses
+/// one larger than [`remaining`](Self::remaining); a streaming one stops
+/// there. Either charges the rows it served; whatever is not charged when
+/// the last clone is dropped is released.
+#[derive(Clone)]
+pub struct ExportQuota(Arc<Reservation>);
+
+struct Reservation {
+    db: SqliteDB,
+    principal: String,
+    day: String,
+    limit: u64,
+    granted: u64,
+    served: AtomicU64,
+}
+
+impl ExportQuota {
+    pub fn remaining(&self) -> usize {
+        let served = self.0.served.load(Ordering::Relaxed);
+        self.0.granted.saturating_sub(served) as usize
+    }
+
+    pub fn charge(&self, rows: usize) {
+        self.0.served.fetch_add(rows as u64, Ordering::Relaxed);
+    }
+
+    /// 429 for an export of `rows` that does not fit.
+    pub fn refuse(&self, rows: usize) -> HttpResponse {
+        exceeded(
+            format!(
+                "Export of {} rows exceeds the {} left of the {} rows per day quota",
+                rows,
+                self.remaining(),
+                self.0.limit
+            ),
+            Some(seconds_to_midnight(Utc::now())),
+        )
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let unused = self.granted.saturating_sub(*self.served.get_mut());
+        if unused == 0 {
+            return;
+        }
+        if let Err(e) =
+            self.db
+                .release_quota_usage(&self.principal, EXPORT_ROWS, &self.day, unused as i64)
+        {
+            error!("Failed to release export rows of {}: {}", self.principal, e);
+        }
+    }
+}
+
+fn seconds_to_midnight(now: DateTime<Utc>) -> i64 {
+    let tomorrow = (now + Duration::days(1)).date_naive();
+    (tomorrow.and_hms_opt(0, 0, 0).unwrap().and_utc() - now).num_seconds()
+}
+
+fn add_headers(map: &mut HeaderMap, headers: &[(&'static str, String)]) {
+    for (name, value) in headers {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            map.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+fn exceeded(error: String, retry_after: Option<i64>) -> HttpResponse {
+    let mut response = HttpResponse::TooManyRequests();
+    if let Some(seconds) = retry_after {
+        response.insert_header((RETRY_AFTER, seconds.max(1).to_string()));
+    }
+    response.json(ErrorResponse {
+        error,
+        code: Some("QUOTA_EXCEEDED".to_string()),
+    })
+}
+
+/// Middleware for the `/api/v1` scope, active when [`Quotas`] is app data.
+pub async fn enforce(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let quotas = req
+        .app_data::<web::Data<Quotas>>()
+        .map(|quotas| quotas.get_ref().clone());
+    let db = req
+        .app_data::<web::Data<SqliteDB>>()
+        .map(|db| db.get_ref().clone());
+    let (Some(quotas), Some(db)) = (quotas, db) else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+    if req.method() == Method::OPTIONS || req.path().starts_with("/api/v1/cluster/") {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+
+    let principal = caller(&req);
+    let limits = quotas.0.limits_for(&principal);
+    let now = Utc::now();
+    let mut headers = Vec::new();
+    let mut refusal = None;
+
+    if let Some(limit) = limits.requests_per_minute {
+        let minute = now.format("%Y-%m-%dT%H:%M").to_string();
+        match db.add_quota_usage(&principal, REQUESTS, &minute, 1) {
+            Ok(used) => {
+                let reset = 60 - i64::from(now.second());
+                let used = used.max(0) as u64;
+                headers.push(("x-quota-requests-limit", limit.to_string()));
+                headers.push((
+                    "x-quota-requests-remaining",
+                    limit.saturating_sub(used).to_string(),
+                ));
+                headers.push(("x-quota-requests-reset", reset.to_string()));
+                if used > limit {
+                    refusal = Some(exceeded(
+                        format!("Quota of {} requests per minute exhausted", limit),
+                        Some(reset),
+                    ));
+                }
+            }
+            Err(e) => error!("Failed to count API request by {}: {}", principal, e),
+        }
+    }
+
+    if let Some(limit) = limits
+        .export_rows_per_day
+        .filter(|_| refusal.is_none() && req.path().starts_with("/api/v1/export/"))
+    {
+        let day = now.format("%Y-%m-%d").to_string();
+        let cap = i64::try_from(limit).unwrap_or(i64::MAX);
+        match db.reserve_quota_usage(&principal, EXPORT_ROWS, &day, cap) {
+            Ok(granted) => {
+                let granted = granted.max(0) as u64;
+                headers.push(("x-quota-export-rows-limit", limit.to_string()));
+                headers.push(("x-quota-export-rows-remaining", granted.to_string()));
+                if granted == 0 {
+                    refusal = Some(exceeded(
+                        format!(
+                            "Quota of {} export rows per day exhausted or held by a running export",
+                            limit
+                        ),
+                        Some(seconds_to_midnight(now)),
+                    ));
+                } else {
+                    req.extensions_mut()
+                        .insert(ExportQuota(Arc::new(Reservation {
+                            db: db.clone(),
+                            principal: principal.clone(),
+                            day,
+                            limit,
+                            granted,
+                            served: AtomicU64::new(0),
+                        })));
+                }
+            }
+            Err(e) => error!("Failed to reserve export rows of {}: {}", principal, e),
+        }
+    }
+
+    if let Some(limit) = limits.concurrent_scans.filter(|_| {
+        refusal.is_none() && req.method() == Method::POST && req.path() == "/api/v1/scan/start"
+    }) {
+        match db.count_running_scan_sessions(&principal) {
+            Ok(running) => {
+                let running = running.max(0) as u64;
+                headers.push(("x-quota-scans-limit", limit.to_string()));
+                headers.push(("x-quota-scans-running", running.to_string()));
+                if running >= limit {
+                    refusal = Some(exceeded(
+                        format!("Quota of {} running scans reached", limit),
+                        None,
+                    ));
+                }
+            }
+            Err(e) => error!("Failed to count running scans of {}: {}", principal, e),
+        }
+    }
+
+    if let Some(mut response) = refusal {
+        add_headers(response.headers_mut(), &headers);
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    let mut response = next.call(req).await?;
+    add_headers(response.headers_mut(), &headers);
+    Ok(response.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::audit::TrustedProxies;
+    use crate::api::handlers;
+    use crate::cli::QuotaLimits;
+    use crate::model::ExcludeList;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{middleware::from_fn, App};
+    use std::collections::HashMap;
+
+    #[actix_web::test]
+    async fn test_quotas_are_counted_per_caller_and_reported() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 22, true),
+                ("192.0.2.2".parse().unwrap(), 22, true),
+                ("192.0.2.3".parse().unwrap(), 80, true),
+            ],
+            1,
+        )
+        .unwrap();
+        db.create_scan_session("scan_1", None, None, None, Some("bob"), 1)
+            .unwrap();
+        let quotas = QuotaConfig {
+            enabled: true,
+            default: QuotaLimits {
+                requests_per_minute: Some(3),
+                export_rows_per_day: Some(2),
+                concurrent_scans: Some(1),
+            },
+            principals: HashMap::from([(
+                "alice".to_string(),
+                QuotaLimits {
+                    requests_per_minute: Some(100),
+                    ..Default::default()
+                },
+            )]),
+        };
+        let proxies = TrustedProxies(ExcludeList::parse("127.0.0.1").unwrap());
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(Quotas(quotas)))
+                .app_data(web::Data::new(proxies))
+                .service(
+                    web::scope("/api/v1")
+                        .wrap(from_fn(enforce))
+                        .route("/export/csv", web::get().to(handlers::export_csv))
+                        .route("/scan/start", web::post().to(HttpResponse::Ok)),
+                ),
+        )
+        .await;
+        let call = |user: &str, request: TestRequest| {
+            let request = request
+                .peer_addr("127.0.0.1:40000".parse().unwrap())
+                .insert_header(("X-Forwarded-User", user))
+                .to_request();
+            let app = &app;
+            async move { call_service(app, request).await }
+        };
+        let header = |response: &ServiceResponse<_>, name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        // Two rows match port 22, charged as they stream; nothing is left
+        // of the day's 2 afterwards.
+        let export = || TestRequest::get().uri("/api/v1/export/csv?port=22");
+        let response = call("alice", export()).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            header(&response, "x-quota-export-rows-remaining").as_deref(),
+            Some("2")
+        );
+        assert_eq!(
+            header(&response, "x-quota-requests-remaining").as_deref(),
+            Some("99")
+        );
+        assert_eq!(read_body(response).await.split(|&b| b == b'\n').count(), 4);
+        let response = call("alice", export()).await;
+        assert_eq!(response.status(), 429);
+        assert!(header(&response, "retry-after").is_some());
+
+        // A streamed export stops where the quota runs out.
+        let all = TestRequest::get().uri("/api/v1/export/csv");
+        let response = call("dave", all).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(read_body(response).await.split(|&b| b == b'\n').count(), 4);
+        assert_eq!(
+            db.get_quota_usage(
+                "dave",
+                EXPORT_ROWS,
+                &Utc::now().format("%Y-%m-%d").to_string()
+            )
+            .unwrap(),
+            2
+        );
+
+        // From a peer that is not a trusted proxy the header is ignored and
+        // the address is the caller.
+        let request = TestRequest::get()
+            .uri("/api/v1/export/csv?port=80")
+            .peer_addr("192.0.2.9:40000".parse().unwrap())
+            .insert_header(("X-Forwarded-User", "alice"))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(
+            header(&response, "x-quota-requests-remaining").as_deref(),
+            Some("2")
+        );
+        assert_eq!(
+            db.get_quota_usage(
+                "192.0.2.9",
+                REQUESTS,
+                &Utc::now().format("%Y-%m-%dT%H:%M").to_string()
+            )
+            .unwrap(),
+            1
+        );
+
+        // Bob already runs a scan.
+        let response = call("bob", TestRequest::post().uri("/api/v1/scan/start")).await;
+        assert_eq!(response.status(), 429);
+        assert_eq!(
+            header(&response, "x-quota-scans-running").as_deref(),
+            Some("1")
+        );
+        assert_eq!(
+            call("carol", TestRequest::post().uri("/api/v1/scan/start"))
+                .await
+                .status(),
+            200
+        );
+
+        // Bob's second and third requests this minute; the third is over
+        // both the request and the export quota.
+        let response = call("bob", export()).await;
+        assert_eq!(response.status(), 200);
+        read_body(response).await;
+        let response = call("bob", export()).await;
+        assert_eq!(response.status(), 429);
+        assert_eq!(
+            header(&response, "x-quota-requests-remaining").as_deref(),
+            Some("0")
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_overlapping_exports_cannot_share_an_allowance() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 22, true),
+                ("192.0.2.2".parse().unwrap(), 22, true),
+                ("192.0.2.3".parse().unwrap(), 80, true),
+            ],
+            1,
+        )
+        .unwrap();
+        let quotas = QuotaConfig {
+            enabled: true,
+            default: QuotaLimits {
+                export_rows_per_day: Some(3),
+                ..Default::default()
+            },
+            principals: HashMap::new(),
+        };
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(Quotas(quotas)))
+                .service(
+                    web::scope("/api/v1")
+                        .wrap(from_fn(enforce))
+                        .route("/export/csv", web::get().to(handlers::export_csv))
+                        .route("/export/json", web::get().to(handlers::export_json)),
+                ),
+        )
+        .await;
+        let export = |uri: &str| {
+            TestRequest::get()
+                .uri(uri)
+                .peer_addr("192.0.2.9:40000".parse().unwrap())
+                .to_request()
+        };
+        let remaining = |response: &ServiceResponse<_>| {
+            response
+                .headers()
+                .get("x-quota-export-rows-remaining")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let used = || {
+            db.get_quota_usage(
+                "192.0.2.9",
+                EXPORT_ROWS,
+                &Utc::now().format("%Y-%m-%d").to_string(),
+            )
+            .unwrap()
+        };
+
+        // The first export holds all 3 rows until its stream is read, so an
+        // overlapping one is refused instead of spending them again.
+        let first = call_service(&app, export("/api/v1/export/csv?port=22")).await;
+        assert_eq!(first.status(), 200);
+        assert_eq!(remaining(&first).as_deref(), Some("3"));
+        let second = call_service(&app, export("/api/v1/export/json")).await;
+        assert_eq!(second.status(), 429);
+        assert_eq!(used(), 3);
+
+        // It served 2; the third row goes back.
+        assert_eq!(read_body(first).await.split(|&b| b == b'\n').count(), 4);
+        assert_eq!(used(), 2);
+
+        // An export refused for its size keeps nothing.
+        let third = call_service(&app, export("/api/v1/export/json")).await;
+        assert_eq!(remaining(&third).as_deref(), Some("1"));
+        assert_eq!(third.status(), 429);
+        drop(third);
+        assert_eq!(used(), 2);
+        let fourth = call_service(&app, export("/api/v1/export/json?port=80")).await;
+        assert_eq!(fourth.status(), 200);
+        read_body(fourth).await;
+        assert_eq!(used(), 3);
+    }
+}
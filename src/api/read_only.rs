@@ -0,0 +1,85 @@
+//! `--api-read-only`: serve results and statistics to a wider audience
+//! without the scanning control plane. Every `/api/v1` request that is not a
+//! GET, HEAD or OPTIONS (scan start/stop, template changes, round and
+//! progress changes) and everything under `/admin` is answered with 403
+//! `READ_ONLY` before it reaches a handler.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+
+use crate::api::models::ErrorResponse;
+
+/// App data marking the server read-only.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadOnlyApi;
+
+fn allowed(req: &ServiceRequest) -> bool {
+    matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        && !req.path().starts_with("/api/v1/admin/")
+}
+
+/// Middleware for the `/api/v1` scope; does nothing without [`ReadOnlyApi`].
+pub async fn reject_changes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    if req.app_data::<web::Data<ReadOnlyApi>>().is_some() && !allowed(&req) {
+        let response = HttpResponse::Forbidden().json(ErrorResponse {
+            error: "This API is read-only".to_string(),
+            code: Some("READ_ONLY".to_string()),
+        });
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware::from_fn, test, App};
+
+    #[actix_web::test]
+    async fn test_read_only_api_refuses_control_and_admin_routes() {
+        let app = test::init_service(
+            App::new().app_data(web::Data::new(ReadOnlyApi)).service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(reject_changes))
+                    .route("/results", web::get().to(HttpResponse::Ok))
+                    .route("/scan/start", web::post().to(HttpResponse::Ok))
+                    .route("/templates/{id}", web::delete().to(HttpResponse::Ok))
+                    .route("/admin/db", web::get().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+        let status = |request: test::TestRequest| {
+            let app = &app;
+            async move {
+                test::call_service(app, request.to_request())
+                    .await
+                    .status()
+                    .as_u16()
+            }
+        };
+        assert_eq!(
+            status(test::TestRequest::get().uri("/api/v1/results")).await,
+            200
+        );
+        assert_eq!(
+            status(test::TestRequest::post().uri("/api/v1/scan/start")).await,
+            403
+        );
+        assert_eq!(
+            status(test::TestRequest::delete().uri("/api/v1/templates/1")).await,
+            403
+        );
+        assert_eq!(
+            status(test::TestRequest::get().uri("/api/v1/admin/db")).await,
+            403
+        );
+    }
+}
@@ -7,6 +7,7 @@ use utoipa::OpenApi;
 
 use crate::api::handlers;
 use crate::api::models;
+use crate::api::validation;
 
 /// Configure results-related routes
 pub fn config_results_routes(cfg: &mut web::ServiceConfig) {
@@ -18,7 +19,22 @@ pub fn config_results_routes(cfg: &mut web::ServiceConfig) {
             .route(
                 "/round/{round}",
                 web::get().to(handlers::get_results_by_round),
-            ),
+            )
+            .route("/{ip}/history", web::get().to(handlers::get_port_history)),
+    );
+}
+
+/// Configure script findings routes
+pub fn config_findings_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/findings", web::get().to(handlers::get_script_findings));
+}
+
+/// Configure full-text and hostname search routes
+pub fn config_search_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/search", web::get().to(handlers::search));
+    cfg.route(
+        "/hostnames/{pattern}",
+        web::get().to(handlers::search_hostnames),
     );
 }
 
@@ -37,7 +53,15 @@ pub fn config_stats_routes(cfg: &mut web::ServiceConfig) {
                 "/changes/{round}/{port}",
                 web::get().to(handlers::get_bitmap_changes),
             )
-            .route("/top-ports", web::get().to(handlers::get_top_ports)),
+            .route("/top-ports", web::get().to(handlers::get_top_ports))
+            .route("/top-ips", web::get().to(handlers::get_top_ips))
+            .route("/rounds", web::get().to(handlers::get_round_metrics))
+            .route("/lifetimes", web::get().to(handlers::get_port_lifetimes))
+            .route(
+                "/timeseries",
+                web::get().to(handlers::get_metrics_timeseries),
+            )
+            .route("/coverage", web::get().to(handlers::get_coverage)),
     );
 }
 
@@ -52,13 +76,79 @@ pub fn config_scan_routes(cfg: &mut web::ServiceConfig) {
     );
 }
 
+/// Configure scan template routes
+pub fn config_template_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/templates")
+            .route("", web::get().to(handlers::list_templates))
+            .route("", web::post().to(handlers::create_template))
+            .route("/{id}", web::get().to(handlers::get_template))
+            .route("/{id}", web::put().to(handlers::update_template))
+            .route("/{id}", web::delete().to(handlers::delete_template)),
+    );
+}
+
+/// Configure round, resume-progress and database administration routes
+pub fn config_admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .route(
+                "/rounds/increment",
+                web::post().to(handlers::increment_round),
+            )
+            .route(
+                "/rounds/current",
+                web::put().to(handlers::set_current_round),
+            )
+            .route("/progress", web::delete().to(handlers::clear_progress))
+            .route("/db", web::get().to(handlers::get_database_stats))
+            .route(
+                "/maintenance",
+                web::get().to(handlers::get_maintenance_status),
+            )
+            .route("/audit", web::get().to(handlers::get_audit_log))
+            .route("/namespaces", web::get().to(handlers::list_namespaces))
+            .route("/namespaces", web::post().to(handlers::create_namespace))
+            .route(
+                "/namespaces/{name}",
+                web::put().to(handlers::update_namespace),
+            )
+            .route(
+                "/namespaces/{name}",
+                web::delete().to(handlers::delete_namespace),
+            ),
+    );
+}
+
 /// Configure export routes
 pub fn config_export_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/export")
             .route("/csv", web::get().to(handlers::export_csv))
             .route("/json", web::get().to(handlers::export_json))
-            .route("/ndjson", web::get().to(handlers::export_ndjson)),
+            .route("/ndjson", web::get().to(handlers::export_ndjson))
+            .route("/html", web::get().to(handlers::export_html))
+            .route("/parquet", web::get().to(handlers::export_parquet)),
+    );
+}
+
+/// Configure coordinator routes used by `--worker` processes
+pub fn config_cluster_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/cluster")
+            // Lease reports carry whole batches; overrides the app-wide cap.
+            .app_data(validation::cluster_json_config())
+            .app_data(web::PayloadConfig::default())
+            .route("/status", web::get().to(handlers::get_cluster_status))
+            .route("/leases", web::post().to(handlers::acquire_lease))
+            .route(
+                "/leases/{id}/heartbeat",
+                web::post().to(handlers::renew_lease),
+            )
+            .route(
+                "/leases/{id}/complete",
+                web::post().to(handlers::complete_lease),
+            ),
     );
 }
 
@@ -71,6 +161,16 @@ pub fn config_service_routes(cfg: &mut web::ServiceConfig) {
     );
 }
 
+/// Configure geo enrichment routes
+pub fn config_geo_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/geo")
+            .route("/status", web::get().to(handlers::get_geo_status))
+            .route("/enrich", web::post().to(handlers::enrich_geo))
+            .route("/enrich/{id}", web::get().to(handlers::get_geo_job)),
+    );
+}
+
 /// OpenAPI documentation
 #[derive(OpenApi)]
 #[openapi(
@@ -79,17 +179,52 @@ pub fn config_service_routes(cfg: &mut web::ServiceConfig) {
         handlers::get_results_by_ip,
         handlers::get_results_by_port,
         handlers::get_results_by_round,
+        handlers::get_port_history,
+        handlers::get_script_findings,
+        handlers::search,
+        handlers::search_hostnames,
         handlers::get_stats,
         handlers::get_prometheus_metrics,
         handlers::get_system_info,
         handlers::get_bitmap_changes,
         handlers::get_health,
         handlers::get_top_ports,
+        handlers::get_top_ips,
+        handlers::get_round_metrics,
+        handlers::get_port_lifetimes,
+        handlers::get_metrics_timeseries,
+        handlers::get_coverage,
+        handlers::get_geo_status,
+        handlers::enrich_geo,
+        handlers::get_geo_job,
         handlers::get_scan_status,
+        handlers::start_scan,
+        handlers::stop_scan,
         handlers::get_scan_history,
+        handlers::list_templates,
+        handlers::get_template,
+        handlers::create_template,
+        handlers::update_template,
+        handlers::delete_template,
+        handlers::increment_round,
+        handlers::set_current_round,
+        handlers::clear_progress,
+        handlers::get_database_stats,
+        handlers::get_maintenance_status,
+        handlers::get_audit_log,
+        handlers::list_namespaces,
+        handlers::create_namespace,
+        handlers::update_namespace,
+        handlers::delete_namespace,
         handlers::export_csv,
         handlers::export_json,
         handlers::export_ndjson,
+        handlers::export_html,
+        handlers::export_parquet,
+        handlers::acquire_lease,
+        handlers::renew_lease,
+        handlers::complete_lease,
+        handlers::get_cluster_status,
     ),
     components(
         schemas(
@@ -101,16 +236,69 @@ pub fn config_service_routes(cfg: &mut web::ServiceConfig) {
             models::TopPortsResponse,
             models::ErrorResponse,
             models::PaginationQuery,
+            models::DbQuery,
             models::FilterQuery,
             models::ResultsQuery,
             models::TopPortsQuery,
+            models::TopIpsQuery,
+            models::StopScanQuery,
+            models::IpStats,
+            models::TopIpsResponse,
+            models::RoundMetricsQuery,
+            models::PortHistoryQuery,
+            models::PortLifetimesQuery,
+            models::TimeseriesQuery,
+            models::TimeseriesResponse,
+            models::CoverageQuery,
+            models::AuditQuery,
+            models::FindingsQuery,
+            models::SearchQuery,
+            models::SearchResult,
+            models::HostnameQuery,
+            models::HostnameMatch,
             models::StartScanRequest,
+            models::ScanTemplateRequest,
+            models::NamespaceRequest,
+            models::NamespacePrincipalsRequest,
+            models::SetRoundRequest,
+            models::RoundResponse,
             models::ExportFormat,
             models::ScanStatus,
             models::ServiceInfoResponse,
+            models::VirtualHostResponse,
             models::IpServiceSummaryResponse,
             models::ServiceSummaryListResponse,
             crate::dao::PortChange,
+            crate::dao::PortStatus,
+            crate::dao::RoundMetrics,
+            crate::dao::Coverage,
+            crate::dao::PrefixCoverage,
+            crate::dao::PortHistoryRun,
+            crate::dao::PortLifetime,
+            crate::dao::AuditEntry,
+            crate::dao::Namespace,
+            crate::dao::ScanSession,
+            crate::dao::ScanTemplate,
+            crate::dao::ScriptFinding,
+            crate::dao::ClusterLease,
+            crate::dao::ClusterProgress,
+            crate::dao::DatabaseStats,
+            crate::dao::TableStats,
+            crate::dao::IndexStats,
+            crate::service::MetricsSample,
+            crate::service::GeoStatus,
+            crate::service::GeoStats,
+            crate::service::GeoProviderStats,
+            crate::service::GeoJob,
+            crate::service::GeoJobState,
+            models::GeoEnrichRequest,
+            crate::service::MaintenanceStatus,
+            crate::service::MaintenanceTaskStatus,
+            crate::service::LeaseRequest,
+            crate::service::LeaseGrant,
+            crate::service::LeaseResult,
+            crate::service::LeaseReport,
+            crate::service::ClusterStatus,
         )
     ),
     tags(
@@ -120,6 +308,8 @@ pub fn config_service_routes(cfg: &mut web::ServiceConfig) {
         (name = "Scan Control", description = "Scan control endpoints"),
         (name = "Export", description = "Data export endpoints"),
         (name = "Services", description = "Service detection endpoints"),
+        (name = "Cluster", description = "Coordinator endpoints for distributed workers"),
+        (name = "Admin", description = "Round and resume-progress administration"),
     )
 )]
 pub struct ApiDoc;
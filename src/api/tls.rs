@@ -0,0 +1,199 @@
+//! `--api-tls-cert`/`--api-tls-key`: serve the API over HTTPS, and with
+//! `--api-client-ca` require every client to present a certificate issued by
+//! one of the listed CAs. The handshake rejects any other client before a
+//! request is read. A verified certificate's subject common name is the
+//! caller the audit log, quotas and scan sessions record, in place of
+//! `X-Forwarded-User`.
+
+use std::any::Any;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::Extensions;
+use anyhow::{anyhow, Context, Result};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use tokio::net::TcpStream;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::cli::Args;
+
+/// The verified certificate a client connected with, as connection data.
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+    /// Subject common name, or the whole subject without one
+    pub subject: String,
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to read certificates from {}", path))?;
+    if certs.is_empty() {
+        return Err(anyhow!("No PEM certificates found in {}", path));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to read the private key from {}", path))?
+        .ok_or_else(|| anyhow!("No PEM private key found in {}", path))
+}
+
+/// The HTTPS settings the flags ask for, or `None` to serve plain HTTP.
+pub fn server_config(args: &Args) -> Result<Option<ServerConfig>> {
+    let (Some(cert), Some(key)) = (&args.api_tls_cert, &args.api_tls_key) else {
+        return Ok(None);
+    };
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match &args.api_client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("Invalid CA certificate in {}", ca))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .with_context(|| format!("Unusable client CA bundle {}", ca))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder
+        .with_single_cert(load_certs(cert)?, load_key(key)?)
+        .with_context(|| format!("{} does not match {}", key, cert))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Some(config))
+}
+
+fn subject(cert: &CertificateDer) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(cert.as_ref()).ok()?;
+    let subject = cert.subject();
+    let common_name = subject
+        .iter_common_name()
+        .next()
+        .and_then(|name| name.as_str().ok());
+    Some(common_name.map_or_else(|| subject.to_string(), str::to_string))
+}
+
+/// `HttpServer::on_connect` callback keeping the client certificate of a
+/// TLS connection as [`ClientCertificate`]. rustls has verified it by the
+/// time the connection reaches actix.
+pub fn on_connect(connection: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    let (_, session) = stream.get_ref();
+    if let Some(subject) = session
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .and_then(subject)
+    {
+        data.insert(ClientCertificate { subject });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, Issuer, KeyPair};
+    use rustls::{ClientConfig, ClientConnection, ServerConnection};
+    use std::io::Write;
+    use std::path::Path;
+
+    fn write(dir: &Path, name: &str, pem: &str) -> String {
+        let path = dir.join(name);
+        File::create(&path)
+            .unwrap()
+            .write_all(pem.as_bytes())
+            .unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Run a handshake in memory; the server side once it completes.
+    fn handshake(
+        server: &ServerConfig,
+        client: ClientConfig,
+    ) -> Result<ServerConnection, rustls::Error> {
+        let mut server = ServerConnection::new(Arc::new(server.clone()))?;
+        let mut client = ClientConnection::new(Arc::new(client), "localhost".try_into().unwrap())?;
+        while server.is_handshaking() || client.is_handshaking() {
+            let mut buf = Vec::new();
+            client.write_tls(&mut buf).unwrap();
+            server.read_tls(&mut buf.as_slice()).unwrap();
+            server.process_new_packets()?;
+            buf.clear();
+            server.write_tls(&mut buf).unwrap();
+            client.read_tls(&mut buf.as_slice()).unwrap();
+            client.process_new_packets()?;
+        }
+        Ok(server)
+    }
+
+    #[test]
+    fn test_client_certificates_are_required_and_name_the_caller() {
+        let dir = std::env::temp_dir().join(format!("ip-scan-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Certificates are generated here; the repository holds no keys.
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let issuer = Issuer::from_params(&ca_params, &ca_key);
+        let server_key = KeyPair::generate().unwrap();
+        let server = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&server_key, &issuer)
+            .unwrap();
+        let client_key = KeyPair::generate().unwrap();
+        let mut client_params = CertificateParams::new(Vec::new()).unwrap();
+        client_params
+            .distinguished_name
+            .push(DnType::CommonName, "inventory-sync");
+        let client = client_params.signed_by(&client_key, &issuer).unwrap();
+
+        let mut args = Args::try_parse_from(["ip-scan"]).unwrap();
+        assert!(server_config(&args).unwrap().is_none());
+        args.api_tls_cert = Some(write(&dir, "server.pem", &server.pem()));
+        args.api_tls_key = Some(write(&dir, "server.key", &server_key.serialize_pem()));
+        args.api_client_ca = Some(write(&dir, "ca.pem", &ca.pem()));
+        let config = server_config(&args).unwrap().unwrap();
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        let builder = || {
+            ClientConfig::builder_with_provider(provider.clone())
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots.clone())
+        };
+        assert!(handshake(&config, builder().with_no_client_auth()).is_err());
+        let with_cert = builder()
+            .with_client_auth_cert(
+                vec![client.der().clone()],
+                PrivateKeyDer::Pkcs8(client_key.serialize_der().into()),
+            )
+            .unwrap();
+        let session = handshake(&config, with_cert).unwrap();
+        let presented = &session.peer_certificates().unwrap()[0];
+        assert_eq!(subject(presented).as_deref(), Some("inventory-sync"));
+
+        // A key that does not belong to the certificate is refused at startup.
+        args.api_tls_key = Some(write(&dir, "client.key", &client_key.serialize_pem()));
+        assert!(server_config(&args).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
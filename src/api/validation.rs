@@ -0,0 +1,346 @@
+//! Input checks shared by every API route, so a bad request is answered
+//! with a structured [`ErrorResponse`] before any handler acts on it:
+//!
+//! * request bodies are capped at [`MAX_BODY_BYTES`] (413
+//!   `PAYLOAD_TOO_LARGE`), whether or not they declare a length, except for
+//!   the `/cluster` worker protocol whose lease reports carry whole batches
+//!   of results and keep actix's larger defaults;
+//! * malformed JSON bodies and query strings answer 400 `INVALID_JSON` and
+//!   `INVALID_QUERY` instead of actix's plain-text errors;
+//! * scan requests and templates have their addresses, ports, hostnames and
+//!   exclusions checked before a scan task is spawned, and the range a scan
+//!   would cover is held to `--api-max-range`.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::http::header::CONTENT_LENGTH;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+
+use crate::api::models::{ErrorResponse, StartScanRequest};
+use crate::cli::Args;
+use crate::model::{is_hostname, parse_port_range, ExcludeList};
+use crate::service::MAX_TARGET_HOSTNAMES;
+
+/// Largest request body outside `/cluster`; scan requests and templates are
+/// a few hundred bytes.
+pub const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Largest `/cluster` JSON body, actix's own default.
+const CLUSTER_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+fn error(code: &str, error: String) -> ErrorResponse {
+    ErrorResponse {
+        error,
+        code: Some(code.to_string()),
+    }
+}
+
+fn payload_too_large(limit: usize) -> HttpResponse {
+    HttpResponse::PayloadTooLarge().json(error(
+        "PAYLOAD_TOO_LARGE",
+        format!("Request body exceeds {} bytes", limit),
+    ))
+}
+
+/// Middleware for the `/api/v1` scope rejecting bodies whose
+/// `Content-Length` exceeds [`MAX_BODY_BYTES`] before they are read.
+/// Chunked bodies are held to the same limit by [`json_config`] and
+/// [`payload_config`] as they are read.
+pub async fn limit_body(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if length.is_some_and(|length| length > MAX_BODY_BYTES)
+        && !req.path().starts_with("/api/v1/cluster/")
+    {
+        return Ok(req
+            .into_response(payload_too_large(MAX_BODY_BYTES))
+            .map_into_right_body());
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// JSON extractor settings: bodies capped at [`MAX_BODY_BYTES`], with
+/// structured errors.
+pub fn json_config() -> web::JsonConfig {
+    json_config_limited(MAX_BODY_BYTES)
+}
+
+/// Raw body extractor settings capped at [`MAX_BODY_BYTES`].
+pub fn payload_config() -> web::PayloadConfig {
+    web::PayloadConfig::new(MAX_BODY_BYTES)
+}
+
+/// JSON extractor settings for the `/cluster` scope: actix's default size
+/// limit, which lease reports rely on, with structured errors.
+pub fn cluster_json_config() -> web::JsonConfig {
+    json_config_limited(CLUSTER_BODY_BYTES)
+}
+
+fn json_config_limited(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(move |err, _req| {
+            let response = match &err {
+                JsonPayloadError::Overflow { .. }
+                | JsonPayloadError::OverflowKnownLength { .. } => payload_too_large(limit),
+                _ => HttpResponse::BadRequest()
+                    .json(error("INVALID_JSON", format!("Invalid JSON body: {}", err))),
+            };
+            InternalError::from_response(err, response).into()
+        })
+}
+
+/// Query string extractor settings with structured errors.
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|err, _req| {
+        let response = HttpResponse::BadRequest().json(error(
+            "INVALID_QUERY",
+            format!("Invalid query string: {}", err),
+        ));
+        InternalError::from_response(err, response).into()
+    })
+}
+
+fn parse_ip(field: &str, value: &str) -> Result<IpAddr, ErrorResponse> {
+    value.parse().map_err(|_| {
+        error(
+            "INVALID_IP",
+            format!("{} {:?} is not an IP address", field, value),
+        )
+    })
+}
+
+fn check_ports(ports: &str, groups: &HashMap<String, String>) -> Result<(), ErrorResponse> {
+    match parse_port_range(ports, groups) {
+        Ok(list) if list.is_empty() => Err(error(
+            "INVALID_PORTS",
+            "Port list selects no ports".to_string(),
+        )),
+        Ok(_) => Ok(()),
+        Err(e) => Err(error("INVALID_PORTS", e)),
+    }
+}
+
+/// Check the fields a request or template sets, on their own: IP syntax,
+/// the port spec (with the configured port `groups`), hostnames and
+/// exclusions.
+pub fn check_scan_fields(
+    request: &StartScanRequest,
+    groups: &HashMap<String, String>,
+) -> Result<(), ErrorResponse> {
+    if let Some(start_ip) = &request.start_ip {
+        parse_ip("start_ip", start_ip)?;
+    }
+    if let Some(end_ip) = &request.end_ip {
+        parse_ip("end_ip", end_ip)?;
+    }
+    if let Some(ports) = &request.ports {
+        check_ports(ports, groups)?;
+    }
+    if request.hostnames.len() > MAX_TARGET_HOSTNAMES {
+        return Err(error(
+            "INVALID_HOSTNAME",
+            format!("At most {} hostnames are supported", MAX_TARGET_HOSTNAMES),
+        ));
+    }
+    if let Some(name) = request.hostnames.iter().find(|name| !is_hostname(name)) {
+        return Err(error(
+            "INVALID_HOSTNAME",
+            format!("{:?} is not a hostname", name),
+        ));
+    }
+    if !request.exclude.is_empty() {
+        ExcludeList::parse(&request.exclude.join("\n")).map_err(|e| error("INVALID_EXCLUDE", e))?;
+    }
+    Ok(())
+}
+
+/// Check a scan request against the server's configuration `args`, which
+/// supplies the fields it leaves out: besides [`check_scan_fields`], the
+/// range must run from a lower to a higher address of one family and cover
+/// at most `--api-max-range` addresses.
+pub fn check_scan_request(request: &StartScanRequest, args: &Args) -> Result<(), ErrorResponse> {
+    check_scan_fields(request, &args.port_groups)?;
+    check_ports(
+        request.ports.as_deref().unwrap_or(&args.ports),
+        &args.port_groups,
+    )?;
+    if !request.hostnames.is_empty() {
+        return Ok(());
+    }
+
+    let start_ip = request.start_ip.as_ref().or(args.start_ip.as_ref());
+    let end_ip = request.end_ip.as_ref().or(args.end_ip.as_ref());
+    // The scan falls back to the whole IPv4 space without both ends.
+    let (start, end) = match start_ip.zip(end_ip) {
+        Some((start, end)) => (parse_ip("start_ip", start)?, parse_ip("end_ip", end)?),
+        None => {
+            let (start, end) = Args::get_default_ipv4_range();
+            (parse_ip("start_ip", &start)?, parse_ip("end_ip", &end)?)
+        }
+    };
+    let size = match (start, end) {
+        (IpAddr::V4(start), IpAddr::V4(end)) if start <= end => {
+            u128::from(u32::from(end) - u32::from(start)) + 1
+        }
+        (IpAddr::V6(start), IpAddr::V6(end)) if start <= end => {
+            (u128::from(end) - u128::from(start)).saturating_add(1)
+        }
+        (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => {
+            return Err(error(
+                "INVALID_RANGE",
+                format!("start_ip {} is after end_ip {}", start, end),
+            ))
+        }
+        _ => {
+            return Err(error(
+                "INVALID_RANGE",
+                "start_ip and end_ip must be the same IP version".to_string(),
+            ))
+        }
+    };
+    if args.api_max_range > 0 && size > u128::from(args.api_max_range) {
+        return Err(error(
+            "RANGE_TOO_LARGE",
+            format!(
+                "Range {} - {} covers {} addresses; at most {} are allowed",
+                start, end, size, args.api_max_range
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use actix_web::{middleware::from_fn, App};
+    use clap::Parser;
+    use serde_json::{json, Value};
+
+    fn rejection(request: Value, args: &Args) -> Option<String> {
+        let request: StartScanRequest = serde_json::from_value(request).unwrap();
+        check_scan_request(&request, args)
+            .err()
+            .and_then(|e| e.code)
+    }
+
+    #[test]
+    fn test_scan_requests_are_checked_before_they_start() {
+        let mut args = Args::try_parse_from(["ip-scan"]).unwrap();
+        args.api_max_range = 65536;
+        let code = |request: Value| rejection(request, &args);
+
+        assert_eq!(
+            code(json!({"start_ip": "10.0.0.0", "end_ip": "10.0.255.255", "ports": "web"})),
+            None
+        );
+        assert_eq!(
+            code(json!({"start_ip": "10.0.0.256", "end_ip": "10.0.0.9"})).as_deref(),
+            Some("INVALID_IP")
+        );
+        assert_eq!(
+            code(json!({"start_ip": "10.0.0.9", "end_ip": "10.0.0.1"})).as_deref(),
+            Some("INVALID_RANGE")
+        );
+        assert_eq!(
+            code(json!({"start_ip": "10.0.0.1", "end_ip": "2001:db8::1"})).as_deref(),
+            Some("INVALID_RANGE")
+        );
+        assert_eq!(
+            code(json!({"start_ip": "10.0.0.0", "end_ip": "10.1.0.0"})).as_deref(),
+            Some("RANGE_TOO_LARGE")
+        );
+        // Without a range the scan covers the whole IPv4 space.
+        assert_eq!(code(json!({})).as_deref(), Some("RANGE_TOO_LARGE"));
+        assert_eq!(
+            code(json!({"start_ip": "2001:db8::", "end_ip": "2001:db8::ffff:ffff"})).as_deref(),
+            Some("RANGE_TOO_LARGE")
+        );
+        assert_eq!(
+            code(json!({"hostnames": ["example.com"], "ports": "80-70"})).as_deref(),
+            Some("INVALID_PORTS")
+        );
+        assert_eq!(code(json!({"hostnames": ["example.com"]})), None);
+        assert_eq!(
+            code(json!({"hostnames": ["bad host"]})).as_deref(),
+            Some("INVALID_HOSTNAME")
+        );
+        assert_eq!(
+            code(json!({"start_ip": "10.0.0.1", "end_ip": "10.0.0.9", "exclude": ["10.0.0.0/33"]}))
+                .as_deref(),
+            Some("INVALID_EXCLUDE")
+        );
+
+        args.api_max_range = 0;
+        assert_eq!(rejection(json!({}), &args), None);
+    }
+
+    #[actix_web::test]
+    async fn test_oversized_and_malformed_bodies_get_structured_errors() {
+        let app = init_service(
+            App::new().app_data(json_config()).service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(limit_body))
+                    .route(
+                        "/templates",
+                        web::post().to(|_: web::Json<Value>| async { HttpResponse::Ok().finish() }),
+                    )
+                    .service(
+                        web::scope("/cluster")
+                            .app_data(cluster_json_config())
+                            .route(
+                                "/report",
+                                web::post().to(|_: web::Json<Value>| async {
+                                    HttpResponse::Ok().finish()
+                                }),
+                            ),
+                    ),
+            ),
+        )
+        .await;
+        let post = |uri: &str, body: String| {
+            TestRequest::post()
+                .uri(uri)
+                .insert_header(("content-type", "application/json"))
+                .set_payload(body)
+                .to_request()
+        };
+        let large = json!({"pad": "x".repeat(MAX_BODY_BYTES)}).to_string();
+
+        let response = call_service(&app, post("/api/v1/templates", large.clone())).await;
+        assert_eq!(response.status(), 413);
+        let body: ErrorResponse = read_body_json(response).await;
+        assert_eq!(body.code.as_deref(), Some("PAYLOAD_TOO_LARGE"));
+
+        // Without a Content-Length (chunked) the extractor enforces the cap.
+        let mut chunked = post("/api/v1/templates", large.clone());
+        chunked.headers_mut().remove(CONTENT_LENGTH);
+        let response = call_service(&app, chunked).await;
+        assert_eq!(response.status(), 413);
+        let body: ErrorResponse = read_body_json(response).await;
+        assert_eq!(body.code.as_deref(), Some("PAYLOAD_TOO_LARGE"));
+
+        let response = call_service(&app, post("/api/v1/templates", "{".to_string())).await;
+        assert_eq!(response.status(), 400);
+        let body: ErrorResponse = read_body_json(response).await;
+        assert_eq!(body.code.as_deref(), Some("INVALID_JSON"));
+
+        let response = call_service(&app, post("/api/v1/cluster/report", large)).await;
+        assert_eq!(response.status(), 200);
+    }
+}
@@ -1,7 +1,11 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
+/// Upper bound for each `--priority-weights` entry.
+const MAX_PRIORITY_WEIGHT: u32 = 64;
+
 fn parse_positive_usize(value: &str) -> Result<usize, String> {
     value
         .parse::<usize>()
@@ -28,17 +32,252 @@ fn parse_positive_u64(value: &str) -> Result<u64, String> {
         })
 }
 
+/// Subcommands; without one, ip-scan runs the scanner and/or API.
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Send SIGTERM to the daemon recorded in the pid file and wait for it to exit
+    Stop,
+    /// Report whether the daemon is alive and print its scan status from the API
+    Status,
+    /// Write a fully commented sample configuration file with current defaults
+    InitConfig {
+        /// Destination path
+        #[arg(default_value = "config.toml")]
+        path: PathBuf,
+        /// Overwrite the destination if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Render a human-readable report from the database
+    Report {
+        #[command(subcommand)]
+        report: ReportCommand,
+    },
+    /// Export scan results to a file for analysis tools
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Export {
+        #[command(subcommand)]
+        list: Option<Box<ExportCommand>>,
+        /// parquet (Snappy-compressed, for Spark/DuckDB/pandas), or csv /
+        /// json in the /api/v1/export/csv and /export/json layouts
+        #[arg(long, default_value = "parquet", value_parser = ["parquet", "csv", "json"])]
+        format: String,
+        /// Destination file
+        #[arg(long, short, required = true)]
+        output: Option<PathBuf>,
+        /// Also write <output>.manifest.json: record count, size and
+        /// SHA-256 of the export, the filters and scan state, and the time
+        #[arg(long)]
+        manifest: bool,
+        /// Sign the manifest with this Ed25519 private key (PKCS#8 PEM) into
+        /// <output>.manifest.sig; implies --manifest
+        #[arg(long, value_name = "PEM")]
+        sign_key: Option<PathBuf>,
+        #[command(flatten)]
+        filter: ResultFilterArgs,
+    },
+    /// Load results exported by another instance (the /api/v1/export/csv
+    /// layout); ports already stored keep the widest first/last seen window
+    Import {
+        /// csv
+        #[arg(long, default_value = "csv", value_parser = ["csv"])]
+        format: String,
+        /// Files to import, in the order given
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Database maintenance
+    Db {
+        #[command(subcommand)]
+        db: DbCommand,
+    },
+    /// List network interfaces with their addresses and MACs, and whether
+    /// SYN scans can use them (datalink channel opens, gateway resolves)
+    Interfaces {
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Estimate how long one round takes in SYN and connect mode, after
+    /// exclusions; nothing is scanned
+    Estimate {
+        /// IP, CIDR or range; defaults to --target, else all of IPv4
+        #[arg(long)]
+        range: Option<String>,
+        /// Ports or port groups; defaults to --ports
+        #[arg(long)]
+        ports: Option<String>,
+        /// Probes per --rate-window-secs; defaults to --max-rate
+        #[arg(long)]
+        max_rate: Option<u64>,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum DbCommand {
+    /// Consolidate databases from sharded scans into one: results keep the
+    /// widest first/last seen window, port bitmaps are OR-ed and per-round
+    /// counters are summed
+    Merge {
+        /// Destination database; created if missing, merged into otherwise
+        output: PathBuf,
+        /// Source databases, merged in the order given
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Re-encrypt the database (opened with --db-key, if any) under a new
+    /// key, or decrypt it; encrypts a plain database. Stop the scanner and
+    /// API first.
+    Rekey {
+        /// Key to encrypt with
+        #[arg(
+            long,
+            env = "SCAN_DB_NEW_KEY",
+            hide_env_values = true,
+            required_unless_present = "decrypt",
+            conflicts_with = "decrypt"
+        )]
+        new_key: Option<String>,
+        /// Write the database unencrypted
+        #[arg(long)]
+        decrypt: bool,
+    },
+    /// File and WAL size, row counts per table, index sizes and pragma
+    /// settings; read-only, so safe next to a running scanner
+    Stats {
+        /// Print JSON, as returned by `/api/v1/admin/db`
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum ExportCommand {
+    /// Distinct addresses with a matching open port, one per line, sorted;
+    /// the file feeds --input-file of a follow-up scan
+    Targets {
+        #[command(flatten)]
+        filter: ResultFilterArgs,
+        /// Write the list here instead of stdout
+        #[arg(long, short, visible_alias = "out")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum ReportCommand {
+    /// Changes between two stored rounds: newly exposed services, hosts gone
+    /// dark and per-port deltas
+    Diff {
+        /// Baseline round
+        #[arg(long)]
+        from: i64,
+        /// Round compared against the baseline
+        #[arg(long)]
+        to: i64,
+        /// md (Markdown) or html (self-contained page)
+        #[arg(long, default_value = "md", value_parser = ["md", "html"])]
+        format: String,
+        /// Write the report here instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Hosts and ports listed per section; counts are always complete
+        #[arg(long, default_value_t = 200, value_parser = parse_positive_usize)]
+        limit: usize,
+    },
+    /// Self-contained HTML report: summary stats, top ports and per-round
+    /// charts, and the filtered results table (same as /api/v1/export/html)
+    Html {
+        #[command(flatten)]
+        filter: ResultFilterArgs,
+        /// Write the report here instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Result rows listed in the table
+        #[arg(long, default_value_t = 5000, value_parser = parse_positive_usize)]
+        limit: usize,
+    },
+}
+
+/// Result filters shared by `report html` and `export`, matching the API's
+/// `ip`/`port`/`round`/`ip_type` query parameters
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+pub struct ResultFilterArgs {
+    /// Filter by IP address (partial match)
+    #[arg(long)]
+    pub ip: Option<String>,
+    /// Filter by port
+    #[arg(long)]
+    pub port: Option<u16>,
+    /// Filter by scan round
+    #[arg(long)]
+    pub round: Option<i64>,
+    /// Filter by IP type
+    #[arg(long, value_parser = ["IPv4", "IPv6"])]
+    pub ip_type: Option<String>,
+    /// active (still seen) or gone (unseen for --stale-rounds rounds)
+    #[arg(long, value_parser = ["active", "gone"])]
+    pub status: Option<String>,
+    /// Only ports last seen by this API scan
+    #[arg(long)]
+    pub scan_id: Option<String>,
+    /// Only IPs whose reverse DNS name matches or this hostname target
+    /// resolved to; `*` matches any run of characters
+    #[arg(long)]
+    pub hostname: Option<String>,
+    /// Only ports with candidate CVEs (`--cve-db`)
+    #[arg(long)]
+    pub has_cves: bool,
+    /// risky (abuse score >= 50 or malicious), scanner (known internet
+    /// scanner) or not-scanner
+    #[arg(long, value_parser = ["risky", "scanner", "not-scanner"])]
+    pub reputation: Option<String>,
+}
+
+impl ResultFilterArgs {
+    pub fn to_filter(&self) -> crate::service::ResultsFilter {
+        crate::service::ResultsFilter {
+            ip: self.ip.clone(),
+            port: self.port,
+            round: self.round,
+            ip_type: self.ip_type.clone(),
+            status: self.status.as_deref().map(|status| match status {
+                "gone" => crate::dao::PortStatus::Gone,
+                _ => crate::dao::PortStatus::Active,
+            }),
+            scan_id: self.scan_id.clone(),
+            hostname: self.hostname.clone(),
+            has_cves: self.has_cves.then_some(true),
+            reputation: self
+                .reputation
+                .as_deref()
+                .map(|reputation| match reputation {
+                    "risky" => crate::dao::ReputationFilter::Risky,
+                    "scanner" => crate::dao::ReputationFilter::Scanner,
+                    _ => crate::dao::ReputationFilter::NotScanner,
+                }),
+        }
+    }
+}
+
 #[derive(Parser, Debug, Clone)]
 #[command(name = "ip-scan")]
 #[command(author = "IP Scanner")]
 #[command(version = "0.1.0")]
 #[command(about = "High-performance IPv4/IPv6 port scanner", long_about = None)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Configuration file path (optional)
     /// Can be provided with --config flag.
     #[arg(
         long = "config",
         visible_alias = "config-flag",
+        global = true,
         env = "SCAN_CONFIG",
         value_name = "FILE_PATH"
     )]
@@ -56,7 +295,8 @@ pub struct Args {
     #[arg(short = 'e', long, env = "SCAN_END_IP")]
     pub end_ip: Option<String>,
 
-    /// Port range (e.g., "80", "1-1000", "22,80,443")
+    /// Port range (e.g., "80", "1-1000", "22,80,443") or named groups such as
+    /// "web", "db", "mail", "remote", "file" (extensible via [port_groups])
     #[arg(
         short = 'p',
         long,
@@ -65,6 +305,11 @@ pub struct Args {
     )]
     pub ports: String,
 
+    /// File with one port, range or port group per line (`#` comments),
+    /// added to --ports; replaces the default --ports list when that is unset
+    #[arg(long, env = "SCAN_PORTS_FILE", value_name = "FILE")]
+    pub ports_file: Option<String>,
+
     /// Connection timeout in milliseconds
     #[arg(short = 't', long, env = "SCAN_TIMEOUT", default_value = "500", value_parser = parse_positive_u64)]
     pub timeout: u64,
@@ -73,6 +318,10 @@ pub struct Args {
     #[arg(short = 'c', long, env = "SCAN_CONCURRENCY", default_value = "500", value_parser = parse_positive_usize)]
     pub concurrency: usize,
 
+    /// Ports probed at once on a single host, within --concurrency
+    #[arg(long, env = "SCAN_HOST_CONCURRENCY", default_value = "4", value_parser = parse_positive_usize)]
+    pub host_concurrency: usize,
+
     /// Database file path
     #[arg(
         short = 'd',
@@ -82,6 +331,11 @@ pub struct Args {
     )]
     pub database: String,
 
+    /// Encrypt the database with SQLCipher under this key (builds with the
+    /// sqlcipher feature only); prefer the environment variable
+    #[arg(long, env = "SCAN_DB_KEY", hide_env_values = true)]
+    pub db_key: Option<String>,
+
     /// Print the resolved scan plan and exit without opening sockets or a database.
     #[arg(long, env = "SCAN_DRY_RUN", action = clap::ArgAction::SetTrue)]
     pub dry_run: bool,
@@ -90,10 +344,19 @@ pub struct Args {
     #[arg(short = 'v', long, env = "SCAN_VERBOSE")]
     pub verbose: bool,
 
+    /// Log only warnings and errors, and print no end-of-round summary
+    #[arg(short = 'q', long, env = "SCAN_QUIET", action = clap::ArgAction::SetTrue)]
+    pub quiet: bool,
+
     /// Enable infinite loop scanning mode
     #[arg(short = 'l', long, env = "SCAN_LOOP_MODE", action = clap::ArgAction::SetTrue)]
     pub loop_mode: bool,
 
+    /// Re-verify the open ports already in the database with connect probes
+    /// instead of scanning a range, then exit
+    #[arg(long, env = "SCAN_RESCAN_OPEN", action = clap::ArgAction::SetTrue)]
+    pub rescan_open: bool,
+
     /// Scan IPv4 addresses
     #[arg(long, env = "SCAN_IPV4", action = clap::ArgAction::SetTrue)]
     pub ipv4: bool,
@@ -119,25 +382,119 @@ pub struct Args {
     pub api: bool,
 
     /// API server port (default: 9090)
-    #[arg(long, env = "SCAN_API_PORT", default_value = "9090")]
+    #[arg(long, env = "SCAN_API_PORT", default_value = "9090", global = true)]
     pub api_port: u16,
 
     /// API server bind address (default: 0.0.0.0)
-    #[arg(long, env = "SCAN_API_HOST", default_value = "0.0.0.0")]
+    #[arg(long, env = "SCAN_API_HOST", default_value = "0.0.0.0", global = true)]
     pub api_host: String,
 
     /// Enable Swagger UI (default: true when API is enabled)
     #[arg(long, env = "SCAN_SWAGGER_UI", action = clap::ArgAction::SetTrue)]
     pub swagger_ui: bool,
 
+    /// Serve another result database read-only next to the main one, as
+    /// NAME=PATH; results and stats endpoints read it with `?db=NAME`
+    #[arg(
+        long,
+        env = "SCAN_ATTACH_DB",
+        value_name = "NAME=PATH",
+        value_delimiter = ','
+    )]
+    pub attach_db: Vec<String>,
+
+    /// Only answer API requests from these networks (IPs, ranges or CIDRs;
+    /// repeatable); others get 403. Unset allows every client
+    #[arg(
+        long,
+        env = "SCAN_API_ALLOW_CIDR",
+        value_name = "CIDR",
+        value_delimiter = ','
+    )]
+    pub api_allow_cidr: Vec<String>,
+
+    /// Reverse proxies (IPs, ranges or CIDRs; repeatable) whose
+    /// X-Forwarded-User header names the API caller. Unset ignores the
+    /// header; callers are then named by client certificate or address
+    #[arg(
+        long,
+        env = "SCAN_API_TRUSTED_PROXY",
+        value_name = "CIDR",
+        value_delimiter = ','
+    )]
+    pub api_trusted_proxy: Vec<String>,
+
+    /// Serve results only: scan control, template changes and the admin
+    /// routes answer 403
+    #[arg(
+        long,
+        env = "SCAN_API_READ_ONLY",
+        action = clap::ArgAction::SetTrue,
+        conflicts_with = "coordinator"
+    )]
+    pub api_read_only: bool,
+
+    /// Largest address range an API scan request may cover; larger ones
+    /// answer 400 `RANGE_TOO_LARGE`. 0 allows any size
+    #[arg(long, env = "SCAN_API_MAX_RANGE", default_value = "4294967296")]
+    pub api_max_range: u64,
+
+    /// Serve the API over HTTPS with this PEM certificate chain; needs
+    /// --api-tls-key
+    #[arg(long, env = "SCAN_API_TLS_CERT", value_name = "PATH")]
+    pub api_tls_cert: Option<String>,
+
+    /// PEM private key of --api-tls-cert
+    #[arg(long, env = "SCAN_API_TLS_KEY", value_name = "PATH")]
+    pub api_tls_key: Option<String>,
+
+    /// Require API clients to present a certificate issued by a CA in this
+    /// PEM bundle (mutual TLS); needs --api-tls-cert
+    #[arg(long, env = "SCAN_API_CLIENT_CA", value_name = "PATH")]
+    pub api_client_ca: Option<String>,
+
+    /// Directory holding the database of each API namespace, as
+    /// `<NAME>.db`; namespaces are managed under /admin/namespaces
+    #[arg(
+        long,
+        env = "SCAN_NAMESPACE_DIR",
+        value_name = "DIR",
+        default_value = "namespaces"
+    )]
+    pub namespace_dir: String,
+
+    /// Principals (client certificate names, or X-Forwarded-User users from
+    /// --api-trusted-proxy) that keep the main database and manage
+    /// namespaces; repeatable. Once a namespace exists, callers neither in a
+    /// namespace nor listed here get 403
+    #[arg(
+        long,
+        env = "SCAN_API_OPERATOR",
+        value_name = "NAME",
+        value_delimiter = ','
+    )]
+    pub api_operator: Vec<String>,
+
     #[arg(
         short = 'T',
         long,
         env = "SCAN_TARGET",
-        help = "Target: IP, CIDR (e.g. 192.168.1.0/24), or range (e.g. 192.168.1.1-192.168.1.255)"
+        help = "Target: IP, CIDR (e.g. 192.168.1.0/24), range (e.g. 192.168.1.1-192.168.1.255), or comma-separated hostnames resolved each round"
     )]
     pub target: Option<String>,
 
+    /// Domain list or certificate-transparency export (crt.sh JSON) whose
+    /// names are resolved and scanned each round; with a range target or
+    /// --start-ip/--end-ip, only addresses inside it are scanned
+    #[arg(long, env = "SCAN_SEED_DOMAINS", value_name = "PATH")]
+    pub seed_domains: Option<String>,
+
+    /// Scan the addresses listed in this file instead of a range: IPs,
+    /// CIDRs and ranges in --excludefile syntax, e.g. the output of
+    /// `export targets`. Read again every round
+    #[arg(long, env = "SCAN_INPUT_FILE", value_name = "PATH")]
+    pub input_file: Option<String>,
+
     #[arg(long, env = "SCAN_PRESET", help = "Scan preset: quick, standard, deep")]
     pub preset: Option<String>,
 
@@ -149,6 +506,16 @@ pub struct Args {
     )]
     pub output_format: String,
 
+    /// End-of-round summary on stdout: text (tables) or json (one line per
+    /// round, for scripts)
+    #[arg(long, env = "SCAN_SUMMARY_FORMAT", default_value = "text")]
+    pub summary_format: String,
+
+    /// Print one JSON document with the run's totals on stdout when the scan
+    /// exits, instead of the end-of-round summaries; logs go to stderr
+    #[arg(long, env = "SCAN_JSON_SUMMARY", action = clap::ArgAction::SetTrue)]
+    pub json_summary: bool,
+
     /// Run only API server (no scanning)
     #[arg(long, env = "SCAN_API_ONLY", action = clap::ArgAction::SetTrue)]
     pub api_only: bool,
@@ -156,10 +523,53 @@ pub struct Args {
     /// Run only scanner (no API)
     #[arg(long, env = "SCAN_NO_API", action = clap::ArgAction::SetTrue)]
     pub no_api: bool,
+
+    /// Split the IPv4 target range into leases for `--worker` processes and
+    /// collect their results through the API; this process does not scan
+    #[arg(
+        long,
+        env = "SCAN_COORDINATOR",
+        action = clap::ArgAction::SetTrue,
+        conflicts_with_all = ["worker", "no_api"]
+    )]
+    pub coordinator: bool,
+
+    /// Scan leases from the coordinator at this base URL, e.g.
+    /// http://10.0.0.1:9090, instead of a local target range
+    #[arg(long, env = "SCAN_WORKER", value_name = "URL")]
+    pub worker: Option<String>,
+
+    /// Name reported to the coordinator (default: <hostname>-<pid>)
+    #[arg(long, env = "SCAN_WORKER_ID")]
+    pub worker_id: Option<String>,
+
+    /// IPv4 addresses per coordinator lease
+    #[arg(long, env = "SCAN_LEASE_SIZE", default_value = "65536", value_parser = parse_positive_u64)]
+    pub lease_size: u64,
+
+    /// Seconds a lease stays assigned without a worker heartbeat
+    #[arg(long, env = "SCAN_LEASE_SECS", default_value = "300", value_parser = parse_positive_u64)]
+    pub lease_secs: u64,
+
+    /// Shared secret workers send as `Authorization: Bearer <token>`
+    #[arg(long, env = "SCAN_CLUSTER_TOKEN", hide_env_values = true)]
+    pub cluster_token: Option<String>,
+
     /// MaxMind GeoIP database path (optional)
     #[arg(long, env = "SCAN_GEOIP_DB")]
     pub geoip_db: Option<String>,
 
+    /// whois server list (whois-rust/node-whois servers.json format);
+    /// overrides the built-in list
+    #[arg(long, env = "SCAN_WHOIS_SERVERS", value_name = "PATH")]
+    pub whois_servers: Option<String>,
+
+    /// Offline IP range -> country/ASN datasets (CSV or TSV, e.g.
+    /// iptoasn.com's ip2asn-combined.tsv); consulted after MaxMind and
+    /// before RDAP/whois/ip-api.com
+    #[arg(long, env = "SCAN_GEO_CSV", value_name = "PATH", value_delimiter = ',')]
+    pub geo_csv: Vec<String>,
+
     /// Disable Geolocation lookup
     #[arg(long, env = "SCAN_NO_GEO", action = clap::ArgAction::SetTrue)]
     pub no_geo: bool,
@@ -176,6 +586,58 @@ pub struct Args {
     #[arg(long, env = "SCAN_PROBE_CONCURRENCY", default_value = "50", value_parser = parse_positive_usize)]
     pub probe_concurrency: usize,
 
+    /// Maximum service probes started per second across all hosts
+    #[arg(long, env = "SCAN_PROBE_RATE", default_value = "100", value_parser = parse_positive_u64)]
+    pub probe_rate: u64,
+
+    /// Wait this long on each open port's connect-scan socket for a
+    /// greeting, which service probing then uses instead of reconnecting
+    /// (0 = close right away; connect scans on the tokio backend only)
+    #[arg(
+        long,
+        env = "SCAN_GRAB_BANNER_MS",
+        default_value = "0",
+        value_name = "MS"
+    )]
+    pub grab_banner_ms: u64,
+
+    /// Hosts-file style list (`IP hostname...` per line) of names to probe
+    /// on each IP's HTTP(S) ports with matching SNI and Host headers
+    #[arg(long, env = "SCAN_SNI_HOSTS", value_name = "PATH")]
+    pub sni_hosts: Option<String>,
+
+    /// Also probe each IP's HTTP(S) ports as its reverse-DNS name
+    #[arg(long, env = "SCAN_SNI_FROM_RDNS", action = clap::ArgAction::SetTrue)]
+    pub sni_from_rdns: bool,
+
+    /// NVD CVE API 2.0 JSON file, or a directory of them, used to record
+    /// candidate CVEs for probed service versions
+    #[arg(long, env = "SCAN_CVE_DB", value_name = "PATH")]
+    pub cve_db: Option<String>,
+
+    /// Look up hosts with open ports in these reputation services
+    /// (abuseipdb, greynoise)
+    #[arg(
+        long,
+        env = "SCAN_REPUTATION_PROVIDERS",
+        value_name = "NAME,...",
+        value_delimiter = ',',
+        value_parser = clap::builder::PossibleValuesParser::new(crate::service::REPUTATION_PROVIDERS)
+    )]
+    pub reputation_providers: Vec<String>,
+
+    /// Reputation lookups started per hour, per provider
+    #[arg(long, env = "SCAN_REPUTATION_RATE", default_value = "40", value_parser = parse_positive_u64)]
+    pub reputation_rate: u64,
+
+    /// AbuseIPDB API key (required for the abuseipdb provider)
+    #[arg(long, env = "SCAN_ABUSEIPDB_KEY", hide_env_values = true)]
+    pub abuseipdb_key: Option<String>,
+
+    /// GreyNoise API key; the community API also works without one
+    #[arg(long, env = "SCAN_GREYNOISE_KEY", hide_env_values = true)]
+    pub greynoise_key: Option<String>,
+
     /// GeoIP/WHOIS/reverse-DNS enrichment concurrency
     #[arg(long, env = "SCAN_GEO_CONCURRENCY", default_value = "8", value_parser = parse_positive_usize)]
     pub geo_concurrency: usize,
@@ -195,17 +657,186 @@ pub struct Args {
     #[arg(long, env = "SCAN_FLUSH_INTERVAL_MS", default_value = "1000")]
     pub flush_interval_ms: u64,
 
+    /// Let the DB writer grow or shrink --db-batch-size and
+    /// --flush-interval-ms from result queue occupancy and write latency
+    #[arg(long, env = "SCAN_ADAPTIVE_BATCHING", action = clap::ArgAction::SetTrue)]
+    pub adaptive_batching: bool,
+
+    /// Seconds between background WAL checkpoints; an idle WAL is
+    /// truncated to zero bytes
+    #[arg(long, env = "SCAN_WAL_CHECKPOINT_SECS", default_value = "30", value_parser = parse_positive_u64)]
+    pub wal_checkpoint_secs: u64,
+
+    /// Truncate the WAL at the next checkpoint once it reaches this many MiB,
+    /// even while writes continue
+    #[arg(long, env = "SCAN_WAL_TRUNCATE_MB", default_value = "64", value_parser = parse_positive_u64)]
+    pub wal_truncate_mb: u64,
+
     #[arg(long, env = "SCAN_MAX_RATE", default_value = "100000")]
     pub max_rate: u64,
 
     #[arg(long, env = "SCAN_RATE_WINDOW_S", default_value = "1")]
     pub rate_window_secs: u64,
 
+    /// Probes that may be sent back to back before the smooth --max-rate
+    /// pace applies (0 = 10 ms worth of traffic)
+    #[arg(long, env = "SCAN_RATE_BURST", default_value = "0")]
+    pub rate_burst: usize,
+
+    /// Seconds a SYN scan keeps listening for replies after its last probe
+    /// before the receiver is stopped
+    #[arg(long, env = "SCAN_SYN_LINGER_S", default_value = "1")]
+    pub syn_linger_secs: u64,
+
+    /// Connect scan I/O: "tokio" (a task per port) or "uring" (batched
+    /// io_uring submissions, Linux only)
+    #[arg(long, env = "SCAN_IO_BACKEND", default_value = "tokio", value_parser = ["tokio", "uring"])]
+    pub io_backend: String,
+
+    /// Send probes from local ports in this range, e.g. "40000-50000", for
+    /// firewall allow-listing (SYN and connect scans)
+    #[arg(long, env = "SCAN_SOURCE_PORT_RANGE", value_name = "START-END")]
+    pub source_port_range: Option<String>,
+
     /// Delay between scan rounds in loop mode (milliseconds, default 0).
     /// Set above 0 when scanning a single fixed range to avoid hammering the
     /// same subnet each pass; leave at 0 for continuous range sweeps.
     #[arg(long, env = "SCAN_ROUND_DELAY_MS", default_value = "0")]
     pub round_delay_ms: u64,
+
+    /// Mark an open port gone (set `closed_at`) once it has not been seen
+    /// for this many consecutive completed rounds; 0 disables aging
+    #[arg(long, env = "SCAN_STALE_ROUNDS", default_value = "3")]
+    pub stale_rounds: u32,
+
+    /// Record the consecutive rounds each open port was seen in
+    /// (`port_history`), for appearance/disappearance and lifetime queries
+    #[arg(long, env = "SCAN_PORT_HISTORY", action = clap::ArgAction::SetTrue)]
+    pub port_history: bool,
+
+    /// Where port bitmaps live: "sqlite" (blobs in `port_bitmaps`) or
+    /// "mmap" (one memory-mapped file per port and round under
+    /// `--bitmap-dir`, updated in place; for full-IPv4 scans)
+    #[arg(long, env = "SCAN_STORAGE_ENGINE", default_value = "sqlite", value_parser = ["sqlite", "mmap"])]
+    pub storage_engine: String,
+
+    /// Directory of the mmap bitmap files (`--storage-engine mmap`)
+    #[arg(long, env = "SCAN_BITMAP_DIR", default_value = "bitmaps")]
+    pub bitmap_dir: String,
+
+    /// Spread port bitmaps over this many `<database>-shardN` files by port
+    /// (2-8), each with its own WAL; set once, later opens find the shards
+    /// themselves. 1 keeps them in the database file
+    #[arg(long, env = "SCAN_DB_SHARDS", default_value = "1")]
+    pub db_shards: usize,
+
+    /// Minutes of metrics samples (every 5 s: rate, queue depths, rate
+    /// limiter tokens) kept for `/stats/timeseries` (max 1440); 0 turns
+    /// sampling off
+    #[arg(long, env = "SCAN_TIMESERIES_MINUTES", default_value = "15")]
+    pub timeseries_minutes: u64,
+
+    /// In loop mode, scan hosts whose open ports changed in the last round
+    /// this many times per round, one weight per following round, e.g.
+    /// "4,2"; empty scans every host once per round
+    #[arg(
+        long,
+        env = "SCAN_PRIORITY_WEIGHTS",
+        value_name = "N,...",
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u32).range(1..=MAX_PRIORITY_WEIGHT as i64)
+    )]
+    pub priority_weights: Vec<u32>,
+
+    /// Daily local-time window in which rounds may run, e.g. "22:00-06:00".
+    /// Outside it the scanner waits before a round and pauses mid-round.
+    #[arg(long, env = "SCAN_WINDOW", value_name = "HH:MM-HH:MM")]
+    pub scan_window: Option<String>,
+
+    /// Never probe addresses listed in this file (masscan `--excludefile`
+    /// format: IPs, ranges and CIDRs, `#` or `;` comments)
+    #[arg(
+        long = "excludefile",
+        visible_alias = "exclude-file",
+        env = "SCAN_EXCLUDE_FILE",
+        value_name = "FILE"
+    )]
+    pub exclude_file: Option<String>,
+
+    /// Never probe addresses that `--geoip-db` or `--geo-csv` place in these
+    /// countries (ISO 3166-1 alpha-2 codes, e.g. `CN,RU`)
+    #[arg(
+        long,
+        env = "SCAN_EXCLUDE_COUNTRY",
+        value_name = "XX",
+        value_delimiter = ','
+    )]
+    pub exclude_country: Vec<String>,
+
+    /// Rhai script whose `on_open_port(event)` can tag, drop or add findings
+    /// to each open port before it is stored
+    #[arg(long, env = "SCAN_SCRIPT", value_name = "FILE")]
+    pub script: Option<String>,
+
+    /// Email a summary to these recipients after each round; SMTP settings
+    /// come from the [report_email] config section
+    #[arg(
+        long,
+        env = "SCAN_REPORT_EMAIL",
+        value_name = "ADDR",
+        value_delimiter = ','
+    )]
+    pub report_email: Vec<String>,
+
+    /// The [report_email] config section; only settable through the config file
+    #[arg(skip)]
+    pub report_email_config: ReportEmailConfig,
+
+    /// The [[notify]] webhook sinks; only settable through the config file
+    #[arg(skip)]
+    pub notify: Vec<NotifyConfig>,
+
+    /// The [mqtt] section; only settable through the config file
+    #[arg(skip)]
+    pub mqtt: MqttConfig,
+
+    /// The [syslog] section; only settable through the config file
+    #[arg(skip)]
+    pub syslog: SyslogConfig,
+
+    /// The [metrics_push] section; only settable through the config file
+    #[arg(skip)]
+    pub metrics_push: MetricsPushConfig,
+
+    /// The [maintenance] section; only settable through the config file
+    #[arg(skip)]
+    pub maintenance: MaintenanceConfig,
+
+    /// The [quotas] section; only settable through the config file
+    #[arg(skip)]
+    pub quotas: QuotaConfig,
+
+    /// The [port_groups] section, names lowercased; only settable through
+    /// the config file
+    #[arg(skip)]
+    pub port_groups: HashMap<String, String>,
+
+    /// Detach from the terminal and run in the background (Unix only)
+    #[arg(long, env = "SCAN_DAEMON", action = clap::ArgAction::SetTrue)]
+    pub daemon: bool,
+
+    /// Pid file written by --daemon and read by `stop`/`status`
+    #[arg(
+        long,
+        env = "SCAN_PID_FILE",
+        default_value = "ip-scan.pid",
+        global = true
+    )]
+    pub pid_file: String,
+
+    /// Log file that receives stdout/stderr when running with --daemon
+    #[arg(long, env = "SCAN_LOG_FILE", default_value = "ip-scan.log")]
+    pub log_file: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -213,28 +844,55 @@ pub struct Config {
     #[serde(default)]
     pub scan: ScanConfig,
     #[serde(default)]
-    #[allow(dead_code)]
     pub rate_limit: RateLimitConfig,
     #[serde(default)]
     pub api: ApiConfig,
+    /// Custom symbolic port groups, e.g. `edge = "web,9000-9100"`
+    #[serde(default)]
+    pub port_groups: HashMap<String, String>,
+    #[serde(default)]
+    pub report_email: ReportEmailConfig,
+    #[serde(default)]
+    pub notify: Vec<NotifyConfig>,
+    #[serde(default)]
+    pub mqtt: MqttConfig,
+    #[serde(default)]
+    pub syslog: SyslogConfig,
+    #[serde(default)]
+    pub metrics_push: MetricsPushConfig,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    #[serde(default)]
+    pub quotas: QuotaConfig,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ScanConfig {
     pub start_ip: Option<String>,
     pub end_ip: Option<String>,
+    pub seed_domains: Option<String>,
+    pub input_file: Option<String>,
     #[serde(default = "default_ports")]
     pub ports: String,
+    pub ports_file: Option<String>,
     #[serde(default = "default_timeout")]
     pub timeout: u64,
     #[serde(default = "default_concurrency")]
     pub concurrency: usize,
+    #[serde(default = "default_host_concurrency")]
+    pub host_concurrency: usize,
     #[serde(default = "default_database")]
     pub database: String,
     #[serde(default)]
     pub verbose: bool,
+    #[serde(default)]
+    pub quiet: bool,
     #[serde(default = "default_loop_mode")]
     pub loop_mode: bool,
+    #[serde(default = "default_summary_format")]
+    pub summary_format: String,
+    #[serde(default)]
+    pub json_summary: bool,
     #[serde(default = "default_ipv4")]
     pub ipv4: bool,
     #[serde(default)]
@@ -246,6 +904,9 @@ pub struct ScanConfig {
     #[serde(default)]
     pub syn: bool,
     pub geoip_db: Option<String>,
+    pub whois_servers: Option<String>,
+    #[serde(default)]
+    pub geo_csv: Vec<String>,
     #[serde(default)]
     pub no_geo: bool,
     #[serde(default)]
@@ -254,6 +915,18 @@ pub struct ScanConfig {
     pub probe_timeout: u64,
     #[serde(default = "default_probe_concurrency")]
     pub probe_concurrency: usize,
+    #[serde(default = "default_probe_rate")]
+    pub probe_rate: u64,
+    #[serde(default)]
+    pub grab_banner_ms: u64,
+    pub sni_hosts: Option<String>,
+    #[serde(default)]
+    pub sni_from_rdns: bool,
+    pub cve_db: Option<String>,
+    #[serde(default)]
+    pub reputation_providers: Vec<String>,
+    #[serde(default = "default_reputation_rate")]
+    pub reputation_rate: u64,
     #[serde(default = "default_geo_concurrency")]
     pub geo_concurrency: usize,
 
@@ -266,12 +939,44 @@ pub struct ScanConfig {
     pub db_batch_size: usize,
     #[serde(default = "default_flush_interval_ms")]
     pub flush_interval_ms: u64,
+    #[serde(default)]
+    pub adaptive_batching: bool,
+    #[serde(default = "default_wal_checkpoint_secs")]
+    pub wal_checkpoint_secs: u64,
+    #[serde(default = "default_wal_truncate_mb")]
+    pub wal_truncate_mb: u64,
     #[serde(default = "default_max_rate")]
     pub max_rate: u64,
     #[serde(default = "default_window_duration")]
     pub rate_window_secs: u64,
+    #[serde(default)]
+    pub rate_burst: usize,
+    #[serde(default = "default_syn_linger_secs")]
+    pub syn_linger_secs: u64,
+    #[serde(default = "default_io_backend")]
+    pub io_backend: String,
+    pub source_port_range: Option<String>,
     #[serde(default = "default_round_delay_ms")]
     pub round_delay_ms: u64,
+    #[serde(default = "default_stale_rounds")]
+    pub stale_rounds: u32,
+    #[serde(default)]
+    pub port_history: bool,
+    #[serde(default = "default_storage_engine")]
+    pub storage_engine: String,
+    #[serde(default = "default_bitmap_dir")]
+    pub bitmap_dir: String,
+    #[serde(default = "default_db_shards")]
+    pub db_shards: usize,
+    #[serde(default = "default_timeseries_minutes")]
+    pub timeseries_minutes: u64,
+    #[serde(default)]
+    pub priority_weights: Vec<u32>,
+    pub scan_window: Option<String>,
+    pub exclude_file: Option<String>,
+    #[serde(default)]
+    pub exclude_country: Vec<String>,
+    pub script: Option<String>,
     #[serde(default)]
     #[allow(dead_code)]
     pub api: bool,
@@ -287,15 +992,19 @@ pub struct ScanConfig {
     pub api_port: u16,
     #[serde(default)]
     pub swagger_ui: bool,
+    #[serde(default)]
+    pub daemon: bool,
+    #[serde(default = "default_pid_file")]
+    pub pid_file: String,
+    #[serde(default = "default_log_file")]
+    pub log_file: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RateLimitConfig {
     #[serde(default = "default_max_rate")]
-    #[allow(dead_code)]
     pub max_rate: u64,
     #[serde(default = "default_window_duration")]
-    #[allow(dead_code)]
     pub window_duration: u64,
 }
 
@@ -307,6 +1016,312 @@ pub struct ApiConfig {
     pub host: String,
     #[serde(default = "default_api_port")]
     pub port: u16,
+    /// Read-only result databases, as NAME=PATH
+    #[serde(default)]
+    pub attach_db: Vec<String>,
+    /// Networks allowed to call the API
+    #[serde(default)]
+    pub allow_cidr: Vec<String>,
+    /// Proxies whose X-Forwarded-User header is believed
+    #[serde(default)]
+    pub trusted_proxy: Vec<String>,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default = "default_api_max_range")]
+    pub max_range: u64,
+    /// HTTPS certificate chain and key
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    /// CA bundle client certificates must chain to
+    pub client_ca: Option<String>,
+    /// Where namespace databases live
+    #[serde(default = "default_namespace_dir")]
+    pub namespace_dir: String,
+    /// Callers that keep the main database once namespaces exist
+    #[serde(default)]
+    pub operators: Vec<String>,
+}
+
+/// SMTP settings for end-of-round email reports
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReportEmailConfig {
+    pub smtp_host: Option<String>,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    /// "starttls", "tls" (implicit TLS, usually port 465) or "none"
+    #[serde(default = "default_smtp_security")]
+    pub security: String,
+    pub username: Option<String>,
+    /// Prefer the SCAN_SMTP_PASSWORD environment variable over this field
+    pub password: Option<String>,
+    pub from: Option<String>,
+    /// Recipients used when --report-email is not given
+    #[serde(default)]
+    pub to: Vec<String>,
+    /// Subject template; see `service::email_report` for placeholders
+    pub subject: Option<String>,
+    /// Path to a plain-text body template
+    pub template: Option<String>,
+    /// Opened/closed ports listed in the body; the counts are always complete
+    #[serde(default = "default_report_max_changes")]
+    pub max_changes: usize,
+}
+
+/// One Slack/Discord/Telegram/webhook sink or PagerDuty/Opsgenie
+/// escalation for scan events
+#[derive(Debug, Clone, Deserialize)]
+pub struct NotifyConfig {
+    /// "slack", "discord", "telegram", "pagerduty", "opsgenie" or "webhook"
+    /// (the event fields as JSON plus `text`)
+    pub kind: String,
+    /// Webhook URL; for telegram an optional Bot API server, by default
+    /// https://api.telegram.org, and for pagerduty/opsgenie an optional
+    /// events/alerts endpoint (e.g. Opsgenie's EU instance)
+    #[serde(default)]
+    pub url: String,
+    /// Telegram bot token; prefer the SCAN_TELEGRAM_BOT_TOKEN environment
+    /// variable over this field
+    pub bot_token: Option<String>,
+    /// Telegram chat, group or channel (`@name` or numeric id) to post to
+    pub chat_id: Option<String>,
+    /// PagerDuty Events API v2 integration key; prefer the
+    /// SCAN_PAGERDUTY_ROUTING_KEY environment variable over this field
+    pub routing_key: Option<String>,
+    /// Opsgenie API integration key; prefer the SCAN_OPSGENIE_API_KEY
+    /// environment variable over this field
+    pub api_key: Option<String>,
+    /// Incident severity for pagerduty/opsgenie: critical (default), error,
+    /// warning or info
+    pub severity: Option<String>,
+    /// open_port, new_country and/or round_complete; all when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Only notify open ports on these ports; all when empty
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    /// Only notify open ports on these IPs, CIDRs or ranges; all when empty
+    #[serde(default)]
+    pub ranges: Vec<String>,
+    /// Only notify new countries among these ISO codes; all when empty
+    #[serde(default)]
+    pub countries: Vec<String>,
+    /// Message template; see `service::notify` for placeholders
+    pub template: Option<String>,
+    /// Messages sent per minute; the rest wait their turn
+    #[serde(default = "default_notify_rate_per_minute")]
+    pub rate_per_minute: u64,
+}
+
+/// MQTT broker for publishing scan events; disabled unless `host` is set
+#[derive(Debug, Clone, Deserialize)]
+pub struct MqttConfig {
+    pub host: Option<String>,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    /// Connect with TLS, verified against the system roots
+    #[serde(default)]
+    pub tls: bool,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    pub username: Option<String>,
+    /// Prefer the SCAN_MQTT_PASSWORD environment variable over this field
+    pub password: Option<String>,
+    /// Topic template, e.g. "ip-scan/{{event}}/{{port}}"
+    #[serde(default = "default_mqtt_topic")]
+    pub topic: String,
+    /// open_port, new_country and/or round_complete; all when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// 0, 1 or 2
+    #[serde(default = "default_mqtt_qos")]
+    pub qos: u8,
+    #[serde(default)]
+    pub retain: bool,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: default_mqtt_port(),
+            tls: false,
+            client_id: default_mqtt_client_id(),
+            username: None,
+            password: None,
+            topic: default_mqtt_topic(),
+            events: Vec::new(),
+            qos: default_mqtt_qos(),
+            retain: false,
+        }
+    }
+}
+
+/// Syslog/SIEM collector for scan events; disabled unless `host` is set
+#[derive(Debug, Clone, Deserialize)]
+pub struct SyslogConfig {
+    pub host: Option<String>,
+    #[serde(default = "default_syslog_port")]
+    pub port: u16,
+    /// udp, tcp (octet-counted framing) or tls (RFC 5425, usually port 6514)
+    #[serde(default = "default_syslog_protocol")]
+    pub protocol: String,
+    /// PEM CA certificates trusted for the collector besides the system
+    /// roots, for tls
+    pub ca_file: Option<String>,
+    /// rfc5424 (structured data) or cef
+    #[serde(default = "default_syslog_format")]
+    pub format: String,
+    /// Facility name, e.g. "local0" or "auth"
+    #[serde(default = "default_syslog_facility")]
+    pub facility: String,
+    /// Severity name, e.g. "notice" or "warning"; also sets the CEF severity
+    #[serde(default = "default_syslog_severity")]
+    pub severity: String,
+    /// HOSTNAME field; defaults to the local host name
+    pub hostname: Option<String>,
+    #[serde(default = "default_syslog_app_name")]
+    pub app_name: String,
+    /// open_port, new_country and/or round_complete; all when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: default_syslog_port(),
+            protocol: default_syslog_protocol(),
+            ca_file: None,
+            format: default_syslog_format(),
+            facility: default_syslog_facility(),
+            severity: default_syslog_severity(),
+            hostname: None,
+            app_name: default_syslog_app_name(),
+            events: Vec::new(),
+        }
+    }
+}
+
+/// Where to push metrics after each round and at shutdown
+#[derive(Debug, Clone, Deserialize)]
+pub struct MetricsPushConfig {
+    /// Pushgateway base URL, e.g. http://pushgateway:9091
+    pub pushgateway_url: Option<String>,
+    /// Prometheus remote-write endpoint, e.g. http://prometheus:9090/api/v1/write
+    pub remote_write_url: Option<String>,
+    #[serde(default = "default_metrics_push_job")]
+    pub job: String,
+    /// Instance label; defaults to the local host name
+    pub instance: Option<String>,
+}
+
+impl Default for MetricsPushConfig {
+    fn default() -> Self {
+        Self {
+            pushgateway_url: None,
+            remote_write_url: None,
+            job: default_metrics_push_job(),
+            instance: None,
+        }
+    }
+}
+
+/// Database housekeeping run while no scan is active; an interval of 0
+/// turns that task off
+#[derive(Debug, Clone, Deserialize)]
+pub struct MaintenanceConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Hours between deletions of old round bitmaps
+    #[serde(default = "default_maintenance_prune_hours")]
+    pub prune_hours: u64,
+    /// Newest rounds whose bitmaps pruning keeps
+    #[serde(default = "default_maintenance_keep_rounds")]
+    pub keep_rounds: i64,
+    /// Hours between marking open ports unseen for --stale-rounds as gone
+    #[serde(default = "default_maintenance_age_hours")]
+    pub age_hours: u64,
+    /// Hours between VACUUMs
+    #[serde(default = "default_maintenance_vacuum_hours")]
+    pub vacuum_hours: u64,
+    /// Hours between queueing outdated geo data for another lookup
+    #[serde(default = "default_maintenance_geo_refresh_hours")]
+    pub geo_refresh_hours: u64,
+    /// Geo data older than this many days is looked up again
+    #[serde(default = "default_maintenance_geo_max_age_days")]
+    pub geo_max_age_days: u64,
+}
+
+/// Limits for one API caller; an unset limit does not apply
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct QuotaLimits {
+    /// Requests to `/api/v1` per calendar minute
+    pub requests_per_minute: Option<u64>,
+    /// Rows served by `/export/*` per UTC day
+    pub export_rows_per_day: Option<u64>,
+    /// API scans running at once
+    pub concurrent_scans: Option<u64>,
+}
+
+/// Per-caller API quotas. A caller is the common name of its client
+/// certificate, the user an authenticating proxy names in `X-Forwarded-User`,
+/// or the client address without either
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct QuotaConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Limits for every caller
+    #[serde(flatten)]
+    pub default: QuotaLimits,
+    /// Limits replacing the defaults for named callers, field by field
+    #[serde(default)]
+    pub principals: HashMap<String, QuotaLimits>,
+}
+
+impl QuotaConfig {
+    /// The limits that apply to `principal`.
+    pub fn limits_for(&self, principal: &str) -> QuotaLimits {
+        let Some(own) = self.principals.get(principal) else {
+            return self.default.clone();
+        };
+        QuotaLimits {
+            requests_per_minute: own.requests_per_minute.or(self.default.requests_per_minute),
+            export_rows_per_day: own.export_rows_per_day.or(self.default.export_rows_per_day),
+            concurrent_scans: own.concurrent_scans.or(self.default.concurrent_scans),
+        }
+    }
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            prune_hours: default_maintenance_prune_hours(),
+            keep_rounds: default_maintenance_keep_rounds(),
+            age_hours: default_maintenance_age_hours(),
+            vacuum_hours: default_maintenance_vacuum_hours(),
+            geo_refresh_hours: default_maintenance_geo_refresh_hours(),
+            geo_max_age_days: default_maintenance_geo_max_age_days(),
+        }
+    }
+}
+
+impl Default for ReportEmailConfig {
+    fn default() -> Self {
+        Self {
+            smtp_host: None,
+            smtp_port: default_smtp_port(),
+            security: default_smtp_security(),
+            username: None,
+            password: None,
+            from: None,
+            to: Vec::new(),
+            subject: None,
+            template: None,
+            max_changes: default_report_max_changes(),
+        }
+    }
 }
 
 impl Default for ApiConfig {
@@ -315,6 +1330,16 @@ impl Default for ApiConfig {
             enabled: default_api_enabled(),
             host: default_api_host(),
             port: default_api_port(),
+            attach_db: Vec::new(),
+            allow_cidr: Vec::new(),
+            trusted_proxy: Vec::new(),
+            read_only: false,
+            max_range: default_api_max_range(),
+            tls_cert: None,
+            tls_key: None,
+            client_ca: None,
+            namespace_dir: default_namespace_dir(),
+            operators: Vec::new(),
         }
     }
 }
@@ -324,37 +1349,74 @@ impl Default for ScanConfig {
         Self {
             start_ip: None,
             end_ip: None,
+            seed_domains: None,
+            input_file: None,
             ports: default_ports(),
+            ports_file: None,
             timeout: default_timeout(),
             concurrency: default_concurrency(),
+            host_concurrency: default_host_concurrency(),
             database: default_database(),
             verbose: false,
+            quiet: false,
             loop_mode: default_loop_mode(),
+            summary_format: default_summary_format(),
+            json_summary: false,
             ipv4: default_ipv4(),
             ipv6: false,
             only_store_open: default_only_store_open(),
             skip_private: default_skip_private(),
             syn: false,
             geoip_db: None,
+            whois_servers: None,
+            geo_csv: Vec::new(),
             no_geo: false,
             probe_service: false,
             probe_timeout: default_probe_timeout(),
             probe_concurrency: default_probe_concurrency(),
+            probe_rate: default_probe_rate(),
+            grab_banner_ms: 0,
+            sni_hosts: None,
+            sni_from_rdns: false,
+            cve_db: None,
+            reputation_providers: Vec::new(),
+            reputation_rate: default_reputation_rate(),
             geo_concurrency: default_geo_concurrency(),
             worker_threads: None,
             pipeline_buffer: default_pipeline_buffer(),
             result_buffer: default_result_buffer(),
             db_batch_size: default_db_batch_size(),
             flush_interval_ms: default_flush_interval_ms(),
+            adaptive_batching: false,
+            wal_checkpoint_secs: default_wal_checkpoint_secs(),
+            wal_truncate_mb: default_wal_truncate_mb(),
             max_rate: default_max_rate(),
             rate_window_secs: default_window_duration(),
+            rate_burst: 0,
+            syn_linger_secs: default_syn_linger_secs(),
+            io_backend: default_io_backend(),
+            source_port_range: None,
             round_delay_ms: default_round_delay_ms(),
+            stale_rounds: default_stale_rounds(),
+            port_history: false,
+            storage_engine: default_storage_engine(),
+            bitmap_dir: default_bitmap_dir(),
+            db_shards: default_db_shards(),
+            timeseries_minutes: default_timeseries_minutes(),
+            priority_weights: Vec::new(),
+            scan_window: None,
+            exclude_file: None,
+            exclude_country: Vec::new(),
+            script: None,
             api: false,
             api_only: false,
             no_api: false,
             api_host: default_api_host(),
             api_port: default_api_port(),
             swagger_ui: false,
+            daemon: false,
+            pid_file: default_pid_file(),
+            log_file: default_log_file(),
         }
     }
 }
@@ -368,22 +1430,30 @@ impl Default for RateLimitConfig {
     }
 }
 
-fn default_ports() -> String {
+pub(crate) fn default_ports() -> String {
     "21,22,23,25,53,80,110,143,443,445,3306,3389,5432,6379,8080,8443,9200,27017".to_string()
 }
 
-fn default_timeout() -> u64 {
+pub(crate) fn default_timeout() -> u64 {
     500
 }
 
-fn default_concurrency() -> usize {
-    1000
+pub(crate) fn default_concurrency() -> usize {
+    500
+}
+
+pub(crate) fn default_host_concurrency() -> usize {
+    4
 }
 
 fn default_database() -> String {
     "scan_results.db".to_string()
 }
 
+fn default_summary_format() -> String {
+    "text".to_string()
+}
+
 fn default_loop_mode() -> bool {
     true
 }
@@ -400,11 +1470,11 @@ fn default_skip_private() -> bool {
     true
 }
 
-fn default_max_rate() -> u64 {
-    200000
+pub(crate) fn default_max_rate() -> u64 {
+    100000
 }
 
-fn default_window_duration() -> u64 {
+pub(crate) fn default_window_duration() -> u64 {
     1
 }
 
@@ -412,22 +1482,66 @@ fn default_pipeline_buffer() -> usize {
     2000
 }
 
-fn default_result_buffer() -> usize {
+pub(crate) fn default_result_buffer() -> usize {
     10000
 }
 
-fn default_db_batch_size() -> usize {
+pub(crate) fn default_db_batch_size() -> usize {
     2000
 }
 
-fn default_flush_interval_ms() -> u64 {
+pub(crate) fn default_flush_interval_ms() -> u64 {
     1000
 }
 
+fn default_wal_checkpoint_secs() -> u64 {
+    30
+}
+
+fn default_wal_truncate_mb() -> u64 {
+    64
+}
+
 fn default_round_delay_ms() -> u64 {
     0
 }
 
+fn default_stale_rounds() -> u32 {
+    3
+}
+
+fn default_syn_linger_secs() -> u64 {
+    1
+}
+
+fn default_io_backend() -> String {
+    "tokio".to_string()
+}
+
+fn default_storage_engine() -> String {
+    "sqlite".to_string()
+}
+
+fn default_bitmap_dir() -> String {
+    "bitmaps".to_string()
+}
+
+fn default_db_shards() -> usize {
+    1
+}
+
+fn default_timeseries_minutes() -> u64 {
+    15
+}
+
+fn default_pid_file() -> String {
+    "ip-scan.pid".to_string()
+}
+
+fn default_log_file() -> String {
+    "ip-scan.log".to_string()
+}
+
 fn default_api_host() -> String {
     "0.0.0.0".to_string()
 }
@@ -436,6 +1550,18 @@ fn default_api_port() -> u16 {
     9090
 }
 
+fn default_metrics_push_job() -> String {
+    "ip-scan".to_string()
+}
+
+fn default_api_max_range() -> u64 {
+    1 << 32
+}
+
+fn default_namespace_dir() -> String {
+    "namespaces".to_string()
+}
+
 fn default_api_enabled() -> bool {
     true
 }
@@ -448,10 +1574,455 @@ fn default_probe_concurrency() -> usize {
     50
 }
 
+fn default_probe_rate() -> u64 {
+    100
+}
+
+fn default_reputation_rate() -> u64 {
+    40
+}
+
 fn default_geo_concurrency() -> usize {
     8
 }
 
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_smtp_security() -> String {
+    "starttls".to_string()
+}
+
+fn default_report_max_changes() -> usize {
+    50
+}
+
+fn default_notify_rate_per_minute() -> u64 {
+    30
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    "ip-scan".to_string()
+}
+
+fn default_mqtt_topic() -> String {
+    "ip-scan/{{event}}".to_string()
+}
+
+fn default_mqtt_qos() -> u8 {
+    1
+}
+
+fn default_syslog_port() -> u16 {
+    514
+}
+
+fn default_syslog_protocol() -> String {
+    "udp".to_string()
+}
+
+fn default_syslog_format() -> String {
+    "rfc5424".to_string()
+}
+
+fn default_syslog_facility() -> String {
+    "local0".to_string()
+}
+
+fn default_syslog_severity() -> String {
+    "notice".to_string()
+}
+
+fn default_syslog_app_name() -> String {
+    "ip-scan".to_string()
+}
+
+fn default_maintenance_prune_hours() -> u64 {
+    24
+}
+
+fn default_maintenance_keep_rounds() -> i64 {
+    2
+}
+
+fn default_maintenance_age_hours() -> u64 {
+    6
+}
+
+fn default_maintenance_vacuum_hours() -> u64 {
+    168
+}
+
+fn default_maintenance_geo_refresh_hours() -> u64 {
+    24
+}
+
+fn default_maintenance_geo_max_age_days() -> u64 {
+    30
+}
+
+/// Render a commented config file whose values are the built-in defaults, so
+/// it stays in sync with `ScanConfig`/`ApiConfig`/`RateLimitConfig`.
+pub fn sample_config() -> String {
+    format!(
+        r#"# IP Scanner Configuration
+#
+# Generated by `ip-scan init-config`. Every value below is the built-in
+# default; command line flags and SCAN_* environment variables take
+# precedence over this file. Only scan networks you are authorized to scan.
+
+[api]
+# Start the HTTP API alongside the scanner (combined mode)
+enabled = {api_enabled}
+# Bind address; use 127.0.0.1 unless the API sits behind an authenticating proxy
+host = "{api_host}"
+port = {api_port}
+# Other result databases served read-only, selected with ?db=NAME
+# attach_db = ["eu=scan_eu.db", "us=scan_us.db"]
+# Only answer clients from these networks (IPs, ranges or CIDRs)
+# allow_cidr = ["127.0.0.1", "10.0.0.0/8"]
+# Reverse proxies whose X-Forwarded-User header names the caller; from
+# anyone else the header is ignored
+# trusted_proxy = ["127.0.0.1"]
+# Serve results only; scan control, template changes and admin routes answer 403
+read_only = false
+# Largest address range an API scan may cover (0 = no limit); the default is
+# the whole IPv4 space
+max_range = {api_max_range}
+# Serve HTTPS; with client_ca every client must present a certificate issued
+# by one of its CAs, and the certificate's common name names the caller
+# tls_cert = "/etc/ip-scan/api.pem"
+# tls_key = "/etc/ip-scan/api.key"
+# client_ca = "/etc/ip-scan/clients-ca.pem"
+# Databases of the namespaces created under /admin/namespaces, one per team
+namespace_dir = "namespaces"
+# Callers that keep the main database and manage namespaces; required before
+# the first namespace is created, after which everyone else must be in one
+# operators = ["ops-admin"]
+
+[scan]
+# Target range (defaults to the whole IPv4 space when unset)
+# start_ip = "192.168.1.1"
+# end_ip = "192.168.1.254"
+# Domain list or CT log export (crt.sh JSON) resolved and scanned each round,
+# limited to the range above when one is set
+# seed_domains = "ct-domains.json"
+# Address list scanned instead of a range (IPs, CIDRs, ranges; one or more
+# per line), e.g. written by `ip-scan export targets`
+# input_file = "hosts.txt"
+
+# Ports: single ports, ranges and lists, e.g. "80", "1-1024", "22,80,443",
+# or named groups: web, db, mail, remote, file and any defined in [port_groups]
+ports = "{ports}"
+# File with one port, range or group per line, added to the ports above
+# ports_file = "ports.txt"
+# TCP connect timeout in milliseconds
+timeout = {timeout}
+# Concurrent connection attempts
+concurrency = {concurrency}
+# Ports probed at once on a single host, within concurrency
+host_concurrency = {host_concurrency}
+# SQLite database path
+database = "{database}"
+verbose = false
+# Only warnings and errors in the log, no end-of-round summary
+quiet = false
+# Keep scanning in rounds instead of exiting after one pass
+loop_mode = {loop_mode}
+# End-of-round summary on stdout: "text" tables or one "json" line
+summary_format = "{summary_format}"
+# One JSON document with the run's totals on stdout at exit; logs go to stderr
+json_summary = false
+# Delay between loop-mode rounds in milliseconds (max 600000)
+round_delay_ms = {round_delay_ms}
+# Mark open ports gone after this many rounds without a sighting (0 = never)
+stale_rounds = {stale_rounds}
+# Keep per-port runs of consecutive rounds seen, for lifetime statistics
+port_history = false
+# Port bitmap storage: "sqlite" (blobs) or "mmap" (in-place files under
+# bitmap_dir, for full-IPv4 scans)
+storage_engine = "sqlite"
+bitmap_dir = "bitmaps"
+# Spread port bitmaps over N <database>-shardN files by port (1 = off, max 8)
+db_shards = 1
+# Minutes of 5-second metrics samples kept for /stats/timeseries (0 = off)
+timeseries_minutes = 15
+# Loop mode: scans per round for hosts that changed 1, 2, ... rounds ago
+priority_weights = []
+# Only scan inside this daily local-time window; wraps past midnight
+# scan_window = "22:00-06:00"
+# Never probe addresses in this masscan-format exclusion list
+# exclude_file = "exclude.conf"
+# Never probe addresses the geo datasets place in these countries
+# exclude_country = ["XX"]
+# Rhai hook run on every open port before it is stored
+# script = "hooks.rhai"
+ipv4 = {ipv4}
+ipv6 = false
+# Persist only open ports
+only_store_open = {only_store_open}
+# Skip RFC1918, loopback, link-local, multicast and reserved IPv4 ranges
+skip_private = {skip_private}
+# Raw-socket SYN scan (requires root/admin); falls back to connect scan
+syn = false
+
+# MaxMind GeoIP database; RDAP, WHOIS and ip-api.com are used as fallbacks
+# geoip_db = "GeoLite2-City.mmdb"
+# whois server list in servers.json format; a minimal list is built in
+# whois_servers = "servers.json"
+# Offline IP range -> country/ASN datasets (CSV/TSV, e.g. iptoasn.com)
+# geo_csv = ["ip2asn-combined.tsv"]
+no_geo = false
+# GeoIP/WHOIS/reverse-DNS lookups in flight
+geo_concurrency = {geo_concurrency}
+
+# Banner/HTTP/TLS probing of discovered open ports
+probe_service = false
+# Per-probe timeout in seconds
+probe_timeout = {probe_timeout}
+probe_concurrency = {probe_concurrency}
+# Probes started per second across all hosts
+probe_rate = {probe_rate}
+# Milliseconds to wait for a greeting on each open port during connect scans,
+# reused by service probing (0 = off)
+grab_banner_ms = 0
+# Extra hostnames probed on HTTP(S) ports with SNI/Host, /etc/hosts format
+# sni_hosts = "sni-hosts.txt"
+# Probe HTTP(S) ports as the reverse-DNS name too
+sni_from_rdns = false
+# NVD CVE JSON file or directory for candidate CVEs of probed versions
+# cve_db = "nvd/"
+# Reputation lookups for hosts with open ports (abuseipdb, greynoise); API keys
+# come from SCAN_ABUSEIPDB_KEY / SCAN_GREYNOISE_KEY, never from this file
+reputation_providers = []
+# Lookups started per hour, per provider
+reputation_rate = {reputation_rate}
+
+# Tokio worker threads (defaults to the number of CPUs)
+# worker_threads = 8
+# Queued IPs between the producer and the scanner
+pipeline_buffer = {pipeline_buffer}
+# Queued results waiting for the database writer
+result_buffer = {result_buffer}
+# Rows per database write batch
+db_batch_size = {db_batch_size}
+# Flush partial batches at least this often (milliseconds)
+flush_interval_ms = {flush_interval_ms}
+# Tune batch size and flush interval from queue depth and write latency
+adaptive_batching = false
+# Background WAL checkpoint interval (seconds); idle WALs are truncated
+wal_checkpoint_secs = {wal_checkpoint_secs}
+# Truncate the WAL once it reaches this size (MiB), even while writing
+wal_truncate_mb = {wal_truncate_mb}
+# Overrides [rate_limit] when set to a non-default value
+max_rate = {max_rate}
+rate_window_secs = {rate_window_secs}
+# Probes sent back to back before the steady pace applies (0 = 10 ms worth)
+rate_burst = 0
+# Seconds to keep listening for SYN-ACKs after the last probe
+syn_linger_secs = {syn_linger_secs}
+# Connect scan I/O: "tokio" or "uring" (Linux io_uring)
+io_backend = "{io_backend}"
+# Send probes only from these local ports, for firewall allow-listing
+# source_port_range = "40000-50000"
+
+# Run only the API, or only the scanner
+api_only = false
+no_api = false
+swagger_ui = false
+
+# Background mode (Unix): see `ip-scan stop` / `ip-scan status`
+daemon = false
+pid_file = "{pid_file}"
+log_file = "{log_file}"
+
+[rate_limit]
+# Maximum probes per window; applies when [scan] keeps the defaults
+max_rate = {max_rate}
+# Window length in seconds
+window_duration = {rate_window_secs}
+
+[port_groups]
+# Custom names for --ports; may reference built-in groups
+# edge = "web,9000-9100"
+
+[report_email]
+# End-of-round summary emails; also enabled by --report-email ADDR
+# smtp_host = "smtp.example.com"
+smtp_port = {smtp_port}
+# starttls, tls or none
+security = "{smtp_security}"
+# username = "scanner@example.com"
+# Set the password through SCAN_SMTP_PASSWORD instead of this file
+# from = "ip-scan <scanner@example.com>"
+# to = ["secops@example.com"]
+# subject = "[ip-scan] round {{{{round}}}}: {{{{opened_count}}}} opened, {{{{closed_count}}}} closed"
+# template = "report.txt"
+max_changes = {report_max_changes}
+
+# Slack/Discord/Telegram/generic webhook notifications and PagerDuty/Opsgenie
+# incidents; repeat the section per sink
+# [[notify]]
+# kind = "slack"
+# url = "https://hooks.slack.com/services/..."
+# events = ["open_port", "new_country", "round_complete"]
+# ports = [3389, 445]
+# countries = ["CN", "RU"]
+# template = ":rotating_light: {{{{ip}}}}:{{{{port}}}} open (round {{{{round}}}})"
+# rate_per_minute = {notify_rate_per_minute}
+#
+# [[notify]]
+# kind = "telegram"
+# Set the token through SCAN_TELEGRAM_BOT_TOKEN instead of this file
+# chat_id = "-1001234567890"
+# events = ["open_port", "round_complete"]
+#
+# Page on-call for RDP/SMB exposed on owned ranges; one incident per IP and port
+# [[notify]]
+# kind = "pagerduty"
+# Set the key through SCAN_PAGERDUTY_ROUTING_KEY instead of this file
+# events = ["open_port"]
+# ports = [3389, 445]
+# ranges = ["198.51.100.0/24"]
+# severity = "critical"
+
+[mqtt]
+# Publish scan events as JSON to an MQTT broker (Home Assistant, Node-RED, ...)
+# host = "broker.local"
+port = {mqtt_port}
+tls = false
+client_id = "{mqtt_client_id}"
+# username = "ip-scan"
+# Set the password through SCAN_MQTT_PASSWORD instead of this file
+# Placeholders: {{{{event}}}}, {{{{ip}}}}, {{{{port}}}}, {{{{round}}}}, {{{{country}}}}
+topic = "{mqtt_topic}"
+# events = ["open_port", "round_complete"]
+qos = {mqtt_qos}
+retain = false
+
+[syslog]
+# Forward scan events to a syslog collector or SIEM
+# host = "siem.example.com"
+port = {syslog_port}
+# udp, tcp or tls (RFC 5425, usually port 6514)
+protocol = "{syslog_protocol}"
+# ca_file = "siem-ca.pem"
+# rfc5424 or cef
+format = "{syslog_format}"
+facility = "{syslog_facility}"
+severity = "{syslog_severity}"
+# hostname = "scanner-1"
+app_name = "{syslog_app_name}"
+# events = ["open_port"]
+
+[metrics_push]
+# Push the /api/v1/stats/prometheus metrics after each round and at shutdown,
+# for scan jobs too short-lived to scrape
+# pushgateway_url = "http://pushgateway:9091"
+# remote_write_url = "http://prometheus:9090/api/v1/write"
+job = "{metrics_push_job}"
+# instance = "scanner-1"
+
+[maintenance]
+# Prune, age, vacuum and refresh geo data while no scan is running (between
+# loop rounds, outside the scan window, or when the API has no scan); the
+# hour settings are intervals and 0 turns that task off
+enabled = false
+prune_hours = {maintenance_prune_hours}
+keep_rounds = {maintenance_keep_rounds}
+age_hours = {maintenance_age_hours}
+vacuum_hours = {maintenance_vacuum_hours}
+geo_refresh_hours = {maintenance_geo_refresh_hours}
+geo_max_age_days = {maintenance_geo_max_age_days}
+
+[quotas]
+# Per-caller API limits, tracked in the database. A caller is the client
+# certificate's common name, the user the authenticating proxy sends in
+# X-Forwarded-User, or the client address without either; usage is reported in X-Quota-* response headers and an
+# exhausted quota answers 429. Unset limits do not apply
+enabled = false
+# requests_per_minute = 600
+# export_rows_per_day = 1000000
+# concurrent_scans = 1
+# Limits for one caller, replacing the defaults above field by field
+# [quotas.principals.alice]
+# export_rows_per_day = 5000000
+"#,
+        api_enabled = default_api_enabled(),
+        api_host = default_api_host(),
+        api_port = default_api_port(),
+        api_max_range = default_api_max_range(),
+        metrics_push_job = default_metrics_push_job(),
+        ports = default_ports(),
+        timeout = default_timeout(),
+        concurrency = default_concurrency(),
+        host_concurrency = default_host_concurrency(),
+        database = default_database(),
+        loop_mode = default_loop_mode(),
+        summary_format = default_summary_format(),
+        round_delay_ms = default_round_delay_ms(),
+        stale_rounds = default_stale_rounds(),
+        syn_linger_secs = default_syn_linger_secs(),
+        io_backend = default_io_backend(),
+        ipv4 = default_ipv4(),
+        only_store_open = default_only_store_open(),
+        skip_private = default_skip_private(),
+        geo_concurrency = default_geo_concurrency(),
+        probe_timeout = default_probe_timeout(),
+        probe_concurrency = default_probe_concurrency(),
+        probe_rate = default_probe_rate(),
+        reputation_rate = default_reputation_rate(),
+        pipeline_buffer = default_pipeline_buffer(),
+        result_buffer = default_result_buffer(),
+        db_batch_size = default_db_batch_size(),
+        flush_interval_ms = default_flush_interval_ms(),
+        wal_checkpoint_secs = default_wal_checkpoint_secs(),
+        wal_truncate_mb = default_wal_truncate_mb(),
+        max_rate = default_max_rate(),
+        rate_window_secs = default_window_duration(),
+        pid_file = default_pid_file(),
+        log_file = default_log_file(),
+        smtp_port = default_smtp_port(),
+        smtp_security = default_smtp_security(),
+        report_max_changes = default_report_max_changes(),
+        notify_rate_per_minute = default_notify_rate_per_minute(),
+        mqtt_port = default_mqtt_port(),
+        mqtt_client_id = default_mqtt_client_id(),
+        mqtt_topic = default_mqtt_topic(),
+        mqtt_qos = default_mqtt_qos(),
+        syslog_port = default_syslog_port(),
+        syslog_protocol = default_syslog_protocol(),
+        syslog_format = default_syslog_format(),
+        syslog_facility = default_syslog_facility(),
+        syslog_severity = default_syslog_severity(),
+        syslog_app_name = default_syslog_app_name(),
+        maintenance_prune_hours = default_maintenance_prune_hours(),
+        maintenance_keep_rounds = default_maintenance_keep_rounds(),
+        maintenance_age_hours = default_maintenance_age_hours(),
+        maintenance_vacuum_hours = default_maintenance_vacuum_hours(),
+        maintenance_geo_refresh_hours = default_maintenance_geo_refresh_hours(),
+        maintenance_geo_max_age_days = default_maintenance_geo_max_age_days(),
+    )
+}
+
+/// `ip-scan init-config`: write [`sample_config`] to `path`.
+pub fn write_sample_config(path: &std::path::Path, force: bool) -> anyhow::Result<()> {
+    if path.exists() && !force {
+        return Err(anyhow::anyhow!(
+            "{} already exists; pass --force to overwrite",
+            path.display()
+        ));
+    }
+    std::fs::write(path, sample_config())?;
+    println!("Wrote sample configuration to {}", path.display());
+    Ok(())
+}
+
 impl Args {
     pub fn apply_preset(&mut self) {
         if let Some(ref preset) = self.preset {
@@ -485,6 +2056,26 @@ impl Args {
     /// Merge configuration from file with command line arguments
     /// Command line arguments take precedence over config file
     pub fn merge_with_config(mut self) -> anyhow::Result<Self> {
+        // `estimate --range/--ports/--max-rate` stand in for the scan's own
+        // flags, so config defaults, presets and port groups apply alike.
+        if let Some(Command::Estimate {
+            range,
+            ports,
+            max_rate,
+            ..
+        }) = &self.command
+        {
+            if let Some(range) = range {
+                self.target = Some(range.clone());
+            }
+            if let Some(ports) = ports {
+                self.ports = ports.clone();
+            }
+            if let Some(max_rate) = max_rate {
+                self.max_rate = *max_rate;
+            }
+        }
+
         let config_path = self.config_flag.clone().or(self.config_pos.clone());
 
         let final_config_path = if let Some(path) = config_path {
@@ -509,24 +2100,50 @@ impl Args {
             if self.end_ip.is_none() {
                 self.end_ip = config.scan.end_ip;
             }
+            if self.seed_domains.is_none() {
+                self.seed_domains = config.scan.seed_domains;
+            }
+            if self.input_file.is_none() {
+                self.input_file = config.scan.input_file;
+            }
             if self.ports == default_ports() {
                 self.ports = config.scan.ports;
             }
+            if self.ports_file.is_none() {
+                self.ports_file = config.scan.ports_file;
+            }
+            self.port_groups = config
+                .port_groups
+                .into_iter()
+                .map(|(name, ports)| (name.to_ascii_lowercase(), ports))
+                .collect();
             if self.timeout == default_timeout() {
                 self.timeout = config.scan.timeout;
             }
             if self.concurrency == default_concurrency() {
                 self.concurrency = config.scan.concurrency;
             }
+            if self.host_concurrency == default_host_concurrency() {
+                self.host_concurrency = config.scan.host_concurrency;
+            }
             if self.database == default_database() {
                 self.database = config.scan.database;
             }
             if !self.verbose {
                 self.verbose = config.scan.verbose;
             }
+            if !self.quiet {
+                self.quiet = config.scan.quiet;
+            }
             if !self.loop_mode {
                 self.loop_mode = config.scan.loop_mode;
             }
+            if self.summary_format == default_summary_format() {
+                self.summary_format = config.scan.summary_format;
+            }
+            if !self.json_summary {
+                self.json_summary = config.scan.json_summary;
+            }
             if !self.ipv4 {
                 self.ipv4 = config.scan.ipv4;
             }
@@ -545,6 +2162,12 @@ impl Args {
             if self.geoip_db.is_none() {
                 self.geoip_db = config.scan.geoip_db;
             }
+            if self.whois_servers.is_none() {
+                self.whois_servers = config.scan.whois_servers;
+            }
+            if self.geo_csv.is_empty() {
+                self.geo_csv = config.scan.geo_csv;
+            }
             if !self.no_geo {
                 self.no_geo = config.scan.no_geo;
             }
@@ -557,6 +2180,27 @@ impl Args {
             if self.probe_concurrency == default_probe_concurrency() {
                 self.probe_concurrency = config.scan.probe_concurrency;
             }
+            if self.probe_rate == default_probe_rate() {
+                self.probe_rate = config.scan.probe_rate;
+            }
+            if self.grab_banner_ms == 0 {
+                self.grab_banner_ms = config.scan.grab_banner_ms;
+            }
+            if self.sni_hosts.is_none() {
+                self.sni_hosts = config.scan.sni_hosts;
+            }
+            if !self.sni_from_rdns {
+                self.sni_from_rdns = config.scan.sni_from_rdns;
+            }
+            if self.cve_db.is_none() {
+                self.cve_db = config.scan.cve_db;
+            }
+            if self.reputation_providers.is_empty() {
+                self.reputation_providers = config.scan.reputation_providers;
+            }
+            if self.reputation_rate == default_reputation_rate() {
+                self.reputation_rate = config.scan.reputation_rate;
+            }
             if self.geo_concurrency == default_geo_concurrency() {
                 self.geo_concurrency = config.scan.geo_concurrency;
             }
@@ -575,15 +2219,89 @@ impl Args {
             if self.flush_interval_ms == default_flush_interval_ms() {
                 self.flush_interval_ms = config.scan.flush_interval_ms;
             }
+            if !self.adaptive_batching {
+                self.adaptive_batching = config.scan.adaptive_batching;
+            }
+            if self.wal_checkpoint_secs == default_wal_checkpoint_secs() {
+                self.wal_checkpoint_secs = config.scan.wal_checkpoint_secs;
+            }
+            if self.wal_truncate_mb == default_wal_truncate_mb() {
+                self.wal_truncate_mb = config.scan.wal_truncate_mb;
+            }
+            // [rate_limit] is the fallback for [scan].max_rate/rate_window_secs.
             if self.max_rate == default_max_rate() {
-                self.max_rate = config.scan.max_rate;
+                self.max_rate = if config.scan.max_rate != default_max_rate() {
+                    config.scan.max_rate
+                } else {
+                    config.rate_limit.max_rate
+                };
             }
             if self.rate_window_secs == default_window_duration() {
-                self.rate_window_secs = config.scan.rate_window_secs;
+                self.rate_window_secs = if config.scan.rate_window_secs != default_window_duration()
+                {
+                    config.scan.rate_window_secs
+                } else {
+                    config.rate_limit.window_duration
+                };
+            }
+            if self.rate_burst == 0 {
+                self.rate_burst = config.scan.rate_burst;
+            }
+            if self.syn_linger_secs == default_syn_linger_secs() {
+                self.syn_linger_secs = config.scan.syn_linger_secs;
+            }
+            if self.io_backend == default_io_backend() {
+                self.io_backend = config.scan.io_backend;
+            }
+            if self.source_port_range.is_none() {
+                self.source_port_range = config.scan.source_port_range;
             }
             if self.round_delay_ms == default_round_delay_ms() {
                 self.round_delay_ms = config.scan.round_delay_ms;
             }
+            if self.stale_rounds == default_stale_rounds() {
+                self.stale_rounds = config.scan.stale_rounds;
+            }
+            if !self.port_history {
+                self.port_history = config.scan.port_history;
+            }
+            if self.storage_engine == default_storage_engine() {
+                self.storage_engine = config.scan.storage_engine;
+            }
+            if self.bitmap_dir == default_bitmap_dir() {
+                self.bitmap_dir = config.scan.bitmap_dir;
+            }
+            if self.db_shards == default_db_shards() {
+                self.db_shards = config.scan.db_shards;
+            }
+            if self.timeseries_minutes == default_timeseries_minutes() {
+                self.timeseries_minutes = config.scan.timeseries_minutes;
+            }
+            if self.priority_weights.is_empty() {
+                self.priority_weights = config.scan.priority_weights;
+            }
+            if self.scan_window.is_none() {
+                self.scan_window = config.scan.scan_window;
+            }
+            if self.exclude_file.is_none() {
+                self.exclude_file = config.scan.exclude_file;
+            }
+            if self.exclude_country.is_empty() {
+                self.exclude_country = config.scan.exclude_country;
+            }
+            if self.script.is_none() {
+                self.script = config.scan.script;
+            }
+            if self.report_email.is_empty() {
+                self.report_email = config.report_email.to.clone();
+            }
+            self.report_email_config = config.report_email;
+            self.notify = config.notify;
+            self.mqtt = config.mqtt;
+            self.syslog = config.syslog;
+            self.metrics_push = config.metrics_push;
+            self.maintenance = config.maintenance;
+            self.quotas = config.quotas;
             if !self.api {
                 self.api = config.api.enabled;
             }
@@ -596,12 +2314,51 @@ impl Args {
             if self.api_host == default_api_host() {
                 self.api_host = config.api.host;
             }
+            if self.attach_db.is_empty() {
+                self.attach_db = config.api.attach_db;
+            }
+            if self.api_allow_cidr.is_empty() {
+                self.api_allow_cidr = config.api.allow_cidr;
+            }
+            if self.api_trusted_proxy.is_empty() {
+                self.api_trusted_proxy = config.api.trusted_proxy;
+            }
+            if !self.api_read_only {
+                self.api_read_only = config.api.read_only;
+            }
+            if self.api_max_range == default_api_max_range() {
+                self.api_max_range = config.api.max_range;
+            }
+            if self.api_tls_cert.is_none() {
+                self.api_tls_cert = config.api.tls_cert;
+            }
+            if self.api_tls_key.is_none() {
+                self.api_tls_key = config.api.tls_key;
+            }
+            if self.api_client_ca.is_none() {
+                self.api_client_ca = config.api.client_ca;
+            }
+            if self.namespace_dir == default_namespace_dir() {
+                self.namespace_dir = config.api.namespace_dir;
+            }
+            if self.api_operator.is_empty() {
+                self.api_operator = config.api.operators;
+            }
             if self.api_port == default_api_port() {
                 self.api_port = config.api.port;
             }
             if !self.swagger_ui {
                 self.swagger_ui = config.scan.swagger_ui;
             }
+            if !self.daemon {
+                self.daemon = config.scan.daemon;
+            }
+            if self.pid_file == default_pid_file() {
+                self.pid_file = config.scan.pid_file;
+            }
+            if self.log_file == default_log_file() {
+                self.log_file = config.scan.log_file;
+            }
         } else {
             // Apply defaults when no config file is found
             if !self.loop_mode {
@@ -618,6 +2375,27 @@ impl Args {
             }
         }
 
+        if let Some(path) = &self.ports_file {
+            let listed = crate::model::read_ports_file(std::path::Path::new(path))
+                .map_err(|e| anyhow::anyhow!(e))?;
+            self.ports = if self.ports == default_ports() {
+                listed
+            } else {
+                format!("{},{}", self.ports, listed)
+            };
+        }
+        // Expand custom groups in the CLI's own ports so coordinator leases
+        // carry concrete ports to workers with other configs. API requests
+        // still name groups and resolve them through `self.port_groups`.
+        if !self.port_groups.is_empty() {
+            self.ports = crate::model::expand_port_groups(&self.ports, &self.port_groups)
+                .map_err(|e| anyhow::anyhow!(e))?;
+        }
+        if let Some(path) = &self.ports_file {
+            crate::model::parse_port_range(&self.ports, &self.port_groups)
+                .map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+        }
+
         self.apply_preset();
 
         if let Some(ref target) = self.target {
@@ -641,6 +2419,21 @@ impl Args {
         Ok(self)
     }
 
+    /// Hostnames given as `--target`, lowercased; `None` for an IP, CIDR or
+    /// range target.
+    pub fn target_hostnames(&self) -> Option<Vec<String>> {
+        let target = self.target.as_deref()?;
+        if crate::model::IpRange::parse_target(target).is_ok() {
+            return None;
+        }
+        Some(
+            target
+                .split(',')
+                .map(|name| name.trim().trim_end_matches('.').to_ascii_lowercase())
+                .collect(),
+        )
+    }
+
     /// Validate configuration parameters
     pub fn validate(&self) -> anyhow::Result<()> {
         // Validate timeout
@@ -652,6 +2445,9 @@ impl Args {
         if self.concurrency == 0 {
             return Err(anyhow::anyhow!("Concurrency must be greater than 0"));
         }
+        if self.host_concurrency == 0 {
+            return Err(anyhow::anyhow!("Host concurrency must be greater than 0"));
+        }
 
         // Validate buffer sizes
         if self.pipeline_buffer == 0 {
@@ -666,6 +2462,16 @@ impl Args {
         if self.db_batch_size == 0 {
             return Err(anyhow::anyhow!("DB batch size must be greater than 0"));
         }
+        if self.maintenance.keep_rounds <= 0 {
+            return Err(anyhow::anyhow!(
+                "[maintenance] keep_rounds must be greater than 0"
+            ));
+        }
+        if self.wal_checkpoint_secs == 0 || self.wal_truncate_mb == 0 {
+            return Err(anyhow::anyhow!(
+                "WAL checkpoint interval and truncate size must be greater than 0"
+            ));
+        }
 
         // Validate rate limiting
         if self.max_rate == 0 {
@@ -675,9 +2481,133 @@ impl Args {
             return Err(anyhow::anyhow!("Rate window must be greater than 0"));
         }
 
+        if self
+            .priority_weights
+            .iter()
+            .any(|w| !(1..=MAX_PRIORITY_WEIGHT).contains(w))
+        {
+            return Err(anyhow::anyhow!(
+                "Priority weights must be between 1 and {}",
+                MAX_PRIORITY_WEIGHT
+            ));
+        }
+
+        if self.api_read_only && self.coordinator {
+            return Err(anyhow::anyhow!(
+                "--api-read-only cannot serve --coordinator leases to workers"
+            ));
+        }
+
         if self.round_delay_ms > 600_000 {
             return Err(anyhow::anyhow!("Round delay must not exceed 600000 ms"));
         }
+        if self.grab_banner_ms > 10_000 {
+            return Err(anyhow::anyhow!("Banner grab wait must not exceed 10000 ms"));
+        }
+
+        if !matches!(self.io_backend.as_str(), "tokio" | "uring") {
+            return Err(anyhow::anyhow!(
+                "IO backend must be \"tokio\" or \"uring\", got {:?}",
+                self.io_backend
+            ));
+        }
+        if self.db_shards == 0 || self.db_shards > crate::dao::MAX_BITMAP_SHARDS {
+            return Err(anyhow::anyhow!(
+                "DB shards must be between 1 and {}",
+                crate::dao::MAX_BITMAP_SHARDS
+            ));
+        }
+        if self.timeseries_minutes > 1440 {
+            return Err(anyhow::anyhow!(
+                "Timeseries minutes must be at most 1440 (one day)"
+            ));
+        }
+        if !matches!(self.storage_engine.as_str(), "sqlite" | "mmap") {
+            return Err(anyhow::anyhow!(
+                "Storage engine must be \"sqlite\" or \"mmap\", got {:?}",
+                self.storage_engine
+            ));
+        }
+
+        self.parsed_scan_window()?;
+        self.attached_databases()?;
+        self.api_allowlist()?;
+        self.api_trusted_proxies()?;
+        self.parsed_source_ports()?;
+        // The file alone: resolving countries walks the geo datasets.
+        if let Some(path) = &self.exclude_file {
+            crate::model::ExcludeList::load(std::path::Path::new(path))
+                .map_err(|e| anyhow::anyhow!(e))?;
+        }
+        for country in &self.exclude_country {
+            if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(anyhow::anyhow!(
+                    "--exclude-country takes ISO 3166-1 alpha-2 codes, got {:?}",
+                    country
+                ));
+            }
+        }
+        if !self.exclude_country.is_empty() && self.geoip_db.is_none() && self.geo_csv.is_empty() {
+            return Err(anyhow::anyhow!(
+                "--exclude-country needs a country dataset: --geoip-db or --geo-csv"
+            ));
+        }
+        self.load_sni_hosts()?;
+        if self.seed_domains.is_some() && self.load_seed_domains()?.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Seed domain file {} contains no domain names",
+                self.seed_domains.as_deref().unwrap_or_default()
+            ));
+        }
+        if let Some(ref path) = self.input_file {
+            if self.target.is_some() || self.seed_domains.is_some() {
+                return Err(anyhow::anyhow!(
+                    "--input-file replaces --target and --seed-domains; give only one"
+                ));
+            }
+            if self.coordinator || self.worker.is_some() {
+                return Err(anyhow::anyhow!(
+                    "--input-file is not supported with --coordinator or --worker"
+                ));
+            }
+            if self.load_input_file()?.is_empty() {
+                return Err(anyhow::anyhow!("Input file {} lists no addresses", path));
+            }
+        }
+
+        if let Some(ref path) = self.cve_db {
+            if !std::path::Path::new(path).exists() {
+                return Err(anyhow::anyhow!("CVE data not found: {}", path));
+            }
+        }
+
+        for provider in &self.reputation_providers {
+            if !crate::service::REPUTATION_PROVIDERS.contains(&provider.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "Unknown reputation provider {:?} (expected one of {})",
+                    provider,
+                    crate::service::REPUTATION_PROVIDERS.join(", ")
+                ));
+            }
+        }
+        if self.reputation_providers.iter().any(|p| p == "abuseipdb")
+            && self.abuseipdb_key.is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "The abuseipdb reputation provider needs SCAN_ABUSEIPDB_KEY"
+            ));
+        }
+
+        if let Some(ref path) = self.whois_servers {
+            if !std::path::Path::new(path).is_file() {
+                return Err(anyhow::anyhow!("Whois server list not found: {}", path));
+            }
+        }
+        for path in &self.geo_csv {
+            if !std::path::Path::new(path).is_file() {
+                return Err(anyhow::anyhow!("Geo dataset not found: {}", path));
+            }
+        }
 
         // Validate API port
         if self.api_port == 0 {
@@ -691,6 +2621,45 @@ impl Args {
             ));
         }
 
+        if self.quiet && self.verbose {
+            return Err(anyhow::anyhow!("Cannot use --quiet and --verbose together"));
+        }
+        if self.json_summary && (self.api_only || self.coordinator || self.worker.is_some()) {
+            return Err(anyhow::anyhow!(
+                "--json-summary needs a scan run, not --api-only, --coordinator or --worker"
+            ));
+        }
+
+        if self.api_tls_cert.is_some() != self.api_tls_key.is_some() {
+            return Err(anyhow::anyhow!(
+                "--api-tls-cert and --api-tls-key must be given together"
+            ));
+        }
+        if self.api_client_ca.is_some() && self.api_tls_cert.is_none() {
+            return Err(anyhow::anyhow!("--api-client-ca needs --api-tls-cert"));
+        }
+        if self.api_client_ca.is_some() && self.coordinator {
+            return Err(anyhow::anyhow!(
+                "--api-client-ca would shut out --worker processes, which present no client certificate"
+            ));
+        }
+        for path in [&self.api_tls_cert, &self.api_tls_key, &self.api_client_ca]
+            .into_iter()
+            .flatten()
+        {
+            if !std::path::Path::new(path).is_file() {
+                return Err(anyhow::anyhow!("API TLS file not found: {}", path));
+            }
+        }
+
+        if let Some(ref url) = self.worker {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(anyhow::anyhow!(
+                    "--worker expects the coordinator's http:// or https:// URL"
+                ));
+            }
+        }
+
         // Validate IP version selection
         if !self.ipv4 && !self.ipv6 {
             return Err(anyhow::anyhow!(
@@ -700,17 +2669,199 @@ impl Args {
 
         if let Some(ref target) = self.target {
             if crate::model::IpRange::parse_target(target).is_err() {
-                return Err(anyhow::anyhow!("Invalid target format: {}. Use IP, CIDR (e.g. 192.168.1.0/24), or range (e.g. 192.168.1.1-192.168.1.255)", target));
+                let names: Vec<&str> = target.split(',').map(str::trim).collect();
+                if !names.iter().all(|name| crate::model::is_hostname(name)) {
+                    return Err(anyhow::anyhow!("Invalid target format: {}. Use IP, CIDR (e.g. 192.168.1.0/24), range (e.g. 192.168.1.1-192.168.1.255), or hostnames (e.g. example.com,www.example.org)", target));
+                }
+                if names.len() > crate::service::MAX_TARGET_HOSTNAMES {
+                    return Err(anyhow::anyhow!(
+                        "At most {} target hostnames are supported",
+                        crate::service::MAX_TARGET_HOSTNAMES
+                    ));
+                }
             }
         }
 
         if self.output_format != "text" && self.output_format != "json" {
             return Err(anyhow::anyhow!("Output format must be 'text' or 'json'"));
         }
+        crate::service::SummaryFormat::parse(&self.summary_format)?;
 
         Ok(())
     }
 
+    /// The `--scan-window` setting, parsed.
+    pub fn parsed_scan_window(&self) -> anyhow::Result<Option<crate::model::ScanWindow>> {
+        self.scan_window
+            .as_deref()
+            .map(crate::model::ScanWindow::parse)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    pub fn parsed_source_ports(&self) -> anyhow::Result<Option<crate::model::SourcePorts>> {
+        self.source_port_range
+            .as_deref()
+            .map(crate::model::SourcePorts::parse)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    /// The `--excludefile` list and the `--exclude-country` networks,
+    /// loaded and merged.
+    pub fn load_exclude_list(&self) -> anyhow::Result<Option<crate::model::ExcludeList>> {
+        let mut list = self
+            .exclude_file
+            .as_deref()
+            .map(|path| crate::model::ExcludeList::load(std::path::Path::new(path)))
+            .transpose()
+            .map_err(|e| anyhow::anyhow!(e))?;
+        if !self.exclude_country.is_empty() {
+            let ranges = crate::service::country_ranges(
+                self.geoip_db.as_deref(),
+                &self.geo_csv,
+                &self.exclude_country,
+            )?;
+            tracing::info!(
+                "Excluding {} networks in {}",
+                ranges.len(),
+                self.exclude_country.join(",")
+            );
+            list.get_or_insert_with(Default::default)
+                .extend(&ranges)
+                .map_err(|e| anyhow::anyhow!(e))?;
+        }
+        Ok(list)
+    }
+
+    /// The `--api-allow-cidr` networks, in the `--excludefile` syntax; `None`
+    /// when every client is allowed.
+    pub fn api_allowlist(&self) -> anyhow::Result<Option<crate::model::ExcludeList>> {
+        if self.api_allow_cidr.is_empty() {
+            return Ok(None);
+        }
+        crate::model::ExcludeList::parse(&self.api_allow_cidr.join("\n"))
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("--api-allow-cidr: {}", e))
+    }
+
+    /// The `--api-trusted-proxy` networks, in the `--excludefile` syntax;
+    /// `None` when no proxy is trusted.
+    pub fn api_trusted_proxies(&self) -> anyhow::Result<Option<crate::model::ExcludeList>> {
+        if self.api_trusted_proxy.is_empty() {
+            return Ok(None);
+        }
+        crate::model::ExcludeList::parse(&self.api_trusted_proxy.join("\n"))
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("--api-trusted-proxy: {}", e))
+    }
+
+    /// The `--attach-db` entries as (name, path) pairs. Names are what the
+    /// API's `db` parameter takes; `main` is the `--database` itself.
+    pub fn attached_databases(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let mut attached: Vec<(String, String)> = Vec::new();
+        for entry in &self.attach_db {
+            let (name, path) = entry
+                .split_once('=')
+                .map(|(name, path)| (name.trim(), path.trim()))
+                .filter(|(name, path)| !name.is_empty() && !path.is_empty())
+                .ok_or_else(|| anyhow::anyhow!("--attach-db {:?} must be NAME=PATH", entry))?;
+            if !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err(anyhow::anyhow!(
+                    "--attach-db name {:?} may only contain letters, digits, '_' and '-'",
+                    name
+                ));
+            }
+            if name == "main" || attached.iter().any(|(other, _)| other == name) {
+                return Err(anyhow::anyhow!(
+                    "--attach-db name {:?} is already in use",
+                    name
+                ));
+            }
+            attached.push((name.to_string(), path.to_string()));
+        }
+        Ok(attached)
+    }
+
+    /// The results database, decrypted with `--db-key` when set, its
+    /// bitmaps sharded by `--db-shards` and under `--bitmap-dir` for
+    /// `--storage-engine mmap`.
+    pub fn open_database(&self) -> anyhow::Result<crate::dao::SqliteDB> {
+        let db = crate::dao::SqliteDB::with_key(&self.database, self.db_key.as_deref())?
+            .with_port_history(self.port_history)
+            .with_bitmap_shards(self.db_shards)?;
+        if self.storage_engine == "mmap" {
+            return Ok(db.with_bitmap_store(crate::dao::MmapBitmapStore::open(&self.bitmap_dir)?));
+        }
+        Ok(db)
+    }
+
+    /// The `--sni-hosts` hostname list; empty when unset.
+    pub fn load_sni_hosts(&self) -> anyhow::Result<crate::model::HostnameList> {
+        match self.sni_hosts.as_deref() {
+            Some(path) => crate::model::HostnameList::load(std::path::Path::new(path))
+                .map_err(|e| anyhow::anyhow!(e)),
+            None => Ok(crate::model::HostnameList::default()),
+        }
+    }
+
+    /// The `--seed-domains` names; empty when unset.
+    pub fn load_seed_domains(&self) -> anyhow::Result<crate::model::DomainSeeds> {
+        match self.seed_domains.as_deref() {
+            Some(path) => crate::model::DomainSeeds::load(std::path::Path::new(path))
+                .map_err(|e| anyhow::anyhow!(e)),
+            None => Ok(crate::model::DomainSeeds::default()),
+        }
+    }
+
+    /// Hostnames scanned instead of an IP range: `--target` hostnames plus
+    /// the `--seed-domains` names. The seed file is read on every call, so
+    /// a refreshed export applies from the next round. `None` when neither
+    /// is given.
+    pub fn scan_hostnames(&self) -> anyhow::Result<Option<Vec<String>>> {
+        let seeds = self.load_seed_domains()?;
+        let mut names = self.target_hostnames().unwrap_or_default();
+        names.extend(seeds.names().cloned());
+        names.sort();
+        names.dedup();
+        Ok((!names.is_empty()).then_some(names))
+    }
+
+    /// The `--input-file` ranges, merged and sorted (IPv4 first); empty
+    /// when unset. The file is read on every call, so a list rewritten
+    /// between rounds applies from the next one.
+    pub fn load_input_file(&self) -> anyhow::Result<Vec<crate::model::IpRange>> {
+        match self.input_file.as_deref() {
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow::anyhow!("Failed to read input file {}: {}", path, e))?;
+                crate::model::ExcludeList::parse(&content)
+                    .map(|list| list.ranges().collect())
+                    .map_err(|e| anyhow::anyhow!("{}: {}", path, e))
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// The range `--seed-domains` addresses must fall in to be scanned:
+    /// the range target or `--start-ip`/`--end-ip`, when given.
+    pub fn seed_scope(&self) -> Option<crate::model::IpRange> {
+        self.seed_domains.as_ref()?;
+        let (start, end) = self.start_ip.as_ref().zip(self.end_ip.as_ref())?;
+        crate::model::IpRange::new(start, end).ok()
+    }
+
+    /// The `--script` hooks, compiled.
+    pub fn load_script_hooks(&self) -> anyhow::Result<Option<crate::service::ScriptHooks>> {
+        self.script
+            .as_deref()
+            .map(|path| crate::service::ScriptHooks::load(std::path::Path::new(path)))
+            .transpose()
+    }
+
     pub fn get_default_ipv4_range() -> (String, String) {
         ("0.0.0.0".to_string(), "255.255.255.255".to_string())
     }
@@ -744,9 +2895,202 @@ mod tests {
         assert_eq!(args.config_flag, Some(PathBuf::from("scanner.toml")));
     }
 
+    #[test]
+    fn test_control_commands_do_not_shadow_positional_config() {
+        let args = Args::try_parse_from(["ip-scan", "stop"]).unwrap();
+        assert_eq!(args.command, Some(Command::Stop));
+        assert_eq!(args.config_pos, None);
+
+        let args =
+            Args::try_parse_from(["ip-scan", "status", "--pid-file", "/tmp/scan.pid"]).unwrap();
+        assert_eq!(args.command, Some(Command::Status));
+        assert_eq!(args.pid_file, "/tmp/scan.pid");
+
+        let args = Args::try_parse_from(["ip-scan", "scanner.toml"]).unwrap();
+        assert_eq!(args.command, None);
+        assert_eq!(args.config_pos, Some(PathBuf::from("scanner.toml")));
+    }
+
+    #[test]
+    fn test_export_targets_needs_no_result_export_flags() {
+        let args = Args::try_parse_from([
+            "ip-scan",
+            "export",
+            "targets",
+            "--port",
+            "443",
+            "--out",
+            "hosts.txt",
+        ])
+        .unwrap();
+        let Some(Command::Export {
+            list: Some(list), ..
+        }) = args.command
+        else {
+            panic!("expected export targets");
+        };
+        let ExportCommand::Targets { filter, output } = *list;
+        assert_eq!(filter.port, Some(443));
+        assert_eq!(output, Some(PathBuf::from("hosts.txt")));
+
+        assert!(Args::try_parse_from(["ip-scan", "export", "--format", "csv"]).is_err());
+        assert!(Args::try_parse_from(["ip-scan", "export", "-o", "a.csv", "targets"]).is_err());
+    }
+
+    #[test]
+    fn test_input_file_lists_the_scan_targets() {
+        let mut list = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(
+            &mut list,
+            b"192.0.2.9\n192.0.2.10\n192.0.2.0/28\n198.51.100.1\n",
+        )
+        .unwrap();
+        let path = list.path().to_str().unwrap();
+
+        let args = Args::try_parse_from(["ip-scan", "--input-file", path])
+            .unwrap()
+            .merge_with_config()
+            .unwrap();
+        let ranges = args.load_input_file().unwrap();
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges[0].count(), 16);
+        assert_eq!(ranges[1].start.to_string(), "198.51.100.1");
+
+        let args =
+            Args::try_parse_from(["ip-scan", "--input-file", path, "-T", "192.0.2.0/24"]).unwrap();
+        assert!(args.merge_with_config().is_err());
+        let args =
+            Args::try_parse_from(["ip-scan", "--input-file", "/nonexistent/hosts.txt"]).unwrap();
+        assert!(args.merge_with_config().is_err());
+    }
+
+    #[test]
+    fn test_priority_weights_are_parsed_and_bounded() {
+        let args = Args::try_parse_from(["ip-scan", "--priority-weights", "4,2"]).unwrap();
+        assert_eq!(args.priority_weights, vec![4, 2]);
+        assert!(Args::try_parse_from(["ip-scan", "--priority-weights", "4,0"]).is_err());
+        assert!(Args::try_parse_from(["ip-scan", "--priority-weights", "65"]).is_err());
+    }
+
+    #[test]
+    fn test_attach_db_entries_need_distinct_names() {
+        let args =
+            Args::try_parse_from(["ip-scan", "--attach-db", "eu=scan_eu.db, us-1=/data/us.db"])
+                .unwrap();
+        assert_eq!(
+            args.attached_databases().unwrap(),
+            vec![
+                ("eu".to_string(), "scan_eu.db".to_string()),
+                ("us-1".to_string(), "/data/us.db".to_string()),
+            ]
+        );
+        for bad in ["eu", "=eu.db", "e u=eu.db", "main=eu.db", "eu=a.db,eu=b.db"] {
+            let args = Args::try_parse_from(["ip-scan", "--attach-db", bad]).unwrap();
+            assert!(args.attached_databases().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_sample_config_matches_defaults() {
+        let config: Config = toml::from_str(&sample_config()).unwrap();
+        let defaults = ScanConfig::default();
+        assert_eq!(config.scan.ports, defaults.ports);
+        assert_eq!(config.scan.timeout, defaults.timeout);
+        assert_eq!(config.scan.concurrency, defaults.concurrency);
+        assert_eq!(config.scan.host_concurrency, defaults.host_concurrency);
+        assert_eq!(config.scan.max_rate, defaults.max_rate);
+        assert_eq!(config.scan.db_batch_size, defaults.db_batch_size);
+        assert_eq!(config.scan.priority_weights, defaults.priority_weights);
+        assert_eq!(config.scan.pid_file, defaults.pid_file);
+        assert_eq!(config.rate_limit.max_rate, default_max_rate());
+        assert_eq!(config.api.port, default_api_port());
+
+        // Clap and config defaults agree, so merging the sample is a no-op.
+        let cli = Args::try_parse_from(["ip-scan"]).unwrap();
+        assert_eq!(cli.concurrency, defaults.concurrency);
+        assert_eq!(cli.host_concurrency, defaults.host_concurrency);
+        assert_eq!(cli.max_rate, defaults.max_rate);
+    }
+
+    #[test]
+    fn test_rate_limit_section_is_fallback_for_scan_rate() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"[rate_limit]\nmax_rate = 5000\n").unwrap();
+        let path = file.path().to_str().unwrap();
+
+        let args = Args::try_parse_from(["ip-scan", "--config", path])
+            .unwrap()
+            .merge_with_config()
+            .unwrap();
+        assert_eq!(args.max_rate, 5000);
+    }
+
+    #[test]
+    fn test_config_port_groups_expand_ports() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"[port_groups]\nEdge = \"web,9000-9001\"\n").unwrap();
+        let path = file.path().to_str().unwrap();
+
+        let args = Args::try_parse_from(["ip-scan", "--config", path, "-p", "edge,22"])
+            .unwrap()
+            .merge_with_config()
+            .unwrap();
+        let ports = crate::model::parse_port_range(&args.ports, &args.port_groups).unwrap();
+        assert!(ports.contains(&22) && ports.contains(&443) && ports.contains(&9001));
+        // Kept for API requests, which name groups after startup.
+        assert_eq!(args.port_groups["edge"], "web,9000-9001");
+    }
+
+    #[test]
+    fn test_ports_file_merges_with_ports() {
+        let mut config = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut config, b"[port_groups]\nedge = \"9000-9001\"\n").unwrap();
+        let mut list = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(
+            &mut list,
+            b"# staging\n8080\n\n10000-10002  # admin\nedge\n",
+        )
+        .unwrap();
+        let (config, list) = (
+            config.path().to_str().unwrap(),
+            list.path().to_str().unwrap(),
+        );
+        let parse = |extra: &[&str]| {
+            let mut argv = vec!["ip-scan", "--config", config, "--ports-file", list];
+            argv.extend(extra);
+            Args::try_parse_from(argv).unwrap().merge_with_config()
+        };
+
+        let no_groups = HashMap::new();
+        let ports = crate::model::parse_port_range(&parse(&[]).unwrap().ports, &no_groups).unwrap();
+        assert_eq!(ports, [8080, 9000, 9001, 10000, 10001, 10002]);
+        let ports =
+            crate::model::parse_port_range(&parse(&["-p", "22"]).unwrap().ports, &no_groups)
+                .unwrap();
+        assert_eq!(ports[0], 22);
+        assert_eq!(ports.len(), 7);
+
+        let mut bad = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut bad, b"80\n70000\n").unwrap();
+        let bad = bad.path().to_str().unwrap();
+        assert!(
+            Args::try_parse_from(["ip-scan", "--config", config, "--ports-file", bad])
+                .unwrap()
+                .merge_with_config()
+                .is_err()
+        );
+        assert!(
+            Args::try_parse_from(["ip-scan", "--ports-file", "/nonexistent/ports.txt"])
+                .unwrap()
+                .merge_with_config()
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_rejects_zero_runtime_limits() {
         assert!(Args::try_parse_from(["ip-scan", "--concurrency", "0"]).is_err());
+        assert!(Args::try_parse_from(["ip-scan", "--host-concurrency", "0"]).is_err());
         assert!(Args::try_parse_from(["ip-scan", "--timeout", "0"]).is_err());
         assert!(Args::try_parse_from(["ip-scan", "--probe-concurrency", "0"]).is_err());
     }
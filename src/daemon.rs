@@ -0,0 +1,254 @@
+//! Background (daemon) mode and the `stop`/`status` control commands.
+//!
+//! The daemon keeps its working directory so that relative paths such as the
+//! database, `./web` and the GeoIP file resolve exactly as they would in the
+//! foreground. The pid file is the only coordination point between the daemon
+//! and the control commands; scan status is read back through the HTTP API.
+
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::cli::Args;
+
+/// How long `ip-scan stop` waits for the daemon to flush and exit.
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fork into the background, detach from the controlling terminal, redirect
+/// stdio to `log_file` and record the daemon pid in `pid_file`.
+///
+/// Must run before the tokio runtime is built: `fork` only carries the calling
+/// thread into the child, so any worker threads would silently disappear.
+#[cfg(unix)]
+pub fn daemonize(pid_file: &str, log_file: &str) -> Result<()> {
+    use std::fs::OpenOptions;
+    use std::os::unix::io::AsRawFd;
+
+    if let Some(pid) = read_pid_file(pid_file)? {
+        if process_alive(pid) {
+            return Err(anyhow!(
+                "ip-scan is already running with pid {} (pid file {})",
+                pid,
+                pid_file
+            ));
+        }
+    }
+
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .with_context(|| format!("Failed to open log file {}", log_file))?;
+    let devnull = OpenOptions::new().read(true).open("/dev/null")?;
+
+    println!(
+        "ip-scan starting in background (pid file: {}, log file: {})",
+        pid_file, log_file
+    );
+
+    // SAFETY: called from the single-threaded part of `main`, before any
+    // runtime or helper thread exists.
+    unsafe {
+        fork_and_exit_parent()?;
+        if libc::setsid() == -1 {
+            return Err(std::io::Error::last_os_error()).context("setsid failed");
+        }
+        // Second fork: the session leader exits so the daemon can never
+        // reacquire a controlling terminal.
+        fork_and_exit_parent()?;
+        libc::umask(0o027);
+
+        for (src, dst) in [
+            (devnull.as_raw_fd(), libc::STDIN_FILENO),
+            (log.as_raw_fd(), libc::STDOUT_FILENO),
+            (log.as_raw_fd(), libc::STDERR_FILENO),
+        ] {
+            if libc::dup2(src, dst) == -1 {
+                return Err(std::io::Error::last_os_error()).context("dup2 failed");
+            }
+        }
+    }
+
+    std::fs::write(pid_file, format!("{}\n", std::process::id()))
+        .with_context(|| format!("Failed to write pid file {}", pid_file))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn daemonize(_pid_file: &str, _log_file: &str) -> Result<()> {
+    Err(anyhow!("--daemon is only supported on Unix platforms"))
+}
+
+#[cfg(unix)]
+unsafe fn fork_and_exit_parent() -> Result<()> {
+    match libc::fork() {
+        -1 => Err(std::io::Error::last_os_error()).context("fork failed"),
+        0 => Ok(()),
+        _ => libc::_exit(0),
+    }
+}
+
+/// Remove the pid file on shutdown, but only if it still belongs to us.
+pub fn remove_pid_file(pid_file: &str) {
+    if let Ok(Some(pid)) = read_pid_file(pid_file) {
+        if pid == std::process::id() {
+            let _ = std::fs::remove_file(pid_file);
+        }
+    }
+}
+
+fn read_pid_file(pid_file: &str) -> Result<Option<u32>> {
+    if !Path::new(pid_file).exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(pid_file)
+        .with_context(|| format!("Failed to read pid file {}", pid_file))?;
+    let pid = content
+        .trim()
+        .parse::<u32>()
+        .with_context(|| format!("Invalid pid file {}", pid_file))?;
+    Ok(Some(pid))
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    // Signal 0 performs the permission and existence checks without
+    // delivering anything. EPERM still means the process exists.
+    let rc = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    rc == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    false
+}
+
+/// `ip-scan stop`: ask the daemon to shut down gracefully and wait for it.
+#[cfg(unix)]
+pub fn stop(args: &Args) -> Result<()> {
+    let pid = match read_pid_file(&args.pid_file)? {
+        Some(pid) => pid,
+        None => {
+            println!("ip-scan is not running (no pid file at {})", args.pid_file);
+            return Ok(());
+        }
+    };
+
+    if !process_alive(pid) {
+        let _ = std::fs::remove_file(&args.pid_file);
+        println!(
+            "ip-scan is not running (removed stale pid file for pid {})",
+            pid
+        );
+        return Ok(());
+    }
+
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == -1 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to signal pid {}", pid));
+    }
+    println!(
+        "Sent SIGTERM to ip-scan (pid {}), waiting for shutdown...",
+        pid
+    );
+
+    let deadline = Instant::now() + STOP_TIMEOUT;
+    while Instant::now() < deadline {
+        if !process_alive(pid) {
+            let _ = std::fs::remove_file(&args.pid_file);
+            println!("ip-scan stopped");
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    Err(anyhow!(
+        "ip-scan (pid {}) did not exit within {}s",
+        pid,
+        STOP_TIMEOUT.as_secs()
+    ))
+}
+
+#[cfg(not(unix))]
+pub fn stop(_args: &Args) -> Result<()> {
+    Err(anyhow!(
+        "`ip-scan stop` is only supported on Unix platforms"
+    ))
+}
+
+/// `ip-scan status`: report the daemon pid and, when the API is reachable,
+/// the current scan status.
+pub fn status(args: &Args) -> Result<()> {
+    match read_pid_file(&args.pid_file)? {
+        Some(pid) if process_alive(pid) => println!("ip-scan is running (pid {})", pid),
+        Some(pid) => println!("ip-scan is not running (stale pid file for pid {})", pid),
+        None => println!("ip-scan is not running (no pid file at {})", args.pid_file),
+    }
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    match rt.block_on(fetch_scan_status(args)) {
+        Ok(status) => println!("{}", serde_json::to_string_pretty(&status)?),
+        Err(e) => println!("API unavailable: {}", e),
+    }
+    Ok(())
+}
+
+async fn fetch_scan_status(args: &Args) -> Result<serde_json::Value> {
+    // A wildcard bind address is not connectable; talk to loopback instead.
+    let host = match args.api_host.as_str() {
+        "0.0.0.0" => "127.0.0.1",
+        "::" | "[::]" => "[::1]",
+        host => host,
+    };
+    let url = format!("http://{}:{}/api/v1/scan/status", host, args.api_port);
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(3))
+        .build()?;
+    let status = client
+        .get(&url)
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach {}", url))?
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await
+        .context("Failed to parse scan status response")?;
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_pid_file_roundtrip_and_cleanup() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+
+        std::fs::write(&path, format!("{}\n", std::process::id())).unwrap();
+        assert_eq!(read_pid_file(&path).unwrap(), Some(std::process::id()));
+
+        remove_pid_file(&path);
+        assert_eq!(read_pid_file(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_remove_pid_file_keeps_foreign_pid() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+
+        std::fs::write(&path, "1\n").unwrap();
+        remove_pid_file(&path);
+        assert_eq!(read_pid_file(&path).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_invalid_pid_file_is_an_error() {
+        let file = NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "not-a-pid").unwrap();
+        assert!(read_pid_file(file.path().to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! `--storage-engine mmap`: port bitmaps kept in memory-mapped files instead
+//! of `port_bitmaps` blobs. Each (round, port) is one sparse file of one bit
+//! per IPv4 address, `<dir>/r<round>/p<port>.bitmap`, laid out like the
+//! segments of [`PortBitmap`]. Updates flip bits in place, so a batch no
+//! longer reads, deserializes and rewrites a blob of up to 512 MiB. The
+//! files are not part of the SQLite transaction: a crash between the two
+//! can leave a few bits and `open_count` out of step until the next round.
+
+use crate::model::PortBitmap;
+use anyhow::{Context, Result};
+use lru::LruCache;
+use memmap2::{Mmap, MmapMut};
+use std::fs::{self, OpenOptions};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// One bit for each of the 2^32 IPv4 addresses.
+const FILE_BYTES: u64 = 1 << 29;
+/// Mapped files kept open; each maps 512 MiB of address space, of which
+/// only the touched pages take memory.
+const OPEN_MAPS: usize = 64;
+
+pub struct MmapBitmapStore {
+    dir: PathBuf,
+    maps: Mutex<LruCache<(i64, u16), MmapMut>>,
+}
+
+impl MmapBitmapStore {
+    /// Keep bitmaps under `dir`, creating it if needed.
+    pub fn open(dir: &str) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("creating bitmap directory {}", dir))?;
+        Ok(Self {
+            dir: PathBuf::from(dir),
+            maps: Mutex::new(LruCache::new(
+                NonZeroUsize::new(OPEN_MAPS).expect("non-zero map cache"),
+            )),
+        })
+    }
+
+    fn round_dir(&self, round: i64) -> PathBuf {
+        self.dir.join(format!("r{}", round))
+    }
+
+    fn path(&self, round: i64, port: u16) -> PathBuf {
+        self.round_dir(round).join(format!("p{}.bitmap", port))
+    }
+
+    /// Set or clear bits by IPv4 index; returns how many open bits the
+    /// changes added (negative when more were cleared).
+    pub fn apply(&self, round: i64, port: u16, bits: &[(u32, bool)]) -> Result<i64> {
+        let mut maps = self.maps.lock().unwrap();
+        let map = self.map_mut(&mut maps, round, port)?;
+        let mut delta = 0i64;
+        for &(index, open) in bits {
+            let byte = &mut map[(index >> 3) as usize];
+            let mask = 1u8 << (index & 7);
+            match (*byte & mask != 0, open) {
+                (false, true) => {
+                    *byte |= mask;
+                    delta += 1;
+                }
+                (true, false) => {
+                    *byte &= !mask;
+                    delta -= 1;
+                }
+                _ => {}
+            }
+        }
+        // Let the kernel write the pages back; a later read maps the same
+        // pages, so nothing waits on the disk here.
+        map.flush_async()?;
+        Ok(delta)
+    }
+
+    /// Set every bit of `bitmap`, e.g. one moved out of a `port_bitmaps`
+    /// blob.
+    pub fn import(&self, round: i64, port: u16, bitmap: &PortBitmap) -> Result<()> {
+        let mut maps = self.maps.lock().unwrap();
+        let map = self.map_mut(&mut maps, round, port)?;
+        bitmap.or_into_flat(map);
+        map.flush_async()?;
+        Ok(())
+    }
+
+    /// Start the bitmap over with no bits set.
+    pub fn reset(&self, round: i64, port: u16) -> Result<()> {
+        let mut maps = self.maps.lock().unwrap();
+        maps.pop(&(round, port));
+        match fs::remove_file(self.path(round, port)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// The bitmap of `port` in `round`; empty when it has no file.
+    pub fn load(&self, round: i64, port: u16) -> Result<PortBitmap> {
+        let maps = self.maps.lock().unwrap();
+        if let Some(map) = maps.peek(&(round, port)) {
+            return Ok(PortBitmap::from_flat(map));
+        }
+        let path = self.path(round, port);
+        let file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(PortBitmap::new()),
+            Err(e) => return Err(e.into()),
+        };
+        // SAFETY: the files belong to this store and are only written
+        // through its maps, under the lock held here.
+        let map =
+            unsafe { Mmap::map(&file) }.with_context(|| format!("mapping {}", path.display()))?;
+        Ok(PortBitmap::from_flat(&map))
+    }
+
+    /// Delete the bitmaps of every round before `cutoff`.
+    pub fn remove_rounds_before(&self, cutoff: i64) -> Result<usize> {
+        let mut maps = self.maps.lock().unwrap();
+        let stale: Vec<(i64, u16)> = maps
+            .iter()
+            .map(|(key, _)| *key)
+            .filter(|(round, _)| *round < cutoff)
+            .collect();
+        for key in stale {
+            maps.pop(&key);
+        }
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let round = name
+                .to_str()
+                .and_then(|name| name.strip_prefix('r'))
+                .and_then(|round| round.parse::<i64>().ok());
+            if round.is_some_and(|round| round < cutoff) {
+                fs::remove_dir_all(entry.path())?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn map_mut<'a>(
+        &self,
+        maps: &'a mut LruCache<(i64, u16), MmapMut>,
+        round: i64,
+        port: u16,
+    ) -> Result<&'a mut MmapMut> {
+        if !maps.contains(&(round, port)) {
+            let map = map_file(&self.path(round, port))?;
+            maps.put((round, port), map);
+        }
+        Ok(maps.get_mut(&(round, port)).expect("map just inserted"))
+    }
+}
+
+/// Map `path` for writing, creating it as a sparse file of `FILE_BYTES`.
+fn map_file(path: &Path) -> Result<MmapMut> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    if file.metadata()?.len() != FILE_BYTES {
+        file.set_len(FILE_BYTES)?;
+    }
+    // SAFETY: the file is private to this store and every map of it is
+    // held in the store's cache, so no other mapping resizes or writes it
+    // while this one is alive.
+    let map = unsafe { MmapMut::map_mut(&file) }
+        .with_context(|| format!("mapping {}", path.display()))?;
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits_survive_a_reopen_and_old_rounds_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().to_str().unwrap();
+        let store = MmapBitmapStore::open(dir).unwrap();
+        let high = u32::from(std::net::Ipv4Addr::new(203, 0, 113, 7));
+
+        assert_eq!(
+            store
+                .apply(1, 80, &[(5, true), (high, true), (5, true)])
+                .unwrap(),
+            2
+        );
+        assert_eq!(store.apply(1, 80, &[(5, false), (6, false)]).unwrap(), -1);
+        store.apply(2, 80, &[(9, true)]).unwrap();
+
+        let reopened = MmapBitmapStore::open(dir).unwrap();
+        let bitmap = reopened.load(1, 80).unwrap();
+        assert!(bitmap.get(high) && !bitmap.get(5));
+        assert_eq!(bitmap.count_ones(), 1);
+        assert_eq!(reopened.load(1, 443).unwrap().count_ones(), 0);
+
+        let mut imported = PortBitmap::new();
+        imported.set(42, true);
+        reopened.import(1, 80, &imported).unwrap();
+        assert_eq!(reopened.load(1, 80).unwrap().count_ones(), 2);
+        reopened.reset(1, 80).unwrap();
+        assert_eq!(reopened.load(1, 80).unwrap().count_ones(), 0);
+
+        assert_eq!(reopened.remove_rounds_before(2).unwrap(), 1);
+        assert!(reopened.load(2, 80).unwrap().get(9));
+        assert!(!Path::new(dir).join("r1").exists());
+    }
+}
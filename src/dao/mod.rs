@@ -1,3 +1,12 @@
+mod bitmap_store;
 mod sqlite_db;
 
-pub use sqlite_db::{PortChange, SqliteDB};
+pub use bitmap_store::MmapBitmapStore;
+
+pub use sqlite_db::{
+    AuditEntry, ClusterLease, ClusterProgress, Coverage, DatabaseStats, HostnameHit, ImportSummary,
+    ImportedResult, IndexStats, MergeSummary, Namespace, PortChange, PortDelta, PortHistoryRun,
+    PortLifetime, PortStatus, PrefixCoverage, ReputationFilter, RoundDiff, RoundMetrics,
+    ScanResultDetail, ScanSession, ScanTemplate, ScriptFinding, SearchHit, SqliteDB, TableStats,
+    WalCheckpoint, MAX_BITMAP_SHARDS,
+};
@@ -1,22 +1,58 @@
+use super::MmapBitmapStore;
 use crate::model::{
-    index_to_ipv4, ipv4_to_index, IpGeoInfo, IpServiceSummary, PortBitmap, ServiceInfo,
+    index_to_ipv4, ipv4_to_index, CveMatch, IpGeoInfo, IpReputation, IpServiceSummary, PortBitmap,
+    ServiceInfo, VirtualHostInfo, RISKY_SCORE,
 };
 use anyhow::Result;
 use chrono::Utc;
-use rusqlite::{params, Connection, OptionalExtension};
-use std::collections::HashMap;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use utoipa::ToSchema;
 
+/// A host from [`SqliteDB::get_top_ips`]: address, active open port count
+/// and, when requested, its open ports.
+pub type TopIp = (String, usize, Option<Vec<u16>>);
+
 #[derive(Clone)]
 pub struct SqliteDB {
     conn: Arc<Mutex<Connection>>,
+    /// API scan credited with the open ports this handle records.
+    scan_id: Option<Arc<str>>,
+    /// SQLCipher key the database was opened with; merged databases are
+    /// opened with it too.
+    key: Option<Arc<str>>,
+    /// `--port-history`: extend `port_history` runs for the open ports this
+    /// handle records.
+    port_history: bool,
+    /// `--storage-engine mmap`: port bitmaps live in these files and their
+    /// `port_bitmaps` rows keep an empty blob and the open count.
+    bitmaps: Option<Arc<MmapBitmapStore>>,
+    /// `--db-shards`: number of `<db>-shardN` files the port bitmaps are
+    /// spread over by port; 1 keeps them in the main file.
+    shards: usize,
 }
 
+/// Most `--db-shards`. Each shard is an attached database and SQLite
+/// attaches at most 10; merge and rekey attach one more.
+pub const MAX_BITMAP_SHARDS: usize = 8;
+
 impl SqliteDB {
     pub fn new(db_path: &str) -> Result<Self> {
+        Self::with_key(db_path, None)
+    }
+
+    /// Open (or create) the database at `db_path`, encrypted with `key`
+    /// when one is given. Needs a build with the `sqlcipher` feature; a
+    /// wrong key, or a key for an unencrypted file, fails here rather than
+    /// on the first query.
+    pub fn with_key(db_path: &str, key: Option<&str>) -> Result<Self> {
         let conn = Connection::open(db_path)?;
+        if let Some(key) = key {
+            apply_key(&conn, key)?;
+        }
 
         // Port bitmaps table
         conn.execute(
@@ -57,6 +93,8 @@ impl SqliteDB {
                 scan_round INTEGER NOT NULL,
                 first_seen TEXT NOT NULL,
                 last_seen TEXT NOT NULL,
+                closed_at TEXT,
+                scan_id TEXT,
                 UNIQUE(ip_address, port)
             )",
             [],
@@ -93,12 +131,56 @@ impl SqliteDB {
                 isp TEXT,
                 asn TEXT,
                 reverse_dns TEXT,
+                abuse_email TEXT,
                 source TEXT NOT NULL,
                 updated_at TEXT NOT NULL
             )",
             [],
         )?;
 
+        // Per-round scanner counters, written once the round finishes
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS round_metrics (
+                scan_round INTEGER PRIMARY KEY,
+                scanned INTEGER NOT NULL DEFAULT 0,
+                open INTEGER NOT NULL DEFAULT 0,
+                errors INTEGER NOT NULL DEFAULT 0,
+                retries INTEGER NOT NULL DEFAULT 0,
+                duration_secs REAL NOT NULL DEFAULT 0,
+                avg_rate REAL NOT NULL DEFAULT 0,
+                finished_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Tags and findings emitted by `--script` hooks
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS script_findings (
+                ip_address TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                value TEXT NOT NULL,
+                scan_round INTEGER NOT NULL,
+                first_seen TEXT NOT NULL,
+                last_seen TEXT NOT NULL,
+                PRIMARY KEY (ip_address, port, kind, value)
+            )",
+            [],
+        )?;
+
+        // Greetings read on connect-scan sockets (`--grab-banner-ms`), used to
+        // seed service probing instead of reconnecting
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS port_banners (
+                ip_address TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                banner TEXT NOT NULL,
+                grabbed_at TEXT NOT NULL,
+                PRIMARY KEY (ip_address, port)
+            )",
+            [],
+        )?;
+
         // Track failed/empty service probes so the background worker does not
         // hammer the same unresponsive host every polling interval.
         conn.execute(
@@ -147,6 +229,204 @@ impl SqliteDB {
             [],
         )?;
 
+        // Per-hostname HTTP/TLS results for name-based virtual hosts
+        // (`--sni-hosts`, `--sni-from-rdns`)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS service_vhosts (
+                ip_address TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                hostname TEXT NOT NULL,
+                http_status INTEGER,
+                http_title TEXT,
+                http_server TEXT,
+                tls_subject TEXT,
+                tls_issuer TEXT,
+                tls_version TEXT,
+                detected_at TEXT NOT NULL,
+                PRIMARY KEY (ip_address, port, hostname)
+            )",
+            [],
+        )?;
+
+        // Addresses each hostname target resolved to (`--target example.com`),
+        // so results can be filtered by the name they were scanned under
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS target_hostnames (
+                hostname TEXT NOT NULL,
+                ip_address TEXT NOT NULL,
+                scan_round INTEGER NOT NULL,
+                resolved_at TEXT NOT NULL,
+                PRIMARY KEY (hostname, ip_address)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_target_hostnames_ip ON target_hostnames(ip_address)",
+            [],
+        )?;
+
+        // Candidate CVEs for each open port, matched from the detected
+        // product and version against a local NVD dataset (`--cve-db`)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS port_cves (
+                ip_address TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                cve_id TEXT NOT NULL,
+                product TEXT NOT NULL,
+                version TEXT NOT NULL,
+                cvss REAL,
+                matched_at TEXT NOT NULL,
+                PRIMARY KEY (ip_address, port, cve_id)
+            )",
+            [],
+        )?;
+
+        // What each reputation source (`--reputation-providers`) reported
+        // for a host; rows without data mark the host as checked
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS ip_reputation (
+                ip_address TEXT NOT NULL,
+                source TEXT NOT NULL,
+                score INTEGER,
+                classification TEXT,
+                scanner INTEGER NOT NULL DEFAULT 0,
+                reports INTEGER,
+                checked_at TEXT NOT NULL,
+                PRIMARY KEY (ip_address, source)
+            )",
+            [],
+        )?;
+
+        // IPv4 slices handed out to `--worker` processes by `--coordinator`
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS cluster_leases (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                scan_round INTEGER NOT NULL,
+                start_index INTEGER NOT NULL,
+                end_index INTEGER NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending',
+                worker_id TEXT,
+                expires_at INTEGER,
+                scanned INTEGER NOT NULL DEFAULT 0,
+                open INTEGER NOT NULL DEFAULT 0,
+                updated_at TEXT NOT NULL,
+                UNIQUE(scan_round, start_index)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_cluster_leases_status ON cluster_leases(scan_round, status)",
+            [],
+        )?;
+
+        // Name, description and owner of scans started through the API
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS scan_sessions (
+                scan_id TEXT PRIMARY KEY,
+                name TEXT,
+                description TEXT,
+                owner TEXT,
+                start_round INTEGER NOT NULL,
+                end_round INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                finished_at TEXT,
+                last_ip TEXT,
+                principal TEXT
+            )",
+            [],
+        )?;
+
+        // Reusable `/scan/start` parameters
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS scan_templates (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                description TEXT,
+                params TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Runs of consecutive rounds each open port was seen in
+        // (`--port-history`); a port that disappears and comes back starts
+        // a new run
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS port_history (
+                ip_address TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                start_round INTEGER NOT NULL,
+                end_round INTEGER NOT NULL,
+                first_seen TEXT NOT NULL,
+                last_seen TEXT NOT NULL,
+                PRIMARY KEY (ip_address, port, start_round)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_port_history_port ON port_history(port)",
+            [],
+        )?;
+
+        // State-changing API calls: who made them, with what, and the result
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at TEXT NOT NULL,
+                method TEXT NOT NULL,
+                path TEXT NOT NULL,
+                principal TEXT,
+                client TEXT,
+                params TEXT,
+                status INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // IPv4 addresses probed in each round, as one bitmap per /16 block
+        // with its count of set bits (`/stats/coverage`)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS scan_coverage (
+                scan_round INTEGER NOT NULL,
+                block INTEGER NOT NULL,
+                hosts BLOB NOT NULL,
+                scanned INTEGER NOT NULL,
+                PRIMARY KEY (scan_round, block)
+            )",
+            [],
+        )?;
+
+        // `[quotas]` usage per caller; only the current period of each kind
+        // is kept
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS api_quota_usage (
+                principal TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                period TEXT NOT NULL,
+                used INTEGER NOT NULL,
+                PRIMARY KEY (principal, kind, period)
+            )",
+            [],
+        )?;
+
+        // API namespaces; each keeps its results in its own database under
+        // `--namespace-dir`, and `principals` (a JSON array) are the callers
+        // confined to it
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS namespaces (
+                name TEXT PRIMARY KEY,
+                principals TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
         // Migrations for existing databases
         let migrations = [
             "ALTER TABLE ip_details ADD COLUMN reverse_dns TEXT",
@@ -158,11 +438,44 @@ impl SqliteDB {
             "ALTER TABLE service_info ADD COLUMN http_security_headers TEXT",
             "ALTER TABLE service_info ADD COLUMN rtt_ms REAL",
             "ALTER TABLE service_info ADD COLUMN os_guess TEXT",
+            "ALTER TABLE ip_details ADD COLUMN abuse_email TEXT",
+            "ALTER TABLE open_ports_detail ADD COLUMN closed_at TEXT",
+            "ALTER TABLE open_ports_detail ADD COLUMN scan_id TEXT",
+            "ALTER TABLE scan_sessions ADD COLUMN last_ip TEXT",
+            "ALTER TABLE scan_sessions ADD COLUMN principal TEXT",
         ];
         for m in &migrations {
             let _ = conn.execute(m, []);
         }
 
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_open_ports_scan_id ON open_ports_detail(scan_id)",
+            [],
+        )?;
+
+        // Reverse DNS names are stored lowercase without the trailing dot so
+        // hostname filters and `/hostnames` can match them through this
+        // index; names saved before it existed are folded when it is created.
+        let rdns_indexed: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master
+                            WHERE type = 'index' AND name = 'idx_ip_details_reverse_dns')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !rdns_indexed {
+            conn.execute(
+                "UPDATE ip_details SET reverse_dns = lower(rtrim(reverse_dns, '.'))
+                 WHERE reverse_dns IS NOT NULL",
+                [],
+            )?;
+            conn.execute(
+                "CREATE INDEX idx_ip_details_reverse_dns ON ip_details(reverse_dns)",
+                [],
+            )?;
+        }
+
+        create_search_index(&conn)?;
+
         // Optimization: Set WAL mode for better concurrency
         conn.pragma_update(None, "journal_mode", "WAL")?;
         conn.pragma_update(None, "synchronous", "NORMAL")?;
@@ -182,23 +495,180 @@ impl SqliteDB {
         conn.pragma_update(None, "journal_size_limit", 64 * 1024 * 1024i64)?;
         let _ = conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);");
 
+        let shards = recorded_shards(&conn, "main")?;
+        if shards > 1 {
+            attach_shards(&conn, db_path, shards, key, true)?;
+        }
+
+        Ok(SqliteDB {
+            conn: Arc::new(Mutex::new(conn)),
+            scan_id: None,
+            key: key.map(Arc::from),
+            port_history: false,
+            bitmaps: None,
+            shards,
+        })
+    }
+
+    /// Open an existing database for reading only, for `--attach-db`: the
+    /// schema is neither created nor migrated and every write fails. A file
+    /// last written by an older release may lack columns some queries read.
+    pub fn open_read_only(db_path: &str, key: Option<&str>) -> Result<Self> {
+        if !std::path::Path::new(db_path).exists() {
+            return Err(anyhow::anyhow!("Database {} does not exist", db_path));
+        }
+        let conn = Connection::open_with_flags(
+            db_path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        if let Some(key) = key {
+            apply_key(&conn, key)?;
+        }
+        // The scanner that owns the file may be checkpointing it.
+        conn.busy_timeout(Duration::from_secs(5))?;
+        let has_results: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master
+                            WHERE type = 'table' AND name = 'open_ports_detail')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_results {
+            return Err(anyhow::anyhow!("{} is not an ip-scan database", db_path));
+        }
+        // Attached shards inherit the read-only flags.
+        let shards = recorded_shards(&conn, "main")?;
+        if shards > 1 {
+            attach_shards(&conn, db_path, shards, key, false)?;
+        }
+
         Ok(SqliteDB {
             conn: Arc::new(Mutex::new(conn)),
+            scan_id: None,
+            key: key.map(Arc::from),
+            port_history: false,
+            bitmaps: None,
+            shards,
         })
     }
 
+    /// A handle on the same database that credits the open ports it records
+    /// to the API scan `scan_id`.
+    pub fn with_scan_id(&self, scan_id: &str) -> SqliteDB {
+        SqliteDB {
+            conn: self.conn.clone(),
+            scan_id: Some(scan_id.into()),
+            key: self.key.clone(),
+            port_history: self.port_history,
+            bitmaps: self.bitmaps.clone(),
+            shards: self.shards,
+        }
+    }
+
+    /// This handle, recording the rounds each open port is seen in when
+    /// `enabled`; handles derived from it inherit the setting.
+    pub fn with_port_history(mut self, enabled: bool) -> SqliteDB {
+        self.port_history = enabled;
+        self
+    }
+
+    /// This database with its port bitmaps spread over `shards` files by
+    /// port, `<db>-shard0` and up, each with its own WAL. The first call
+    /// moves the existing bitmaps into the shards and records the count,
+    /// so later opens attach them without being asked; reads see all
+    /// shards through one `port_bitmaps` view. The count cannot change
+    /// once set.
+    pub fn with_bitmap_shards(mut self, shards: usize) -> Result<SqliteDB> {
+        if shards <= 1 || shards == self.shards {
+            return Ok(self);
+        }
+        if self.shards > 1 {
+            return Err(anyhow::anyhow!(
+                "Database keeps its bitmaps in {} shard files; it cannot be resharded to {}",
+                self.shards,
+                shards
+            ));
+        }
+        if shards > MAX_BITMAP_SHARDS {
+            return Err(anyhow::anyhow!(
+                "At most {} bitmap shards are supported",
+                MAX_BITMAP_SHARDS
+            ));
+        }
+        {
+            let conn = self.conn.lock().unwrap();
+            let path = database_path(&conn)?
+                .ok_or_else(|| anyhow::anyhow!("Sharding needs a database file"))?;
+            attach_shards(&conn, &path, shards, self.key.as_deref(), true)?;
+            let transaction = conn.unchecked_transaction()?;
+            for shard in 0..shards {
+                transaction.execute(
+                    &format!(
+                        "INSERT INTO shard{}.port_bitmaps SELECT * FROM main.port_bitmaps
+                         WHERE port % ?1 = ?2",
+                        shard
+                    ),
+                    params![shards as i64, shard as i64],
+                )?;
+            }
+            transaction.execute("DELETE FROM main.port_bitmaps", [])?;
+            transaction.execute(
+                "INSERT INTO scan_metadata (key, value, updated_at) VALUES ('bitmap_shards', ?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value = ?1, updated_at = ?2",
+                params![shards.to_string(), Utc::now().to_rfc3339()],
+            )?;
+            transaction.commit()?;
+        }
+        self.shards = shards;
+        Ok(self)
+    }
+
+    /// Schema holding the bitmaps of `port`.
+    fn bitmap_schema(&self, port: u16) -> String {
+        bitmap_schema(self.shards, port)
+    }
+
+    /// This handle, writing port bitmaps to `store` instead of blobs. Rows
+    /// still holding a blob move into the store the next time they change.
+    pub fn with_bitmap_store(mut self, store: MmapBitmapStore) -> SqliteDB {
+        self.bitmaps = Some(Arc::new(store));
+        self
+    }
+
     /// Trigger a passive WAL checkpoint. Returns true when the WAL was fully
     /// checkpointed. Use this between rounds to keep the WAL file bounded
     /// even when the autocheckpoint threshold is not hit.
     pub fn checkpoint_wal(&self) -> Result<bool> {
+        // PASSIVE never blocks readers. We treat a non-zero checkpointed
+        // count as a successful shrink of the WAL tail.
+        Ok(self.checkpoint_wal_frames(false)?.checkpointed_frames > 0)
+    }
+
+    /// Run a WAL checkpoint: PASSIVE copies back what it can without
+    /// waiting; with `truncate` it waits (up to the busy timeout) for other
+    /// connections and then resets the WAL file to zero bytes. Writers of
+    /// this handle wait on the connection lock meanwhile, so the checkpoint
+    /// always falls between two of their batches.
+    pub fn checkpoint_wal_frames(&self, truncate: bool) -> Result<WalCheckpoint> {
+        let conn = self.conn.lock().unwrap();
+        let mode = if truncate { "TRUNCATE" } else { "PASSIVE" };
+        let (busy, log_frames, checkpointed_frames): (i64, i64, i64) =
+            conn.query_row(&format!("PRAGMA wal_checkpoint({})", mode), [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?;
+        Ok(WalCheckpoint {
+            busy: busy != 0,
+            log_frames,
+            checkpointed_frames,
+        })
+    }
+
+    /// Size of the `-wal` file; `None` for in-memory databases or when
+    /// there is no WAL file.
+    pub fn wal_bytes(&self) -> Result<Option<u64>> {
         let conn = self.conn.lock().unwrap();
-        // PRAGMA wal_checkpoint(PASSIVE) returns (busy, log_pages, checkpointed_pages)
-        // and never blocks readers. We treat a non-zero checkpointed count as a
-        // successful shrink of the WAL tail.
-        let mut stmt = conn.prepare("PRAGMA wal_checkpoint(PASSIVE)")?;
-        let row: (i64, i64, i64) =
-            stmt.query_row([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
-        Ok(row.2 > 0)
+        Ok(database_path(&conn)?.and_then(|path| file_size(&format!("{}-wal", path))))
     }
 
     pub fn cleanup_old_rounds(&self, keep_rounds: i64) -> Result<u64> {
@@ -216,9 +686,27 @@ impl SqliteDB {
             // repeatedly delete the rounds that were meant to be retained.
             let cutoff = max_round.saturating_sub(keep_rounds - 1);
             conn.execute(
-                "DELETE FROM port_bitmaps WHERE scan_round < ?1",
+                "DELETE FROM cluster_leases WHERE scan_round < ?1",
                 params![cutoff],
-            )?
+            )?;
+            conn.execute(
+                "DELETE FROM scan_coverage WHERE scan_round < ?1",
+                params![cutoff],
+            )?;
+            let mut deleted = 0;
+            for shard in 0..self.shards {
+                deleted += conn.execute(
+                    &format!(
+                        "DELETE FROM {}.port_bitmaps WHERE scan_round < ?1",
+                        bitmap_schema(self.shards, shard as u16)
+                    ),
+                    params![cutoff],
+                )?;
+            }
+            if let Some(store) = &self.bitmaps {
+                store.remove_rounds_before(cutoff)?;
+            }
+            deleted
         } else {
             0
         };
@@ -229,6 +717,32 @@ impl SqliteDB {
         Ok(deleted as u64)
     }
 
+    /// Have the geo worker look up again every IP whose geo data was saved
+    /// before `cutoff` (RFC 3339). The old data stays until a lookup
+    /// succeeds. Returns how many IPs are due.
+    pub fn refresh_geo_before(&self, cutoff: &str) -> Result<usize> {
+        self.save_metadata("geo_refresh_before", cutoff)?;
+        let conn = self.conn.lock().unwrap();
+        let due: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM ip_details WHERE updated_at < ?1",
+            [cutoff],
+            |row| row.get(0),
+        )?;
+        Ok(due as usize)
+    }
+
+    /// Rewrite the database file to return free pages to the file system,
+    /// returning how many bytes those pages held. Takes as long as copying
+    /// the whole database, and every other user of this handle waits
+    /// meanwhile.
+    pub fn vacuum(&self) -> Result<u64> {
+        let conn = self.conn.lock().unwrap();
+        let free_pages: i64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+        let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        conn.execute_batch("VACUUM")?;
+        Ok((free_pages * page_size) as u64)
+    }
+
     /// Persist multiple GeoIP records in one SQLite transaction.
     pub fn save_ip_geo_info_batch(&self, infos: &[IpGeoInfo]) -> Result<()> {
         if infos.is_empty() {
@@ -238,7 +752,7 @@ impl SqliteDB {
         let tx = conn.transaction()?;
         {
             let mut stmt = tx.prepare(
-                "INSERT INTO ip_details (ip_address, country, region, city, isp, asn, reverse_dns, source, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9) ON CONFLICT(ip_address) DO UPDATE SET country=?2, region=?3, city=?4, isp=?5, asn=?6, reverse_dns=?7, source=?8, updated_at=?9"
+                "INSERT INTO ip_details (ip_address, country, region, city, isp, asn, reverse_dns, abuse_email, source, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10) ON CONFLICT(ip_address) DO UPDATE SET country=?2, region=?3, city=?4, isp=?5, asn=?6, reverse_dns=?7, abuse_email=?8, source=?9, updated_at=?10"
             )?;
             let timestamp = Utc::now().to_rfc3339();
             for info in infos {
@@ -249,7 +763,8 @@ impl SqliteDB {
                     info.city,
                     info.isp,
                     info.asn,
-                    info.reverse_dns,
+                    info.reverse_dns.as_deref().map(normalize_hostname),
+                    info.abuse_email,
                     info.source,
                     timestamp
                 ])?;
@@ -264,7 +779,7 @@ impl SqliteDB {
         let conn = self.conn.lock().unwrap();
 
         let result = conn.query_row(
-            "SELECT ip_address, country, region, city, isp, asn, reverse_dns, source FROM ip_details WHERE ip_address = ?1",
+            "SELECT ip_address, country, region, city, isp, asn, reverse_dns, abuse_email, source FROM ip_details WHERE ip_address = ?1",
             [ip],
             |row| {
                 Ok(IpGeoInfo {
@@ -275,7 +790,8 @@ impl SqliteDB {
                     isp: row.get(4)?,
                     asn: row.get(5)?,
                     reverse_dns: row.get(6)?,
-                    source: row.get(7)?,
+                    abuse_email: row.get(7)?,
+                    source: row.get(8)?,
                 })
             },
         ).optional()?;
@@ -283,21 +799,73 @@ impl SqliteDB {
         Ok(result)
     }
 
-    pub fn get_ips_missing_geo(&self, limit: usize) -> Result<Vec<String>> {
+    /// Open-port IPs without geo data, or with data older than the cutoff
+    /// set by [`SqliteDB::refresh_geo_before`], in `ip_address` order
+    /// starting after `after` (pass "" to start from the beginning). The
+    /// ordering lets the enrichment worker page through the backlog without
+    /// re-reading IPs that are still being looked up or whose lookups failed.
+    pub fn get_ips_missing_geo(&self, after: &str, limit: usize) -> Result<Vec<String>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT DISTINCT ip_address FROM open_ports_detail 
-             WHERE ip_address NOT IN (SELECT ip_address FROM ip_details)
-             LIMIT ?1",
+             WHERE ip_address > ?1
+               AND ip_address NOT IN (
+                   SELECT ip_address FROM ip_details
+                   WHERE updated_at >= COALESCE(
+                       (SELECT value FROM scan_metadata WHERE key = 'geo_refresh_before'), ''))
+             ORDER BY ip_address
+             LIMIT ?2",
         )?;
 
         let ips = stmt
-            .query_map([limit], |row| row.get(0))?
+            .query_map(params![after, limit], |row| row.get(0))?
             .collect::<Result<Vec<String>, _>>()?;
 
         Ok(ips)
     }
 
+    /// Up to `limit` distinct IPs with open ports, on `port` if given; with
+    /// `missing_only`, only those `get_ips_missing_geo` would return.
+    pub fn get_ips_for_geo(
+        &self,
+        port: Option<u16>,
+        missing_only: bool,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT ip_address FROM open_ports_detail
+             WHERE (?1 IS NULL OR port = ?1)
+               AND (NOT ?2 OR ip_address NOT IN (
+                   SELECT ip_address FROM ip_details
+                   WHERE updated_at >= COALESCE(
+                       (SELECT value FROM scan_metadata WHERE key = 'geo_refresh_before'), '')))
+             ORDER BY ip_address
+             LIMIT ?3",
+        )?;
+        let ips = stmt
+            .query_map(params![port, missing_only, limit], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(ips)
+    }
+
+    /// Distinct IPs with open ports, and how many of them
+    /// `get_ips_missing_geo` would still hand to the geo worker.
+    pub fn count_geo_backlog(&self) -> Result<(usize, usize)> {
+        let conn = self.conn.lock().unwrap();
+        let (ips, missing): (i64, i64) = conn.query_row(
+            "SELECT COUNT(*),
+                    COUNT(*) FILTER (WHERE ip_address NOT IN (
+                        SELECT ip_address FROM ip_details
+                        WHERE updated_at >= COALESCE(
+                            (SELECT value FROM scan_metadata WHERE key = 'geo_refresh_before'), '')))
+             FROM (SELECT DISTINCT ip_address FROM open_ports_detail)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok((ips as usize, missing as usize))
+    }
+
     #[allow(dead_code)]
     pub fn set_port_status(
         &self,
@@ -308,44 +876,31 @@ impl SqliteDB {
     ) -> Result<()> {
         let ip_index = ipv4_to_index(ip)?;
         let conn = self.conn.lock().unwrap();
-
-        // Get or create bitmap for this port
-        let mut bitmap = self.get_port_bitmap_internal(&conn, port, "IPv4", scan_round)?;
-
-        // Update bitmap
-        bitmap.set(ip_index, is_open);
-
-        // Save back to database
-        let blob = bitmap.to_blob()?;
-        let open_count = bitmap.count_ones() as i64;
         let timestamp = Utc::now().to_rfc3339();
-
-        conn.execute(
-            "INSERT INTO port_bitmaps (port, ip_type, scan_round, bitmap, open_count, last_updated)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
-             ON CONFLICT(port, ip_type, scan_round)
-             DO UPDATE SET bitmap = ?4, open_count = ?5, last_updated = ?6",
-            params![port, "IPv4", scan_round, blob, open_count, timestamp],
-        )?;
+        self.write_bits(&conn, port, scan_round, &[(ip_index, is_open)], &timestamp)?;
 
         // If port is open, also store in detail table
         if is_open {
             let now = Utc::now().to_rfc3339();
             conn.execute(
-                "INSERT INTO open_ports_detail (ip_address, ip_type, port, scan_round, first_seen, last_seen)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                "INSERT INTO open_ports_detail (ip_address, ip_type, port, scan_round, first_seen, last_seen, scan_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
                  ON CONFLICT(ip_address, port)
-                 DO UPDATE SET scan_round = ?4, last_seen = ?6",
-                params![ip, "IPv4", port, scan_round, now.clone(), now],
+                 DO UPDATE SET scan_round = ?4, last_seen = ?6, closed_at = NULL, scan_id = ?7",
+                params![ip, "IPv4", port, scan_round, now.clone(), now, self.scan_id.as_deref()],
             )?;
         }
 
         Ok(())
     }
 
+    /// Record a batch of probe results. IPv4 results set port bitmap bits;
+    /// the bitmaps cannot index IPv6, so only its open ports are stored, as
+    /// detail rows. IPs are formatted only for the open ports written to
+    /// the detail table.
     pub fn bulk_update_port_status(
         &self,
-        updates: Vec<(String, u16, bool)>,
+        updates: Vec<(IpAddr, u16, bool)>,
         scan_round: i64,
     ) -> Result<()> {
         if updates.is_empty() {
@@ -354,86 +909,313 @@ impl SqliteDB {
 
         let mut conn = self.conn.lock().unwrap();
         let transaction = conn.transaction()?;
+        // One timestamp for the whole batch: these rows were all seen in the
+        // same flush.
+        let timestamp = Utc::now().to_rfc3339();
 
         // Group by port to minimize bitmap loads/saves
-        let mut updates_by_port: HashMap<u16, Vec<(u32, bool, String)>> = HashMap::new();
+        let mut updates_by_port: HashMap<u16, Vec<(u32, bool)>> = HashMap::new();
+        // Every result, open or not, marks its address scanned.
+        let mut scanned_by_block: BTreeMap<u32, Vec<u16>> = BTreeMap::new();
+        let mut open_v6_by_port: BTreeMap<u16, Vec<String>> = BTreeMap::new();
 
         for (ip, port, is_open) in updates {
-            match ipv4_to_index(&ip) {
-                Ok(ip_index) => {
+            match ip {
+                IpAddr::V4(ip) => {
+                    let index = u32::from(ip);
                     updates_by_port
                         .entry(port)
                         .or_default()
-                        .push((ip_index, is_open, ip));
+                        .push((index, is_open));
+                    scanned_by_block
+                        .entry(index >> 16)
+                        .or_default()
+                        .push(index as u16);
+                }
+                IpAddr::V6(ip) if is_open => {
+                    open_v6_by_port
+                        .entry(port)
+                        .or_default()
+                        .push(ip.to_string());
                 }
-                Err(_) => continue, // Skip invalid IPs
+                IpAddr::V6(_) => {}
             }
         }
+        for (block, hosts) in scanned_by_block {
+            mark_coverage(&transaction, scan_round, block, |bits| {
+                for host in hosts {
+                    bits[usize::from(host >> 3)] |= 1 << (host & 7);
+                }
+            })?;
+        }
 
-        for (port, items) in updates_by_port {
+        for (port, bits) in updates_by_port {
             // 1. Update Bitmap
-            let mut bitmap =
-                self.get_port_bitmap_internal(&transaction, port, "IPv4", scan_round)?;
-
-            for (ip_index, is_open, _) in &items {
-                bitmap.set(*ip_index, *is_open);
-            }
-
-            let blob = bitmap.to_blob()?;
-            let open_count = bitmap.count_ones() as i64;
-            let timestamp = Utc::now().to_rfc3339();
-
-            transaction.execute(
-                "INSERT INTO port_bitmaps (port, ip_type, scan_round, bitmap, open_count, last_updated)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
-                 ON CONFLICT(port, ip_type, scan_round)
-                 DO UPDATE SET bitmap = ?4, open_count = ?5, last_updated = ?6",
-                params![port, "IPv4", scan_round, blob, open_count, timestamp],
+            self.write_bits(&transaction, port, scan_round, &bits, &timestamp)?;
+
+            // 2. Update Details (Only for open ports), many rows per statement
+            let open_ips: Vec<String> = bits
+                .iter()
+                .filter(|(_, is_open)| *is_open)
+                .map(|(ip_index, _)| index_to_ipv4(*ip_index))
+                .collect();
+            self.record_open_ports(
+                &transaction,
+                "IPv4",
+                port,
+                scan_round,
+                &timestamp,
+                &open_ips,
+            )?;
+        }
+        for (port, open_ips) in open_v6_by_port {
+            self.record_open_ports(
+                &transaction,
+                "IPv6",
+                port,
+                scan_round,
+                &timestamp,
+                &open_ips,
             )?;
-
-            // 2. Update Details (Only for open ports)
-            // Prepare statement for better performance
-            {
-                let mut stmt = transaction.prepare(
-                    "INSERT INTO open_ports_detail (ip_address, ip_type, port, scan_round, first_seen, last_seen)
-                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)
-                     ON CONFLICT(ip_address, port)
-                     DO UPDATE SET scan_round = ?4, last_seen = ?6"
-                )?;
-
-                for (_, is_open, ip) in &items {
-                    if *is_open {
-                        let now = Utc::now().to_rfc3339();
-                        stmt.execute(params![ip, "IPv4", port, scan_round, now.clone(), now])?;
-                    }
-                }
-            }
         }
 
         transaction.commit()?;
         Ok(())
     }
 
-    fn get_port_bitmap_internal(
+    /// Upsert the detail rows of `ips`, all of family `ip_type` and open on
+    /// `port`, and extend their port history.
+    fn record_open_ports(
         &self,
         conn: &Connection,
-        port: u16,
         ip_type: &str,
+        port: u16,
         scan_round: i64,
-    ) -> Result<PortBitmap> {
-        let result: rusqlite::Result<Vec<u8>> = conn.query_row(
-            "SELECT bitmap FROM port_bitmaps WHERE port = ?1 AND ip_type = ?2 AND scan_round = ?3",
+        timestamp: &str,
+        ips: &[String],
+    ) -> Result<()> {
+        for chunk in ips.chunks(DETAIL_INSERT_ROWS) {
+            upsert_open_details(
+                conn,
+                ip_type,
+                port,
+                scan_round,
+                timestamp,
+                self.scan_id.as_deref(),
+                chunk,
+            )?;
+        }
+
+        // Extend the port's history run, or start a new one
+        if self.port_history {
+            let mut extend = conn.prepare_cached(
+                "UPDATE port_history SET end_round = MAX(end_round, ?3), last_seen = ?4
+                 WHERE ip_address = ?1 AND port = ?2
+                   AND start_round <= ?3 AND end_round >= ?3 - 1",
+            )?;
+            let mut start = conn.prepare_cached(
+                "INSERT OR IGNORE INTO port_history
+                    (ip_address, port, start_round, end_round, first_seen, last_seen)
+                 VALUES (?1, ?2, ?3, ?3, ?4, ?4)",
+            )?;
+            for ip in ips {
+                if extend.execute(params![ip, port, scan_round, timestamp])? == 0 {
+                    start.execute(params![ip, port, scan_round, timestamp])?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Set or clear `bits` in the IPv4 bitmap of `port` for `scan_round`
+    /// and store it with its open count.
+    fn write_bits(
+        &self,
+        conn: &Connection,
+        port: u16,
+        scan_round: i64,
+        bits: &[(u32, bool)],
+        timestamp: &str,
+    ) -> Result<()> {
+        let schema = self.bitmap_schema(port);
+        let Some(store) = &self.bitmaps else {
+            let mut bitmap = self.get_port_bitmap_internal(conn, port, "IPv4", scan_round)?;
+            for &(ip_index, is_open) in bits {
+                bitmap.set(ip_index, is_open);
+            }
+            conn.execute(
+                &format!(
+                    "INSERT INTO {}.port_bitmaps (port, ip_type, scan_round, bitmap, open_count, last_updated)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                     ON CONFLICT(port, ip_type, scan_round)
+                     DO UPDATE SET bitmap = ?4, open_count = ?5, last_updated = ?6",
+                    schema
+                ),
+                params![
+                    port,
+                    "IPv4",
+                    scan_round,
+                    bitmap.to_blob()?,
+                    bitmap.count_ones() as i64,
+                    timestamp
+                ],
+            )?;
+            return Ok(());
+        };
+
+        // Only the blob length is read: an mmap row's blob is empty, and a
+        // blob row's is moved into the store once.
+        let row: Option<(i64, i64)> = conn
+            .query_row(
+                &format!(
+                    "SELECT LENGTH(bitmap), open_count FROM {}.port_bitmaps
+                     WHERE port = ?1 AND ip_type = 'IPv4' AND scan_round = ?2",
+                    schema
+                ),
+                params![port, scan_round],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let open_count = match row {
+            // A file left behind by a rolled back batch must not count.
+            None => {
+                store.reset(scan_round, port)?;
+                store.apply(scan_round, port, bits)?
+            }
+            Some((0, open_count)) => open_count + store.apply(scan_round, port, bits)?,
+            Some(_) => {
+                let bitmap = self.get_port_bitmap_internal(conn, port, "IPv4", scan_round)?;
+                store.reset(scan_round, port)?;
+                store.import(scan_round, port, &bitmap)?;
+                store.apply(scan_round, port, bits)?;
+                store.load(scan_round, port)?.count_ones() as i64
+            }
+        };
+        conn.execute(
+            &format!(
+                "INSERT INTO {}.port_bitmaps (port, ip_type, scan_round, bitmap, open_count, last_updated)
+                 VALUES (?1, 'IPv4', ?2, X'', ?3, ?4)
+                 ON CONFLICT(port, ip_type, scan_round)
+                 DO UPDATE SET bitmap = X'', open_count = ?3, last_updated = ?4",
+                schema
+            ),
+            params![port, scan_round, open_count, timestamp],
+        )?;
+        Ok(())
+    }
+
+    fn get_port_bitmap_internal(
+        &self,
+        conn: &Connection,
+        port: u16,
+        ip_type: &str,
+        scan_round: i64,
+    ) -> Result<PortBitmap> {
+        let result: rusqlite::Result<Vec<u8>> = conn.query_row(
+            &format!(
+                "SELECT bitmap FROM {}.port_bitmaps WHERE port = ?1 AND ip_type = ?2 AND scan_round = ?3",
+                self.bitmap_schema(port)
+            ),
             params![port, ip_type, scan_round],
             |row| row.get(0),
         );
 
         match result {
-            Ok(blob) => PortBitmap::from_blob(&blob),
+            Ok(blob) => self.decode_bitmap(&blob, port, scan_round),
             Err(rusqlite::Error::QueryReturnedNoRows) => Ok(PortBitmap::new()),
             Err(e) => Err(e.into()),
         }
     }
 
+    /// A `port_bitmaps` blob, or for an empty one the bitmap in the mmap
+    /// store.
+    fn decode_bitmap(&self, blob: &[u8], port: u16, scan_round: i64) -> Result<PortBitmap> {
+        if !blob.is_empty() {
+            return PortBitmap::from_blob(blob);
+        }
+        match &self.bitmaps {
+            Some(store) => store.load(scan_round, port),
+            None => Err(anyhow::anyhow!(
+                "Bitmap of port {} in round {} is in the mmap bitmap store; open with --storage-engine mmap",
+                port,
+                scan_round
+            )),
+        }
+    }
+
+    fn load_ipv4_bitmap(
+        &self,
+        conn: &Connection,
+        round: i64,
+        port: u16,
+    ) -> Result<Option<PortBitmap>> {
+        let blob: Option<Vec<u8>> = conn
+            .query_row(
+                &format!(
+                    "SELECT bitmap FROM {}.port_bitmaps WHERE port = ?1 AND ip_type = 'IPv4' AND scan_round = ?2",
+                    self.bitmap_schema(port)
+                ),
+                params![port, round],
+                |row| row.get(0),
+            )
+            .optional()?;
+        blob.map(|blob| self.decode_bitmap(&blob, port, round))
+            .transpose()
+    }
+
+    /// Close open ports whose last sighting is `stale_rounds` or more rounds
+    /// before `completed_round`. Returns how many were marked gone; a later
+    /// sighting reopens the row. `stale_rounds == 0` disables aging.
+    pub fn mark_stale_ports(&self, completed_round: i64, stale_rounds: u32) -> Result<usize> {
+        if stale_rounds == 0 {
+            return Ok(0);
+        }
+        let conn = self.conn.lock().unwrap();
+        let closed = conn.execute(
+            "UPDATE open_ports_detail SET closed_at = ?1
+             WHERE closed_at IS NULL AND scan_round <= ?2",
+            params![
+                Utc::now().to_rfc3339(),
+                completed_round - stale_rounds as i64
+            ],
+        )?;
+        Ok(closed)
+    }
+
+    /// Every (ip, port) currently recorded as open (`closed_at` is null),
+    /// ordered by IP then port.
+    pub fn get_active_open_ports(&self) -> Result<Vec<(String, u16)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT ip_address, port FROM open_ports_detail
+             WHERE closed_at IS NULL ORDER BY ip_address, port",
+        )?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Mark these open ports as gone now. Rows already closed keep their
+    /// original `closed_at`. Returns how many rows changed.
+    pub fn mark_ports_closed(&self, ports: &[(String, u16)]) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        let now = Utc::now().to_rfc3339();
+        let mut closed = 0;
+        {
+            let mut stmt = transaction.prepare(
+                "UPDATE open_ports_detail SET closed_at = ?1
+                 WHERE ip_address = ?2 AND port = ?3 AND closed_at IS NULL",
+            )?;
+            for (ip, port) in ports {
+                closed += stmt.execute(params![now, ip, port])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(closed)
+    }
+
     pub fn get_stats(&self) -> Result<(usize, usize)> {
         let conn = self.conn.lock().unwrap();
 
@@ -510,7 +1292,36 @@ impl SqliteDB {
         Ok(new_round)
     }
 
+    /// Checkpoint the last dispatched IP. A handle tagged with a scan ID
+    /// records it on that API scan's session instead, so API scans never
+    /// move the CLI's resume point.
+    /// Make `round` the current round.
+    pub fn set_current_round(&self, round: i64) -> Result<()> {
+        self.save_metadata("current_round", &round.to_string())
+    }
+
+    /// Forget every resume point: the CLI's saved position and the
+    /// `last_ip` of every API scan session. The next scan of any kind
+    /// starts its round from the beginning.
+    pub fn clear_progress(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM scan_metadata WHERE key IN ('last_ip', 'last_ip_type', 'last_scan_round')",
+            [],
+        )?;
+        conn.execute("UPDATE scan_sessions SET last_ip = NULL", [])?;
+        Ok(())
+    }
+
     pub fn save_progress(&self, ip: &str, ip_type: &str, scan_round: i64) -> Result<()> {
+        if let Some(scan_id) = &self.scan_id {
+            let conn = self.conn.lock().unwrap();
+            conn.execute(
+                "UPDATE scan_sessions SET last_ip = ?2 WHERE scan_id = ?1",
+                params![scan_id.as_ref(), ip],
+            )?;
+            return Ok(());
+        }
         self.save_metadata("last_ip", ip)?;
         self.save_metadata("last_ip_type", ip_type)?;
         self.save_metadata("last_scan_round", &scan_round.to_string())?;
@@ -538,9 +1349,99 @@ impl SqliteDB {
         Ok(size as usize)
     }
 
+    /// File and WAL sizes, per-table row counts, per-table and per-index
+    /// page usage and the connection's pragma settings, for
+    /// `/admin/db` and `ip-scan db stats`. Counts every table's rows, so it
+    /// takes a while on large databases and holds writers back meanwhile.
+    pub fn database_stats(&self) -> Result<DatabaseStats> {
+        let conn = self.conn.lock().unwrap();
+        let path = database_path(&conn)?;
+        let (file_bytes, wal_bytes) = match &path {
+            Some(path) => (file_size(path), file_size(&format!("{}-wal", path))),
+            None => (None, None),
+        };
+
+        let pragma = |name: &str| -> Result<u64> {
+            Ok(conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get::<_, i64>(0))? as u64)
+        };
+        let page_size = pragma("page_size")?;
+        let page_count = pragma("page_count")?;
+        let freelist_pages = pragma("freelist_count")?;
+
+        let mut pragmas = BTreeMap::new();
+        for name in [
+            "journal_mode",
+            "synchronous",
+            "busy_timeout",
+            "cache_size",
+            "temp_store",
+            "wal_autocheckpoint",
+            "journal_size_limit",
+            "auto_vacuum",
+            "foreign_keys",
+        ] {
+            let value: rusqlite::types::Value =
+                conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get(0))?;
+            let value = match value {
+                rusqlite::types::Value::Integer(n) => n.to_string(),
+                rusqlite::types::Value::Text(text) => text,
+                other => format!("{:?}", other),
+            };
+            pragmas.insert(name.to_string(), value);
+        }
+
+        let bytes: HashMap<String, u64> = conn
+            .prepare("SELECT name, SUM(pgsize) FROM dbstat GROUP BY name")?
+            .query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as u64)))?
+            .collect::<Result<_, _>>()?;
+        let objects: Vec<(String, String, String)> = conn
+            .prepare(
+                "SELECT type, name, tbl_name FROM sqlite_master
+                 WHERE type = 'index' OR (type = 'table' AND name NOT LIKE 'sqlite_%')
+                 ORDER BY name",
+            )?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+        let mut tables = Vec::new();
+        let mut indexes = Vec::new();
+        for (kind, name, table) in objects {
+            let size = bytes.get(&name).copied().unwrap_or(0);
+            if kind == "index" {
+                indexes.push(IndexStats {
+                    name,
+                    table,
+                    bytes: size,
+                });
+                continue;
+            }
+            let rows: i64 =
+                conn.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", name), [], |row| {
+                    row.get(0)
+                })?;
+            tables.push(TableStats {
+                name,
+                rows: rows as u64,
+                bytes: size,
+            });
+        }
+
+        Ok(DatabaseStats {
+            path,
+            file_bytes,
+            wal_bytes,
+            page_size,
+            page_count,
+            freelist_pages,
+            tables,
+            indexes,
+            pragmas,
+        })
+    }
+
     // API-specific methods
 
     /// Get paginated scan results with filtering
+    #[allow(clippy::too_many_arguments)]
     pub fn get_scan_results(
         &self,
         page: usize,
@@ -549,33 +1450,25 @@ impl SqliteDB {
         port_filter: Option<u16>,
         round_filter: Option<i64>,
         ip_type_filter: Option<&str>,
+        status_filter: Option<PortStatus>,
+        scan_id_filter: Option<&str>,
+        hostname_filter: Option<&str>,
+        has_cves_filter: Option<bool>,
+        reputation_filter: Option<ReputationFilter>,
     ) -> Result<(Vec<ScanResultDetail>, usize)> {
         let conn = self.conn.lock().unwrap();
 
-        // Build WHERE clause
-        let mut where_clauses = Vec::new();
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-
-        if let Some(ip) = ip_filter {
-            where_clauses.push("ip_address LIKE ?");
-            params.push(Box::new(format!("%{}%", ip)));
-        }
-
-        if let Some(port) = port_filter {
-            where_clauses.push("port = ?");
-            params.push(Box::new(port));
-        }
-
-        if let Some(round) = round_filter {
-            where_clauses.push("scan_round = ?");
-            params.push(Box::new(round));
-        }
-
-        if let Some(ip_type) = ip_type_filter {
-            where_clauses.push("ip_type = ?");
-            params.push(Box::new(ip_type));
-        }
-
+        let (where_clauses, params) = result_filter_clauses(
+            ip_filter,
+            port_filter,
+            round_filter,
+            ip_type_filter,
+            status_filter,
+            scan_id_filter,
+            hostname_filter,
+            has_cves_filter,
+            reputation_filter,
+        );
         let where_clause = if where_clauses.is_empty() {
             "".to_string()
         } else {
@@ -583,7 +1476,7 @@ impl SqliteDB {
         };
 
         // Get total count
-        let count_query = format!("SELECT COUNT(*) FROM open_ports_detail {}", where_clause);
+        let count_query = format!("SELECT COUNT(*) FROM open_ports_detail o {}", where_clause);
 
         let total: i64 = conn.query_row(
             &count_query,
@@ -595,7 +1488,12 @@ impl SqliteDB {
         let offset = (page - 1) * page_size;
         let query = format!(
             "SELECT o.ip_address, o.ip_type, o.port, o.scan_round, o.first_seen, o.last_seen,
-                    i.country, i.city, i.reverse_dns
+                    i.country, i.city, i.reverse_dns, i.abuse_email, o.closed_at, o.scan_id,
+                    (SELECT group_concat(c.cve_id, ' ') FROM port_cves c
+                     WHERE c.ip_address = o.ip_address AND c.port = o.port),
+                    (SELECT MAX(r.score) FROM ip_reputation r WHERE r.ip_address = o.ip_address),
+                    EXISTS (SELECT 1 FROM ip_reputation r
+                            WHERE r.ip_address = o.ip_address AND r.scanner = 1)
              FROM open_ports_detail o
              LEFT JOIN ip_details i ON o.ip_address = i.ip_address
              {}
@@ -629,6 +1527,12 @@ impl SqliteDB {
                         country: row.get(6)?,
                         city: row.get(7)?,
                         reverse_dns: row.get(8)?,
+                        abuse_email: row.get(9)?,
+                        closed_at: row.get(10)?,
+                        scan_id: row.get(11)?,
+                        cves: split_cves(row.get(12)?),
+                        reputation_score: row.get(13)?,
+                        known_scanner: row.get(14)?,
                     })
                 },
             )?
@@ -637,13 +1541,96 @@ impl SqliteDB {
         Ok((results, total as usize))
     }
 
+    /// Filtered results with `id > after_id` in id order, for exports that
+    /// walk the whole table without OFFSET rescans. Returns each row's id.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_scan_results_after(
+        &self,
+        after_id: i64,
+        limit: usize,
+        ip_filter: Option<&str>,
+        port_filter: Option<u16>,
+        round_filter: Option<i64>,
+        ip_type_filter: Option<&str>,
+        status_filter: Option<PortStatus>,
+        scan_id_filter: Option<&str>,
+        hostname_filter: Option<&str>,
+        has_cves_filter: Option<bool>,
+        reputation_filter: Option<ReputationFilter>,
+    ) -> Result<Vec<(i64, ScanResultDetail)>> {
+        let conn = self.conn.lock().unwrap();
+        let (mut where_clauses, mut params) = result_filter_clauses(
+            ip_filter,
+            port_filter,
+            round_filter,
+            ip_type_filter,
+            status_filter,
+            scan_id_filter,
+            hostname_filter,
+            has_cves_filter,
+            reputation_filter,
+        );
+        where_clauses.insert(0, "o.id > ?");
+        params.insert(0, Box::new(after_id));
+        params.push(Box::new(limit as i64));
+        let query = format!(
+            "SELECT o.id, o.ip_address, o.ip_type, o.port, o.scan_round, o.first_seen, o.last_seen,
+                    i.country, i.city, i.reverse_dns, i.abuse_email, o.closed_at, o.scan_id,
+                    (SELECT group_concat(c.cve_id, ' ') FROM port_cves c
+                     WHERE c.ip_address = o.ip_address AND c.port = o.port),
+                    (SELECT MAX(r.score) FROM ip_reputation r WHERE r.ip_address = o.ip_address),
+                    EXISTS (SELECT 1 FROM ip_reputation r
+                            WHERE r.ip_address = o.ip_address AND r.scanner = 1)
+             FROM open_ports_detail o
+             LEFT JOIN ip_details i ON o.ip_address = i.ip_address
+             WHERE {}
+             ORDER BY o.id
+             LIMIT ?",
+            where_clauses.join(" AND ")
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt
+            .query_map(
+                params.iter().map(|p| &**p).collect::<Vec<_>>().as_slice(),
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        ScanResultDetail {
+                            ip_address: row.get(1)?,
+                            ip_type: row.get(2)?,
+                            port: row.get(3)?,
+                            scan_round: row.get(4)?,
+                            first_seen: row.get(5)?,
+                            last_seen: row.get(6)?,
+                            country: row.get(7)?,
+                            city: row.get(8)?,
+                            reverse_dns: row.get(9)?,
+                            abuse_email: row.get(10)?,
+                            closed_at: row.get(11)?,
+                            scan_id: row.get(12)?,
+                            cves: split_cves(row.get(13)?),
+                            reputation_score: row.get(14)?,
+                            known_scanner: row.get(15)?,
+                        },
+                    ))
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
     /// Get scan results for a specific IP
     pub fn get_results_by_ip(&self, ip: &str) -> Result<Vec<ScanResultDetail>> {
         let conn = self.conn.lock().unwrap();
 
         let mut stmt = conn.prepare(
             "SELECT o.ip_address, o.ip_type, o.port, o.scan_round, o.first_seen, o.last_seen,
-                    i.country, i.city, i.reverse_dns
+                    i.country, i.city, i.reverse_dns, i.abuse_email, o.closed_at, o.scan_id,
+                    (SELECT group_concat(c.cve_id, ' ') FROM port_cves c
+                     WHERE c.ip_address = o.ip_address AND c.port = o.port),
+                    (SELECT MAX(r.score) FROM ip_reputation r WHERE r.ip_address = o.ip_address),
+                    EXISTS (SELECT 1 FROM ip_reputation r
+                            WHERE r.ip_address = o.ip_address AND r.scanner = 1)
              FROM open_ports_detail o
              LEFT JOIN ip_details i ON o.ip_address = i.ip_address
              WHERE o.ip_address = ? 
@@ -662,6 +1649,12 @@ impl SqliteDB {
                     country: row.get(6)?,
                     city: row.get(7)?,
                     reverse_dns: row.get(8)?,
+                    abuse_email: row.get(9)?,
+                    closed_at: row.get(10)?,
+                    scan_id: row.get(11)?,
+                    cves: split_cves(row.get(12)?),
+                    reputation_score: row.get(13)?,
+                    known_scanner: row.get(14)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
@@ -669,53 +1662,110 @@ impl SqliteDB {
         Ok(results)
     }
 
-    /// Get scan results for a specific port
-    pub fn get_results_by_port(&self, port: u16) -> Result<Vec<ScanResultDetail>> {
-        let conn = self.conn.lock().unwrap();
-
-        let mut stmt = conn.prepare(
-            "SELECT o.ip_address, o.ip_type, o.port, o.scan_round, o.first_seen, o.last_seen,
-                    i.country, i.city, i.reverse_dns
-             FROM open_ports_detail o
-             LEFT JOIN ip_details i ON o.ip_address = i.ip_address
-             WHERE o.port = ? 
-             ORDER BY o.last_seen DESC, o.ip_address",
-        )?;
+    /// One page of the results for `port`, most recently seen first, and
+    /// the total number of them.
+    pub fn get_results_by_port(
+        &self,
+        port: u16,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ScanResultDetail>, usize)> {
+        self.results_page(
+            "o.port = ?",
+            Box::new(port),
+            "o.last_seen DESC, o.ip_address",
+            page,
+            page_size,
+        )
+    }
 
-        let results = stmt
-            .query_map([port], |row| {
-                Ok(ScanResultDetail {
-                    ip_address: row.get(0)?,
-                    ip_type: row.get(1)?,
-                    port: row.get(2)?,
-                    scan_round: row.get(3)?,
-                    first_seen: row.get(4)?,
-                    last_seen: row.get(5)?,
-                    country: row.get(6)?,
-                    city: row.get(7)?,
-                    reverse_dns: row.get(8)?,
-                })
-            })?
-            .collect::<Result<Vec<_>, _>>()?;
+    /// One page of the results last seen in `round`, by address, and the
+    /// total number of them.
+    pub fn get_results_by_round(
+        &self,
+        round: i64,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ScanResultDetail>, usize)> {
+        self.results_page(
+            "o.scan_round = ?",
+            Box::new(round),
+            "o.ip_address, o.port",
+            page,
+            page_size,
+        )
+    }
 
+    /// Every result last seen in `round`, for the small databases of
+    /// workers and embedded scans; the API pages through
+    /// [`get_results_by_round`](Self::get_results_by_round) instead.
+    pub fn get_all_results_by_round(&self, round: i64) -> Result<Vec<ScanResultDetail>> {
+        const BATCH: usize = 10_000;
+        let mut results = Vec::new();
+        let mut after_id = 0;
+        loop {
+            let rows = self.get_scan_results_after(
+                after_id,
+                BATCH,
+                None,
+                None,
+                Some(round),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )?;
+            let Some((last_id, _)) = rows.last() else {
+                break;
+            };
+            after_id = *last_id;
+            let done = rows.len() < BATCH;
+            results.extend(rows.into_iter().map(|(_, r)| r));
+            if done {
+                break;
+            }
+        }
         Ok(results)
     }
 
-    /// Get scan results for a specific round
-    pub fn get_results_by_round(&self, round: i64) -> Result<Vec<ScanResultDetail>> {
+    fn results_page(
+        &self,
+        condition: &str,
+        value: Box<dyn rusqlite::ToSql>,
+        order_by: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ScanResultDetail>, usize)> {
         let conn = self.conn.lock().unwrap();
+        let total: i64 = conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM open_ports_detail o WHERE {}",
+                condition
+            ),
+            [&value],
+            |row| row.get(0),
+        )?;
 
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare(&format!(
             "SELECT o.ip_address, o.ip_type, o.port, o.scan_round, o.first_seen, o.last_seen,
-                    i.country, i.city, i.reverse_dns
+                    i.country, i.city, i.reverse_dns, i.abuse_email, o.closed_at, o.scan_id,
+                    (SELECT group_concat(c.cve_id, ' ') FROM port_cves c
+                     WHERE c.ip_address = o.ip_address AND c.port = o.port),
+                    (SELECT MAX(r.score) FROM ip_reputation r WHERE r.ip_address = o.ip_address),
+                    EXISTS (SELECT 1 FROM ip_reputation r
+                            WHERE r.ip_address = o.ip_address AND r.scanner = 1)
              FROM open_ports_detail o
              LEFT JOIN ip_details i ON o.ip_address = i.ip_address
-             WHERE o.scan_round = ? 
-             ORDER BY o.ip_address, o.port",
-        )?;
-
+             WHERE {}
+             ORDER BY {}
+             LIMIT ? OFFSET ?",
+            condition, order_by
+        ))?;
+        let offset = page.saturating_sub(1) * page_size;
         let results = stmt
-            .query_map([round], |row| {
+            .query_map(params![value, page_size as i64, offset as i64], |row| {
                 Ok(ScanResultDetail {
                     ip_address: row.get(0)?,
                     ip_type: row.get(1)?,
@@ -726,11 +1776,17 @@ impl SqliteDB {
                     country: row.get(6)?,
                     city: row.get(7)?,
                     reverse_dns: row.get(8)?,
+                    abuse_email: row.get(9)?,
+                    closed_at: row.get(10)?,
+                    scan_id: row.get(11)?,
+                    cves: split_cves(row.get(12)?),
+                    reputation_score: row.get(13)?,
+                    known_scanner: row.get(14)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(results)
+        Ok((results, total as usize))
     }
 
     /// Get top ports statistics
@@ -754,6 +1810,80 @@ impl SqliteDB {
         Ok(results)
     }
 
+    /// Hosts with the most active open ports, with their port numbers in
+    /// ascending order when `with_ports` is set.
+    pub fn get_top_ips(&self, limit: usize, with_ports: bool) -> Result<Vec<TopIp>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT ip_address, COUNT(*) as count, CASE WHEN ?2 THEN GROUP_CONCAT(port) END
+             FROM open_ports_detail
+             WHERE closed_at IS NULL
+             GROUP BY ip_address
+             ORDER BY count DESC, ip_address
+             LIMIT ?1",
+        )?;
+
+        let results = stmt
+            .query_map(params![limit as i64, with_ports], |row| {
+                let ports = row.get::<_, Option<String>>(2)?.map(|list| {
+                    let mut ports: Vec<u16> =
+                        list.split(',').filter_map(|p| p.parse().ok()).collect();
+                    ports.sort_unstable();
+                    ports
+                });
+                Ok((row.get(0)?, row.get::<_, i64>(1)? as usize, ports))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(results)
+    }
+
+    /// Countries with the most hosts that have an active open port; hosts
+    /// without geo data yet are left out.
+    pub fn get_top_countries(&self, limit: usize) -> Result<Vec<(String, usize)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT i.country, COUNT(DISTINCT o.ip_address) AS hosts
+             FROM open_ports_detail o
+             JOIN ip_details i ON i.ip_address = o.ip_address
+             WHERE o.closed_at IS NULL AND i.country IS NOT NULL AND i.country != ''
+             GROUP BY i.country
+             ORDER BY hosts DESC, i.country
+             LIMIT ?1",
+        )?;
+        let countries = stmt
+            .query_map([limit as i64], |row| {
+                Ok((row.get(0)?, row.get::<_, i64>(1)? as usize))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(countries)
+    }
+
+    /// Open ports first seen at or after `since` (RFC 3339), i.e. found for
+    /// the first time rather than seen again.
+    pub fn count_open_ports_since(&self, since: &str) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM open_ports_detail WHERE first_seen >= ?1",
+            [since],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Distinct countries already recorded in `ip_details`
+    pub fn get_known_countries(&self) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT country FROM ip_details WHERE country IS NOT NULL AND country != ''",
+        )?;
+        let countries = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(countries)
+    }
+
     /// Get total count of all open ports
     pub fn get_total_open_ports_count(&self) -> Result<usize> {
         let conn = self.conn.lock().unwrap();
@@ -777,30 +1907,47 @@ impl SqliteDB {
         Ok(result)
     }
 
-    /// Get scan history grouped by scan round
+    /// Get scan history grouped by scan round. Rounds scanned by an API
+    /// scan carry that scan's session; the latest one wins if several
+    /// covered the same round.
     pub fn get_scan_history(&self, limit: usize) -> Result<Vec<ScanHistoryRecord>> {
         let conn = self.conn.lock().unwrap();
 
         let mut stmt = conn.prepare(
-            "SELECT scan_round,
-                    MIN(last_updated) as start_time,
-                    MAX(last_updated) as end_time,
-                    SUM(open_count) as total_open_ports,
-                    COUNT(DISTINCT port) as ports_scanned
-             FROM port_bitmaps
-             GROUP BY scan_round
-             ORDER BY scan_round DESC
-             LIMIT ?",
+            "SELECT h.scan_round, h.start_time, h.end_time, h.total_open_ports, h.ports_scanned,
+                    s.scan_id, s.name, s.description, s.owner, s.start_round, s.end_round,
+                    s.status, s.started_at, s.finished_at, s.last_ip, s.principal
+             FROM (SELECT scan_round,
+                          MIN(last_updated) as start_time,
+                          MAX(last_updated) as end_time,
+                          SUM(open_count) as total_open_ports,
+                          COUNT(DISTINCT port) as ports_scanned
+                   FROM port_bitmaps
+                   GROUP BY scan_round
+                   ORDER BY scan_round DESC
+                   LIMIT ?) h
+             LEFT JOIN scan_sessions s ON s.rowid = (
+                 SELECT rowid FROM scan_sessions
+                 WHERE start_round <= h.scan_round AND end_round >= h.scan_round
+                 ORDER BY started_at DESC
+                 LIMIT 1
+             )
+             ORDER BY h.scan_round DESC",
         )?;
 
         let results = stmt
             .query_map([limit as i64], |row| {
+                let session = match row.get::<_, Option<String>>(5)? {
+                    Some(_) => Some(scan_session_from_row(row, 5)?),
+                    None => None,
+                };
                 Ok(ScanHistoryRecord {
                     round: row.get(0)?,
                     start_time: row.get(1)?,
                     end_time: row.get(2)?,
                     total_open_ports: row.get::<_, i64>(3)? as usize,
                     ports_scanned: row.get::<_, i64>(4)? as usize,
+                    session,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
@@ -808,243 +1955,2543 @@ impl SqliteDB {
         Ok(results)
     }
 
-    // ── Service Info CRUD ──────────────────────────────────────────
-
-    #[allow(dead_code)]
-    pub fn save_service_info(&self, info: &ServiceInfo) -> Result<()> {
+    /// Record an API scan starting in `round`, started by `principal`.
+    /// Reusing a `scan_id` replaces the earlier session.
+    pub fn create_scan_session(
+        &self,
+        scan_id: &str,
+        name: Option<&str>,
+        description: Option<&str>,
+        owner: Option<&str>,
+        principal: Option<&str>,
+        round: i64,
+    ) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO service_info (ip_address, port, service_name, protocol, banner, http_title, http_server, http_body_preview, tls_subject, tls_issuer, tls_not_before, tls_not_after, tls_version, service_version, http_body_hash, http_security_headers, rtt_ms, os_guess, detected_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)
-             ON CONFLICT(ip_address, port)
-             DO UPDATE SET service_name=?3, protocol=?4, banner=?5, http_title=?6, http_server=?7, http_body_preview=?8, tls_subject=?9, tls_issuer=?10, tls_not_before=?11, tls_not_after=?12, tls_version=?13, service_version=?14, http_body_hash=?15, http_security_headers=?16, rtt_ms=?17, os_guess=?18, detected_at=?19",
+            "INSERT OR REPLACE INTO scan_sessions
+                (scan_id, name, description, owner, start_round, end_round, status, started_at,
+                 finished_at, principal)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?5, 'running', ?6, NULL, ?7)",
             params![
-                info.ip, info.port, info.service_name, info.protocol,
-                info.banner, info.http_title, info.http_server,
-                info.http_body_preview, info.tls_subject, info.tls_issuer,
-                info.tls_not_before, info.tls_not_after, info.tls_version,
-                info.service_version, info.http_body_hash, info.http_security_headers, info.rtt_ms, info.os_guess,
-                info.detected_at,
+                scan_id,
+                name,
+                description,
+                owner,
+                round,
+                Utc::now().to_rfc3339(),
+                principal
             ],
         )?;
         Ok(())
     }
 
-    pub fn save_service_info_batch(&self, infos: &[ServiceInfo]) -> Result<()> {
-        if infos.is_empty() {
-            return Ok(());
-        }
-        let mut conn = self.conn.lock().unwrap();
-        let tx = conn.transaction()?;
-        {
-            let mut stmt = tx.prepare(
-                "INSERT INTO service_info (ip_address, port, service_name, protocol, banner, http_title, http_server, http_body_preview, tls_subject, tls_issuer, tls_not_before, tls_not_after, tls_version, service_version, http_body_hash, http_security_headers, rtt_ms, os_guess, detected_at)\n                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)\n                 ON CONFLICT(ip_address, port)\n                 DO UPDATE SET service_name=?3, protocol=?4, banner=?5, http_title=?6, http_server=?7, http_body_preview=?8, tls_subject=?9, tls_issuer=?10, tls_not_before=?11, tls_not_after=?12, tls_version=?13, service_version=?14, http_body_hash=?15, http_security_headers=?16, rtt_ms=?17, os_guess=?18, detected_at=?19"
-            )?;
-            for info in infos {
-                stmt.execute(params![
-                    info.ip,
-                    info.port,
-                    info.service_name,
-                    info.protocol,
-                    info.banner,
-                    info.http_title,
-                    info.http_server,
-                    info.http_body_preview,
-                    info.tls_subject,
-                    info.tls_issuer,
-                    info.tls_not_before,
-                    info.tls_not_after,
-                    info.tls_version,
-                    info.service_version,
-                    info.http_body_hash,
-                    info.http_security_headers,
-                    info.rtt_ms,
-                    info.os_guess,
-                    info.detected_at,
-                ])?;
-            }
-        }
-        tx.commit()?;
+    /// Running API scans started by `principal`.
+    pub fn count_running_scan_sessions(&self, principal: &str) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        let running = conn.query_row(
+            "SELECT COUNT(*) FROM scan_sessions WHERE status = 'running' AND principal = ?1",
+            [principal],
+            |row| row.get(0),
+        )?;
+        Ok(running)
+    }
+
+    /// Note that a loop-mode session went on to scan `round`. Moving to a
+    /// later round drops the previous round's resume point.
+    pub fn extend_scan_session(&self, scan_id: &str, round: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE scan_sessions
+             SET last_ip = CASE WHEN end_round >= ?2 THEN last_ip END,
+                 end_round = MAX(end_round, ?2)
+             WHERE scan_id = ?1",
+            params![scan_id, round],
+        )?;
         Ok(())
     }
 
-    pub fn get_service_info_by_ip(&self, ip: &str) -> Result<Vec<ServiceInfo>> {
+    /// Mark a stopped, failed or interrupted session as running again, for
+    /// a scan resuming from its `last_ip`.
+    pub fn reopen_scan_session(&self, scan_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE scan_sessions SET status = 'running', finished_at = NULL WHERE scan_id = ?1",
+            [scan_id],
+        )?;
+        Ok(())
+    }
+
+    /// Record how a session ended: `completed`, `stopped` or `error`.
+    pub fn finish_scan_session(&self, scan_id: &str, status: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE scan_sessions SET status = ?2, finished_at = ?3 WHERE scan_id = ?1",
+            params![scan_id, status, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_scan_session(&self, scan_id: &str) -> Result<Option<ScanSession>> {
+        let conn = self.conn.lock().unwrap();
+        let session = conn
+            .query_row(
+                "SELECT scan_id, name, description, owner, start_round, end_round, status,
+                        started_at, finished_at, last_ip, principal
+                 FROM scan_sessions WHERE scan_id = ?1",
+                [scan_id],
+                |row| scan_session_from_row(row, 0),
+            )
+            .optional()?;
+        Ok(session)
+    }
+
+    /// The most recently started API scan session, if any.
+    pub fn get_latest_scan_session(&self) -> Result<Option<ScanSession>> {
+        let conn = self.conn.lock().unwrap();
+        let session = conn
+            .query_row(
+                "SELECT scan_id, name, description, owner, start_round, end_round, status,
+                        started_at, finished_at, last_ip, principal
+                 FROM scan_sessions ORDER BY started_at DESC, rowid DESC LIMIT 1",
+                [],
+                |row| scan_session_from_row(row, 0),
+            )
+            .optional()?;
+        Ok(session)
+    }
+
+    /// Record the counters of a finished (or interrupted) round. A resumed
+    /// round adds to the row written by the earlier run, so the totals and
+    /// average rate cover the whole round.
+    pub fn save_round_metrics(&self, metrics: &RoundMetrics) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO round_metrics (scan_round, scanned, open, errors, retries, duration_secs, avg_rate, finished_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, CASE WHEN ?6 > 0 THEN ?2 / ?6 ELSE 0 END, ?7)
+             ON CONFLICT(scan_round) DO UPDATE SET
+                scanned = scanned + excluded.scanned,
+                open = open + excluded.open,
+                errors = errors + excluded.errors,
+                retries = retries + excluded.retries,
+                duration_secs = duration_secs + excluded.duration_secs,
+                avg_rate = CASE WHEN duration_secs + excluded.duration_secs > 0
+                    THEN (scanned + excluded.scanned) / (duration_secs + excluded.duration_secs)
+                    ELSE 0 END,
+                finished_at = excluded.finished_at",
+            params![
+                metrics.round,
+                metrics.scanned as i64,
+                metrics.open as i64,
+                metrics.errors as i64,
+                metrics.retries as i64,
+                metrics.duration_secs,
+                Utc::now().to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Store a new template. Returns `None` when `name` is already taken.
+    pub fn create_scan_template(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        params: &serde_json::Value,
+    ) -> Result<Option<ScanTemplate>> {
+        let id = {
+            let conn = self.conn.lock().unwrap();
+            let now = Utc::now().to_rfc3339();
+            let inserted = conn.execute(
+                "INSERT OR IGNORE INTO scan_templates (name, description, params, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?4)",
+                params![name, description, params.to_string(), now],
+            )?;
+            if inserted == 0 {
+                return Ok(None);
+            }
+            conn.last_insert_rowid()
+        };
+        self.get_scan_template(id)
+    }
+
+    /// Templates ordered by name.
+    pub fn list_scan_templates(&self) -> Result<Vec<ScanTemplate>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT ip_address, port, service_name, protocol, banner, http_title, http_server, http_body_preview, tls_subject, tls_issuer, tls_not_before, tls_not_after, tls_version, service_version, http_body_hash, http_security_headers, rtt_ms, os_guess, detected_at
-             FROM service_info WHERE ip_address = ?1 ORDER BY port",
+            "SELECT id, name, description, params, created_at, updated_at
+             FROM scan_templates ORDER BY name",
         )?;
-        let results = stmt
-            .query_map([ip], |row| {
-                Ok(ServiceInfo {
-                    ip: row.get(0)?,
-                    port: row.get(1)?,
-                    service_name: row.get(2)?,
-                    protocol: row.get(3)?,
-                    banner: row.get(4)?,
-                    http_title: row.get(5)?,
-                    http_server: row.get(6)?,
-                    http_body_preview: row.get(7)?,
-                    tls_subject: row.get(8)?,
-                    tls_issuer: row.get(9)?,
-                    tls_not_before: row.get(10)?,
-                    tls_not_after: row.get(11)?,
-                    tls_version: row.get(12)?,
-                    service_version: row.get(13)?,
-                    http_body_hash: row.get(14)?,
-                    http_security_headers: row.get(15)?,
-                    rtt_ms: row.get(16)?,
-                    os_guess: row.get(17)?,
-                    detected_at: row.get(18)?,
-                })
-            })?
+        let rows = stmt
+            .query_map([], scan_template_from_row)?
             .collect::<Result<Vec<_>, _>>()?;
-        Ok(results)
+        Ok(rows)
     }
 
-    pub fn mark_service_probe_attempts(&self, ips: &[String]) -> Result<()> {
-        if ips.is_empty() {
-            return Ok(());
+    pub fn get_scan_template(&self, id: i64) -> Result<Option<ScanTemplate>> {
+        let conn = self.conn.lock().unwrap();
+        let template = conn
+            .query_row(
+                "SELECT id, name, description, params, created_at, updated_at
+                 FROM scan_templates WHERE id = ?1",
+                [id],
+                scan_template_from_row,
+            )
+            .optional()?;
+        Ok(template)
+    }
+
+    /// Replace a template's name, description and parameters. Returns
+    /// `None` when no template has `id` or `name` belongs to another one.
+    pub fn update_scan_template(
+        &self,
+        id: i64,
+        name: &str,
+        description: Option<&str>,
+        params: &serde_json::Value,
+    ) -> Result<Option<ScanTemplate>> {
+        let updated = {
+            let conn = self.conn.lock().unwrap();
+            conn.execute(
+                "UPDATE OR IGNORE scan_templates
+                 SET name = ?2, description = ?3, params = ?4, updated_at = ?5
+                 WHERE id = ?1",
+                params![
+                    id,
+                    name,
+                    description,
+                    params.to_string(),
+                    Utc::now().to_rfc3339()
+                ],
+            )?
+        };
+        if updated == 0 {
+            return Ok(None);
         }
-        let mut conn = self.conn.lock().unwrap();
-        let tx = conn.transaction()?;
-        let now = Utc::now().to_rfc3339();
+        self.get_scan_template(id)
+    }
+
+    /// Returns whether a template was deleted.
+    pub fn delete_scan_template(&self, id: i64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute("DELETE FROM scan_templates WHERE id = ?1", [id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Register a namespace. Returns `None` when `name` is already taken.
+    pub fn create_namespace(&self, name: &str, principals: &[String]) -> Result<Option<Namespace>> {
         {
-            let mut stmt = tx.prepare("INSERT INTO service_probe_state (ip_address, last_probe) VALUES (?1, ?2) ON CONFLICT(ip_address) DO UPDATE SET last_probe = ?2")?;
-            for ip in ips {
-                stmt.execute(params![ip, now])?;
+            let conn = self.conn.lock().unwrap();
+            let now = Utc::now().to_rfc3339();
+            let inserted = conn.execute(
+                "INSERT OR IGNORE INTO namespaces (name, principals, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?3)",
+                params![name, serde_json::to_string(principals)?, now],
+            )?;
+            if inserted == 0 {
+                return Ok(None);
             }
         }
-        tx.commit()?;
-        Ok(())
+        self.get_namespace(name)
     }
 
-    pub fn get_ips_missing_service_probe(&self, limit: usize) -> Result<Vec<(String, Vec<u16>)>> {
+    /// Namespaces ordered by name.
+    pub fn list_namespaces(&self) -> Result<Vec<Namespace>> {
         let conn = self.conn.lock().unwrap();
-        let retry_before = (Utc::now() - chrono::Duration::hours(1)).to_rfc3339();
         let mut stmt = conn.prepare(
-            "SELECT o.ip_address, GROUP_CONCAT(o.port) as ports
-             FROM open_ports_detail o
-             WHERE o.ip_address NOT IN (SELECT DISTINCT ip_address FROM service_info)
-               AND (NOT EXISTS (SELECT 1 FROM service_probe_state s WHERE s.ip_address = o.ip_address)
-                    OR EXISTS (SELECT 1 FROM service_probe_state s WHERE s.ip_address = o.ip_address AND s.last_probe < ?2))
-             GROUP BY o.ip_address
-             LIMIT ?1",
+            "SELECT name, principals, created_at, updated_at FROM namespaces ORDER BY name",
         )?;
-        let results = stmt
-            .query_map(params![limit as i64, retry_before], |row| {
-                let ip: String = row.get(0)?;
-                let ports_str: String = row.get(1)?;
-                let ports: Vec<u16> = ports_str
-                    .split(',')
-                    .filter_map(|s| s.parse().ok())
-                    .collect();
-                Ok((ip, ports))
-            })?
+        let rows = stmt
+            .query_map([], namespace_from_row)?
             .collect::<Result<Vec<_>, _>>()?;
-        Ok(results)
+        Ok(rows)
     }
 
-    pub fn get_all_ip_service_summaries(
-        &self,
-        limit: usize,
-        offset: usize,
-    ) -> Result<Vec<IpServiceSummary>> {
-        // Release the connection mutex before loading each IP's services.
-        // get_service_info_by_ip acquires the same non-reentrant mutex.
-        let ips: Vec<String> = {
+    pub fn get_namespace(&self, name: &str) -> Result<Option<Namespace>> {
+        let conn = self.conn.lock().unwrap();
+        let namespace = conn
+            .query_row(
+                "SELECT name, principals, created_at, updated_at FROM namespaces WHERE name = ?1",
+                [name],
+                namespace_from_row,
+            )
+            .optional()?;
+        Ok(namespace)
+    }
+
+    /// Replace the callers of a namespace. Returns `None` when it does not
+    /// exist.
+    pub fn update_namespace(&self, name: &str, principals: &[String]) -> Result<Option<Namespace>> {
+        let updated = {
             let conn = self.conn.lock().unwrap();
-            let mut stmt = conn.prepare(
-                "SELECT ip_address FROM (SELECT DISTINCT ip_address FROM service_info) LIMIT ?1 OFFSET ?2",
-            )?;
-            let rows = stmt
-                .query_map([limit as i64, offset as i64], |row| row.get(0))?
-                .collect::<Result<Vec<_>, _>>()?;
-            rows
+            conn.execute(
+                "UPDATE namespaces SET principals = ?2, updated_at = ?3 WHERE name = ?1",
+                params![
+                    name,
+                    serde_json::to_string(principals)?,
+                    Utc::now().to_rfc3339()
+                ],
+            )?
         };
+        if updated == 0 {
+            return Ok(None);
+        }
+        self.get_namespace(name)
+    }
 
-        let mut summaries = Vec::new();
-        for ip in ips {
-            let services = self.get_service_info_by_ip(&ip)?;
-            let category = IpServiceSummary::categorize(&services);
-            let (risk_score, risk_reasons) = IpServiceSummary::assess_risk(&services);
-            summaries.push(IpServiceSummary {
-                ip: ip.clone(),
-                services,
-                ip_type: None,
-                category,
-                risk_score,
-                risk_reasons,
-            });
+    /// Returns whether a namespace was deleted; its database is left alone.
+    pub fn delete_namespace(&self, name: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute("DELETE FROM namespaces WHERE name = ?1", [name])?;
+        Ok(deleted > 0)
+    }
+
+    /// Append one API call to `audit_log`; `entry.id` is ignored.
+    pub fn record_audit(&self, entry: &AuditEntry) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO audit_log (created_at, method, path, principal, client, params, status)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                entry.created_at,
+                entry.method,
+                entry.path,
+                entry.principal,
+                entry.client,
+                entry.params.as_ref().map(|params| params.to_string()),
+                entry.status
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Add `amount` to `principal`'s usage of `kind` in `period` and return
+    /// the new total. Earlier periods of that kind are dropped.
+    pub fn add_quota_usage(
+        &self,
+        principal: &str,
+        kind: &str,
+        period: &str,
+        amount: i64,
+    ) -> Result<i64> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM api_quota_usage WHERE principal = ?1 AND kind = ?2 AND period <> ?3",
+            params![principal, kind, period],
+        )?;
+        tx.execute(
+            "INSERT INTO api_quota_usage (principal, kind, period, used) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(principal, kind, period) DO UPDATE SET used = used + excluded.used",
+            params![principal, kind, period, amount],
+        )?;
+        let used = tx.query_row(
+            "SELECT used FROM api_quota_usage WHERE principal = ?1 AND kind = ?2 AND period = ?3",
+            params![principal, kind, period],
+            |row| row.get(0),
+        )?;
+        tx.commit()?;
+        Ok(used)
+    }
+
+    /// Reserve what is left of `principal`'s `limit` of `kind` in `period`
+    /// and return the amount granted: usage is raised to `limit` under the
+    /// database write lock, so concurrent callers, in this process or
+    /// another sharing the database, are never granted the same allowance.
+    /// Earlier periods of that kind are dropped.
+    pub fn reserve_quota_usage(
+        &self,
+        principal: &str,
+        kind: &str,
+        period: &str,
+        limit: i64,
+    ) -> Result<i64> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        tx.execute(
+            "DELETE FROM api_quota_usage WHERE principal = ?1 AND kind = ?2 AND period <> ?3",
+            params![principal, kind, period],
+        )?;
+        let used: i64 = tx
+            .query_row(
+                "SELECT used FROM api_quota_usage WHERE principal = ?1 AND kind = ?2 AND period = ?3",
+                params![principal, kind, period],
+                |row| row.get(0),
+            )
+            .optional()?
+            .unwrap_or(0);
+        let granted = (limit - used).max(0);
+        if granted > 0 {
+            tx.execute(
+                "INSERT INTO api_quota_usage (principal, kind, period, used) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(principal, kind, period) DO UPDATE SET used = excluded.used",
+                params![principal, kind, period, limit],
+            )?;
         }
-        Ok(summaries)
+        tx.commit()?;
+        Ok(granted)
     }
 
-    /// Compare two persisted IPv4 bitmap rounds and return bounded port changes.
-    pub fn get_bitmap_changes(
+    /// Give back `amount` of a [`reserve_quota_usage`](Self::reserve_quota_usage)
+    /// that went unused. Nothing happens once `period` has been dropped.
+    pub fn release_quota_usage(
         &self,
-        round: i64,
-        port: u16,
-        limit: usize,
-    ) -> Result<Vec<PortChange>> {
+        principal: &str,
+        kind: &str,
+        period: &str,
+        amount: i64,
+    ) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-        let load = |scan_round: i64| -> Result<Option<PortBitmap>> {
-            let result: rusqlite::Result<Vec<u8>> = conn.query_row(
-                "SELECT bitmap FROM port_bitmaps WHERE port = ?1 AND ip_type = 'IPv4' AND scan_round = ?2",
-                params![port, scan_round], |row| row.get(0));
-            match result {
-                Ok(blob) => Ok(Some(PortBitmap::from_blob(&blob)?)),
-                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-                Err(e) => Err(e.into()),
-            }
-        };
-        let Some(current) = load(round)? else {
-            return Ok(Vec::new());
-        };
-        let previous = load(round - 1)?.unwrap_or_else(PortBitmap::new);
-        Ok(current
-            .changed_indices(&previous, limit)
-            .into_iter()
-            .map(|index| PortChange {
-                ip_address: index_to_ipv4(index),
-                port,
-                round,
-                is_open: current.get(index),
-            })
-            .collect())
+        conn.execute(
+            "UPDATE api_quota_usage SET used = MAX(used - ?4, 0)
+             WHERE principal = ?1 AND kind = ?2 AND period = ?3",
+            params![principal, kind, period, amount],
+        )?;
+        Ok(())
     }
 
-    pub fn count_ips_with_service_info(&self) -> Result<usize> {
+    /// `principal`'s usage of `kind` in `period`; 0 when nothing is recorded.
+    pub fn get_quota_usage(&self, principal: &str, kind: &str, period: &str) -> Result<i64> {
         let conn = self.conn.lock().unwrap();
-        let count: i64 = conn.query_row(
-            "SELECT COUNT(DISTINCT ip_address) FROM service_info",
-            [],
-            |row| row.get(0),
+        let used = conn
+            .query_row(
+                "SELECT used FROM api_quota_usage WHERE principal = ?1 AND kind = ?2 AND period = ?3",
+                params![principal, kind, period],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(used.unwrap_or(0))
+    }
+
+    /// Newest calls first; `before` pages back from an earlier answer's
+    /// last `id`.
+    pub fn get_audit_log(&self, limit: usize, before: Option<i64>) -> Result<Vec<AuditEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, created_at, method, path, principal, client, params, status
+             FROM audit_log
+             WHERE ?1 IS NULL OR id < ?1
+             ORDER BY id DESC
+             LIMIT ?2",
         )?;
-        Ok(count as usize)
+        let rows = stmt
+            .query_map(params![before, limit as i64], |row| {
+                let params: Option<String> = row.get(6)?;
+                Ok(AuditEntry {
+                    id: row.get(0)?,
+                    created_at: row.get(1)?,
+                    method: row.get(2)?,
+                    path: row.get(3)?,
+                    principal: row.get(4)?,
+                    client: row.get(5)?,
+                    params: params.and_then(|params| serde_json::from_str(&params).ok()),
+                    status: row.get(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
     }
-}
 
-/// Detailed scan result for API responses
-#[derive(Debug)]
-pub struct ScanResultDetail {
-    pub ip_address: String,
-    pub ip_type: String,
-    pub port: u16,
-    pub scan_round: i64,
-    pub first_seen: String,
-    pub last_seen: String,
-    pub country: Option<String>,
-    pub city: Option<String>,
+    /// Most recent rounds first.
+    pub fn get_round_metrics(&self, limit: usize) -> Result<Vec<RoundMetrics>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT scan_round, scanned, open, errors, retries, duration_secs, avg_rate, finished_at
+             FROM round_metrics
+             ORDER BY scan_round DESC
+             LIMIT ?",
+        )?;
+        let rows = stmt
+            .query_map([limit as i64], |row| {
+                Ok(RoundMetrics {
+                    round: row.get(0)?,
+                    scanned: row.get::<_, i64>(1)? as u64,
+                    open: row.get::<_, i64>(2)? as u64,
+                    errors: row.get::<_, i64>(3)? as u64,
+                    retries: row.get::<_, i64>(4)? as u64,
+                    duration_secs: row.get(5)?,
+                    avg_rate: row.get(6)?,
+                    finished_at: row.get(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// The newest round scanned to the end: the current round once it is
+    /// marked complete, otherwise the one before it (0 when none is).
+    pub fn last_completed_round(&self) -> Result<i64> {
+        let round = self.get_current_round()?;
+        if self.get_metadata(&format!("round_{}_complete", round))? == Some("true".to_string()) {
+            Ok(round)
+        } else {
+            Ok(round - 1)
+        }
+    }
+
+    /// `port_history` runs of `ip`, optionally for one port, oldest first.
+    /// A run that ends before the last completed round has disappeared.
+    pub fn get_port_history(&self, ip: &str, port: Option<u16>) -> Result<Vec<PortHistoryRun>> {
+        let completed = self.last_completed_round()?;
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT port, start_round, end_round, first_seen, last_seen
+             FROM port_history
+             WHERE ip_address = ?1 AND (?2 IS NULL OR port = ?2)
+             ORDER BY port, start_round",
+        )?;
+        let runs = stmt
+            .query_map(params![ip, port], |row| {
+                let end_round: i64 = row.get(2)?;
+                Ok(PortHistoryRun {
+                    port: row.get(0)?,
+                    start_round: row.get(1)?,
+                    end_round,
+                    first_seen: row.get(3)?,
+                    last_seen: row.get(4)?,
+                    ended: end_round < completed,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(runs)
+    }
+
+    /// How long ports stay open, from `port_history`: per port, the number
+    /// of runs, how many have ended and their average length, for the
+    /// `limit` ports with the most runs.
+    pub fn get_port_lifetimes(&self, limit: usize) -> Result<Vec<PortLifetime>> {
+        let completed = self.last_completed_round()?;
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT port,
+                    COUNT(*),
+                    SUM(end_round < ?1),
+                    AVG(CASE WHEN end_round < ?1 THEN end_round - start_round + 1 END),
+                    AVG(CASE WHEN end_round < ?1
+                             THEN (julianday(last_seen) - julianday(first_seen)) * 24 END),
+                    MAX(end_round - start_round + 1)
+             FROM port_history
+             GROUP BY port
+             ORDER BY COUNT(*) DESC, port
+             LIMIT ?2",
+        )?;
+        let rows = stmt
+            .query_map(params![completed, limit as i64], |row| {
+                Ok(PortLifetime {
+                    port: row.get(0)?,
+                    runs: row.get::<_, i64>(1)? as u64,
+                    ended_runs: row.get::<_, i64>(2)? as u64,
+                    avg_ended_rounds: row.get(3)?,
+                    avg_ended_hours: row.get(4)?,
+                    max_rounds: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Store script findings as `(ip, port, kind, value)`. Repeated findings
+    /// keep their `first_seen` and move `last_seen` and `scan_round` forward.
+    pub fn save_script_findings(
+        &self,
+        findings: &[(String, u16, String, String)],
+        scan_round: i64,
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = Utc::now().to_rfc3339();
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO script_findings (ip_address, port, kind, value, scan_round, first_seen, last_seen)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+                 ON CONFLICT(ip_address, port, kind, value) DO UPDATE SET
+                    scan_round = excluded.scan_round,
+                    last_seen = excluded.last_seen",
+            )?;
+            for (ip, port, kind, value) in findings {
+                stmt.execute(params![ip, port, kind, value, scan_round, now])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Most recently seen findings first, optionally filtered by IP and kind.
+    pub fn get_script_findings(
+        &self,
+        ip: Option<&str>,
+        kind: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ScriptFinding>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT ip_address, port, kind, value, scan_round, first_seen, last_seen
+             FROM script_findings
+             WHERE (?1 IS NULL OR ip_address = ?1) AND (?2 IS NULL OR kind = ?2)
+             ORDER BY last_seen DESC, ip_address, port
+             LIMIT ?3",
+        )?;
+        let rows = stmt
+            .query_map(params![ip, kind, limit as i64], |row| {
+                Ok(ScriptFinding {
+                    ip_address: row.get(0)?,
+                    port: row.get(1)?,
+                    kind: row.get(2)?,
+                    value: row.get(3)?,
+                    scan_round: row.get(4)?,
+                    first_seen: row.get(5)?,
+                    last_seen: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Banners, HTTP titles, server headers, body previews and TLS names
+    /// matching every word of `query`, best match first. See [`SearchHit`]
+    /// for how matches are marked.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let Some(query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT ip_address, port, source, hostname,
+                    snippet(search_index, -1, char(2), char(3), '…', 16)
+             FROM search_index
+             WHERE search_index MATCH ?1
+             ORDER BY rank
+             LIMIT ?2",
+        )?;
+        let hits = stmt
+            .query_map(params![query, limit as i64], |row| {
+                Ok(SearchHit {
+                    ip_address: row.get(0)?,
+                    port: row.get(1)?,
+                    source: row.get(2)?,
+                    hostname: row.get(3)?,
+                    snippet: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(hits)
+    }
+
+    /// Hostnames matching `pattern` (`*` matches any run of characters, case
+    /// does not matter), with the address each belongs to: reverse DNS names
+    /// from geo enrichment and the names hostname targets resolved from.
+    /// Patterns that start with a literal part use the hostname indexes.
+    pub fn search_hostnames(&self, pattern: &str, limit: usize) -> Result<Vec<HostnameHit>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT h.hostname, h.ip_address, h.source,
+                    (SELECT COUNT(*) FROM open_ports_detail o
+                     WHERE o.ip_address = h.ip_address AND o.closed_at IS NULL)
+             FROM (SELECT reverse_dns AS hostname, ip_address, 'reverse_dns' AS source
+                   FROM ip_details WHERE reverse_dns GLOB ?1
+                   UNION ALL
+                   SELECT hostname, ip_address, 'target'
+                   FROM target_hostnames WHERE hostname GLOB ?1) h
+             ORDER BY h.hostname, h.ip_address, h.source
+             LIMIT ?2",
+        )?;
+        let hits = stmt
+            .query_map(params![hostname_glob(pattern), limit as i64], |row| {
+                Ok(HostnameHit {
+                    hostname: row.get(0)?,
+                    ip_address: row.get(1)?,
+                    source: row.get(2)?,
+                    open_ports: row.get::<_, i64>(3)? as usize,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(hits)
+    }
+
+    /// Store greetings read during a connect scan as `(ip, port, banner)`,
+    /// replacing any earlier one for the same port.
+    pub fn save_port_banners(&self, banners: &[(String, u16, String)]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = Utc::now().to_rfc3339();
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO port_banners (ip_address, port, banner, grabbed_at)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(ip_address, port) DO UPDATE SET
+                    banner = excluded.banner,
+                    grabbed_at = excluded.grabbed_at",
+            )?;
+            for (ip, port, banner) in banners {
+                stmt.execute(params![ip, port, banner, now])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Greetings grabbed on `ip`, by port.
+    pub fn get_port_banners(&self, ip: &str) -> Result<HashMap<u16, String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare_cached("SELECT port, banner FROM port_banners WHERE ip_address = ?1")?;
+        let banners = stmt
+            .query_map([ip], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(banners)
+    }
+
+    // ── Service Info CRUD ──────────────────────────────────────────
+
+    #[allow(dead_code)]
+    pub fn save_service_info(&self, info: &ServiceInfo) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO service_info (ip_address, port, service_name, protocol, banner, http_title, http_server, http_body_preview, tls_subject, tls_issuer, tls_not_before, tls_not_after, tls_version, service_version, http_body_hash, http_security_headers, rtt_ms, os_guess, detected_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)
+             ON CONFLICT(ip_address, port)
+             DO UPDATE SET service_name=?3, protocol=?4, banner=?5, http_title=?6, http_server=?7, http_body_preview=?8, tls_subject=?9, tls_issuer=?10, tls_not_before=?11, tls_not_after=?12, tls_version=?13, service_version=?14, http_body_hash=?15, http_security_headers=?16, rtt_ms=?17, os_guess=?18, detected_at=?19",
+            params![
+                info.ip, info.port, info.service_name, info.protocol,
+                info.banner, info.http_title, info.http_server,
+                info.http_body_preview, info.tls_subject, info.tls_issuer,
+                info.tls_not_before, info.tls_not_after, info.tls_version,
+                info.service_version, info.http_body_hash, info.http_security_headers, info.rtt_ms, info.os_guess,
+                info.detected_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn save_service_info_batch(&self, infos: &[ServiceInfo]) -> Result<()> {
+        if infos.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO service_info (ip_address, port, service_name, protocol, banner, http_title, http_server, http_body_preview, tls_subject, tls_issuer, tls_not_before, tls_not_after, tls_version, service_version, http_body_hash, http_security_headers, rtt_ms, os_guess, detected_at)\n                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)\n                 ON CONFLICT(ip_address, port)\n                 DO UPDATE SET service_name=?3, protocol=?4, banner=?5, http_title=?6, http_server=?7, http_body_preview=?8, tls_subject=?9, tls_issuer=?10, tls_not_before=?11, tls_not_after=?12, tls_version=?13, service_version=?14, http_body_hash=?15, http_security_headers=?16, rtt_ms=?17, os_guess=?18, detected_at=?19"
+            )?;
+            for info in infos {
+                stmt.execute(params![
+                    info.ip,
+                    info.port,
+                    info.service_name,
+                    info.protocol,
+                    info.banner,
+                    info.http_title,
+                    info.http_server,
+                    info.http_body_preview,
+                    info.tls_subject,
+                    info.tls_issuer,
+                    info.tls_not_before,
+                    info.tls_not_after,
+                    info.tls_version,
+                    info.service_version,
+                    info.http_body_hash,
+                    info.http_security_headers,
+                    info.rtt_ms,
+                    info.os_guess,
+                    info.detected_at,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Store per-hostname results, replacing earlier ones for the same IP,
+    /// port and hostname.
+    pub fn save_service_vhosts(&self, vhosts: &[VirtualHostInfo]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO service_vhosts (ip_address, port, hostname, http_status, http_title, http_server, tls_subject, tls_issuer, tls_version, detected_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                 ON CONFLICT(ip_address, port, hostname) DO UPDATE SET
+                    http_status = excluded.http_status, http_title = excluded.http_title,
+                    http_server = excluded.http_server, tls_subject = excluded.tls_subject,
+                    tls_issuer = excluded.tls_issuer, tls_version = excluded.tls_version,
+                    detected_at = excluded.detected_at",
+            )?;
+            for v in vhosts {
+                stmt.execute(params![
+                    v.ip,
+                    v.port,
+                    v.hostname,
+                    v.http_status,
+                    v.http_title,
+                    v.http_server,
+                    v.tls_subject,
+                    v.tls_issuer,
+                    v.tls_version,
+                    v.detected_at
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Record that `hostname` resolved to `ips` in `round`. Earlier
+    /// addresses are kept, so results scanned under an address the name no
+    /// longer has still match it.
+    pub fn save_hostname_resolution(
+        &self,
+        hostname: &str,
+        ips: &[IpAddr],
+        round: i64,
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = Utc::now().to_rfc3339();
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO target_hostnames (hostname, ip_address, scan_round, resolved_at)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(hostname, ip_address) DO UPDATE SET
+                    scan_round = excluded.scan_round,
+                    resolved_at = excluded.resolved_at",
+            )?;
+            let hostname = hostname.to_ascii_lowercase();
+            for ip in ips {
+                stmt.execute(params![hostname, ip.to_string(), round, now])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Replace the candidate CVEs of `ip:port` with `cves`; an empty list
+    /// clears them.
+    pub fn replace_port_cves(&self, ip: &str, port: u16, cves: &[CveMatch]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = Utc::now().to_rfc3339();
+        tx.execute(
+            "DELETE FROM port_cves WHERE ip_address = ?1 AND port = ?2",
+            params![ip, port],
+        )?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO port_cves (ip_address, port, cve_id, product, version, cvss, matched_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for cve in cves {
+                stmt.execute(params![
+                    ip,
+                    port,
+                    cve.cve_id,
+                    cve.product,
+                    cve.version,
+                    cve.cvss,
+                    now
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Hosts with active open ports after `after` (in address order) that no
+    /// reputation source has checked since `checked_since`.
+    pub fn get_ips_missing_reputation(
+        &self,
+        after: &str,
+        checked_since: &str,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT ip_address FROM open_ports_detail o
+             WHERE ip_address > ?1 AND closed_at IS NULL
+               AND NOT EXISTS (SELECT 1 FROM ip_reputation r
+                               WHERE r.ip_address = o.ip_address AND r.checked_at >= ?2)
+             ORDER BY ip_address
+             LIMIT ?3",
+        )?;
+        let ips = stmt
+            .query_map(params![after, checked_since, limit], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(ips)
+    }
+
+    pub fn save_ip_reputation_batch(&self, reputations: &[IpReputation]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = Utc::now().to_rfc3339();
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO ip_reputation (ip_address, source, score, classification, scanner, reports, checked_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for rep in reputations {
+                stmt.execute(params![
+                    rep.ip,
+                    rep.source,
+                    rep.score,
+                    rep.classification,
+                    rep.scanner,
+                    rep.reports,
+                    now
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Every source's latest report on `ip`, by source.
+    pub fn get_ip_reputation(&self, ip: &str) -> Result<Vec<IpReputation>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT ip_address, source, score, classification, scanner, reports
+             FROM ip_reputation WHERE ip_address = ?1 ORDER BY source",
+        )?;
+        let reputations = stmt
+            .query_map([ip], |row| {
+                Ok(IpReputation {
+                    ip: row.get(0)?,
+                    source: row.get(1)?,
+                    score: row.get(2)?,
+                    classification: row.get(3)?,
+                    scanner: row.get(4)?,
+                    reports: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(reputations)
+    }
+
+    /// Per-hostname results for `ip`, by port and hostname.
+    pub fn get_service_vhosts(&self, ip: &str) -> Result<Vec<VirtualHostInfo>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT ip_address, port, hostname, http_status, http_title, http_server, tls_subject, tls_issuer, tls_version, detected_at
+             FROM service_vhosts WHERE ip_address = ?1 ORDER BY port, hostname",
+        )?;
+        let rows = stmt
+            .query_map([ip], |row| {
+                Ok(VirtualHostInfo {
+                    ip: row.get(0)?,
+                    port: row.get(1)?,
+                    hostname: row.get(2)?,
+                    http_status: row.get(3)?,
+                    http_title: row.get(4)?,
+                    http_server: row.get(5)?,
+                    tls_subject: row.get(6)?,
+                    tls_issuer: row.get(7)?,
+                    tls_version: row.get(8)?,
+                    detected_at: row.get(9)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    pub fn get_service_info_by_ip(&self, ip: &str) -> Result<Vec<ServiceInfo>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT ip_address, port, service_name, protocol, banner, http_title, http_server, http_body_preview, tls_subject, tls_issuer, tls_not_before, tls_not_after, tls_version, service_version, http_body_hash, http_security_headers, rtt_ms, os_guess, detected_at
+             FROM service_info WHERE ip_address = ?1 ORDER BY port",
+        )?;
+        let results = stmt
+            .query_map([ip], |row| {
+                Ok(ServiceInfo {
+                    ip: row.get(0)?,
+                    port: row.get(1)?,
+                    service_name: row.get(2)?,
+                    protocol: row.get(3)?,
+                    banner: row.get(4)?,
+                    http_title: row.get(5)?,
+                    http_server: row.get(6)?,
+                    http_body_preview: row.get(7)?,
+                    tls_subject: row.get(8)?,
+                    tls_issuer: row.get(9)?,
+                    tls_not_before: row.get(10)?,
+                    tls_not_after: row.get(11)?,
+                    tls_version: row.get(12)?,
+                    service_version: row.get(13)?,
+                    http_body_hash: row.get(14)?,
+                    http_security_headers: row.get(15)?,
+                    rtt_ms: row.get(16)?,
+                    os_guess: row.get(17)?,
+                    detected_at: row.get(18)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(results)
+    }
+
+    pub fn mark_service_probe_attempts(&self, ips: &[String]) -> Result<()> {
+        if ips.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = Utc::now().to_rfc3339();
+        {
+            let mut stmt = tx.prepare("INSERT INTO service_probe_state (ip_address, last_probe) VALUES (?1, ?2) ON CONFLICT(ip_address) DO UPDATE SET last_probe = ?2")?;
+            for ip in ips {
+                stmt.execute(params![ip, now])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_ips_missing_service_probe(&self, limit: usize) -> Result<Vec<(String, Vec<u16>)>> {
+        let conn = self.conn.lock().unwrap();
+        let retry_before = (Utc::now() - chrono::Duration::hours(1)).to_rfc3339();
+        let mut stmt = conn.prepare(
+            "SELECT o.ip_address, GROUP_CONCAT(o.port) as ports
+             FROM open_ports_detail o
+             WHERE o.ip_address NOT IN (SELECT DISTINCT ip_address FROM service_info)
+               AND (NOT EXISTS (SELECT 1 FROM service_probe_state s WHERE s.ip_address = o.ip_address)
+                    OR EXISTS (SELECT 1 FROM service_probe_state s WHERE s.ip_address = o.ip_address AND s.last_probe < ?2))
+             GROUP BY o.ip_address
+             LIMIT ?1",
+        )?;
+        let results = stmt
+            .query_map(params![limit as i64, retry_before], |row| {
+                let ip: String = row.get(0)?;
+                let ports_str: String = row.get(1)?;
+                let ports: Vec<u16> = ports_str
+                    .split(',')
+                    .filter_map(|s| s.parse().ok())
+                    .collect();
+                Ok((ip, ports))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(results)
+    }
+
+    pub fn get_all_ip_service_summaries(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<IpServiceSummary>> {
+        // Release the connection mutex before loading each IP's services.
+        // get_service_info_by_ip acquires the same non-reentrant mutex.
+        let ips: Vec<String> = {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare(
+                "SELECT ip_address FROM (SELECT DISTINCT ip_address FROM service_info) LIMIT ?1 OFFSET ?2",
+            )?;
+            let rows = stmt
+                .query_map([limit as i64, offset as i64], |row| row.get(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            rows
+        };
+
+        let mut summaries = Vec::new();
+        for ip in ips {
+            let services = self.get_service_info_by_ip(&ip)?;
+            let category = IpServiceSummary::categorize(&services);
+            let (risk_score, risk_reasons) = IpServiceSummary::assess_risk(&services);
+            summaries.push(IpServiceSummary {
+                ip: ip.clone(),
+                services,
+                ip_type: None,
+                category,
+                risk_score,
+                risk_reasons,
+            });
+        }
+        Ok(summaries)
+    }
+
+    /// Compare two persisted IPv4 bitmap rounds and return bounded port changes.
+    pub fn get_bitmap_changes(
+        &self,
+        round: i64,
+        port: u16,
+        limit: usize,
+    ) -> Result<Vec<PortChange>> {
+        self.get_bitmap_diff(round - 1, round, port, limit)
+    }
+
+    /// Changes on `port` between any two stored rounds; a round without a
+    /// bitmap for the port counts as all closed. Empty when `to` has none.
+    pub fn get_bitmap_diff(
+        &self,
+        from: i64,
+        to: i64,
+        port: u16,
+        limit: usize,
+    ) -> Result<Vec<PortChange>> {
+        let conn = self.conn.lock().unwrap();
+        let Some(current) = self.load_ipv4_bitmap(&conn, to, port)? else {
+            return Ok(Vec::new());
+        };
+        let previous = self
+            .load_ipv4_bitmap(&conn, from, port)?
+            .unwrap_or_default();
+        Ok(current
+            .changed_indices(&previous, limit)
+            .into_iter()
+            .map(|index| PortChange {
+                ip_address: index_to_ipv4(index),
+                port,
+                round: to,
+                is_open: current.get(index),
+            })
+            .collect())
+    }
+
+    /// [`Self::get_bitmap_changes`] for every port with a bitmap in `round`,
+    /// stopping once `limit` changes have been collected.
+    pub fn get_round_changes(&self, round: i64, limit: usize) -> Result<Vec<PortChange>> {
+        let mut changes = Vec::new();
+        for port in self.get_bitmap_ports(round, round)? {
+            if changes.len() >= limit {
+                break;
+            }
+            changes.extend(self.get_bitmap_changes(round, port, limit - changes.len())?);
+        }
+        Ok(changes)
+    }
+
+    /// Ports with an IPv4 bitmap in round `a` or `b`, ascending.
+    fn get_bitmap_ports(&self, a: i64, b: i64) -> Result<Vec<u16>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT port FROM port_bitmaps
+             WHERE ip_type = 'IPv4' AND scan_round IN (?1, ?2)
+             ORDER BY port",
+        )?;
+        let ports = stmt
+            .query_map([a, b], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ports)
+    }
+
+    /// Mark every IPv4 address from `start` to `end` (indices, inclusive)
+    /// scanned in `scan_round`, for slices whose closed results were never
+    /// sent, such as completed cluster leases.
+    pub fn record_scanned_range(&self, scan_round: i64, start: u32, end: u32) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        for block in (start >> 16)..=(end >> 16) {
+            let first = start.max(block << 16) as u16;
+            let last = end.min((block << 16) | 0xFFFF) as u16;
+            mark_coverage(&transaction, scan_round, block, |bits| {
+                for host in first..=last {
+                    bits[usize::from(host >> 3)] |= 1 << (host & 7);
+                }
+            })?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Addresses scanned and hosts with an open port in `round`, per IPv4
+    /// prefix of `prefix_len` (1 to 16) bits. Open hosts are the union of
+    /// the round's port bitmaps.
+    pub fn get_coverage(&self, round: i64, prefix_len: u8) -> Result<Coverage> {
+        if !(1..=16).contains(&prefix_len) {
+            return Err(anyhow::anyhow!("prefix length must be between 1 and 16"));
+        }
+        let mut prefixes: BTreeMap<u32, PrefixCoverage> = BTreeMap::new();
+        {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt =
+                conn.prepare("SELECT block, scanned FROM scan_coverage WHERE scan_round = ?1")?;
+            let rows = stmt.query_map([round], |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, u64>(1)?))
+            })?;
+            for row in rows {
+                let (block, scanned) = row?;
+                prefix_coverage(&mut prefixes, block >> (16 - prefix_len), prefix_len).scanned +=
+                    scanned;
+            }
+        }
+        let mut hosts = PortBitmap::new();
+        for port in self.get_bitmap_ports(round, round)? {
+            let conn = self.conn.lock().unwrap();
+            if let Some(bitmap) = self.load_ipv4_bitmap(&conn, round, port)? {
+                hosts.union_with(&bitmap);
+            }
+        }
+        for (key, open) in hosts.count_ones_by_prefix(prefix_len) {
+            prefix_coverage(&mut prefixes, key, prefix_len).open_hosts = open as u64;
+        }
+        let prefixes: Vec<PrefixCoverage> = prefixes.into_values().collect();
+        Ok(Coverage {
+            round,
+            prefix_len,
+            scanned: prefixes.iter().map(|prefix| prefix.scanned).sum(),
+            open_hosts: prefixes.iter().map(|prefix| prefix.open_hosts).sum(),
+            prefixes,
+        })
+    }
+
+    /// Rounds that still have IPv4 bitmaps, newest first.
+    pub fn get_bitmap_rounds(&self) -> Result<Vec<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT scan_round FROM port_bitmaps WHERE ip_type = 'IPv4' ORDER BY scan_round DESC",
+        )?;
+        let rounds = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rounds)
+    }
+
+    /// Full comparison of two stored rounds: per-port counts, up to `limit`
+    /// opened and closed ports each, and hosts with no open port left.
+    pub fn get_round_diff(&self, from: i64, to: i64, limit: usize) -> Result<RoundDiff> {
+        let stored = self.get_bitmap_rounds()?;
+        if let Some(missing) = [from, to].into_iter().find(|r| !stored.contains(r)) {
+            return Err(anyhow::anyhow!(
+                "Round {} has no stored bitmaps (available: {:?}); older rounds are pruned",
+                missing,
+                stored
+            ));
+        }
+
+        let mut diff = RoundDiff {
+            from_round: from,
+            to_round: to,
+            ..Default::default()
+        };
+        let mut hosts_before = PortBitmap::new();
+        let mut hosts_after = PortBitmap::new();
+        for port in self.get_bitmap_ports(from, to)? {
+            let (before, after) = {
+                let conn = self.conn.lock().unwrap();
+                (
+                    self.load_ipv4_bitmap(&conn, from, port)?
+                        .unwrap_or_default(),
+                    self.load_ipv4_bitmap(&conn, to, port)?.unwrap_or_default(),
+                )
+            };
+            let (opened, closed) = after.diff_counts(&before);
+            diff.ports.push(PortDelta {
+                port,
+                open_before: before.count_ones(),
+                open_after: after.count_ones(),
+                opened,
+                closed,
+            });
+            let to_change = |index: u32, is_open: bool| PortChange {
+                ip_address: index_to_ipv4(index),
+                port,
+                round: to,
+                is_open,
+            };
+            let room = limit.saturating_sub(diff.opened.len());
+            diff.opened.extend(
+                before
+                    .cleared_indices(&after, room)
+                    .into_iter()
+                    .map(|index| to_change(index, true)),
+            );
+            let room = limit.saturating_sub(diff.closed.len());
+            diff.closed.extend(
+                after
+                    .cleared_indices(&before, room)
+                    .into_iter()
+                    .map(|index| to_change(index, false)),
+            );
+            hosts_before.union_with(&before);
+            hosts_after.union_with(&after);
+        }
+        diff.hosts_before = hosts_before.count_ones();
+        diff.hosts_after = hosts_after.count_ones();
+        (diff.new_hosts, diff.hosts_gone_dark_count) = hosts_after.diff_counts(&hosts_before);
+        diff.hosts_gone_dark = hosts_after
+            .cleared_indices(&hosts_before, limit)
+            .into_iter()
+            .map(index_to_ipv4)
+            .collect();
+        Ok(diff)
+    }
+
+    /// Split a round into leases; slices already stored for the round are
+    /// kept, so a restarted coordinator resumes where it left off.
+    pub fn create_cluster_leases(&self, round: i64, slices: &[(u32, u32)]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        {
+            let mut stmt = transaction.prepare(
+                "INSERT OR IGNORE INTO cluster_leases (scan_round, start_index, end_index, updated_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            let now = Utc::now().to_rfc3339();
+            for (start, end) in slices {
+                stmt.execute(params![round, start, end, now])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Hand the lowest pending or expired slice of `round` to `worker_id`
+    /// until `expires_at` (Unix seconds).
+    pub fn acquire_cluster_lease(
+        &self,
+        round: i64,
+        worker_id: &str,
+        now: i64,
+        expires_at: i64,
+    ) -> Result<Option<ClusterLease>> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        let id: Option<i64> = transaction
+            .query_row(
+                "SELECT id FROM cluster_leases
+                 WHERE scan_round = ?1
+                   AND (status = 'pending' OR (status = 'leased' AND expires_at <= ?2))
+                 ORDER BY id
+                 LIMIT 1",
+                params![round, now],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        transaction.execute(
+            "UPDATE cluster_leases SET status = 'leased', worker_id = ?2, expires_at = ?3, updated_at = ?4
+             WHERE id = ?1",
+            params![id, worker_id, expires_at, Utc::now().to_rfc3339()],
+        )?;
+        let lease = load_cluster_lease(&transaction, id)?;
+        transaction.commit()?;
+        Ok(lease)
+    }
+
+    /// Extend a lease still held by `worker_id`; `None` once it expired and
+    /// went to another worker, or was completed.
+    pub fn renew_cluster_lease(
+        &self,
+        id: i64,
+        worker_id: &str,
+        expires_at: i64,
+    ) -> Result<Option<ClusterLease>> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE cluster_leases SET expires_at = ?3, updated_at = ?4
+             WHERE id = ?1 AND worker_id = ?2 AND status = 'leased'",
+            params![id, worker_id, expires_at, Utc::now().to_rfc3339()],
+        )?;
+        if updated == 0 {
+            return Ok(None);
+        }
+        load_cluster_lease(&conn, id)
+    }
+
+    /// Mark a lease held by `worker_id` as done; false if it no longer holds it.
+    pub fn complete_cluster_lease(
+        &self,
+        id: i64,
+        worker_id: &str,
+        scanned: u64,
+        open: u64,
+    ) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE cluster_leases SET status = 'done', expires_at = NULL, scanned = ?3, open = ?4, updated_at = ?5
+             WHERE id = ?1 AND worker_id = ?2 AND status = 'leased'",
+            params![
+                id,
+                worker_id,
+                scanned as i64,
+                open as i64,
+                Utc::now().to_rfc3339()
+            ],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Lease counts for `round` plus the slices currently held by workers.
+    pub fn get_cluster_progress(&self, round: i64) -> Result<ClusterProgress> {
+        let conn = self.conn.lock().unwrap();
+        let mut progress = conn.query_row(
+            "SELECT COUNT(*),
+                    COALESCE(SUM(status = 'pending'), 0),
+                    COALESCE(SUM(status = 'leased'), 0),
+                    COALESCE(SUM(status = 'done'), 0),
+                    COALESCE(SUM(scanned), 0),
+                    COALESCE(SUM(open), 0)
+             FROM cluster_leases WHERE scan_round = ?1",
+            [round],
+            |row| {
+                Ok(ClusterProgress {
+                    round,
+                    total: row.get::<_, i64>(0)? as usize,
+                    pending: row.get::<_, i64>(1)? as usize,
+                    leased: row.get::<_, i64>(2)? as usize,
+                    done: row.get::<_, i64>(3)? as usize,
+                    scanned: row.get::<_, i64>(4)? as u64,
+                    open: row.get::<_, i64>(5)? as u64,
+                    active: Vec::new(),
+                })
+            },
+        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM cluster_leases WHERE scan_round = ?1 AND status = 'leased' ORDER BY id",
+            CLUSTER_LEASE_COLUMNS
+        ))?;
+        progress.active = stmt
+            .query_map([round], cluster_lease_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(progress)
+    }
+
+    pub fn count_ips_with_service_info(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(DISTINCT ip_address) FROM service_info",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Fold the database at `path` into this one. Port bitmaps of the same
+    /// port and round are OR-ed, open ports keep the earliest `first_seen`
+    /// and latest `last_seen`, enrichment rows keep the most recent lookup,
+    /// and round counters are summed on the assumption that the sources are
+    /// shards of the same rounds scanned side by side. The source's resume
+    /// progress and cluster leases are not copied.
+    pub fn merge_from(&self, path: &str) -> Result<MergeSummary> {
+        if !std::path::Path::new(path).exists() {
+            return Err(anyhow::anyhow!("Database {} does not exist", path));
+        }
+        // Opening the source runs the migrations, so databases written by
+        // older releases have every column the statements below read.
+        drop(SqliteDB::with_key(path, self.key.as_deref())?);
+
+        let mut conn = self.conn.lock().unwrap();
+        match &self.key {
+            Some(key) => conn.execute(
+                "ATTACH DATABASE ?1 AS src KEY ?2",
+                params![path, key.as_ref()],
+            )?,
+            None => conn.execute("ATTACH DATABASE ?1 AS src", [path])?,
+        };
+        let summary = merge_attached(&mut conn, self.shards);
+        conn.execute("DETACH DATABASE src", [])?;
+        summary
+    }
+
+    /// Load open ports exported from another instance, for `ip-scan
+    /// import`. A port already stored is combined with the imported row the
+    /// way [`SqliteDB::merge_from`] combines them, active IPv4 rows are set
+    /// in their round's port bitmap, and the current round is raised to the
+    /// newest imported one. All rows go in one transaction, so the first
+    /// error leaves the database untouched.
+    pub fn import_results<I>(&self, rows: I) -> Result<ImportSummary>
+    where
+        I: IntoIterator<Item = Result<ImportedResult>>,
+    {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        let mut summary = ImportSummary::default();
+        let mut active: HashMap<(u16, i64), Vec<u32>> = HashMap::new();
+        let mut max_round = 0;
+        {
+            let mut stmt = transaction.prepare(&format!(
+                "INSERT INTO open_ports_detail (ip_address, ip_type, port, scan_round, first_seen, last_seen, closed_at, scan_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                 {}",
+                OPEN_PORT_UPSERT
+            ))?;
+            for row in rows {
+                let row = row?;
+                stmt.execute(params![
+                    row.ip_address,
+                    row.ip_type,
+                    row.port,
+                    row.scan_round,
+                    row.first_seen,
+                    row.last_seen,
+                    row.closed_at,
+                    row.scan_id
+                ])?;
+                if row.closed_at.is_none() {
+                    if let Ok(index) = ipv4_to_index(&row.ip_address) {
+                        active
+                            .entry((row.port, row.scan_round))
+                            .or_default()
+                            .push(index);
+                    }
+                }
+                max_round = max_round.max(row.scan_round);
+                summary.results += 1;
+            }
+        }
+
+        for ((port, round), indexes) in active {
+            let bits: Vec<(u32, bool)> = indexes.into_iter().map(|index| (index, true)).collect();
+            self.write_bits(&transaction, port, round, &bits, &Utc::now().to_rfc3339())?;
+            summary.bitmaps += 1;
+        }
+
+        transaction.execute(
+            "INSERT INTO scan_metadata (key, value, updated_at) VALUES ('current_round', ?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = ?1, updated_at = ?2
+             WHERE CAST(value AS INTEGER) < CAST(?1 AS INTEGER)",
+            params![max_round.max(1).to_string(), Utc::now().to_rfc3339()],
+        )?;
+        transaction.commit()?;
+        Ok(summary)
+    }
+
+    /// Write a copy of this database to `dest` encrypted with `key`, or
+    /// unencrypted when `key` is empty, for `ip-scan db rekey`. `dest` must
+    /// not exist yet.
+    pub fn export_rekeyed(&self, dest: &str, key: &str) -> Result<()> {
+        if std::path::Path::new(dest).exists() {
+            return Err(anyhow::anyhow!("{} already exists", dest));
+        }
+        if cfg!(not(feature = "sqlcipher")) {
+            return Err(anyhow::anyhow!(
+                "Database encryption needs a build with the sqlcipher feature"
+            ));
+        }
+        if self.shards > 1 {
+            return Err(anyhow::anyhow!(
+                "Rekeying a database with sharded bitmaps is not supported"
+            ));
+        }
+        let conn = self.conn.lock().unwrap();
+        conn.execute("ATTACH DATABASE ?1 AS rekeyed KEY ?2", params![dest, key])?;
+        let exported = conn
+            .query_row("SELECT sqlcipher_export('rekeyed')", [], |_| Ok(()))
+            .map_err(anyhow::Error::from);
+        conn.execute("DETACH DATABASE rekeyed", [])?;
+        exported
+    }
+}
+
+/// `shardN` schema holding the bitmaps of `port`, or `main` unsharded.
+fn bitmap_schema(shards: usize, port: u16) -> String {
+    if shards > 1 {
+        format!("shard{}", port as usize % shards)
+    } else {
+        "main".to_string()
+    }
+}
+
+/// Bitmap shard count recorded in the `scan_metadata` of `schema`.
+fn recorded_shards(conn: &Connection, schema: &str) -> Result<usize> {
+    let value: Option<String> = conn
+        .query_row(
+            &format!(
+                "SELECT value FROM {}.scan_metadata WHERE key = 'bitmap_shards'",
+                schema
+            ),
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(value.and_then(|value| value.parse().ok()).unwrap_or(1))
+}
+
+/// Attach the `<db_path>-shardN` bitmap files as `shardN` and put a temp
+/// `port_bitmaps` view over them in front of the main table, so queries
+/// read every shard while writes name their shard. `create` sets up the
+/// files' schema and WAL.
+fn attach_shards(
+    conn: &Connection,
+    db_path: &str,
+    shards: usize,
+    key: Option<&str>,
+    create: bool,
+) -> Result<()> {
+    for shard in 0..shards {
+        let path = format!("{}-shard{}", db_path, shard);
+        let schema = format!("shard{}", shard);
+        match key {
+            Some(key) => conn.execute(
+                &format!("ATTACH DATABASE ?1 AS {} KEY ?2", schema),
+                params![path, key],
+            )?,
+            None => conn.execute(&format!("ATTACH DATABASE ?1 AS {}", schema), [&path])?,
+        };
+        if create {
+            conn.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {0}.port_bitmaps (
+                    port INTEGER NOT NULL,
+                    ip_type TEXT NOT NULL,
+                    scan_round INTEGER NOT NULL,
+                    bitmap BLOB NOT NULL,
+                    open_count INTEGER DEFAULT 0,
+                    last_updated TEXT NOT NULL,
+                    PRIMARY KEY (port, ip_type, scan_round)
+                );
+                CREATE INDEX IF NOT EXISTS {0}.idx_port_round ON port_bitmaps(port, scan_round);
+                PRAGMA {0}.journal_mode = WAL;
+                PRAGMA {0}.synchronous = NORMAL;
+                PRAGMA {0}.journal_size_limit = 67108864;",
+                schema
+            ))?;
+        }
+    }
+    let union = (0..shards)
+        .map(|shard| format!("SELECT * FROM shard{}.port_bitmaps", shard))
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ");
+    conn.execute(&format!("CREATE TEMP VIEW port_bitmaps AS {}", union), [])?;
+    Ok(())
+}
+
+/// File of the main database, `None` when it is in memory.
+fn database_path(conn: &Connection) -> Result<Option<String>> {
+    let path: String = conn.query_row("PRAGMA database_list", [], |row| row.get(2))?;
+    Ok((!path.is_empty()).then_some(path))
+}
+
+fn file_size(path: &str) -> Option<u64> {
+    std::fs::metadata(path).ok().map(|meta| meta.len())
+}
+
+/// Tables whose text is indexed in `search_index`: source name, table, and
+/// the values of the `hostname, title, server, banner, body, tls` columns
+/// with `{r}` standing for the row. Index row IDs are the source row ID
+/// times 4 plus the position here, so triggers can replace a row's entry
+/// without scanning the index.
+const SEARCH_SOURCES: [(&str, &str, &str); 3] = [
+    (
+        "service",
+        "service_info",
+        "NULL, {r}.http_title, COALESCE({r}.http_server, '') || ' ' || COALESCE({r}.service_version, ''),
+         {r}.banner, {r}.http_body_preview, COALESCE({r}.tls_subject, '') || ' ' || COALESCE({r}.tls_issuer, '')",
+    ),
+    ("banner", "port_banners", "NULL, NULL, NULL, {r}.banner, NULL, NULL"),
+    (
+        "vhost",
+        "service_vhosts",
+        "{r}.hostname, {r}.http_title, {r}.http_server, NULL, NULL,
+         COALESCE({r}.tls_subject, '') || ' ' || COALESCE({r}.tls_issuer, '')",
+    ),
+];
+
+/// Create the FTS5 index over banners, HTTP and TLS text and the triggers
+/// that keep it in step with its source tables, filling it from existing
+/// rows the first time.
+fn create_search_index(conn: &Connection) -> Result<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'search_index')",
+        [],
+        |row| row.get(0),
+    )?;
+    conn.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS search_index USING fts5(
+            ip_address UNINDEXED, port UNINDEXED, source UNINDEXED, hostname UNINDEXED,
+            title, server, banner, body, tls
+        )",
+        [],
+    )?;
+    let columns = "rowid, ip_address, port, source, hostname, title, server, banner, body, tls";
+    for (n, (source, table, values)) in SEARCH_SOURCES.iter().enumerate() {
+        let entry = |r: &str| {
+            format!(
+                "{r}.rowid * 4 + {n}, {r}.ip_address, {r}.port, '{source}', {}",
+                values.replace("{r}", r)
+            )
+        };
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER IF NOT EXISTS {table}_search_insert AFTER INSERT ON {table} BEGIN
+                INSERT INTO search_index ({columns}) VALUES ({new});
+             END;
+             CREATE TRIGGER IF NOT EXISTS {table}_search_update AFTER UPDATE ON {table} BEGIN
+                DELETE FROM search_index WHERE rowid = old.rowid * 4 + {n};
+                INSERT INTO search_index ({columns}) VALUES ({new});
+             END;
+             CREATE TRIGGER IF NOT EXISTS {table}_search_delete AFTER DELETE ON {table} BEGIN
+                DELETE FROM search_index WHERE rowid = old.rowid * 4 + {n};
+             END;",
+            new = entry("new"),
+        ))?;
+        if !exists {
+            conn.execute(
+                &format!(
+                    "INSERT INTO search_index ({columns}) SELECT {} FROM {table}",
+                    entry(table)
+                ),
+                [],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// An FTS5 query matching every word of `text`, each taken literally (so
+/// `nginx/1.18` or `"` are not query syntax); a trailing `*` makes a word
+/// a prefix. `None` when there are no words.
+fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .filter_map(|word| {
+            let (word, prefix) = match word.strip_suffix('*') {
+                Some(stem) => (stem, "*"),
+                None => (word, ""),
+            };
+            (!word.is_empty()).then(|| format!("\"{}\"{}", word.replace('"', "\"\""), prefix))
+        })
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+#[cfg(feature = "sqlcipher")]
+fn apply_key(conn: &Connection, key: &str) -> Result<()> {
+    conn.pragma_update(None, "key", key)?;
+    // SQLCipher only reads the header, and so notices a wrong key, on the
+    // first query.
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+        .map_err(|_| {
+            anyhow::anyhow!("Cannot open the database: wrong key, or the file is not encrypted")
+        })
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+fn apply_key(_conn: &Connection, _key: &str) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "--db-key needs a build with the sqlcipher feature (cargo build --features sqlcipher)"
+    ))
+}
+
+/// Open ports written per `open_ports_detail` INSERT in
+/// [`SqliteDB::bulk_update_port_status`]; one bound parameter each, well
+/// under SQLite's limit.
+const DETAIL_INSERT_ROWS: usize = 500;
+
+/// Insert or refresh the detail rows of `ips`, all open on `port`, in one
+/// multi-row statement.
+fn upsert_open_details(
+    conn: &Connection,
+    ip_type: &str,
+    port: u16,
+    scan_round: i64,
+    timestamp: &str,
+    scan_id: Option<&str>,
+    ips: &[String],
+) -> Result<()> {
+    // ?1-?5 are shared by every row; the IPs follow from ?6.
+    let rows: Vec<String> = (0..ips.len())
+        .map(|i| format!("(?{}, ?5, ?1, ?2, ?3, ?3, ?4)", i + 6))
+        .collect();
+    let sql = format!(
+        "INSERT INTO open_ports_detail (ip_address, ip_type, port, scan_round, first_seen, last_seen, scan_id)
+         VALUES {}
+         ON CONFLICT(ip_address, port)
+         DO UPDATE SET scan_round = excluded.scan_round, last_seen = excluded.last_seen,
+                       closed_at = NULL, scan_id = excluded.scan_id",
+        rows.join(", ")
+    );
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let mut values: Vec<&dyn rusqlite::ToSql> =
+        vec![&port, &scan_round, &timestamp, &scan_id, &ip_type];
+    values.extend(ips.iter().map(|ip| ip as &dyn rusqlite::ToSql));
+    stmt.execute(values.as_slice())?;
+    Ok(())
+}
+
+/// Conflict clause combining a merged or imported open port with the stored
+/// one: the widest first/last seen window, the newest round and its scan,
+/// and gone only when both sides are.
+const OPEN_PORT_UPSERT: &str = "ON CONFLICT(ip_address, port) DO UPDATE SET
+    scan_id = CASE WHEN excluded.scan_round > scan_round THEN excluded.scan_id ELSE scan_id END,
+    scan_round = MAX(scan_round, excluded.scan_round),
+    first_seen = MIN(first_seen, excluded.first_seen),
+    last_seen = MAX(last_seen, excluded.last_seen),
+    closed_at = CASE WHEN closed_at IS NULL OR excluded.closed_at IS NULL THEN NULL
+        ELSE MAX(closed_at, excluded.closed_at) END";
+
+fn merge_attached(conn: &mut Connection, shards: usize) -> Result<MergeSummary> {
+    let transaction = conn.transaction()?;
+    let mut summary = MergeSummary::default();
+    if recorded_shards(&transaction, "src")? > 1 {
+        return Err(anyhow::anyhow!(
+            "Merging a database with sharded bitmaps is not supported"
+        ));
+    }
+
+    let keys = {
+        let mut stmt =
+            transaction.prepare("SELECT port, ip_type, scan_round FROM src.port_bitmaps")?;
+        let keys = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u16>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        keys
+    };
+    // One bitmap pair in memory at a time; a full-range port is 512 MiB.
+    for (port, ip_type, round) in keys {
+        let load = |schema: &str| -> Result<PortBitmap> {
+            let blob: Option<Vec<u8>> = transaction
+                .query_row(
+                    &format!(
+                        "SELECT bitmap FROM {}.port_bitmaps WHERE port = ?1 AND ip_type = ?2 AND scan_round = ?3",
+                        schema
+                    ),
+                    params![port, ip_type, round],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            match blob {
+                Some(blob) if blob.is_empty() => Err(anyhow::anyhow!(
+                    "Bitmap of port {} in round {} is in an mmap bitmap store; only bitmaps stored in SQLite can be merged",
+                    port,
+                    round
+                )),
+                Some(blob) => PortBitmap::from_blob(&blob),
+                None => Ok(PortBitmap::new()),
+            }
+        };
+        let schema = bitmap_schema(shards, port);
+        let mut bitmap = load(&schema)?;
+        bitmap.union_with(&load("src")?);
+        transaction.execute(
+            &format!(
+                "INSERT INTO {}.port_bitmaps (port, ip_type, scan_round, bitmap, open_count, last_updated)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT(port, ip_type, scan_round)
+                 DO UPDATE SET bitmap = ?4, open_count = ?5, last_updated = ?6",
+                schema
+            ),
+            params![
+                port,
+                ip_type,
+                round,
+                bitmap.to_blob()?,
+                bitmap.count_ones() as i64,
+                Utc::now().to_rfc3339()
+            ],
+        )?;
+        summary.bitmaps += 1;
+    }
+
+    // `WHERE true` keeps SQLite from parsing ON CONFLICT as a join clause.
+    summary.results = transaction.execute(
+        &format!(
+            "INSERT INTO open_ports_detail (ip_address, ip_type, port, scan_round, first_seen, last_seen, closed_at, scan_id)
+             SELECT ip_address, ip_type, port, scan_round, first_seen, last_seen, closed_at, scan_id
+             FROM src.open_ports_detail WHERE true
+             {}",
+            OPEN_PORT_UPSERT
+        ),
+        [],
+    )?;
+
+    transaction.execute(
+        "INSERT INTO ip_details (ip_address, country, region, city, isp, asn, reverse_dns, abuse_email, source, updated_at)
+         SELECT ip_address, country, region, city, isp, asn, reverse_dns, abuse_email, source, updated_at
+         FROM src.ip_details WHERE true
+         ON CONFLICT(ip_address) DO UPDATE SET
+             country = excluded.country, region = excluded.region, city = excluded.city,
+             isp = excluded.isp, asn = excluded.asn, reverse_dns = excluded.reverse_dns,
+             abuse_email = excluded.abuse_email, source = excluded.source,
+             updated_at = excluded.updated_at
+         WHERE excluded.updated_at > ip_details.updated_at",
+        [],
+    )?;
+
+    transaction.execute(
+        "INSERT INTO service_info (ip_address, port, service_name, protocol, banner, http_title, http_server, http_body_preview, tls_subject, tls_issuer, tls_not_before, tls_not_after, tls_version, service_version, http_body_hash, http_security_headers, rtt_ms, os_guess, detected_at)
+         SELECT ip_address, port, service_name, protocol, banner, http_title, http_server, http_body_preview, tls_subject, tls_issuer, tls_not_before, tls_not_after, tls_version, service_version, http_body_hash, http_security_headers, rtt_ms, os_guess, detected_at
+         FROM src.service_info WHERE true
+         ON CONFLICT(ip_address, port) DO UPDATE SET
+             service_name = excluded.service_name, protocol = excluded.protocol,
+             banner = excluded.banner, http_title = excluded.http_title,
+             http_server = excluded.http_server, http_body_preview = excluded.http_body_preview,
+             tls_subject = excluded.tls_subject, tls_issuer = excluded.tls_issuer,
+             tls_not_before = excluded.tls_not_before, tls_not_after = excluded.tls_not_after,
+             tls_version = excluded.tls_version, service_version = excluded.service_version,
+             http_body_hash = excluded.http_body_hash,
+             http_security_headers = excluded.http_security_headers,
+             rtt_ms = excluded.rtt_ms, os_guess = excluded.os_guess,
+             detected_at = excluded.detected_at
+         WHERE excluded.detected_at > service_info.detected_at",
+        [],
+    )?;
+
+    transaction.execute(
+        "INSERT INTO service_probe_state (ip_address, last_probe)
+         SELECT ip_address, last_probe FROM src.service_probe_state WHERE true
+         ON CONFLICT(ip_address) DO UPDATE SET last_probe = MAX(last_probe, excluded.last_probe)",
+        [],
+    )?;
+
+    transaction.execute(
+        "INSERT INTO script_findings (ip_address, port, kind, value, scan_round, first_seen, last_seen)
+         SELECT ip_address, port, kind, value, scan_round, first_seen, last_seen
+         FROM src.script_findings WHERE true
+         ON CONFLICT(ip_address, port, kind, value) DO UPDATE SET
+             scan_round = MAX(scan_round, excluded.scan_round),
+             first_seen = MIN(first_seen, excluded.first_seen),
+             last_seen = MAX(last_seen, excluded.last_seen)",
+        [],
+    )?;
+
+    // Runs starting in the same round are one run; others are kept as they
+    // are, so runs observed by different nodes may overlap.
+    transaction.execute(
+        "INSERT INTO port_history (ip_address, port, start_round, end_round, first_seen, last_seen)
+         SELECT ip_address, port, start_round, end_round, first_seen, last_seen
+         FROM src.port_history WHERE true
+         ON CONFLICT(ip_address, port, start_round) DO UPDATE SET
+             end_round = MAX(end_round, excluded.end_round),
+             first_seen = MIN(first_seen, excluded.first_seen),
+             last_seen = MAX(last_seen, excluded.last_seen)",
+        [],
+    )?;
+
+    transaction.execute(
+        "INSERT INTO service_vhosts (ip_address, port, hostname, http_status, http_title, http_server, tls_subject, tls_issuer, tls_version, detected_at)
+         SELECT ip_address, port, hostname, http_status, http_title, http_server, tls_subject, tls_issuer, tls_version, detected_at
+         FROM src.service_vhosts WHERE true
+         ON CONFLICT(ip_address, port, hostname) DO UPDATE SET
+             http_status = excluded.http_status, http_title = excluded.http_title,
+             http_server = excluded.http_server, tls_subject = excluded.tls_subject,
+             tls_issuer = excluded.tls_issuer, tls_version = excluded.tls_version,
+             detected_at = excluded.detected_at
+         WHERE excluded.detected_at > service_vhosts.detected_at",
+        [],
+    )?;
+
+    transaction.execute(
+        "INSERT INTO port_cves (ip_address, port, cve_id, product, version, cvss, matched_at)
+         SELECT ip_address, port, cve_id, product, version, cvss, matched_at FROM src.port_cves WHERE true
+         ON CONFLICT(ip_address, port, cve_id) DO UPDATE SET
+             product = excluded.product, version = excluded.version,
+             cvss = excluded.cvss, matched_at = excluded.matched_at
+         WHERE excluded.matched_at > port_cves.matched_at",
+        [],
+    )?;
+
+    transaction.execute(
+        "INSERT INTO ip_reputation (ip_address, source, score, classification, scanner, reports, checked_at)
+         SELECT ip_address, source, score, classification, scanner, reports, checked_at FROM src.ip_reputation WHERE true
+         ON CONFLICT(ip_address, source) DO UPDATE SET
+             score = excluded.score, classification = excluded.classification,
+             scanner = excluded.scanner, reports = excluded.reports,
+             checked_at = excluded.checked_at
+         WHERE excluded.checked_at > ip_reputation.checked_at",
+        [],
+    )?;
+
+    transaction.execute(
+        "INSERT INTO target_hostnames (hostname, ip_address, scan_round, resolved_at)
+         SELECT hostname, ip_address, scan_round, resolved_at FROM src.target_hostnames WHERE true
+         ON CONFLICT(hostname, ip_address) DO UPDATE SET
+             scan_round = excluded.scan_round, resolved_at = excluded.resolved_at
+         WHERE excluded.resolved_at > target_hostnames.resolved_at",
+        [],
+    )?;
+
+    transaction.execute(
+        "INSERT INTO port_banners (ip_address, port, banner, grabbed_at)
+         SELECT ip_address, port, banner, grabbed_at FROM src.port_banners WHERE true
+         ON CONFLICT(ip_address, port) DO UPDATE SET
+             banner = excluded.banner, grabbed_at = excluded.grabbed_at
+         WHERE excluded.grabbed_at > port_banners.grabbed_at",
+        [],
+    )?;
+
+    // Shards of one round run in parallel, so the round took as long as the
+    // slowest shard rather than the sum of all of them.
+    summary.rounds = transaction.execute(
+        "INSERT INTO round_metrics (scan_round, scanned, open, errors, retries, duration_secs, avg_rate, finished_at)
+         SELECT scan_round, scanned, open, errors, retries, duration_secs, avg_rate, finished_at
+         FROM src.round_metrics WHERE true
+         ON CONFLICT(scan_round) DO UPDATE SET
+             scanned = scanned + excluded.scanned,
+             open = open + excluded.open,
+             errors = errors + excluded.errors,
+             retries = retries + excluded.retries,
+             duration_secs = MAX(duration_secs, excluded.duration_secs),
+             avg_rate = CASE WHEN MAX(duration_secs, excluded.duration_secs) > 0
+                 THEN (scanned + excluded.scanned) / MAX(duration_secs, excluded.duration_secs)
+                 ELSE 0 END,
+             finished_at = MAX(finished_at, excluded.finished_at)",
+        [],
+    )?;
+
+    // The merged database continues from the furthest shard.
+    transaction.execute(
+        "INSERT INTO scan_metadata (key, value, updated_at)
+         SELECT key, value, updated_at FROM src.scan_metadata
+         WHERE key IN ('current_round', 'last_scan_time')
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+         WHERE CASE key
+             WHEN 'current_round' THEN CAST(excluded.value AS INTEGER) > CAST(value AS INTEGER)
+             ELSE excluded.value > value END",
+        [],
+    )?;
+
+    transaction.commit()?;
+    Ok(summary)
+}
+
+const CLUSTER_LEASE_COLUMNS: &str =
+    "id, scan_round, start_index, end_index, status, worker_id, expires_at";
+
+fn scan_template_from_row(row: &rusqlite::Row) -> rusqlite::Result<ScanTemplate> {
+    let params: String = row.get(3)?;
+    Ok(ScanTemplate {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        params: serde_json::from_str(&params).unwrap_or_default(),
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+    })
+}
+
+/// Bytes of one `scan_coverage` block: a bit per address of a /16.
+const COVERAGE_BLOCK_BYTES: usize = 1 << 13;
+
+/// Set bits in the coverage bitmap of /16 `block` in `scan_round`. The row
+/// is only rewritten when `mark` set a new bit.
+fn mark_coverage(
+    conn: &Connection,
+    scan_round: i64,
+    block: u32,
+    mark: impl FnOnce(&mut [u8]),
+) -> Result<()> {
+    let stored: Option<(Vec<u8>, i64)> = conn
+        .prepare_cached(
+            "SELECT hosts, scanned FROM scan_coverage WHERE scan_round = ?1 AND block = ?2",
+        )?
+        .query_row(params![scan_round, block], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .optional()?;
+    let (mut hosts, before) = stored.unwrap_or_default();
+    hosts.resize(COVERAGE_BLOCK_BYTES, 0);
+    mark(&mut hosts);
+    let scanned: i64 = hosts.iter().map(|byte| i64::from(byte.count_ones())).sum();
+    if scanned == before {
+        return Ok(());
+    }
+    conn.prepare_cached(
+        "INSERT INTO scan_coverage (scan_round, block, hosts, scanned) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(scan_round, block) DO UPDATE SET hosts = excluded.hosts, scanned = excluded.scanned",
+    )?
+    .execute(params![scan_round, block, hosts, scanned])?;
+    Ok(())
+}
+
+/// The entry of the `prefix_len`-bit prefix `key`, created empty.
+fn prefix_coverage(
+    prefixes: &mut BTreeMap<u32, PrefixCoverage>,
+    key: u32,
+    prefix_len: u8,
+) -> &mut PrefixCoverage {
+    let host_bits = 32 - u32::from(prefix_len);
+    prefixes.entry(key).or_insert_with(|| PrefixCoverage {
+        prefix: format!(
+            "{}/{}",
+            std::net::Ipv4Addr::from(key << host_bits),
+            prefix_len
+        ),
+        addresses: 1u64 << host_bits,
+        scanned: 0,
+        open_hosts: 0,
+    })
+}
+
+fn namespace_from_row(row: &rusqlite::Row) -> rusqlite::Result<Namespace> {
+    let principals: String = row.get(1)?;
+    Ok(Namespace {
+        name: row.get(0)?,
+        principals: serde_json::from_str(&principals).unwrap_or_default(),
+        created_at: row.get(2)?,
+        updated_at: row.get(3)?,
+    })
+}
+
+fn cluster_lease_from_row(row: &rusqlite::Row) -> rusqlite::Result<ClusterLease> {
+    Ok(ClusterLease {
+        id: row.get(0)?,
+        round: row.get(1)?,
+        start_ip: index_to_ipv4(row.get(2)?),
+        end_ip: index_to_ipv4(row.get(3)?),
+        status: row.get(4)?,
+        worker_id: row.get(5)?,
+        expires_at: row.get(6)?,
+    })
+}
+
+fn load_cluster_lease(conn: &Connection, id: i64) -> Result<Option<ClusterLease>> {
+    Ok(conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM cluster_leases WHERE id = ?1",
+                CLUSTER_LEASE_COLUMNS
+            ),
+            [id],
+            cluster_lease_from_row,
+        )
+        .optional()?)
+}
+
+/// Hostnames are compared lowercase and without the root's trailing dot.
+fn normalize_hostname(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// GLOB pattern for a hostname filter, where `*` is the only wildcard;
+/// GLOB's own `?` and `[` are matched literally.
+fn hostname_glob(pattern: &str) -> String {
+    normalize_hostname(pattern.trim())
+        .replace('[', "[[]")
+        .replace('?', "[?]")
+}
+
+/// WHERE terms and parameters for the result filters shared by the results
+/// and export queries. Column names are qualified for the `o`/`i` join.
+#[allow(clippy::too_many_arguments)]
+fn result_filter_clauses(
+    ip_filter: Option<&str>,
+    port_filter: Option<u16>,
+    round_filter: Option<i64>,
+    ip_type_filter: Option<&str>,
+    status_filter: Option<PortStatus>,
+    scan_id_filter: Option<&str>,
+    hostname_filter: Option<&str>,
+    has_cves_filter: Option<bool>,
+    reputation_filter: Option<ReputationFilter>,
+) -> (Vec<&'static str>, Vec<Box<dyn rusqlite::ToSql>>) {
+    let mut where_clauses = Vec::new();
+    let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+    if let Some(ip) = ip_filter {
+        where_clauses.push("o.ip_address LIKE ?");
+        params.push(Box::new(format!("%{}%", ip)));
+    }
+
+    if let Some(port) = port_filter {
+        where_clauses.push("o.port = ?");
+        params.push(Box::new(port));
+    }
+
+    if let Some(round) = round_filter {
+        where_clauses.push("o.scan_round = ?");
+        params.push(Box::new(round));
+    }
+
+    if let Some(ip_type) = ip_type_filter {
+        where_clauses.push("o.ip_type = ?");
+        params.push(Box::new(ip_type.to_string()));
+    }
+
+    match status_filter {
+        Some(PortStatus::Active) => where_clauses.push("o.closed_at IS NULL"),
+        Some(PortStatus::Gone) => where_clauses.push("o.closed_at IS NOT NULL"),
+        None => {}
+    }
+
+    if let Some(scan_id) = scan_id_filter {
+        where_clauses.push("o.scan_id = ?");
+        params.push(Box::new(scan_id.to_string()));
+    }
+
+    if let Some(hostname) = hostname_filter {
+        where_clauses.push(
+            "(o.ip_address IN (SELECT ip_address FROM target_hostnames WHERE hostname GLOB ?)
+              OR o.ip_address IN (SELECT ip_address FROM ip_details WHERE reverse_dns GLOB ?))",
+        );
+        let pattern = hostname_glob(hostname);
+        params.push(Box::new(pattern.clone()));
+        params.push(Box::new(pattern));
+    }
+
+    match has_cves_filter {
+        Some(true) => where_clauses.push(
+            "EXISTS (SELECT 1 FROM port_cves c WHERE c.ip_address = o.ip_address AND c.port = o.port)",
+        ),
+        Some(false) => where_clauses.push(
+            "NOT EXISTS (SELECT 1 FROM port_cves c WHERE c.ip_address = o.ip_address AND c.port = o.port)",
+        ),
+        None => {}
+    }
+
+    match reputation_filter {
+        Some(ReputationFilter::Risky) => {
+            where_clauses.push(
+                "EXISTS (SELECT 1 FROM ip_reputation r WHERE r.ip_address = o.ip_address
+                         AND (r.score >= ? OR r.classification = 'malicious'))",
+            );
+            params.push(Box::new(RISKY_SCORE));
+        }
+        Some(ReputationFilter::Scanner) => where_clauses.push(
+            "EXISTS (SELECT 1 FROM ip_reputation r WHERE r.ip_address = o.ip_address AND r.scanner = 1)",
+        ),
+        Some(ReputationFilter::NotScanner) => where_clauses.push(
+            "NOT EXISTS (SELECT 1 FROM ip_reputation r WHERE r.ip_address = o.ip_address AND r.scanner = 1)",
+        ),
+        None => {}
+    }
+
+    (where_clauses, params)
+}
+
+/// Detailed scan result for API responses
+#[derive(Debug)]
+pub struct ScanResultDetail {
+    pub ip_address: String,
+    pub ip_type: String,
+    pub port: u16,
+    pub scan_round: i64,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub country: Option<String>,
+    pub city: Option<String>,
     pub reverse_dns: Option<String>,
+    pub abuse_email: Option<String>,
+    /// Set once the port went unseen for the configured number of rounds or
+    /// failed a `--rescan-open` check.
+    pub closed_at: Option<String>,
+    /// API scan that last saw the port open; `None` for CLI and worker scans.
+    pub scan_id: Option<String>,
+    /// Candidate CVE IDs matched to the port's detected service
+    /// (`--cve-db`), sorted.
+    pub cves: Vec<String>,
+    /// Highest abuse score any reputation source gave the host.
+    pub reputation_score: Option<u8>,
+    /// A reputation source saw the host scanning the internet.
+    pub known_scanner: bool,
+}
+
+/// The space-separated `group_concat` of a port's CVE IDs, sorted.
+fn split_cves(cves: Option<String>) -> Vec<String> {
+    let mut cves: Vec<String> = cves
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    cves.sort();
+    cves
+}
+
+/// Reputation filter for open-port results, over every source that
+/// reported on the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReputationFilter {
+    /// Abuse score of at least 50, or classified malicious
+    Risky,
+    /// Known to scan the internet itself
+    Scanner,
+    /// Not known to scan the internet, including hosts not yet checked
+    NotScanner,
+}
+
+/// Lifecycle filter for open-port results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PortStatus {
+    /// Seen within the aging window (`closed_at` is null)
+    Active,
+    /// Marked gone after going unseen (`closed_at` is set)
+    Gone,
+}
+
+#[derive(Debug, Clone, serde::Serialize, ToSchema)]
+pub struct PortChange {
+    pub ip_address: String,
+    pub port: u16,
+    pub round: i64,
+    pub is_open: bool,
+}
+
+/// Open-port counts for one port in a [`RoundDiff`].
+#[derive(Debug, Clone, serde::Serialize, ToSchema)]
+pub struct PortDelta {
+    pub port: u16,
+    pub open_before: usize,
+    pub open_after: usize,
+    pub opened: usize,
+    pub closed: usize,
+}
+
+/// Comparison of two stored IPv4 bitmap rounds. Counts are exact; the
+/// `opened`, `closed` and `hosts_gone_dark` lists are capped by the caller.
+#[derive(Debug, Clone, Default, serde::Serialize, ToSchema)]
+pub struct RoundDiff {
+    pub from_round: i64,
+    pub to_round: i64,
+    pub ports: Vec<PortDelta>,
+    pub opened: Vec<PortChange>,
+    pub closed: Vec<PortChange>,
+    /// Hosts with at least one open port in each round
+    pub hosts_before: usize,
+    pub hosts_after: usize,
+    pub new_hosts: usize,
+    pub hosts_gone_dark_count: usize,
+    /// Hosts open in `from_round` with no open port in `to_round`
+    pub hosts_gone_dark: Vec<String>,
+}
+
+/// Per-prefix scan coverage of one round, from `scan_coverage` and the
+/// port bitmaps.
+#[derive(Debug, Clone, serde::Serialize, ToSchema)]
+pub struct Coverage {
+    pub round: i64,
+    pub prefix_len: u8,
+    /// Addresses scanned in the round, over every prefix
+    pub scanned: u64,
+    /// Hosts with at least one open port, over every prefix
+    pub open_hosts: u64,
+    /// Prefixes with a scanned address or an open host, in address order
+    pub prefixes: Vec<PrefixCoverage>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, ToSchema)]
+pub struct PrefixCoverage {
+    /// CIDR, e.g. `10.0.0.0/8`
+    pub prefix: String,
+    /// Addresses in the prefix
+    pub addresses: u64,
+    pub scanned: u64,
+    pub open_hosts: u64,
+}
+
+/// A slice of the IPv4 target range, as stored in `cluster_leases`.
+#[derive(Debug, Clone, serde::Serialize, ToSchema)]
+pub struct ClusterLease {
+    pub id: i64,
+    pub round: i64,
+    pub start_ip: String,
+    pub end_ip: String,
+    /// pending, leased or done
+    pub status: String,
+    pub worker_id: Option<String>,
+    /// Unix time after which a leased slice is handed out again
+    pub expires_at: Option<i64>,
+}
+
+/// Lease counts for one coordinator round. `scanned`/`open` sum the
+/// completed leases only.
+#[derive(Debug, Clone, Default, serde::Serialize, ToSchema)]
+pub struct ClusterProgress {
+    pub round: i64,
+    pub total: usize,
+    pub pending: usize,
+    pub leased: usize,
+    pub done: usize,
+    pub scanned: u64,
+    pub open: u64,
+    /// Leases currently held by workers, possibly expired
+    pub active: Vec<ClusterLease>,
+}
+
+/// Scanner counters for one round, as stored in `round_metrics`.
+#[derive(Debug, Clone, serde::Serialize, ToSchema)]
+pub struct RoundMetrics {
+    pub round: i64,
+    pub scanned: u64,
+    pub open: u64,
+    pub errors: u64,
+    pub retries: u64,
+    pub duration_secs: f64,
+    /// Targets per second; derived from `scanned` and `duration_secs`.
+    pub avg_rate: f64,
+    /// When the round (or its last resumed part) finished. Ignored on save.
+    pub finished_at: String,
+}
+
+/// Consecutive rounds an open port was seen in, as stored in `port_history`.
+#[derive(Debug, Clone, serde::Serialize, ToSchema)]
+pub struct PortHistoryRun {
+    pub port: u16,
+    /// First round of the run: when the port appeared
+    pub start_round: i64,
+    /// Last round the port was seen in
+    pub end_round: i64,
+    pub first_seen: String,
+    pub last_seen: String,
+    /// Not seen in the last completed round: the port disappeared after
+    /// `end_round`
+    pub ended: bool,
+}
+
+/// Lifetime of one port's runs in `port_history`. Averages cover ended runs
+/// only, since the open ones are still growing.
+#[derive(Debug, Clone, serde::Serialize, ToSchema)]
+pub struct PortLifetime {
+    pub port: u16,
+    pub runs: u64,
+    pub ended_runs: u64,
+    /// Average rounds an ended run lasted; absent when none has ended
+    pub avg_ended_rounds: Option<f64>,
+    /// Average hours between first and last sighting of an ended run
+    pub avg_ended_hours: Option<f64>,
+    /// Longest run, ended or not, in rounds
+    pub max_rounds: i64,
+}
+
+/// A state-changing API call, as stored in `audit_log`.
+#[derive(Debug, Clone, serde::Serialize, ToSchema)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// RFC 3339 time the call was answered
+    pub created_at: String,
+    pub method: String,
+    pub path: String,
+    /// User named by the authenticating proxy, if any
+    pub principal: Option<String>,
+    /// Address the call came from
+    pub client: Option<String>,
+    /// Query string and request body
+    #[schema(value_type = Option<Object>)]
+    pub params: Option<serde_json::Value>,
+    /// HTTP status of the answer: the call's outcome
+    pub status: u16,
+}
+
+/// An API namespace, as stored in `namespaces`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, ToSchema)]
+pub struct Namespace {
+    pub name: String,
+    /// Callers (client certificate names or `X-Forwarded-User` users)
+    /// confined to this namespace
+    pub principals: Vec<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Saved `/scan/start` parameters, as stored in `scan_templates`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, ToSchema)]
+pub struct ScanTemplate {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    /// `/scan/start` request fields applied when a scan names this template.
+    #[schema(value_type = Object)]
+    pub params: serde_json::Value,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A tag or finding emitted by a `--script` hook, as stored in `script_findings`.
+#[derive(Debug, Clone, serde::Serialize, ToSchema)]
+pub struct ScriptFinding {
+    pub ip_address: String,
+    pub port: u16,
+    /// `tag` for tags; otherwise the kind chosen by the script.
+    pub kind: String,
+    pub value: String,
+    /// Round of the most recent sighting.
+    pub scan_round: i64,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+/// Result of [`SqliteDB::checkpoint_wal_frames`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalCheckpoint {
+    /// Another connection kept the checkpoint from completing
+    pub busy: bool,
+    /// Frames in the WAL; 0 after a TRUNCATE
+    pub log_frames: i64,
+    /// Frames already copied back into the database file
+    pub checkpointed_frames: i64,
+}
+
+/// Size and settings of the database file, from [`SqliteDB::database_stats`].
+#[derive(Debug, Clone, serde::Serialize, ToSchema)]
+pub struct DatabaseStats {
+    /// Database file; absent for in-memory databases
+    pub path: Option<String>,
+    pub file_bytes: Option<u64>,
+    /// Size of the `-wal` file, absent when there is none
+    pub wal_bytes: Option<u64>,
+    pub page_size: u64,
+    pub page_count: u64,
+    /// Unused pages that `VACUUM` would return to the file system
+    pub freelist_pages: u64,
+    pub tables: Vec<TableStats>,
+    pub indexes: Vec<IndexStats>,
+    /// Connection settings such as `journal_mode` and `cache_size`
+    pub pragmas: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, ToSchema)]
+pub struct TableStats {
+    pub name: String,
+    pub rows: u64,
+    /// Pages used by the table's rows, excluding its indexes
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, ToSchema)]
+pub struct IndexStats {
+    pub name: String,
+    pub table: String,
+    pub bytes: u64,
+}
+
+/// A match from [`SqliteDB::search_hostnames`].
+#[derive(Debug, Clone)]
+pub struct HostnameHit {
+    pub hostname: String,
+    pub ip_address: String,
+    /// `reverse_dns` (PTR name from geo enrichment) or `target` (a hostname
+    /// target that resolved to the address)
+    pub source: String,
+    /// Ports still open on the address
+    pub open_ports: usize,
+}
+
+/// A match from [`SqliteDB::search`].
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub ip_address: String,
+    pub port: u16,
+    /// `service` (probe results), `banner` (connect-scan greeting) or
+    /// `vhost` (per-hostname HTTP/TLS results)
+    pub source: String,
+    /// Virtual host the match was served under, for `vhost` hits
+    pub hostname: Option<String>,
+    /// Up to 16 words around the match, with each matched word between
+    /// `\u{2}` and `\u{3}`; the text itself is unescaped.
+    pub snippet: String,
+}
+
+/// Rows taken from one source by [`SqliteDB::merge_from`].
+#[derive(Debug, Clone, Default)]
+pub struct MergeSummary {
+    /// Open-port rows inserted or updated.
+    pub results: usize,
+    pub bitmaps: usize,
+    pub rounds: usize,
+}
+
+/// One open port read from a results export, for
+/// [`SqliteDB::import_results`]. Timestamps are RFC 3339 in UTC, like the
+/// ones the scanner stores, so they compare as text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedResult {
+    pub ip_address: String,
+    pub ip_type: String,
+    pub port: u16,
+    pub scan_round: i64,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub closed_at: Option<String>,
+    pub scan_id: Option<String>,
 }
 
-#[derive(Debug, Clone, serde::Serialize, ToSchema)]
-pub struct PortChange {
-    pub ip_address: String,
-    pub port: u16,
-    pub round: i64,
-    pub is_open: bool,
+/// Rows written by [`SqliteDB::import_results`].
+#[derive(Debug, Clone, Default)]
+pub struct ImportSummary {
+    /// Open-port rows inserted or updated.
+    pub results: usize,
+    pub bitmaps: usize,
 }
 
 /// Scan history record
@@ -1055,6 +4502,49 @@ pub struct ScanHistoryRecord {
     pub end_time: Option<String>,
     pub total_open_ports: usize,
     pub ports_scanned: usize,
+    /// The API scan that covered this round, if any.
+    pub session: Option<ScanSession>,
+}
+
+/// An API-started scan, as stored in `scan_sessions`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, ToSchema)]
+pub struct ScanSession {
+    pub scan_id: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Team or person responsible for the scan.
+    pub owner: Option<String>,
+    pub start_round: i64,
+    /// Last round scanned so far; equals `start_round` unless in loop mode.
+    pub end_round: i64,
+    /// `running`, `completed`, `stopped` or `error`.
+    pub status: String,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    /// Last IP dispatched in `end_round`; a resumed scan continues from
+    /// here. Cleared when the session moves to a new round.
+    pub last_ip: Option<String>,
+    /// Caller that started the scan: its client certificate's common name
+    /// or `X-Forwarded-User`.
+    pub principal: Option<String>,
+}
+
+/// Read the `scan_sessions` columns starting at column `first`, in table
+/// order.
+fn scan_session_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<ScanSession> {
+    Ok(ScanSession {
+        scan_id: row.get(first)?,
+        name: row.get(first + 1)?,
+        description: row.get(first + 2)?,
+        owner: row.get(first + 3)?,
+        start_round: row.get(first + 4)?,
+        end_round: row.get(first + 5)?,
+        status: row.get(first + 6)?,
+        started_at: row.get(first + 7)?,
+        finished_at: row.get(first + 8)?,
+        last_ip: row.get(first + 9)?,
+        principal: row.get(first + 10)?,
+    })
 }
 
 #[cfg(test)]
@@ -1115,6 +4605,208 @@ mod tests {
         );
     }
 
+    #[test]
+    fn mmap_store_keeps_bitmaps_out_of_sqlite() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = || MmapBitmapStore::open(dir.path().to_str().unwrap()).unwrap();
+        let db = SqliteDB::new(":memory:").unwrap();
+        // A blob written before the switch moves into the store.
+        db.set_port_status("192.0.2.1", 80, true, 1).unwrap();
+        let db = db.with_bitmap_store(store());
+
+        let update = |ips: &[(&str, bool)], round| {
+            let updates = ips
+                .iter()
+                .map(|(ip, open)| (ip.parse::<IpAddr>().unwrap(), 80, *open))
+                .collect();
+            db.bulk_update_port_status(updates, round).unwrap();
+        };
+        update(&[("192.0.2.2", true), ("198.51.100.9", true)], 1);
+        update(&[("192.0.2.2", true), ("203.0.113.5", true)], 2);
+        update(&[("203.0.113.5", false), ("192.0.2.3", true)], 2);
+
+        let row = |round: i64| -> (i64, i64) {
+            db.conn
+                .lock()
+                .unwrap()
+                .query_row(
+                    "SELECT LENGTH(bitmap), open_count FROM port_bitmaps
+                     WHERE port = 80 AND scan_round = ?1",
+                    [round],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap()
+        };
+        assert_eq!(row(1), (0, 3));
+        assert_eq!(row(2), (0, 2));
+        let changes = db.get_bitmap_diff(1, 2, 80, 10).unwrap();
+        assert_eq!(changes.len(), 3);
+
+        // Without the store the rows cannot be read.
+        let plain = SqliteDB {
+            bitmaps: None,
+            ..db.clone()
+        };
+        assert!(plain.get_bitmap_diff(1, 2, 80, 10).is_err());
+
+        assert_eq!(db.cleanup_old_rounds(1).unwrap(), 1);
+        assert!(!dir.path().join("r1").exists());
+        assert_eq!(store().load(2, 80).unwrap().count_ones(), 2);
+    }
+
+    #[test]
+    fn bitmap_shards_are_moved_into_and_read_as_one_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.db");
+        let path = path.to_str().unwrap();
+        let db = SqliteDB::new(path).unwrap();
+        db.set_port_status("192.0.2.1", 80, true, 1).unwrap();
+        db.set_port_status("192.0.2.1", 81, true, 1).unwrap();
+        let db = db.with_bitmap_shards(3).unwrap();
+        for (ip, port) in [("192.0.2.2", 80), ("192.0.2.3", 443), ("192.0.2.4", 81)] {
+            db.bulk_update_port_status(vec![(ip.parse().unwrap(), port, true)], 2)
+                .unwrap();
+        }
+        assert!(std::path::Path::new(&format!("{}-shard2", path)).exists());
+
+        let in_main: i64 = db
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM main.port_bitmaps", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(in_main, 0);
+        assert_eq!(db.get_stats().unwrap().0, 5);
+        assert_eq!(db.get_stats_by_port(2).unwrap().len(), 3);
+        assert_eq!(db.get_bitmap_rounds().unwrap(), vec![2, 1]);
+        assert_eq!(db.get_bitmap_diff(1, 2, 80, 10).unwrap().len(), 2);
+        drop(db);
+
+        // Later opens attach the shards on their own.
+        let db = SqliteDB::new(path).unwrap();
+        assert_eq!(db.get_stats().unwrap().0, 5);
+        assert!(db.clone().with_bitmap_shards(3).is_ok());
+        assert!(db.clone().with_bitmap_shards(4).is_err());
+        let read_only = SqliteDB::open_read_only(path, None).unwrap();
+        assert_eq!(read_only.get_stats_by_port(1).unwrap().len(), 2);
+        drop(read_only);
+
+        assert_eq!(db.cleanup_old_rounds(1).unwrap(), 2);
+        assert_eq!(db.get_bitmap_rounds().unwrap(), vec![2]);
+        let target = SqliteDB::new(dir.path().join("other.db").to_str().unwrap()).unwrap();
+        assert!(target.merge_from(path).is_err());
+        assert!(SqliteDB::new(":memory:")
+            .unwrap()
+            .with_bitmap_shards(2)
+            .is_err());
+    }
+
+    #[test]
+    fn missing_geo_pages_by_ip_and_skips_enriched() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        for ip in ["192.0.2.3", "192.0.2.1", "192.0.2.2"] {
+            db.set_port_status(ip, 80, true, 1).unwrap();
+        }
+        let mut info = IpGeoInfo::new("192.0.2.2".to_string(), "test".to_string());
+        info.abuse_email = Some("abuse@example.net".to_string());
+        db.save_ip_geo_info_batch(&[info]).unwrap();
+        assert_eq!(
+            db.get_ip_geo_info("192.0.2.2")
+                .unwrap()
+                .and_then(|i| i.abuse_email)
+                .as_deref(),
+            Some("abuse@example.net")
+        );
+
+        assert_eq!(db.get_ips_missing_geo("", 1).unwrap(), vec!["192.0.2.1"]);
+        assert_eq!(
+            db.get_ips_missing_geo("192.0.2.1", 10).unwrap(),
+            vec!["192.0.2.3"]
+        );
+        assert!(db.get_ips_missing_geo("192.0.2.3", 10).unwrap().is_empty());
+
+        db.set_port_status("192.0.2.2", 443, true, 1).unwrap();
+        assert_eq!(
+            db.get_ips_for_geo(None, true, 10).unwrap(),
+            vec!["192.0.2.1", "192.0.2.3"]
+        );
+        assert_eq!(
+            db.get_ips_for_geo(Some(443), false, 10).unwrap(),
+            vec!["192.0.2.2"]
+        );
+        assert!(db.get_ips_for_geo(Some(443), true, 10).unwrap().is_empty());
+        assert_eq!(db.get_ips_for_geo(None, false, 2).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn hostnames_match_reverse_dns_and_targets_with_wildcards() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        for ip in ["192.0.2.1", "192.0.2.2", "192.0.2.3"] {
+            db.set_port_status(ip, 443, true, 1).unwrap();
+        }
+        let mut info = IpGeoInfo::new("192.0.2.1".to_string(), "test".to_string());
+        info.reverse_dns = Some("Mail.Example.com.".to_string());
+        db.save_ip_geo_info_batch(&[info]).unwrap();
+        db.save_hostname_resolution("www.example.com", &["192.0.2.2".parse().unwrap()], 1)
+            .unwrap();
+        db.save_hostname_resolution("a?b[1].example.org", &["192.0.2.3".parse().unwrap()], 1)
+            .unwrap();
+
+        let hits = db.search_hostnames("*.EXAMPLE.com", 10).unwrap();
+        let found: Vec<_> = hits
+            .iter()
+            .map(|h| {
+                (
+                    h.hostname.as_str(),
+                    h.ip_address.as_str(),
+                    h.source.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("mail.example.com", "192.0.2.1", "reverse_dns"),
+                ("www.example.com", "192.0.2.2", "target"),
+            ]
+        );
+        assert_eq!(hits[0].open_ports, 1);
+        assert_eq!(db.search_hostnames("*.example.com", 1).unwrap().len(), 1);
+        assert!(db.search_hostnames("example.com", 10).unwrap().is_empty());
+        // GLOB's own wildcards are taken literally.
+        assert_eq!(db.search_hostnames("a?b[1]*", 10).unwrap().len(), 1);
+        assert!(db.search_hostnames("a?c*", 10).unwrap().is_empty());
+
+        let filtered = |hostname: &str| {
+            let (results, total) = db
+                .get_scan_results(
+                    1,
+                    10,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(hostname),
+                    None,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(results.len(), total);
+            results
+                .into_iter()
+                .map(|r| r.ip_address)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(filtered("mail.example.com"), ["192.0.2.1"]);
+        assert_eq!(filtered("*.example.com").len(), 2);
+        assert_eq!(filtered("www.*"), ["192.0.2.2"]);
+        assert!(filtered("*.example.net").is_empty());
+    }
+
     #[test]
     fn test_database_operations() {
         // Use in-memory database for testing
@@ -1176,4 +4868,852 @@ mod tests {
         assert_eq!(ip_type, "IPv4");
         assert_eq!(round, 1);
     }
+
+    #[test]
+    fn round_metrics_accumulate_across_resumed_runs() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let run = |round, scanned, duration_secs| RoundMetrics {
+            round,
+            scanned,
+            open: 2,
+            errors: 1,
+            retries: 0,
+            duration_secs,
+            avg_rate: 0.0,
+            finished_at: String::new(),
+        };
+        db.save_round_metrics(&run(1, 100, 10.0)).unwrap();
+        db.save_round_metrics(&run(2, 300, 10.0)).unwrap();
+        db.save_round_metrics(&run(2, 100, 10.0)).unwrap();
+
+        let rounds = db.get_round_metrics(10).unwrap();
+        assert_eq!(rounds.len(), 2);
+        assert_eq!(rounds[0].round, 2);
+        assert_eq!((rounds[0].scanned, rounds[0].open), (400, 4));
+        assert_eq!(rounds[0].duration_secs, 20.0);
+        assert_eq!(rounds[0].avg_rate, 20.0);
+        assert_eq!(rounds[1].avg_rate, 10.0);
+        assert!(!rounds[1].finished_at.is_empty());
+        assert_eq!(db.get_round_metrics(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn scan_templates_round_trip() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let params = serde_json::json!({ "ports": "web", "max_rate": 500 });
+        let web = db
+            .create_scan_template("web", Some("HTTP sweep"), &params)
+            .unwrap()
+            .unwrap();
+        assert_eq!(web.params, params);
+        assert!(db
+            .create_scan_template("web", None, &params)
+            .unwrap()
+            .is_none());
+        let db_ports = db
+            .create_scan_template("db", None, &serde_json::json!({ "ports": "db" }))
+            .unwrap()
+            .unwrap();
+
+        let names: Vec<_> = db
+            .list_scan_templates()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(names, ["db", "web"]);
+
+        let updated = db
+            .update_scan_template(
+                web.id,
+                "web-slow",
+                None,
+                &serde_json::json!({ "max_rate": 50 }),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.name, "web-slow");
+        assert_eq!(updated.description, None);
+        assert_eq!(updated.created_at, web.created_at);
+        assert!(db
+            .update_scan_template(web.id, "db", None, &params)
+            .unwrap()
+            .is_none());
+        assert!(db
+            .update_scan_template(999, "other", None, &params)
+            .unwrap()
+            .is_none());
+
+        assert!(db.delete_scan_template(db_ports.id).unwrap());
+        assert!(!db.delete_scan_template(db_ports.id).unwrap());
+        assert!(db.get_scan_template(db_ports.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn top_ips_rank_hosts_by_active_open_ports() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let open = |ip: &str, ports: &[u16]| {
+            ports
+                .iter()
+                .map(|&port| (ip.parse::<IpAddr>().unwrap(), port, true))
+                .collect::<Vec<_>>()
+        };
+        db.bulk_update_port_status(open("192.0.2.7", &[8080, 22, 443]), 1)
+            .unwrap();
+        db.bulk_update_port_status(open("192.0.2.5", &[80, 443]), 1)
+            .unwrap();
+        db.bulk_update_port_status(open("192.0.2.6", &[80, 443]), 1)
+            .unwrap();
+        db.mark_ports_closed(&[("192.0.2.6".to_string(), 80)])
+            .unwrap();
+
+        let top = db.get_top_ips(2, true).unwrap();
+        assert_eq!(
+            top,
+            vec![
+                ("192.0.2.7".to_string(), 3, Some(vec![22, 443, 8080])),
+                ("192.0.2.5".to_string(), 2, Some(vec![80, 443])),
+            ]
+        );
+        let top = db.get_top_ips(10, false).unwrap();
+        assert_eq!(top.len(), 3);
+        assert_eq!(top[2], ("192.0.2.6".to_string(), 1, None));
+    }
+
+    #[test]
+    fn port_and_round_results_are_paged() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let hosts: Vec<_> = (1..=5)
+            .map(|host| (IpAddr::from([192, 0, 2, host]), 443, true))
+            .collect();
+        db.bulk_update_port_status(hosts, 4).unwrap();
+        db.bulk_update_port_status(vec![("192.0.2.9".parse().unwrap(), 22, true)], 5)
+            .unwrap();
+
+        let (page, total) = db.get_results_by_round(4, 2, 2).unwrap();
+        assert_eq!(total, 5);
+        let ips: Vec<_> = page.iter().map(|r| r.ip_address.as_str()).collect();
+        assert_eq!(ips, ["192.0.2.3", "192.0.2.4"]);
+        let (page, total) = db.get_results_by_round(4, 4, 2).unwrap();
+        assert!(page.is_empty());
+        assert_eq!(total, 5);
+
+        let (page, total) = db.get_results_by_port(443, 1, 3).unwrap();
+        assert_eq!((page.len(), total), (3, 5));
+        assert_eq!(db.get_results_by_port(22, 1, 50).unwrap().1, 1);
+        assert_eq!(db.get_results_by_port(80, 1, 50).unwrap().1, 0);
+        assert_eq!(db.get_all_results_by_round(4).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn bulk_updates_write_details_in_multi_row_chunks() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let hosts = |round: i64| -> Vec<_> {
+            (0..DETAIL_INSERT_ROWS as u32 * 2 + 1)
+                .map(|i| {
+                    let ip = std::net::Ipv4Addr::from(0xC633_6400 + i);
+                    (IpAddr::V4(ip), 443, round % 2 == 1 || i % 2 == 0)
+                })
+                .collect()
+        };
+        db.bulk_update_port_status(hosts(1), 1).unwrap();
+        assert_eq!(db.get_results_by_port(443, 1, 10).unwrap().1, 1001);
+        db.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE open_ports_detail SET closed_at = last_seen WHERE ip_address = '198.51.100.0'",
+                [],
+            )
+            .unwrap();
+
+        // Only the even hosts are open in round 2; the closed one reopens.
+        db.bulk_update_port_status(hosts(2), 2).unwrap();
+        let round = db.get_all_results_by_round(2).unwrap();
+        assert_eq!(round.len(), 501);
+        assert!(round.iter().all(|r| r.closed_at.is_none()));
+        assert!(round.iter().all(|r| r.last_seen == round[0].last_seen));
+        assert_eq!(db.get_all_results_by_round(1).unwrap().len(), 500);
+    }
+
+    #[test]
+    fn ipv6_open_ports_are_stored_as_detail_rows() {
+        let db = SqliteDB::new(":memory:").unwrap().with_port_history(true);
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        db.bulk_update_port_status(
+            vec![
+                (ip("192.0.2.1"), 443, true),
+                (ip("2001:db8::1"), 443, true),
+                (ip("2001:db8::2"), 443, false),
+                (ip("2001:db8::1"), 22, true),
+            ],
+            1,
+        )
+        .unwrap();
+
+        let by_type = |ip_type: &str| {
+            db.get_scan_results(
+                1,
+                50,
+                None,
+                None,
+                None,
+                Some(ip_type),
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap()
+        };
+        let (v6, total) = by_type("IPv6");
+        assert_eq!(total, 2);
+        assert!(v6
+            .iter()
+            .all(|r| r.ip_type == "IPv6" && r.ip_address == "2001:db8::1"));
+        assert_eq!(by_type("IPv4").1, 1);
+        // The IPv4 bitmap only holds IPv4 results.
+        assert_eq!(db.get_stats_by_port(1).unwrap(), vec![(443, 1)]);
+        assert_eq!(
+            db.get_port_history("2001:db8::1", Some(22)).unwrap().len(),
+            1
+        );
+
+        db.bulk_update_port_status(vec![(ip("2001:db8::1"), 22, true)], 2)
+            .unwrap();
+        let (v6, _) = by_type("IPv6");
+        assert!(v6.iter().any(|r| r.port == 22 && r.scan_round == 2));
+    }
+
+    #[test]
+    fn open_ports_are_credited_to_the_scan_that_last_saw_them() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let found = |ip: &str| vec![(ip.parse::<IpAddr>().unwrap(), 80, true)];
+        db.with_scan_id("scan_a")
+            .bulk_update_port_status(found("192.0.2.1"), 1)
+            .unwrap();
+        db.with_scan_id("scan_b")
+            .bulk_update_port_status(found("192.0.2.2"), 1)
+            .unwrap();
+        db.bulk_update_port_status(found("192.0.2.3"), 1).unwrap();
+
+        let by_scan = |scan_id| {
+            db.get_scan_results(
+                1,
+                10,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(scan_id),
+                None,
+                None,
+                None,
+            )
+            .unwrap()
+            .0
+            .into_iter()
+            .map(|r| r.ip_address)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(by_scan("scan_a"), ["192.0.2.1"]);
+        assert_eq!(by_scan("scan_b"), ["192.0.2.2"]);
+
+        // A later sighting by a CLI scan takes the credit away.
+        db.bulk_update_port_status(found("192.0.2.1"), 2).unwrap();
+        assert!(by_scan("scan_a").is_empty());
+        let rows = db.get_results_by_ip("192.0.2.3").unwrap();
+        assert_eq!(rows[0].scan_id, None);
+    }
+
+    #[test]
+    fn scan_history_carries_the_session_of_each_round() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        for round in 1..=3 {
+            db.set_port_status("192.0.2.1", 80, true, round).unwrap();
+        }
+        db.create_scan_session(
+            "scan_1",
+            Some("dmz"),
+            None,
+            Some("netops"),
+            Some("alice"),
+            2,
+        )
+        .unwrap();
+        db.extend_scan_session("scan_1", 3).unwrap();
+        db.extend_scan_session("scan_1", 2).unwrap();
+        assert_eq!(db.count_running_scan_sessions("alice").unwrap(), 1);
+        db.finish_scan_session("scan_1", "stopped").unwrap();
+        assert_eq!(db.count_running_scan_sessions("alice").unwrap(), 0);
+
+        let session = db.get_scan_session("scan_1").unwrap().unwrap();
+        assert_eq!((session.start_round, session.end_round), (2, 3));
+        assert_eq!(session.status, "stopped");
+        assert_eq!(session.principal.as_deref(), Some("alice"));
+        assert!(session.finished_at.is_some());
+        assert!(db.get_scan_session("scan_2").unwrap().is_none());
+
+        let history = db.get_scan_history(10).unwrap();
+        let rounds: Vec<_> = history
+            .iter()
+            .map(|r| (r.round, r.session.as_ref().and_then(|s| s.name.as_deref())))
+            .collect();
+        assert_eq!(rounds, [(3, Some("dmz")), (2, Some("dmz")), (1, None)]);
+        assert_eq!(db.get_scan_history(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn api_scan_progress_is_kept_on_its_session() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.save_progress("198.51.100.9", "IPv4", 4).unwrap();
+        db.create_scan_session("scan_1", None, None, None, None, 4)
+            .unwrap();
+        db.with_scan_id("scan_1")
+            .save_progress("192.0.2.77", "IPv4", 4)
+            .unwrap();
+        // The CLI resume point is untouched.
+        assert_eq!(
+            db.get_progress().unwrap().unwrap().0,
+            "198.51.100.9".to_string()
+        );
+        db.finish_scan_session("scan_1", "stopped").unwrap();
+
+        let latest = db.get_latest_scan_session().unwrap().unwrap();
+        assert_eq!(latest.last_ip.as_deref(), Some("192.0.2.77"));
+        db.reopen_scan_session("scan_1").unwrap();
+        db.extend_scan_session("scan_1", 4).unwrap();
+        let session = db.get_scan_session("scan_1").unwrap().unwrap();
+        assert_eq!(
+            (session.status.as_str(), session.finished_at),
+            ("running", None)
+        );
+        assert_eq!(session.last_ip.as_deref(), Some("192.0.2.77"));
+
+        db.extend_scan_session("scan_1", 5).unwrap();
+        let session = db.get_scan_session("scan_1").unwrap().unwrap();
+        assert_eq!((session.end_round, session.last_ip), (5, None));
+
+        db.create_scan_session("scan_2", None, None, None, None, 5)
+            .unwrap();
+        assert_eq!(
+            db.get_latest_scan_session().unwrap().unwrap().scan_id,
+            "scan_2"
+        );
+    }
+
+    #[test]
+    fn clearing_progress_forgets_every_resume_point() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.save_progress("198.51.100.9", "IPv4", 2).unwrap();
+        db.create_scan_session("scan_1", None, None, None, None, 2)
+            .unwrap();
+        db.with_scan_id("scan_1")
+            .save_progress("192.0.2.77", "IPv4", 2)
+            .unwrap();
+        db.set_current_round(7).unwrap();
+        assert_eq!(db.get_current_round().unwrap(), 7);
+
+        db.clear_progress().unwrap();
+        assert!(db.get_progress().unwrap().is_none());
+        let session = db.get_scan_session("scan_1").unwrap().unwrap();
+        assert_eq!(session.last_ip, None);
+        assert_eq!(db.get_current_round().unwrap(), 7);
+    }
+
+    #[test]
+    fn cluster_leases_expire_and_move_to_another_worker() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.create_cluster_leases(3, &[(0, 255), (256, 511)])
+            .unwrap();
+        // A restarted coordinator re-creating the round keeps existing rows.
+        db.create_cluster_leases(3, &[(0, 255), (256, 511)])
+            .unwrap();
+
+        let a = db.acquire_cluster_lease(3, "a", 100, 160).unwrap().unwrap();
+        assert_eq!(
+            (a.start_ip.as_str(), a.end_ip.as_str()),
+            ("0.0.0.0", "0.0.0.255")
+        );
+        let b = db.acquire_cluster_lease(3, "b", 100, 160).unwrap().unwrap();
+        assert_eq!(b.start_ip, "0.0.1.0");
+        assert!(db
+            .acquire_cluster_lease(3, "c", 100, 160)
+            .unwrap()
+            .is_none());
+
+        // a's lease expires and is handed to c; a can no longer renew or complete it.
+        let c = db.acquire_cluster_lease(3, "c", 160, 220).unwrap().unwrap();
+        assert_eq!(c.id, a.id);
+        assert!(db.renew_cluster_lease(a.id, "a", 300).unwrap().is_none());
+        assert!(!db.complete_cluster_lease(a.id, "a", 256, 1).unwrap());
+        assert_eq!(
+            db.renew_cluster_lease(b.id, "b", 300)
+                .unwrap()
+                .unwrap()
+                .expires_at,
+            Some(300)
+        );
+        assert!(db.complete_cluster_lease(c.id, "c", 256, 2).unwrap());
+
+        let progress = db.get_cluster_progress(3).unwrap();
+        assert_eq!((progress.total, progress.pending), (2, 0));
+        assert_eq!((progress.leased, progress.done), (1, 1));
+        assert_eq!((progress.scanned, progress.open), (256, 2));
+        assert_eq!(progress.active.len(), 1);
+        assert_eq!(progress.active[0].worker_id.as_deref(), Some("b"));
+        assert_eq!(db.get_cluster_progress(4).unwrap().total, 0);
+    }
+
+    #[test]
+    fn merge_unions_bitmaps_and_keeps_widest_sighting_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let metrics = |scanned, duration_secs| RoundMetrics {
+            round: 2,
+            scanned,
+            open: 2,
+            errors: 0,
+            retries: 0,
+            duration_secs,
+            avg_rate: 0.0,
+            finished_at: String::new(),
+        };
+
+        let a = SqliteDB::new(&path("a.db")).unwrap();
+        a.with_scan_id("scan_a")
+            .bulk_update_port_status(
+                vec![
+                    ("192.0.2.1".parse().unwrap(), 443, true),
+                    ("192.0.2.2".parse().unwrap(), 443, true),
+                ],
+                2,
+            )
+            .unwrap();
+        a.save_round_metrics(&metrics(100, 10.0)).unwrap();
+        a.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE open_ports_detail SET first_seen = '2026-01-01T00:00:00+00:00' WHERE ip_address = '192.0.2.2'",
+                [],
+            )
+            .unwrap();
+        a.save_metadata("current_round", "3").unwrap();
+
+        let b = SqliteDB::new(&path("b.db")).unwrap();
+        b.bulk_update_port_status(
+            vec![
+                ("198.51.100.1".parse().unwrap(), 443, true),
+                ("192.0.2.2".parse().unwrap(), 443, true),
+            ],
+            2,
+        )
+        .unwrap();
+        b.save_round_metrics(&metrics(300, 20.0)).unwrap();
+        b.save_metadata("current_round", "2").unwrap();
+        b.save_progress("198.51.100.1", "IPv4", 2).unwrap();
+
+        let out = SqliteDB::new(&path("out.db")).unwrap();
+        assert!(out.merge_from(&path("missing.db")).is_err());
+        let summary = out.merge_from(&path("a.db")).unwrap();
+        assert_eq!(
+            (summary.results, summary.bitmaps, summary.rounds),
+            (2, 1, 1)
+        );
+        out.merge_from(&path("b.db")).unwrap();
+
+        assert_eq!(out.get_stats_by_port(2).unwrap(), vec![(443, 3)]);
+        let shared = out.get_results_by_ip("192.0.2.2").unwrap();
+        assert_eq!(shared.len(), 1);
+        assert_eq!(shared[0].first_seen, "2026-01-01T00:00:00+00:00");
+        // Same round in both sources: the first credit stands.
+        assert_eq!(shared[0].scan_id.as_deref(), Some("scan_a"));
+        assert_eq!(out.get_results_by_port(443, 1, 50).unwrap().1, 3);
+
+        let round = &out.get_round_metrics(10).unwrap()[0];
+        assert_eq!((round.scanned, round.open), (400, 4));
+        assert_eq!(round.duration_secs, 20.0);
+        assert_eq!(round.avg_rate, 20.0);
+        assert_eq!(out.get_current_round().unwrap(), 3);
+        assert!(out.get_progress().unwrap().is_none());
+    }
+
+    #[test]
+    fn stale_ports_are_marked_gone_and_reopen_when_seen() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 22, true),
+                ("192.0.2.2".parse().unwrap(), 22, true),
+            ],
+            1,
+        )
+        .unwrap();
+        db.bulk_update_port_status(vec![("192.0.2.1".parse().unwrap(), 22, true)], 3)
+            .unwrap();
+
+        assert_eq!(db.mark_stale_ports(3, 0).unwrap(), 0);
+        assert_eq!(db.mark_stale_ports(3, 3).unwrap(), 0);
+        assert_eq!(db.mark_stale_ports(4, 3).unwrap(), 1);
+        // Already closed rows keep their original closed_at.
+        assert_eq!(db.mark_stale_ports(5, 3).unwrap(), 0);
+
+        let query = |status| {
+            db.get_scan_results(
+                1,
+                10,
+                None,
+                None,
+                None,
+                None,
+                Some(status),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap()
+            .0
+        };
+        let gone = query(PortStatus::Gone);
+        assert_eq!(gone.len(), 1);
+        assert_eq!(gone[0].ip_address, "192.0.2.2");
+        assert!(gone[0].closed_at.is_some());
+        assert_eq!(query(PortStatus::Active)[0].ip_address, "192.0.2.1");
+
+        db.bulk_update_port_status(vec![("192.0.2.2".parse().unwrap(), 22, true)], 6)
+            .unwrap();
+        assert!(query(PortStatus::Gone).is_empty());
+
+        assert_eq!(
+            db.get_active_open_ports().unwrap(),
+            vec![("192.0.2.1".to_string(), 22), ("192.0.2.2".to_string(), 22)]
+        );
+        let target = [("192.0.2.1".to_string(), 22)];
+        assert_eq!(db.mark_ports_closed(&target).unwrap(), 1);
+        assert_eq!(db.mark_ports_closed(&target).unwrap(), 0);
+        assert_eq!(
+            db.get_active_open_ports().unwrap(),
+            vec![("192.0.2.2".to_string(), 22)]
+        );
+        assert!(db.get_results_by_ip("192.0.2.2").unwrap()[0]
+            .closed_at
+            .is_none());
+    }
+
+    #[test]
+    fn port_history_records_runs_of_consecutive_rounds() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let open = |ip: &str| vec![(ip.parse::<IpAddr>().unwrap(), 22, true)];
+        // Not recorded until enabled.
+        db.bulk_update_port_status(open("192.0.2.1"), 1).unwrap();
+        assert!(db.get_port_history("192.0.2.1", None).unwrap().is_empty());
+
+        let db = db.with_port_history(true);
+        for round in [1, 2, 2, 3, 5] {
+            db.bulk_update_port_status(open("192.0.2.1"), round)
+                .unwrap();
+        }
+        db.with_scan_id("scan_1")
+            .bulk_update_port_status(open("192.0.2.2"), 5)
+            .unwrap();
+        db.save_metadata("current_round", "5").unwrap();
+        db.save_metadata("round_5_complete", "true").unwrap();
+
+        let runs = db.get_port_history("192.0.2.1", Some(22)).unwrap();
+        let spans: Vec<_> = runs
+            .iter()
+            .map(|run| (run.start_round, run.end_round, run.ended))
+            .collect();
+        assert_eq!(spans, [(1, 3, true), (5, 5, false)]);
+        assert!(db
+            .get_port_history("192.0.2.1", Some(80))
+            .unwrap()
+            .is_empty());
+        assert_eq!(db.get_port_history("192.0.2.2", None).unwrap().len(), 1);
+
+        let lifetimes = db.get_port_lifetimes(10).unwrap();
+        assert_eq!(lifetimes.len(), 1);
+        assert_eq!(lifetimes[0].port, 22);
+        assert_eq!(lifetimes[0].runs, 3);
+        assert_eq!(lifetimes[0].ended_runs, 1);
+        assert_eq!(lifetimes[0].avg_ended_rounds, Some(3.0));
+        assert_eq!(lifetimes[0].max_rounds, 3);
+    }
+
+    #[test]
+    fn service_vhosts_are_kept_per_hostname() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let vhost = |hostname: &str, status| VirtualHostInfo {
+            ip: "192.0.2.10".to_string(),
+            port: 443,
+            hostname: hostname.to_string(),
+            http_status: Some(status),
+            http_title: None,
+            http_server: None,
+            tls_subject: Some(hostname.to_string()),
+            tls_issuer: None,
+            tls_version: Some("TLS".to_string()),
+            detected_at: "2026-01-01T00:00:00+00:00".to_string(),
+        };
+        db.save_service_vhosts(&[vhost("www.example.com", 200), vhost("api.example.com", 404)])
+            .unwrap();
+        db.save_service_vhosts(&[vhost("api.example.com", 200)])
+            .unwrap();
+
+        let stored = db.get_service_vhosts("192.0.2.10").unwrap();
+        assert_eq!(
+            stored,
+            vec![vhost("api.example.com", 200), vhost("www.example.com", 200)]
+        );
+        assert!(db.get_service_vhosts("192.0.2.11").unwrap().is_empty());
+    }
+
+    #[test]
+    fn port_cves_are_replaced_and_filterable() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 80, true),
+                ("192.0.2.1".parse().unwrap(), 22, true),
+            ],
+            1,
+        )
+        .unwrap();
+        let cve = |id: &str| CveMatch {
+            cve_id: id.to_string(),
+            product: "http_server".to_string(),
+            version: "2.4.49".to_string(),
+            cvss: Some(9.8),
+        };
+        db.replace_port_cves(
+            "192.0.2.1",
+            80,
+            &[cve("CVE-2021-41773"), cve("CVE-2000-0001")],
+        )
+        .unwrap();
+        db.replace_port_cves(
+            "192.0.2.1",
+            80,
+            &[cve("CVE-2021-41773"), cve("CVE-2021-42013")],
+        )
+        .unwrap();
+
+        let filtered = |has_cves| {
+            db.get_scan_results(
+                1, 10, None, None, None, None, None, None, None, has_cves, None,
+            )
+            .unwrap()
+        };
+        let (with, total) = filtered(Some(true));
+        assert_eq!(total, 1);
+        assert_eq!(with[0].port, 80);
+        assert_eq!(with[0].cves, ["CVE-2021-41773", "CVE-2021-42013"]);
+        let (without, total) = filtered(Some(false));
+        assert_eq!(total, 1);
+        assert_eq!(without[0].port, 22);
+        assert!(without[0].cves.is_empty());
+        assert_eq!(filtered(None).1, 2);
+    }
+
+    #[test]
+    fn search_finds_banner_and_http_text_and_follows_updates() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let mut jenkins = ServiceInfo::new("192.0.2.1".to_string(), 8080);
+        jenkins.http_title = Some("Dashboard [Jenkins]".to_string());
+        jenkins.http_server = Some("Jetty(10.0.13)".to_string());
+        db.save_service_info_batch(&[jenkins.clone()]).unwrap();
+        db.save_port_banners(&[(
+            "192.0.2.2".to_string(),
+            22,
+            "SSH-2.0-ROSSSH MikroTik".to_string(),
+        )])
+        .unwrap();
+
+        let hits = db.search("jenkins", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            (hits[0].ip_address.as_str(), hits[0].port),
+            ("192.0.2.1", 8080)
+        );
+        assert_eq!(hits[0].source, "service");
+        assert_eq!(hits[0].snippet, "Dashboard [\u{2}Jenkins\u{3}]");
+        assert_eq!(db.search("mikro*", 10).unwrap()[0].source, "banner");
+        // Words are matched literally, not as FTS5 query syntax.
+        assert_eq!(db.search("jetty(10.0.13", 10).unwrap().len(), 1);
+        assert!(db.search("jenkins mikrotik", 10).unwrap().is_empty());
+        assert!(db.search("  ", 10).unwrap().is_empty());
+
+        jenkins.http_title = Some("Grafana".to_string());
+        db.save_service_info_batch(&[jenkins]).unwrap();
+        assert!(db.search("jenkins", 10).unwrap().is_empty());
+        assert_eq!(db.search("grafana", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn database_stats_report_sizes_rows_and_pragmas() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.db");
+        let db = SqliteDB::new(path.to_str().unwrap()).unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 80, true),
+                ("192.0.2.2".parse().unwrap(), 443, true),
+            ],
+            1,
+        )
+        .unwrap();
+
+        let stats = db.database_stats().unwrap();
+        assert_eq!(stats.path.as_deref(), path.to_str());
+        assert!(stats.file_bytes.unwrap() > 0);
+        assert!(stats.page_size > 0 && stats.page_count > 0);
+        let detail = stats
+            .tables
+            .iter()
+            .find(|table| table.name == "open_ports_detail")
+            .unwrap();
+        assert_eq!(detail.rows, 2);
+        assert!(detail.bytes >= stats.page_size);
+        assert!(stats
+            .indexes
+            .iter()
+            .any(|index| index.name == "idx_open_ports_ip" && index.table == "open_ports_detail"));
+        assert_eq!(stats.pragmas["journal_mode"], "wal");
+
+        let memory = SqliteDB::new(":memory:").unwrap().database_stats().unwrap();
+        assert!(memory.path.is_none() && memory.file_bytes.is_none());
+    }
+
+    #[test]
+    fn read_only_handles_query_but_never_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("region.db").to_str().unwrap().to_string();
+        let db = SqliteDB::new(&path).unwrap();
+        db.bulk_update_port_status(vec![("192.0.2.1".parse().unwrap(), 22, true)], 1)
+            .unwrap();
+
+        let attached = SqliteDB::open_read_only(&path, None).unwrap();
+        assert_eq!(attached.get_results_by_ip("192.0.2.1").unwrap().len(), 1);
+        assert!(attached
+            .bulk_update_port_status(vec![("192.0.2.2".parse().unwrap(), 22, true)], 1)
+            .is_err());
+        // Writes through the owning handle show up in the attached one.
+        db.bulk_update_port_status(vec![("192.0.2.2".parse().unwrap(), 22, true)], 1)
+            .unwrap();
+        assert_eq!(attached.get_results_by_ip("192.0.2.2").unwrap().len(), 1);
+
+        assert!(SqliteDB::open_read_only(&format!("{}.missing", path), None).is_err());
+        let other = dir.path().join("other.db");
+        rusqlite::Connection::open(&other)
+            .unwrap()
+            .execute_batch("CREATE TABLE notes (text TEXT)")
+            .unwrap();
+        assert!(SqliteDB::open_read_only(other.to_str().unwrap(), None).is_err());
+    }
+
+    #[cfg(not(feature = "sqlcipher"))]
+    #[test]
+    fn db_key_needs_the_sqlcipher_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.db").to_str().unwrap().to_string();
+        let err = SqliteDB::with_key(&path, Some("secret")).err().unwrap();
+        assert!(err.to_string().contains("sqlcipher"));
+        let db = SqliteDB::new(&path).unwrap();
+        assert!(db
+            .export_rekeyed(&format!("{}.rekey", path), "new")
+            .is_err());
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn encrypted_databases_need_their_key_and_can_be_rekeyed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let db = SqliteDB::with_key(&path("scan.db"), Some("old")).unwrap();
+        db.bulk_update_port_status(vec![("192.0.2.1".parse().unwrap(), 22, true)], 1)
+            .unwrap();
+        db.export_rekeyed(&path("rekeyed.db"), "new").unwrap();
+        db.export_rekeyed(&path("plain.db"), "").unwrap();
+        drop(db);
+
+        assert!(SqliteDB::new(&path("scan.db")).is_err());
+        assert!(SqliteDB::with_key(&path("scan.db"), Some("wrong")).is_err());
+        assert!(SqliteDB::with_key(&path("rekeyed.db"), Some("old")).is_err());
+        let rekeyed = SqliteDB::with_key(&path("rekeyed.db"), Some("new")).unwrap();
+        assert_eq!(rekeyed.get_results_by_ip("192.0.2.1").unwrap().len(), 1);
+        let plain = SqliteDB::new(&path("plain.db")).unwrap();
+        assert_eq!(plain.get_results_by_ip("192.0.2.1").unwrap().len(), 1);
+
+        // Merging reads sources with the destination's key.
+        let merged = SqliteDB::with_key(&path("merged.db"), Some("new")).unwrap();
+        merged.merge_from(&path("rekeyed.db")).unwrap();
+        assert_eq!(merged.get_active_open_ports().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn coverage_counts_scanned_addresses_and_open_hosts_per_prefix() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        db.bulk_update_port_status(
+            vec![
+                (ip("10.0.0.1"), 22, true),
+                (ip("10.0.0.1"), 80, true),
+                (ip("10.0.0.2"), 22, false),
+                (ip("10.1.0.1"), 22, false),
+            ],
+            1,
+        )
+        .unwrap();
+        // A cluster lease crossing a /16 boundary.
+        let start = u32::from(std::net::Ipv4Addr::new(192, 0, 255, 255));
+        db.record_scanned_range(1, start, start + 2).unwrap();
+        db.record_scanned_range(1, start, start).unwrap();
+        db.bulk_update_port_status(vec![(ip("192.1.0.0"), 443, true)], 1)
+            .unwrap();
+        db.bulk_update_port_status(vec![(ip("10.0.0.3"), 22, true)], 2)
+            .unwrap();
+
+        let coverage = db.get_coverage(1, 8).unwrap();
+        assert_eq!((coverage.scanned, coverage.open_hosts), (6, 2));
+        assert_eq!(
+            coverage.prefixes,
+            vec![
+                PrefixCoverage {
+                    prefix: "10.0.0.0/8".to_string(),
+                    addresses: 1 << 24,
+                    scanned: 3,
+                    open_hosts: 1,
+                },
+                PrefixCoverage {
+                    prefix: "192.0.0.0/8".to_string(),
+                    addresses: 1 << 24,
+                    scanned: 3,
+                    open_hosts: 1,
+                },
+            ]
+        );
+        let by_16: Vec<(String, u64, u64)> = db
+            .get_coverage(1, 16)
+            .unwrap()
+            .prefixes
+            .into_iter()
+            .map(|prefix| (prefix.prefix, prefix.scanned, prefix.open_hosts))
+            .collect();
+        assert_eq!(
+            by_16,
+            vec![
+                ("10.0.0.0/16".to_string(), 2, 1),
+                ("10.1.0.0/16".to_string(), 1, 0),
+                ("192.0.0.0/16".to_string(), 1, 0),
+                ("192.1.0.0/16".to_string(), 2, 1),
+            ]
+        );
+        assert_eq!(db.get_coverage(2, 8).unwrap().scanned, 1);
+        assert!(db.get_coverage(1, 17).is_err());
+        assert!(db.get_coverage(3, 8).unwrap().prefixes.is_empty());
+    }
 }
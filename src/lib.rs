@@ -0,0 +1,16 @@
+//! TCP port scanner with SQLite storage, geo/service enrichment and an HTTP
+//! API. The `ip-scan` binary is a thin CLI over this crate; [`Scan`] is the
+//! simplest way to embed it, while the scanners, [`SqliteDB`] and
+//! [`GeoService`] remain available for finer control.
+
+pub mod api;
+pub mod cli;
+pub mod dao;
+pub mod model;
+mod scan;
+pub mod service;
+
+pub use dao::SqliteDB;
+pub use model::{IpRange, OpenPort, ScanMetrics};
+pub use scan::{Scan, ScanBuilder, ScanHandle};
+pub use service::{ConScanner, ConScannerConfig, GeoService, SynScanner};
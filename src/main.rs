@@ -1,25 +1,57 @@
-mod api;
-mod cli;
-mod dao;
+mod daemon;
 mod error;
-mod model;
-mod service;
 #[allow(dead_code)]
 mod skill;
+mod systemd;
+
+use ip_scan::{api, cli, dao, model, service};
 
 use anyhow::Result;
 use clap::Parser;
-use tracing::{error, info, Level};
+use tracing::{debug, error, info, warn, Level};
 
-use cli::Args;
+use cli::{Args, Command};
 use dao::SqliteDB;
 use service::GeoService;
 
 fn main() -> Result<()> {
-    let args = Args::parse().merge_with_config()?;
+    let args = Args::parse();
+    // Generating a config must not depend on (or validate) an existing one.
+    if let Some(Command::InitConfig { path, force }) = &args.command {
+        return cli::write_sample_config(path, *force);
+    }
+    let args = args.merge_with_config()?;
+    match args.command {
+        Some(Command::Stop) => return daemon::stop(&args),
+        Some(Command::Status) => return daemon::status(&args),
+        Some(Command::Report { ref report }) => return run_report(&args, report),
+        Some(Command::Export {
+            list: Some(ref list),
+            ..
+        }) => return run_export_list(&args, list),
+        Some(Command::Export {
+            ref format,
+            output: Some(ref output),
+            manifest,
+            ref sign_key,
+            ref filter,
+            ..
+        }) => return run_export(&args, format, output, filter, manifest, sign_key.as_deref()),
+        Some(Command::Export { .. }) => return Err(anyhow::anyhow!("export needs --output")),
+        Some(Command::Import { ref inputs, .. }) => return run_import(&args, inputs),
+        Some(Command::Db { ref db }) => return run_db(&args, db),
+        Some(Command::Interfaces { json }) => return print_interfaces(json),
+        Some(Command::Estimate { json, .. }) => return print_estimate(&args, json),
+        Some(Command::InitConfig { .. }) | None => {}
+    }
     if args.dry_run {
         return print_scan_plan(&args);
     }
+    // Daemonize before the runtime exists: fork() only keeps the calling thread.
+    if args.daemon {
+        daemon::daemonize(&args.pid_file, &args.log_file)?;
+    }
+    let pid_file = args.daemon.then(|| args.pid_file.clone());
     let worker_threads = args.worker_threads.unwrap_or_else(|| {
         std::thread::available_parallelism()
             .map(|n| n.get())
@@ -30,18 +62,260 @@ fn main() -> Result<()> {
         .enable_all()
         .build()
         .unwrap();
-    rt.block_on(async_main(args))
+    let result = rt.block_on(async_main(args));
+    if let Some(pid_file) = pid_file {
+        daemon::remove_pid_file(&pid_file);
+    }
+    result
+}
+
+/// `ip-scan report ...`: read-only, so it is safe next to a running scanner.
+fn run_report(args: &Args, report: &cli::ReportCommand) -> Result<()> {
+    let db = args.open_database()?;
+    let (rendered, output) = match report {
+        cli::ReportCommand::Diff {
+            from,
+            to,
+            format,
+            output,
+            limit,
+        } => {
+            let report = service::DiffReport::collect(&db, *from, *to, *limit)?;
+            let rendered = match format.as_str() {
+                "html" => report.to_html(),
+                _ => report.to_markdown(),
+            };
+            (rendered, output)
+        }
+        cli::ReportCommand::Html {
+            filter,
+            output,
+            limit,
+        } => {
+            let report = service::ResultsReport::collect(&db, filter.to_filter(), *limit)?;
+            (report.to_html(), output)
+        }
+    };
+    match output {
+        Some(path) => {
+            std::fs::write(path, rendered)?;
+            println!("Wrote report to {}", path.display());
+        }
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
+
+/// `ip-scan interfaces`
+fn print_interfaces(json: bool) -> Result<()> {
+    let report = service::InterfaceReport::collect();
+    if json {
+        println!("{}", report.to_json()?);
+    } else {
+        print!("{}", report.to_text());
+    }
+    Ok(())
+}
+
+/// `ip-scan estimate`
+fn print_estimate(args: &Args, json: bool) -> Result<()> {
+    let estimate = service::ScanEstimate::from_args(args)?;
+    if json {
+        println!("{}", estimate.to_json()?);
+    } else {
+        print!("{}", estimate.to_text());
+    }
+    Ok(())
+}
+
+/// `ip-scan db stats`
+fn print_database_stats(args: &Args, json: bool) -> Result<()> {
+    if !std::path::Path::new(&args.database).exists() {
+        return Err(anyhow::anyhow!("Database {} does not exist", args.database));
+    }
+    let stats = args.open_database()?.database_stats()?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+    let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    println!("Database: {}", args.database);
+    println!("File:     {:.1} MiB", mib(stats.file_bytes.unwrap_or(0)));
+    println!("WAL:      {:.1} MiB", mib(stats.wal_bytes.unwrap_or(0)));
+    println!(
+        "Pages:    {} x {} bytes, {} free",
+        stats.page_count, stats.page_size, stats.freelist_pages
+    );
+    println!("\n{:<32} {:>14} {:>12}", "Table", "Rows", "MiB");
+    for table in &stats.tables {
+        println!(
+            "{:<32} {:>14} {:>12.2}",
+            table.name,
+            table.rows,
+            mib(table.bytes)
+        );
+    }
+    println!("\n{:<40} {:<24} {:>12}", "Index", "Table", "MiB");
+    for index in &stats.indexes {
+        println!(
+            "{:<40} {:<24} {:>12.2}",
+            index.name,
+            index.table,
+            mib(index.bytes)
+        );
+    }
+    println!();
+    for (name, value) in &stats.pragmas {
+        println!("{} = {}", name, value);
+    }
+    Ok(())
+}
+
+/// `ip-scan export`: stream matching results into `output`.
+fn run_export(
+    args: &Args,
+    format: &str,
+    output: &std::path::Path,
+    filter: &cli::ResultFilterArgs,
+    manifest: bool,
+    sign_key: Option<&std::path::Path>,
+) -> Result<()> {
+    // Load the key first, so a bad one fails before the export is written.
+    let signer = sign_key.map(service::ManifestSigner::load).transpose()?;
+    let db = args.open_database()?;
+    let filter = filter.to_filter();
+    let file = std::io::BufWriter::new(std::fs::File::create(output)?);
+    let mut out = service::HashingWriter::new(file);
+    let rows = match format {
+        "csv" => service::write_results_csv(&db, &filter, &mut out)?,
+        "json" => service::write_results_json(&db, &filter, &mut out)?,
+        _ => service::write_results_parquet(&db, &filter, None, &mut out)?,
+    };
+    std::io::Write::flush(&mut out)?;
+    println!("Exported {} results to {}", rows, output.display());
+    if manifest || signer.is_some() {
+        let manifest =
+            service::ExportManifest::new(&db, output, format, &filter, rows, out.finish())?;
+        let (manifest, signature) = manifest.write(output, signer.as_ref())?;
+        println!("Wrote manifest {}", manifest.display());
+        if let Some(signature) = signature {
+            println!("Wrote signature {}", signature.display());
+        }
+    }
+    Ok(())
+}
+
+/// `ip-scan export targets`: the hosts behind matching open ports, as a
+/// list for `--input-file`.
+fn run_export_list(args: &Args, list: &cli::ExportCommand) -> Result<()> {
+    let cli::ExportCommand::Targets { filter, output } = list;
+    let db = args.open_database()?;
+    let filter = filter.to_filter();
+    match output {
+        Some(path) => {
+            let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+            let hosts = service::write_target_list(&db, &filter, file)?;
+            println!("Exported {} hosts to {}", hosts, path.display());
+        }
+        None => {
+            service::write_target_list(&db, &filter, std::io::stdout().lock())?;
+        }
+    }
+    Ok(())
+}
+
+fn run_import(args: &Args, inputs: &[std::path::PathBuf]) -> Result<()> {
+    let db = args.open_database()?;
+    for input in inputs {
+        let file = std::fs::File::open(input)
+            .map_err(|e| anyhow::anyhow!("Cannot open {}: {}", input.display(), e))?;
+        let rows = service::read_results_csv(std::io::BufReader::new(file))
+            .map_err(|e| anyhow::anyhow!("{}: {}", input.display(), e))?;
+        let summary = db
+            .import_results(rows)
+            .map_err(|e| anyhow::anyhow!("{}: {:#}", input.display(), e))?;
+        println!(
+            "Imported {}: {} results, {} port bitmaps",
+            input.display(),
+            summary.results,
+            summary.bitmaps
+        );
+    }
+    db.checkpoint_wal()?;
+    Ok(())
+}
+
+fn run_db(args: &Args, command: &cli::DbCommand) -> Result<()> {
+    let (output, inputs) = match command {
+        cli::DbCommand::Merge { output, inputs } => (output, inputs),
+        cli::DbCommand::Rekey { new_key, decrypt } => {
+            let key = if *decrypt {
+                ""
+            } else {
+                new_key.as_deref().unwrap_or_default()
+            };
+            return rekey_database(args, key);
+        }
+        cli::DbCommand::Stats { json } => return print_database_stats(args, *json),
+    };
+    let same_file = |a: &std::path::Path, b: &std::path::Path| matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b);
+    if let Some(input) = inputs.iter().find(|input| !input.exists()) {
+        return Err(anyhow::anyhow!(
+            "Database {} does not exist",
+            input.display()
+        ));
+    }
+    if let Some(input) = inputs.iter().find(|input| same_file(input, output)) {
+        return Err(anyhow::anyhow!(
+            "{} is both a source and the destination",
+            input.display()
+        ));
+    }
+    let db = SqliteDB::with_key(&output.to_string_lossy(), args.db_key.as_deref())?;
+    for input in inputs {
+        let summary = db.merge_from(&input.to_string_lossy())?;
+        println!(
+            "Merged {}: {} results, {} port bitmaps, {} rounds",
+            input.display(),
+            summary.results,
+            summary.bitmaps,
+            summary.rounds
+        );
+    }
+    db.checkpoint_wal()?;
+    println!("Wrote {}", output.display());
+    Ok(())
 }
 
 fn print_scan_plan(args: &Args) -> Result<()> {
-    let ports = model::parse_port_range(&args.ports).map_err(|e| anyhow::anyhow!(e))?;
+    let ports =
+        model::parse_port_range(&args.ports, &args.port_groups).map_err(|e| anyhow::anyhow!(e))?;
+    // Compile the hook script and check SMTP and webhook settings so a dry
+    // run catches configuration errors too.
+    args.load_script_hooks()?;
+    service::EmailReporter::from_config(&args.report_email_config, &args.report_email)?;
+    service::validate_notifiers(&args.notify)?;
+    service::MqttPublisher::from_config(&args.mqtt)?;
+    service::SyslogSink::from_config(&args.syslog)?;
+    service::MetricsPusher::from_config(&args.metrics_push)?;
     let (start, end) = args
         .start_ip
         .as_deref()
         .zip(args.end_ip.as_deref())
         .map(|(start, end)| (start.to_string(), end.to_string()))
         .unwrap_or_else(Args::get_default_ipv4_range);
-    let mode = if args.syn { "SYN" } else { "TCP connect" };
+    let mode = if args.rescan_open {
+        "rescan known open ports (TCP connect)"
+    } else if args.syn {
+        "SYN"
+    } else {
+        "TCP connect"
+    };
+    let cluster = if args.coordinator {
+        Some("coordinator".to_string())
+    } else {
+        args.worker.as_ref().map(|url| format!("worker of {}", url))
+    };
     let api = if args.api_only {
         "API-only"
     } else if args.no_api {
@@ -55,20 +329,66 @@ fn print_scan_plan(args: &Args) -> Result<()> {
             serde_json::json!({
                 "target_start": start, "target_end": end, "ports": ports,
                 "port_expression": args.ports, "mode": mode,
-                "concurrency": args.concurrency, "geo_concurrency": args.geo_concurrency,
-                "service_probing": args.probe_service, "database": args.database, "api": api
+                "concurrency": args.concurrency, "host_concurrency": args.host_concurrency,
+                "geo_concurrency": args.geo_concurrency,
+                "service_probing": args.probe_service, "database": args.database, "api": api,
+                "scan_window": args.scan_window, "exclude_file": args.exclude_file,
+                "exclude_country": args.exclude_country, "input_file": args.input_file,
+                "script": args.script, "report_email": args.report_email,
+                "notify": args.notify.iter().map(|n| &n.kind).collect::<Vec<_>>(),
+                "mqtt": args.mqtt.host, "syslog": args.syslog.host, "cluster": cluster,
+                "lease_size": args.lease_size, "priority_weights": args.priority_weights
             })
         );
     } else {
         println!("Resolved scan plan:");
-        println!("  target: {} - {}", start, end);
+        match &args.input_file {
+            Some(path) => println!("  target: addresses in {}", path),
+            None => println!("  target: {} - {}", start, end),
+        }
         println!("  ports: {} ({} ports)", args.ports, ports.len());
         println!("  mode: {}", mode);
+        if let Some(cluster) = &cluster {
+            println!("  cluster: {} (lease size {})", cluster, args.lease_size);
+        }
         println!("  concurrency: {}", args.concurrency);
+        println!("  host concurrency: {}", args.host_concurrency);
         println!("  geo concurrency: {}", args.geo_concurrency);
         println!("  service probing: {}", args.probe_service);
         println!("  database: {}", args.database);
         println!("  api: {}", api);
+        if let Some(window) = &args.scan_window {
+            println!("  scan window: {}", window);
+        }
+        if !args.priority_weights.is_empty() {
+            let weights: Vec<String> = args.priority_weights.iter().map(u32::to_string).collect();
+            println!("  priority weights: {}", weights.join(","));
+        }
+        if let Some(path) = &args.exclude_file {
+            println!("  exclude file: {}", path);
+        }
+        if !args.exclude_country.is_empty() {
+            println!("  exclude countries: {}", args.exclude_country.join(","));
+        }
+        if let Some(path) = &args.script {
+            println!("  script: {}", path);
+        }
+        if !args.report_email.is_empty() {
+            println!("  report email: {}", args.report_email.join(", "));
+        }
+        if !args.notify.is_empty() {
+            let kinds: Vec<&str> = args.notify.iter().map(|n| n.kind.as_str()).collect();
+            println!("  notify: {}", kinds.join(", "));
+        }
+        if let Some(host) = &args.mqtt.host {
+            println!("  mqtt: {}:{}", host, args.mqtt.port);
+        }
+        if let Some(host) = &args.syslog.host {
+            println!(
+                "  syslog: {}:{} ({}, {})",
+                host, args.syslog.port, args.syslog.protocol, args.syslog.format
+            );
+        }
     }
     Ok(())
 }
@@ -82,69 +402,307 @@ async fn async_main(args: Args) -> Result<()> {
             .with_thread_ids(true)
             .with_file(true)
             .with_line_number(true)
+            .with_ansi(!args.daemon)
     } else {
         tracing_subscriber::fmt()
-            .with_max_level(Level::INFO)
+            .with_max_level(if args.quiet { Level::WARN } else { Level::INFO })
             .with_target(false)
+            .with_ansi(!args.daemon)
     };
 
-    log_format.init();
+    // Keep stdout for the JSON document a wrapping job parses.
+    if args.json_summary {
+        log_format.with_writer(std::io::stderr).init();
+    } else {
+        log_format.init();
+    }
 
-    // Setup Ctrl+C handler
-    let shutdown_signal = tokio::signal::ctrl_c();
+    // Setup Ctrl+C / SIGTERM handler
+    let shutdown_signal = shutdown_signal();
 
     // Determine running mode and run with graceful shutdown
-    let result = if args.api_only {
+    let result = if args.coordinator {
+        info!("Starting in coordinator mode");
+        run_coordinator(&args).await
+    } else if args.worker.is_some() {
+        info!("Starting in worker mode");
+        let shutdown_flag = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        spawn_shutdown_listener(shutdown_flag.clone());
+        systemd::notify_ready();
+        service::run_worker(&args, shutdown_flag).await
+    } else if args.api_only {
         info!("Starting in API-only mode");
         tokio::select! {
             result = run_api_server(&args) => result,
             _ = shutdown_signal => {
-                info!("Received Ctrl+C, shutting down gracefully...");
+                info!("Received shutdown signal, shutting down gracefully...");
                 Ok(())
             }
         }
     } else if args.no_api {
+        // Scanner modes handle the signal themselves so the pipeline can drain
+        // and persist progress before the runtime is torn down.
         info!("Starting in scanner-only mode");
-        tokio::select! {
-            result = run_scanner(&args) => result,
-            _ = shutdown_signal => {
-                info!("Received Ctrl+C, shutting down gracefully...");
-                Ok(())
-            }
-        }
+        run_scanner(&args).await
     } else if args.api {
         info!("Starting in combined mode (scanner + API)");
-        tokio::select! {
-            result = run_combined(&args) => result,
-            _ = shutdown_signal => {
-                info!("Received Ctrl+C, shutting down gracefully...");
-                Ok(())
-            }
-        }
+        run_combined(&args).await
     } else {
         info!("Starting in API-only mode (default)");
         tokio::select! {
             result = run_api_server(&args) => result,
             _ = shutdown_signal => {
-                info!("Received Ctrl+C, shutting down gracefully...");
+                info!("Received shutdown signal, shutting down gracefully...");
                 Ok(())
             }
         }
     };
 
+    systemd::notify_stopping();
     result
 }
 
+/// Resolve on Ctrl+C, or on SIGTERM where available (`ip-scan stop`, systemd).
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Set `flag` on the first shutdown signal so the scan loop can stop gracefully;
+/// a second signal exits immediately without waiting for the drain.
+fn spawn_shutdown_listener(flag: std::sync::Arc<std::sync::atomic::AtomicBool>) {
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Received shutdown signal, draining scan pipeline (repeat to force exit)...");
+        systemd::notify_stopping();
+        flag.store(true, std::sync::atomic::Ordering::SeqCst);
+
+        shutdown_signal().await;
+        error!("Received second shutdown signal, exiting without flushing");
+        std::process::exit(130);
+    });
+}
+
+/// The end-of-round tables, or JSON line, on stdout; a failed query only
+/// costs the summary. `--quiet` and `--json-summary` leave them out.
+fn print_round_summary(db: &SqliteDB, args: &Args, round: i64, metrics: &model::ScanMetrics) {
+    if args.quiet || args.json_summary {
+        return;
+    }
+    let format = match service::SummaryFormat::parse(&args.summary_format) {
+        Ok(format) => format,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    match service::RoundSummary::collect(db, round, metrics) {
+        Ok(summary) => summary.print(format),
+        Err(e) => error!("Failed to summarize round {}: {}", round, e),
+    }
+}
+
+/// `--json-summary`: the run's totals as one JSON document on stdout.
+fn print_run_summary(
+    db: &SqliteDB,
+    args: &Args,
+    mut run: service::RunSummary,
+    result: &Result<()>,
+    interrupted: bool,
+) {
+    if args.json_summary {
+        run.finish(db, result, interrupted);
+        println!("{}", run.render());
+    }
+}
+
+/// Publish latency percentiles, probe reply ratios, queue depths and the
+/// per-port / per-prefix breakdown for `/scan/status` and `/metrics`, which run in the
+/// API process and can only see the database.
+fn save_metrics_snapshot(db: &SqliteDB, metrics: &model::ScanMetrics) {
+    let latency = serde_json::json!({
+        "connect": metrics.connect_latency(),
+        "syn_rtt": metrics.syn_rtt(),
+    });
+    let breakdown = serde_json::json!({
+        "ports": metrics.top_ports(20),
+        "prefixes": metrics.top_prefixes(20),
+    });
+    let replies = serde_json::to_value(metrics.reply_stats()).unwrap_or_default();
+    let queues = serde_json::to_value(metrics.queue_stats()).unwrap_or_default();
+    for (key, value) in [
+        ("latency_stats", latency),
+        ("metrics_breakdown", breakdown),
+        ("reply_stats", replies),
+        ("queue_stats", queues),
+    ] {
+        if let Err(e) = db.save_metadata(key, &value.to_string()) {
+            error!("Failed to save {}: {}", key, e);
+        }
+    }
+}
+
+/// Block until the local time is inside `window`, recording the expected resume
+/// time in `scan_window_wait_until` so `/scan/status` can report the wait.
+/// Returns `false` if a shutdown was requested while waiting.
+async fn wait_for_scan_window(
+    window: model::ScanWindow,
+    db: &SqliteDB,
+    maintenance: &service::Maintenance,
+    shutdown_flag: &std::sync::atomic::AtomicBool,
+) -> bool {
+    let now = chrono::Local::now();
+    let wait = window.wait_from(now.time());
+    if wait.is_zero() {
+        return true;
+    }
+    let resume_at = now + chrono::Duration::from_std(wait).unwrap_or_default();
+    info!(
+        "Outside scan window {}, pausing until {}",
+        window,
+        resume_at.format("%Y-%m-%d %H:%M")
+    );
+    systemd::notify_status(&format!("Waiting for scan window {}", window));
+    if let Err(e) = db.save_metadata("scan_window_wait_until", &resume_at.to_rfc3339()) {
+        error!("Failed to record scan window wait: {}", e);
+    }
+
+    // Re-check the clock every second instead of sleeping until `resume_at`,
+    // so wall-clock adjustments and shutdown requests are honoured promptly.
+    // The scanner is idle meanwhile, so due maintenance runs once a minute.
+    let mut proceed = true;
+    let mut next_maintenance = std::time::Instant::now();
+    while !window.contains(chrono::Local::now().time()) {
+        if shutdown_flag.load(std::sync::atomic::Ordering::SeqCst) {
+            proceed = false;
+            break;
+        }
+        systemd::heartbeat();
+        if std::time::Instant::now() >= next_maintenance {
+            maintenance.run_idle(db).await;
+            next_maintenance = std::time::Instant::now() + std::time::Duration::from_secs(60);
+        }
+        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    }
+
+    if let Err(e) = db.save_metadata("scan_window_wait_until", "") {
+        error!("Failed to clear scan window wait: {}", e);
+    }
+    if proceed {
+        info!("Scan window {} open, resuming", window);
+    }
+    proceed
+}
+
+/// `ip-scan db rekey`: export the database under `key` (empty for none)
+/// next to the original, then swap it in. The copy is complete before the
+/// original is replaced, so an interrupted rekey leaves the old file intact.
+fn rekey_database(args: &Args, key: &str) -> Result<()> {
+    if key.is_empty() && args.db_key.is_none() {
+        return Err(anyhow::anyhow!(
+            "{} is not encrypted (no --db-key given)",
+            args.database
+        ));
+    }
+    if !std::path::Path::new(&args.database).exists() {
+        return Err(anyhow::anyhow!("Database {} does not exist", args.database));
+    }
+    let staged = format!("{}.rekey", args.database);
+    let db = args.open_database()?;
+    db.export_rekeyed(&staged, key)?;
+    drop(db);
+    // The export holds every committed row; the old WAL must not be
+    // replayed onto the new file.
+    for suffix in ["-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", args.database, suffix));
+    }
+    std::fs::rename(&staged, &args.database)?;
+    let action = match (args.db_key.is_some(), key.is_empty()) {
+        (_, true) => "Decrypted",
+        (false, false) => "Encrypted",
+        (true, false) => "Re-encrypted",
+    };
+    println!("{} {}", action, args.database);
+    Ok(())
+}
+
 /// Run only the API server
 async fn run_api_server(args: &Args) -> Result<()> {
     info!("API Server starting on {}:{}", args.api_host, args.api_port);
 
     // Initialize database
-    let db = SqliteDB::new(&args.database)?;
+    let db = args.open_database()?;
     info!("Database initialized: {}", args.database);
+    service::WalCheckpointer::from_args(args).spawn(db.clone());
+
+    // Without a scan loop there is nothing else to feed the systemd watchdog;
+    // a responsive runtime is the liveness signal.
+    if let Some(interval) = systemd::watchdog_interval() {
+        tokio::spawn(async move {
+            loop {
+                systemd::heartbeat();
+                tokio::time::sleep(interval / 4).await;
+            }
+        });
+    }
 
     // Start API server without a CLI-managed scanner.
-    start_api_server(db, args, service::RuntimeScanState::default()).await
+    start_api_server(
+        db,
+        args,
+        service::RuntimeScanState::default(),
+        None,
+        geo_service(args),
+    )
+    .await
+}
+
+/// Run the API server with the cluster lease endpoints; workers do the scanning.
+async fn run_coordinator(args: &Args) -> Result<()> {
+    let db = args.open_database()?;
+    info!("Database initialized: {}", args.database);
+    service::WalCheckpointer::from_args(args).spawn(db.clone());
+    if args.cluster_token.is_none() {
+        warn!("No --cluster-token set; anyone who can reach the API can lease work and submit results");
+    }
+    let (event_bus, event_sinks) = spawn_event_sinks(args, &db)?;
+    let coordinator = std::sync::Arc::new(service::Coordinator::new(
+        db.clone(),
+        args,
+        event_bus.clone(),
+    )?);
+    let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let rounds = coordinator.clone().spawn(stop.clone());
+
+    let result = start_api_server(
+        db,
+        args,
+        service::RuntimeScanState::default(),
+        Some(coordinator),
+        geo_service(args),
+    )
+    .await;
+    stop.store(true, std::sync::atomic::Ordering::SeqCst);
+    let _ = rounds.await;
+    drain_event_sinks(event_bus, event_sinks).await;
+    result
 }
 
 /// Run only the scanner
@@ -160,19 +718,35 @@ async fn run_scanner(args: &Args) -> Result<()> {
         args.concurrency, args.timeout, args.database, args.loop_mode, args.ipv4, args.ipv6, args.only_store_open, args.skip_private);
 
     // Initialize bitmap database
-    let db = SqliteDB::new(&args.database)?;
+    let db = args.open_database()?;
     info!("Database initialized");
+    service::WalCheckpointer::from_args(args).spawn(db.clone());
+    systemd::notify_ready();
 
-    // Initialize GeoService
-    let geo_service = if !args.no_geo {
-        info!("Initializing GeoIP service...");
-        Some(GeoService::new(args.geoip_db.as_deref()))
-    } else {
-        info!("GeoIP lookup disabled");
-        None
-    };
+    let (event_bus, event_sinks) = spawn_event_sinks(args, &db)?;
+    let geo = geo_service(args).map(|geo| {
+        geo.spawn_enrichment_worker(db.clone(), args.geo_concurrency, event_bus.clone())
+    });
 
-    run_scanner_logic(db, args, geo_service).await
+    let shutdown_flag = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    spawn_shutdown_listener(shutdown_flag.clone());
+
+    let mut run = service::RunSummary::new(&args.database);
+    let result = run_scanner_logic(
+        db.clone(),
+        args,
+        event_bus.clone(),
+        shutdown_flag.clone(),
+        &mut run,
+    )
+    .await;
+    if let Some(geo) = geo {
+        geo.shutdown().await;
+    }
+    drain_event_sinks(event_bus, event_sinks).await;
+    let interrupted = shutdown_flag.load(std::sync::atomic::Ordering::SeqCst);
+    print_run_summary(&db, args, run, &result, interrupted);
+    result
 }
 
 /// Run both scanner and API server
@@ -180,8 +754,9 @@ async fn run_combined(args: &Args) -> Result<()> {
     info!("Starting combined scanner and API server");
 
     // Initialize database
-    let db = SqliteDB::new(&args.database)?;
+    let db = args.open_database()?;
     info!("Database initialized: {}", args.database);
+    service::WalCheckpointer::from_args(args).spawn(db.clone());
 
     // Start scanner in background and expose its lifecycle to the API. This
     // prevents the API controller from reporting Idle or starting a second scan.
@@ -189,16 +764,34 @@ async fn run_combined(args: &Args) -> Result<()> {
     db.save_metadata("last_scan_start_time", &chrono::Utc::now().to_rfc3339())?;
     let runtime_scan_state = service::RuntimeScanState::with_cli_scan_running(true);
     let scanner_state = runtime_scan_state.clone();
+    let shutdown_flag = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    spawn_shutdown_listener(shutdown_flag.clone());
+    // Enrichment lives next to the API rather than inside the scanner task,
+    // so it keeps working through pauses between rounds and is shut down
+    // with the process.
+    // The API's on-demand lookups share its provider budgets.
+    let (event_bus, event_sinks) = spawn_event_sinks(args, &db)?;
+    let geo_lookups = geo_service(args);
+    let geo = geo_lookups.as_ref().map(|geo| {
+        geo.spawn_enrichment_worker(db.clone(), args.geo_concurrency, event_bus.clone())
+    });
+    let scanner_events = event_bus.clone();
+    let scanner_shutdown = shutdown_flag.clone();
     let scanner_args = args.clone();
     let scanner_db = db.clone();
     let scanner_status_db = db.clone();
     let scanner_handle = tokio::spawn(async move {
-        let geo = if !scanner_args.no_geo {
-            Some(GeoService::new(scanner_args.geoip_db.as_deref()))
-        } else {
-            None
-        };
-        let result = run_scanner_logic(scanner_db, &scanner_args, geo).await;
+        let mut run = service::RunSummary::new(&scanner_args.database);
+        let result = run_scanner_logic(
+            scanner_db,
+            &scanner_args,
+            scanner_events,
+            scanner_shutdown.clone(),
+            &mut run,
+        )
+        .await;
+        let interrupted = scanner_shutdown.load(std::sync::atomic::Ordering::SeqCst);
+        print_run_summary(&scanner_status_db, &scanner_args, run, &result, interrupted);
         scanner_state.set_cli_scan_running(false);
         let final_status = if result.is_ok() { "stopped" } else { "error" };
         let _ = scanner_status_db.save_metadata("scan_status", final_status);
@@ -210,18 +803,29 @@ async fn run_combined(args: &Args) -> Result<()> {
     });
 
     // Start API server (in current task, not spawned)
-    let api_task = start_api_server(db, args, runtime_scan_state);
+    let api_task = start_api_server(db, args, runtime_scan_state, None, geo_lookups);
 
     // Wait for either scanner to complete or API server
-    tokio::select! {
-        _ = scanner_handle => {
+    let mut scanner_handle = scanner_handle;
+    let result = tokio::select! {
+        _ = &mut scanner_handle => {
             info!("Scanner finished");
             Ok(())
         }
         result = api_task => {
+            // The API server stops on SIGINT/SIGTERM (or failed to start);
+            // either way, let the scanner flush before the runtime goes away.
+            shutdown_flag.store(true, std::sync::atomic::Ordering::SeqCst);
+            info!("API server stopped, waiting for scanner to flush results...");
+            let _ = scanner_handle.await;
             result
         }
+    };
+    if let Some(geo) = geo {
+        geo.shutdown().await;
     }
+    drain_event_sinks(event_bus, event_sinks).await;
+    result
 }
 
 /// Start the API server
@@ -229,20 +833,63 @@ async fn start_api_server(
     db: SqliteDB,
     args: &Args,
     runtime_scan_state: service::RuntimeScanState,
+    coordinator: Option<std::sync::Arc<service::Coordinator>>,
+    geo: Option<GeoService>,
 ) -> Result<()> {
     use crate::service::ScanController;
     use actix_cors::Cors;
     use actix_files::Files;
     use actix_web::{web, App, HttpServer};
-    use std::sync::Arc;
     use utoipa::OpenApi;
 
     let db_data = web::Data::new(db.clone());
+    // `POST /geo/enrich` jobs; refused with GEO_DISABLED under --no-geo.
+    let geo_jobs_data =
+        geo.map(|geo| web::Data::new(service::GeoJobs::new(geo, db.clone(), args.geo_concurrency)));
+    let attached_data = web::Data::new(api::AttachedDatabases::open(args)?);
+    // Resolve --exclude-country now; scans started later reuse the result.
+    if !args.exclude_country.is_empty() {
+        let args = args.clone();
+        tokio::task::spawn_blocking(move || args.load_exclude_list()).await??;
+    }
+    let namespaces_data = web::Data::new(service::Namespaces::open(db.clone(), args)?);
 
-    // Create global scan controller singleton with async-aware mutex
-    let scan_controller = Arc::new(tokio::sync::Mutex::new(ScanController::new(db)));
-    let controller_data = web::Data::new(scan_controller);
+    // Global scan controller; it synchronizes its own state
+    let controller_data = web::Data::new(ScanController::new(db.clone()));
     let runtime_scan_data = web::Data::new(runtime_scan_state);
+    // API scans start from the server's own configuration.
+    let args_data = web::Data::new(args.clone());
+    // A coordinator's rounds are driven by its workers; it does not know
+    // when the cluster is idle.
+    let maintenance = service::Maintenance::from_args(args);
+    if maintenance.is_enabled() && coordinator.is_none() {
+        maintenance.spawn(
+            db,
+            controller_data.clone().into_inner(),
+            runtime_scan_data.get_ref().clone(),
+        );
+    }
+    let coordinator_data = coordinator.map(web::Data::from);
+    let allowlist_data = args
+        .api_allowlist()?
+        .map(|networks| web::Data::new(api::ClientAllowlist(networks)));
+    let trusted_proxies_data = args
+        .api_trusted_proxies()?
+        .map(|networks| web::Data::new(api::TrustedProxies(networks)));
+    let read_only = args.api_read_only;
+    if read_only {
+        info!("API is read-only: scan control, template changes and admin routes are disabled");
+    }
+    let quotas_data = args
+        .quotas
+        .enabled
+        .then(|| web::Data::new(api::Quotas(args.quotas.clone())));
+    if allowlist_data.is_some() {
+        info!(
+            "API only answers clients from: {}",
+            args.api_allow_cidr.join(", ")
+        );
+    }
 
     // Get OpenAPI documentation
     let openapi = api::ApiDoc::openapi();
@@ -251,10 +898,19 @@ async fn start_api_server(
     let swagger_ui_enabled = args.swagger_ui || args.api || args.api_only;
     let api_host = args.api_host.clone();
     let api_port = args.api_port;
+    let tls_config = api::tls_server_config(args)?;
+    let scheme = if tls_config.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    if args.api_client_ca.is_some() {
+        info!("API requires client certificates issued by a CA in the --api-client-ca bundle");
+    }
 
     info!("Starting HTTP server on {}:{}", api_host, api_port);
 
-    let mut server = HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
@@ -263,10 +919,32 @@ async fn start_api_server(
 
         let mut app = App::new()
             .wrap(cors)
+            .wrap(actix_web::middleware::from_fn(api::check_client))
             .app_data(db_data.clone())
+            .app_data(attached_data.clone())
+            .app_data(namespaces_data.clone())
             .app_data(controller_data.clone())
             .app_data(runtime_scan_data.clone())
+            .app_data(args_data.clone())
             .configure(api::init_routes);
+        if let Some(coordinator) = &coordinator_data {
+            app = app.app_data(coordinator.clone());
+        }
+        if let Some(geo_jobs) = &geo_jobs_data {
+            app = app.app_data(geo_jobs.clone());
+        }
+        if let Some(allowlist) = &allowlist_data {
+            app = app.app_data(allowlist.clone());
+        }
+        if let Some(proxies) = &trusted_proxies_data {
+            app = app.app_data(proxies.clone());
+        }
+        if read_only {
+            app = app.app_data(web::Data::new(api::ReadOnlyApi));
+        }
+        if let Some(quotas) = &quotas_data {
+            app = app.app_data(quotas.clone());
+        }
 
         if swagger_ui_enabled {
             let openapi_clone = openapi.clone();
@@ -291,16 +969,22 @@ async fn start_api_server(
     });
 
     // Bind to specified address and port
-    server = server.bind((api_host.as_str(), api_port))?;
+    let server = match tls_config {
+        Some(config) => server
+            .on_connect(api::on_connect)
+            .bind_rustls_0_23((api_host.as_str(), api_port), config)?,
+        None => server.bind((api_host.as_str(), api_port))?,
+    };
+    systemd::notify_ready();
 
     info!("API server started successfully");
     info!(
-        "API endpoints: http://{}:{}/api/v1/",
-        args.api_host, args.api_port
+        "API endpoints: {}://{}:{}/api/v1/",
+        scheme, args.api_host, args.api_port
     );
     info!(
-        "OpenAPI JSON: http://{}:{}/api-docs/openapi.json",
-        args.api_host, args.api_port
+        "OpenAPI JSON: {}://{}:{}/api-docs/openapi.json",
+        scheme, args.api_host, args.api_port
     );
 
     server.run().await?;
@@ -308,100 +992,78 @@ async fn start_api_server(
     Ok(())
 }
 
-/// Scanner logic (extracted from original main function)
-async fn enrich_discovered_assets(
+/// Probe one batch of open ports that have not been service-probed yet.
+/// HTTP(S) ports are probed again as each hostname known for the IP, from
+/// `sni_hosts` and, with `sni_from_rdns`, its reverse-DNS name. With `cves`,
+/// each probed port's candidate CVEs are replaced by the current matches.
+async fn probe_discovered_services(
     db: &SqliteDB,
-    geo: Option<&GeoService>,
-    args: &Args,
+    prober: &service::ServiceProber,
+    sni_hosts: &std::sync::Arc<model::HostnameList>,
+    sni_from_rdns: bool,
+    cves: Option<&std::sync::Arc<service::CveIndex>>,
 ) -> Result<()> {
-    let mut jobs = tokio::task::JoinSet::new();
-    if let Some(geo) = geo {
-        let ips = db.get_ips_missing_geo(256)?;
-        let geo_concurrency = args.geo_concurrency;
-        let geo = geo.clone();
+    let ip_ports = db.get_ips_missing_service_probe(128)?;
+    let attempted_ips: Vec<String> = ip_ports.iter().map(|(ip, _)| ip.clone()).collect();
+    let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(16));
+    let mut tasks = tokio::task::JoinSet::new();
+    for (ip, ports) in ip_ports {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let prober = prober.clone();
         let db = db.clone();
-        jobs.spawn(async move {
-            // Geo/WHOIS/DNS are external I/O; cap them independently from
-            // port concurrency to avoid overwhelming the resolver or provider.
-            let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(geo_concurrency));
-            let mut tasks: tokio::task::JoinSet<Result<Option<crate::model::IpGeoInfo>>> =
-                tokio::task::JoinSet::new();
-            for ip in ips {
-                let permit = semaphore.clone().acquire_owned().await.unwrap();
-                let geo = geo.clone();
-                tasks.spawn(async move {
-                    let _permit = permit;
-                    match tokio::time::timeout(tokio::time::Duration::from_secs(6), geo.lookup(&ip))
-                        .await
-                    {
-                        Ok(Ok(info)) => Ok(Some(info)),
-                        Ok(Err(_)) | Err(_) => Ok(None),
-                    }
-                });
-            }
-            let mut infos = Vec::new();
-            while let Some(result) = tasks.join_next().await {
-                if let Some(info) = result?? {
-                    infos.push(info);
+        let sni_hosts = sni_hosts.clone();
+        let cves = cves.cloned();
+        tasks.spawn(async move {
+            let _permit = permit;
+            let banners = db.get_port_banners(&ip)?;
+            let services = prober.probe_ip_with_banners(&ip, &ports, &banners).await;
+            db.save_service_info_batch(&services)?;
+            if let Some(cves) = &cves {
+                for info in &services {
+                    db.replace_port_cves(&info.ip, info.port, &cves.match_service(info))?;
                 }
             }
-            db.save_ip_geo_info_batch(&infos)?;
-            Ok::<(), anyhow::Error>(())
-        });
-    }
-    if args.probe_service {
-        let ip_ports = db.get_ips_missing_service_probe(128)?;
-        let attempted_ips: Vec<String> = ip_ports.iter().map(|(ip, _)| ip.clone()).collect();
-        let db = db.clone();
-        let prober = service::ServiceProber::new(args.probe_timeout, args.probe_concurrency);
-        jobs.spawn(async move {
-            let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(16));
-            let mut tasks = tokio::task::JoinSet::new();
-            for (ip, ports) in ip_ports {
-                let permit = semaphore.clone().acquire_owned().await.unwrap();
-                let prober = prober.clone();
-                let db = db.clone();
-                tasks.spawn(async move {
-                    let _permit = permit;
-                    let services = prober.probe_ip(&ip, &ports).await;
-                    db.save_service_info_batch(&services)?;
-                    Ok::<(), anyhow::Error>(())
-                });
+
+            let mut hostnames = ip
+                .parse()
+                .map(|addr| sni_hosts.get(addr).to_vec())
+                .unwrap_or_default();
+            if sni_from_rdns {
+                let rdns = db.get_ip_geo_info(&ip)?.and_then(|geo| geo.reverse_dns);
+                if let Some(name) = rdns.filter(|name| model::is_hostname(name)) {
+                    let name = name.trim_end_matches('.').to_ascii_lowercase();
+                    if !hostnames.contains(&name) {
+                        hostnames.push(name);
+                    }
+                }
             }
-            while let Some(result) = tasks.join_next().await {
-                result??;
+            if !hostnames.is_empty() {
+                let vhosts = prober.probe_hostnames(&ip, &ports, &hostnames).await;
+                db.save_service_vhosts(&vhosts)?;
             }
-            db.mark_service_probe_attempts(&attempted_ips)?;
             Ok::<(), anyhow::Error>(())
         });
     }
-    while let Some(result) = jobs.join_next().await {
+    while let Some(result) = tasks.join_next().await {
         result??;
     }
+    db.mark_service_probe_attempts(&attempted_ips)?;
     Ok(())
 }
 
 async fn run_scanner_logic(
     db: SqliteDB,
     args: &Args,
-    geo_service: Option<GeoService>,
+    event_bus: Option<service::EventBus>,
+    shutdown_flag: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    run: &mut service::RunSummary,
 ) -> Result<()> {
     use model::{parse_port_range, IpRange};
-    use service::{ConScanner, SynScanner};
-    use std::sync::atomic::{AtomicBool, Ordering};
-    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
 
-    // Setup shutdown flag
-    let shutdown_flag = Arc::new(AtomicBool::new(false));
-    let shutdown_flag_clone = shutdown_flag.clone();
-
-    // Setup Ctrl+C handler for scanner
-    tokio::spawn(async move {
-        if tokio::signal::ctrl_c().await.is_ok() {
-            info!("Scanner received Ctrl+C, initiating shutdown...");
-            shutdown_flag_clone.store(true, Ordering::SeqCst);
-        }
-    });
+    if args.rescan_open {
+        return rescan_known_open_ports(&db, args, shutdown_flag, run).await;
+    }
 
     // Check for previous scan progress
     let (mut current_round, mut resume_ip, mut resume_ip_type) = match db.get_progress()? {
@@ -427,27 +1089,98 @@ async fn run_scanner_logic(
         }
         None => {
             info!("No previous scan progress found, starting fresh scan");
-            (1, None, None)
+            (db.get_current_round()?, None, None)
         }
     };
 
     // Parse port range
-    let ports = parse_port_range(&args.ports).map_err(|e| anyhow::anyhow!(e))?;
+    let ports = parse_port_range(&args.ports, &args.port_groups).map_err(|e| anyhow::anyhow!(e))?;
     info!("Scanning {} ports: {:?}", ports.len(), ports);
 
-    // Enrichment consumes newly persisted open ports during the scan.
+    let scan_window = args.parsed_scan_window()?;
+    db.save_metadata(
+        "scan_window",
+        &scan_window.map(|w| w.to_string()).unwrap_or_default(),
+    )?;
+    db.save_metadata("scan_window_wait_until", "")?;
+
+    let exclude_list = args.load_exclude_list()?.map(std::sync::Arc::new);
+    let script_hooks = args.load_script_hooks()?.map(std::sync::Arc::new);
+    let reporter =
+        service::EmailReporter::from_config(&args.report_email_config, &args.report_email)?
+            .map(std::sync::Arc::new);
+    if let Some(path) = &args.script {
+        info!("Running open-port script hooks from {}", path);
+    }
+    if let (Some(list), Some(path)) = (&exclude_list, &args.exclude_file) {
+        info!("Excluding {} address ranges from {}", list.len(), path);
+    }
+
+    // Enrichment consumes newly persisted open ports during the scan. Geo
+    // lookups run in their own long-lived worker (see `geo_service`), so slow providers never hold back service
+    // probing (and vice versa).
     let enrichment_stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
-    let enrichment_handle = if geo_service.is_some() || args.probe_service {
+    let reputation_handle = match service::ReputationService::from_args(args)? {
+        Some(reputation) => {
+            info!(
+                "Reputation lookups enabled: {} ({} per hour each)",
+                args.reputation_providers.join(", "),
+                args.reputation_rate
+            );
+            Some(reputation.spawn_worker(db.clone(), enrichment_stop.clone()))
+        }
+        None => None,
+    };
+    if args.cve_db.is_some() && !args.probe_service {
+        warn!("--cve-db has no effect without --probe-service");
+    }
+    let probe_handle = if args.probe_service {
         let db_worker = db.clone();
-        let geo_worker = geo_service.clone();
-        let args_worker = args.clone();
+        // One prober for the whole run, so its concurrency and rate limits
+        // hold across polling batches.
+        let prober = service::ServiceProber::new(args.probe_timeout, args.probe_concurrency)
+            .with_rate_limit(args.probe_rate);
+        let sni_hosts = std::sync::Arc::new(args.load_sni_hosts()?);
+        let sni_from_rdns = args.sni_from_rdns;
+        let cve_db = args.cve_db.clone();
         let stop_worker = enrichment_stop.clone();
         Some(tokio::spawn(async move {
+            // A full NVD dataset takes a while to parse; probing starts
+            // once it is loaded, the scan does not wait for it.
+            let cves = match cve_db {
+                Some(path) => {
+                    let loaded = tokio::task::spawn_blocking(move || {
+                        service::CveIndex::load(std::path::Path::new(&path))
+                    })
+                    .await;
+                    match loaded {
+                        Ok(Ok(index)) => {
+                            info!("Loaded CVE data for {} products", index.len());
+                            Some(std::sync::Arc::new(index))
+                        }
+                        Ok(Err(e)) => {
+                            error!("CVE mapping disabled: {:#}", e);
+                            None
+                        }
+                        Err(e) => {
+                            error!("CVE mapping disabled: {}", e);
+                            None
+                        }
+                    }
+                }
+                None => None,
+            };
             while !stop_worker.load(std::sync::atomic::Ordering::Relaxed) {
-                if let Err(e) =
-                    enrich_discovered_assets(&db_worker, geo_worker.as_ref(), &args_worker).await
+                if let Err(e) = probe_discovered_services(
+                    &db_worker,
+                    &prober,
+                    &sni_hosts,
+                    sni_from_rdns,
+                    cves.as_ref(),
+                )
+                .await
                 {
-                    error!("Background enrichment failed: {}", e);
+                    error!("Background service probing failed: {}", e);
                 }
                 tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
             }
@@ -456,6 +1189,25 @@ async fn run_scanner_logic(
         None
     };
 
+    let mut priority = service::PriorityScheduler::new(args.priority_weights.clone());
+    let maintenance = service::Maintenance::from_args(args);
+    // Samples run on across rounds, so charts show the gaps between them.
+    let timeseries = service::MetricsTimeseries::new(args.timeseries_minutes);
+
+    if let Some(path) = &args.seed_domains {
+        let seeds = args.load_seed_domains()?;
+        info!(
+            "Seeding targets from {}: {} domains ({} entries skipped){}",
+            path,
+            seeds.len(),
+            seeds.skipped(),
+            match args.seed_scope() {
+                Some(scope) => format!(", limited to {} - {}", scope.start, scope.end),
+                None => String::new(),
+            }
+        );
+    }
+
     loop {
         // Check shutdown flag
         if shutdown_flag.load(Ordering::SeqCst) {
@@ -463,7 +1215,16 @@ async fn run_scanner_logic(
             break;
         }
 
+        if let Some(window) = scan_window {
+            if !wait_for_scan_window(window, &db, &maintenance, &shutdown_flag).await {
+                info!("Shutdown requested while waiting for scan window");
+                break;
+            }
+        }
+
         info!("=== Starting scan round {} ===", current_round);
+        systemd::heartbeat();
+        systemd::notify_status(&format!("Scanning round {}", current_round));
 
         // Mark round as in progress
         db.save_metadata(&format!("round_{}_complete", current_round), "false")?;
@@ -477,12 +1238,43 @@ async fn run_scanner_logic(
                 .map(|(s, e)| (s.clone(), e.clone()))
                 .unwrap_or_else(Args::get_default_ipv4_range);
 
-            // Resume from last position if applicable
-            let actual_start_ip = if resume_ip_type.as_deref() == Some("IPv4") {
+            // Hostname targets are resolved again every round, so the scan
+            // follows DNS changes between rounds.
+            let hostnames = args.scan_hostnames();
+            // Likewise the --input-file list, so it can be rewritten between rounds.
+            let inputs = args.load_input_file();
+
+            // Extra probes for recently changed hosts are planned over the
+            // whole range so a resumed round keeps the same spacing.
+            let mut rescans = match (
+                start_ip.parse::<std::net::Ipv4Addr>(),
+                end_ip.parse::<std::net::Ipv4Addr>(),
+            ) {
+                (Ok(start), Ok(end))
+                    if matches!(hostnames, Ok(None)) && args.input_file.is_none() =>
+                {
+                    priority.plan(start, end)
+                }
+                _ => service::RescanQueue::default(),
+            };
+            if !rescans.is_empty() {
+                info!(
+                    "Priority rescans: {} extra probes for {} recently changed hosts",
+                    rescans.len(),
+                    priority.boosted_hosts()
+                );
+            }
+
+            // Resume from last position if it lies in the range's family
+            let start_type = match start_ip.parse::<std::net::IpAddr>() {
+                Ok(std::net::IpAddr::V6(_)) => "IPv6",
+                _ => "IPv4",
+            };
+            let actual_start_ip = if resume_ip_type.as_deref() == Some(start_type) {
                 resume_ip
                     .as_ref()
                     .map(|ip| {
-                        info!("Resuming IPv4 from: {}", ip);
+                        info!("Resuming {} from: {}", start_type, ip);
                         ip.clone()
                     })
                     .unwrap_or(start_ip)
@@ -490,21 +1282,124 @@ async fn run_scanner_logic(
                 start_ip
             };
 
-            info!("Scanning IPv4: {} - {}", actual_start_ip, end_ip);
-            match IpRange::new(&actual_start_ip, &end_ip) {
-                Ok(ip_range) => {
+            let targets: Result<(service::TargetIter, usize)> = match (&hostnames, &inputs) {
+                (Err(e), _) | (_, Err(e)) => Err(anyhow::anyhow!("{}", e)),
+                (_, Ok(ranges)) if args.input_file.is_some() => {
+                    // Ranges come merged and in address order, so a resumed
+                    // round skips everything below the saved address.
+                    let resume_from = resume_ip
+                        .as_deref()
+                        .and_then(|ip| ip.parse::<std::net::IpAddr>().ok());
+                    let ranges: Vec<IpRange> = ranges
+                        .iter()
+                        .filter(|range| resume_from.is_none_or(|from| range.end >= from))
+                        .map(|range| IpRange {
+                            start: resume_from.map_or(range.start, |from| range.start.max(from)),
+                            end: range.end,
+                        })
+                        .collect();
+                    let count = ranges
+                        .iter()
+                        .fold(0usize, |count, range| count.saturating_add(range.count()));
+                    info!(
+                        "Scanning {} addresses from {}",
+                        count,
+                        args.input_file.as_deref().unwrap_or_default()
+                    );
+                    Ok((
+                        Box::new(ranges.into_iter().flat_map(|range| range.iter()))
+                            as service::TargetIter,
+                        count,
+                    ))
+                }
+                (Ok(Some(hostnames)), _) => {
+                    // Addresses are scanned in sorted order, so a resumed
+                    // round picks up from the saved address whatever its family.
+                    let resume_from = resume_ip
+                        .as_deref()
+                        .and_then(|ip| ip.parse::<std::net::IpAddr>().ok());
+                    let scope = args.seed_scope();
+                    service::HostResolver::default()
+                        .resolve_targets(&db, hostnames, current_round)
+                        .await
+                        .map(|mut addrs| {
+                            if let Some(scope) = scope {
+                                let resolved = addrs.len();
+                                addrs.retain(|ip| scope.start <= *ip && *ip <= scope.end);
+                                if addrs.len() < resolved {
+                                    info!(
+                                        "Skipping {} resolved addresses outside {} - {}",
+                                        resolved - addrs.len(),
+                                        scope.start,
+                                        scope.end
+                                    );
+                                }
+                            }
+                            info!(
+                                "Scanning {} addresses of {} target hostnames",
+                                addrs.len(),
+                                hostnames.len()
+                            );
+                            addrs.retain(|ip| resume_from.is_none_or(|from| *ip >= from));
+                            let count = addrs.len();
+                            (Box::new(addrs.into_iter()) as service::TargetIter, count)
+                        })
+                }
+                (Ok(None), _) => {
+                    info!("Scanning {}: {} - {}", start_type, actual_start_ip, end_ip);
+                    IpRange::new(&actual_start_ip, &end_ip)
+                        .map(|range| (Box::new(range.iter()) as service::TargetIter, range.count()))
+                        .map_err(|e| anyhow::anyhow!(e))
+                }
+            };
+            match targets {
+                Ok((ip_iter, target_count)) => {
                     let start_time = std::time::Instant::now();
+                    let planned_probes = target_count
+                        .saturating_add(rescans.len())
+                        .saturating_mul(ports.len())
+                        as u64;
 
                     let (tx, rx) = tokio::sync::mpsc::channel(args.pipeline_buffer);
 
                     // Producer Task
                     let args_clone = args.clone();
-                    let ip_iter = ip_range.iter();
+                    let producer_shutdown = shutdown_flag.clone();
+                    let producer_db = db.clone();
+                    let producer_exclude = exclude_list.clone();
+                    let producer_maintenance = maintenance.clone();
                     let producer = tokio::spawn(async move {
-                        for ip in ip_iter {
+                        for (produced, ip) in ip_iter.enumerate() {
+                            // Stop feeding new IPs; dropping `tx` lets the
+                            // scanner drain what is already queued and return.
+                            if producer_shutdown.load(Ordering::Relaxed) {
+                                break;
+                            }
+                            // Pause mid-round when the scan window closes. Checking
+                            // every 1024 IPs keeps clock reads off the hot path.
+                            if let Some(window) = scan_window {
+                                if produced % 1024 == 0
+                                    && !window.contains(chrono::Local::now().time())
+                                    && !wait_for_scan_window(
+                                        window,
+                                        &producer_db,
+                                        &producer_maintenance,
+                                        &producer_shutdown,
+                                    )
+                                    .await
+                                {
+                                    break;
+                                }
+                            }
                             if args_clone.skip_private && Args::is_private_ipv4(&ip.to_string()) {
                                 continue;
                             }
+                            if producer_exclude
+                                .as_ref()
+                                .is_some_and(|list| list.contains(ip))
+                            {
+                                continue;
+                            }
                             // Skip 0.0.0.0/8 range as it's not routable
                             if let std::net::IpAddr::V4(ipv4) = ip {
                                 if ipv4.octets()[0] == 0 {
@@ -514,101 +1409,77 @@ async fn run_scanner_logic(
                             if tx.send(ip).await.is_err() {
                                 break;
                             }
+                            if let std::net::IpAddr::V4(ipv4) = ip {
+                                while let Some(host) = rescans.pop_due(ipv4) {
+                                    if tx.send(host.into()).await.is_err() {
+                                        return;
+                                    }
+                                }
+                            }
                         }
                     });
 
                     // Consumer (Scanner)
                     let current_round_clone = current_round;
 
-                    let metrics = if args.syn {
-                        // SYN Scan Mode
-                        match SynScanner::new(
-                            db.clone(),
-                            current_round,
-                            args.result_buffer,
-                            args.db_batch_size,
-                            args.flush_interval_ms,
-                            args.max_rate,
-                            args.rate_window_secs,
-                        ) {
-                            Ok(scanner) => {
-                                scanner
-                                    .run_pipeline(rx, ports.clone(), move |total_scanned| {
-                                        if total_scanned % 1000 == 0 {
-                                            let elapsed = start_time.elapsed().as_secs_f64();
-                                            let rate = total_scanned as f64 / elapsed;
-                                            info!(
-                                                "IPv4 Progress [R{}]: {} IPs - {:.2} packets/sec",
-                                                current_round_clone, total_scanned, rate
-                                            );
-                                        }
-                                    })
-                                    .await?;
-                                scanner.get_metrics().clone()
-                            }
-                            Err(e) => {
-                                error!("Failed to initialize SYN scanner: {}", e);
-                                error!(
-                                    "提示: SYN 扫描需要 Root/Admin 权限。降级为普通连接扫描模式..."
-                                );
-                                info!("如需使用 SYN 扫描,请使用超级管理员权限重新运行程序:");
-                                #[cfg(target_os = "windows")]
-                                info!("  - Windows: 右键以管理员身份运行");
-                                #[cfg(not(target_os = "windows"))]
-                                info!("  - Linux/macOS: sudo ./ip-scan --syn ...");
-
-                                // 降级为连接扫描
-                                let config = service::ConScannerConfig {
-                                    timeout_ms: args.timeout,
-                                    concurrent_limit: args.concurrency,
-                                    result_buffer: args.result_buffer,
-                                    db_batch_size: args.db_batch_size,
-                                    flush_interval_ms: args.flush_interval_ms,
-                                    max_rate: args.max_rate,
-                                    rate_window_secs: args.rate_window_secs,
-                                };
-                                let scanner = ConScanner::new(db.clone(), current_round, config);
-                                scanner
-                                    .run_pipeline(rx, ports.clone(), move |total_scanned| {
-                                        if total_scanned % 1000 == 0 {
-                                            let elapsed = start_time.elapsed().as_secs_f64();
-                                            let rate = total_scanned as f64 / elapsed;
-                                            info!(
-                                                "IPv4 Progress [R{}]: {} IPs - {:.2} IPs/sec",
-                                                current_round_clone, total_scanned, rate
-                                            );
-                                        }
-                                    })
-                                    .await?;
-                                scanner.get_metrics().clone()
-                            }
-                        }
-                    } else {
-                        // Connect Scan Mode
-                        let config = service::ConScannerConfig {
-                            timeout_ms: args.timeout,
-                            concurrent_limit: args.concurrency,
-                            result_buffer: args.result_buffer,
-                            db_batch_size: args.db_batch_size,
-                            flush_interval_ms: args.flush_interval_ms,
-                            max_rate: args.max_rate,
-                            rate_window_secs: args.rate_window_secs,
+                    let scanner = service::scanner_from_args(
+                        args,
+                        db.clone(),
+                        current_round,
+                        script_hooks.clone(),
+                        tokio_util::sync::CancellationToken::new(),
+                    )?;
+                    let progress_metrics = scanner.get_metrics().clone();
+                    // A single pass has a known end, so it gets a bar with an
+                    // ETA in place of the periodic progress log.
+                    let progress_bar =
+                        if args.loop_mode || args.quiet || args.json_summary || args.daemon {
+                            None
+                        } else {
+                            service::RoundProgressBar::start(
+                                current_round,
+                                planned_probes,
+                                progress_metrics.clone(),
+                            )
                         };
-                        let scanner = ConScanner::new(db.clone(), current_round, config);
-                        scanner
-                            .run_pipeline(rx, ports.clone(), move |total_scanned| {
+                    let log_progress = progress_bar.is_none();
+                    let sampler = timeseries.sample(
+                        current_round,
+                        progress_metrics.clone(),
+                        scanner.rate_limiter().clone(),
+                        Some(db.clone()),
+                    );
+                    let forwarder = event_bus
+                        .as_ref()
+                        .map(|bus| bus.forward(scanner.subscribe()));
+                    let progress_db = db.clone();
+                    scanner
+                        .run_pipeline(
+                            rx,
+                            ports.clone(),
+                            Box::new(move |total_scanned| {
+                                systemd::heartbeat();
                                 if total_scanned % 1000 == 0 {
-                                    let elapsed = start_time.elapsed().as_secs_f64();
-                                    let rate = total_scanned as f64 / elapsed;
-                                    info!(
-                                        "IPv4 Progress [R{}]: {} IPs - {:.2} IPs/sec",
-                                        current_round_clone, total_scanned, rate
-                                    );
+                                    if log_progress {
+                                        let elapsed = start_time.elapsed().as_secs_f64();
+                                        let rate = total_scanned as f64 / elapsed;
+                                        info!(
+                                            "IPv4 Progress [R{}]: {} IPs - {:.2} IPs/sec",
+                                            current_round_clone, total_scanned, rate
+                                        );
+                                    }
+                                    save_metrics_snapshot(&progress_db, &progress_metrics);
                                 }
-                            })
-                            .await?;
-                        scanner.get_metrics().clone()
-                    };
+                            }),
+                        )
+                        .await?;
+                    drop(progress_bar);
+                    drop(sampler);
+                    let metrics = scanner.get_metrics().clone();
+                    scanner.finish().await;
+                    if let Some(forwarder) = forwarder {
+                        forwarder.finish().await;
+                    }
 
                     // Wait for producer
                     let _ = producer.await;
@@ -620,16 +1491,92 @@ async fn run_scanner_logic(
                         start_time.elapsed().as_secs_f64(),
                         total_processed as f64 / start_time.elapsed().as_secs_f64()
                     );
-                    metrics.print_summary();
+                    print_round_summary(&db, args, current_round, &metrics);
+                    run.add_round(current_round, &metrics);
+                    save_metrics_snapshot(&db, &metrics);
+                    let round_metrics = dao::RoundMetrics {
+                        round: current_round,
+                        scanned: metrics.get_scanned(),
+                        open: metrics.get_open(),
+                        errors: metrics.get_errors(),
+                        retries: metrics.get_retries(),
+                        duration_secs: start_time.elapsed().as_secs_f64(),
+                        avg_rate: 0.0,
+                        finished_at: String::new(),
+                    };
+                    if let Err(e) = db.save_round_metrics(&round_metrics) {
+                        error!("Failed to save round metrics: {}", e);
+                    } else if !shutdown_flag.load(Ordering::SeqCst) {
+                        if let Some(reporter) = &reporter {
+                            reporter.spawn_round_report(db.clone(), current_round);
+                        }
+                        if let Some(bus) = &event_bus {
+                            // The stored row also covers earlier resumed parts.
+                            let saved = db.get_round_metrics(1).ok().and_then(|rows| {
+                                rows.into_iter().find(|m| m.round == current_round)
+                            });
+                            bus.publish(service::ScanEvent::RoundComplete(
+                                saved.unwrap_or(round_metrics),
+                            ));
+                        }
+                    }
 
-                    // Clear resume IP since IPv4 scan is complete
-                    if resume_ip_type.as_deref() == Some("IPv4") {
-                        info!("IPv4 scan complete, clearing resume state");
+                    // Clear resume IP since the targets are scanned
+                    if resume_ip.is_some() {
+                        info!("Target scan complete, clearing resume state");
                         resume_ip = None;
                         resume_ip_type = None;
                     }
                 }
-                Err(e) => error!("Failed to create IPv4 range: {}", e),
+                Err(e) => error!("Failed to prepare IPv4 targets: {}", e),
+            }
+        }
+
+        if shutdown_flag.load(Ordering::SeqCst) {
+            // Leave the round marked incomplete: the saved progress lets the
+            // next start resume from the last fully scanned IP.
+            info!(
+                "Scan round {} interrupted, progress saved for resume",
+                current_round
+            );
+            break;
+        }
+        db.save_metadata(&format!("round_{}_complete", current_round), "true")?;
+        // Only a finished round counts as a missed sighting; an interrupted
+        // one has not visited every target yet.
+        match db.mark_stale_ports(current_round, args.stale_rounds) {
+            Ok(0) => {}
+            Ok(closed) => info!(
+                "Marked {} open ports gone after {} rounds unseen",
+                closed, args.stale_rounds
+            ),
+            Err(e) => error!("Failed to age stale open ports: {}", e),
+        }
+        if args.loop_mode && priority.is_enabled() {
+            match db.get_round_diff(
+                current_round - 1,
+                current_round,
+                service::PRIORITY_HOST_LIMIT,
+            ) {
+                Ok(diff) => {
+                    let changed: std::collections::HashSet<std::net::Ipv4Addr> = diff
+                        .opened
+                        .iter()
+                        .chain(&diff.closed)
+                        .filter_map(|change| change.ip_address.parse().ok())
+                        .filter(|ip: &std::net::Ipv4Addr| {
+                            !exclude_list
+                                .as_ref()
+                                .is_some_and(|list| list.contains((*ip).into()))
+                        })
+                        .collect();
+                    priority.record_round(changed);
+                }
+                // The first round has nothing to compare against.
+                Err(e) => {
+                    debug!("No round diff for priority rescans: {}", e);
+                    priority.record_round([]);
+                }
             }
         }
 
@@ -675,6 +1622,7 @@ async fn run_scanner_logic(
                 info!("Cleaned up {} old bitmap rows", deleted);
             }
         }
+        maintenance.run_idle(&db).await;
 
         current_round = db.increment_round()?;
 
@@ -684,17 +1632,136 @@ async fn run_scanner_logic(
                 "Starting round {} after {} ms delay...",
                 current_round, round_delay_ms
             );
-            tokio::time::sleep(tokio::time::Duration::from_millis(round_delay_ms)).await;
+            // Sleep in short slices so the systemd watchdog keeps being fed and
+            // a shutdown request does not have to wait out the whole delay.
+            let resume_at =
+                std::time::Instant::now() + std::time::Duration::from_millis(round_delay_ms);
+            while !shutdown_flag.load(Ordering::SeqCst) {
+                systemd::heartbeat();
+                let remaining = resume_at.saturating_duration_since(std::time::Instant::now());
+                if remaining.is_zero() {
+                    break;
+                }
+                tokio::time::sleep(remaining.min(std::time::Duration::from_secs(1))).await;
+            }
         } else {
             info!("Starting round {} immediately...", current_round);
         }
     }
 
     enrichment_stop.store(true, std::sync::atomic::Ordering::Relaxed);
-    if let Some(handle) = enrichment_handle {
+    // Both workers may be waiting on a provider budget; nothing they hold
+    // is lost by aborting.
+    for handle in [probe_handle, reputation_handle].into_iter().flatten() {
         handle.abort();
         let _ = handle.await;
     }
 
     Ok(())
 }
+
+/// `--rescan-open`: one verification pass over the ports already recorded as
+/// open, credited to the current round.
+async fn rescan_known_open_ports(
+    db: &SqliteDB,
+    args: &Args,
+    shutdown_flag: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    run: &mut service::RunSummary,
+) -> Result<()> {
+    if args.syn {
+        info!("--rescan-open verifies with connect probes; --syn is ignored");
+    }
+    let round = db.get_current_round()?;
+    let exclude_list = args.load_exclude_list()?;
+    let hooks = args.load_script_hooks()?.map(std::sync::Arc::new);
+    let cancel = tokio_util::sync::CancellationToken::new();
+    let config = service::connect_config(args, hooks, cancel.clone())?;
+    let watcher = tokio::spawn(async move {
+        while !shutdown_flag.load(std::sync::atomic::Ordering::SeqCst) {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        }
+        cancel.cancel();
+    });
+
+    let start_time = std::time::Instant::now();
+    let result = service::rescan_open_ports(db, round, config, exclude_list.as_ref()).await;
+    watcher.abort();
+    let (summary, metrics) = result?;
+    info!(
+        "Rescan of round {} complete: {} ports checked, {} still open, {} marked gone in {:.2}s",
+        round,
+        summary.checked,
+        summary.still_open,
+        summary.closed,
+        start_time.elapsed().as_secs_f64()
+    );
+    print_round_summary(db, args, round, &metrics);
+    run.add_round(round, &metrics);
+    save_metrics_snapshot(db, &metrics);
+    db.save_metadata("last_scan_time", &chrono::Utc::now().to_rfc3339())?;
+    Ok(())
+}
+
+/// The geo service unless `--no-geo`. Scanners start its enrichment worker,
+/// which fills in locations for stored open ports independently of scan
+/// rounds, and shut it down before draining the event sinks it publishes
+/// new countries to; the API runs `/geo/enrich` jobs on it.
+fn geo_service(args: &Args) -> Option<GeoService> {
+    if args.no_geo {
+        info!("GeoIP lookup disabled");
+        return None;
+    }
+    info!("Initializing GeoIP service...");
+    Some(
+        GeoService::new(args.geoip_db.as_deref(), args.whois_servers.as_deref())
+            .with_range_datasets(&args.geo_csv),
+    )
+}
+
+/// Notifiers, MQTT, syslog and the metrics pusher hang off a bus fed by the
+/// scanners, the geo worker and the round loop; without sinks nothing is
+/// published.
+fn spawn_event_sinks(
+    args: &Args,
+    db: &SqliteDB,
+) -> Result<(Option<service::EventBus>, Vec<tokio::task::JoinHandle<()>>)> {
+    let mqtt = service::MqttPublisher::from_config(&args.mqtt)?;
+    let syslog = service::SyslogSink::from_config(&args.syslog)?;
+    let pusher = service::MetricsPusher::from_config(&args.metrics_push)?;
+    if args.notify.is_empty() && mqtt.is_none() && syslog.is_none() && pusher.is_none() {
+        return Ok((None, Vec::new()));
+    }
+    let bus = service::EventBus::new();
+    let mut sinks = service::spawn_notifiers(&args.notify, &bus)?;
+    if !args.notify.is_empty() {
+        info!(
+            "Sending scan events to {} notification sinks",
+            args.notify.len()
+        );
+    }
+    if let Some(mqtt) = mqtt {
+        sinks.push(mqtt.spawn(&bus));
+    }
+    if let Some(syslog) = syslog {
+        sinks.push(syslog.spawn(&bus));
+    }
+    if let Some(pusher) = pusher {
+        sinks.push(pusher.spawn(&bus, db.clone()));
+    }
+    Ok((Some(bus), sinks))
+}
+
+/// Dropping the last bus handle lets each sink drain what is queued and exit.
+async fn drain_event_sinks(
+    event_bus: Option<service::EventBus>,
+    event_sinks: Vec<tokio::task::JoinHandle<()>>,
+) {
+    drop(event_bus);
+    let drain = futures::future::join_all(event_sinks);
+    if tokio::time::timeout(std::time::Duration::from_secs(10), drain)
+        .await
+        .is_err()
+    {
+        warn!("Gave up delivering queued notifications after 10s");
+    }
+}
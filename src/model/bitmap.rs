@@ -1,4 +1,5 @@
 use anyhow::Result;
+use std::collections::BTreeMap;
 
 const SEGMENT_SIZE: usize = 2 * 1024 * 1024; // 2MB per segment (16,777,216 IPs)
 
@@ -24,6 +25,31 @@ impl PortBitmap {
         Ok(bincode::serialize(&self.segments)?)
     }
 
+    /// Bitmap over a flat array of one bit per IPv4 address, in the segment
+    /// layout; only segments with a bit set are kept.
+    pub fn from_flat(flat: &[u8]) -> Self {
+        let segments = flat
+            .chunks(SEGMENT_SIZE)
+            .enumerate()
+            .filter(|(_, segment)| segment.iter().any(|&byte| byte != 0))
+            .map(|(segment_id, segment)| (segment_id as u32, segment.to_vec()))
+            .collect();
+        PortBitmap { segments }
+    }
+
+    /// Set every bit of this bitmap in the flat array `flat`.
+    pub fn or_into_flat(&self, flat: &mut [u8]) {
+        for (segment_id, segment) in &self.segments {
+            let start = *segment_id as usize * SEGMENT_SIZE;
+            let Some(target) = flat.get_mut(start..start + segment.len()) else {
+                continue;
+            };
+            for (dst, src) in target.iter_mut().zip(segment) {
+                *dst |= src;
+            }
+        }
+    }
+
     fn get_segment_and_offset(ip_index: u32) -> (u32, u32) {
         let segment_id = ip_index >> 24; // High 8 bits
         let bit_offset = ip_index & 0xFFFFFF; // Low 24 bits
@@ -87,6 +113,77 @@ impl PortBitmap {
         changes
     }
 
+    /// Segment ids present in either bitmap, ascending.
+    fn segment_ids(&self, other: &Self) -> std::collections::BTreeSet<u32> {
+        self.segments
+            .keys()
+            .chain(other.segments.keys())
+            .copied()
+            .collect()
+    }
+
+    /// Number of bits newly set here and cleared here relative to `previous`.
+    pub fn diff_counts(&self, previous: &Self) -> (usize, usize) {
+        let (mut set, mut cleared) = (0, 0);
+        for segment_id in self.segment_ids(previous) {
+            let current = self.segments.get(&segment_id);
+            let old = previous.segments.get(&segment_id);
+            for byte_index in 0..SEGMENT_SIZE {
+                let a = current
+                    .and_then(|v| v.get(byte_index))
+                    .copied()
+                    .unwrap_or(0);
+                let b = old.and_then(|v| v.get(byte_index)).copied().unwrap_or(0);
+                set += (a & !b).count_ones() as usize;
+                cleared += (b & !a).count_ones() as usize;
+            }
+        }
+        (set, cleared)
+    }
+
+    /// Indices set in `previous` but not here, ascending.
+    pub fn cleared_indices(&self, previous: &Self, limit: usize) -> Vec<u32> {
+        let mut cleared = Vec::new();
+        if limit == 0 {
+            return cleared;
+        }
+        for segment_id in self.segment_ids(previous) {
+            let Some(old) = previous.segments.get(&segment_id) else {
+                continue;
+            };
+            let current = self.segments.get(&segment_id);
+            for (byte_index, &b) in old.iter().enumerate() {
+                let a = current
+                    .and_then(|v| v.get(byte_index))
+                    .copied()
+                    .unwrap_or(0);
+                let mut bits = b & !a;
+                while bits != 0 {
+                    let bit = bits.trailing_zeros();
+                    cleared.push((segment_id << 24) | ((byte_index as u32) << 3) | bit);
+                    if cleared.len() >= limit {
+                        return cleared;
+                    }
+                    bits &= bits - 1;
+                }
+            }
+        }
+        cleared
+    }
+
+    /// Set every bit that is set in `other`.
+    pub fn union_with(&mut self, other: &Self) {
+        for (segment_id, segment) in &other.segments {
+            let target = self
+                .segments
+                .entry(*segment_id)
+                .or_insert_with(|| vec![0u8; SEGMENT_SIZE]);
+            for (dst, src) in target.iter_mut().zip(segment) {
+                *dst |= src;
+            }
+        }
+    }
+
     pub fn count_ones(&self) -> usize {
         self.segments
             .values()
@@ -98,6 +195,38 @@ impl PortBitmap {
             })
             .sum()
     }
+
+    /// Set bits per IPv4 prefix of `prefix_len` (1 to 16) bits, keyed by the
+    /// prefix's index (the address shifted right by `32 - prefix_len`).
+    /// Prefixes with no bit set are left out.
+    pub fn count_ones_by_prefix(&self, prefix_len: u8) -> BTreeMap<u32, usize> {
+        let mut counts = BTreeMap::new();
+        let count = |bytes: &[u8]| bytes.iter().map(|b| b.count_ones() as usize).sum::<usize>();
+        for (segment_id, segment) in &self.segments {
+            if prefix_len <= 8 {
+                let ones = count(segment);
+                if ones > 0 {
+                    *counts.entry(segment_id >> (8 - prefix_len)).or_default() += ones;
+                }
+                continue;
+            }
+            // Each segment is one /8; split it into the longer prefixes.
+            let extra = u32::from(prefix_len - 8);
+            for (index, chunk) in segment.chunks(SEGMENT_SIZE >> extra).enumerate() {
+                let ones = count(chunk);
+                if ones > 0 {
+                    counts.insert((segment_id << extra) | index as u32, ones);
+                }
+            }
+        }
+        counts
+    }
+}
+
+impl Default for PortBitmap {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub fn ipv4_to_index(ip: &str) -> Result<u32> {
@@ -144,6 +273,50 @@ mod tests {
         assert_eq!(bitmap.count_ones(), 3);
     }
 
+    #[test]
+    fn test_diff_against_previous() {
+        let mut previous = PortBitmap::new();
+        previous.set(1, true);
+        previous.set(2, true);
+        previous.set(3 << 24, true);
+        let mut current = PortBitmap::new();
+        current.set(2, true);
+        current.set(5, true);
+
+        assert_eq!(current.diff_counts(&previous), (1, 2));
+        assert_eq!(current.cleared_indices(&previous, 10), vec![1, 3 << 24]);
+        assert_eq!(current.cleared_indices(&previous, 1), vec![1]);
+
+        current.union_with(&previous);
+        assert_eq!(current.count_ones(), 4);
+        assert!(current.get(3 << 24));
+
+        let by_8: Vec<_> = current.count_ones_by_prefix(8).into_iter().collect();
+        assert_eq!(by_8, vec![(0, 3), (3, 1)]);
+        current.set((3 << 24) | (1 << 16), true);
+        let by_16: Vec<_> = current.count_ones_by_prefix(16).into_iter().collect();
+        assert_eq!(by_16, vec![(0, 3), (3 << 8, 1), ((3 << 8) | 1, 1)]);
+        let by_1: Vec<_> = current.count_ones_by_prefix(1).into_iter().collect();
+        assert_eq!(by_1, vec![(0, 5)]);
+    }
+
+    #[test]
+    fn test_flat_layout_round_trip() {
+        let mut bitmap = PortBitmap::new();
+        bitmap.set(9, true);
+        bitmap.set((2 << 24) | 17, true);
+
+        let mut flat = vec![0u8; 3 * SEGMENT_SIZE];
+        bitmap.or_into_flat(&mut flat);
+        assert_eq!(flat[1], 1 << 1);
+        assert_eq!(flat[2 * SEGMENT_SIZE + 2], 1 << 1);
+
+        let restored = PortBitmap::from_flat(&flat);
+        assert_eq!(restored.segments.len(), 2);
+        assert!(restored.get(9) && restored.get((2 << 24) | 17));
+        assert_eq!(restored.count_ones(), 2);
+    }
+
     #[test]
     fn test_serialization() {
         let mut bitmap = PortBitmap::new();
@@ -0,0 +1,115 @@
+use super::is_hostname;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Names taken from one seed file.
+pub const MAX_SEED_DOMAINS: usize = 10_000;
+
+/// Domain names to scan, loaded from `--seed-domains`: either a
+/// certificate-transparency export (a crt.sh-style JSON array of entries
+/// with `name_value` and `common_name`) or a plain list with one name per
+/// line and `#` comments. Wildcards are reduced to their base name, and
+/// entries that are not hostnames (e-mail addresses, IPs) are skipped,
+/// since CT data routinely contains them.
+#[derive(Debug, Default, Clone)]
+pub struct DomainSeeds {
+    names: BTreeSet<String>,
+    skipped: usize,
+}
+
+impl DomainSeeds {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read seed domain file {}: {}", path.display(), e))?;
+        Self::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut seeds = DomainSeeds::default();
+        if content.trim_start().starts_with('[') {
+            let entries: Vec<serde_json::Value> =
+                serde_json::from_str(content).map_err(|e| format!("invalid CT log JSON: {}", e))?;
+            for entry in &entries {
+                for field in ["name_value", "common_name"] {
+                    if let Some(value) = entry.get(field).and_then(|v| v.as_str()) {
+                        value.split_whitespace().for_each(|name| seeds.insert(name));
+                    }
+                }
+            }
+        } else {
+            for line in content.lines() {
+                let line = line.split('#').next().unwrap_or_default();
+                line.split_whitespace().for_each(|name| seeds.insert(name));
+            }
+        }
+        if seeds.names.len() > MAX_SEED_DOMAINS {
+            return Err(format!(
+                "{} domains, at most {} are supported",
+                seeds.names.len(),
+                MAX_SEED_DOMAINS
+            ));
+        }
+        Ok(seeds)
+    }
+
+    fn insert(&mut self, name: &str) {
+        let name = name.strip_prefix("*.").unwrap_or(name);
+        if is_hostname(name) {
+            self.names
+                .insert(name.trim_end_matches('.').to_ascii_lowercase());
+        } else {
+            self.skipped += 1;
+        }
+    }
+
+    /// Distinct names, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.names.iter()
+    }
+
+    /// Entries dropped because they were not hostnames.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ct_export_and_plain_list() {
+        let seeds = DomainSeeds::parse(
+            r#"[
+                {"issuer_ca_id": 1, "common_name": "example.com",
+                 "name_value": "example.com\n*.example.com\nWWW.example.com"},
+                {"common_name": "admin@example.com", "name_value": "api.example.com"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            seeds.names().collect::<Vec<_>>(),
+            ["api.example.com", "example.com", "www.example.com"]
+        );
+        assert_eq!(seeds.skipped(), 1);
+
+        let seeds =
+            DomainSeeds::parse("# staging\nstaging.example.org\n192.0.2.1\n\nexample.org.\n")
+                .unwrap();
+        assert_eq!(
+            seeds.names().collect::<Vec<_>>(),
+            ["example.org", "staging.example.org"]
+        );
+        assert_eq!(seeds.skipped(), 1);
+
+        assert!(DomainSeeds::parse("[{\"name_value\": ").is_err());
+    }
+}
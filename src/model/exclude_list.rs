@@ -0,0 +1,229 @@
+use super::IpRange;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Addresses that must never be probed, loaded from a masscan-style
+/// `--excludefile`: one or more IPs, `a.b.c.d-w.x.y.z` ranges or CIDRs per
+/// line, separated by commas or whitespace, with `#`/`;` comments.
+#[derive(Debug, Default, Clone)]
+pub struct ExcludeList {
+    v4: Vec<(u128, u128)>,
+    v6: Vec<(u128, u128)>,
+}
+
+impl ExcludeList {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read exclude file {}: {}", path.display(), e))?;
+        Self::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut list = ExcludeList::default();
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            // masscan accepts "10.0.0.1 - 10.0.0.9"; glue the range back together,
+            // whatever whitespace surrounds the dash, before splitting entries
+            // on whitespace.
+            let line = line.split('-').map(str::trim).collect::<Vec<_>>().join("-");
+            for entry in line.split(|c: char| c == ',' || c.is_whitespace()) {
+                if entry.is_empty() {
+                    continue;
+                }
+                let range = IpRange::parse_target(entry)
+                    .map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+                list.insert(&range)
+                    .map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+            }
+        }
+        list.v4 = merge(std::mem::take(&mut list.v4));
+        list.v6 = merge(std::mem::take(&mut list.v6));
+        Ok(list)
+    }
+
+    /// Add `ranges`, e.g. the networks of an `--exclude-country`.
+    pub fn extend(&mut self, ranges: &[IpRange]) -> Result<(), String> {
+        for range in ranges {
+            self.insert(range)?;
+        }
+        self.v4 = merge(std::mem::take(&mut self.v4));
+        self.v6 = merge(std::mem::take(&mut self.v6));
+        Ok(())
+    }
+
+    /// Add every range of `other`.
+    pub fn merge(&mut self, other: &ExcludeList) {
+        self.v4.extend_from_slice(&other.v4);
+        self.v6.extend_from_slice(&other.v6);
+        self.v4 = merge(std::mem::take(&mut self.v4));
+        self.v6 = merge(std::mem::take(&mut self.v6));
+    }
+
+    fn insert(&mut self, range: &IpRange) -> Result<(), String> {
+        let (bucket, start, end) = match (range.start, range.end) {
+            (IpAddr::V4(s), IpAddr::V4(e)) => {
+                (&mut self.v4, u32::from(s) as u128, u32::from(e) as u128)
+            }
+            (IpAddr::V6(s), IpAddr::V6(e)) => (&mut self.v6, u128::from(s), u128::from(e)),
+            _ => return Err("range mixes IPv4 and IPv6".to_string()),
+        };
+        if start > end {
+            return Err(format!("range {}-{} is reversed", range.start, range.end));
+        }
+        bucket.push((start, end));
+        Ok(())
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (ranges, value) = match ip {
+            IpAddr::V4(v4) => (&self.v4, u32::from(v4) as u128),
+            IpAddr::V6(v6) => (&self.v6, u128::from(v6)),
+        };
+        let idx = ranges.partition_point(|&(start, _)| start <= value);
+        idx > 0 && ranges[idx - 1].1 >= value
+    }
+
+    /// Excluded IPv4 ranges within `start..=end`, clipped to it.
+    pub fn v4_overlaps(&self, start: u32, end: u32) -> Vec<(u32, u32)> {
+        let (start, end) = (start as u128, end as u128);
+        let first = self.v4.partition_point(|&(_, e)| e < start);
+        self.v4[first..]
+            .iter()
+            .take_while(|&&(s, _)| s <= end)
+            .map(|&(s, e)| (s.max(start) as u32, e.min(end) as u32))
+            .collect()
+    }
+
+    /// The merged ranges in address order, IPv4 first. `--input-file`
+    /// shares this file format, so it reads its targets from here too.
+    pub fn ranges(&self) -> impl Iterator<Item = IpRange> + '_ {
+        let v4 = self.v4.iter().map(|&(start, end)| IpRange {
+            start: IpAddr::V4((start as u32).into()),
+            end: IpAddr::V4((end as u32).into()),
+        });
+        let v6 = self.v6.iter().map(|&(start, end)| IpRange {
+            start: IpAddr::V6(start.into()),
+            end: IpAddr::V6(end.into()),
+        });
+        v4.chain(v6)
+    }
+
+    /// Number of disjoint ranges after merging overlaps.
+    pub fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn merge(mut ranges: Vec<(u128, u128)>) -> Vec<(u128, u128)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(u128, u128)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parses_masscan_excludefile() {
+        let list = ExcludeList::parse(
+            "# masscan exclude list\n\
+             10.0.0.0/8\n\
+             192.168.1.10 - 192.168.1.20 ; lab hosts\n\
+             203.0.113.5, 203.0.113.7\n\
+             \n\
+             2001:db8::/32\n",
+        )
+        .unwrap();
+
+        assert!(list.contains(ip("10.255.0.1")));
+        assert!(list.contains(ip("192.168.1.15")));
+        assert!(!list.contains(ip("192.168.1.21")));
+        assert!(list.contains(ip("203.0.113.7")));
+        assert!(!list.contains(ip("203.0.113.6")));
+        assert!(list.contains(ip("2001:db8::1")));
+        assert!(!list.contains(ip("8.8.8.8")));
+    }
+
+    #[test]
+    fn test_ranges_allow_any_whitespace_around_the_dash() {
+        let list = ExcludeList::parse(
+            "10.0.0.1  -  10.0.0.9\n10.0.1.1\t-\t10.0.1.9 10.0.2.1 -\t 10.0.2.9\n",
+        )
+        .unwrap();
+        assert_eq!(list.len(), 3);
+        for addr in ["10.0.0.5", "10.0.1.9", "10.0.2.1"] {
+            assert!(list.contains(ip(addr)), "{}", addr);
+        }
+        assert!(!list.contains(ip("10.0.1.10")));
+    }
+
+    #[test]
+    fn test_merges_overlapping_and_adjacent_ranges() {
+        let list = ExcludeList::parse("1.1.1.0/25\n1.1.1.128/25\n1.1.1.5\n").unwrap();
+        assert_eq!(list.len(), 1);
+        assert!(list.contains(ip("1.1.1.255")));
+
+        let mut list = list;
+        list.extend(&[
+            IpRange::parse_target("1.1.2.0/24").unwrap(),
+            IpRange::parse_target("2001:db8::/64").unwrap(),
+        ])
+        .unwrap();
+        assert_eq!(list.len(), 2);
+        assert!(list.contains(ip("1.1.2.9")));
+        assert!(list.contains(ip("2001:db8::9")));
+
+        list.merge(&ExcludeList::parse("1.1.3.0/24\n10.0.0.1").unwrap());
+        assert_eq!(list.len(), 3);
+        assert!(list.contains(ip("1.1.3.1")) && list.contains(ip("10.0.0.1")));
+
+        let ranges: Vec<(IpAddr, IpAddr)> = list.ranges().map(|r| (r.start, r.end)).collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (ip("1.1.1.0"), ip("1.1.3.255")),
+                (ip("10.0.0.1"), ip("10.0.0.1")),
+                (ip("2001:db8::"), ip("2001:db8::ffff:ffff:ffff:ffff")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clips_v4_overlaps_to_slice() {
+        let list = ExcludeList::parse(
+            "10.0.0.0/30
+10.0.0.8-10.0.0.20
+10.0.1.0
+",
+        )
+        .unwrap();
+        let start = u32::from(std::net::Ipv4Addr::new(10, 0, 0, 2));
+        let end = u32::from(std::net::Ipv4Addr::new(10, 0, 0, 15));
+        assert_eq!(
+            list.v4_overlaps(start, end),
+            vec![(start, start + 1), (start + 6, end)]
+        );
+        assert!(list.v4_overlaps(end + 6, end + 100).is_empty());
+    }
+
+    #[test]
+    fn test_reports_line_of_invalid_entry() {
+        let err = ExcludeList::parse("10.0.0.1\nnot-an-ip\n").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+    }
+}
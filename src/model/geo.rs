@@ -9,6 +9,7 @@ pub struct IpGeoInfo {
     pub isp: Option<String>,
     pub asn: Option<String>,
     pub reverse_dns: Option<String>,
+    pub abuse_email: Option<String>,
     pub source: String,
 }
 
@@ -22,6 +23,7 @@ impl IpGeoInfo {
             isp: None,
             asn: None,
             reverse_dns: None,
+            abuse_email: None,
             source,
         }
     }
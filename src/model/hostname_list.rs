@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Hostnames probed on each IP with SNI and a matching `Host` header,
+/// loaded from `--sni-hosts` in hosts-file format: an IP followed by one or
+/// more hostnames per line, with `#` comments.
+#[derive(Debug, Default, Clone)]
+pub struct HostnameList {
+    hosts: HashMap<IpAddr, Vec<String>>,
+}
+
+impl HostnameList {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read SNI host file {}: {}", path.display(), e))?;
+        Self::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut list = HostnameList::default();
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(ip) = fields.next() else {
+                continue;
+            };
+            let ip: IpAddr = ip
+                .parse()
+                .map_err(|_| format!("line {}: invalid IP address {:?}", line_no + 1, ip))?;
+            let fields: Vec<&str> = fields.collect();
+            if fields.is_empty() {
+                return Err(format!("line {}: {} has no hostnames", line_no + 1, ip));
+            }
+            let names = list.hosts.entry(ip).or_default();
+            for name in fields {
+                if !is_hostname(name) {
+                    return Err(format!("line {}: invalid hostname {:?}", line_no + 1, name));
+                }
+                let name = name.trim_end_matches('.').to_ascii_lowercase();
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        Ok(list)
+    }
+
+    /// Hostnames listed for `ip`, in file order.
+    pub fn get(&self, ip: IpAddr) -> &[String] {
+        self.hosts.get(&ip).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Number of IPs with hostnames.
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+}
+
+/// A DNS name usable as an SNI server name: dot-separated labels of ASCII
+/// letters, digits and hyphens, not an IP address.
+pub fn is_hostname(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    !name.is_empty()
+        && name.len() <= 253
+        && name.parse::<IpAddr>().is_err()
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hosts_file() {
+        let list = HostnameList::parse(
+            "# shared hosting\n\
+             192.0.2.10 www.example.com Example.org\n\
+             192.0.2.10 example.org api.example.com  # merged\n\
+             2001:db8::1 v6.example.net\n\n",
+        )
+        .unwrap();
+        let ip = "192.0.2.10".parse().unwrap();
+        assert_eq!(
+            list.get(ip),
+            ["www.example.com", "example.org", "api.example.com"]
+        );
+        assert_eq!(list.get("2001:db8::1".parse().unwrap()), ["v6.example.net"]);
+        assert!(list.get("192.0.2.11".parse().unwrap()).is_empty());
+        assert_eq!(list.len(), 2);
+
+        assert!(HostnameList::parse("example.com 192.0.2.1")
+            .unwrap_err()
+            .contains("line 1"));
+        assert!(HostnameList::parse("192.0.2.1")
+            .unwrap_err()
+            .contains("no hostnames"));
+        assert!(HostnameList::parse("192.0.2.1 bad_host-.com").is_err());
+        assert!(!is_hostname("192.0.2.1"));
+        assert!(is_hostname("host-1.example.com."));
+    }
+}
@@ -1,6 +1,9 @@
+use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
 use std::str::FromStr;
 
+#[derive(Debug, Clone, Copy)]
 pub struct IpRange {
     pub start: IpAddr,
     pub end: IpAddr,
@@ -110,7 +113,6 @@ impl IpRange {
         IpIterator::new(self.start, self.end)
     }
 
-    #[allow(dead_code)]
     pub fn count(&self) -> usize {
         match (self.start, self.end) {
             (IpAddr::V4(s), IpAddr::V4(e)) => {
@@ -198,7 +200,79 @@ impl Iterator for IpIterator {
     }
 }
 
-pub fn parse_port_range(range: &str) -> Result<Vec<u16>, String> {
+/// Built-in symbolic port groups usable anywhere a port expression is
+/// accepted, e.g. `-p web,db` or `-p mail,8000-8100`.
+const BUILTIN_PORT_GROUPS: &[(&str, &str)] = &[
+    ("web", "80,443,8000,8008,8080,8081,8443,8888"),
+    ("db", "1433,1521,3306,5432,5984,6379,9042,9200,11211,27017"),
+    ("mail", "25,110,143,465,587,993,995"),
+    ("remote", "22,23,3389,5900,5985,5986"),
+    ("file", "21,69,139,445,873,2049"),
+];
+
+/// Replace symbolic group names in a port expression with their port lists.
+/// `custom` groups (from `[port_groups]` in the config file) shadow the
+/// built-ins and may themselves reference other groups.
+pub fn expand_port_groups(range: &str, custom: &HashMap<String, String>) -> Result<String, String> {
+    expand_port_groups_at_depth(range, custom, 0)
+}
+
+fn expand_port_groups_at_depth(
+    range: &str,
+    custom: &HashMap<String, String>,
+    depth: usize,
+) -> Result<String, String> {
+    // Deep enough for any sane nesting; deeper means a group refers to itself.
+    const MAX_DEPTH: usize = 8;
+
+    let mut parts = Vec::new();
+    for part in range.split(',') {
+        let part = part.trim();
+        if !part.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            parts.push(part.to_string());
+            continue;
+        }
+        let name = part.to_ascii_lowercase();
+        let definition = custom
+            .get(&name)
+            .map(String::as_str)
+            .or_else(|| {
+                BUILTIN_PORT_GROUPS
+                    .iter()
+                    .find(|(group, _)| *group == name)
+                    .map(|(_, ports)| *ports)
+            })
+            .ok_or_else(|| format!("Unknown port group: {}", part))?;
+        if depth >= MAX_DEPTH {
+            return Err(format!("Port group '{}' is defined recursively", part));
+        }
+        parts.push(expand_port_groups_at_depth(definition, custom, depth + 1)?);
+    }
+    Ok(parts.join(","))
+}
+
+/// Read a `--ports-file`: one port, range or group per line (commas work
+/// too), `#` starts a comment. Returns the entries as one port expression.
+pub fn read_ports_file(path: &Path) -> Result<String, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read ports file {}: {}", path.display(), e))?;
+    let entries: Vec<&str> = content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(|line| line.split(','))
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .collect();
+    if entries.is_empty() {
+        return Err(format!("Ports file {} lists no ports", path.display()));
+    }
+    Ok(entries.join(","))
+}
+
+/// Parse a port expression into ports; group names resolve against the
+/// configured `[port_groups]` in `custom` before the built-ins.
+pub fn parse_port_range(range: &str, custom: &HashMap<String, String>) -> Result<Vec<u16>, String> {
+    let range = expand_port_groups(range, custom)?;
     let mut ports = Vec::new();
 
     for part in range.split(',') {
@@ -305,18 +379,43 @@ mod tests {
         assert_eq!(range.count(), 1);
     }
 
+    /// Parse with the built-in groups only.
+    fn parse_ports(range: &str) -> Result<Vec<u16>, String> {
+        parse_port_range(range, &HashMap::new())
+    }
+
     #[test]
     fn test_parse_port_range() {
-        assert_eq!(parse_port_range("80").unwrap(), vec![80]);
-        assert_eq!(parse_port_range("80,443").unwrap(), vec![80, 443]);
-        assert_eq!(parse_port_range("1-5").unwrap(), vec![1, 2, 3, 4, 5]);
+        assert_eq!(parse_ports("80").unwrap(), vec![80]);
+        assert_eq!(parse_ports("80,443").unwrap(), vec![80, 443]);
+        assert_eq!(parse_ports("1-5").unwrap(), vec![1, 2, 3, 4, 5]);
         assert_eq!(
-            parse_port_range("80,443,8080-8082").unwrap(),
+            parse_ports("80,443,8080-8082").unwrap(),
             vec![80, 443, 8080, 8081, 8082]
         );
-        assert!(parse_port_range("1-").is_err());
-        assert!(parse_port_range("a").is_err());
-        assert!(parse_port_range("5-1").is_err());
+        assert!(parse_ports("1-").is_err());
+        assert!(parse_ports("a").is_err());
+        assert!(parse_ports("5-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_port_range_expands_builtin_groups() {
+        let ports = parse_ports("web,22").unwrap();
+        assert!(ports.contains(&443) && ports.contains(&8080) && ports.contains(&22));
+        assert_eq!(parse_ports("MAIL").unwrap(), parse_ports("mail").unwrap());
+        assert!(parse_ports("nosuchgroup").is_err());
+    }
+
+    #[test]
+    fn test_custom_port_groups_nest_and_detect_cycles() {
+        let mut custom = HashMap::new();
+        custom.insert("edge".to_string(), "web,9000-9001".to_string());
+        custom.insert("loop".to_string(), "loop".to_string());
+
+        let ports = parse_port_range("edge,22", &custom).unwrap();
+        assert!(ports.contains(&443) && ports.contains(&9001) && ports.contains(&22));
+        assert!(expand_port_groups("loop", &custom).is_err());
+        assert!(parse_ports("edge").is_err());
     }
 
     #[test]
@@ -1,6 +1,231 @@
+use serde::Serialize;
+use std::net::IpAddr;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+/// Linear sub-buckets per power of two; bounds the relative error of a
+/// reported percentile to 1/16.
+const SUB_BUCKET_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+/// Values are recorded in microseconds and clamped to `u32::MAX` (~71 min).
+const MAX_MAGNITUDE: u32 = 31;
+const BUCKET_COUNT: usize = (MAX_MAGNITUDE - SUB_BUCKET_BITS + 2) as usize * SUB_BUCKETS;
+
+/// HDR-style log-linear histogram of latencies. Recording is one atomic add,
+/// so it can be shared by every scan task without locking.
+pub struct LatencyHistogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    max_us: AtomicU64,
+}
+
+/// Percentiles of a [`LatencyHistogram`] in milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: (0..BUCKET_COUNT).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            max_us: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, latency: Duration) {
+        let us = (latency.as_micros() as u64).min(u32::MAX as u64);
+        self.buckets[bucket_index(us)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Upper bound of the bucket holding the `q`-quantile (0.0..=1.0), in
+    /// microseconds. Returns 0 when nothing has been recorded.
+    pub fn percentile_us(&self, q: f64) -> u64 {
+        let count = self.count();
+        if count == 0 {
+            return 0;
+        }
+        let target = ((q.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let max = self.max_us.load(Ordering::Relaxed);
+        let mut seen = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= target {
+                return bucket_upper_bound(index).min(max);
+            }
+        }
+        max
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        let ms = |us: u64| us as f64 / 1000.0;
+        LatencySummary {
+            count: self.count(),
+            p50_ms: ms(self.percentile_us(0.50)),
+            p95_ms: ms(self.percentile_us(0.95)),
+            p99_ms: ms(self.percentile_us(0.99)),
+            max_ms: ms(self.max_us.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Values below `SUB_BUCKETS` get one bucket each; above that, every power of
+/// two is split into `SUB_BUCKETS` equal-width buckets.
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let magnitude = 63 - value.leading_zeros();
+    let sub = (value >> (magnitude - SUB_BUCKET_BITS)) as usize - SUB_BUCKETS;
+    (magnitude - SUB_BUCKET_BITS + 1) as usize * SUB_BUCKETS + sub
+}
+
+fn bucket_upper_bound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let sub = (index % SUB_BUCKETS + SUB_BUCKETS) as u64;
+    ((sub + 1) << shift) - 1
+}
+
+#[derive(Default)]
+struct TargetCounters {
+    scanned: AtomicU64,
+    open: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// How probes were answered. Probes with neither a SYN-ACK nor a RST were
+/// dropped, filtered or sent to an unused address; a rising
+/// `no_answer_ratio` at a steady target set means probes are being lost.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ReplyStats {
+    pub sent: u64,
+    pub syn_ack: u64,
+    pub rst: u64,
+    pub no_answer_ratio: f64,
+    pub rst_ratio: f64,
+}
+
+/// Scanned/open/error counts for one port or one IPv4 /8.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BreakdownEntry {
+    pub key: String,
+    pub scanned: u64,
+    pub open: u64,
+    pub errors: u64,
+}
+
+/// Counters indexed by port and by IPv4 first octet. Fixed arrays keep
+/// recording to plain atomic adds; IPv6 targets only count per port.
+struct Breakdown {
+    by_port: Box<[TargetCounters]>,
+    by_prefix: Box<[TargetCounters]>,
+}
+
+impl Breakdown {
+    fn new() -> Self {
+        Self {
+            by_port: (0..=u16::MAX).map(|_| TargetCounters::default()).collect(),
+            by_prefix: (0..=u8::MAX).map(|_| TargetCounters::default()).collect(),
+        }
+    }
+
+    fn record(&self, ip: IpAddr, port: u16, field: fn(&TargetCounters) -> &AtomicU64) {
+        field(&self.by_port[port as usize]).fetch_add(1, Ordering::Relaxed);
+        if let IpAddr::V4(v4) = ip {
+            field(&self.by_prefix[v4.octets()[0] as usize]).fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The `limit` busiest entries, errors first so failing ports or networks
+/// surface even when they see little traffic.
+fn top_entries(
+    counters: &[TargetCounters],
+    limit: usize,
+    key: impl Fn(usize) -> String,
+) -> Vec<BreakdownEntry> {
+    let mut entries: Vec<BreakdownEntry> = counters
+        .iter()
+        .enumerate()
+        .filter_map(|(index, c)| {
+            let scanned = c.scanned.load(Ordering::Relaxed);
+            let errors = c.errors.load(Ordering::Relaxed);
+            (scanned > 0 || errors > 0).then(|| BreakdownEntry {
+                key: key(index),
+                scanned,
+                open: c.open.load(Ordering::Relaxed),
+                errors,
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| b.errors.cmp(&a.errors).then(b.scanned.cmp(&a.scanned)));
+    entries.truncate(limit);
+    entries
+}
+
+/// Items waiting in a bounded channel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct QueueDepth {
+    pub depth: u64,
+    pub capacity: u64,
+}
+
+/// Backpressure between the scan pipeline stages: targets waiting for a
+/// probe, results waiting for the DB writer, and the writer's current
+/// batching (which `--adaptive-batching` changes during the scan).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct QueueStats {
+    pub pipeline: QueueDepth,
+    pub results: QueueDepth,
+    pub db_batch_size: u64,
+    pub flush_interval_ms: u64,
+    pub db_write: LatencySummary,
+}
+
+/// Counters of a scanner that is still running, with the probe rate since it
+/// started and the same backpressure view as [`QueueStats`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LiveStats {
+    pub scanned: u64,
+    pub open: u64,
+    pub errors: u64,
+    pub retries: u64,
+    pub scan_rate: f64,
+    pub elapsed_secs: f64,
+    pub queues: QueueStats,
+}
+
+#[derive(Default)]
+struct QueueGauges {
+    pipeline_depth: AtomicU64,
+    pipeline_capacity: AtomicU64,
+    result_depth: AtomicU64,
+    result_capacity: AtomicU64,
+    db_batch_size: AtomicU64,
+    flush_interval_ms: AtomicU64,
+    db_write: LatencyHistogram,
+}
 
 #[derive(Clone)]
 pub struct ScanMetrics {
@@ -8,6 +233,12 @@ pub struct ScanMetrics {
     total_open: Arc<AtomicU64>,
     total_errors: Arc<AtomicU64>,
     total_retries: Arc<AtomicU64>,
+    connect_latency: Arc<LatencyHistogram>,
+    syn_rtt: Arc<LatencyHistogram>,
+    breakdown: Arc<Breakdown>,
+    syn_ack_replies: Arc<AtomicU64>,
+    rst_replies: Arc<AtomicU64>,
+    queues: Arc<QueueGauges>,
     start_time: Arc<Instant>,
 }
 
@@ -18,6 +249,12 @@ impl ScanMetrics {
             total_open: Arc::new(AtomicU64::new(0)),
             total_errors: Arc::new(AtomicU64::new(0)),
             total_retries: Arc::new(AtomicU64::new(0)),
+            connect_latency: Arc::new(LatencyHistogram::new()),
+            syn_rtt: Arc::new(LatencyHistogram::new()),
+            breakdown: Arc::new(Breakdown::new()),
+            syn_ack_replies: Arc::new(AtomicU64::new(0)),
+            rst_replies: Arc::new(AtomicU64::new(0)),
+            queues: Arc::new(QueueGauges::default()),
             start_time: Arc::new(Instant::now()),
         }
     }
@@ -38,6 +275,140 @@ impl ScanMetrics {
         self.total_retries.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Count a probe sent to `ip:port`, both globally and in the per-port
+    /// and per-/8 breakdown.
+    pub fn record_scanned(&self, ip: IpAddr, port: u16) {
+        self.increment_scanned();
+        self.breakdown.record(ip, port, |c| &c.scanned);
+    }
+
+    pub fn record_open(&self, ip: IpAddr, port: u16) {
+        self.increment_open();
+        self.breakdown.record(ip, port, |c| &c.open);
+    }
+
+    pub fn record_error(&self, ip: IpAddr, port: u16) {
+        self.increment_errors();
+        self.breakdown.record(ip, port, |c| &c.errors);
+    }
+
+    /// Count a SYN-ACK (`rst == false`) or RST answering one of our probes.
+    pub fn record_reply(&self, rst: bool) {
+        let counter = if rst {
+            &self.rst_replies
+        } else {
+            &self.syn_ack_replies
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn reply_stats(&self) -> ReplyStats {
+        let sent = self.get_scanned();
+        let syn_ack = self.syn_ack_replies.load(Ordering::Relaxed);
+        let rst = self.rst_replies.load(Ordering::Relaxed);
+        if sent == 0 {
+            return ReplyStats::default();
+        }
+        ReplyStats {
+            sent,
+            syn_ack,
+            rst,
+            no_answer_ratio: sent.saturating_sub(syn_ack + rst) as f64 / sent as f64,
+            rst_ratio: rst as f64 / sent as f64,
+        }
+    }
+
+    /// Busiest ports, most errors first.
+    pub fn top_ports(&self, limit: usize) -> Vec<BreakdownEntry> {
+        top_entries(&self.breakdown.by_port, limit, |port| port.to_string())
+    }
+
+    /// Busiest IPv4 /8 prefixes, most errors first.
+    pub fn top_prefixes(&self, limit: usize) -> Vec<BreakdownEntry> {
+        top_entries(&self.breakdown.by_prefix, limit, |octet| {
+            format!("{}.0.0.0/8", octet)
+        })
+    }
+
+    /// Time for a TCP connect attempt to complete (accepted or refused).
+    pub fn record_connect_latency(&self, latency: Duration) {
+        self.connect_latency.record(latency);
+    }
+
+    /// Time between sending a SYN and receiving its SYN-ACK.
+    pub fn record_syn_rtt(&self, rtt: Duration) {
+        self.syn_rtt.record(rtt);
+    }
+
+    pub fn connect_latency(&self) -> LatencySummary {
+        self.connect_latency.summary()
+    }
+
+    pub fn syn_rtt(&self) -> LatencySummary {
+        self.syn_rtt.summary()
+    }
+
+    /// Targets queued for the probe pipeline.
+    pub fn set_pipeline_queue(&self, depth: usize, capacity: usize) {
+        self.queues
+            .pipeline_depth
+            .store(depth as u64, Ordering::Relaxed);
+        self.queues
+            .pipeline_capacity
+            .store(capacity as u64, Ordering::Relaxed);
+    }
+
+    /// Results queued for the DB writer.
+    pub fn set_result_queue(&self, depth: usize, capacity: usize) {
+        self.queues
+            .result_depth
+            .store(depth as u64, Ordering::Relaxed);
+        self.queues
+            .result_capacity
+            .store(capacity as u64, Ordering::Relaxed);
+    }
+
+    /// A finished DB flush, with the batching the writer uses next.
+    pub fn record_db_flush(&self, write: Duration, batch_size: usize, flush_interval: Duration) {
+        self.queues.db_write.record(write);
+        self.queues
+            .db_batch_size
+            .store(batch_size as u64, Ordering::Relaxed);
+        self.queues
+            .flush_interval_ms
+            .store(flush_interval.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn queue_stats(&self) -> QueueStats {
+        let load = |v: &AtomicU64| v.load(Ordering::Relaxed);
+        let q = &self.queues;
+        QueueStats {
+            pipeline: QueueDepth {
+                depth: load(&q.pipeline_depth),
+                capacity: load(&q.pipeline_capacity),
+            },
+            results: QueueDepth {
+                depth: load(&q.result_depth),
+                capacity: load(&q.result_capacity),
+            },
+            db_batch_size: load(&q.db_batch_size),
+            flush_interval_ms: load(&q.flush_interval_ms),
+            db_write: q.db_write.summary(),
+        }
+    }
+
+    pub fn live_stats(&self) -> LiveStats {
+        LiveStats {
+            scanned: self.get_scanned(),
+            open: self.get_open(),
+            errors: self.get_errors(),
+            retries: self.get_retries(),
+            scan_rate: self.get_scan_rate(),
+            elapsed_secs: self.start_time.elapsed().as_secs_f64(),
+            queues: self.queue_stats(),
+        }
+    }
+
     pub fn get_scanned(&self) -> u64 {
         self.total_scanned.load(Ordering::Relaxed)
     }
@@ -80,21 +451,6 @@ impl ScanMetrics {
             0.0
         }
     }
-
-    pub fn print_summary(&self) {
-        tracing::info!("=== Scan Metrics Summary ===");
-        tracing::info!("  Total scanned: {}", self.get_scanned());
-        tracing::info!("  Total open ports: {}", self.get_open());
-        tracing::info!("  Total errors: {}", self.get_errors());
-        tracing::info!("  Total retries: {}", self.get_retries());
-        tracing::info!("  Scan rate: {:.2} targets/sec", self.get_scan_rate());
-        tracing::info!("  Success rate: {:.2}%", self.get_success_rate());
-        tracing::info!("  Open port rate: {:.4}%", self.get_open_rate());
-        tracing::info!(
-            "  Elapsed time: {:.2}s",
-            self.start_time.elapsed().as_secs_f64()
-        );
-    }
 }
 
 impl Default for ScanMetrics {
@@ -143,4 +499,131 @@ mod tests {
         assert_eq!(metrics.get_success_rate(), 80.0);
         assert_eq!(metrics.get_open_rate(), 50.0);
     }
+
+    #[test]
+    fn test_breakdown_by_port_and_prefix() {
+        let metrics = ScanMetrics::new();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        for _ in 0..3 {
+            metrics.record_scanned(ip("10.0.0.1"), 80);
+        }
+        metrics.record_open(ip("10.0.0.1"), 80);
+        metrics.record_scanned(ip("192.0.2.1"), 443);
+        metrics.record_error(ip("192.0.2.1"), 443);
+        metrics.record_scanned(ip("2001:db8::1"), 22);
+
+        assert_eq!(metrics.get_scanned(), 5);
+        assert_eq!(metrics.get_errors(), 1);
+
+        let ports = metrics.top_ports(10);
+        let keys: Vec<&str> = ports.iter().map(|e| e.key.as_str()).collect();
+        // Errors rank first, then traffic.
+        assert_eq!(keys, ["443", "80", "22"]);
+        assert_eq!((ports[1].scanned, ports[1].open), (3, 1));
+        assert_eq!(metrics.top_ports(1).len(), 1);
+
+        let prefixes = metrics.top_prefixes(10);
+        assert_eq!(prefixes.len(), 2);
+        assert_eq!(prefixes[0].key, "192.0.0.0/8");
+        assert_eq!(prefixes[1].key, "10.0.0.0/8");
+    }
+
+    #[test]
+    fn test_reply_stats() {
+        let metrics = ScanMetrics::new();
+        assert_eq!(metrics.reply_stats(), ReplyStats::default());
+
+        for _ in 0..10 {
+            metrics.increment_scanned();
+        }
+        metrics.record_reply(false);
+        metrics.record_reply(true);
+        metrics.record_reply(true);
+        let stats = metrics.reply_stats();
+        assert_eq!((stats.sent, stats.syn_ack, stats.rst), (10, 1, 2));
+        assert_eq!(stats.rst_ratio, 0.2);
+        assert_eq!(stats.no_answer_ratio, 0.7);
+    }
+
+    #[test]
+    fn test_queue_stats() {
+        let metrics = ScanMetrics::new();
+        assert_eq!(metrics.queue_stats(), QueueStats::default());
+
+        metrics.set_pipeline_queue(3, 10);
+        metrics.set_result_queue(7, 100);
+        metrics.record_db_flush(Duration::from_millis(4), 2000, Duration::from_millis(250));
+        let stats = metrics.queue_stats();
+        assert_eq!(
+            stats.pipeline,
+            QueueDepth {
+                depth: 3,
+                capacity: 10
+            }
+        );
+        assert_eq!(
+            stats.results,
+            QueueDepth {
+                depth: 7,
+                capacity: 100
+            }
+        );
+        assert_eq!((stats.db_batch_size, stats.flush_interval_ms), (2000, 250));
+        assert_eq!(stats.db_write.count, 1);
+    }
+
+    #[test]
+    fn test_live_stats() {
+        let metrics = ScanMetrics::new();
+        for _ in 0..4 {
+            metrics.increment_scanned();
+        }
+        metrics.increment_open();
+        metrics.increment_errors();
+        metrics.set_result_queue(5, 50);
+        let stats = metrics.live_stats();
+        assert_eq!((stats.scanned, stats.open), (4, 1));
+        assert_eq!((stats.errors, stats.retries), (1, 0));
+        assert!(stats.scan_rate > 0.0 && stats.elapsed_secs > 0.0);
+        assert_eq!(stats.queues, metrics.queue_stats());
+    }
+
+    #[test]
+    fn test_bucket_bounds_are_contiguous() {
+        for value in [0u64, 15, 16, 31, 32, 1000, 65_535, u32::MAX as u64] {
+            let index = bucket_index(value);
+            assert!(index < BUCKET_COUNT);
+            assert!(bucket_upper_bound(index) >= value);
+            if index > 0 {
+                assert!(bucket_upper_bound(index - 1) < value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let histogram = LatencyHistogram::new();
+        assert_eq!(histogram.summary(), LatencySummary::default());
+
+        for ms in 1..=100 {
+            histogram.record(Duration::from_millis(ms));
+        }
+        let summary = histogram.summary();
+        assert_eq!(summary.count, 100);
+        // Within the 1/16 bucket resolution of the true value.
+        for (actual, expected) in [
+            (summary.p50_ms, 50.0),
+            (summary.p95_ms, 95.0),
+            (summary.p99_ms, 99.0),
+        ] {
+            assert!(
+                actual >= expected && actual <= expected * 1.07,
+                "{} vs {}",
+                actual,
+                expected
+            );
+        }
+        assert_eq!(summary.max_ms, 100.0);
+    }
 }
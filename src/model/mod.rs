@@ -1,11 +1,25 @@
 mod bitmap;
+mod domain_seeds;
+mod exclude_list;
 pub mod geo;
+mod hostname_list;
 mod ip_range;
 mod metrics;
+mod open_port;
+mod reputation;
+mod scan_window;
 pub mod service_info;
+mod source_ports;
 
 pub use bitmap::{index_to_ipv4, ipv4_to_index, PortBitmap};
+pub use domain_seeds::{DomainSeeds, MAX_SEED_DOMAINS};
+pub use exclude_list::ExcludeList;
 pub use geo::IpGeoInfo;
-pub use ip_range::{parse_port_range, IpRange};
-pub use metrics::ScanMetrics;
-pub use service_info::{IpServiceSummary, ServiceInfo};
+pub use hostname_list::{is_hostname, HostnameList};
+pub use ip_range::{expand_port_groups, parse_port_range, read_ports_file, IpRange};
+pub use metrics::{BreakdownEntry, LatencySummary, ReplyStats, ScanMetrics};
+pub use open_port::OpenPort;
+pub use reputation::{IpReputation, RISKY_SCORE};
+pub use scan_window::ScanWindow;
+pub use service_info::{CveMatch, IpServiceSummary, ServiceInfo, VirtualHostInfo};
+pub use source_ports::SourcePorts;
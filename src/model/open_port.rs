@@ -0,0 +1,11 @@
+use serde::Serialize;
+use std::net::IpAddr;
+
+/// An open port as reported by a scanner, published to subscribers as soon
+/// as it is found (before the batched database write).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct OpenPort {
+    pub ip: IpAddr,
+    pub port: u16,
+    pub scan_round: i64,
+}
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+/// Abuse score (0-100) from which a host counts as risky.
+pub const RISKY_SCORE: u8 = 50;
+
+/// What one reputation source reported for an IP. A lookup that found
+/// nothing is still recorded, with every field empty, so the IP is not
+/// checked again until the entry expires.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpReputation {
+    pub ip: String,
+    pub source: String,
+    /// Abuse confidence, 0 (clean) to 100
+    pub score: Option<u8>,
+    /// `malicious`, `benign` or `unknown` where the source classifies hosts
+    pub classification: Option<String>,
+    /// The host is itself seen scanning the internet (research scanners,
+    /// botnets, honeypot-style noise)
+    pub scanner: bool,
+    /// Reports filed against the host, where the source counts them
+    pub reports: Option<u32>,
+}
+
+impl IpReputation {
+    pub fn new(ip: String, source: String) -> Self {
+        Self {
+            ip,
+            source,
+            score: None,
+            classification: None,
+            scanner: false,
+            reports: None,
+        }
+    }
+
+    pub fn is_risky(&self) -> bool {
+        self.score.is_some_and(|score| score >= RISKY_SCORE)
+            || self.classification.as_deref() == Some("malicious")
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::NaiveTime;
+use std::fmt;
+use std::time::Duration;
+
+/// Daily time-of-day window (local time) in which scanning is permitted,
+/// e.g. `22:00-06:00`. Windows whose end is earlier than their start wrap
+/// past midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl ScanWindow {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (start, end) = value
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid scan window '{}', expected HH:MM-HH:MM", value))?;
+        let parse_time = |part: &str| {
+            NaiveTime::parse_from_str(part.trim(), "%H:%M")
+                .map_err(|_| format!("Invalid time '{}' in scan window, expected HH:MM", part))
+        };
+        let window = ScanWindow {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        };
+        if window.start == window.end {
+            return Err(format!(
+                "Scan window '{}' is empty; omit --scan-window to scan around the clock",
+                value
+            ));
+        }
+        Ok(window)
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// Time from `now` until the window next opens; zero when already inside.
+    pub fn wait_from(&self, now: NaiveTime) -> Duration {
+        if self.contains(now) {
+            return Duration::ZERO;
+        }
+        let mut wait = self.start.signed_duration_since(now);
+        if wait < chrono::Duration::zero() {
+            wait += chrono::Duration::days(1);
+        }
+        wait.to_std().unwrap_or(Duration::ZERO)
+    }
+}
+
+impl fmt::Display for ScanWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_daytime_window() {
+        let window = ScanWindow::parse("09:00-17:30").unwrap();
+        assert!(window.contains(t(9, 0)));
+        assert!(window.contains(t(17, 29)));
+        assert!(!window.contains(t(17, 30)));
+        assert!(!window.contains(t(8, 59)));
+        assert_eq!(window.wait_from(t(8, 0)), Duration::from_secs(3600));
+        assert_eq!(window.to_string(), "09:00-17:30");
+    }
+
+    #[test]
+    fn test_overnight_window_wraps_midnight() {
+        let window = ScanWindow::parse("22:00-06:00").unwrap();
+        assert!(window.contains(t(23, 0)));
+        assert!(window.contains(t(0, 30)));
+        assert!(!window.contains(t(6, 0)));
+        assert!(!window.contains(t(12, 0)));
+        assert_eq!(window.wait_from(t(1, 0)), Duration::ZERO);
+        assert_eq!(window.wait_from(t(21, 0)), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn test_rejects_malformed_windows() {
+        assert!(ScanWindow::parse("22:00").is_err());
+        assert!(ScanWindow::parse("25:00-06:00").is_err());
+        assert!(ScanWindow::parse("08:00-08:00").is_err());
+    }
+}
@@ -135,6 +135,36 @@ impl ServiceInfo {
     }
 }
 
+/// A CVE whose affected versions include the product and version detected
+/// on a port. Candidates only: backported fixes and build options are not
+/// visible to the scanner.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CveMatch {
+    pub cve_id: String,
+    /// CPE product name, e.g. `openssh`
+    pub product: String,
+    /// Version as detected on the port
+    pub version: String,
+    /// CVSS base score from the dataset (v3.1, else v3.0, else v2)
+    pub cvss: Option<f64>,
+}
+
+/// HTTP and TLS results for one hostname served on an IP and port, probed
+/// with that name as SNI and `Host` header.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VirtualHostInfo {
+    pub ip: String,
+    pub port: u16,
+    pub hostname: String,
+    pub http_status: Option<u16>,
+    pub http_title: Option<String>,
+    pub http_server: Option<String>,
+    pub tls_subject: Option<String>,
+    pub tls_issuer: Option<String>,
+    pub tls_version: Option<String>,
+    pub detected_at: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IpServiceSummary {
     pub ip: String,
@@ -0,0 +1,83 @@
+use rand::Rng;
+use std::fmt;
+
+/// Inclusive range of local ports probes are sent from, e.g. `40000-50000`,
+/// so scan traffic can be allow-listed by source port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourcePorts {
+    start: u16,
+    end: u16,
+}
+
+impl SourcePorts {
+    /// Accepts `START-END` or a single port.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (start, end) = value.split_once('-').unwrap_or((value, value));
+        let parse_port = |part: &str| {
+            part.trim()
+                .parse::<u16>()
+                .ok()
+                .filter(|&p| p > 0)
+                .ok_or_else(|| format!("Invalid source port '{}', expected 1-65535", part))
+        };
+        let ports = SourcePorts {
+            start: parse_port(start)?,
+            end: parse_port(end)?,
+        };
+        if ports.start > ports.end {
+            return Err(format!(
+                "Invalid source port range '{}': start is above end",
+                value
+            ));
+        }
+        Ok(ports)
+    }
+
+    /// A uniformly random port from the range.
+    pub fn pick(&self) -> u16 {
+        rand::thread_rng().gen_range(self.start..=self.end)
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        (self.start..=self.end).contains(&port)
+    }
+}
+
+/// The unprivileged ports SYN probes use when no range is configured.
+impl Default for SourcePorts {
+    fn default() -> Self {
+        SourcePorts {
+            start: 1025,
+            end: 65535,
+        }
+    }
+}
+
+impl fmt::Display for SourcePorts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_pick_within_range() {
+        let ports = SourcePorts::parse("40000-40003").unwrap();
+        assert_eq!(ports.to_string(), "40000-40003");
+        for _ in 0..100 {
+            assert!(ports.contains(ports.pick()));
+        }
+        assert_eq!(SourcePorts::parse(" 5353 ").unwrap().pick(), 5353);
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_ranges() {
+        assert!(SourcePorts::parse("50000-40000").is_err());
+        assert!(SourcePorts::parse("0-100").is_err());
+        assert!(SourcePorts::parse("40000-70000").is_err());
+        assert!(SourcePorts::parse("abc").is_err());
+    }
+}
@@ -0,0 +1,377 @@
+//! High-level entry point for embedding the scanner in other programs.
+
+use crate::cli;
+use crate::dao::SqliteDB;
+use crate::model::{parse_port_range, ExcludeList, IpRange, OpenPort, ScanMetrics};
+use crate::service::{ConScanner, ConScannerConfig, Scanner, SynScanner};
+use anyhow::{anyhow, Result};
+use futures::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+const PIPELINE_BUFFER: usize = 2000;
+const RESULT_BUFFER: usize = 1024;
+
+/// A single scan round over a fixed set of targets.
+///
+/// ```no_run
+/// use futures::StreamExt;
+/// use ip_scan::Scan;
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let mut scan = Scan::builder()
+///     .target("192.0.2.0/24")
+///     .ports("web,22")
+///     .build()?
+///     .start()
+///     .await?;
+/// while let Some(open) = scan.next().await {
+///     println!("{}:{}", open.ip, open.port);
+/// }
+/// let metrics = scan.finish().await?;
+/// println!("{} probes", metrics.get_scanned());
+/// # Ok(())
+/// # }
+/// ```
+pub struct Scan {
+    targets: Vec<IpRange>,
+    ports: Vec<u16>,
+    syn: bool,
+    exclude: Option<ExcludeList>,
+    database: String,
+    config: ConScannerConfig,
+}
+
+/// Builder for [`Scan`]; defaults match the CLI's.
+pub struct ScanBuilder {
+    targets: Vec<String>,
+    ports: String,
+    syn: bool,
+    exclude: Option<ExcludeList>,
+    database: String,
+    config: ConScannerConfig,
+}
+
+impl Scan {
+    pub fn builder() -> ScanBuilder {
+        ScanBuilder {
+            targets: Vec::new(),
+            ports: cli::default_ports(),
+            syn: false,
+            exclude: None,
+            database: ":memory:".to_string(),
+            config: ConScannerConfig {
+                timeout_ms: cli::default_timeout(),
+                concurrent_limit: cli::default_concurrency(),
+                host_concurrent_limit: cli::default_host_concurrency(),
+                result_buffer: cli::default_result_buffer(),
+                db_batch_size: cli::default_db_batch_size(),
+                flush_interval_ms: cli::default_flush_interval_ms(),
+                adaptive_batching: false,
+                max_rate: cli::default_max_rate(),
+                rate_window_secs: cli::default_window_duration(),
+                rate_burst: 0,
+                hooks: None,
+                cancel: CancellationToken::new(),
+                io_uring: false,
+                source_ports: None,
+                banner_grab_ms: 0,
+            },
+        }
+    }
+
+    /// Open the database, create the scanner and start scanning in the
+    /// background. Open ports are delivered through the returned handle.
+    pub async fn start(self) -> Result<ScanHandle> {
+        let db = SqliteDB::new(&self.database)?;
+        let round = db.get_current_round()?;
+        let scanner: Box<dyn Scanner> = if self.syn {
+            let c = &self.config;
+            Box::new(SynScanner::new(
+                db,
+                round,
+                c.result_buffer,
+                c.db_batch_size,
+                c.flush_interval_ms,
+                c.adaptive_batching,
+                c.max_rate,
+                c.rate_window_secs,
+                c.rate_burst,
+                c.hooks.clone(),
+                c.cancel.clone(),
+            )?)
+        } else {
+            Box::new(ConScanner::new(db, round, self.config.clone()))
+        };
+        // Subscribe before any probe is sent so no result is missed.
+        let events = scanner.subscribe();
+
+        let (results_tx, results) = mpsc::channel(RESULT_BUFFER);
+        let (done_tx, done_rx) = oneshot::channel();
+        let forwarder = tokio::spawn(forward_results(events, results_tx, done_rx));
+
+        let (ip_tx, ip_rx) = mpsc::channel(PIPELINE_BUFFER);
+        let Scan {
+            targets,
+            ports,
+            exclude,
+            config,
+            ..
+        } = self;
+        let cancel = config.cancel;
+        let producer_cancel = cancel.clone();
+        let producer = tokio::spawn(async move {
+            for range in targets {
+                for ip in range.iter() {
+                    if exclude.as_ref().is_some_and(|list| list.contains(ip)) {
+                        continue;
+                    }
+                    tokio::select! {
+                        _ = producer_cancel.cancelled() => return,
+                        sent = ip_tx.send(ip) => {
+                            if sent.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        let task = tokio::spawn(async move {
+            let result = run_scanner(scanner, ip_rx, ports).await;
+            producer.abort();
+            // SYN receiver threads outlive the scan, so the event channel
+            // never closes on its own.
+            let _ = done_tx.send(());
+            let _ = forwarder.await;
+            result
+        });
+        Ok(ScanHandle {
+            results,
+            task,
+            cancel,
+        })
+    }
+}
+
+impl ScanBuilder {
+    /// Add an IP, CIDR or `start-end` range. May be called repeatedly.
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.targets.push(target.into());
+        self
+    }
+
+    /// Ports, ranges and named groups in `--ports` syntax.
+    pub fn ports(mut self, ports: impl Into<String>) -> Self {
+        self.ports = ports.into();
+        self
+    }
+
+    /// Use raw SYN probes (IPv4 only, needs root/CAP_NET_RAW).
+    pub fn syn(mut self, syn: bool) -> Self {
+        self.syn = syn;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout_ms = timeout.as_millis() as u64;
+        self
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.config.concurrent_limit = concurrency;
+        self
+    }
+
+    /// Ports probed at once on a single host; defaults to 4.
+    pub fn host_concurrency(mut self, concurrency: usize) -> Self {
+        self.config.host_concurrent_limit = concurrency;
+        self
+    }
+
+    /// Maximum probes per rate window (one second by default).
+    pub fn max_rate(mut self, max_rate: u64) -> Self {
+        self.config.max_rate = max_rate;
+        self
+    }
+
+    /// Probes allowed back to back before the `max_rate` pace applies;
+    /// 0 (the default) allows 10 ms worth.
+    pub fn rate_burst(mut self, burst: usize) -> Self {
+        self.config.rate_burst = burst;
+        self
+    }
+
+    /// Connect through io_uring instead of a task per port (Linux only;
+    /// ignored by SYN scans).
+    pub fn io_uring(mut self, enabled: bool) -> Self {
+        self.config.io_uring = enabled;
+        self
+    }
+
+    /// Resize DB write batches from queue occupancy and write latency.
+    pub fn adaptive_batching(mut self, enabled: bool) -> Self {
+        self.config.adaptive_batching = enabled;
+        self
+    }
+
+    pub fn exclude(mut self, exclude: ExcludeList) -> Self {
+        self.exclude = Some(exclude);
+        self
+    }
+
+    /// SQLite file that receives the results; in-memory by default.
+    pub fn database(mut self, path: impl Into<String>) -> Self {
+        self.database = path.into();
+        self
+    }
+
+    pub fn build(self) -> Result<Scan> {
+        if self.targets.is_empty() {
+            return Err(anyhow!("At least one target is required"));
+        }
+        if self.config.timeout_ms == 0
+            || self.config.concurrent_limit == 0
+            || self.config.host_concurrent_limit == 0
+        {
+            return Err(anyhow!("Timeout and concurrency must be positive"));
+        }
+        let targets = self
+            .targets
+            .iter()
+            .map(|t| IpRange::parse_target(t).map_err(|e| anyhow!("Invalid target {}: {}", t, e)))
+            .collect::<Result<Vec<_>>>()?;
+        let ports = parse_port_range(&self.ports, &Default::default()).map_err(|e| anyhow!(e))?;
+        Ok(Scan {
+            targets,
+            ports,
+            syn: self.syn,
+            exclude: self.exclude,
+            database: self.database,
+            config: self.config,
+        })
+    }
+}
+
+/// A running scan. As a [`Stream`] it yields open ports as they are found
+/// and ends when the scan is complete.
+pub struct ScanHandle {
+    results: mpsc::Receiver<OpenPort>,
+    task: JoinHandle<Result<ScanMetrics>>,
+    cancel: CancellationToken,
+}
+
+impl ScanHandle {
+    /// Stop sending probes. Open ports already found are still written and
+    /// delivered, after which the stream ends.
+    pub fn stop(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Wait for the scan to complete, discarding results not yet consumed.
+    pub async fn finish(self) -> Result<ScanMetrics> {
+        drop(self.results);
+        self.task.await?
+    }
+}
+
+impl Stream for ScanHandle {
+    type Item = OpenPort;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<OpenPort>> {
+        self.results.poll_recv(cx)
+    }
+}
+
+async fn run_scanner(
+    scanner: Box<dyn Scanner>,
+    rx: mpsc::Receiver<std::net::IpAddr>,
+    ports: Vec<u16>,
+) -> Result<ScanMetrics> {
+    scanner.run_pipeline(rx, ports, Box::new(|_| {})).await?;
+    let metrics = scanner.get_metrics().clone();
+    scanner.finish().await;
+    Ok(metrics)
+}
+
+async fn forward_results(
+    mut events: broadcast::Receiver<OpenPort>,
+    results: mpsc::Sender<OpenPort>,
+    mut done: oneshot::Receiver<()>,
+) {
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            _ = &mut done => break,
+        };
+        match event {
+            Ok(open) => {
+                if results.send(open).await.is_err() {
+                    return;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Result stream lagged, {} open ports not delivered", missed);
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+    while let Ok(open) = events.try_recv() {
+        if results.send(open).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_scan_streams_open_ports() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+
+        let closed_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_port = closed_listener.local_addr().unwrap().port();
+        drop(closed_listener);
+
+        let mut scan = Scan::builder()
+            .target("127.0.0.1")
+            .ports(format!("{},{}", port, closed_port))
+            .timeout(Duration::from_millis(200))
+            .build()
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+
+        let found: Vec<OpenPort> = (&mut scan).collect().await;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].port, port);
+        assert_eq!(found[0].ip.to_string(), "127.0.0.1");
+
+        let metrics = scan.finish().await.unwrap();
+        assert_eq!(metrics.get_scanned(), 2);
+        assert_eq!(metrics.get_open(), 1);
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_input() {
+        assert!(Scan::builder().build().is_err());
+        assert!(Scan::builder().target("not-an-ip").build().is_err());
+        assert!(Scan::builder()
+            .target("10.0.0.1")
+            .ports("70000")
+            .build()
+            .is_err());
+    }
+}
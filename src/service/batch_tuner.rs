@@ -0,0 +1,131 @@
+//! Adaptive DB writer batching (`--adaptive-batching`).
+//!
+//! After every flush the writer reports how full its result channel was and
+//! how long the write took. A backed-up channel means the writer is falling
+//! behind, so batches grow (fewer, larger transactions) and the flush timer
+//! shortens; slow writes hold the database lock long enough to stall API
+//! readers, so batches shrink; an idle channel drifts back to the configured
+//! values, which keeps results fresh.
+
+use crate::model::ScanMetrics;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Channel fill above which the writer is considered behind.
+const BACKLOG: f64 = 0.5;
+/// Channel fill below which the writer is considered idle.
+const IDLE: f64 = 0.05;
+/// Flushes slower than this shrink the batch.
+const SLOW_WRITE: Duration = Duration::from_millis(500);
+const MIN_BATCH: usize = 64;
+const MAX_BATCH: usize = 50_000;
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct BatchTuner {
+    batch_size: usize,
+    flush_interval: Duration,
+    base_batch_size: usize,
+    base_interval: Duration,
+    adaptive: bool,
+}
+
+impl BatchTuner {
+    /// Start from the configured values; with `adaptive` off they never
+    /// change.
+    pub(crate) fn new(batch_size: usize, flush_interval: Duration, adaptive: bool) -> Self {
+        let batch_size = batch_size.max(1);
+        BatchTuner {
+            batch_size,
+            flush_interval,
+            base_batch_size: batch_size,
+            base_interval: flush_interval,
+            adaptive,
+        }
+    }
+
+    pub(crate) fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    pub(crate) fn flush_interval(&self) -> Duration {
+        self.flush_interval
+    }
+
+    /// Adjust after a flush. `fill` is the result channel's occupancy (0 to
+    /// 1) once the flush finished; `write` is how long the flush took.
+    pub(crate) fn observe(&mut self, fill: f64, write: Duration) {
+        if !self.adaptive {
+            return;
+        }
+        if write > SLOW_WRITE {
+            self.batch_size = (self.batch_size / 2).max(MIN_BATCH.min(self.base_batch_size));
+        } else if fill >= BACKLOG {
+            self.batch_size = (self.batch_size * 2).min(MAX_BATCH.max(self.base_batch_size));
+            self.flush_interval =
+                (self.flush_interval / 2).max(MIN_INTERVAL.min(self.base_interval));
+        } else if fill <= IDLE {
+            self.batch_size = if self.batch_size > self.base_batch_size {
+                (self.batch_size * 3 / 4).max(self.base_batch_size)
+            } else {
+                (self.batch_size * 5 / 4 + 1).min(self.base_batch_size)
+            };
+            self.flush_interval = (self.flush_interval * 2).min(self.base_interval);
+        }
+    }
+
+    /// Retune after a flush that began at `started` by the writer draining
+    /// `rx`, and publish the writer's state to `metrics`.
+    pub(crate) fn flushed<T>(
+        &mut self,
+        started: Instant,
+        rx: &mpsc::Receiver<T>,
+        metrics: &ScanMetrics,
+    ) {
+        let write = started.elapsed();
+        let fill = rx.len() as f64 / rx.max_capacity().max(1) as f64;
+        self.observe(fill, write);
+        metrics.record_db_flush(write, self.batch_size, self.flush_interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_tuner_ignores_observations() {
+        let mut tuner = BatchTuner::new(500, Duration::from_secs(1), false);
+        tuner.observe(1.0, Duration::ZERO);
+        tuner.observe(0.0, Duration::from_secs(5));
+        assert_eq!(tuner.batch_size(), 500);
+        assert_eq!(tuner.flush_interval(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_backlog_grows_batches_and_idle_returns_to_base() {
+        let mut tuner = BatchTuner::new(500, Duration::from_secs(1), true);
+        for _ in 0..10 {
+            tuner.observe(0.9, Duration::from_millis(20));
+        }
+        assert_eq!(tuner.batch_size(), MAX_BATCH);
+        assert_eq!(tuner.flush_interval(), MIN_INTERVAL);
+
+        for _ in 0..40 {
+            tuner.observe(0.0, Duration::from_millis(20));
+        }
+        assert_eq!(tuner.batch_size(), 500);
+        assert_eq!(tuner.flush_interval(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_slow_writes_shrink_batches() {
+        let mut tuner = BatchTuner::new(4000, Duration::from_secs(1), true);
+        tuner.observe(0.9, Duration::from_secs(2));
+        assert_eq!(tuner.batch_size(), 2000);
+        for _ in 0..20 {
+            tuner.observe(0.9, Duration::from_secs(2));
+        }
+        assert_eq!(tuner.batch_size(), MIN_BATCH);
+    }
+}
@@ -0,0 +1,778 @@
+//! Coordinator/worker distributed scanning.
+//!
+//! The coordinator splits the IPv4 target range of each round into
+//! `cluster_leases` rows and hands them out over the HTTP API. A worker
+//! leases a slice, scans it into an in-memory database, renews the lease
+//! while it runs and posts the open ports back when the slice is done. A
+//! slice whose worker stops renewing expires and goes to the next worker
+//! that asks, so losing a worker only costs the slice it held.
+
+use super::syslog::local_hostname;
+use super::{scanner_from_args, EventBus, ScanEvent};
+use crate::cli::Args;
+use crate::dao::{ClusterLease, ClusterProgress, RoundMetrics, SqliteDB};
+use crate::model::{parse_port_range, ExcludeList, IpRange, OpenPort, ScanMetrics, ScanWindow};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// How often the coordinator checks whether the round is finished.
+const ROUND_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// How long an idle worker waits before asking for work again.
+const IDLE_POLL: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const REPORT_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LeaseRequest {
+    pub worker_id: String,
+}
+
+/// A slice of the target range and what to scan in it.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LeaseGrant {
+    pub lease_id: i64,
+    pub round: i64,
+    pub start_ip: String,
+    pub end_ip: String,
+    /// Port expression, as for `--ports`
+    pub ports: String,
+    pub skip_private: bool,
+    /// Coordinator exclusions inside this slice, as `a.b.c.d-w.x.y.z`
+    pub exclude: Vec<String>,
+    /// Renew well before this many seconds pass
+    pub lease_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LeaseResult {
+    pub ip: String,
+    pub port: u16,
+}
+
+/// Counters and open ports of a finished slice.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LeaseReport {
+    pub worker_id: String,
+    pub scanned: u64,
+    pub errors: u64,
+    pub retries: u64,
+    pub duration_secs: f64,
+    pub results: Vec<LeaseResult>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ClusterStatus {
+    /// The round is complete and loop mode is off; no more leases are issued
+    pub finished: bool,
+    #[serde(flatten)]
+    pub progress: ClusterProgress,
+}
+
+pub enum LeaseOutcome {
+    Granted(LeaseGrant),
+    /// Every slice is leased, or the scan window is closed
+    Wait,
+    Finished,
+}
+
+pub enum ReportOutcome {
+    Accepted(usize),
+    /// The lease expired and went to another worker, or is already done
+    NotHeld,
+    Invalid(String),
+}
+
+pub struct Coordinator {
+    db: SqliteDB,
+    start: u32,
+    end: u32,
+    ports: String,
+    port_set: HashSet<u16>,
+    lease_size: u64,
+    lease_secs: u64,
+    skip_private: bool,
+    exclude: Option<ExcludeList>,
+    scan_window: Option<ScanWindow>,
+    loop_mode: bool,
+    round_delay: Duration,
+    stale_rounds: u32,
+    token: Option<String>,
+    events: Option<EventBus>,
+    round: AtomicI64,
+    finished: AtomicBool,
+}
+
+impl Coordinator {
+    /// Resume the current round, or start the next one if it is complete.
+    pub fn new(db: SqliteDB, args: &Args, events: Option<EventBus>) -> Result<Self> {
+        let (start, end) = args
+            .start_ip
+            .clone()
+            .zip(args.end_ip.clone())
+            .unwrap_or_else(Args::get_default_ipv4_range);
+        let range = IpRange::new(&start, &end).map_err(|e| anyhow!(e))?;
+        let (IpAddr::V4(start), IpAddr::V4(end)) = (range.start, range.end) else {
+            return Err(anyhow!("--coordinator only distributes IPv4 ranges"));
+        };
+        if start > end {
+            return Err(anyhow!("Target range {}-{} is reversed", start, end));
+        }
+        let port_set = parse_port_range(&args.ports, &args.port_groups)
+            .map_err(|e| anyhow!(e))?
+            .into_iter()
+            .collect();
+
+        let mut round = db.get_current_round()?;
+        if round_complete(&db, round)? {
+            round = db.increment_round()?;
+        }
+        let coordinator = Self {
+            db,
+            start: u32::from(start),
+            end: u32::from(end),
+            ports: args.ports.clone(),
+            port_set,
+            lease_size: args.lease_size,
+            lease_secs: args.lease_secs,
+            skip_private: args.skip_private,
+            exclude: args.load_exclude_list()?,
+            scan_window: args.parsed_scan_window()?,
+            loop_mode: args.loop_mode,
+            round_delay: Duration::from_millis(args.round_delay_ms),
+            stale_rounds: args.stale_rounds,
+            token: args.cluster_token.clone(),
+            events,
+            round: AtomicI64::new(round),
+            finished: AtomicBool::new(false),
+        };
+        coordinator.start_round(round)?;
+        Ok(coordinator)
+    }
+
+    /// Whether a request's `Authorization` header carries the cluster token.
+    pub fn authorized(&self, authorization: Option<&str>) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        let presented = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        presented.len() == token.len()
+            && presented
+                .bytes()
+                .zip(token.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+
+    pub fn lease(&self, worker_id: &str) -> Result<LeaseOutcome> {
+        if self.finished.load(Ordering::SeqCst) {
+            return Ok(LeaseOutcome::Finished);
+        }
+        if let Some(window) = self.scan_window {
+            if !window.contains(chrono::Local::now().time()) {
+                return Ok(LeaseOutcome::Wait);
+            }
+        }
+        let now = chrono::Utc::now().timestamp();
+        let round = self.round.load(Ordering::SeqCst);
+        let Some(lease) =
+            self.db
+                .acquire_cluster_lease(round, worker_id, now, now + self.lease_secs as i64)?
+        else {
+            return Ok(LeaseOutcome::Wait);
+        };
+        let (start, end) = lease_bounds(&lease)?;
+        let exclude = self
+            .exclude
+            .as_ref()
+            .map(|list| {
+                list.v4_overlaps(start, end)
+                    .into_iter()
+                    .map(|(s, e)| format!("{}-{}", Ipv4Addr::from(s), Ipv4Addr::from(e)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        info!(
+            "Leased {} - {} (round {}) to {}",
+            lease.start_ip, lease.end_ip, lease.round, worker_id
+        );
+        Ok(LeaseOutcome::Granted(LeaseGrant {
+            lease_id: lease.id,
+            round: lease.round,
+            start_ip: lease.start_ip,
+            end_ip: lease.end_ip,
+            ports: self.ports.clone(),
+            skip_private: self.skip_private,
+            exclude,
+            lease_secs: self.lease_secs,
+        }))
+    }
+
+    /// Extend a lease; false once the worker no longer holds it.
+    pub fn renew(&self, lease_id: i64, worker_id: &str) -> Result<bool> {
+        let expires_at = chrono::Utc::now().timestamp() + self.lease_secs as i64;
+        Ok(self
+            .db
+            .renew_cluster_lease(lease_id, worker_id, expires_at)?
+            .is_some())
+    }
+
+    /// Store a finished slice. Results are only accepted for addresses and
+    /// ports the lease covers, and are written before the lease is closed so
+    /// a crash in between costs a rescan, never results.
+    pub fn complete(&self, lease_id: i64, report: LeaseReport) -> Result<ReportOutcome> {
+        let expires_at = chrono::Utc::now().timestamp() + self.lease_secs as i64;
+        let Some(lease) = self
+            .db
+            .renew_cluster_lease(lease_id, &report.worker_id, expires_at)?
+        else {
+            return Ok(ReportOutcome::NotHeld);
+        };
+        let (start, end) = lease_bounds(&lease)?;
+        let mut updates = Vec::with_capacity(report.results.len());
+        for result in &report.results {
+            let ip = result
+                .ip
+                .parse::<Ipv4Addr>()
+                .ok()
+                .filter(|ip| (start..=end).contains(&u32::from(*ip)));
+            let Some(ip) = ip.filter(|_| self.port_set.contains(&result.port)) else {
+                return Ok(ReportOutcome::Invalid(format!(
+                    "{}:{} is outside lease {}",
+                    result.ip, result.port, lease_id
+                )));
+            };
+            updates.push((IpAddr::V4(ip), result.port, true));
+        }
+        let open = updates.len();
+        self.db.bulk_update_port_status(updates, lease.round)?;
+        if !self.db.complete_cluster_lease(
+            lease_id,
+            &report.worker_id,
+            report.scanned,
+            open as u64,
+        )? {
+            return Ok(ReportOutcome::NotHeld);
+        }
+        // Workers only report open results; the whole lease was probed.
+        self.db.record_scanned_range(lease.round, start, end)?;
+        self.db.save_round_metrics(&RoundMetrics {
+            round: lease.round,
+            scanned: report.scanned,
+            open: open as u64,
+            errors: report.errors,
+            retries: report.retries,
+            duration_secs: report.duration_secs,
+            avg_rate: 0.0,
+            finished_at: String::new(),
+        })?;
+        if let Some(bus) = &self.events {
+            for result in &report.results {
+                if let Ok(ip) = result.ip.parse() {
+                    bus.publish(ScanEvent::OpenPort(OpenPort {
+                        ip,
+                        port: result.port,
+                        scan_round: lease.round,
+                    }));
+                }
+            }
+        }
+        info!(
+            "{} finished {} - {}: {} scanned, {} open",
+            report.worker_id, lease.start_ip, lease.end_ip, report.scanned, open
+        );
+        Ok(ReportOutcome::Accepted(open))
+    }
+
+    pub fn status(&self) -> Result<ClusterStatus> {
+        Ok(ClusterStatus {
+            finished: self.finished.load(Ordering::SeqCst),
+            progress: self
+                .db
+                .get_cluster_progress(self.round.load(Ordering::SeqCst))?,
+        })
+    }
+
+    /// Close rounds as their last lease completes and, in loop mode, open
+    /// the next one. Runs until `stop` is set.
+    pub fn spawn(self: Arc<Self>, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            while !stop.load(Ordering::SeqCst) && !self.finished.load(Ordering::SeqCst) {
+                tokio::time::sleep(ROUND_CHECK_INTERVAL).await;
+                match self.advance() {
+                    Ok(true) if !self.round_delay.is_zero() => {
+                        let resume_at = tokio::time::Instant::now() + self.round_delay;
+                        while !stop.load(Ordering::SeqCst)
+                            && tokio::time::Instant::now() < resume_at
+                        {
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                        }
+                        if let Err(e) = self.next_round() {
+                            error!("Failed to start next cluster round: {}", e);
+                        }
+                    }
+                    Ok(true) => {
+                        if let Err(e) = self.next_round() {
+                            error!("Failed to start next cluster round: {}", e);
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => error!("Cluster round check failed: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Returns true when the round just completed and another should follow.
+    fn advance(&self) -> Result<bool> {
+        let round = self.round.load(Ordering::SeqCst);
+        let progress = self.db.get_cluster_progress(round)?;
+        if progress.done < progress.total {
+            return Ok(false);
+        }
+        self.db
+            .save_metadata(&format!("round_{}_complete", round), "true")?;
+        self.db
+            .save_metadata("last_scan_time", &chrono::Utc::now().to_rfc3339())?;
+        match self.db.mark_stale_ports(round, self.stale_rounds) {
+            Ok(0) => {}
+            Ok(closed) => info!(
+                "Marked {} open ports gone after {} rounds unseen",
+                closed, self.stale_rounds
+            ),
+            Err(e) => error!("Failed to age stale open ports: {}", e),
+        }
+        if let Err(e) = self.db.checkpoint_wal() {
+            error!("WAL checkpoint failed: {}", e);
+        }
+        info!(
+            "=== Cluster round {} complete: {} leases, {} scanned, {} open ===",
+            round, progress.total, progress.scanned, progress.open
+        );
+        if let Some(bus) = &self.events {
+            let metrics = self.db.get_round_metrics(1)?.into_iter().next();
+            if let Some(metrics) = metrics.filter(|m| m.round == round) {
+                bus.publish(ScanEvent::RoundComplete(metrics));
+            }
+        }
+        if !self.loop_mode {
+            info!("Loop mode disabled, no further leases will be issued");
+            self.finished.store(true, Ordering::SeqCst);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn next_round(&self) -> Result<()> {
+        if let Ok(deleted) = self.db.cleanup_old_rounds(2) {
+            if deleted > 0 {
+                info!("Cleaned up {} old bitmap rows", deleted);
+            }
+        }
+        let round = self.db.increment_round()?;
+        self.start_round(round)?;
+        self.round.store(round, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Create the round's leases, skipping slices excluded as a whole.
+    fn start_round(&self, round: i64) -> Result<()> {
+        self.db
+            .save_metadata(&format!("round_{}_complete", round), "false")?;
+        let mut slices = Vec::new();
+        let mut start = self.start as u64;
+        while start <= self.end as u64 {
+            let end = (start + self.lease_size - 1).min(self.end as u64);
+            let (s, e) = (start as u32, end as u32);
+            let excluded = self
+                .exclude
+                .as_ref()
+                .is_some_and(|list| list.v4_overlaps(s, e) == [(s, e)]);
+            if !excluded {
+                slices.push((s, e));
+            }
+            start = end + 1;
+        }
+        self.db.create_cluster_leases(round, &slices)?;
+        let progress = self.db.get_cluster_progress(round)?;
+        info!(
+            "=== Cluster round {}: {} leases of up to {} addresses, {} done ===",
+            round, progress.total, self.lease_size, progress.done
+        );
+        Ok(())
+    }
+}
+
+fn round_complete(db: &SqliteDB, round: i64) -> Result<bool> {
+    Ok(db
+        .get_metadata(&format!("round_{}_complete", round))?
+        .is_some_and(|v| v == "true"))
+}
+
+fn lease_bounds(lease: &ClusterLease) -> Result<(u32, u32)> {
+    let start: Ipv4Addr = lease.start_ip.parse()?;
+    let end: Ipv4Addr = lease.end_ip.parse()?;
+    Ok((u32::from(start), u32::from(end)))
+}
+
+/// HTTP client side of a worker.
+#[derive(Clone)]
+struct CoordinatorClient {
+    http: reqwest::Client,
+    base: String,
+    token: Option<String>,
+    worker_id: String,
+}
+
+impl CoordinatorClient {
+    fn post<T: Serialize>(&self, path: &str, body: &T) -> reqwest::RequestBuilder {
+        let request = self
+            .http
+            .post(format!("{}/api/v1/cluster{}", self.base, path))
+            .json(body);
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn lease(&self) -> Result<LeaseOutcome> {
+        let response = self
+            .post(
+                "/leases",
+                &LeaseRequest {
+                    worker_id: self.worker_id.clone(),
+                },
+            )
+            .send()
+            .await?;
+        match response.status() {
+            reqwest::StatusCode::NO_CONTENT => Ok(LeaseOutcome::Wait),
+            reqwest::StatusCode::GONE => Ok(LeaseOutcome::Finished),
+            status if status.is_success() => Ok(LeaseOutcome::Granted(response.json().await?)),
+            status => Err(anyhow!("coordinator answered {}", status)),
+        }
+    }
+
+    /// False once the coordinator gave the lease to someone else.
+    async fn renew(&self, lease_id: i64) -> Result<bool> {
+        let response = self
+            .post(
+                &format!("/leases/{}/heartbeat", lease_id),
+                &LeaseRequest {
+                    worker_id: self.worker_id.clone(),
+                },
+            )
+            .send()
+            .await?;
+        match response.status() {
+            reqwest::StatusCode::CONFLICT => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(anyhow!("coordinator answered {}", status)),
+        }
+    }
+
+    async fn report(&self, lease_id: i64, report: &LeaseReport) -> Result<()> {
+        let response = self
+            .post(&format!("/leases/{}/complete", lease_id), report)
+            .send()
+            .await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(anyhow!("coordinator answered {}: {}", status, body))
+    }
+}
+
+/// `--worker`: lease slices from the coordinator until it has finished the
+/// scan or `stop` is set. An interrupted slice is not reported; its lease
+/// expires and another worker rescans it.
+pub async fn run_worker(args: &Args, stop: Arc<AtomicBool>) -> Result<()> {
+    let base = args
+        .worker
+        .as_deref()
+        .ok_or_else(|| anyhow!("--worker needs the coordinator URL"))?
+        .trim_end_matches('/')
+        .to_string();
+    let client = CoordinatorClient {
+        http: reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?,
+        base,
+        token: args.cluster_token.clone(),
+        worker_id: args
+            .worker_id
+            .clone()
+            .unwrap_or_else(|| format!("{}-{}", local_hostname(), std::process::id())),
+    };
+    let local_exclude = args.load_exclude_list()?.map(Arc::new);
+    info!(
+        "Worker {} polling coordinator {}",
+        client.worker_id, client.base
+    );
+
+    while !stop.load(Ordering::SeqCst) {
+        let grant = match client.lease().await {
+            Ok(LeaseOutcome::Granted(grant)) => grant,
+            Ok(LeaseOutcome::Wait) => {
+                tokio::time::sleep(IDLE_POLL).await;
+                continue;
+            }
+            Ok(LeaseOutcome::Finished) => {
+                info!("Coordinator has no more work, worker exiting");
+                break;
+            }
+            Err(e) => {
+                warn!("Lease request to {} failed: {}", client.base, e);
+                tokio::time::sleep(IDLE_POLL).await;
+                continue;
+            }
+        };
+        info!(
+            "Scanning lease {}: {} - {} (round {})",
+            grant.lease_id, grant.start_ip, grant.end_ip, grant.round
+        );
+        let report = match scan_lease(args, &client, &grant, local_exclude.clone(), &stop).await {
+            Ok(Some(report)) => report,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Lease {} failed: {}", grant.lease_id, e);
+                continue;
+            }
+        };
+        let open = report.results.len();
+        let mut attempt = 1;
+        loop {
+            match client.report(grant.lease_id, &report).await {
+                Ok(()) => {
+                    info!(
+                        "Reported lease {}: {} scanned, {} open",
+                        grant.lease_id, report.scanned, open
+                    );
+                    break;
+                }
+                Err(e) if attempt < REPORT_ATTEMPTS => {
+                    warn!("Reporting lease {} failed: {}", grant.lease_id, e);
+                    attempt += 1;
+                    tokio::time::sleep(IDLE_POLL).await;
+                }
+                Err(e) => {
+                    error!(
+                        "Giving up on lease {}, it will be rescanned: {}",
+                        grant.lease_id, e
+                    );
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Scan one slice into a throwaway database. `None` when the scan was
+/// interrupted or the lease was lost, so there is nothing to report.
+async fn scan_lease(
+    args: &Args,
+    client: &CoordinatorClient,
+    grant: &LeaseGrant,
+    local_exclude: Option<Arc<ExcludeList>>,
+    stop: &Arc<AtomicBool>,
+) -> Result<Option<LeaseReport>> {
+    let ports = parse_port_range(&grant.ports, &args.port_groups).map_err(|e| anyhow!(e))?;
+    let range = IpRange::new(&grant.start_ip, &grant.end_ip).map_err(|e| anyhow!(e))?;
+    let exclude = ExcludeList::parse(&grant.exclude.join("\n"))
+        .map_err(|e| anyhow!(e))
+        .context("Invalid exclusions from coordinator")?;
+    let db = SqliteDB::new(":memory:")?;
+
+    // Renew at a third of the lease so one missed heartbeat is survivable.
+    let lost = Arc::new(AtomicBool::new(false));
+    let heartbeat = {
+        let lost = lost.clone();
+        let client = client.clone();
+        let lease_id = grant.lease_id;
+        let interval = Duration::from_secs((grant.lease_secs / 3).max(1));
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                match client.renew(lease_id).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        warn!("Lease {} was reassigned, abandoning it", lease_id);
+                        lost.store(true, Ordering::SeqCst);
+                        return;
+                    }
+                    Err(e) => warn!("Renewing lease {} failed: {}", lease_id, e),
+                }
+            }
+        })
+    };
+
+    let (tx, rx) = tokio::sync::mpsc::channel(args.pipeline_buffer);
+    let skip_private = grant.skip_private;
+    let producer_stop = stop.clone();
+    let producer_lost = lost.clone();
+    let producer = tokio::spawn(async move {
+        for ip in range.iter() {
+            if producer_stop.load(Ordering::Relaxed) || producer_lost.load(Ordering::Relaxed) {
+                break;
+            }
+            if skip_private && Args::is_private_ipv4(&ip.to_string()) {
+                continue;
+            }
+            if exclude.contains(ip) || local_exclude.as_ref().is_some_and(|l| l.contains(ip)) {
+                continue;
+            }
+            if let IpAddr::V4(v4) = ip {
+                if v4.octets()[0] == 0 {
+                    continue;
+                }
+            }
+            if tx.send(ip).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let started = std::time::Instant::now();
+    let metrics = run_scanner(args, db.clone(), grant.round, rx, ports).await;
+    let _ = producer.await;
+    heartbeat.abort();
+    let metrics = metrics?;
+    if stop.load(Ordering::SeqCst) || lost.load(Ordering::SeqCst) {
+        return Ok(None);
+    }
+
+    let results = db
+        .get_all_results_by_round(grant.round)?
+        .into_iter()
+        .map(|r| LeaseResult {
+            ip: r.ip_address,
+            port: r.port,
+        })
+        .collect();
+    Ok(Some(LeaseReport {
+        worker_id: client.worker_id.clone(),
+        scanned: metrics.get_scanned(),
+        errors: metrics.get_errors(),
+        retries: metrics.get_retries(),
+        duration_secs: started.elapsed().as_secs_f64(),
+        results,
+    }))
+}
+
+async fn run_scanner(
+    args: &Args,
+    db: SqliteDB,
+    round: i64,
+    rx: tokio::sync::mpsc::Receiver<IpAddr>,
+    ports: Vec<u16>,
+) -> Result<ScanMetrics> {
+    let scanner = scanner_from_args(args, db, round, None, CancellationToken::new())?;
+    scanner.run_pipeline(rx, ports, Box::new(|_| {})).await?;
+    let metrics = scanner.get_metrics().clone();
+    scanner.finish().await;
+    Ok(metrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_coordinator_checks_reports_against_lease() {
+        let args = Args::try_parse_from([
+            "ip-scan",
+            "--coordinator",
+            "--start-ip",
+            "198.51.100.0",
+            "--end-ip",
+            "198.51.100.255",
+            "--ports",
+            "22,443",
+            "--lease-size",
+            "200",
+        ])
+        .unwrap();
+        let db = SqliteDB::new(":memory:").unwrap();
+        let coordinator = Coordinator::new(db.clone(), &args, None).unwrap();
+        assert!(coordinator.authorized(None));
+
+        let LeaseOutcome::Granted(first) = coordinator.lease("w1").unwrap() else {
+            panic!("expected a lease");
+        };
+        assert_eq!(
+            (first.start_ip.as_str(), first.end_ip.as_str()),
+            ("198.51.100.0", "198.51.100.199")
+        );
+        let LeaseOutcome::Granted(second) = coordinator.lease("w2").unwrap() else {
+            panic!("expected a lease");
+        };
+        assert_eq!(second.end_ip, "198.51.100.255");
+        assert!(matches!(
+            coordinator.lease("w3").unwrap(),
+            LeaseOutcome::Wait
+        ));
+
+        let report = |ip: &str, port| LeaseReport {
+            worker_id: "w1".to_string(),
+            scanned: 200,
+            errors: 0,
+            retries: 0,
+            duration_secs: 2.0,
+            results: vec![LeaseResult {
+                ip: ip.to_string(),
+                port,
+            }],
+        };
+        for (ip, port) in [("198.51.100.220", 22), ("198.51.100.7", 80)] {
+            assert!(matches!(
+                coordinator
+                    .complete(first.lease_id, report(ip, port))
+                    .unwrap(),
+                ReportOutcome::Invalid(_)
+            ));
+        }
+        let mut stolen = report("198.51.100.220", 22);
+        stolen.worker_id = "w3".to_string();
+        assert!(matches!(
+            coordinator.complete(second.lease_id, stolen).unwrap(),
+            ReportOutcome::NotHeld
+        ));
+        assert!(matches!(
+            coordinator
+                .complete(first.lease_id, report("198.51.100.7", 443))
+                .unwrap(),
+            ReportOutcome::Accepted(1)
+        ));
+        assert_eq!(db.get_results_by_ip("198.51.100.7").unwrap().len(), 1);
+        assert!(!coordinator.advance().unwrap());
+
+        let mut last = report("198.51.100.201", 22);
+        last.worker_id = "w2".to_string();
+        coordinator.complete(second.lease_id, last).unwrap();
+        // Without --loop the round closes and workers are told to exit.
+        assert!(!coordinator.advance().unwrap());
+        assert!(matches!(
+            coordinator.lease("w1").unwrap(),
+            LeaseOutcome::Finished
+        ));
+        let status = coordinator.status().unwrap();
+        assert!(status.finished);
+        assert_eq!((status.progress.done, status.progress.open), (2, 2));
+        assert_eq!(db.get_round_metrics(1).unwrap()[0].scanned, 400);
+    }
+}
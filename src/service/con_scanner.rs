@@ -1,136 +1,437 @@
-use super::RateLimiter;
+use super::batch_tuner::BatchTuner;
+use super::script_hooks::Finding;
+use super::{RateLimiter, ScriptHooks};
 use crate::dao::SqliteDB;
-use crate::model::ScanMetrics;
+use crate::model::{OpenPort, ScanMetrics, SourcePorts};
 use anyhow::Result;
-use std::net::{IpAddr, SocketAddr};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use tokio::net::TcpStream;
-use tokio::sync::mpsc;
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::sync::{broadcast, mpsc, Semaphore};
 use tokio::task::JoinSet;
 use tokio::time::timeout;
-use tracing::{debug, error, info};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+#[cfg(target_os = "linux")]
+use super::uring_connect::{ProbePermits, RingContext, UringConnector};
 
 const MAX_RETRIES: usize = 0;
 const RETRY_DELAY_MS: u64 = 50;
 
 const JOINSET_CAPACITY_FACTOR: usize = 4;
 
+/// Open-port notifications buffered per subscriber before it starts lagging.
+pub(crate) const EVENT_BUFFER: usize = 4096;
+
+/// Run the `--script` hook on a probe result and announce it if it is an open
+/// port the script kept. The DB writers call this rather than the probes, so
+/// a port the script drops is neither stored nor sent to the event stream or
+/// notifiers. Returns whether the result should be written.
+pub(crate) fn admit_result(
+    hooks: Option<&ScriptHooks>,
+    events: &broadcast::Sender<OpenPort>,
+    (ip, port, open): (IpAddr, u16, bool),
+    scan_round: i64,
+    findings: &mut Vec<Finding>,
+) -> bool {
+    if !open {
+        return true;
+    }
+    let keep = hooks.is_none_or(|h| h.on_open_port(&ip.to_string(), port, scan_round, findings));
+    if keep {
+        let _ = events.send(OpenPort {
+            ip,
+            port,
+            scan_round,
+        });
+    }
+    keep
+}
+
+/// Longest greeting kept from a connect-scan socket (`--grab-banner-ms`).
+const BANNER_MAX_BYTES: usize = 2048;
+
+/// Greetings read on connect-scan sockets, waiting for the DB writer.
+type BannerBuffer = Arc<Mutex<Vec<(String, u16, String)>>>;
+
 /// Lightweight state passed to each scan task. Sharing one Arc per task keeps
 /// the per-task clone cost down to a single Arc bump, which matters because
 /// the hot loop dispatches thousands of tasks per round.
 struct TaskContext {
     metrics: ScanMetrics,
     rate_limiter: RateLimiter,
-    result_tx: mpsc::Sender<(String, u16, bool)>,
+    result_tx: mpsc::Sender<(IpAddr, u16, bool)>,
+    cancel: CancellationToken,
+    source_ports: Option<SourcePorts>,
     scan_round: i64,
     timeout_ms: u64,
+    /// How long to wait for a greeting on each open port; `None` closes the
+    /// socket right after the handshake.
+    banner_grab: Option<Duration>,
+    banners: BannerBuffer,
+}
+
+/// Outcome of probing one port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PortState {
+    Open,
+    /// Refused, or the connect failed locally or in routing.
+    Closed,
+    /// No answer before the timeout.
+    Filtered,
+}
+
+/// One connect attempt. Completed handshakes and refusals both feed the
+/// connect-latency histogram; timeouts carry no latency information. Local
+/// or routing failures (unreachable, out of sockets, ...) count as errors.
+/// An open port comes back with its connected socket.
+async fn try_connect(
+    metrics: &ScanMetrics,
+    addr: &SocketAddr,
+    source_ports: Option<SourcePorts>,
+    dur: Duration,
+) -> (PortState, Option<TcpStream>) {
+    let started = Instant::now();
+    match timeout(dur, connect(addr, source_ports)).await {
+        Ok(Ok(stream)) => {
+            metrics.record_connect_latency(started.elapsed());
+            metrics.record_reply(false);
+            (PortState::Open, Some(stream))
+        }
+        Ok(Err(e)) => {
+            metrics.record_connect_latency(started.elapsed());
+            if e.kind() == std::io::ErrorKind::ConnectionRefused {
+                metrics.record_reply(true);
+            } else {
+                metrics.record_error(addr.ip(), addr.port());
+            }
+            (PortState::Closed, None)
+        }
+        Err(_) => (PortState::Filtered, None),
+    }
+}
+
+/// Wait up to `wait` for the service to speak first, without sending
+/// anything, and return what it sent.
+async fn read_banner(mut stream: TcpStream, wait: Duration) -> Option<String> {
+    let mut buf = vec![0u8; BANNER_MAX_BYTES];
+    match timeout(wait, stream.read(&mut buf)).await {
+        Ok(Ok(n)) if n > 0 => Some(String::from_utf8_lossy(&buf[..n]).into_owned()),
+        _ => None,
+    }
+}
+
+/// Connect from a random port in `source_ports` when set. Concurrent probes
+/// to different targets may pick the same port; SO_REUSEPORT lets them share
+/// it since their 4-tuples still differ.
+async fn connect(
+    addr: &SocketAddr,
+    source_ports: Option<SourcePorts>,
+) -> std::io::Result<TcpStream> {
+    let Some(ports) = source_ports else {
+        return TcpStream::connect(addr).await;
+    };
+    let (socket, any) = match addr {
+        SocketAddr::V4(_) => (TcpSocket::new_v4()?, IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        SocketAddr::V6(_) => (TcpSocket::new_v6()?, IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+    };
+    socket.set_reuseaddr(true)?;
+    #[cfg(unix)]
+    socket.set_reuseport(true)?;
+    socket.bind(SocketAddr::new(any, ports.pick()))?;
+    socket.connect(*addr).await
 }
 
 #[inline]
 async fn scan_port_with_retry(
-    rate_limiter: &RateLimiter,
-    timeout_ms: u64,
+    ctx: &TaskContext,
     ip: IpAddr,
     port: u16,
-) -> bool {
-    rate_limiter.acquire().await;
+) -> (PortState, Option<TcpStream>) {
+    ctx.rate_limiter.acquire().await;
 
     let addr = SocketAddr::new(ip, port);
-    let dur = Duration::from_millis(timeout_ms);
+    let dur = Duration::from_millis(ctx.timeout_ms);
 
-    if matches!(timeout(dur, TcpStream::connect(&addr)).await, Ok(Ok(_))) {
-        return true;
+    let mut result = try_connect(&ctx.metrics, &addr, ctx.source_ports, dur).await;
+    if result.0 == PortState::Open {
+        return result;
     }
 
     #[allow(clippy::reversed_empty_ranges)]
     for retry in 0..MAX_RETRIES {
-        rate_limiter.acquire().await;
+        ctx.rate_limiter.acquire().await;
         tokio::time::sleep(Duration::from_millis(RETRY_DELAY_MS)).await;
-        if matches!(timeout(dur, TcpStream::connect(&addr)).await, Ok(Ok(_))) {
+        ctx.metrics.increment_retries();
+        result = try_connect(&ctx.metrics, &addr, ctx.source_ports, dur).await;
+        if result.0 == PortState::Open {
             debug!(ip = %ip, port = port, retry = retry + 1, "Retry success");
-            return true;
+            return result;
         }
     }
 
-    false
+    result
+}
+
+/// Probe one port while holding a global permit and report the result.
+/// Returns early without a result when the scan is cancelled. With banner
+/// grabbing on, an open port's socket is read after the permit is released,
+/// so only the host's own slot waits on a silent service.
+async fn probe_port(
+    ctx: &TaskContext,
+    semaphore: &Semaphore,
+    ip: IpAddr,
+    ip_type: &'static str,
+    port: u16,
+) {
+    let probe = async {
+        let _permit = semaphore.acquire().await.unwrap();
+        ctx.metrics.record_scanned(ip, port);
+        scan_port_with_retry(ctx, ip, port).await
+    };
+    let (state, stream) = tokio::select! {
+        biased;
+        _ = ctx.cancel.cancelled() => return,
+        result = probe => result,
+    };
+    let is_open = state == PortState::Open;
+
+    if is_open {
+        ctx.metrics.record_open(ip, port);
+        info!(
+            ip = %ip, port,
+            ip_type = %ip_type,
+            round = ctx.scan_round,
+            "Found open port"
+        );
+        if let (Some(wait), Some(stream)) = (ctx.banner_grab, stream) {
+            let banner = tokio::select! {
+                biased;
+                _ = ctx.cancel.cancelled() => None,
+                banner = read_banner(stream, wait) => banner,
+            };
+            if let Some(banner) = banner {
+                ctx.banners
+                    .lock()
+                    .unwrap()
+                    .push((ip.to_string(), port, banner));
+            }
+        }
+    }
+
+    if let Err(e) = ctx.result_tx.send((ip, port, is_open)).await {
+        error!("Result channel send error: {}", e);
+    }
 }
 
 pub struct ConScanner {
     db: SqliteDB,
     timeout_ms: u64,
     concurrent_limit: usize,
+    host_limit: usize,
     scan_round: i64,
     scanned_count: Arc<AtomicUsize>,
     metrics: ScanMetrics,
     rate_limiter: RateLimiter,
-    result_tx: mpsc::Sender<(String, u16, bool)>,
+    result_tx: mpsc::Sender<(IpAddr, u16, bool)>,
+    events: broadcast::Sender<OpenPort>,
+    cancel: CancellationToken,
+    source_ports: Option<SourcePorts>,
+    banner_grab: Option<Duration>,
+    banners: BannerBuffer,
+    writer: tokio::task::JoinHandle<()>,
+    #[cfg(target_os = "linux")]
+    uring: Option<UringConnector>,
 }
 
 #[derive(Clone)]
 pub struct ConScannerConfig {
     pub timeout_ms: u64,
     pub concurrent_limit: usize,
+    /// Ports probed at once on a single host (`--host-concurrency`), within
+    /// the global `concurrent_limit`.
+    pub host_concurrent_limit: usize,
     pub result_buffer: usize,
     pub db_batch_size: usize,
     pub flush_interval_ms: u64,
+    /// Let the DB writer resize batches and its flush interval from queue
+    /// occupancy and write latency (`--adaptive-batching`).
+    pub adaptive_batching: bool,
     pub max_rate: u64,
     pub rate_window_secs: u64,
+    /// Token bucket size; 0 picks 10 ms worth of `max_rate`.
+    pub rate_burst: usize,
+    /// `--script` hooks, run on each open port before it is written.
+    pub hooks: Option<Arc<ScriptHooks>>,
+    /// Cancelling stops dispatch and in-flight probes; results already
+    /// received are still written.
+    pub cancel: CancellationToken,
+    /// Issue connects through io_uring instead of one tokio task per port
+    /// (Linux only; falls back to tokio when the ring cannot be created).
+    pub io_uring: bool,
+    /// Bind probes to ports in this range (`--source-port-range`); `None`
+    /// leaves the choice to the OS.
+    pub source_ports: Option<SourcePorts>,
+    /// Keep each open port's socket this long to read a greeting for
+    /// service probing (`--grab-banner-ms`); 0 closes it right away. Not
+    /// supported by the io_uring backend.
+    pub banner_grab_ms: u64,
 }
 
 impl ConScanner {
     pub fn new(db: SqliteDB, scan_round: i64, config: ConScannerConfig) -> Self {
-        let rate_limiter = RateLimiter::new(
+        let rate_limiter = RateLimiter::with_burst(
             config.max_rate as usize,
             Duration::from_secs(config.rate_window_secs),
+            config.rate_burst,
         );
 
         let (tx, rx) = mpsc::channel(config.result_buffer);
+        let metrics = ScanMetrics::new();
 
         let db_clone = db.clone();
-        tokio::spawn(async move {
+        let writer_cancel = config.cancel.clone();
+        let tuner = BatchTuner::new(
+            config.db_batch_size,
+            Duration::from_millis(config.flush_interval_ms),
+            config.adaptive_batching,
+        );
+        let writer_metrics = metrics.clone();
+        let banners = BannerBuffer::default();
+        let writer_banners = banners.clone();
+        let events = broadcast::channel(EVENT_BUFFER).0;
+        let writer_events = events.clone();
+        let writer = tokio::spawn(async move {
             Self::run_db_writer(
                 rx,
                 db_clone,
                 scan_round,
-                config.db_batch_size,
-                config.flush_interval_ms,
+                tuner,
+                config.hooks,
+                writer_events,
+                writer_banners,
+                writer_metrics,
+                writer_cancel,
             )
             .await;
         });
 
+        #[cfg(target_os = "linux")]
+        let uring = if config.io_uring {
+            let ctx = RingContext {
+                metrics: metrics.clone(),
+                result_tx: tx.clone(),
+                source_ports: config.source_ports,
+                scan_round,
+            };
+            match UringConnector::new(ctx, Duration::from_millis(config.timeout_ms)) {
+                Ok(connector) => {
+                    if config.banner_grab_ms > 0 {
+                        warn!("Banner grabbing is not supported by the io_uring backend");
+                    }
+                    Some(connector)
+                }
+                Err(e) => {
+                    warn!("io_uring unavailable, using tokio connect backend: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        #[cfg(not(target_os = "linux"))]
+        if config.io_uring {
+            warn!("io_uring backend is only available on Linux, using tokio");
+        }
+
         ConScanner {
             db,
             timeout_ms: config.timeout_ms,
             concurrent_limit: config.concurrent_limit,
+            host_limit: config.host_concurrent_limit.max(1),
             scan_round,
             scanned_count: Arc::new(AtomicUsize::new(0)),
-            metrics: ScanMetrics::new(),
+            metrics,
             rate_limiter,
             result_tx: tx,
+            events,
+            cancel: config.cancel,
+            source_ports: config.source_ports,
+            banner_grab: (config.banner_grab_ms > 0)
+                .then(|| Duration::from_millis(config.banner_grab_ms)),
+            banners,
+            writer,
+            #[cfg(target_os = "linux")]
+            uring,
+        }
+    }
+
+    /// Receive every open port found from now on. A subscriber that falls
+    /// more than `EVENT_BUFFER` notifications behind misses the oldest ones;
+    /// the database still receives all results.
+    pub fn subscribe(&self) -> broadcast::Receiver<OpenPort> {
+        self.events.subscribe()
+    }
+
+    /// Close the result channel and wait until the DB writer has flushed its
+    /// final batch. Call after `run_pipeline` returns so no result is lost
+    /// when the process exits right afterwards.
+    pub async fn finish(self) {
+        let ConScanner {
+            result_tx,
+            writer,
+            #[cfg(target_os = "linux")]
+            uring,
+            ..
+        } = self;
+        // The ring thread holds a result sender until it is joined.
+        #[cfg(target_os = "linux")]
+        if let Some(uring) = uring {
+            if let Err(e) = tokio::task::spawn_blocking(move || drop(uring)).await {
+                error!("Failed to stop io_uring connect thread: {}", e);
+            }
+        }
+        drop(result_tx);
+        if let Err(e) = writer.await {
+            error!("DB writer task failed: {}", e);
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn run_db_writer(
-        mut rx: mpsc::Receiver<(String, u16, bool)>,
+        mut rx: mpsc::Receiver<(IpAddr, u16, bool)>,
         db: SqliteDB,
         round: i64,
-        batch_size: usize,
-        flush_interval_ms: u64,
+        mut tuner: BatchTuner,
+        hooks: Option<Arc<ScriptHooks>>,
+        events: broadcast::Sender<OpenPort>,
+        banners: BannerBuffer,
+        metrics: ScanMetrics,
+        cancel: CancellationToken,
     ) {
-        let mut buffer = Vec::with_capacity(batch_size);
+        let mut buffer = Vec::with_capacity(tuner.batch_size());
+        let mut findings = Vec::new();
         let mut last_flush = Instant::now();
-        let flush_interval = Duration::from_millis(flush_interval_ms);
 
         loop {
             let result = timeout(Duration::from_millis(100), rx.recv()).await;
+            metrics.set_result_queue(rx.len(), rx.max_capacity());
 
             match result {
                 Ok(Some(item)) => {
-                    buffer.push(item);
-                    if buffer.len() >= batch_size {
-                        Self::flush_buffer(&db, &mut buffer, round);
+                    if admit_result(hooks.as_deref(), &events, item, round, &mut findings) {
+                        buffer.push(item);
+                    }
+                    if buffer.len() >= tuner.batch_size() {
+                        let started = Instant::now();
+                        Self::flush_buffer(&db, &mut buffer, &mut findings, &banners, round);
+                        tuner.flushed(started, &rx, &metrics);
                         last_flush = Instant::now();
                     }
                 }
@@ -138,22 +439,43 @@ impl ConScanner {
                 Err(_) => {}
             }
 
-            if !buffer.is_empty() && last_flush.elapsed() >= flush_interval {
-                Self::flush_buffer(&db, &mut buffer, round);
+            // Once the scan is cancelled nothing is held back for batching.
+            let due = last_flush.elapsed() >= tuner.flush_interval() || cancel.is_cancelled();
+            if !buffer.is_empty() && due {
+                let started = Instant::now();
+                Self::flush_buffer(&db, &mut buffer, &mut findings, &banners, round);
+                tuner.flushed(started, &rx, &metrics);
                 last_flush = Instant::now();
             }
         }
 
-        if !buffer.is_empty() {
-            Self::flush_buffer(&db, &mut buffer, round);
+        if !buffer.is_empty() || !findings.is_empty() {
+            Self::flush_buffer(&db, &mut buffer, &mut findings, &banners, round);
         }
     }
 
     #[inline]
-    fn flush_buffer(db: &SqliteDB, buffer: &mut Vec<(String, u16, bool)>, round: i64) {
+    fn flush_buffer(
+        db: &SqliteDB,
+        buffer: &mut Vec<(IpAddr, u16, bool)>,
+        findings: &mut Vec<Finding>,
+        banners: &BannerBuffer,
+        round: i64,
+    ) {
+        let banners = std::mem::take(&mut *banners.lock().unwrap());
+        if !banners.is_empty() {
+            if let Err(e) = db.save_port_banners(&banners) {
+                error!("Failed to save port banners: {}", e);
+            }
+        }
         if let Err(e) = db.bulk_update_port_status(std::mem::take(buffer), round) {
             error!("Failed to bulk update port status: {}", e);
         }
+        if !findings.is_empty() {
+            if let Err(e) = db.save_script_findings(&std::mem::take(findings), round) {
+                error!("Failed to save script findings: {}", e);
+            }
+        }
     }
 
     fn get_ip_type(ip: &IpAddr) -> &'static str {
@@ -169,8 +491,19 @@ impl ConScanner {
         ports: Vec<u16>,
         progress_callback: impl Fn(usize) + Send + Sync + 'static,
     ) -> Result<()> {
+        #[cfg(target_os = "linux")]
+        if let Some(uring) = &self.uring {
+            return self
+                .run_pipeline_uring(uring, rx, ports, progress_callback)
+                .await;
+        }
+
         let semaphore = Arc::new(tokio::sync::Semaphore::new(self.concurrent_limit));
-        let max_inflight = self.concurrent_limit * JOINSET_CAPACITY_FACTOR;
+        // Enough hosts in flight to keep every global permit busy even when
+        // each host is held to `host_limit` ports at a time.
+        let max_hosts = self.concurrent_limit.div_ceil(self.host_limit) * JOINSET_CAPACITY_FACTOR;
+        let host_limit = self.host_limit;
+        let ports: Arc<[u16]> = ports.into();
         let progress_callback = Arc::new(progress_callback);
         // Share lightweight references across all in-flight scan tasks so each
         // task clone is a single Arc bump instead of cloning 6+ Arcs and two
@@ -180,18 +513,29 @@ impl ConScanner {
             metrics: self.metrics.clone(),
             rate_limiter: self.rate_limiter.clone(),
             result_tx: self.result_tx.clone(),
+            cancel: self.cancel.clone(),
+            source_ports: self.source_ports,
             scan_round: self.scan_round,
             timeout_ms: self.timeout_ms,
+            banner_grab: self.banner_grab,
+            banners: self.banners.clone(),
         });
         let mut join_set: JoinSet<()> = JoinSet::new();
         let mut total_dispatched: usize = 0;
+        let mut last_dispatched: Option<IpAddr> = None;
 
         loop {
-            let inflight = join_set.len();
-
-            if inflight >= max_inflight {
-                if let Some(Err(e)) = join_set.join_next().await {
-                    error!("Task error: {}", e);
+            // One task per host; its ports run `host_limit` at a time, each
+            // also holding a global permit while it connects.
+            if join_set.len() >= max_hosts {
+                tokio::select! {
+                    biased;
+                    _ = self.cancel.cancelled() => break,
+                    Some(res) = join_set.join_next() => {
+                        if let Err(e) = res {
+                            error!("Task error: {}", e);
+                        }
+                    }
                 }
                 continue;
             }
@@ -199,6 +543,8 @@ impl ConScanner {
             tokio::select! {
                 biased;
 
+                _ = self.cancel.cancelled() => break,
+
                 Some(res) = join_set.join_next(), if !join_set.is_empty() => {
                     if let Err(e) = res {
                         error!("Task error: {}", e);
@@ -206,61 +552,28 @@ impl ConScanner {
                 }
 
                 ip = rx.recv() => {
+                    self.metrics.set_pipeline_queue(rx.len(), rx.max_capacity());
                     match ip {
                         Some(ip) => {
-                            let ip_str = ip.to_string();
                             let ip_type = Self::get_ip_type(&ip);
+                            let ctx = task_ctx.clone();
+                            let sem = semaphore.clone();
+                            let host_ports = ports.clone();
 
-                            for &port in &ports {
-                                // Bound tasks while dispatching a large port range (e.g. 1-65535).
-                                // Without this backpressure, one IP could allocate tens of
-                                // thousands of tasks before the outer loop gets a chance to reap.
-                                while join_set.len() >= max_inflight {
-                                    if let Some(Err(e)) = join_set.join_next().await {
-                                        error!("Task error: {}", e);
-                                    }
-                                }
-                                let ctx = task_ctx.clone();
-                                let ip_str_c = ip_str.clone();
-                                let sem = semaphore.clone();
-
-                                join_set.spawn(async move {
-                                    let _permit = sem.acquire().await.unwrap();
-
-                                    ctx.metrics.increment_scanned();
-
-                                    let is_open = scan_port_with_retry(
-                                        &ctx.rate_limiter,
-                                        ctx.timeout_ms,
-                                        ip,
-                                        port,
-                                    ).await;
-
-                                    if is_open {
-                                        ctx.metrics.increment_open();
-                                        info!(
-                                            ip = %ip_str_c, port,
-                                            ip_type = %ip_type,
-                                            round = ctx.scan_round,
-                                            "Found open port"
-                                        );
-                                    }
-
-                                    if let Err(e) = ctx.result_tx.send((ip_str_c, port, is_open)).await {
-                                        error!("Result channel send error: {}", e);
-                                    }
-                                });
-                            }
+                            join_set.spawn(async move {
+                                futures::stream::iter(host_ports.iter().copied())
+                                    .take_until(ctx.cancel.cancelled())
+                                    .for_each_concurrent(host_limit, |port| {
+                                        probe_port(&ctx, &sem, ip, ip_type, port)
+                                    })
+                                    .await;
+                            });
 
                             total_dispatched += 1;
                             progress_callback(total_dispatched);
 
-                            let count = self.scanned_count.fetch_add(1, Ordering::Relaxed) + 1;
-                            if count.is_multiple_of(200) {
-                                if let Err(e) = self.db.save_progress(&ip_str, ip_type, self.scan_round) {
-                                    error!("Progress save error: {}", e);
-                                }
-                            }
+                            self.checkpoint(ip);
+                            last_dispatched = Some(ip);
                         }
                         None => {
                             break;
@@ -276,9 +589,117 @@ impl ConScanner {
             }
         }
 
+        // Every dispatched IP has been probed at this point, so the last one is
+        // a safe resume position even if the producer stopped early. A
+        // cancelled run dropped some of those probes; keep the last periodic
+        // checkpoint instead.
+        if let Some(ip) = last_dispatched.filter(|_| !self.cancel.is_cancelled()) {
+            self.save_progress(ip);
+        }
+
+        Ok(())
+    }
+
+    /// `run_pipeline` on the io_uring backend. Probes go straight to the ring
+    /// thread, which reports results itself; the semaphores cap sockets in
+    /// flight at `concurrent_limit` overall and `host_limit` per host without
+    /// a task per port. Hosts are dispatched from futures polled here.
+    #[cfg(target_os = "linux")]
+    async fn run_pipeline_uring(
+        &self,
+        uring: &UringConnector,
+        mut rx: mpsc::Receiver<IpAddr>,
+        ports: Vec<u16>,
+        progress_callback: impl Fn(usize) + Send + Sync + 'static,
+    ) -> Result<()> {
+        let semaphore = Arc::new(Semaphore::new(self.concurrent_limit));
+        let max_hosts = self.concurrent_limit.div_ceil(self.host_limit) * JOINSET_CAPACITY_FACTOR;
+        let mut hosts = futures::stream::FuturesUnordered::new();
+        let mut producer_done = false;
+        let mut total_dispatched: usize = 0;
+        let mut last_dispatched: Option<IpAddr> = None;
+
+        loop {
+            if producer_done && hosts.is_empty() {
+                break;
+            }
+            tokio::select! {
+                biased;
+                _ = self.cancel.cancelled() => break,
+                Some(()) = hosts.next(), if !hosts.is_empty() => {}
+                ip = rx.recv(), if !producer_done && hosts.len() < max_hosts => {
+                    self.metrics.set_pipeline_queue(rx.len(), rx.max_capacity());
+                    let Some(ip) = ip else {
+                        producer_done = true;
+                        continue;
+                    };
+                    hosts.push(self.dispatch_host_uring(uring, &semaphore, ip, &ports));
+
+                    total_dispatched += 1;
+                    progress_callback(total_dispatched);
+                    self.checkpoint(ip);
+                    last_dispatched = Some(ip);
+                }
+            }
+        }
+        drop(hosts);
+
+        if self.cancel.is_cancelled() {
+            uring.cancel();
+        }
+        // Every permit returned means every probe has reported its result.
+        let _ = semaphore.acquire_many(self.concurrent_limit as u32).await?;
+
+        if let Some(ip) = last_dispatched.filter(|_| !self.cancel.is_cancelled()) {
+            self.save_progress(ip);
+        }
+
         Ok(())
     }
 
+    /// Hand every port of `ip` to the ring, at most `host_limit` at a time.
+    #[cfg(target_os = "linux")]
+    async fn dispatch_host_uring(
+        &self,
+        uring: &UringConnector,
+        semaphore: &Arc<Semaphore>,
+        ip: IpAddr,
+        ports: &[u16],
+    ) {
+        let host = Arc::new(Semaphore::new(self.host_limit));
+        for &port in ports {
+            let Ok(host) = host.clone().acquire_owned().await else {
+                return;
+            };
+            let Ok(global) = semaphore.clone().acquire_owned().await else {
+                return;
+            };
+            if !self.rate_limiter.acquire_or_cancel(&self.cancel).await {
+                return;
+            }
+            self.metrics.record_scanned(ip, port);
+            uring.probe(ip, port, ProbePermits { global, host });
+        }
+    }
+
+    /// Save a resume position every 200 dispatched IPs.
+    fn checkpoint(&self, ip: IpAddr) {
+        let count = self.scanned_count.fetch_add(1, Ordering::Relaxed) + 1;
+        if count.is_multiple_of(200) {
+            self.save_progress(ip);
+        }
+    }
+
+    fn save_progress(&self, ip: IpAddr) {
+        let ip_type = Self::get_ip_type(&ip);
+        if let Err(e) = self
+            .db
+            .save_progress(&ip.to_string(), ip_type, self.scan_round)
+        {
+            error!("Progress save error: {}", e);
+        }
+    }
+
     #[allow(dead_code)]
     pub async fn scan_port(&self, ip: IpAddr, port: u16) -> bool {
         self.rate_limiter.acquire().await;
@@ -289,57 +710,79 @@ impl ConScanner {
 
     #[allow(dead_code)]
     pub async fn scan_ip_ports(&self, ip: IpAddr, ports: Vec<u16>) -> Result<Vec<u16>> {
-        let mut open_ports = Vec::with_capacity(ports.len() / 10);
-        let semaphore = Arc::new(tokio::sync::Semaphore::new(self.concurrent_limit));
-        let ip_str = ip.to_string();
+        let states = self.scan_ip_ports_classified(ip, &ports).await?;
+        Ok(states
+            .into_iter()
+            .filter(|(_, state)| *state == PortState::Open)
+            .map(|(port, _)| port)
+            .collect())
+    }
+
+    /// Probe `ports` on one host, `host_concurrent_limit` at a time, and
+    /// return every port's state sorted by port. Results are written like
+    /// pipeline results.
+    pub async fn scan_ip_ports_classified(
+        &self,
+        ip: IpAddr,
+        ports: &[u16],
+    ) -> Result<Vec<(u16, PortState)>> {
+        let mut states = Vec::with_capacity(ports.len());
+        let semaphore = Arc::new(Semaphore::new(self.concurrent_limit.min(self.host_limit)));
         let ip_type = Self::get_ip_type(&ip);
         let task_ctx = Arc::new(TaskContext {
             metrics: self.metrics.clone(),
             rate_limiter: self.rate_limiter.clone(),
             result_tx: self.result_tx.clone(),
+            cancel: self.cancel.clone(),
+            source_ports: self.source_ports,
             scan_round: self.scan_round,
             timeout_ms: self.timeout_ms,
+            banner_grab: self.banner_grab,
+            banners: self.banners.clone(),
         });
         let mut join_set = JoinSet::new();
 
-        for port in ports {
+        for &port in ports {
             let ctx = task_ctx.clone();
             let sem = semaphore.clone();
             join_set.spawn(async move {
                 let _permit = sem.acquire().await.unwrap();
-                ctx.metrics.increment_scanned();
-                let is_open =
-                    scan_port_with_retry(&ctx.rate_limiter, ctx.timeout_ms, ip, port).await;
-                (port, is_open)
+                ctx.metrics.record_scanned(ip, port);
+                let (state, _) = scan_port_with_retry(&ctx, ip, port).await;
+                (port, state)
             });
         }
 
         while let Some(res) = join_set.join_next().await {
-            if let Ok((port, is_open)) = res {
-                if let Err(e) = self.result_tx.send((ip_str.clone(), port, is_open)).await {
+            if let Ok((port, state)) = res {
+                let is_open = state == PortState::Open;
+                if let Err(e) = self.result_tx.send((ip, port, is_open)).await {
                     error!("Result channel error: {}", e);
                 }
                 if is_open {
-                    open_ports.push(port);
-                    self.metrics.increment_open();
+                    self.metrics.record_open(ip, port);
                     info!(ip = %ip, port, ip_type = %ip_type, round = self.scan_round, "Found open port");
                 }
+                states.push((port, state));
             }
         }
 
         let count = self.scanned_count.fetch_add(1, Ordering::Relaxed) + 1;
         if count.is_multiple_of(200) {
-            if let Err(e) = self.db.save_progress(&ip_str, ip_type, self.scan_round) {
-                error!("Progress save error: {}", e);
-            }
+            self.save_progress(ip);
         }
 
-        Ok(open_ports)
+        states.sort_by_key(|(port, _)| *port);
+        Ok(states)
     }
 
     pub fn get_metrics(&self) -> &ScanMetrics {
         &self.metrics
     }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
 }
 
 #[cfg(test)]
@@ -356,11 +799,19 @@ mod tests {
         let config = ConScannerConfig {
             timeout_ms: 500,
             concurrent_limit: 10,
+            host_concurrent_limit: 4,
             result_buffer: 100,
             db_batch_size: 100,
             flush_interval_ms: 1000,
+            adaptive_batching: false,
             max_rate: 10000,
             rate_window_secs: 1,
+            rate_burst: 0,
+            hooks: None,
+            cancel: CancellationToken::new(),
+            io_uring: false,
+            source_ports: None,
+            banner_grab_ms: 0,
         };
         let scanner = ConScanner::new(db, 1, config);
         let ip: IpAddr = "127.0.0.1".parse().unwrap();
@@ -377,11 +828,19 @@ mod tests {
         let config = ConScannerConfig {
             timeout_ms: 200,
             concurrent_limit: 10,
+            host_concurrent_limit: 4,
             result_buffer: 100,
             db_batch_size: 100,
             flush_interval_ms: 1000,
+            adaptive_batching: false,
             max_rate: 10000,
             rate_window_secs: 1,
+            rate_burst: 0,
+            hooks: None,
+            cancel: CancellationToken::new(),
+            io_uring: false,
+            source_ports: None,
+            banner_grab_ms: 0,
         };
         let scanner = ConScanner::new(db, 1, config);
         let ip: IpAddr = "127.0.0.1".parse().unwrap();
@@ -402,11 +861,19 @@ mod tests {
         let config = ConScannerConfig {
             timeout_ms: 200,
             concurrent_limit: 10,
+            host_concurrent_limit: 4,
             result_buffer: 100,
             db_batch_size: 100,
             flush_interval_ms: 1000,
+            adaptive_batching: false,
             max_rate: 10000,
             rate_window_secs: 1,
+            rate_burst: 0,
+            hooks: None,
+            cancel: CancellationToken::new(),
+            io_uring: false,
+            source_ports: None,
+            banner_grab_ms: 0,
         };
         let scanner = ConScanner::new(db.clone(), 1, config);
         let ip: IpAddr = "127.0.0.1".parse().unwrap();
@@ -418,4 +885,369 @@ mod tests {
         assert_eq!(open_ports.len(), 1);
         assert_eq!(open_ports[0], port);
     }
+
+    #[tokio::test]
+    async fn test_finish_flushes_pending_results_and_progress() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        let config = ConScannerConfig {
+            timeout_ms: 200,
+            concurrent_limit: 10,
+            host_concurrent_limit: 4,
+            result_buffer: 100,
+            // Large batch and interval: only the final flush can persist the result.
+            db_batch_size: 1000,
+            flush_interval_ms: 60_000,
+            adaptive_batching: false,
+            max_rate: 10000,
+            rate_window_secs: 1,
+            rate_burst: 0,
+            hooks: None,
+            cancel: CancellationToken::new(),
+            io_uring: false,
+            source_ports: None,
+            banner_grab_ms: 0,
+        };
+        let scanner = ConScanner::new(db.clone(), 1, config);
+        let (tx, rx) = mpsc::channel(4);
+        tx.send("127.0.0.1".parse().unwrap()).await.unwrap();
+        drop(tx);
+
+        scanner.run_pipeline(rx, vec![port], |_| {}).await.unwrap();
+        scanner.finish().await;
+
+        assert_eq!(db.get_total_open_ports_count().unwrap(), 1);
+        let (ip, ip_type, round) = db.get_progress().unwrap().unwrap();
+        assert_eq!(
+            (ip.as_str(), ip_type.as_str(), round),
+            ("127.0.0.1", "IPv4", 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_stores_ipv6_targets() {
+        // Hosts without IPv6 loopback cannot run this test.
+        let Ok(listener) = tokio::net::TcpListener::bind("[::1]:0").await else {
+            return;
+        };
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        let config = ConScannerConfig {
+            timeout_ms: 200,
+            concurrent_limit: 10,
+            host_concurrent_limit: 4,
+            result_buffer: 100,
+            db_batch_size: 1000,
+            flush_interval_ms: 60_000,
+            adaptive_batching: false,
+            max_rate: 10000,
+            rate_window_secs: 1,
+            rate_burst: 0,
+            hooks: None,
+            cancel: CancellationToken::new(),
+            io_uring: false,
+            source_ports: Some(SourcePorts::parse("40000-40999").unwrap()),
+            banner_grab_ms: 0,
+        };
+        let scanner = ConScanner::new(db.clone(), 1, config);
+        let (tx, rx) = mpsc::channel(4);
+        tx.send("::1".parse().unwrap()).await.unwrap();
+        drop(tx);
+
+        scanner.run_pipeline(rx, vec![port], |_| {}).await.unwrap();
+        scanner.finish().await;
+
+        let results = db.get_results_by_ip("::1").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            (results[0].port, results[0].ip_type.as_str()),
+            (port, "IPv6")
+        );
+        let (_, ip_type, _) = db.get_progress().unwrap().unwrap();
+        assert_eq!(ip_type, "IPv6");
+    }
+
+    #[tokio::test]
+    async fn test_banner_grab_reads_greeting_on_connect_socket() {
+        use tokio::io::AsyncWriteExt;
+
+        let greeter = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let greeting_port = greeter.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = greeter.accept().await {
+                let _ = stream.write_all(b"SSH-2.0-test\r\n").await;
+            }
+        });
+        // Holds connections open without saying anything.
+        let silent = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let silent_port = silent.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = silent.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        let config = ConScannerConfig {
+            timeout_ms: 200,
+            concurrent_limit: 10,
+            host_concurrent_limit: 4,
+            result_buffer: 100,
+            db_batch_size: 1000,
+            flush_interval_ms: 60_000,
+            adaptive_batching: false,
+            max_rate: 10000,
+            rate_window_secs: 1,
+            rate_burst: 0,
+            hooks: None,
+            cancel: CancellationToken::new(),
+            io_uring: false,
+            source_ports: None,
+            banner_grab_ms: 300,
+        };
+        let scanner = ConScanner::new(db.clone(), 1, config);
+        let (tx, rx) = mpsc::channel(4);
+        tx.send("127.0.0.1".parse().unwrap()).await.unwrap();
+        drop(tx);
+
+        scanner
+            .run_pipeline(rx, vec![greeting_port, silent_port], |_| {})
+            .await
+            .unwrap();
+        scanner.finish().await;
+
+        assert_eq!(db.get_total_open_ports_count().unwrap(), 2);
+        let banners = db.get_port_banners("127.0.0.1").unwrap();
+        assert_eq!(banners.len(), 1);
+        assert_eq!(banners[&greeting_port], "SSH-2.0-test\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_connect_uses_source_port_range() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let ports = SourcePorts::parse("47000-47099").unwrap();
+
+        let accept = tokio::spawn(async move { listener.accept().await.unwrap().1 });
+        let stream = connect(&addr, Some(ports)).await.unwrap();
+        let peer = accept.await.unwrap();
+        assert!(ports.contains(peer.port()), "{}", peer);
+        assert_eq!(stream.local_addr().unwrap().port(), peer.port());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_uring_backend_pipeline_matches_tokio() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+        let closed_port = {
+            let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            l.local_addr().unwrap().port()
+        };
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        let config = ConScannerConfig {
+            timeout_ms: 200,
+            concurrent_limit: 2,
+            host_concurrent_limit: 4,
+            result_buffer: 100,
+            db_batch_size: 1000,
+            flush_interval_ms: 60_000,
+            adaptive_batching: false,
+            max_rate: 10000,
+            rate_window_secs: 1,
+            rate_burst: 0,
+            hooks: None,
+            cancel: CancellationToken::new(),
+            // Falls back to tokio where io_uring is unavailable.
+            io_uring: true,
+            source_ports: None,
+            banner_grab_ms: 0,
+        };
+        let scanner = ConScanner::new(db.clone(), 1, config);
+        let (tx, rx) = mpsc::channel(4);
+        tx.send("127.0.0.1".parse().unwrap()).await.unwrap();
+        drop(tx);
+
+        scanner
+            .run_pipeline(rx, vec![port, closed_port], |_| {})
+            .await
+            .unwrap();
+        let metrics = scanner.get_metrics().clone();
+        scanner.finish().await;
+
+        assert_eq!(metrics.get_scanned(), 2);
+        assert_eq!(db.get_total_open_ports_count().unwrap(), 1);
+        assert!(db.get_progress().unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_host_limit_of_one_still_probes_every_port() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+        let closed_port = {
+            let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            l.local_addr().unwrap().port()
+        };
+
+        for io_uring in [false, true] {
+            let db = SqliteDB::new(":memory:").unwrap();
+            let config = ConScannerConfig {
+                timeout_ms: 200,
+                concurrent_limit: 3,
+                host_concurrent_limit: 1,
+                result_buffer: 100,
+                db_batch_size: 1000,
+                flush_interval_ms: 60_000,
+                adaptive_batching: false,
+                max_rate: 10000,
+                rate_window_secs: 1,
+                rate_burst: 0,
+                hooks: None,
+                cancel: CancellationToken::new(),
+                io_uring,
+                source_ports: None,
+                banner_grab_ms: 0,
+            };
+            let scanner = ConScanner::new(db.clone(), 1, config);
+            let (tx, rx) = mpsc::channel(4);
+            for ip in ["127.0.0.1", "127.0.0.2", "127.0.0.3"] {
+                tx.send(ip.parse().unwrap()).await.unwrap();
+            }
+            drop(tx);
+
+            scanner
+                .run_pipeline(rx, vec![port, closed_port], |_| {})
+                .await
+                .unwrap();
+            let metrics = scanner.get_metrics().clone();
+            scanner.finish().await;
+
+            assert_eq!(metrics.get_scanned(), 6, "io_uring={}", io_uring);
+            assert_eq!(db.get_total_open_ports_count().unwrap(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stops_pipeline_and_keeps_found_ports() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        let cancel = CancellationToken::new();
+        let config = ConScannerConfig {
+            timeout_ms: 200,
+            concurrent_limit: 10,
+            host_concurrent_limit: 4,
+            result_buffer: 100,
+            db_batch_size: 1000,
+            flush_interval_ms: 60_000,
+            adaptive_batching: false,
+            // One probe every 100 ms: the remaining ports would take seconds.
+            max_rate: 10,
+            rate_window_secs: 1,
+            rate_burst: 1,
+            hooks: None,
+            cancel: cancel.clone(),
+            io_uring: false,
+            source_ports: None,
+            banner_grab_ms: 0,
+        };
+        let scanner = ConScanner::new(db.clone(), 1, config);
+        // The sender stays open, so only cancellation ends the pipeline.
+        let (tx, rx) = mpsc::channel(4);
+        tx.send("127.0.0.1".parse().unwrap()).await.unwrap();
+        let mut ports = vec![port];
+        ports.extend(1..50);
+
+        let stopper = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            stopper.cancel();
+        });
+        let start = Instant::now();
+        scanner.run_pipeline(rx, ports, |_| {}).await.unwrap();
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "{:?}",
+            start.elapsed()
+        );
+        scanner.finish().await;
+        drop(tx);
+
+        assert_eq!(db.get_total_open_ports_count().unwrap(), 1);
+        // Only part of the IP was probed, so it is not a resume position.
+        assert!(db.get_progress().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_script_hooks_drop_and_tag_before_persisting() {
+        let mut ports = Vec::new();
+        for _ in 0..2 {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            ports.push(listener.local_addr().unwrap().port());
+            tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+        }
+        let script = format!(
+            "fn on_open_port(event) {{ if event.port == {} {{ return false; }} #{{ tags: [\"kept\"] }} }}",
+            ports[1]
+        );
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        let config = ConScannerConfig {
+            timeout_ms: 200,
+            concurrent_limit: 10,
+            host_concurrent_limit: 4,
+            result_buffer: 100,
+            db_batch_size: 100,
+            flush_interval_ms: 60_000,
+            adaptive_batching: false,
+            max_rate: 10000,
+            rate_window_secs: 1,
+            rate_burst: 0,
+            hooks: Some(Arc::new(ScriptHooks::compile(&script).unwrap())),
+            cancel: CancellationToken::new(),
+            io_uring: false,
+            source_ports: None,
+            banner_grab_ms: 0,
+        };
+        let scanner = ConScanner::new(db.clone(), 1, config);
+        let mut events = scanner.subscribe();
+        let (tx, rx) = mpsc::channel(4);
+        tx.send("127.0.0.1".parse().unwrap()).await.unwrap();
+        drop(tx);
+
+        scanner
+            .run_pipeline(rx, ports.clone(), |_| {})
+            .await
+            .unwrap();
+        scanner.finish().await;
+
+        // The dropped port is not announced either.
+        assert_eq!(events.try_recv().unwrap().port, ports[0]);
+        assert!(events.try_recv().is_err());
+        assert_eq!(db.get_total_open_ports_count().unwrap(), 1);
+        let findings = db.get_script_findings(Some("127.0.0.1"), None, 10).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            (
+                findings[0].port,
+                findings[0].kind.as_str(),
+                findings[0].value.as_str()
+            ),
+            (ports[0], "tag", "kept")
+        );
+        assert!(db
+            .get_script_findings(None, Some("note"), 10)
+            .unwrap()
+            .is_empty());
+    }
 }
@@ -0,0 +1,393 @@
+//! `--cve-db`: match detected product/version strings against a local NVD
+//! dataset and record candidate CVEs per open port.
+//!
+//! The dataset is one or more NVD CVE API 2.0 JSON files (the
+//! `{"vulnerabilities": [...]}` pages the API returns). Only the `cpeMatch`
+//! entries marked vulnerable are used, each on its own: configurations that
+//! combine an application with a specific OS are treated as the application
+//! alone, so results are candidates to verify, not findings.
+
+use crate::model::{CveMatch, ServiceInfo};
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Products named differently in server banners and in CPE names.
+const PRODUCT_ALIASES: &[(&str, &str)] = &[
+    ("apache", "http_server"),
+    ("microsoft-iis", "internet_information_services"),
+];
+
+/// Affected versions of one CPE product for one CVE.
+#[derive(Debug, Clone)]
+struct CveRule {
+    cve_id: String,
+    cvss: Option<f64>,
+    exact: Option<String>,
+    start_including: Option<String>,
+    start_excluding: Option<String>,
+    end_including: Option<String>,
+    end_excluding: Option<String>,
+}
+
+impl CveRule {
+    fn matches(&self, version: &str) -> bool {
+        if let Some(exact) = &self.exact {
+            return compare_versions(version, exact) == Ordering::Equal;
+        }
+        let cmp = |bound: &Option<String>, ok: &[Ordering]| {
+            bound
+                .as_deref()
+                .is_none_or(|bound| ok.contains(&compare_versions(version, bound)))
+        };
+        cmp(&self.start_including, &[Ordering::Greater, Ordering::Equal])
+            && cmp(&self.start_excluding, &[Ordering::Greater])
+            && cmp(&self.end_including, &[Ordering::Less, Ordering::Equal])
+            && cmp(&self.end_excluding, &[Ordering::Less])
+    }
+}
+
+/// Vulnerable version ranges by CPE product name.
+#[derive(Debug, Default)]
+pub struct CveIndex {
+    rules: HashMap<String, Vec<CveRule>>,
+}
+
+impl CveIndex {
+    /// Load an NVD JSON file, or every `.json` file in a directory.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut index = CveIndex::default();
+        let files = if path.is_dir() {
+            let mut files: Vec<_> = std::fs::read_dir(path)
+                .with_context(|| format!("Failed to read CVE directory {}", path.display()))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| file.extension().is_some_and(|ext| ext == "json"))
+                .collect();
+            files.sort();
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+        for file in files {
+            let content = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read CVE data {}", file.display()))?;
+            index
+                .add_feed(&content)
+                .with_context(|| format!("Invalid NVD JSON in {}", file.display()))?;
+        }
+        if index.is_empty() {
+            return Err(anyhow!(
+                "No versioned CPE matches found in {}",
+                path.display()
+            ));
+        }
+        Ok(index)
+    }
+
+    /// Add the vulnerable CPE matches of one NVD CVE API 2.0 response.
+    pub fn add_feed(&mut self, json: &str) -> Result<()> {
+        let feed: NvdFeed = serde_json::from_str(json)?;
+        for item in feed.vulnerabilities {
+            let cve = item.cve;
+            let cvss = cve.metrics.base_score();
+            let matches = cve
+                .configurations
+                .iter()
+                .flat_map(|config| &config.nodes)
+                .flat_map(|node| &node.cpe_match)
+                .filter(|m| m.vulnerable);
+            for m in matches {
+                // cpe:2.3:part:vendor:product:version:update:...
+                let fields: Vec<&str> = m.criteria.split(':').collect();
+                let (Some(product), Some(version)) = (fields.get(4), fields.get(5)) else {
+                    continue;
+                };
+                let exact = match (*version, fields.get(6).copied()) {
+                    ("*" | "-", _) => None,
+                    (version, Some(update)) if update != "*" && update != "-" => {
+                        Some(format!("{}{}", version, update))
+                    }
+                    (version, _) => Some(version.to_string()),
+                };
+                let rule = CveRule {
+                    cve_id: cve.id.clone(),
+                    cvss,
+                    exact,
+                    start_including: m.version_start_including.clone(),
+                    start_excluding: m.version_start_excluding.clone(),
+                    end_including: m.version_end_including.clone(),
+                    end_excluding: m.version_end_excluding.clone(),
+                };
+                // A match without any version constraint would flag every
+                // sighting of the product.
+                if rule.exact.is_none()
+                    && rule.start_including.is_none()
+                    && rule.start_excluding.is_none()
+                    && rule.end_including.is_none()
+                    && rule.end_excluding.is_none()
+                {
+                    continue;
+                }
+                self.rules
+                    .entry(product.to_ascii_lowercase())
+                    .or_default()
+                    .push(rule);
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of products with at least one rule.
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Candidate CVEs for the products and versions named in `info`'s
+    /// version, server header and banner, by CVE ID.
+    pub fn match_service(&self, info: &ServiceInfo) -> Vec<CveMatch> {
+        let mut found: HashMap<String, CveMatch> = HashMap::new();
+        let texts = [&info.service_version, &info.http_server, &info.banner];
+        for text in texts.into_iter().flatten() {
+            for (product, version) in detected_products(text) {
+                let Some(rules) = self.rules.get(&product) else {
+                    continue;
+                };
+                for rule in rules.iter().filter(|rule| rule.matches(&version)) {
+                    found
+                        .entry(rule.cve_id.clone())
+                        .or_insert_with(|| CveMatch {
+                            cve_id: rule.cve_id.clone(),
+                            product: product.clone(),
+                            version: version.clone(),
+                            cvss: rule.cvss,
+                        });
+                }
+            }
+        }
+        let mut matches: Vec<CveMatch> = found.into_values().collect();
+        matches.sort_by(|a, b| a.cve_id.cmp(&b.cve_id));
+        matches
+    }
+}
+
+/// `(CPE product, version)` pairs named in a banner-like string, such as
+/// `OpenSSH_8.9p1`, `nginx/1.18.0` or `vsFTPd 3.0.3`.
+fn detected_products(text: &str) -> Vec<(String, String)> {
+    static PRODUCT_VERSION: OnceLock<Regex> = OnceLock::new();
+    let re = PRODUCT_VERSION.get_or_init(|| {
+        Regex::new(r"(?i)\b([a-z][a-z0-9+-]*?)[/_ ]v?(\d+(?:\.\d+)+[a-z]*\d*)").unwrap()
+    });
+    re.captures_iter(text)
+        .map(|caps| {
+            let token = caps[1].to_ascii_lowercase();
+            let product = PRODUCT_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == token)
+                .map(|(_, product)| product.to_string())
+                .unwrap_or(token);
+            (product, caps[2].to_ascii_lowercase())
+        })
+        .collect()
+}
+
+/// Compare versions by their numeric and alphabetic runs, so that
+/// `8.9p1 < 9.3 < 9.3p2` and `1.10 > 1.9`. A version that extends another
+/// sorts after it.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a, b) = (version_parts(a), version_parts(b));
+    for (x, y) in a.iter().zip(&b) {
+        let ord = match (x, y) {
+            (Part::Num(x), Part::Num(y)) => x.cmp(y),
+            (Part::Alpha(x), Part::Alpha(y)) => x.cmp(y),
+            (Part::Num(_), Part::Alpha(_)) => Ordering::Greater,
+            (Part::Alpha(_), Part::Num(_)) => Ordering::Less,
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Num(u64),
+    Alpha(String),
+}
+
+fn version_parts(version: &str) -> Vec<Part> {
+    let mut parts = Vec::new();
+    let mut chars = version.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_ascii_digit() {
+            let mut digits = String::new();
+            while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                digits.push(d);
+            }
+            parts.push(Part::Num(digits.parse().unwrap_or(u64::MAX)));
+        } else if c.is_ascii_alphabetic() {
+            let mut letters = String::new();
+            while let Some(l) = chars.next_if(|l| l.is_ascii_alphabetic()) {
+                letters.push(l.to_ascii_lowercase());
+            }
+            parts.push(Part::Alpha(letters));
+        } else {
+            chars.next();
+        }
+    }
+    parts
+}
+
+#[derive(Deserialize)]
+struct NvdFeed {
+    #[serde(default)]
+    vulnerabilities: Vec<NvdItem>,
+}
+
+#[derive(Deserialize)]
+struct NvdItem {
+    cve: NvdCve,
+}
+
+#[derive(Deserialize)]
+struct NvdCve {
+    id: String,
+    #[serde(default)]
+    metrics: NvdMetrics,
+    #[serde(default)]
+    configurations: Vec<NvdConfiguration>,
+}
+
+#[derive(Default, Deserialize)]
+struct NvdMetrics {
+    #[serde(default, rename = "cvssMetricV31")]
+    v31: Vec<NvdCvssMetric>,
+    #[serde(default, rename = "cvssMetricV30")]
+    v30: Vec<NvdCvssMetric>,
+    #[serde(default, rename = "cvssMetricV2")]
+    v2: Vec<NvdCvssMetric>,
+}
+
+impl NvdMetrics {
+    fn base_score(&self) -> Option<f64> {
+        [&self.v31, &self.v30, &self.v2]
+            .into_iter()
+            .find_map(|metrics| metrics.first())
+            .map(|metric| metric.cvss_data.base_score)
+    }
+}
+
+#[derive(Deserialize)]
+struct NvdCvssMetric {
+    #[serde(rename = "cvssData")]
+    cvss_data: NvdCvssData,
+}
+
+#[derive(Deserialize)]
+struct NvdCvssData {
+    #[serde(rename = "baseScore")]
+    base_score: f64,
+}
+
+#[derive(Deserialize)]
+struct NvdConfiguration {
+    #[serde(default)]
+    nodes: Vec<NvdNode>,
+}
+
+#[derive(Deserialize)]
+struct NvdNode {
+    #[serde(default, rename = "cpeMatch")]
+    cpe_match: Vec<NvdCpeMatch>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NvdCpeMatch {
+    vulnerable: bool,
+    criteria: String,
+    version_start_including: Option<String>,
+    version_start_excluding: Option<String>,
+    version_end_including: Option<String>,
+    version_end_excluding: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = r#"{
+        "resultsPerPage": 3,
+        "vulnerabilities": [
+            {"cve": {"id": "CVE-2023-38408",
+                "metrics": {"cvssMetricV31": [{"cvssData": {"baseScore": 9.8}}]},
+                "configurations": [{"nodes": [{"operator": "OR", "cpeMatch": [
+                    {"vulnerable": true, "criteria": "cpe:2.3:a:openbsd:openssh:*:*:*:*:*:*:*:*",
+                     "versionStartIncluding": "5.5", "versionEndExcluding": "9.3"}]}]}]}},
+            {"cve": {"id": "CVE-2021-23017",
+                "metrics": {"cvssMetricV2": [{"cvssData": {"baseScore": 6.8}}]},
+                "configurations": [{"nodes": [{"cpeMatch": [
+                    {"vulnerable": true, "criteria": "cpe:2.3:a:f5:nginx:*:*:*:*:*:*:*:*",
+                     "versionStartIncluding": "0.6.18", "versionEndExcluding": "1.20.1"},
+                    {"vulnerable": false, "criteria": "cpe:2.3:o:debian:debian_linux:10.0:*:*:*:*:*:*:*"}]}]}]}},
+            {"cve": {"id": "CVE-2011-2523",
+                "configurations": [{"nodes": [{"cpeMatch": [
+                    {"vulnerable": true, "criteria": "cpe:2.3:a:vsftpd_project:vsftpd:2.3.4:*:*:*:*:*:*:*"},
+                    {"vulnerable": true, "criteria": "cpe:2.3:a:example:anything:*:*:*:*:*:*:*:*"}]}]}]}}
+        ]
+    }"#;
+
+    fn service(version: Option<&str>, server: Option<&str>) -> ServiceInfo {
+        let mut info = ServiceInfo::new("192.0.2.10".to_string(), 22);
+        info.service_version = version.map(str::to_string);
+        info.http_server = server.map(str::to_string);
+        info
+    }
+
+    #[test]
+    fn test_match_service_versions_against_nvd_ranges() {
+        let mut index = CveIndex::default();
+        index.add_feed(FEED).unwrap();
+        // The unversioned `anything` match is dropped.
+        assert_eq!(index.len(), 3);
+
+        let matches = index.match_service(&service(Some("SSH-2.0-OpenSSH_8.9p1"), None));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].cve_id, "CVE-2023-38408");
+        assert_eq!(matches[0].product, "openssh");
+        assert_eq!(matches[0].version, "8.9p1");
+        assert_eq!(matches[0].cvss, Some(9.8));
+        assert!(index
+            .match_service(&service(Some("SSH-2.0-OpenSSH_9.3p2"), None))
+            .is_empty());
+
+        let nginx = index.match_service(&service(None, Some("nginx/1.18.0")));
+        assert_eq!(nginx[0].cve_id, "CVE-2021-23017");
+        assert_eq!(nginx[0].cvss, Some(6.8));
+        assert!(index
+            .match_service(&service(None, Some("nginx/1.20.1")))
+            .is_empty());
+
+        let ftp = index.match_service(&service(Some("(vsFTPd 2.3.4)"), None));
+        assert_eq!(ftp[0].cve_id, "CVE-2011-2523");
+        assert!(index
+            .match_service(&service(Some("(vsFTPd 3.0.3)"), None))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("8.9p1", "9.3"), Ordering::Less);
+        assert_eq!(compare_versions("9.3p2", "9.3"), Ordering::Greater);
+        assert_eq!(compare_versions("1.10.0", "1.9.9"), Ordering::Greater);
+        assert_eq!(compare_versions("2.4.41", "2.4.41"), Ordering::Equal);
+    }
+}
@@ -0,0 +1,310 @@
+//! End-of-round summary emails.
+//!
+//! Subject and body are plain-text templates with `{{name}}` placeholders:
+//! `round`, `scanned`, `open`, `errors`, `retries`, `duration_secs`,
+//! `avg_rate`, `opened_count`, `closed_count`, `opened`, `closed` and
+//! `top_ports`. The list placeholders render one entry per line; unknown
+//! ones render empty, as in notifier templates.
+
+use super::notify::render_template;
+use crate::cli::ReportEmailConfig;
+use crate::dao::{PortChange, RoundMetrics, SqliteDB};
+use anyhow::{anyhow, Context, Result};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(60);
+/// Changes counted per report; beyond this the counts read "10000+".
+const MAX_COUNTED_CHANGES: usize = 10_000;
+const TOP_PORTS: usize = 10;
+
+const DEFAULT_SUBJECT: &str =
+    "[ip-scan] round {{round}}: {{opened_count}} opened, {{closed_count}} closed";
+const DEFAULT_TEMPLATE: &str = "\
+Scan round {{round}} finished.
+
+Scanned: {{scanned}} in {{duration_secs}}s ({{avg_rate}}/s)
+Open ports: {{open}}
+Errors: {{errors}} (retries: {{retries}})
+
+Newly open since the previous round ({{opened_count}}):
+{{opened}}
+
+No longer open ({{closed_count}}):
+{{closed}}
+
+Top ports:
+{{top_ports}}
+";
+
+/// What a round report is rendered from.
+pub struct RoundReport {
+    pub metrics: RoundMetrics,
+    pub opened: Vec<PortChange>,
+    pub closed: Vec<PortChange>,
+    pub top_ports: Vec<(u16, usize)>,
+}
+
+impl RoundReport {
+    /// Build the report for `round` from its `round_metrics` row, which
+    /// covers resumed runs too, and its bitmap diff against the round before.
+    pub fn collect(db: &SqliteDB, round: i64) -> Result<Self> {
+        let metrics = db
+            .get_round_metrics(1)?
+            .into_iter()
+            .find(|m| m.round == round)
+            .ok_or_else(|| anyhow!("No metrics recorded for round {}", round))?;
+        let (opened, closed) = db
+            .get_round_changes(round, MAX_COUNTED_CHANGES)?
+            .into_iter()
+            .partition(|change| change.is_open);
+        Ok(Self {
+            metrics,
+            opened,
+            closed,
+            top_ports: db.get_top_ports(TOP_PORTS)?,
+        })
+    }
+
+    pub fn render(&self, template: &str, max_changes: usize) -> String {
+        let m = &self.metrics;
+        let count = |changes: &[PortChange]| {
+            if changes.len() >= MAX_COUNTED_CHANGES {
+                format!("{}+", MAX_COUNTED_CHANGES)
+            } else {
+                changes.len().to_string()
+            }
+        };
+        let list = |changes: &[PortChange]| {
+            if changes.is_empty() {
+                return "  (none)".to_string();
+            }
+            let mut lines: Vec<String> = changes
+                .iter()
+                .take(max_changes)
+                .map(|c| format!("  {}:{}", c.ip_address, c.port))
+                .collect();
+            if changes.len() > max_changes {
+                lines.push(format!("  ... and {} more", changes.len() - max_changes));
+            }
+            lines.join("\n")
+        };
+        let top_ports = if self.top_ports.is_empty() {
+            "  (none)".to_string()
+        } else {
+            self.top_ports
+                .iter()
+                .map(|(port, count)| format!("  {:>5}  {}", port, count))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        let values = [
+            ("round", m.round.to_string()),
+            ("scanned", m.scanned.to_string()),
+            ("open", m.open.to_string()),
+            ("errors", m.errors.to_string()),
+            ("retries", m.retries.to_string()),
+            ("duration_secs", format!("{:.1}", m.duration_secs)),
+            ("avg_rate", format!("{:.1}", m.avg_rate)),
+            ("opened_count", count(&self.opened)),
+            ("closed_count", count(&self.closed)),
+            ("opened", list(&self.opened)),
+            ("closed", list(&self.closed)),
+            ("top_ports", top_ports),
+        ];
+        render_template(template, &values)
+    }
+}
+
+pub struct EmailReporter {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    subject: String,
+    template: String,
+    max_changes: usize,
+}
+
+impl EmailReporter {
+    /// `None` when no recipients are configured. Errors on incomplete SMTP
+    /// settings, so a typo fails at startup rather than after the first round.
+    pub fn from_config(config: &ReportEmailConfig, recipients: &[String]) -> Result<Option<Self>> {
+        if recipients.is_empty() {
+            return Ok(None);
+        }
+        let host = config
+            .smtp_host
+            .as_deref()
+            .ok_or_else(|| anyhow!("--report-email needs smtp_host in [report_email]"))?;
+        let from = config
+            .from
+            .as_deref()
+            .ok_or_else(|| anyhow!("--report-email needs from in [report_email]"))?
+            .parse::<Mailbox>()
+            .context("Invalid [report_email] from address")?;
+        let to = recipients
+            .iter()
+            .map(|addr| {
+                addr.parse::<Mailbox>()
+                    .with_context(|| format!("Invalid report recipient {}", addr))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut builder = match config.security.as_str() {
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            other => {
+                return Err(anyhow!(
+                    "Invalid [report_email] security {}; expected starttls, tls or none",
+                    other
+                ))
+            }
+        }
+        .port(config.smtp_port)
+        .timeout(Some(SEND_TIMEOUT));
+        if let Some(username) = &config.username {
+            let password = std::env::var("SCAN_SMTP_PASSWORD")
+                .ok()
+                .or_else(|| config.password.clone())
+                .unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+
+        let template = match &config.template {
+            Some(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read report template {}", path))?,
+            None => DEFAULT_TEMPLATE.to_string(),
+        };
+        Ok(Some(Self {
+            transport: builder.build(),
+            from,
+            to,
+            subject: config
+                .subject
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SUBJECT.to_string()),
+            template,
+            max_changes: config.max_changes,
+        }))
+    }
+
+    pub async fn send(&self, report: &RoundReport) -> Result<()> {
+        let mut message = Message::builder().from(self.from.clone());
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let subject = report.render(&self.subject, self.max_changes);
+        let message = message
+            .subject(subject.lines().next().unwrap_or_default())
+            .header(ContentType::TEXT_PLAIN)
+            .body(report.render(&self.template, self.max_changes))?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    /// Collect and send the report for a finished round in the background;
+    /// failures are logged and never delay the next round.
+    pub fn spawn_round_report(self: &Arc<Self>, db: SqliteDB, round: i64) {
+        let reporter = self.clone();
+        tokio::spawn(async move {
+            let report =
+                match tokio::task::spawn_blocking(move || RoundReport::collect(&db, round)).await {
+                    Ok(Ok(report)) => report,
+                    Ok(Err(e)) => {
+                        error!("Failed to build report for round {}: {}", round, e);
+                        return;
+                    }
+                    Err(e) => {
+                        error!("Report task for round {} failed: {}", round, e);
+                        return;
+                    }
+                };
+            match tokio::time::timeout(SEND_TIMEOUT, reporter.send(&report)).await {
+                Ok(Ok(())) => info!(
+                    "Sent report for round {} to {} recipients",
+                    round,
+                    reporter.to.len()
+                ),
+                Ok(Err(e)) => error!("Failed to send report for round {}: {}", round, e),
+                Err(_) => error!("Sending report for round {} timed out", round),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_diffs_against_previous_round() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 22, true),
+                ("192.0.2.2".parse().unwrap(), 22, true),
+            ],
+            1,
+        )
+        .unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.2".parse().unwrap(), 22, true),
+                ("192.0.2.3".parse().unwrap(), 22, true),
+                ("192.0.2.3".parse().unwrap(), 443, true),
+            ],
+            2,
+        )
+        .unwrap();
+
+        assert!(RoundReport::collect(&db, 2).is_err());
+        db.save_round_metrics(&RoundMetrics {
+            round: 2,
+            scanned: 512,
+            open: 3,
+            errors: 3,
+            retries: 1,
+            duration_secs: 4.0,
+            avg_rate: 0.0,
+            finished_at: String::new(),
+        })
+        .unwrap();
+
+        let report = RoundReport::collect(&db, 2).unwrap();
+        let body = report.render(DEFAULT_TEMPLATE, 1);
+        assert!(body.contains("Scan round 2 finished."));
+        assert!(body.contains("Scanned: 512 in 4.0s (128.0/s)"));
+        assert!(body.contains("Errors: 3 (retries: 1)"));
+        assert!(body.contains(
+            "Newly open since the previous round (2):\n  192.0.2.3:22\n  ... and 1 more"
+        ));
+        assert!(body.contains("No longer open (1):\n  192.0.2.1:22"));
+        assert!(body.contains("     22  3"));
+        assert_eq!(
+            report.render(DEFAULT_SUBJECT, 1),
+            "[ip-scan] round 2: 2 opened, 1 closed"
+        );
+    }
+
+    #[test]
+    fn test_reporter_requires_smtp_settings() {
+        let mut config = ReportEmailConfig::default();
+        assert!(EmailReporter::from_config(&config, &[]).unwrap().is_none());
+
+        let to = vec!["secops@example.com".to_string()];
+        assert!(EmailReporter::from_config(&config, &to).is_err());
+        config.smtp_host = Some("smtp.example.com".to_string());
+        config.from = Some("ip-scan <scanner@example.com>".to_string());
+        config.security = "ssl".to_string();
+        assert!(EmailReporter::from_config(&config, &to).is_err());
+        config.security = "none".to_string();
+        assert!(EmailReporter::from_config(&config, &to).unwrap().is_some());
+    }
+}
@@ -0,0 +1,257 @@
+//! `ip-scan estimate`: how many probes one round over a range sends once
+//! the exclude file, private ranges (`skip_private`) and 0.0.0.0/8 are taken
+//! out, and how long that takes. A SYN scan is paced by `--max-rate` alone.
+//! A connect scan holds a `--concurrency` slot per probe until it answers,
+//! so its time lies between the `--max-rate` pace (every port answers at
+//! once) and every probe waiting out `--timeout` (every port filtered).
+
+use crate::cli::Args;
+use crate::model::{parse_port_range, ExcludeList, IpRange};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// The ranges `Args::is_private_ipv4` matches; 224.0.0.0/4 and
+/// 240.0.0.0/4 are one block.
+const PRIVATE_V4: [(u32, u32); 6] = [
+    (0x0A00_0000, 0x0AFF_FFFF), // 10.0.0.0/8
+    (0x7F00_0000, 0x7FFF_FFFF), // 127.0.0.0/8
+    (0xA9FE_0000, 0xA9FE_FFFF), // 169.254.0.0/16
+    (0xAC10_0000, 0xAC1F_FFFF), // 172.16.0.0/12
+    (0xC0A8_0000, 0xC0A8_FFFF), // 192.168.0.0/16
+    (0xE000_0000, 0xFFFF_FFFF), // 224.0.0.0/3
+];
+
+/// 0.0.0.0/8 is never scanned.
+const THIS_NETWORK: (u32, u32) = (0, 0x00FF_FFFF);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanEstimate {
+    pub start: Ipv4Addr,
+    pub end: Ipv4Addr,
+    /// Addresses in the range
+    pub addresses: u64,
+    /// Addresses in it the scan skips
+    pub excluded: u64,
+    pub targets: u64,
+    pub ports: usize,
+    pub probes: u64,
+    /// `--max-rate` per second
+    pub rate: f64,
+    pub syn_secs: f64,
+    /// Every port answers right away
+    pub connect_min_secs: f64,
+    /// Every port is filtered and waits out `--timeout`
+    pub connect_max_secs: f64,
+    pub concurrency: usize,
+    pub timeout_ms: u64,
+}
+
+impl ScanEstimate {
+    pub fn from_args(args: &Args) -> Result<Self> {
+        if args.target_hostnames().is_some() {
+            return Err(anyhow!(
+                "estimate needs an IP, CIDR or range; hostnames are only resolved when a scan runs"
+            ));
+        }
+        if args.input_file.is_some() {
+            return Err(anyhow!(
+                "estimate covers a single range, not an --input-file list"
+            ));
+        }
+        let (start, end) = args
+            .start_ip
+            .clone()
+            .zip(args.end_ip.clone())
+            .unwrap_or_else(Args::get_default_ipv4_range);
+        let range = IpRange::new(&start, &end).map_err(|e| anyhow!(e))?;
+        let (IpAddr::V4(start), IpAddr::V4(end)) = (range.start, range.end) else {
+            return Err(anyhow!("estimate covers IPv4 ranges only"));
+        };
+        let ports = parse_port_range(&args.ports, &args.port_groups).map_err(|e| anyhow!(e))?;
+        let exclude = args.load_exclude_list()?;
+
+        let addresses = u64::from(u32::from(end) - u32::from(start)) + 1;
+        let excluded = excluded_count(start, end, exclude.as_ref(), args.skip_private);
+        let targets = addresses - excluded;
+        let probes = targets * ports.len() as u64;
+        let rate = args.max_rate as f64 / args.rate_window_secs.max(1) as f64;
+        let timeout = args.timeout as f64 / 1000.0;
+        // Slots held for the whole timeout, by all hosts or by too few hosts
+        // to fill --concurrency.
+        let slots =
+            (args.concurrency as u64).min(targets.saturating_mul(args.host_concurrency as u64));
+        let filtered_rate = rate.min(slots as f64 / timeout);
+        let seconds = |rate: f64| {
+            if probes == 0 {
+                0.0
+            } else {
+                probes as f64 / rate
+            }
+        };
+
+        Ok(Self {
+            start,
+            end,
+            addresses,
+            excluded,
+            targets,
+            ports: ports.len(),
+            probes,
+            rate,
+            syn_secs: seconds(rate) + args.syn_linger_secs as f64,
+            connect_min_secs: seconds(rate),
+            connect_max_secs: seconds(filtered_rate) + timeout,
+            concurrency: args.concurrency,
+            timeout_ms: args.timeout,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_text(&self) -> String {
+        format!(
+            "Range:    {} - {} ({} addresses, {} excluded)\n\
+             Probes:   {} targets x {} ports = {}\n\
+             SYN:      {} at {:.0} probes/s\n\
+             Connect:  {} if every port answers, {} if every port is filtered\n\
+             \x20         ({} concurrent probes, {} ms timeout)\n",
+            self.start,
+            self.end,
+            self.addresses,
+            self.excluded,
+            self.targets,
+            self.ports,
+            self.probes,
+            format_duration(self.syn_secs),
+            self.rate,
+            format_duration(self.connect_min_secs),
+            format_duration(self.connect_max_secs),
+            self.concurrency,
+            self.timeout_ms,
+        )
+    }
+}
+
+/// Addresses in `start..=end` the producer skips, each counted once.
+fn excluded_count(
+    start: Ipv4Addr,
+    end: Ipv4Addr,
+    exclude: Option<&ExcludeList>,
+    skip_private: bool,
+) -> u64 {
+    let (start, end) = (u32::from(start), u32::from(end));
+    let clip = |&(s, e): &(u32, u32)| (s <= end && e >= start).then(|| (s.max(start), e.min(end)));
+    let mut ranges: Vec<(u32, u32)> = exclude
+        .map(|list| list.v4_overlaps(start, end))
+        .unwrap_or_default();
+    ranges.extend(clip(&THIS_NETWORK));
+    if skip_private {
+        ranges.extend(PRIVATE_V4.iter().filter_map(clip));
+    }
+    ranges.sort_unstable();
+
+    let mut total = 0u64;
+    let mut covered: Option<u32> = None;
+    for (s, e) in ranges {
+        let s = match covered {
+            Some(c) if c >= e => continue,
+            Some(c) if c >= s => c + 1,
+            _ => s,
+        };
+        total += u64::from(e - s) + 1;
+        covered = Some(e);
+    }
+    total
+}
+
+/// `3d 4h`, `2h 5m`, `8m 21s` or `12s`.
+fn format_duration(secs: f64) -> String {
+    let secs = secs.ceil() as u64;
+    let (days, hours, minutes, seconds) =
+        (secs / 86_400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_private_ranges_match_the_scanner() {
+        for (start, end) in PRIVATE_V4 {
+            for ip in [start, end] {
+                assert!(Args::is_private_ipv4(&Ipv4Addr::from(ip).to_string()));
+            }
+            if start > 0 {
+                assert!(!Args::is_private_ipv4(
+                    &Ipv4Addr::from(start - 1).to_string()
+                ));
+            }
+        }
+        assert!(!Args::is_private_ipv4("223.255.255.255"));
+    }
+
+    #[test]
+    fn test_exclusions_are_counted_once() {
+        let exclude = ExcludeList::parse("10.1.0.0/16\n192.0.2.0/25\n").unwrap();
+        let ip = |s: &str| s.parse::<Ipv4Addr>().unwrap();
+        // 10.1.0.0/16 lies inside 10.0.0.0/8.
+        assert_eq!(
+            excluded_count(ip("9.255.255.0"), ip("10.0.0.255"), Some(&exclude), true),
+            256
+        );
+        assert_eq!(
+            excluded_count(ip("192.0.2.0"), ip("192.0.2.255"), Some(&exclude), false),
+            128
+        );
+        assert_eq!(
+            excluded_count(ip("0.0.0.0"), ip("255.255.255.255"), None, false),
+            1 << 24
+        );
+    }
+
+    #[test]
+    fn test_estimate_bounds_connect_time() {
+        let mut args = Args::try_parse_from([
+            "ip-scan",
+            "--target",
+            "198.51.100.0/24",
+            "--ports",
+            "22,80,443,8080",
+            "--max-rate",
+            "1000",
+            "--concurrency",
+            "100",
+            "--timeout",
+            "2000",
+        ])
+        .unwrap();
+        args.start_ip = Some("198.51.100.0".to_string());
+        args.end_ip = Some("198.51.100.255".to_string());
+
+        let estimate = ScanEstimate::from_args(&args).unwrap();
+        assert_eq!(estimate.targets, 256);
+        assert_eq!(estimate.probes, 1024);
+        assert_eq!(estimate.connect_min_secs, 1.024);
+        // 100 slots held 2 s each: 50 probes/s, plus the last timeout.
+        assert_eq!(estimate.connect_max_secs, 1024.0 / 50.0 + 2.0);
+        assert_eq!(estimate.syn_secs, 1.024 + 1.0);
+        assert!(estimate.to_text().contains("23s if every port is filtered"));
+
+        args.target = Some("example.com".to_string());
+        assert!(ScanEstimate::from_args(&args).is_err());
+        assert_eq!(format_duration(3.0 * 86_400.0 + 5000.0), "3d 1h");
+    }
+}
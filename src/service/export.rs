@@ -0,0 +1,355 @@
+//! File exports of scan results: Parquet for analysis tools, and the CSV and
+//! JSON layouts of `/export/csv` and `/export/json`.
+//!
+//! Rows are read in id order in fixed-size batches (for Parquet, one Snappy
+//! compressed row group each), so memory stays flat however many rows
+//! match. Timestamps keep the RFC 3339 text stored in SQLite.
+
+use super::ResultsFilter;
+use crate::dao::{PortStatus, ScanResultDetail, SqliteDB};
+use anyhow::Result;
+use arrow_array::{
+    ArrayRef, BooleanArray, Int64Array, RecordBatch, StringArray, UInt16Array, UInt8Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Rows fetched per query; also the Parquet row group size.
+const BATCH_ROWS: usize = 65_536;
+
+/// Header line of the CSV layout, which `ip-scan import` reads back.
+pub const CSV_HEADER: &str = "ip_address,ip_type,port,scan_round,first_seen,last_seen,closed_at,scan_id,cves,reputation_score,known_scanner\n";
+
+/// One result as a CSV line of [`CSV_HEADER`]'s columns. No field holds a
+/// comma or quote, so nothing is escaped.
+pub fn csv_row(r: &ScanResultDetail) -> String {
+    format!(
+        "{},{},{},{},{},{},{},{},{},{},{}\n",
+        r.ip_address,
+        r.ip_type,
+        r.port,
+        r.scan_round,
+        r.first_seen,
+        r.last_seen,
+        r.closed_at.as_deref().unwrap_or_default(),
+        r.scan_id.as_deref().unwrap_or_default(),
+        r.cves.join(" "),
+        r.reputation_score
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        r.known_scanner
+    )
+}
+
+/// Call `write` with every result matching `filter`, a batch at a time,
+/// stopping after `max_rows` when set; returns the number of rows.
+fn for_each_batch(
+    db: &SqliteDB,
+    filter: &ResultsFilter,
+    max_rows: Option<usize>,
+    mut write: impl FnMut(Vec<ScanResultDetail>) -> Result<()>,
+) -> Result<usize> {
+    let max_rows = max_rows.unwrap_or(usize::MAX);
+    let mut after_id = 0;
+    let mut written = 0;
+    while written < max_rows {
+        let rows = db.get_scan_results_after(
+            after_id,
+            BATCH_ROWS.min(max_rows - written),
+            filter.ip.as_deref(),
+            filter.port,
+            filter.round,
+            filter.ip_type.as_deref(),
+            filter.status,
+            filter.scan_id.as_deref(),
+            filter.hostname.as_deref(),
+            filter.has_cves,
+            filter.reputation,
+        )?;
+        let Some((last_id, _)) = rows.last() else {
+            break;
+        };
+        after_id = *last_id;
+        let full = rows.len() == BATCH_ROWS.min(max_rows - written);
+        written += rows.len();
+        write(rows.into_iter().map(|(_, r)| r).collect())?;
+        if !full {
+            break;
+        }
+    }
+    Ok(written)
+}
+
+/// Write every result matching `filter` to `out` in the `/export/csv`
+/// layout; returns the number of rows written.
+pub fn write_results_csv<W: Write>(
+    db: &SqliteDB,
+    filter: &ResultsFilter,
+    mut out: W,
+) -> Result<usize> {
+    out.write_all(CSV_HEADER.as_bytes())?;
+    let written = for_each_batch(db, filter, None, |rows| {
+        for row in &rows {
+            out.write_all(csv_row(row).as_bytes())?;
+        }
+        Ok(())
+    })?;
+    out.flush()?;
+    Ok(written)
+}
+
+/// Write every result matching `filter` to `out` as the JSON array
+/// `/export/json` returns, without its row limit; returns the number of
+/// rows written.
+pub fn write_results_json<W: Write>(
+    db: &SqliteDB,
+    filter: &ResultsFilter,
+    mut out: W,
+) -> Result<usize> {
+    out.write_all(b"[")?;
+    let mut first = true;
+    let written = for_each_batch(db, filter, None, |rows| {
+        for row in rows {
+            if !std::mem::take(&mut first) {
+                out.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut out, &crate::api::models::ScanResult::from(row))?;
+        }
+        Ok(())
+    })?;
+    out.write_all(b"]")?;
+    out.flush()?;
+    Ok(written)
+}
+
+/// Write the distinct addresses of the results matching `filter` to `out`,
+/// one per line in address order, the `--input-file` format; returns the
+/// number of addresses. Only still-open ports count unless `filter` asks
+/// for another status.
+pub fn write_target_list<W: Write>(
+    db: &SqliteDB,
+    filter: &ResultsFilter,
+    mut out: W,
+) -> Result<usize> {
+    let mut filter = filter.clone();
+    filter.status.get_or_insert(PortStatus::Active);
+    let mut hosts = BTreeSet::new();
+    for_each_batch(db, &filter, None, |rows| {
+        hosts.extend(
+            rows.iter()
+                .filter_map(|r| r.ip_address.parse::<IpAddr>().ok()),
+        );
+        Ok(())
+    })?;
+    for host in &hosts {
+        writeln!(out, "{}", host)?;
+    }
+    out.flush()?;
+    Ok(hosts.len())
+}
+
+fn results_schema() -> Arc<Schema> {
+    let text = |name: &str, nullable: bool| Field::new(name, DataType::Utf8, nullable);
+    Arc::new(Schema::new(vec![
+        text("ip_address", false),
+        text("ip_type", false),
+        Field::new("port", DataType::UInt16, false),
+        Field::new("scan_round", DataType::Int64, false),
+        text("first_seen", false),
+        text("last_seen", false),
+        text("country", true),
+        text("city", true),
+        text("reverse_dns", true),
+        text("abuse_email", true),
+        text("closed_at", true),
+        text("scan_id", true),
+        text("cves", true),
+        Field::new("reputation_score", DataType::UInt8, true),
+        Field::new("known_scanner", DataType::Boolean, false),
+    ]))
+}
+
+/// Write every result matching `filter`, or the first `max_rows` of them,
+/// to `out` as Parquet; returns the number of rows written.
+pub fn write_results_parquet<W: Write + Send>(
+    db: &SqliteDB,
+    filter: &ResultsFilter,
+    max_rows: Option<usize>,
+    out: W,
+) -> Result<usize> {
+    let schema = results_schema();
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_max_row_group_size(BATCH_ROWS)
+        .build();
+    let mut writer = ArrowWriter::try_new(out, schema.clone(), Some(props))?;
+    let written = for_each_batch(db, filter, max_rows, |rows| {
+        let text = |f: fn(&ScanResultDetail) -> Option<&str>| -> ArrayRef {
+            Arc::new(rows.iter().map(f).collect::<StringArray>())
+        };
+        let mut columns: Vec<ArrayRef> = vec![
+            text(|r| Some(&r.ip_address)),
+            text(|r| Some(&r.ip_type)),
+            Arc::new(rows.iter().map(|r| r.port).collect::<UInt16Array>()),
+            Arc::new(rows.iter().map(|r| r.scan_round).collect::<Int64Array>()),
+            text(|r| Some(&r.first_seen)),
+            text(|r| Some(&r.last_seen)),
+            text(|r| r.country.as_deref()),
+            text(|r| r.city.as_deref()),
+            text(|r| r.reverse_dns.as_deref()),
+            text(|r| r.abuse_email.as_deref()),
+            text(|r| r.closed_at.as_deref()),
+            text(|r| r.scan_id.as_deref()),
+        ];
+        let cves: ArrayRef = Arc::new(
+            rows.iter()
+                .map(|r| (!r.cves.is_empty()).then(|| r.cves.join(" ")))
+                .collect::<StringArray>(),
+        );
+        columns.push(cves);
+        columns.push(Arc::new(
+            rows.iter()
+                .map(|r| r.reputation_score)
+                .collect::<UInt8Array>(),
+        ));
+        columns.push(Arc::new(
+            rows.iter()
+                .map(|r| Some(r.known_scanner))
+                .collect::<BooleanArray>(),
+        ));
+        writer.write(&RecordBatch::try_new(schema.clone(), columns)?)?;
+        Ok(())
+    })?;
+    writer.close()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn test_parquet_round_trip_with_filter() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 22, true),
+                ("192.0.2.2".parse().unwrap(), 443, true),
+                ("192.0.2.3".parse().unwrap(), 443, true),
+            ],
+            3,
+        )
+        .unwrap();
+
+        let filter = ResultsFilter {
+            port: Some(443),
+            ..Default::default()
+        };
+        let file = tempfile::tempfile().unwrap();
+        let written = write_results_parquet(&db, &filter, None, file.try_clone().unwrap()).unwrap();
+        assert_eq!(written, 2);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+        let batch = &batches[0];
+        let ips = batch
+            .column_by_name("ip_address")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(ips.value(0), "192.0.2.2");
+        let ports = batch
+            .column_by_name("port")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<UInt16Array>()
+            .unwrap();
+        assert_eq!(ports.value(1), 443);
+        assert!(batch.column_by_name("country").unwrap().is_null(0));
+    }
+
+    #[test]
+    fn test_csv_and_json_use_the_api_layouts() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 22, true),
+                ("192.0.2.2".parse().unwrap(), 443, true),
+            ],
+            3,
+        )
+        .unwrap();
+        let filter = ResultsFilter::default();
+
+        let mut csv = Vec::new();
+        assert_eq!(write_results_csv(&db, &filter, &mut csv).unwrap(), 2);
+        let csv = String::from_utf8(csv).unwrap();
+        let rows = crate::service::read_results_csv(csv.as_bytes()).unwrap();
+        assert_eq!(rows.filter(|row| row.is_ok()).count(), 2);
+        assert!(csv.starts_with(CSV_HEADER));
+
+        let mut json = Vec::new();
+        assert_eq!(write_results_json(&db, &filter, &mut json).unwrap(), 2);
+        let parsed: Vec<crate::api::models::ScanResult> = serde_json::from_slice(&json).unwrap();
+        let mut ports: Vec<u16> = parsed.iter().map(|result| result.port).collect();
+        ports.sort_unstable();
+        assert_eq!(ports, vec![22, 443]);
+
+        let mut empty = Vec::new();
+        let none = ResultsFilter {
+            port: Some(8080),
+            ..Default::default()
+        };
+        assert_eq!(write_results_json(&db, &none, &mut empty).unwrap(), 0);
+        assert_eq!(empty, b"[]");
+    }
+
+    #[test]
+    fn test_target_list_holds_distinct_open_hosts_in_address_order() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.10".parse().unwrap(), 443, true),
+                ("192.0.2.9".parse().unwrap(), 443, true),
+                ("192.0.2.9".parse().unwrap(), 8443, true),
+                ("192.0.2.7".parse().unwrap(), 22, true),
+                ("192.0.2.8".parse().unwrap(), 443, false),
+            ],
+            1,
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(
+            write_target_list(&db, &ResultsFilter::default(), &mut out).unwrap(),
+            3
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "192.0.2.7\n192.0.2.9\n192.0.2.10\n"
+        );
+
+        let https = ResultsFilter {
+            port: Some(443),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        assert_eq!(write_target_list(&db, &https, &mut out).unwrap(), 2);
+        let list = crate::model::ExcludeList::parse(&String::from_utf8(out).unwrap()).unwrap();
+        assert!(list.contains("192.0.2.10".parse().unwrap()));
+        assert!(!list.contains("192.0.2.7".parse().unwrap()));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::model::IpGeoInfo;
+use lru::LruCache;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct CacheEntry {
+    /// `None` records a recent failed lookup.
+    info: Option<IpGeoInfo>,
+    expires: Instant,
+}
+
+/// Bounded, expiring cache of external geo lookups, keyed by IP and by
+/// network prefix for results that describe a whole network.
+pub(super) struct GeoCache {
+    entries: Mutex<LruCache<String, CacheEntry>>,
+}
+
+impl GeoCache {
+    pub(super) fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// `Some(None)` means a lookup for `key` failed recently and should not
+    /// be retried yet.
+    pub(super) fn get(&self, key: &str) -> Option<Option<IpGeoInfo>> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.info.clone()),
+            Some(_) => {
+                entries.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub(super) fn put(&self, key: String, info: Option<IpGeoInfo>, ttl: Duration) {
+        let entry = CacheEntry {
+            info,
+            expires: Instant::now() + ttl,
+        };
+        self.entries.lock().unwrap().put(key, entry);
+    }
+}
+
+/// Cache key shared by every IPv4 address in the same /24. Registry and
+/// ip-api.com data rarely differs inside one; IPv6 is only cached per IP.
+pub(super) fn prefix_key(ip: &str) -> Option<String> {
+    match ip.parse::<IpAddr>().ok()? {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            Some(format!("{}.{}.{}.0/24", a, b, c))
+        }
+        IpAddr::V6(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_expires_and_evicts_least_recently_used() {
+        let cache = GeoCache::new(2);
+        let info = |ip: &str| Some(IpGeoInfo::new(ip.to_string(), "test".to_string()));
+
+        cache.put("a".to_string(), info("a"), Duration::from_secs(60));
+        cache.put("b".to_string(), None, Duration::from_secs(60));
+        assert!(matches!(cache.get("b"), Some(None)));
+        assert!(cache.get("a").unwrap().is_some());
+
+        // "b" is now least recently used.
+        cache.put("c".to_string(), info("c"), Duration::from_secs(60));
+        assert!(cache.get("b").is_none());
+
+        cache.put("d".to_string(), info("d"), Duration::ZERO);
+        assert!(cache.get("d").is_none());
+    }
+
+    #[test]
+    fn test_prefix_key() {
+        assert_eq!(prefix_key("192.0.2.77").as_deref(), Some("192.0.2.0/24"));
+        assert_eq!(prefix_key("2001:db8::1"), None);
+    }
+}
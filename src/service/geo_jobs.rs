@@ -0,0 +1,253 @@
+//! `POST /geo/enrich` jobs: geo lookups for chosen IPs right away instead of
+//! whenever the background worker's pass reaches them. Jobs run one at a
+//! time with the worker's per-lookup timeout and `--geo-concurrency`, and
+//! share its provider budgets when both live in one process. The API keeps
+//! the most recent jobs in memory so their progress can be polled.
+
+use super::geo_service::{Lookup, SAVE_BATCH};
+use super::GeoService;
+use crate::dao::SqliteDB;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+/// Most IPs one job may look up.
+pub const MAX_JOB_IPS: usize = 1000;
+/// Jobs waiting behind the running one before new ones are refused.
+const MAX_QUEUED_JOBS: usize = 10;
+/// Finished jobs are forgotten beyond this many.
+const MAX_KEPT_JOBS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GeoJobState {
+    Queued,
+    Running,
+    Completed,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GeoJob {
+    pub id: u64,
+    pub state: GeoJobState,
+    /// IPs the job looks up
+    pub total: usize,
+    /// IPs looked up so far
+    pub done: usize,
+    /// Lookups that returned geo data and were saved
+    pub found: usize,
+    /// Lookups that failed or timed out
+    pub failed: usize,
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+}
+
+pub struct GeoJobs {
+    geo: GeoService,
+    db: SqliteDB,
+    concurrency: usize,
+    jobs: Arc<Mutex<VecDeque<GeoJob>>>,
+    next_id: AtomicU64,
+    runner: Arc<Semaphore>,
+}
+
+impl GeoJobs {
+    pub fn new(geo: GeoService, db: SqliteDB, concurrency: usize) -> Self {
+        Self {
+            geo,
+            db,
+            concurrency: concurrency.max(1),
+            jobs: Arc::new(Mutex::new(VecDeque::new())),
+            next_id: AtomicU64::new(1),
+            runner: Arc::new(Semaphore::new(1)),
+        }
+    }
+
+    /// Queue a job over `ips`, or `None` when `MAX_QUEUED_JOBS` are already
+    /// waiting.
+    pub fn submit(&self, ips: Vec<String>) -> Option<GeoJob> {
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let queued = jobs
+                .iter()
+                .filter(|job| job.state == GeoJobState::Queued)
+                .count();
+            if queued >= MAX_QUEUED_JOBS {
+                return None;
+            }
+            let job = GeoJob {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                state: GeoJobState::Queued,
+                total: ips.len(),
+                done: 0,
+                found: 0,
+                failed: 0,
+                created_at: chrono::Utc::now().to_rfc3339(),
+                started_at: None,
+                finished_at: None,
+            };
+            jobs.push_back(job.clone());
+            while jobs.len() > MAX_KEPT_JOBS {
+                match jobs
+                    .iter()
+                    .position(|job| job.state == GeoJobState::Completed)
+                {
+                    Some(index) => {
+                        jobs.remove(index);
+                    }
+                    None => break,
+                }
+            }
+            job
+        };
+
+        let run = JobRun {
+            id: job.id,
+            geo: self.geo.clone(),
+            db: self.db.clone(),
+            concurrency: self.concurrency,
+            jobs: self.jobs.clone(),
+        };
+        let runner = self.runner.clone();
+        tokio::spawn(async move {
+            let Ok(_permit) = runner.acquire_owned().await else {
+                return;
+            };
+            run.run(ips).await;
+        });
+        Some(job)
+    }
+
+    pub fn get(&self, id: u64) -> Option<GeoJob> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.iter().find(|job| job.id == id).cloned()
+    }
+}
+
+/// One job on its way through the lookups.
+struct JobRun {
+    id: u64,
+    geo: GeoService,
+    db: SqliteDB,
+    concurrency: usize,
+    jobs: Arc<Mutex<VecDeque<GeoJob>>>,
+}
+
+impl JobRun {
+    async fn run(self, ips: Vec<String>) {
+        info!(
+            "Geo enrichment job {} looking up {} IPs",
+            self.id,
+            ips.len()
+        );
+        self.update(|job| {
+            job.state = GeoJobState::Running;
+            job.started_at = Some(chrono::Utc::now().to_rfc3339());
+        });
+
+        let geo = &self.geo;
+        let mut lookups = stream::iter(ips)
+            .map(|ip| async move { geo.lookup_bounded(&ip).await })
+            .buffer_unordered(self.concurrency);
+        let mut pending = Vec::with_capacity(SAVE_BATCH);
+        while let Some(lookup) = lookups.next().await {
+            match lookup {
+                Lookup::Found(info) => pending.push(*info),
+                Lookup::Failed | Lookup::TimedOut => self.update(|job| job.failed += 1),
+            }
+            self.update(|job| job.done += 1);
+            if pending.len() >= SAVE_BATCH {
+                self.save(&mut pending);
+            }
+        }
+        self.save(&mut pending);
+
+        self.update(|job| {
+            job.state = GeoJobState::Completed;
+            job.finished_at = Some(chrono::Utc::now().to_rfc3339());
+        });
+    }
+
+    /// Save found lookups; they only count as found once stored.
+    fn save(&self, pending: &mut Vec<crate::model::IpGeoInfo>) {
+        if pending.is_empty() {
+            return;
+        }
+        match self.db.save_ip_geo_info_batch(pending) {
+            Ok(_) => {
+                let saved = pending.len();
+                self.update(|job| job.found += saved);
+            }
+            Err(e) => {
+                error!("Geo enrichment job {} failed to save: {}", self.id, e);
+                let lost = pending.len();
+                self.update(|job| job.failed += lost);
+            }
+        }
+        pending.clear();
+    }
+
+    fn update(&self, change: impl FnOnce(&mut GeoJob)) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == self.id) {
+            change(job);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::IpGeoInfo;
+    use anyhow::Result;
+    use futures::future::BoxFuture;
+
+    struct Fixed;
+
+    impl crate::service::geo_service::GeoProvider for Fixed {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn lookup<'a>(&'a self, ip: &'a str) -> BoxFuture<'a, Result<Option<IpGeoInfo>>> {
+            Box::pin(async move {
+                let mut info = IpGeoInfo::new(ip.to_string(), "fixed".to_string());
+                info.country = Some("NL".to_string());
+                Ok(Some(info))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jobs_look_up_ips_and_report_progress() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let mut geo = GeoService::new(None, None);
+        geo.register_provider(Arc::new(Fixed));
+        let jobs = GeoJobs::new(geo, db.clone(), 2);
+
+        let first = jobs.submit(vec!["127.0.0.1".to_string()]).unwrap();
+        let empty = jobs.submit(Vec::new()).unwrap();
+        assert_eq!((first.state, first.total), (GeoJobState::Queued, 1));
+        assert!(empty.id > first.id);
+        for _ in 0..100 {
+            if jobs.get(empty.id).unwrap().state == GeoJobState::Completed {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+
+        let job = jobs.get(first.id).unwrap();
+        assert_eq!(job.state, GeoJobState::Completed);
+        assert_eq!((job.done, job.found, job.failed), (1, 1, 0));
+        assert!(job.finished_at.is_some());
+        let info = db.get_ip_geo_info("127.0.0.1").unwrap().unwrap();
+        assert_eq!(info.country.as_deref(), Some("NL"));
+        assert!(jobs.get(999).is_none());
+    }
+}
@@ -0,0 +1,414 @@
+//! Offline country/ASN lookups from IP range datasets such as iptoasn.com's
+//! `ip2asn-combined.tsv` or the ip-location-db CSVs, for `--geo-csv`.
+//!
+//! Each line is a range followed by its data, comma- or tab-separated:
+//!
+//! - `start,end,country` (country datasets)
+//! - `start,end,asn,org` (ASN datasets)
+//! - `start,end,asn,country,org` (iptoasn.com)
+//!
+//! A header line and blank lines are skipped; ASN 0 and the country `None`
+//! mark unrouted space and are left out.
+//!
+//! [`country_ranges`] turns `--exclude-country` codes into address ranges
+//! from these datasets and the MaxMind database.
+
+use crate::model::{IpGeoInfo, IpRange};
+use anyhow::{anyhow, Context, Result};
+use maxminddb::PathElement;
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// What a range maps to; shared by all ranges with the same values.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Network {
+    country: Option<String>,
+    asn: Option<u32>,
+    org: Option<String>,
+}
+
+/// Ranges sorted by start, pointing into `GeoRanges::networks`.
+struct Table<T> {
+    ranges: Vec<(T, T, u32)>,
+}
+
+impl<T: Ord + Copy> Table<T> {
+    fn find(&self, ip: T) -> Option<u32> {
+        let after = self.ranges.partition_point(|&(start, _, _)| start <= ip);
+        let &(_, end, network) = self.ranges.get(after.checked_sub(1)?)?;
+        (ip <= end).then_some(network)
+    }
+
+    fn sort(&mut self) {
+        self.ranges.sort_unstable_by_key(|&(start, _, _)| start);
+    }
+}
+
+/// One loaded dataset.
+pub(super) struct GeoRanges {
+    name: String,
+    v4: Table<u32>,
+    v6: Table<u128>,
+    networks: Vec<Network>,
+}
+
+impl GeoRanges {
+    pub(super) fn open(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("opening geo dataset {}", path.display()))?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self::read(name, std::io::BufReader::new(file))
+            .with_context(|| format!("reading geo dataset {}", path.display()))
+    }
+
+    fn read<R: BufRead>(name: String, reader: R) -> Result<Self> {
+        let mut ranges = Self {
+            name,
+            v4: Table { ranges: Vec::new() },
+            v6: Table { ranges: Vec::new() },
+            networks: Vec::new(),
+        };
+        let mut interned: HashMap<Network, u32> = HashMap::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim_start_matches('\u{feff}').trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            let fields = split_line(line);
+            let (start, end) = match (fields[0].parse::<IpAddr>(), fields.get(1)) {
+                (Ok(start), Some(end)) => (start, end),
+                // Header
+                (Err(_), _) if index == 0 => continue,
+                _ => return Err(anyhow!("line {}: expected an IP range", index + 1)),
+            };
+            let network =
+                parse_network(&fields[2..]).with_context(|| format!("line {}", index + 1))?;
+            if network.country.is_none() && network.asn.is_none() {
+                continue;
+            }
+            let next = ranges.networks.len() as u32;
+            let id = *interned.entry(network.clone()).or_insert(next);
+            if id == next {
+                ranges.networks.push(network);
+            }
+            match (start, end.parse::<IpAddr>()) {
+                (IpAddr::V4(start), Ok(IpAddr::V4(end))) if start <= end => {
+                    ranges.v4.ranges.push((start.into(), end.into(), id))
+                }
+                (IpAddr::V6(start), Ok(IpAddr::V6(end))) if start <= end => {
+                    ranges.v6.ranges.push((start.into(), end.into(), id))
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "line {}: {} - {} is not an IP range",
+                        index + 1,
+                        start,
+                        end
+                    ))
+                }
+            }
+        }
+        ranges.v4.sort();
+        ranges.v6.sort();
+        Ok(ranges)
+    }
+
+    pub(super) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Ranges loaded, IPv4 and IPv6 together.
+    pub(super) fn len(&self) -> usize {
+        self.v4.ranges.len() + self.v6.ranges.len()
+    }
+
+    /// Ranges placed in one of `countries` (upper-case ISO codes).
+    fn in_countries<'a>(
+        &'a self,
+        countries: &'a HashSet<String>,
+    ) -> impl Iterator<Item = IpRange> + 'a {
+        let wanted = |network: u32| {
+            self.networks[network as usize]
+                .country
+                .as_ref()
+                .is_some_and(|country| countries.contains(&country.to_ascii_uppercase()))
+        };
+        let v4 = self
+            .v4
+            .ranges
+            .iter()
+            .filter(move |range| wanted(range.2))
+            .map(|&(start, end, _)| IpRange {
+                start: IpAddr::V4(start.into()),
+                end: IpAddr::V4(end.into()),
+            });
+        let v6 = self
+            .v6
+            .ranges
+            .iter()
+            .filter(move |range| wanted(range.2))
+            .map(|&(start, end, _)| IpRange {
+                start: IpAddr::V6(start.into()),
+                end: IpAddr::V6(end.into()),
+            });
+        v4.chain(v6)
+    }
+
+    /// Fill the fields of `info` this dataset knows and `info` lacks.
+    /// Returns whether the IP is in the dataset.
+    pub(super) fn fill(&self, ip: IpAddr, info: &mut IpGeoInfo) -> bool {
+        let id = match ip {
+            IpAddr::V4(ip) => self.v4.find(ip.into()),
+            IpAddr::V6(ip) => self.v6.find(ip.into()),
+        };
+        let Some(network) = id.map(|id| &self.networks[id as usize]) else {
+            return false;
+        };
+        if info.country.is_none() {
+            info.country = network.country.clone();
+        }
+        if info.asn.is_none() {
+            info.asn = network.asn.map(|asn| format!("AS{}", asn));
+        }
+        if info.isp.is_none() {
+            info.isp = network.org.clone();
+        }
+        true
+    }
+}
+
+/// Networks that `geoip_db` or any of the `geo_csv` datasets place in one of
+/// `countries` (ISO 3166-1 alpha-2 codes), for `--exclude-country`. An
+/// address is included when any source places it there. Datasets that fail
+/// to load are errors: an exclusion must not be silently dropped.
+///
+/// The last result is kept for the life of the process, so API scans reuse
+/// what startup resolved instead of walking the datasets again.
+pub fn country_ranges(
+    geoip_db: Option<&str>,
+    geo_csv: &[String],
+    countries: &[String],
+) -> Result<Vec<IpRange>> {
+    static LAST: Mutex<Option<(String, Vec<IpRange>)>> = Mutex::new(None);
+    let mut countries: Vec<String> = countries
+        .iter()
+        .map(|country| country.trim().to_ascii_uppercase())
+        .collect();
+    countries.sort_unstable();
+    countries.dedup();
+    let key = format!("{:?}", (geoip_db, geo_csv, &countries));
+    let mut last = LAST.lock().unwrap();
+    if let Some((_, ranges)) = last.as_ref().filter(|(last_key, _)| *last_key == key) {
+        return Ok(ranges.clone());
+    }
+    let ranges = resolve_countries(geoip_db, geo_csv, &countries.into_iter().collect())?;
+    *last = Some((key, ranges.clone()));
+    Ok(ranges)
+}
+
+fn resolve_countries(
+    geoip_db: Option<&str>,
+    geo_csv: &[String],
+    countries: &HashSet<String>,
+) -> Result<Vec<IpRange>> {
+    let mut ranges = Vec::new();
+    if let Some(path) = geoip_db {
+        let reader = maxminddb::Reader::open_readfile(path)
+            .with_context(|| format!("opening GeoIP database {}", path))?;
+        let iso_code = [PathElement::Key("country"), PathElement::Key("iso_code")];
+        for network in reader.networks(Default::default())? {
+            let network = network?;
+            let country: Option<String> = network.decode_path(&iso_code)?;
+            if country.is_some_and(|country| countries.contains(&country.to_ascii_uppercase())) {
+                let network = network.network()?;
+                ranges.push(IpRange {
+                    start: network.network(),
+                    end: network.broadcast(),
+                });
+            }
+        }
+    }
+    for path in geo_csv {
+        ranges.extend(GeoRanges::open(Path::new(path))?.in_countries(countries));
+    }
+    Ok(ranges)
+}
+
+fn parse_network(fields: &[String]) -> Result<Network> {
+    let text = |field: &String| {
+        let field = field.trim();
+        (!field.is_empty() && field != "None" && field != "Not routed").then(|| field.to_string())
+    };
+    let asn = |field: &String| -> Result<Option<u32>> {
+        let field = field.trim();
+        let digits = field
+            .strip_prefix("AS")
+            .or_else(|| field.strip_prefix("as"))
+            .unwrap_or(field);
+        let asn = digits
+            .parse::<u32>()
+            .map_err(|_| anyhow!("invalid ASN {:?}", field))?;
+        Ok((asn != 0).then_some(asn))
+    };
+    let network = match fields {
+        [country] => Network {
+            country: text(country),
+            asn: None,
+            org: None,
+        },
+        [number, org] => {
+            let asn = asn(number)?;
+            Network {
+                country: None,
+                org: asn.and(text(org)),
+                asn,
+            }
+        }
+        [number, country, org] => {
+            let asn = asn(number)?;
+            Network {
+                country: text(country),
+                org: asn.and(text(org)),
+                asn,
+            }
+        }
+        _ => {
+            return Err(anyhow!(
+                "expected country, asn,org or asn,country,org after the range"
+            ))
+        }
+    };
+    Ok(network)
+}
+
+/// Split on tabs when the line has any, otherwise on commas with
+/// double-quoted fields (`""` for a quote).
+fn split_line(line: &str) -> Vec<String> {
+    if line.contains('\t') {
+        return line.split('\t').map(str::to_string).collect();
+    }
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(ranges: &GeoRanges, ip: &str) -> Option<IpGeoInfo> {
+        let mut info = IpGeoInfo::new(ip.to_string(), "test".to_string());
+        ranges.fill(ip.parse().unwrap(), &mut info).then_some(info)
+    }
+
+    #[test]
+    fn test_reads_iptoasn_and_ip_location_db_layouts() {
+        let iptoasn = "1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n\
+                       1.0.1.0\t1.0.3.255\t0\tNone\tNot routed\n\
+                       1.0.4.0\t1.0.7.255\t38803\tAU\tWPL-AS-AP Wirefreebroadband Pty Ltd\n\
+                       2001:db8::\t2001:db8::ffff\t64500\tNL\tEXAMPLE\n";
+        let ranges = GeoRanges::read("ip2asn".to_string(), iptoasn.as_bytes()).unwrap();
+        assert_eq!(ranges.len(), 3);
+        let info = lookup(&ranges, "1.0.5.9").unwrap();
+        assert_eq!(info.country.as_deref(), Some("AU"));
+        assert_eq!(info.asn.as_deref(), Some("AS38803"));
+        assert_eq!(
+            info.isp.as_deref(),
+            Some("WPL-AS-AP Wirefreebroadband Pty Ltd")
+        );
+        assert_eq!(
+            lookup(&ranges, "2001:db8::10").unwrap().asn.as_deref(),
+            Some("AS64500")
+        );
+        assert!(lookup(&ranges, "1.0.2.1").is_none());
+        assert!(lookup(&ranges, "0.255.255.255").is_none());
+        assert!(lookup(&ranges, "1.0.8.0").is_none());
+
+        // Unsorted, with a header and quoted organisation names.
+        let asn =
+            "ip_range_start,ip_range_end,autonomous_system_number,autonomous_system_organization\n\
+                   192.0.2.128,192.0.2.255,64497,\"Example, Inc.\"\n\
+                   192.0.2.0,192.0.2.127,64496,Example\n";
+        let ranges = GeoRanges::read("asn".to_string(), asn.as_bytes()).unwrap();
+        let mut info = IpGeoInfo::new("192.0.2.200".to_string(), "MaxMind".to_string());
+        info.country = Some("United States".to_string());
+        assert!(ranges.fill("192.0.2.200".parse().unwrap(), &mut info));
+        assert_eq!(info.country.as_deref(), Some("United States"));
+        assert_eq!(info.isp.as_deref(), Some("Example, Inc."));
+        assert_eq!(
+            lookup(&ranges, "192.0.2.1").unwrap().asn.as_deref(),
+            Some("AS64496")
+        );
+
+        let country = "192.0.2.0,192.0.2.255,JP\n";
+        let ranges = GeoRanges::read("country".to_string(), country.as_bytes()).unwrap();
+        let info = lookup(&ranges, "192.0.2.9").unwrap();
+        assert_eq!((info.country.as_deref(), info.asn), (Some("JP"), None));
+    }
+
+    #[test]
+    fn test_country_ranges_come_from_every_dataset() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, text: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, text).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let files = [
+            write(
+                "a.csv",
+                "192.0.2.0,192.0.2.127,jp\n192.0.2.128,192.0.2.255,US\n2001:db8::,2001:db8::ff,JP\n",
+            ),
+            write("b.tsv", "198.51.100.0\t198.51.100.255\t64496\tJP\tEXAMPLE\n"),
+        ];
+        let ranges = country_ranges(None, &files, &["JP".to_string()]).unwrap();
+        let ranges: Vec<String> = ranges
+            .iter()
+            .map(|range| format!("{}-{}", range.start, range.end))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                "192.0.2.0-192.0.2.127",
+                "2001:db8::-2001:db8::ff",
+                "198.51.100.0-198.51.100.255"
+            ]
+        );
+        assert!(country_ranges(None, &files, &["DE".to_string()])
+            .unwrap()
+            .is_empty());
+        let missing = dir.path().join("missing.csv").to_str().unwrap().to_string();
+        assert!(country_ranges(None, &[missing], &["JP".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_malformed_lines() {
+        let read = |text: &str| GeoRanges::read("bad".to_string(), text.as_bytes());
+        assert!(read("192.0.2.0,192.0.2.255,JP\nnot-an-ip,x,JP\n").is_err());
+        assert!(read("192.0.2.255,192.0.2.0,JP\n").is_err());
+        assert!(read("192.0.2.0,2001:db8::,JP\n").is_err());
+        assert!(read("192.0.2.0,192.0.2.255,ASX,Example\n").is_err());
+        assert!(read("192.0.2.0,192.0.2.255\n").is_err());
+    }
+}
@@ -1,20 +1,301 @@
+use super::geo_cache::{prefix_key, GeoCache};
+use super::geo_ranges::GeoRanges;
+use super::rate_limiter::now_ms;
+use super::rdap::{RdapClient, RdapError};
+use super::{EventBus, RateLimiter, ScanEvent};
+use crate::dao::SqliteDB;
 use crate::model::IpGeoInfo;
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use futures::future::BoxFuture;
 use maxminddb::geoip2;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::{HashSet, VecDeque};
 use std::net::IpAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{debug, error, info, warn};
+use utoipa::ToSchema;
 use whois_rust::{WhoIs, WhoIsLookupOptions};
 
+/// Budget for one IP across the whole provider chain plus the PTR lookup.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(6);
+/// Completed lookups are written in batches of this size, or sooner when the
+/// backlog runs dry.
+pub(super) const SAVE_BATCH: usize = 64;
+/// Pause between passes once every known IP has been attempted.
+const IDLE_POLL: Duration = Duration::from_secs(1);
+/// How often the enrichment worker publishes its counters as `geo_stats`.
+const STATS_INTERVAL: Duration = Duration::from_secs(10);
+/// Time in-flight lookups get to finish and be saved on shutdown.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// Minimal whois server list compiled into the binary: IP lookups start at
+/// ARIN and follow its referrals to the other RIRs.
+const DEFAULT_WHOIS_SERVERS: &str = include_str!("whois_servers.json");
+
+/// External lookups remembered per IP (and per IPv4 /24 for network-level
+/// sources) so repeated lookups within a round skip the providers.
+const CACHE_CAPACITY: usize = 65_536;
+const CACHE_TTL: Duration = Duration::from_secs(3600);
+/// Failed lookups are remembered briefly so retries do not hammer providers.
+const NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// First backoff after a provider throttles or refuses us; doubles per
+/// consecutive failure up to `MAX_BACKOFF`.
+const BASE_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_secs(600);
+
+/// Request budget and backoff state for one external provider. Budgets use
+/// short windows so a lookup waiting for a token stays well inside
+/// `LOOKUP_TIMEOUT`; a provider in backoff is skipped rather than waited on.
+struct ProviderLimit {
+    name: &'static str,
+    limiter: RateLimiter,
+    backoff_until_ms: AtomicU64,
+    failures: AtomicU32,
+    /// Throttles and failed requests since startup
+    errors: AtomicU64,
+}
+
+impl ProviderLimit {
+    fn new(name: &'static str, max_requests: usize, window: Duration) -> Self {
+        Self {
+            name,
+            limiter: RateLimiter::new(max_requests, window),
+            backoff_until_ms: AtomicU64::new(0),
+            failures: AtomicU32::new(0),
+            errors: AtomicU64::new(0),
+        }
+    }
+
+    fn in_backoff(&self) -> bool {
+        now_ms() < self.backoff_until_ms.load(Ordering::Relaxed)
+    }
+
+    /// Wait for a request token, or fail fast while the provider is backing off.
+    async fn acquire(&self) -> Result<()> {
+        if self.in_backoff() {
+            return Err(anyhow!("{} is backing off", self.name));
+        }
+        self.limiter.acquire().await;
+        Ok(())
+    }
+
+    fn record_success(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+    }
+
+    /// Back off after a 429 or refusal. `retry_after` from the provider wins;
+    /// otherwise the delay grows exponentially with consecutive failures.
+    fn record_throttled(&self, retry_after: Option<Duration>) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed);
+        let delay = retry_after
+            .unwrap_or_else(|| BASE_BACKOFF.saturating_mul(1 << failures.min(5)))
+            .min(MAX_BACKOFF);
+        self.pause(delay);
+    }
+
+    /// Stop using the provider for `delay` without counting a failure.
+    fn pause(&self, delay: Duration) {
+        let now = now_ms();
+        let until = now + delay.as_millis() as u64;
+        if self.backoff_until_ms.fetch_max(until, Ordering::Relaxed) < now && !delay.is_zero() {
+            warn!("{} is throttling us; pausing it for {:?}", self.name, delay);
+        }
+    }
+}
+
+/// Publishes the first saved IP of every country not yet in `ip_details`.
+struct CountryTracker {
+    events: Option<EventBus>,
+    known: HashSet<String>,
+}
+
+impl CountryTracker {
+    fn new(db: &SqliteDB, events: Option<EventBus>) -> Self {
+        let known = match events {
+            Some(_) => db.get_known_countries().unwrap_or_else(|e| {
+                error!("Failed to load known countries: {}", e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        Self {
+            events,
+            known: known.into_iter().collect(),
+        }
+    }
+
+    fn observe(&mut self, saved: &[IpGeoInfo]) {
+        let Some(events) = &self.events else {
+            return;
+        };
+        for info in saved {
+            let Some(country) = info.country.as_deref().filter(|c| !c.is_empty()) else {
+                continue;
+            };
+            if self.known.insert(country.to_string()) {
+                events.publish(ScanEvent::NewCountry {
+                    ip: info.ip.clone(),
+                    country: country.to_string(),
+                });
+            }
+        }
+    }
+}
+
+/// A user-supplied enrichment source (internal IPAM, commercial feed, ...).
+///
+/// Registered providers are consulted in registration order ahead of the
+/// built-in MaxMind -> RDAP -> whois -> ip-api.com chain, and their results
+/// are persisted to `ip_details` like any other lookup. Implementations
+/// should set `IpGeoInfo::source` to identify themselves and must bound their
+/// own latency: the whole chain shares one per-IP timeout.
+pub trait GeoProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// `Ok(None)` means "no data for this IP"; the chain moves on. Errors are
+    /// logged and also fall through to the next provider.
+    fn lookup<'a>(&'a self, ip: &'a str) -> BoxFuture<'a, Result<Option<IpGeoInfo>>>;
+}
+
+/// Error count and backoff state of one external geo provider.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct GeoProviderStats {
+    pub name: String,
+    /// Throttled or failed requests since the worker started
+    pub errors: u64,
+    /// Skipped until its backoff ends
+    pub backing_off: bool,
+}
+
+/// Counters of the enrichment worker since it started, saved under the
+/// `geo_stats` metadata key for `/stats/prometheus` and `/geo/status`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct GeoStats {
+    /// False once the worker has shut down
+    #[serde(default)]
+    pub running: bool,
+    /// Lookups that returned geo data
+    pub found: u64,
+    pub failed: u64,
+    pub timed_out: u64,
+    pub in_flight: usize,
+    /// IPs fetched for the current pass and not looked up yet
+    pub queued: usize,
+    /// Lookups finished per second since the previous snapshot
+    pub lookups_per_sec: f64,
+    #[serde(default)]
+    pub providers: Vec<GeoProviderStats>,
+    pub updated_at: String,
+}
+
+impl GeoStats {
+    fn finished(&self) -> u64 {
+        self.found + self.failed + self.timed_out
+    }
+}
+
+/// What `/geo/status` reports: the backlog from the database and the
+/// worker's last published counters.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GeoStatus {
+    /// Distinct IPs with open ports
+    pub ips: usize,
+    /// IPs still waiting for a geo lookup (new, or older than a geo refresh)
+    pub missing: usize,
+    /// Whether a worker published counters within the last 30 seconds
+    pub active: bool,
+    /// Seconds to clear `missing` at the current lookup rate; `None` when
+    /// no worker is active or it has not finished a lookup recently
+    pub eta_secs: Option<u64>,
+    /// Last counters a worker published, if one ever ran on this database
+    pub worker: Option<GeoStats>,
+}
+
+impl GeoStatus {
+    pub fn collect(db: &SqliteDB) -> Result<Self> {
+        let (ips, missing) = db.count_geo_backlog()?;
+        let worker: Option<GeoStats> = db
+            .get_metadata("geo_stats")?
+            .and_then(|json| serde_json::from_str(&json).ok());
+        let active = worker.as_ref().is_some_and(|stats| {
+            stats.running
+                && chrono::DateTime::parse_from_rfc3339(&stats.updated_at).is_ok_and(|at| {
+                    chrono::Utc::now().signed_duration_since(at)
+                        < chrono::Duration::from_std(STATS_INTERVAL * 3).unwrap_or_default()
+                })
+        });
+        let eta_secs = match &worker {
+            _ if missing == 0 => Some(0),
+            Some(stats) if active && stats.lookups_per_sec > 0.0 => {
+                Some((missing as f64 / stats.lookups_per_sec).ceil() as u64)
+            }
+            _ => None,
+        };
+        Ok(Self {
+            ips,
+            missing,
+            active,
+            eta_secs,
+            worker,
+        })
+    }
+}
+
+pub(super) enum Lookup {
+    Found(Box<IpGeoInfo>),
+    Failed,
+    TimedOut,
+}
+
+/// The running enrichment worker. It is not tied to scan rounds: it polls
+/// for open ports missing geo data until [`GeoEnrichment::shutdown`].
+pub struct GeoEnrichment {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl GeoEnrichment {
+    /// Stop taking new IPs and give in-flight lookups `SHUTDOWN_GRACE` to
+    /// finish and be saved before abandoning them.
+    pub async fn shutdown(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if tokio::time::timeout(SHUTDOWN_GRACE, &mut self.handle)
+            .await
+            .is_err()
+        {
+            warn!(
+                "Geo enrichment did not stop within {}s; abandoning in-flight lookups",
+                SHUTDOWN_GRACE.as_secs()
+            );
+            self.handle.abort();
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct GeoService {
+    providers: Vec<Arc<dyn GeoProvider>>,
     reader: Option<Arc<maxminddb::Reader<Vec<u8>>>>,
+    /// `--geo-csv` datasets, in the order given
+    ranges: Arc<Vec<GeoRanges>>,
     whois: Option<Arc<WhoIs>>,
+    rdap: Option<Arc<RdapClient>>,
+    rdap_limit: Arc<ProviderLimit>,
+    whois_limit: Arc<ProviderLimit>,
+    api_limit: Arc<ProviderLimit>,
+    cache: Arc<GeoCache>,
 }
 
 impl GeoService {
-    pub fn new(db_path: Option<&str>) -> Self {
+    pub fn new(db_path: Option<&str>, whois_servers: Option<&str>) -> Self {
         let reader = db_path.and_then(|path| match maxminddb::Reader::open_readfile(path) {
             Ok(reader) => Some(Arc::new(reader)),
             Err(e) => {
@@ -23,15 +304,252 @@ impl GeoService {
             }
         });
 
-        let whois = match WhoIs::from_string(include_str!("../../servers.json")) {
-            Ok(w) => Some(Arc::new(w)),
-            Err(_) => {
-                eprintln!("Warning: No servers.json found for Whois. Whois lookup might fail.");
+        let whois = Self::load_whois(whois_servers).map(Arc::new);
+
+        let rdap = match RdapClient::new() {
+            Ok(client) => Some(Arc::new(client)),
+            Err(e) => {
+                eprintln!("Warning: RDAP client unavailable: {}", e);
+                None
+            }
+        };
+
+        Self {
+            providers: Vec::new(),
+            reader,
+            ranges: Arc::new(Vec::new()),
+            whois,
+            rdap,
+            // RIR RDAP services tolerate a few queries per second per client.
+            rdap_limit: Arc::new(ProviderLimit::new("rdap", 2, Duration::from_secs(1))),
+            // Registry whois servers throttle aggressively: ~1 query / 2 s.
+            whois_limit: Arc::new(ProviderLimit::new("whois", 1, Duration::from_secs(2))),
+            // ip-api.com free tier allows 45 requests/minute.
+            api_limit: Arc::new(ProviderLimit::new("ip-api.com", 3, Duration::from_secs(4))),
+            cache: Arc::new(GeoCache::new(CACHE_CAPACITY)),
+        }
+    }
+
+    /// Load `--geo-csv` range datasets. They answer IPs MaxMind does not
+    /// know without network lookups, and fill in the ASN and organisation
+    /// of MaxMind answers; earlier files win where datasets overlap. A file
+    /// that fails to load is skipped with a warning.
+    pub fn with_range_datasets(mut self, paths: &[String]) -> Self {
+        let ranges = paths
+            .iter()
+            .filter_map(|path| match GeoRanges::open(Path::new(path)) {
+                Ok(ranges) => {
+                    info!("Loaded {} ranges from {}", ranges.len(), path);
+                    Some(ranges)
+                }
+                Err(e) => {
+                    warn!("Skipping geo dataset: {:#}", e);
+                    None
+                }
+            })
+            .collect();
+        self.ranges = Arc::new(ranges);
+        self
+    }
+
+    /// Add a custom provider after any previously registered ones.
+    #[allow(dead_code)]
+    pub fn register_provider(&mut self, provider: Arc<dyn GeoProvider>) {
+        self.providers.push(provider);
+    }
+
+    /// Load the whois server list from `path`, falling back to the embedded
+    /// default when it is unset or unusable.
+    fn load_whois(path: Option<&str>) -> Option<WhoIs> {
+        if let Some(path) = path {
+            match std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| WhoIs::from_string(content).map_err(|e| e.to_string()))
+            {
+                Ok(whois) => return Some(whois),
+                Err(e) => eprintln!(
+                    "Warning: Failed to load whois server list {}: {}; using the built-in list",
+                    path, e
+                ),
+            }
+        }
+        match WhoIs::from_string(DEFAULT_WHOIS_SERVERS) {
+            Ok(whois) => Some(whois),
+            Err(e) => {
+                eprintln!("Warning: Built-in whois server list is invalid: {}", e);
                 None
             }
+        }
+    }
+
+    /// Spawn the background enrichment pool. It keeps up to `concurrency`
+    /// lookups in flight, continuously paging through `get_ips_missing_geo`,
+    /// and exits after draining in-flight lookups once shut down. With
+    /// `events`, the first IP saved for each country publishes a
+    /// [`ScanEvent::NewCountry`].
+    pub fn spawn_enrichment_worker(
+        &self,
+        db: SqliteDB,
+        concurrency: usize,
+        events: Option<EventBus>,
+    ) -> GeoEnrichment {
+        let geo = self.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker_stop = stop.clone();
+        let handle = tokio::spawn(async move {
+            geo.run_enrichment(db, concurrency.max(1), worker_stop, events)
+                .await
+        });
+        GeoEnrichment { stop, handle }
+    }
+
+    async fn run_enrichment(
+        self,
+        db: SqliteDB,
+        concurrency: usize,
+        stop: Arc<AtomicBool>,
+        events: Option<EventBus>,
+    ) {
+        let mut countries = CountryTracker::new(&db, events);
+        let mut cursor = String::new();
+        let mut exhausted = false;
+        let mut queue = VecDeque::new();
+        let mut in_flight: JoinSet<Lookup> = JoinSet::new();
+        let mut pending = Vec::with_capacity(SAVE_BATCH);
+        let mut stats = GeoStats {
+            running: true,
+            ..Default::default()
         };
+        let mut published = (Instant::now(), 0);
+
+        loop {
+            let stopping = stop.load(Ordering::Relaxed);
+            if !stopping {
+                if !exhausted && queue.len() < concurrency {
+                    match db.get_ips_missing_geo(&cursor, concurrency * 4) {
+                        Ok(ips) => match ips.last() {
+                            Some(last) => {
+                                cursor = last.clone();
+                                queue.extend(ips);
+                            }
+                            None => exhausted = true,
+                        },
+                        Err(e) => {
+                            error!("Failed to load IPs missing geo data: {}", e);
+                            exhausted = true;
+                        }
+                    }
+                }
+                while in_flight.len() < concurrency {
+                    let Some(ip) = queue.pop_front() else {
+                        break;
+                    };
+                    let geo = self.clone();
+                    in_flight.spawn(async move { geo.lookup_bounded(&ip).await });
+                }
+            }
+
+            stats.in_flight = in_flight.len();
+            stats.queued = queue.len();
+            if published.0.elapsed() >= STATS_INTERVAL {
+                stats.providers = self.provider_stats();
+                Self::publish_stats(&db, &mut stats, &mut published);
+            }
+
+            if in_flight.is_empty() {
+                Self::flush_geo_batch(&db, &mut pending, &mut countries);
+                if stopping {
+                    break;
+                }
+                // Pass complete: wait for new open ports, then start over from
+                // the lowest IP so earlier failures get another attempt.
+                tokio::time::sleep(IDLE_POLL).await;
+                cursor.clear();
+                exhausted = false;
+                continue;
+            }
+
+            match in_flight.join_next().await {
+                Some(Ok(Lookup::Found(info))) => {
+                    stats.found += 1;
+                    pending.push(*info);
+                }
+                Some(Ok(Lookup::TimedOut)) => stats.timed_out += 1,
+                Some(Ok(Lookup::Failed)) | Some(Err(_)) => stats.failed += 1,
+                None => {}
+            }
+            if pending.len() >= SAVE_BATCH {
+                Self::flush_geo_batch(&db, &mut pending, &mut countries);
+            }
+        }
+        stats.running = false;
+        stats.in_flight = 0;
+        stats.queued = 0;
+        stats.providers = self.provider_stats();
+        Self::publish_stats(&db, &mut stats, &mut published);
+    }
 
-        Self { reader, whois }
+    /// [`GeoService::lookup`] within `LOOKUP_TIMEOUT`.
+    pub(super) async fn lookup_bounded(&self, ip: &str) -> Lookup {
+        match tokio::time::timeout(LOOKUP_TIMEOUT, self.lookup(ip)).await {
+            Ok(Ok(info)) => Lookup::Found(Box::new(info)),
+            Ok(Err(e)) => {
+                debug!("Geo lookup for {} failed: {}", ip, e);
+                Lookup::Failed
+            }
+            Err(_) => {
+                debug!("Geo lookup for {} timed out", ip);
+                Lookup::TimedOut
+            }
+        }
+    }
+
+    /// Error counts of the external providers this service can use.
+    fn provider_stats(&self) -> Vec<GeoProviderStats> {
+        [
+            self.rdap.as_ref().map(|_| &self.rdap_limit),
+            self.whois.as_ref().map(|_| &self.whois_limit),
+            Some(&self.api_limit),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|limit| GeoProviderStats {
+            name: limit.name.to_string(),
+            errors: limit.errors.load(Ordering::Relaxed),
+            backing_off: limit.in_backoff(),
+        })
+        .collect()
+    }
+
+    /// Save `stats` with the lookup rate since the last snapshot, `published`
+    /// holding when that was and how many lookups had finished by then.
+    fn publish_stats(db: &SqliteDB, stats: &mut GeoStats, published: &mut (Instant, u64)) {
+        let secs = published.0.elapsed().as_secs_f64();
+        if secs > 0.0 {
+            stats.lookups_per_sec = (stats.finished() - published.1) as f64 / secs;
+        }
+        stats.updated_at = chrono::Utc::now().to_rfc3339();
+        *published = (Instant::now(), stats.finished());
+        match serde_json::to_string(stats) {
+            Ok(json) => {
+                if let Err(e) = db.save_metadata("geo_stats", &json) {
+                    error!("Failed to save geo_stats: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to encode geo_stats: {}", e),
+        }
+    }
+
+    fn flush_geo_batch(
+        db: &SqliteDB,
+        pending: &mut Vec<IpGeoInfo>,
+        countries: &mut CountryTracker,
+    ) {
+        match db.save_ip_geo_info_batch(pending) {
+            Ok(_) => countries.observe(pending),
+            Err(e) => error!("Failed to save geo data: {}", e),
+        }
+        pending.clear();
     }
 
     pub async fn lookup(&self, ip: &str) -> Result<IpGeoInfo> {
@@ -44,6 +562,14 @@ impl GeoService {
     }
 
     async fn lookup_geo_only(&self, ip: &str) -> Result<IpGeoInfo> {
+        for provider in &self.providers {
+            match provider.lookup(ip).await {
+                Ok(Some(info)) => return Ok(info),
+                Ok(None) => {}
+                Err(e) => debug!("Geo provider {} failed for {}: {}", provider.name(), ip, e),
+            }
+        }
+
         if let Some(reader) = &self.reader {
             if let Ok(addr) = ip.parse::<IpAddr>() {
                 let lookup_result = reader.lookup(addr);
@@ -64,6 +590,9 @@ impl GeoService {
                             if !city.city.names.is_empty() {
                                 info.city = city.city.names.english.map(|s: &str| s.to_string());
                             }
+                            // City databases carry no ASN; take it from the
+                            // range datasets.
+                            self.fill_from_ranges(addr, &mut info);
 
                             return Ok(info);
                         }
@@ -72,13 +601,95 @@ impl GeoService {
             }
         }
 
-        if let Some(whois) = &self.whois {
-            if let Ok(info) = Self::fetch_from_whois(whois, ip).await {
+        if let Ok(addr) = ip.parse::<IpAddr>() {
+            let mut info = IpGeoInfo::new(ip.to_string(), "CSV".to_string());
+            if self.fill_from_ranges(addr, &mut info) {
                 return Ok(info);
             }
         }
 
-        Self::fetch_from_api(ip).await
+        if let Some(cached) = self.cache.get(ip) {
+            return cached.ok_or_else(|| anyhow!("Geo lookup for {} failed recently", ip));
+        }
+        let prefix = prefix_key(ip);
+        if let Some(Some(mut info)) = prefix.as_deref().and_then(|key| self.cache.get(key)) {
+            info.ip = ip.to_string();
+            return Ok(info);
+        }
+
+        let result = self.lookup_external(ip).await;
+        match &result {
+            Ok(info) => {
+                self.cache
+                    .put(ip.to_string(), Some(info.clone()), CACHE_TTL);
+                // Whois text may carry host-specific details; RDAP and ip-api
+                // answers describe the surrounding network.
+                if info.source != "Whois" {
+                    if let Some(prefix) = prefix {
+                        self.cache.put(prefix, Some(info.clone()), CACHE_TTL);
+                    }
+                }
+            }
+            Err(_) => self.cache.put(ip.to_string(), None, NEGATIVE_CACHE_TTL),
+        }
+        result
+    }
+
+    /// Fill `info` from every range dataset holding `ip`. Returns whether
+    /// any did.
+    fn fill_from_ranges(&self, ip: IpAddr, info: &mut IpGeoInfo) -> bool {
+        let mut found = false;
+        for ranges in self.ranges.iter() {
+            if ranges.fill(ip, info) {
+                debug!("{} found in {}", ip, ranges.name());
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// The remote fallback chain, without caching.
+    async fn lookup_external(&self, ip: &str) -> Result<IpGeoInfo> {
+        // RDAP returns structured registry data, so prefer it over parsing
+        // free-form whois text.
+        if let Some(rdap) = &self.rdap {
+            if self.rdap_limit.acquire().await.is_ok() {
+                match rdap.lookup(ip).await {
+                    Ok(info) => {
+                        self.rdap_limit.record_success();
+                        if info.country.is_some() || info.isp.is_some() {
+                            return Ok(info);
+                        }
+                    }
+                    Err(RdapError::Throttled(retry_after)) => {
+                        self.rdap_limit.record_throttled(retry_after)
+                    }
+                    Err(RdapError::Unavailable(e)) => {
+                        debug!("RDAP lookup for {} failed: {}", ip, e);
+                        self.rdap_limit.record_throttled(None);
+                    }
+                    Err(RdapError::NoData(e)) => {
+                        debug!("RDAP has no data for {}: {}", ip, e);
+                    }
+                }
+            }
+        }
+
+        if let Some(whois) = &self.whois {
+            if self.whois_limit.acquire().await.is_ok() {
+                match Self::fetch_from_whois(whois, ip).await {
+                    Ok(info) => {
+                        self.whois_limit.record_success();
+                        return Ok(info);
+                    }
+                    // Lookups only fail on connection errors or refusals.
+                    Err(_) => self.whois_limit.record_throttled(None),
+                }
+            }
+        }
+
+        self.api_limit.acquire().await?;
+        self.fetch_from_api(ip).await
     }
 
     async fn fetch_from_whois(whois: &WhoIs, ip: &str) -> Result<IpGeoInfo> {
@@ -110,19 +721,46 @@ impl GeoService {
             info.asn = Some(caps[1].trim().to_string());
         }
 
+        info.abuse_email = parse_whois_abuse_email(&text);
+
         Ok(info)
     }
 
-    async fn fetch_from_api(ip: &str) -> Result<IpGeoInfo> {
+    async fn fetch_from_api(&self, ip: &str) -> Result<IpGeoInfo> {
         let url = format!("http://ip-api.com/json/{}", ip);
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(5))
             .build()?;
-        let resp = client
-            .get(&url)
-            .send()
-            .await
-            .context("Failed to call IP API")?
+        let response = match client.get(&url).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                self.api_limit.record_throttled(None);
+                return Err(e).context("Failed to call IP API");
+            }
+        };
+
+        // ip-api reports the remaining quota (X-Rl) and seconds until it
+        // resets (X-Ttl) on every response.
+        let header_u64 = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+        };
+        let reset = header_u64("X-Ttl").map(Duration::from_secs);
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            self.api_limit.record_throttled(reset);
+            return Err(anyhow!("IP API rate limit exceeded"));
+        }
+        self.api_limit.record_success();
+        if header_u64("X-Rl") == Some(0) {
+            // This request used up the quota; pause until it resets.
+            self.api_limit
+                .pause(reset.unwrap_or(BASE_BACKOFF).min(MAX_BACKOFF));
+        }
+
+        let resp = response
             .json::<Value>()
             .await
             .context("Failed to parse API response")?;
@@ -141,14 +779,216 @@ impl GeoService {
     }
 }
 
+/// Abuse mailbox from whois text: ARIN `OrgAbuseEmail:`, RPSL
+/// `abuse-mailbox:` (RIPE/APNIC/AFRINIC/LACNIC), or RIPE's
+/// `% Abuse contact for '...' is '...'` comment.
+fn parse_whois_abuse_email(text: &str) -> Option<String> {
+    let re_abuse = Regex::new(
+        r"(?mi)^(?:OrgAbuseEmail|abuse-mailbox):\s*(\S+@\S+)|abuse contact for .*? is '([^'\s]+@[^'\s]+)'",
+    )
+    .unwrap();
+    let caps = re_abuse.captures(text)?;
+    caps.get(1)
+        .or_else(|| caps.get(2))
+        .map(|m| m.as_str().to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    struct StaticProvider {
+        name: &'static str,
+        result: Option<&'static str>,
+    }
+
+    impl GeoProvider for StaticProvider {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn lookup<'a>(&'a self, ip: &'a str) -> BoxFuture<'a, Result<Option<IpGeoInfo>>> {
+            Box::pin(async move {
+                match self.result {
+                    Some("error") => Err(anyhow!("provider down")),
+                    Some(country) => {
+                        let mut info = IpGeoInfo::new(ip.to_string(), self.name.to_string());
+                        info.country = Some(country.to_string());
+                        Ok(Some(info))
+                    }
+                    None => Ok(None),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_providers_run_first_and_fall_through_in_order() {
+        let mut service = GeoService::new(None, None);
+        for (name, result) in [
+            ("down", Some("error")),
+            ("empty", None),
+            ("ipam", Some("NL")),
+        ] {
+            service.register_provider(Arc::new(StaticProvider { name, result }));
+        }
+
+        let info = service.lookup_geo_only("192.0.2.1").await.unwrap();
+        assert_eq!(info.source, "ipam");
+        assert_eq!(info.country.as_deref(), Some("NL"));
+    }
+
+    #[tokio::test]
+    async fn test_range_datasets_answer_offline_and_combine() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, text: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, text).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let country = write("country.csv", "192.0.2.0,192.0.2.255,JP\n");
+        let asn = write(
+            "asn.tsv",
+            "192.0.2.0\t192.0.2.127\t64496\tUS\tEXAMPLE\n198.51.100.0\t198.51.100.255\t64497\tDE\tOTHER\n",
+        );
+        let missing = dir.path().join("missing.csv").to_str().unwrap().to_string();
+        let service = GeoService::new(None, None).with_range_datasets(&[country, missing, asn]);
+        assert_eq!(service.ranges.len(), 2);
+
+        let info = service.lookup_geo_only("192.0.2.1").await.unwrap();
+        assert_eq!(info.source, "CSV");
+        // The first dataset wins the country; the second adds the ASN.
+        assert_eq!(info.country.as_deref(), Some("JP"));
+        assert_eq!(info.asn.as_deref(), Some("AS64496"));
+        assert_eq!(info.isp.as_deref(), Some("EXAMPLE"));
+        let info = service.lookup_geo_only("198.51.100.7").await.unwrap();
+        assert_eq!(info.country.as_deref(), Some("DE"));
+    }
+
+    #[tokio::test]
+    async fn test_enrichment_worker_saves_lookups_and_publishes_stats() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(vec![("127.0.0.1".parse().unwrap(), 22, true)], 1)
+            .unwrap();
+        let mut service = GeoService::new(None, None);
+        service.register_provider(Arc::new(StaticProvider {
+            name: "ipam",
+            result: Some("NL"),
+        }));
+
+        let worker = service.spawn_enrichment_worker(db.clone(), 2, None);
+        for _ in 0..100 {
+            if db.get_ip_geo_info("127.0.0.1").unwrap().is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        worker.shutdown().await;
+
+        let info = db.get_ip_geo_info("127.0.0.1").unwrap().unwrap();
+        assert_eq!(info.country.as_deref(), Some("NL"));
+        let stats: GeoStats =
+            serde_json::from_str(&db.get_metadata("geo_stats").unwrap().unwrap()).unwrap();
+        assert_eq!((stats.found, stats.failed, stats.timed_out), (1, 0, 0));
+        assert_eq!((stats.in_flight, stats.queued), (0, 0));
+        assert!(!stats.running);
+        assert!(stats.providers.iter().any(|p| p.name == "ip-api.com"));
+    }
+
+    #[test]
+    fn test_geo_status_reports_backlog_and_eta() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let open = (1..=5)
+            .map(|i| (IpAddr::from([192, 0, 2, i]), 443, true))
+            .collect();
+        db.bulk_update_port_status(open, 1).unwrap();
+        db.save_ip_geo_info_batch(&[IpGeoInfo::new("192.0.2.1".to_string(), "test".to_string())])
+            .unwrap();
+
+        let status = GeoStatus::collect(&db).unwrap();
+        assert_eq!((status.ips, status.missing), (5, 4));
+        assert!(!status.active && status.worker.is_none() && status.eta_secs.is_none());
+
+        let mut stats = GeoStats {
+            running: true,
+            lookups_per_sec: 0.5,
+            updated_at: chrono::Utc::now().to_rfc3339(),
+            ..Default::default()
+        };
+        db.save_metadata("geo_stats", &serde_json::to_string(&stats).unwrap())
+            .unwrap();
+        let status = GeoStatus::collect(&db).unwrap();
+        assert!(status.active);
+        assert_eq!(status.eta_secs, Some(8));
+
+        // A worker that stopped publishing a minute ago is not active.
+        stats.updated_at = (chrono::Utc::now() - chrono::Duration::seconds(60)).to_rfc3339();
+        db.save_metadata("geo_stats", &serde_json::to_string(&stats).unwrap())
+            .unwrap();
+        let status = GeoStatus::collect(&db).unwrap();
+        assert!(!status.active && status.eta_secs.is_none());
+        assert!(status.worker.is_some());
+    }
+
+    #[test]
+    fn test_parse_whois_abuse_email() {
+        let arin = "NetRange: 8.8.8.0 - 8.8.8.255\nOrgAbuseEmail:  network-abuse@google.com\n";
+        assert_eq!(
+            parse_whois_abuse_email(arin).as_deref(),
+            Some("network-abuse@google.com")
+        );
+        let ripe = "% Abuse contact for '193.0.0.0 - 193.0.7.255' is 'abuse@ripe.net'\n";
+        assert_eq!(
+            parse_whois_abuse_email(ripe).as_deref(),
+            Some("abuse@ripe.net")
+        );
+        let apnic = "irt: IRT-EXAMPLE\nabuse-mailbox:  abuse@example.net\n";
+        assert_eq!(
+            parse_whois_abuse_email(apnic).as_deref(),
+            Some("abuse@example.net")
+        );
+        assert_eq!(parse_whois_abuse_email("OrgName: Example\n"), None);
+    }
+
+    #[test]
+    fn test_builtin_whois_servers_cover_ip_lookups() {
+        let servers: Value = serde_json::from_str(DEFAULT_WHOIS_SERVERS).unwrap();
+        assert!(servers["_"]["ip"]["host"].is_string());
+        assert!(GeoService::load_whois(Some("/nonexistent/servers.json")).is_some());
+    }
+
+    #[test]
+    fn test_provider_backoff_grows_and_honours_retry_after() {
+        let limit = ProviderLimit::new("test", 1, Duration::from_secs(1));
+        assert!(!limit.in_backoff());
+
+        limit.record_throttled(None);
+        let first = limit.backoff_until_ms.load(Ordering::Relaxed) - now_ms();
+        assert!(limit.in_backoff());
+        limit.record_throttled(None);
+        let second = limit.backoff_until_ms.load(Ordering::Relaxed) - now_ms();
+        assert!(second > first + 20_000, "{} vs {}", second, first);
+
+        let limit = ProviderLimit::new("test", 1, Duration::from_secs(1));
+        limit.record_throttled(Some(Duration::ZERO));
+        assert!(!limit.in_backoff());
+        limit.record_success();
+        assert_eq!(limit.failures.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_provider_in_backoff_fails_fast() {
+        let limit = ProviderLimit::new("test", 1, Duration::from_secs(60));
+        limit.record_throttled(Some(Duration::from_secs(60)));
+        let start = std::time::Instant::now();
+        assert!(limit.acquire().await.is_err());
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_api_lookup() {
-        let service = GeoService::new(None);
+        let service = GeoService::new(None, None);
         let result = service.lookup("8.8.8.8").await;
 
         match result {
@@ -0,0 +1,229 @@
+//! `ip-scan import`: load results exported by another instance.
+//!
+//! The CSV layout is the one `/api/v1/export/csv` writes. Columns are found
+//! by header name, so `ip_address`, `port`, `scan_round`, `first_seen` and
+//! `last_seen` are required (`ip` and `round` are accepted too), `ip_type`,
+//! `closed_at` and `scan_id` are read when present, and derived columns
+//! such as `cves` or `reputation_score` are ignored; they come back once the
+//! enrichment workers look the hosts up again.
+
+use crate::dao::ImportedResult;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use std::io::BufRead;
+use std::net::IpAddr;
+
+/// Column positions of one CSV file.
+struct Columns {
+    ip: usize,
+    ip_type: Option<usize>,
+    port: usize,
+    round: usize,
+    first_seen: usize,
+    last_seen: usize,
+    closed_at: Option<usize>,
+    scan_id: Option<usize>,
+}
+
+impl Columns {
+    fn from_header(header: &[String]) -> Result<Self> {
+        let find = |names: &[&str]| {
+            header
+                .iter()
+                .position(|column| names.contains(&column.trim().to_ascii_lowercase().as_str()))
+        };
+        let require = |names: &[&str]| {
+            find(names).ok_or_else(|| anyhow!("CSV header has no {} column", names[0]))
+        };
+        Ok(Self {
+            ip: require(&["ip_address", "ip"])?,
+            ip_type: find(&["ip_type"]),
+            port: require(&["port"])?,
+            round: require(&["scan_round", "round"])?,
+            first_seen: require(&["first_seen"])?,
+            last_seen: require(&["last_seen"])?,
+            closed_at: find(&["closed_at"]),
+            scan_id: find(&["scan_id"]),
+        })
+    }
+
+    fn parse(&self, fields: &[String]) -> Result<ImportedResult> {
+        let field = |index: usize| {
+            fields
+                .get(index)
+                .map(|value| value.trim())
+                .ok_or_else(|| anyhow!("expected at least {} fields", index + 1))
+        };
+        let optional = |index: Option<usize>| {
+            index
+                .and_then(|index| fields.get(index))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+
+        let ip: IpAddr = field(self.ip)?
+            .parse()
+            .map_err(|_| anyhow!("invalid IP address {:?}", field(self.ip).unwrap_or("")))?;
+        let ip_type = match optional(self.ip_type) {
+            Some(ip_type @ ("IPv4" | "IPv6")) => ip_type.to_string(),
+            Some(other) => return Err(anyhow!("invalid ip_type {:?}", other)),
+            None if ip.is_ipv4() => "IPv4".to_string(),
+            None => "IPv6".to_string(),
+        };
+        let port = field(self.port)?
+            .parse::<u16>()
+            .ok()
+            .filter(|port| *port > 0)
+            .ok_or_else(|| anyhow!("invalid port {:?}", field(self.port).unwrap_or("")))?;
+        let scan_round = field(self.round)?
+            .parse::<i64>()
+            .ok()
+            .filter(|round| *round > 0)
+            .ok_or_else(|| anyhow!("invalid round {:?}", field(self.round).unwrap_or("")))?;
+        Ok(ImportedResult {
+            ip_address: ip.to_string(),
+            ip_type,
+            port,
+            scan_round,
+            first_seen: timestamp(field(self.first_seen)?)?,
+            last_seen: timestamp(field(self.last_seen)?)?,
+            closed_at: optional(self.closed_at).map(timestamp).transpose()?,
+            scan_id: optional(self.scan_id).map(str::to_string),
+        })
+    }
+}
+
+/// Normalize an RFC 3339 timestamp to UTC, so imported times compare as
+/// text against the stored ones.
+fn timestamp(value: &str) -> Result<String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc).to_rfc3339())
+        .map_err(|_| anyhow!("invalid timestamp {:?}", value))
+}
+
+/// Split one CSV line. Fields may be double-quoted, with `""` for a quote,
+/// as spreadsheet programs write them; quoted line breaks are not supported.
+fn split_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Rows of a results CSV, each tagged with its line number on error. Blank
+/// lines are skipped.
+pub fn read_results_csv<R: BufRead>(
+    reader: R,
+) -> Result<impl Iterator<Item = Result<ImportedResult>>> {
+    let mut lines = reader.lines().enumerate();
+    let header = loop {
+        match lines.next() {
+            Some((_, line)) => {
+                let line = line?;
+                let line = line.trim_start_matches('\u{feff}');
+                if !line.trim().is_empty() {
+                    break split_line(line);
+                }
+            }
+            None => return Err(anyhow!("CSV file is empty")),
+        }
+    };
+    let columns = Columns::from_header(&header)?;
+    Ok(lines.filter_map(move |(index, line)| {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e.into())),
+        };
+        if line.trim().is_empty() {
+            return None;
+        }
+        Some(
+            columns
+                .parse(&split_line(&line))
+                .with_context(|| format!("line {}", index + 1)),
+        )
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dao::SqliteDB;
+
+    const EXPORT: &str = "\
+ip_address,ip_type,port,scan_round,first_seen,last_seen,closed_at,scan_id,cves,reputation_score,known_scanner
+192.0.2.1,IPv4,22,3,2026-01-01T00:00:00+00:00,2026-01-03T00:00:00+00:00,,scan_1,,,false
+192.0.2.2,IPv4,80,2,2026-01-01T00:00:00+00:00,2026-01-02T00:00:00+00:00,2026-01-04T00:00:00+00:00,,CVE-2024-0001,90,true
+
+\"2001:db8::1\",IPv6,443,3,2026-01-02T08:00:00+08:00,2026-01-03T00:00:00Z,,,,,false
+";
+
+    #[test]
+    fn test_import_round_trips_the_csv_export_and_merges_conflicts() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(vec![("192.0.2.1".parse().unwrap(), 22, true)], 1)
+            .unwrap();
+
+        let rows = read_results_csv(EXPORT.as_bytes()).unwrap();
+        let summary = db.import_results(rows).unwrap();
+        assert_eq!(summary.results, 3);
+        // Only the active IPv4 row lands in a bitmap.
+        assert_eq!(summary.bitmaps, 1);
+        assert_eq!(db.get_current_round().unwrap(), 3);
+
+        let existing = &db.get_results_by_ip("192.0.2.1").unwrap()[0];
+        assert_eq!(existing.first_seen, "2026-01-01T00:00:00+00:00");
+        assert_eq!(existing.scan_round, 3);
+        assert_eq!(existing.scan_id.as_deref(), Some("scan_1"));
+        let gone = &db.get_results_by_ip("192.0.2.2").unwrap()[0];
+        assert_eq!(gone.closed_at.as_deref(), Some("2026-01-04T00:00:00+00:00"));
+        let v6 = &db.get_results_by_ip("2001:db8::1").unwrap()[0];
+        assert_eq!(v6.ip_type, "IPv6");
+        assert_eq!(v6.first_seen, "2026-01-02T00:00:00+00:00");
+        let (_, total) = db
+            .get_scan_results(
+                1,
+                10,
+                None,
+                Some(22),
+                Some(3),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(total, 1);
+    }
+
+    #[test]
+    fn test_import_rejects_bad_rows_without_writing() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        let csv = "ip,port,round,first_seen,last_seen\n\
+                   192.0.2.1,22,1,2026-01-01T00:00:00Z,2026-01-01T00:00:00Z\n\
+                   192.0.2.2,0,1,2026-01-01T00:00:00Z,2026-01-01T00:00:00Z\n";
+        let err = db
+            .import_results(read_results_csv(csv.as_bytes()).unwrap())
+            .unwrap_err();
+        assert_eq!(format!("{:#}", err), "line 3: invalid port \"0\"");
+        assert!(db.get_results_by_ip("192.0.2.1").unwrap().is_empty());
+
+        assert!(read_results_csv("ip_address,port\n".as_bytes()).is_err());
+        assert!(read_results_csv("".as_bytes()).is_err());
+    }
+}
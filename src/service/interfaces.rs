@@ -0,0 +1,267 @@
+//! `ip-scan interfaces`: the network interfaces SYN scans could use, with
+//! their addresses, whether a datalink channel opens on them (it needs root,
+//! or Npcap and an elevated prompt on Windows) and the default gateway.
+//! SYN scans send through the interface holding the default route; on
+//! Windows they also need the gateway's MAC address from the ARP cache.
+
+use anyhow::Result;
+use comfy_table::presets::ASCII_MARKDOWN;
+use comfy_table::Table;
+use pnet_datalink::{self as datalink, NetworkInterface};
+use serde::Serialize;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Gateway {
+    pub ip: Ipv4Addr,
+    /// Interface the default route leaves through
+    pub interface: String,
+    /// MAC address of the gateway, `None` when not in the ARP cache
+    pub mac: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InterfaceInfo {
+    pub name: String,
+    /// Adapter description (Npcap device names are GUIDs)
+    pub description: String,
+    pub mac: Option<String>,
+    /// Addresses with prefix length, e.g. `192.0.2.10/24`
+    pub ips: Vec<String>,
+    pub up: bool,
+    pub loopback: bool,
+    /// Whether the default route leaves through this interface
+    pub default_route: bool,
+    /// Error opening a datalink channel, `None` when it opened
+    pub datalink_error: Option<String>,
+    pub syn_ready: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InterfaceReport {
+    pub gateway: Option<Gateway>,
+    pub interfaces: Vec<InterfaceInfo>,
+}
+
+impl InterfaceReport {
+    /// Read the interfaces and try a datalink channel on each.
+    pub fn collect() -> Self {
+        let gateway = default_gateway();
+        let interfaces = datalink::interfaces()
+            .into_iter()
+            .map(|iface| describe(&iface, gateway.as_ref(), open_datalink(&iface)))
+            .collect();
+        Self {
+            gateway,
+            interfaces,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut table = Table::new();
+        table.load_preset(ASCII_MARKDOWN).set_header(vec![
+            "Interface",
+            "MAC",
+            "Addresses",
+            "State",
+            "Datalink",
+            "SYN",
+        ]);
+        for iface in &self.interfaces {
+            let mut name = iface.name.clone();
+            if !iface.description.is_empty() && iface.description != iface.name {
+                name = format!("{}\n{}", name, iface.description);
+            }
+            let mut state = vec![if iface.up { "up" } else { "down" }];
+            if iface.loopback {
+                state.push("loopback");
+            }
+            if iface.default_route {
+                state.push("default route");
+            }
+            table.add_row(vec![
+                name,
+                iface.mac.clone().unwrap_or_else(|| "-".to_string()),
+                iface.ips.join("\n"),
+                state.join(", "),
+                iface
+                    .datalink_error
+                    .clone()
+                    .unwrap_or_else(|| "ok".to_string()),
+                if iface.syn_ready { "yes" } else { "no" }.to_string(),
+            ]);
+        }
+        let gateway = match &self.gateway {
+            Some(gw) => format!(
+                "Default gateway: {} via {} (MAC {})",
+                gw.ip,
+                gw.interface,
+                gw.mac.as_deref().unwrap_or("not in the ARP cache")
+            ),
+            None => "Default gateway: not found".to_string(),
+        };
+        format!("{}\n\n{}\n", table, gateway)
+    }
+}
+
+fn open_datalink(iface: &NetworkInterface) -> Option<String> {
+    let config = datalink::Config {
+        read_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    // The channel is closed again as soon as it is dropped.
+    datalink::channel(iface, config)
+        .err()
+        .map(|e| e.to_string())
+}
+
+fn describe(
+    iface: &NetworkInterface,
+    gateway: Option<&Gateway>,
+    datalink_error: Option<String>,
+) -> InterfaceInfo {
+    let has_ipv4 = iface.ips.iter().any(|net| net.is_ipv4());
+    let default_route = gateway.is_some_and(|gw| {
+        gw.interface == iface.name || iface.ips.iter().any(|net| net.ip() == IpAddr::V4(gw.ip))
+    });
+    // Windows sends frames to the gateway's MAC from the interface with the
+    // default route; elsewhere the kernel routes the raw packets.
+    let routable = if cfg!(target_os = "windows") {
+        default_route && gateway.is_some_and(|gw| gw.mac.is_some())
+    } else {
+        !iface.is_loopback()
+    };
+    InterfaceInfo {
+        name: iface.name.clone(),
+        description: iface.description.clone(),
+        mac: iface.mac.map(|mac| mac.to_string()),
+        ips: iface.ips.iter().map(|net| net.to_string()).collect(),
+        up: iface.is_up(),
+        loopback: iface.is_loopback(),
+        default_route,
+        syn_ready: iface.is_up() && has_ipv4 && routable && datalink_error.is_none(),
+        datalink_error,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn default_gateway() -> Option<Gateway> {
+    let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+    let (interface, ip) = parse_default_route(&routes)?;
+    let mac = std::fs::read_to_string("/proc/net/arp")
+        .ok()
+        .and_then(|arp| parse_arp_mac(&arp, ip));
+    Some(Gateway { ip, interface, mac })
+}
+
+#[cfg(target_os = "windows")]
+fn default_gateway() -> Option<Gateway> {
+    let (ip, mac, interface_ip) = super::SynScanner::get_gateway_info_windows().ok()?;
+    Some(Gateway {
+        ip,
+        interface: interface_ip.to_string(),
+        mac: Some(mac.to_string()),
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn default_gateway() -> Option<Gateway> {
+    None
+}
+
+/// The interface and gateway of the default route in `/proc/net/route`,
+/// whose addresses are little-endian hex.
+#[cfg(target_os = "linux")]
+fn parse_default_route(routes: &str) -> Option<(String, Ipv4Addr)> {
+    routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [iface, "00000000", gateway, _, _, _, _, "00000000", ..] => {
+                let gateway = u32::from_str_radix(gateway, 16).ok()?;
+                Some((iface.to_string(), Ipv4Addr::from(gateway.swap_bytes())))
+            }
+            _ => None,
+        }
+    })
+}
+
+/// The MAC of `ip` in `/proc/net/arp`, if the entry is complete.
+#[cfg(target_os = "linux")]
+fn parse_arp_mac(arp: &str, ip: Ipv4Addr) -> Option<String> {
+    arp.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [addr, _, flags, mac, ..]
+                if addr.parse() == Ok(ip) && *flags != "0x0" && *mac != "00:00:00:00:00:00" =>
+            {
+                Some(mac.to_string())
+            }
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_default_gateway_from_proc_tables() {
+        let routes =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+            eth0\t000200C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n\
+            eth0\t00000000\t010200C0\t0003\t0\t0\t100\t00000000\t0\t0\t0\n";
+        let (iface, ip) = parse_default_route(routes).unwrap();
+        assert_eq!((iface.as_str(), ip), ("eth0", Ipv4Addr::new(192, 0, 2, 1)));
+        assert!(parse_default_route("Iface\tDestination\n").is_none());
+
+        let arp =
+            "IP address       HW type     Flags       HW address            Mask     Device\n\
+            192.0.2.7        0x1         0x0         00:00:00:00:00:00     *        eth0\n\
+            192.0.2.1        0x1         0x2         02:fc:00:00:00:05     *        eth0\n";
+        assert_eq!(parse_arp_mac(arp, ip).as_deref(), Some("02:fc:00:00:00:05"));
+        assert_eq!(parse_arp_mac(arp, Ipv4Addr::new(192, 0, 2, 7)), None);
+    }
+
+    #[test]
+    fn test_only_usable_interfaces_are_syn_ready() {
+        let gateway = Gateway {
+            ip: Ipv4Addr::new(192, 0, 2, 1),
+            interface: "eth0".to_string(),
+            mac: Some("02:fc:00:00:00:05".to_string()),
+        };
+        let iface = |name: &str, ip: &str, flags: u32| NetworkInterface {
+            name: name.to_string(),
+            description: String::new(),
+            index: 1,
+            mac: None,
+            ips: vec![ip.parse().unwrap()],
+            flags,
+        };
+        // IFF_UP | IFF_RUNNING, plus IFF_LOOPBACK for lo
+        let eth0 = iface("eth0", "192.0.2.10/24", 0x41);
+        let lo = iface("lo", "127.0.0.1/8", 0x49);
+
+        let info = describe(&eth0, Some(&gateway), None);
+        assert!(info.default_route && info.syn_ready);
+        assert_eq!(info.ips, ["192.0.2.10/24"]);
+        let denied = describe(&eth0, Some(&gateway), Some("permission denied".to_string()));
+        assert!(!denied.syn_ready);
+        let info = describe(&lo, Some(&gateway), None);
+        assert!(info.loopback && !info.default_route && !info.syn_ready);
+
+        let report = InterfaceReport {
+            gateway: Some(gateway),
+            interfaces: vec![describe(&eth0, None, None)],
+        };
+        assert!(report
+            .to_text()
+            .contains("Default gateway: 192.0.2.1 via eth0"));
+    }
+}
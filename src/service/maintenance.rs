@@ -0,0 +1,314 @@
+//! `[maintenance]`: database housekeeping that otherwise needs a cron job or
+//! a manual `ip-scan db` call. Pruning old round bitmaps, marking long-unseen
+//! open ports as gone, VACUUM and queueing outdated geo data for another
+//! lookup each run on their own interval, but only while nothing is being
+//! scanned: between loop rounds, while waiting for the scan window, or while
+//! the API has no scan. When each task last ran, and how that went, is kept
+//! in `scan_metadata` so the schedule survives restarts and the API can show
+//! it.
+
+use crate::cli::{Args, MaintenanceConfig};
+use crate::dao::SqliteDB;
+use crate::service::{RuntimeScanState, ScanController};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+/// How often the API server checks whether a task is due.
+const IDLE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceTask {
+    Prune,
+    Age,
+    Vacuum,
+    GeoRefresh,
+}
+
+impl MaintenanceTask {
+    pub const ALL: [MaintenanceTask; 4] = [
+        MaintenanceTask::Prune,
+        MaintenanceTask::Age,
+        MaintenanceTask::Vacuum,
+        MaintenanceTask::GeoRefresh,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaintenanceTask::Prune => "prune",
+            MaintenanceTask::Age => "age",
+            MaintenanceTask::Vacuum => "vacuum",
+            MaintenanceTask::GeoRefresh => "geo_refresh",
+        }
+    }
+
+    fn last_run_key(&self) -> String {
+        format!("maintenance_{}_last_run", self.as_str())
+    }
+
+    fn last_result_key(&self) -> String {
+        format!("maintenance_{}_last_result", self.as_str())
+    }
+}
+
+/// Schedule of one task, as served by `GET /api/v1/admin/maintenance`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MaintenanceTaskStatus {
+    /// prune, age, vacuum or geo_refresh
+    pub task: String,
+    /// Hours between runs; 0 when the task is turned off
+    pub interval_hours: u64,
+    /// RFC 3339 time of the last run
+    pub last_run: Option<String>,
+    /// What the last run did, or `error: ...`
+    pub last_result: Option<String>,
+    /// When the task may run next, once the scanner is idle; absent when
+    /// maintenance or the task is turned off
+    pub next_due: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    pub tasks: Vec<MaintenanceTaskStatus>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Maintenance {
+    config: MaintenanceConfig,
+    stale_rounds: u32,
+}
+
+impl Maintenance {
+    pub fn new(config: MaintenanceConfig, stale_rounds: u32) -> Self {
+        Self {
+            config,
+            stale_rounds,
+        }
+    }
+
+    pub fn from_args(args: &Args) -> Self {
+        Self::new(args.maintenance.clone(), args.stale_rounds)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    fn interval_hours(&self, task: MaintenanceTask) -> u64 {
+        match task {
+            MaintenanceTask::Prune => self.config.prune_hours,
+            MaintenanceTask::Age => self.config.age_hours,
+            MaintenanceTask::Vacuum => self.config.vacuum_hours,
+            MaintenanceTask::GeoRefresh => self.config.geo_refresh_hours,
+        }
+    }
+
+    fn last_run(db: &SqliteDB, task: MaintenanceTask) -> Result<Option<DateTime<Utc>>> {
+        Ok(db
+            .get_metadata(&task.last_run_key())?
+            .and_then(|value| DateTime::parse_from_rfc3339(&value).ok())
+            .map(|time| time.with_timezone(&Utc)))
+    }
+
+    /// When `task` may next run, or `None` when it is turned off.
+    fn next_due(&self, db: &SqliteDB, task: MaintenanceTask) -> Result<Option<DateTime<Utc>>> {
+        let hours = self.interval_hours(task);
+        if !self.config.enabled || hours == 0 {
+            return Ok(None);
+        }
+        let last_run = Self::last_run(db, task)?;
+        Ok(Some(last_run.map_or(DateTime::<Utc>::MIN_UTC, |last| {
+            last + Duration::hours(hours as i64)
+        })))
+    }
+
+    pub fn status(&self, db: &SqliteDB) -> Result<MaintenanceStatus> {
+        let mut tasks = Vec::new();
+        for task in MaintenanceTask::ALL {
+            tasks.push(MaintenanceTaskStatus {
+                task: task.as_str().to_string(),
+                interval_hours: self.interval_hours(task),
+                last_run: db.get_metadata(&task.last_run_key())?,
+                last_result: db.get_metadata(&task.last_result_key())?,
+                next_due: self.next_due(db, task)?.map(|due| due.to_rfc3339()),
+            });
+        }
+        Ok(MaintenanceStatus {
+            enabled: self.config.enabled,
+            tasks,
+        })
+    }
+
+    /// Run every task whose interval has passed at `now`. A failing task is
+    /// logged and recorded like a successful one, so it neither stops the
+    /// others nor is retried before its next interval. Blocking; VACUUM in
+    /// particular holds the database for as long as it takes to copy it.
+    pub fn run_due(&self, db: &SqliteDB, now: DateTime<Utc>) -> Vec<MaintenanceTask> {
+        let mut ran = Vec::new();
+        for task in MaintenanceTask::ALL {
+            match self.next_due(db, task) {
+                Ok(Some(due)) if due <= now => {}
+                Ok(_) => continue,
+                Err(e) => {
+                    error!(
+                        "Failed to read {} maintenance schedule: {}",
+                        task.as_str(),
+                        e
+                    );
+                    continue;
+                }
+            }
+            let result = match self.run(db, task, now) {
+                Ok(summary) => {
+                    info!("Maintenance {}: {}", task.as_str(), summary);
+                    summary
+                }
+                Err(e) => {
+                    error!("Maintenance {} failed: {}", task.as_str(), e);
+                    format!("error: {}", e)
+                }
+            };
+            if let Err(e) = db
+                .save_metadata(&task.last_run_key(), &now.to_rfc3339())
+                .and_then(|_| db.save_metadata(&task.last_result_key(), &result))
+            {
+                error!("Failed to record {} maintenance run: {}", task.as_str(), e);
+            }
+            ran.push(task);
+        }
+        ran
+    }
+
+    fn run(&self, db: &SqliteDB, task: MaintenanceTask, now: DateTime<Utc>) -> Result<String> {
+        match task {
+            MaintenanceTask::Prune => {
+                let deleted = db.cleanup_old_rounds(self.config.keep_rounds)?;
+                Ok(format!("deleted {} old bitmap rows", deleted))
+            }
+            MaintenanceTask::Age => {
+                let completed = db.last_completed_round()?;
+                if completed < 1 {
+                    return Ok("no completed round yet".to_string());
+                }
+                let closed = db.mark_stale_ports(completed, self.stale_rounds)?;
+                Ok(format!(
+                    "marked {} open ports gone as of round {}",
+                    closed, completed
+                ))
+            }
+            MaintenanceTask::Vacuum => {
+                let reclaimed = db.vacuum()?;
+                Ok(format!("reclaimed {} bytes", reclaimed))
+            }
+            MaintenanceTask::GeoRefresh => {
+                let cutoff = now - Duration::days(self.config.geo_max_age_days as i64);
+                let due = db.refresh_geo_before(&cutoff.to_rfc3339())?;
+                Ok(format!("queued {} IPs for another geo lookup", due))
+            }
+        }
+    }
+
+    /// [`Maintenance::run_due`] off the async workers; for the scan loop's
+    /// idle points. Does nothing when maintenance is turned off.
+    pub async fn run_idle(&self, db: &SqliteDB) {
+        if !self.config.enabled {
+            return;
+        }
+        let maintenance = self.clone();
+        let db = db.clone();
+        if let Err(e) =
+            tokio::task::spawn_blocking(move || maintenance.run_due(&db, Utc::now())).await
+        {
+            error!("Maintenance task failed: {}", e);
+        }
+    }
+
+    /// For the API server: check for due tasks every minute and run them
+    /// while neither the CLI scanner nor an API scan is active, until the
+    /// task is aborted.
+    pub fn spawn(
+        self,
+        db: SqliteDB,
+        controller: Arc<ScanController>,
+        runtime_scan_state: RuntimeScanState,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(IDLE_CHECK_INTERVAL);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                if !runtime_scan_state.is_cli_scan_running() && !controller.has_active_task().await
+                {
+                    self.run_idle(&db).await;
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::IpGeoInfo;
+
+    #[test]
+    fn test_run_due_records_runs_and_queues_stale_geo_data() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        for round in 1..=3 {
+            db.bulk_update_port_status(vec![("192.0.2.1".parse().unwrap(), 80, true)], round)
+                .unwrap();
+        }
+        db.bulk_update_port_status(vec![("192.0.2.2".parse().unwrap(), 22, true)], 1)
+            .unwrap();
+        db.save_metadata("current_round", "3").unwrap();
+        db.save_metadata("round_3_complete", "true").unwrap();
+        db.save_ip_geo_info_batch(&[
+            IpGeoInfo::new("192.0.2.1".to_string(), "test".to_string()),
+            IpGeoInfo::new("192.0.2.2".to_string(), "test".to_string()),
+        ])
+        .unwrap();
+        assert!(db.get_ips_missing_geo("", 10).unwrap().is_empty());
+
+        let config = MaintenanceConfig {
+            enabled: true,
+            keep_rounds: 1,
+            vacuum_hours: 0,
+            ..Default::default()
+        };
+        let maintenance = Maintenance::new(config, 2);
+        let now = Utc::now();
+        // Anything saved before the run counts as outdated.
+        let later = now + Duration::days(31);
+        let ran = maintenance.run_due(&db, later);
+        assert_eq!(
+            ran,
+            [
+                MaintenanceTask::Prune,
+                MaintenanceTask::Age,
+                MaintenanceTask::GeoRefresh
+            ]
+        );
+        assert_eq!(db.get_ips_missing_geo("", 10).unwrap().len(), 2);
+
+        let status = maintenance.status(&db).unwrap();
+        let age = &status.tasks[1];
+        assert_eq!(age.last_run.as_deref(), Some(later.to_rfc3339().as_str()));
+        assert_eq!(
+            age.last_result.as_deref(),
+            Some("marked 1 open ports gone as of round 3")
+        );
+        assert!(status.tasks[2].next_due.is_none());
+
+        // Nothing is due again until the intervals pass.
+        assert!(maintenance.run_due(&db, later).is_empty());
+        assert_eq!(
+            maintenance.run_due(&db, later + Duration::hours(7)),
+            [MaintenanceTask::Age]
+        );
+    }
+}
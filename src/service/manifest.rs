@@ -0,0 +1,228 @@
+//! `ip-scan export --manifest`: a JSON manifest next to the export with its
+//! record count, size and SHA-256, the filter and scan state it was taken
+//! from, and when. With `--sign-key`, an Ed25519 signature over the manifest
+//! file goes next to it, so a recipient holding the public key can check
+//! that neither the manifest nor (through its hash) the export changed:
+//!
+//! `openssl pkeyutl -verify -pubin -inkey pub.pem -rawin -in X.manifest.json -sigfile X.manifest.sig`
+
+use super::ResultsFilter;
+use crate::dao::SqliteDB;
+use anyhow::{anyhow, Context, Result};
+use ring::digest::{Context as Digest, SHA256};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use rustls::pki_types::PrivateKeyDer;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Passes writes through to `inner`, hashing and counting the bytes.
+pub struct HashingWriter<W> {
+    inner: W,
+    digest: Digest,
+    bytes: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            digest: Digest::new(&SHA256),
+            bytes: 0,
+        }
+    }
+
+    /// Hex SHA-256 and length of everything written.
+    pub fn finish(self) -> (String, u64) {
+        (hex(self.digest.finish().as_ref()), self.bytes)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.digest.update(&buf[..n]);
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportManifest {
+    /// File name of the export, without its directory
+    pub file: String,
+    pub format: String,
+    pub records: usize,
+    pub bytes: u64,
+    /// Hex SHA-256 of the export file
+    pub sha256: String,
+    pub created_at: String,
+    /// `ip-scan <version>`
+    pub generator: String,
+    /// Filters the export was taken with; unset ones are `null`
+    pub filter: ResultsFilter,
+    /// Round the database was on
+    pub current_round: i64,
+    pub last_scan_time: Option<String>,
+    /// Hex Ed25519 public key of the signer, when signed
+    pub public_key: Option<String>,
+}
+
+impl ExportManifest {
+    /// Describe `output`, whose bytes hashed to `sha256`.
+    pub fn new(
+        db: &SqliteDB,
+        output: &Path,
+        format: &str,
+        filter: &ResultsFilter,
+        records: usize,
+        (sha256, bytes): (String, u64),
+    ) -> Result<Self> {
+        Ok(Self {
+            file: output
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            format: format.to_string(),
+            records,
+            bytes,
+            sha256,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            filter: filter.clone(),
+            current_round: db.get_current_round()?,
+            last_scan_time: db.get_metadata("last_scan_time")?,
+            public_key: None,
+        })
+    }
+
+    /// Write `<output>.manifest.json` and, with a signer,
+    /// `<output>.manifest.sig` (the raw 64-byte signature of the manifest
+    /// file); returns the paths written.
+    pub fn write(
+        mut self,
+        output: &Path,
+        signer: Option<&ManifestSigner>,
+    ) -> Result<(PathBuf, Option<PathBuf>)> {
+        self.public_key = signer.map(|signer| signer.public_key());
+        let json = serde_json::to_vec_pretty(&self)?;
+        let manifest = sibling(output, "manifest.json");
+        std::fs::write(&manifest, &json)
+            .with_context(|| format!("Failed to write {}", manifest.display()))?;
+        let Some(signer) = signer else {
+            return Ok((manifest, None));
+        };
+        let signature = sibling(output, "manifest.sig");
+        std::fs::write(&signature, signer.sign(&json))
+            .with_context(|| format!("Failed to write {}", signature.display()))?;
+        Ok((manifest, Some(signature)))
+    }
+}
+
+/// An Ed25519 key manifests are signed with.
+pub struct ManifestSigner {
+    key: Ed25519KeyPair,
+}
+
+impl ManifestSigner {
+    /// Read a PKCS#8 PEM Ed25519 private key, e.g. from
+    /// `openssl genpkey -algorithm ed25519`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let key = rustls_pemfile::private_key(&mut BufReader::new(file))
+            .with_context(|| format!("Failed to read the private key from {}", path.display()))?
+            .ok_or_else(|| anyhow!("No PEM private key found in {}", path.display()))?;
+        let PrivateKeyDer::Pkcs8(der) = key else {
+            return Err(anyhow!("{} is not a PKCS#8 Ed25519 key", path.display()));
+        };
+        let key = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der.secret_pkcs8_der())
+            .map_err(|e| anyhow!("{} is not an Ed25519 key: {}", path.display(), e))?;
+        Ok(Self { key })
+    }
+
+    pub fn public_key(&self) -> String {
+        hex(self.key.public_key().as_ref())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.key.sign(message).as_ref().to_vec()
+    }
+}
+
+/// `<output>.<suffix>`, next to `output`.
+fn sibling(output: &Path, suffix: &str) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{:02x}", b);
+        out
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{UnparsedPublicKey, ED25519};
+
+    #[test]
+    fn test_manifest_hashes_the_export_and_verifies_with_the_public_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(vec![("192.0.2.1".parse().unwrap(), 22, true)], 1)
+            .unwrap();
+        let filter = ResultsFilter {
+            port: Some(22),
+            ..Default::default()
+        };
+        let output = dir.path().join("results.csv");
+        let mut out = HashingWriter::new(std::fs::File::create(&output).unwrap());
+        let records = crate::service::write_results_csv(&db, &filter, &mut out).unwrap();
+        let (sha256, bytes) = out.finish();
+        let content = std::fs::read(&output).unwrap();
+        assert_eq!(bytes, content.len() as u64);
+        assert_eq!(
+            sha256,
+            hex(ring::digest::digest(&SHA256, &content).as_ref())
+        );
+
+        let key = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+        let key_path = dir.path().join("sign.pem");
+        std::fs::write(&key_path, key.serialize_pem()).unwrap();
+        let signer = ManifestSigner::load(&key_path).unwrap();
+
+        let manifest =
+            ExportManifest::new(&db, &output, "csv", &filter, records, (sha256, bytes)).unwrap();
+        let (manifest_path, signature_path) = manifest.write(&output, Some(&signer)).unwrap();
+        assert_eq!(manifest_path, dir.path().join("results.csv.manifest.json"));
+        let json = std::fs::read(&manifest_path).unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(parsed["file"], "results.csv");
+        assert_eq!(
+            (
+                parsed["records"].as_u64(),
+                parsed["filter"]["port"].as_u64()
+            ),
+            (Some(1), Some(22))
+        );
+        assert_eq!(parsed["public_key"], signer.public_key());
+
+        let signature = std::fs::read(signature_path.unwrap()).unwrap();
+        let public = UnparsedPublicKey::new(&ED25519, key.public_key_raw());
+        assert!(public.verify(&json, &signature).is_ok());
+        assert!(public.verify(b"tampered", &signature).is_err());
+
+        std::fs::write(&key_path, "not a key").unwrap();
+        assert!(ManifestSigner::load(&key_path).is_err());
+    }
+}
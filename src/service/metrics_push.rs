@@ -0,0 +1,405 @@
+//! `[metrics_push]`: send the `/stats/prometheus` metrics to a Pushgateway
+//! and/or a Prometheus remote-write endpoint when a round completes and once
+//! more at shutdown, for short-lived scan jobs nobody scrapes.
+//!
+//! The pusher is an event sink: it waits for `round_complete` on the
+//! [`EventBus`] and pushes again when the bus closes. Rendering runs on the
+//! blocking pool and every push has its own timeout, so an unreachable
+//! gateway only costs a warning.
+
+use super::{EventBus, ScanEvent};
+use crate::cli::MetricsPushConfig;
+use crate::dao::SqliteDB;
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+const PUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct MetricsPusher {
+    client: reqwest::Client,
+    /// Pushgateway group URL, ending in `/metrics/job/<job>/instance/<instance>`
+    pushgateway: Option<String>,
+    remote_write: Option<String>,
+    job: String,
+    instance: String,
+}
+
+impl MetricsPusher {
+    /// `None` when neither target is configured. Errors on invalid settings,
+    /// so a typo fails at startup rather than at the end of the first round.
+    pub fn from_config(config: &MetricsPushConfig) -> Result<Option<Self>> {
+        if config.pushgateway_url.is_none() && config.remote_write_url.is_none() {
+            return Ok(None);
+        }
+        for url in [&config.pushgateway_url, &config.remote_write_url]
+            .into_iter()
+            .flatten()
+        {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(anyhow!(
+                    "Invalid [metrics_push] URL {:?}; expected http:// or https://",
+                    url
+                ));
+            }
+        }
+        let instance = config
+            .instance
+            .clone()
+            .unwrap_or_else(super::syslog::local_hostname);
+        for (field, value) in [("job", &config.job), ("instance", &instance)] {
+            if value.is_empty() || value.contains('/') {
+                return Err(anyhow!(
+                    "Invalid [metrics_push] {} {:?}; it must be non-empty and contain no '/'",
+                    field,
+                    value
+                ));
+            }
+        }
+        let pushgateway = config.pushgateway_url.as_ref().map(|url| {
+            format!(
+                "{}/metrics/job/{}/instance/{}",
+                url.trim_end_matches('/'),
+                config.job,
+                instance
+            )
+        });
+        Ok(Some(Self {
+            client: reqwest::Client::builder().timeout(PUSH_TIMEOUT).build()?,
+            pushgateway,
+            remote_write: config.remote_write_url.clone(),
+            job: config.job.clone(),
+            instance,
+        }))
+    }
+
+    /// Push after every completed round and once the bus closes.
+    pub fn spawn(self, bus: &EventBus, db: SqliteDB) -> JoinHandle<()> {
+        let mut events = bus.subscribe();
+        if let Some(url) = &self.pushgateway {
+            info!("Pushing metrics to {} after each round", url);
+        }
+        if let Some(url) = &self.remote_write {
+            info!("Remote-writing metrics to {} after each round", url);
+        }
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(ScanEvent::RoundComplete(_)) => self.push(&db).await,
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        debug!("Metrics pusher skipped {} events", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        self.push(&db).await;
+                        break;
+                    }
+                }
+            }
+        })
+    }
+
+    async fn push(&self, db: &SqliteDB) {
+        let db = db.clone();
+        let text = match tokio::task::spawn_blocking(move || crate::api::prometheus_text(&db))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|text| text)
+        {
+            Ok(text) => text,
+            Err(e) => {
+                warn!("Failed to collect metrics to push: {}", e);
+                return;
+            }
+        };
+        let pushgateway = async {
+            if let Some(url) = &self.pushgateway {
+                let request = self
+                    .client
+                    .put(url)
+                    .header("Content-Type", "text/plain; version=0.0.4")
+                    .body(text.clone());
+                report("Pushgateway", url, request.send().await);
+            }
+        };
+        let remote_write = async {
+            if let Some(url) = &self.remote_write {
+                let samples = parse_text(&text);
+                let now = chrono::Utc::now().timestamp_millis();
+                let body = write_request(&samples, &self.job, &self.instance, now);
+                match snap::raw::Encoder::new().compress_vec(&body) {
+                    Ok(body) => {
+                        let request = self
+                            .client
+                            .post(url)
+                            .header("Content-Type", "application/x-protobuf")
+                            .header("Content-Encoding", "snappy")
+                            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+                            .body(body);
+                        report("Remote write", url, request.send().await);
+                    }
+                    Err(e) => warn!("Failed to compress remote-write request: {}", e),
+                }
+            }
+        };
+        futures::join!(pushgateway, remote_write);
+    }
+}
+
+fn report(target: &str, url: &str, result: reqwest::Result<reqwest::Response>) {
+    match result {
+        Ok(response) if response.status().is_success() => {
+            debug!("{} {} accepted metrics", target, url)
+        }
+        Ok(response) => warn!("{} {} answered {}", target, url, response.status()),
+        Err(e) => warn!("{} push to {} failed: {}", target, url, e),
+    }
+}
+
+/// One line of the text format.
+#[derive(Debug, PartialEq)]
+struct Sample {
+    name: String,
+    labels: Vec<(String, String)>,
+    value: f64,
+}
+
+/// Samples of a text-format exposition; comments and lines that do not
+/// parse are skipped.
+fn parse_text(text: &str) -> Vec<Sample> {
+    text.lines().filter_map(parse_line).collect()
+}
+
+fn parse_line(line: &str) -> Option<Sample> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let name_end = line.find(['{', ' '])?;
+    let (labels, rest) = match line[name_end..].strip_prefix('{') {
+        Some(labels) => parse_labels(labels)?,
+        None => (Vec::new(), &line[name_end..]),
+    };
+    Some(Sample {
+        name: line[..name_end].to_string(),
+        labels,
+        value: rest.split_whitespace().next()?.parse().ok()?,
+    })
+}
+
+/// `name="value",...}` and what follows the closing brace.
+fn parse_labels(mut text: &str) -> Option<(Vec<(String, String)>, &str)> {
+    let mut labels = Vec::new();
+    loop {
+        text = text.trim_start_matches([',', ' ']);
+        if let Some(rest) = text.strip_prefix('}') {
+            return Some((labels, rest));
+        }
+        let (name, rest) = text.split_once("=\"")?;
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        let end = loop {
+            match chars.next()? {
+                (i, '"') => break i,
+                (_, '\\') => match chars.next()?.1 {
+                    'n' => value.push('\n'),
+                    other => value.push(other),
+                },
+                (_, other) => value.push(other),
+            }
+        };
+        labels.push((name.trim().to_string(), value));
+        text = &rest[end + 1..];
+    }
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    put_varint(buf, field << 3 | 2);
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+/// Protobuf `prometheus.WriteRequest`, one series per sample, each carrying
+/// `job` and `instance` as a Pushgateway group would.
+fn write_request(samples: &[Sample], job: &str, instance: &str, timestamp_ms: i64) -> Vec<u8> {
+    let mut request = Vec::new();
+    for sample in samples {
+        let mut labels: Vec<(&str, &str)> = vec![
+            ("__name__", sample.name.as_str()),
+            ("job", job),
+            ("instance", instance),
+        ];
+        labels.extend(
+            sample
+                .labels
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        );
+        // Remote write requires label names in order.
+        labels.sort();
+        let mut series = Vec::new();
+        for (name, value) in labels {
+            let mut label = Vec::new();
+            put_bytes(&mut label, 1, name.as_bytes());
+            put_bytes(&mut label, 2, value.as_bytes());
+            put_bytes(&mut series, 1, &label);
+        }
+        let mut point = Vec::new();
+        put_varint(&mut point, 1 << 3 | 1);
+        point.extend_from_slice(&sample.value.to_le_bytes());
+        put_varint(&mut point, 2 << 3);
+        put_varint(&mut point, timestamp_ms as u64);
+        put_bytes(&mut series, 2, &point);
+        put_bytes(&mut request, 1, &series);
+    }
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_text_format_becomes_remote_write_series() {
+        let text = "# HELP ip_scan_round Current scan round\n# TYPE ip_scan_round gauge\nip_scan_round 3\nip_scan_probe_latency_seconds{probe=\"tcp\",quantile=\"0.5\"} 0.012\n";
+        let samples = parse_text(text);
+        assert_eq!(
+            samples,
+            [
+                Sample {
+                    name: "ip_scan_round".to_string(),
+                    labels: Vec::new(),
+                    value: 3.0,
+                },
+                Sample {
+                    name: "ip_scan_probe_latency_seconds".to_string(),
+                    labels: vec![
+                        ("probe".to_string(), "tcp".to_string()),
+                        ("quantile".to_string(), "0.5".to_string()),
+                    ],
+                    value: 0.012,
+                },
+            ]
+        );
+
+        let request = write_request(&samples[..1], "scan", "a", 1000);
+        let mut expected = vec![0x0a, 0x45];
+        for (name, value) in [
+            ("__name__", "ip_scan_round"),
+            ("instance", "a"),
+            ("job", "scan"),
+        ] {
+            expected.extend([0x0a, (name.len() + value.len() + 4) as u8]);
+            expected.extend([0x0a, name.len() as u8]);
+            expected.extend(name.as_bytes());
+            expected.extend([0x12, value.len() as u8]);
+            expected.extend(value.as_bytes());
+        }
+        expected.extend([0x12, 0x0c, 0x09]);
+        expected.extend(3.0f64.to_le_bytes());
+        expected.extend([0x10, 0xe8, 0x07]);
+        assert_eq!(request, expected);
+    }
+
+    #[tokio::test]
+    async fn test_pushes_when_a_round_completes_and_at_shutdown() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            while requests.len() < 2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 65536];
+                let mut len = 0;
+                // Read until the head and Content-Length bytes of body arrived.
+                loop {
+                    len += stream.read(&mut buf[len..]).await.unwrap();
+                    let text = String::from_utf8_lossy(&buf[..len]).to_string();
+                    if let Some(head_end) = text.find("\r\n\r\n") {
+                        let length = text[..head_end]
+                            .lines()
+                            .find_map(|l| {
+                                l.to_lowercase()
+                                    .strip_prefix("content-length: ")
+                                    .map(str::to_string)
+                            })
+                            .and_then(|l| l.trim().parse::<usize>().ok())
+                            .unwrap_or(0);
+                        if len >= head_end + 4 + length {
+                            requests.push(text);
+                            break;
+                        }
+                    }
+                }
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .await
+                    .unwrap();
+            }
+            requests
+        });
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.save_metadata("current_round", "4").unwrap();
+        let config = MetricsPushConfig {
+            pushgateway_url: Some(format!("http://{}/", addr)),
+            instance: Some("scanner-1".to_string()),
+            ..Default::default()
+        };
+        let pusher = MetricsPusher::from_config(&config).unwrap().unwrap();
+        let bus = EventBus::new();
+        let sink = pusher.spawn(&bus, db);
+        bus.publish(ScanEvent::NewCountry {
+            ip: "192.0.2.1".to_string(),
+            country: "NL".to_string(),
+        });
+        bus.publish(ScanEvent::RoundComplete(crate::dao::RoundMetrics {
+            round: 4,
+            scanned: 1,
+            open: 0,
+            errors: 0,
+            retries: 0,
+            duration_secs: 1.0,
+            avg_rate: 1.0,
+            finished_at: String::new(),
+        }));
+        drop(bus);
+        sink.await.unwrap();
+
+        let requests = server.await.unwrap();
+        for request in requests {
+            assert!(request.starts_with("PUT /metrics/job/ip-scan/instance/scanner-1 HTTP/1.1"));
+            assert!(request.contains("\nip_scan_round 4\n"));
+        }
+    }
+
+    #[test]
+    fn test_rejects_invalid_settings() {
+        assert!(MetricsPusher::from_config(&MetricsPushConfig::default())
+            .unwrap()
+            .is_none());
+        let config = MetricsPushConfig {
+            remote_write_url: Some("localhost:9090/api/v1/write".to_string()),
+            ..Default::default()
+        };
+        assert!(MetricsPusher::from_config(&config).is_err());
+        let config = MetricsPushConfig {
+            pushgateway_url: Some("http://localhost:9091".to_string()),
+            job: "nightly/scan".to_string(),
+            ..Default::default()
+        };
+        assert!(MetricsPusher::from_config(&config).is_err());
+    }
+}
@@ -1,18 +1,85 @@
+mod batch_tuner;
+mod cluster;
 mod con_scanner;
+mod cve_mapper;
+mod email_report;
+mod estimate;
+mod export;
+mod geo_cache;
+mod geo_jobs;
+mod geo_ranges;
 pub mod geo_service;
-pub mod optimized_scanner;
+mod import;
+mod interfaces;
+mod maintenance;
+mod manifest;
+mod metrics_push;
+mod mqtt;
+mod namespaces;
+mod notify;
+mod priority_scheduler;
+mod probe;
+mod progress_bar;
 mod rate_limiter;
+mod rdap;
+mod report;
+mod reputation;
+mod rescan;
+mod resolver;
+mod round_summary;
 mod scan_controller;
+mod scanner;
+mod script_hooks;
 pub mod service_prober;
 mod syn_scanner;
+mod syslog;
+mod timeseries;
+#[cfg(target_os = "linux")]
+mod uring_connect;
+mod wal_checkpointer;
 
-pub use con_scanner::{ConScanner, ConScannerConfig};
-pub use geo_service::GeoService;
-#[allow(unused_imports)]
-pub use optimized_scanner::{
-    quick_scan, range_scan, OptimizedScanner, OptimizedScannerConfig, PortState,
+pub use cluster::{
+    run_worker, ClusterStatus, Coordinator, LeaseGrant, LeaseOutcome, LeaseReport, LeaseRequest,
+    LeaseResult, ReportOutcome,
 };
+pub use con_scanner::{ConScanner, ConScannerConfig, PortState};
+pub use cve_mapper::CveIndex;
+pub use email_report::{EmailReporter, RoundReport};
+pub use estimate::ScanEstimate;
+pub use export::{
+    csv_row, write_results_csv, write_results_json, write_results_parquet, write_target_list,
+    CSV_HEADER,
+};
+pub use geo_jobs::{GeoJob, GeoJobState, GeoJobs, MAX_JOB_IPS};
+pub use geo_ranges::country_ranges;
+pub use geo_service::{GeoEnrichment, GeoProviderStats, GeoService, GeoStats, GeoStatus};
+pub use import::read_results_csv;
+pub use interfaces::InterfaceReport;
+pub use maintenance::{Maintenance, MaintenanceStatus, MaintenanceTask, MaintenanceTaskStatus};
+pub use manifest::{ExportManifest, HashingWriter, ManifestSigner};
+pub use metrics_push::MetricsPusher;
+pub use mqtt::MqttPublisher;
+pub use namespaces::{NamespaceOutcome, Namespaces, Tenant, MAX_NAMESPACE_PRINCIPALS};
+pub use notify::{spawn_notifiers, validate_notifiers, EventBus, Forwarder, ScanEvent};
+pub use priority_scheduler::{PriorityScheduler, RescanQueue, PRIORITY_HOST_LIMIT};
+pub use probe::{Probe, ProbeContext};
+pub use progress_bar::RoundProgressBar;
 pub use rate_limiter::RateLimiter;
-pub use scan_controller::{RuntimeScanState, ScanController};
+pub(crate) use report::html_escape;
+pub use report::{DiffReport, ResultsFilter, ResultsReport};
+pub use reputation::{
+    AbuseIpDb, GreyNoise, ReputationProvider, ReputationService, REPUTATION_PROVIDERS,
+};
+pub use rescan::{rescan_open_ports, RescanSummary};
+pub use resolver::{HostResolver, TargetIter, MAX_TARGET_HOSTNAMES};
+pub use round_summary::{RoundSummary, RunSummary, SummaryFormat};
+pub use scan_controller::{RoundProgress, RuntimeScanState, ScanController};
+pub use scanner::{connect_config, scanner_from_args, ProgressFn, Scanner};
+pub use script_hooks::ScriptHooks;
 pub use service_prober::{reverse_dns_lookup, ServiceProber};
 pub use syn_scanner::SynScanner;
+pub use syslog::SyslogSink;
+pub use timeseries::{
+    MetricsSample, MetricsTimeseries, TimeseriesSampler, SAMPLE_EVERY, TIMESERIES_KEY,
+};
+pub use wal_checkpointer::WalCheckpointer;
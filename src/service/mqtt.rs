@@ -0,0 +1,178 @@
+//! MQTT sink for scan events.
+//!
+//! Each [`ScanEvent`] is published as a flat JSON object of its fields to a
+//! topic rendered from the configured template, so Home Assistant or
+//! Node-RED can subscribe per event kind or per port.
+
+use super::notify::{render_template, EVENT_KINDS};
+use super::{EventBus, ScanEvent};
+use crate::cli::MqttConfig;
+use anyhow::{anyhow, Result};
+use rumqttc::{AsyncClient, Event, MqttOptions, Outgoing, QoS, TlsConfiguration, Transport};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Publishes queued in the client before `publish` waits for the broker.
+const REQUEST_QUEUE: usize = 1024;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// How long pending QoS 1/2 publishes get to reach the broker at shutdown.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct MqttPublisher {
+    options: MqttOptions,
+    topic: String,
+    events: Vec<String>,
+    qos: QoS,
+    retain: bool,
+}
+
+impl MqttPublisher {
+    /// `None` when no broker is configured. Errors on invalid settings, so a
+    /// typo fails at startup rather than on the first event.
+    pub fn from_config(config: &MqttConfig) -> Result<Option<Self>> {
+        let Some(host) = config.host.as_deref() else {
+            return Ok(None);
+        };
+        let qos = match config.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            2 => QoS::ExactlyOnce,
+            other => return Err(anyhow!("Invalid [mqtt] qos {}; expected 0, 1 or 2", other)),
+        };
+        if let Some(event) = config
+            .events
+            .iter()
+            .find(|e| !EVENT_KINDS.contains(&e.as_str()))
+        {
+            return Err(anyhow!(
+                "Invalid [mqtt] event {}; expected one of {}",
+                event,
+                EVENT_KINDS.join(", ")
+            ));
+        }
+        if config.topic.is_empty() || config.topic.contains(['+', '#']) {
+            return Err(anyhow!(
+                "Invalid [mqtt] topic {:?}; wildcards are not allowed",
+                config.topic
+            ));
+        }
+
+        let mut options = MqttOptions::new(&config.client_id, host, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = &config.username {
+            let password = std::env::var("SCAN_MQTT_PASSWORD")
+                .ok()
+                .or_else(|| config.password.clone())
+                .unwrap_or_default();
+            options.set_credentials(username, password);
+        }
+        if config.tls {
+            options.set_transport(Transport::tls_with_config(TlsConfiguration::Native));
+        }
+        Ok(Some(Self {
+            options,
+            topic: config.topic.clone(),
+            events: config.events.clone(),
+            qos,
+            retain: config.retain,
+        }))
+    }
+
+    /// Publish bus events until every bus handle is dropped. Broker outages
+    /// are retried in the background; events that arrive meanwhile queue in
+    /// the client and then lag the bus subscription, never the scanner.
+    pub fn spawn(self, bus: &EventBus) -> JoinHandle<()> {
+        let mut events = bus.subscribe();
+        let (client, mut eventloop) = AsyncClient::new(self.options.clone(), REQUEST_QUEUE);
+        let (host, port) = self.options.broker_address();
+        let broker = format!("{}:{}", host, port);
+        let connection = tokio::spawn(async move {
+            let mut connected = false;
+            loop {
+                match eventloop.poll().await {
+                    Ok(Event::Incoming(rumqttc::Packet::ConnAck(_))) => {
+                        info!("Connected to MQTT broker {}", broker);
+                        connected = true;
+                    }
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => return,
+                    Ok(_) => {}
+                    Err(e) => {
+                        if connected {
+                            warn!("MQTT connection to {} lost: {}", broker, e);
+                        } else {
+                            warn!("MQTT broker {} unavailable: {}", broker, e);
+                        }
+                        connected = false;
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        });
+
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("MQTT publisher lagged, {} events dropped", missed);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if !self.matches(&event) {
+                    continue;
+                }
+                let topic = render_template(&self.topic, &event.fields());
+                let payload = event.to_json().to_string();
+                if let Err(e) = client.publish(topic, self.qos, self.retain, payload).await {
+                    warn!("MQTT publish failed: {}", e);
+                }
+            }
+            let _ = client.disconnect().await;
+            let _ = tokio::time::timeout(DISCONNECT_TIMEOUT, connection).await;
+        })
+    }
+
+    fn matches(&self, event: &ScanEvent) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event.kind())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::OpenPort;
+
+    #[test]
+    fn test_publisher_validates_config() {
+        let mut config = MqttConfig::default();
+        assert!(MqttPublisher::from_config(&config).unwrap().is_none());
+
+        config.host = Some("broker.local".to_string());
+        config.qos = 3;
+        assert!(MqttPublisher::from_config(&config).is_err());
+        config.qos = 1;
+        config.topic = "ip-scan/#".to_string();
+        assert!(MqttPublisher::from_config(&config).is_err());
+        config.topic = "ip-scan/{{event}}/{{port}}".to_string();
+        config.events = vec!["round_complete".to_string()];
+
+        let publisher = MqttPublisher::from_config(&config).unwrap().unwrap();
+        let open = ScanEvent::OpenPort(OpenPort {
+            ip: "192.0.2.7".parse().unwrap(),
+            port: 1883,
+            scan_round: 2,
+        });
+        assert!(!publisher.matches(&open));
+        assert_eq!(
+            render_template(&publisher.topic, &open.fields()),
+            "ip-scan/open_port/1883"
+        );
+        assert_eq!(
+            open.to_json().to_string(),
+            r#"{"event":"open_port","ip":"192.0.2.7","port":"1883","round":"2"}"#
+        );
+    }
+}
@@ -0,0 +1,398 @@
+//! API namespaces: one deployment serving several teams without their data
+//! mixing. Each namespace keeps its scans and results in a database of its
+//! own, `<--namespace-dir>/<name>.db`, with its own scan controller, and
+//! lists the principals (client certificate names or `X-Forwarded-User`
+//! users, see `api::audit`) confined to it. The registry lives in the main
+//! database's `namespaces` table. The `--api-operator` principals see the
+//! main database and manage namespaces; once a namespace exists, any other
+//! caller is refused.
+//!
+//! A database per namespace rather than a namespace column filtered in every
+//! DAO query: the isolation then holds for every query, including ones added
+//! later, instead of depending on each remembering its filter, and the
+//! scanner, exports and retention work unchanged. The principal is what the
+//! namespace scopes in place of API keys, which the API does not have.
+
+use super::{RuntimeScanState, ScanController};
+use crate::cli::Args;
+use crate::dao::{Namespace, SqliteDB};
+use anyhow::{anyhow, Context, Result};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+/// Longest namespace name.
+const MAX_NAME_CHARS: usize = 64;
+/// Most principals one namespace may list.
+pub const MAX_NAMESPACE_PRINCIPALS: usize = 100;
+
+/// What a namespaced request runs against.
+#[derive(Clone)]
+pub struct Tenant {
+    pub name: String,
+    pub db: SqliteDB,
+    pub controller: Arc<ScanController>,
+    /// Never set: CLI scans only write the main database
+    pub runtime: RuntimeScanState,
+}
+
+pub enum NamespaceOutcome {
+    Done(Namespace),
+    Invalid(String),
+    /// The name, or one of the principals, belongs to another namespace,
+    /// or the namespace is scanning
+    Conflict(String),
+    NotFound,
+}
+
+#[derive(Default)]
+struct Registry {
+    tenants: BTreeMap<String, Tenant>,
+    /// Principal -> namespace name
+    principals: HashMap<String, String>,
+}
+
+pub struct Namespaces {
+    db: SqliteDB,
+    dir: PathBuf,
+    db_key: Option<String>,
+    port_history: bool,
+    /// `--api-operator`: may use the main database once namespaces exist
+    operators: HashSet<String>,
+    registry: RwLock<Registry>,
+}
+
+impl Namespaces {
+    /// Open the database of every registered namespace under
+    /// `--namespace-dir`.
+    pub fn open(db: SqliteDB, args: &Args) -> Result<Self> {
+        let namespaces = Self {
+            db,
+            dir: PathBuf::from(&args.namespace_dir),
+            db_key: args.db_key.clone(),
+            port_history: args.port_history,
+            operators: args.api_operator.iter().cloned().collect(),
+            registry: RwLock::new(Registry::default()),
+        };
+        for namespace in namespaces.db.list_namespaces()? {
+            namespaces.load(&namespace)?;
+        }
+        Ok(namespaces)
+    }
+
+    /// The namespace `principal` is confined to, if any.
+    pub fn for_principal(&self, principal: &str) -> Option<Tenant> {
+        let registry = self.registry.read().unwrap();
+        let name = registry.principals.get(principal)?;
+        registry.tenants.get(name).cloned()
+    }
+
+    /// Whether any namespace is registered. From then on a caller must be
+    /// in a namespace or an operator.
+    pub fn is_active(&self) -> bool {
+        !self.registry.read().unwrap().tenants.is_empty()
+    }
+
+    pub fn is_operator(&self, principal: &str) -> bool {
+        self.operators.contains(principal)
+    }
+
+    pub fn list(&self) -> Result<Vec<Namespace>> {
+        self.db.list_namespaces()
+    }
+
+    /// Register a namespace and create its database.
+    pub fn create(&self, name: &str, principals: Vec<String>) -> Result<NamespaceOutcome> {
+        if let Err(reason) = check_name(name) {
+            return Ok(NamespaceOutcome::Invalid(reason));
+        }
+        if self.operators.is_empty() {
+            // Otherwise nobody could reach /admin once the namespace exists.
+            return Ok(NamespaceOutcome::Invalid(
+                "Set --api-operator before creating a namespace".to_string(),
+            ));
+        }
+        let principals = match self.check_principals(principals) {
+            Ok(principals) => principals,
+            Err(reason) => return Ok(NamespaceOutcome::Invalid(reason)),
+        };
+        // Held across the insert so two creates cannot claim one principal.
+        let mut registry = self.registry.write().unwrap();
+        if let Some(reason) = claimed(&registry, name, &principals) {
+            return Ok(NamespaceOutcome::Conflict(reason));
+        }
+        let Some(namespace) = self.db.create_namespace(name, &principals)? else {
+            return Ok(NamespaceOutcome::Conflict(format!(
+                "Namespace {:?} already exists",
+                name
+            )));
+        };
+        match self.open_tenant(name) {
+            Ok(tenant) => {
+                register(&mut registry, tenant, &principals);
+                info!("Created namespace {:?}", name);
+                Ok(NamespaceOutcome::Done(namespace))
+            }
+            Err(e) => {
+                self.db.delete_namespace(name)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Replace the principals of a namespace.
+    pub fn update(&self, name: &str, principals: Vec<String>) -> Result<NamespaceOutcome> {
+        let principals = match self.check_principals(principals) {
+            Ok(principals) => principals,
+            Err(reason) => return Ok(NamespaceOutcome::Invalid(reason)),
+        };
+        let mut registry = self.registry.write().unwrap();
+        let Some(tenant) = registry.tenants.get(name).cloned() else {
+            return Ok(NamespaceOutcome::NotFound);
+        };
+        if let Some(reason) = claimed(&registry, name, &principals) {
+            return Ok(NamespaceOutcome::Conflict(reason));
+        }
+        let Some(namespace) = self.db.update_namespace(name, &principals)? else {
+            return Ok(NamespaceOutcome::NotFound);
+        };
+        registry.principals.retain(|_, owner| owner != name);
+        register(&mut registry, tenant, &principals);
+        Ok(NamespaceOutcome::Done(namespace))
+    }
+
+    /// Unregister a namespace; refused while it is scanning. Its database
+    /// file is kept.
+    pub async fn delete(&self, name: &str) -> Result<NamespaceOutcome> {
+        let tenant = self.registry.read().unwrap().tenants.get(name).cloned();
+        let Some(tenant) = tenant else {
+            return Ok(NamespaceOutcome::NotFound);
+        };
+        if tenant.controller.has_active_task().await {
+            return Ok(NamespaceOutcome::Conflict(format!(
+                "Namespace {:?} has a scan running; stop it first",
+                name
+            )));
+        }
+        let Some(namespace) = self.db.get_namespace(name)? else {
+            return Ok(NamespaceOutcome::NotFound);
+        };
+        let mut registry = self.registry.write().unwrap();
+        self.db.delete_namespace(name)?;
+        registry.tenants.remove(name);
+        registry.principals.retain(|_, owner| owner != name);
+        info!("Deleted namespace {:?}", name);
+        Ok(NamespaceOutcome::Done(namespace))
+    }
+
+    /// [`check_principals`], also refusing operators, who must keep the
+    /// main database.
+    fn check_principals(&self, principals: Vec<String>) -> Result<Vec<String>, String> {
+        let principals = check_principals(principals)?;
+        match principals.iter().find(|p| self.is_operator(p)) {
+            Some(operator) => Err(format!(
+                "{:?} is an --api-operator and cannot be confined to a namespace",
+                operator
+            )),
+            None => Ok(principals),
+        }
+    }
+
+    fn load(&self, namespace: &Namespace) -> Result<()> {
+        let tenant = self.open_tenant(&namespace.name)?;
+        let mut registry = self.registry.write().unwrap();
+        register(&mut registry, tenant, &namespace.principals);
+        Ok(())
+    }
+
+    fn open_tenant(&self, name: &str) -> Result<Tenant> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("creating namespace directory {}", self.dir.display()))?;
+        let path = self.dir.join(format!("{}.db", name));
+        let path = path
+            .to_str()
+            .ok_or_else(|| anyhow!("{} is not valid UTF-8", path.display()))?;
+        let db = SqliteDB::with_key(path, self.db_key.as_deref())
+            .with_context(|| format!("opening namespace database {}", path))?
+            .with_port_history(self.port_history);
+        Ok(Tenant {
+            name: name.to_string(),
+            controller: Arc::new(ScanController::new(db.clone())),
+            db,
+            runtime: RuntimeScanState::default(),
+        })
+    }
+}
+
+fn register(registry: &mut Registry, tenant: Tenant, principals: &[String]) {
+    for principal in principals {
+        registry
+            .principals
+            .insert(principal.clone(), tenant.name.clone());
+    }
+    registry.tenants.insert(tenant.name.clone(), tenant);
+}
+
+/// Why `principals` cannot go to namespace `name`, if one of them is
+/// already confined to another.
+fn claimed(registry: &Registry, name: &str, principals: &[String]) -> Option<String> {
+    principals.iter().find_map(|principal| {
+        registry
+            .principals
+            .get(principal)
+            .filter(|owner| owner.as_str() != name)
+            .map(|owner| {
+                format!(
+                    "Principal {:?} already belongs to namespace {:?}",
+                    principal, owner
+                )
+            })
+    })
+}
+
+/// Names become file names: lowercase letters, digits, `-` and `_`,
+/// starting with a letter or digit.
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_CHARS
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Namespace names are 1-{} lowercase letters, digits, '-' or '_', starting with a letter or digit",
+            MAX_NAME_CHARS
+        ))
+    }
+}
+
+/// Trimmed and deduplicated, in the order given.
+fn check_principals(principals: Vec<String>) -> Result<Vec<String>, String> {
+    let mut checked: Vec<String> = Vec::with_capacity(principals.len());
+    for principal in principals {
+        let principal = principal.trim().to_string();
+        if principal.is_empty() {
+            return Err("Principals must not be empty".to_string());
+        }
+        if !checked.contains(&principal) {
+            checked.push(principal);
+        }
+    }
+    if checked.is_empty() {
+        return Err("A namespace needs at least one principal".to_string());
+    }
+    if checked.len() > MAX_NAMESPACE_PRINCIPALS {
+        return Err(format!(
+            "A namespace may list at most {} principals",
+            MAX_NAMESPACE_PRINCIPALS
+        ));
+    }
+    Ok(checked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn names(principals: &[&str]) -> Vec<String> {
+        principals.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_namespaces_confine_their_principals_to_their_own_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut args = Args::try_parse_from(["ip-scan"]).unwrap();
+        args.namespace_dir = dir.path().join("ns").to_str().unwrap().to_string();
+        let main = SqliteDB::new(":memory:").unwrap();
+        let namespaces = Namespaces::open(main.clone(), &args).unwrap();
+        // Without operators nobody could manage namespaces once one exists.
+        assert!(matches!(
+            namespaces.create("red", names(&["alice"])).unwrap(),
+            NamespaceOutcome::Invalid(_)
+        ));
+        args.api_operator = names(&["root"]);
+        let namespaces = Namespaces::open(main.clone(), &args).unwrap();
+        assert!(!namespaces.is_active());
+        assert!(matches!(
+            namespaces.create("red", names(&["alice", "root"])).unwrap(),
+            NamespaceOutcome::Invalid(_)
+        ));
+
+        let created = namespaces
+            .create("red", names(&["alice", " bob", "alice"]))
+            .unwrap();
+        assert!(
+            matches!(created, NamespaceOutcome::Done(ref ns) if ns.principals == names(&["alice", "bob"]))
+        );
+        assert!(matches!(
+            namespaces.create("red", names(&["carol"])).unwrap(),
+            NamespaceOutcome::Conflict(_)
+        ));
+        assert!(matches!(
+            namespaces.create("blue", names(&["bob"])).unwrap(),
+            NamespaceOutcome::Conflict(_)
+        ));
+        assert!(matches!(
+            namespaces.create("../etc", names(&["carol"])).unwrap(),
+            NamespaceOutcome::Invalid(_)
+        ));
+        assert!(matches!(
+            namespaces.create("blue", Vec::new()).unwrap(),
+            NamespaceOutcome::Invalid(_)
+        ));
+        assert!(matches!(
+            namespaces.create("blue", names(&["carol"])).unwrap(),
+            NamespaceOutcome::Done(_)
+        ));
+        assert!(namespaces.is_active());
+        assert!(namespaces.is_operator("root"));
+
+        let red = namespaces.for_principal("alice").unwrap();
+        red.db
+            .bulk_update_port_status(vec![("192.0.2.1".parse().unwrap(), 22, true)], 1)
+            .unwrap();
+        assert_eq!(red.db.get_total_open_ports_count().unwrap(), 1);
+        assert_eq!(main.get_total_open_ports_count().unwrap(), 0);
+        let blue = namespaces.for_principal("carol").unwrap();
+        assert_eq!(blue.db.get_total_open_ports_count().unwrap(), 0);
+        assert!(namespaces.for_principal("mallory").is_none());
+
+        // Reopening reads the registry and the namespace databases back.
+        let reopened = Namespaces::open(main.clone(), &args).unwrap();
+        let red = reopened.for_principal("bob").unwrap();
+        assert_eq!(red.name, "red");
+        assert_eq!(red.db.get_total_open_ports_count().unwrap(), 1);
+
+        assert!(matches!(
+            reopened.update("red", names(&["dave"])).unwrap(),
+            NamespaceOutcome::Done(_)
+        ));
+        assert!(reopened.for_principal("alice").is_none());
+        assert_eq!(reopened.for_principal("dave").unwrap().name, "red");
+        assert!(matches!(
+            reopened.update("green", names(&["erin"])).unwrap(),
+            NamespaceOutcome::NotFound
+        ));
+
+        assert!(matches!(
+            reopened.delete("red").await.unwrap(),
+            NamespaceOutcome::Done(_)
+        ));
+        assert!(reopened.for_principal("dave").is_none());
+        assert_eq!(
+            reopened
+                .list()
+                .unwrap()
+                .into_iter()
+                .map(|ns| ns.name)
+                .collect::<Vec<_>>(),
+            vec!["blue"]
+        );
+        assert!(dir.path().join("ns/red.db").exists());
+    }
+}
@@ -0,0 +1,667 @@
+//! Scan event bus and webhook notifiers (Slack, Discord, Telegram bots,
+//! generic JSON), plus PagerDuty and Opsgenie incidents for the events that
+//! should page someone.
+//!
+//! Scanners, the Geo worker and the round loop publish [`ScanEvent`]s to an
+//! [`EventBus`]; every configured `[[notify]]` sink runs as its own task with
+//! its own filter, rate limit and HTTP timeout, so a slow or failing endpoint
+//! only loses its own messages. Incidents carry a deduplication key per
+//! (ip, port), so an exposure seen again every round stays one incident.
+
+use super::RateLimiter;
+use crate::cli::NotifyConfig;
+use crate::dao::RoundMetrics;
+use crate::model::{ExcludeList, OpenPort};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Events buffered per sink before it starts lagging and dropping the oldest.
+const BUS_BUFFER: usize = 4096;
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+const TELEGRAM_API: &str = "https://api.telegram.org";
+const PAGERDUTY_EVENTS: &str = "https://events.pagerduty.com/v2/enqueue";
+const OPSGENIE_ALERTS: &str = "https://api.opsgenie.com/v2/alerts";
+/// Opsgenie truncates alert messages beyond this many characters.
+const OPSGENIE_MESSAGE_LEN: usize = 130;
+const SEVERITIES: [&str; 4] = ["critical", "error", "warning", "info"];
+
+#[derive(Debug, Clone)]
+pub enum ScanEvent {
+    OpenPort(OpenPort),
+    /// An IP was geolocated to a country no earlier result had.
+    NewCountry {
+        ip: String,
+        country: String,
+    },
+    RoundComplete(RoundMetrics),
+}
+
+impl ScanEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ScanEvent::OpenPort(_) => "open_port",
+            ScanEvent::NewCountry { .. } => "new_country",
+            ScanEvent::RoundComplete(_) => "round_complete",
+        }
+    }
+
+    /// Template placeholders; fields an event does not have are left out and
+    /// render as empty strings.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("event", self.kind().to_string())];
+        match self {
+            ScanEvent::OpenPort(open) => {
+                fields.push(("ip", open.ip.to_string()));
+                fields.push(("port", open.port.to_string()));
+                fields.push(("round", open.scan_round.to_string()));
+            }
+            ScanEvent::NewCountry { ip, country } => {
+                fields.push(("ip", ip.clone()));
+                fields.push(("country", country.clone()));
+            }
+            ScanEvent::RoundComplete(m) => {
+                fields.push(("round", m.round.to_string()));
+                fields.push(("scanned", m.scanned.to_string()));
+                fields.push(("open", m.open.to_string()));
+                fields.push(("errors", m.errors.to_string()));
+                fields.push(("duration_secs", format!("{:.1}", m.duration_secs)));
+            }
+        }
+        fields
+    }
+
+    /// The event's fields as a flat JSON object of strings.
+    pub fn to_json(&self) -> Value {
+        Value::Object(
+            self.fields()
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), Value::String(value)))
+                .collect(),
+        )
+    }
+
+    /// Incident deduplication key: repeats of the same exposure update one
+    /// incident instead of paging again.
+    pub fn dedup_key(&self) -> String {
+        match self {
+            ScanEvent::OpenPort(open) => format!("ip-scan/open_port/{}/{}", open.ip, open.port),
+            ScanEvent::NewCountry { country, .. } => format!("ip-scan/new_country/{}", country),
+            ScanEvent::RoundComplete(m) => format!("ip-scan/round_complete/{}", m.round),
+        }
+    }
+
+    pub(crate) fn default_template(&self) -> &'static str {
+        match self {
+            ScanEvent::OpenPort(_) => "Open port {{ip}}:{{port}} (round {{round}})",
+            ScanEvent::NewCountry { .. } => "First result from {{country}}: {{ip}}",
+            ScanEvent::RoundComplete(_) => {
+                "Round {{round}} finished: {{scanned}} scanned, {{open}} open, {{errors}} errors in {{duration_secs}}s"
+            }
+        }
+    }
+}
+
+/// Replace `{{name}}` placeholders; unknown placeholders render empty.
+pub(crate) fn render_template(template: &str, fields: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find("}}") else {
+            // Unclosed: keep the rest, braces included, as literal text.
+            rest = &rest[start..];
+            break;
+        };
+        let name = rest[start + 2..start + 2 + len].trim();
+        if let Some((_, value)) = fields.iter().find(|(key, _)| *key == name) {
+            out.push_str(value);
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<ScanEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(BUS_BUFFER).0,
+        }
+    }
+
+    /// Never blocks; events published with no sink subscribed are dropped.
+    pub fn publish(&self, event: ScanEvent) {
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ScanEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Republish a scanner's open-port stream until [`Forwarder::finish`].
+    pub fn forward(&self, mut events: broadcast::Receiver<OpenPort>) -> Forwarder {
+        let bus = self.clone();
+        let (done_tx, mut done_rx) = oneshot::channel::<()>();
+        let task = tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    event = events.recv() => event,
+                    _ = &mut done_rx => break,
+                };
+                match event {
+                    Ok(open) => bus.publish(ScanEvent::OpenPort(open)),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!(
+                            "Event forwarder lagged, {} open ports not published",
+                            missed
+                        )
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+            while let Ok(open) = events.try_recv() {
+                bus.publish(ScanEvent::OpenPort(open));
+            }
+        });
+        Forwarder { done_tx, task }
+    }
+}
+
+/// Handle for a scanner-to-bus forwarding task.
+pub struct Forwarder {
+    done_tx: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl Forwarder {
+    /// Publish what the scanner already sent, then stop. SYN receiver
+    /// threads keep their sender alive, so the stream never ends by itself.
+    pub async fn finish(self) {
+        let _ = self.done_tx.send(());
+        let _ = self.task.await;
+    }
+}
+
+pub(crate) const EVENT_KINDS: [&str; 3] = ["open_port", "new_country", "round_complete"];
+
+impl NotifyConfig {
+    fn validate(&self) -> Result<()> {
+        if !matches!(
+            self.kind.as_str(),
+            "slack" | "discord" | "telegram" | "pagerduty" | "opsgenie" | "webhook"
+        ) {
+            return Err(anyhow!(
+                "Invalid [[notify]] kind {}; expected slack, discord, telegram, pagerduty, opsgenie or webhook",
+                self.kind
+            ));
+        }
+        if let Some((field, var)) = self.credential_source() {
+            if self.credential().is_none() {
+                return Err(anyhow!(
+                    "[[notify]] {} needs {} or {}",
+                    self.kind,
+                    field,
+                    var
+                ));
+            }
+        }
+        if self.kind == "telegram"
+            && self
+                .chat_id
+                .as_deref()
+                .is_none_or(|id| id.trim().is_empty())
+        {
+            return Err(anyhow!("[[notify]] telegram needs chat_id"));
+        }
+        if let Some(severity) = &self.severity {
+            if !SEVERITIES.contains(&severity.as_str()) {
+                return Err(anyhow!(
+                    "Invalid [[notify]] severity {}; expected one of {}",
+                    severity,
+                    SEVERITIES.join(", ")
+                ));
+            }
+        }
+        self.owned_ranges()?;
+        let url = reqwest::Url::parse(self.base_url())
+            .map_err(|e| anyhow!("Invalid [[notify]] url for {}: {}", self.kind, e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("[[notify]] url must be http(s): {}", self.kind));
+        }
+        if let Some(event) = self
+            .events
+            .iter()
+            .find(|e| !EVENT_KINDS.contains(&e.as_str()))
+        {
+            return Err(anyhow!(
+                "Invalid [[notify]] event {}; expected one of {}",
+                event,
+                EVENT_KINDS.join(", ")
+            ));
+        }
+        if self.rate_per_minute == 0 {
+            return Err(anyhow!("[[notify]] rate_per_minute must be positive"));
+        }
+        Ok(())
+    }
+
+    /// The config field and environment variable holding the sink's
+    /// credential, for kinds that need one.
+    fn credential_source(&self) -> Option<(&'static str, &'static str)> {
+        match self.kind.as_str() {
+            "telegram" => Some(("bot_token", "SCAN_TELEGRAM_BOT_TOKEN")),
+            "pagerduty" => Some(("routing_key", "SCAN_PAGERDUTY_ROUTING_KEY")),
+            "opsgenie" => Some(("api_key", "SCAN_OPSGENIE_API_KEY")),
+            _ => None,
+        }
+    }
+
+    /// The bot token, routing key or API key; the config field wins over
+    /// the environment variable.
+    fn credential(&self) -> Option<String> {
+        let (_, var) = self.credential_source()?;
+        let field = match self.kind.as_str() {
+            "telegram" => &self.bot_token,
+            "pagerduty" => &self.routing_key,
+            _ => &self.api_key,
+        };
+        field
+            .clone()
+            .or_else(|| std::env::var(var).ok())
+            .filter(|key| !key.trim().is_empty())
+    }
+
+    fn base_url(&self) -> &str {
+        if !self.url.is_empty() {
+            return &self.url;
+        }
+        match self.kind.as_str() {
+            "telegram" => TELEGRAM_API,
+            "pagerduty" => PAGERDUTY_EVENTS,
+            "opsgenie" => OPSGENIE_ALERTS,
+            _ => &self.url,
+        }
+    }
+
+    /// Where messages are posted. The Telegram URL holds the bot token, so
+    /// it never goes into logs.
+    fn endpoint(&self) -> String {
+        match self.kind.as_str() {
+            "telegram" => format!(
+                "{}/bot{}/sendMessage",
+                self.base_url().trim_end_matches('/'),
+                self.credential().unwrap_or_default()
+            ),
+            _ => self.base_url().to_string(),
+        }
+    }
+
+    /// The `ranges` filter, `None` when it is empty.
+    fn owned_ranges(&self) -> Result<Option<ExcludeList>> {
+        if self.ranges.is_empty() {
+            return Ok(None);
+        }
+        ExcludeList::parse(&self.ranges.join("\n"))
+            .map(Some)
+            .map_err(|e| anyhow!("Invalid [[notify]] ranges for {}: {}", self.kind, e))
+    }
+
+    fn request(
+        &self,
+        client: &reqwest::Client,
+        endpoint: &str,
+        event: &ScanEvent,
+    ) -> reqwest::RequestBuilder {
+        let request = client.post(endpoint).json(&self.payload(event));
+        match self.kind.as_str() {
+            "opsgenie" => request.header(
+                reqwest::header::AUTHORIZATION,
+                format!("GenieKey {}", self.credential().unwrap_or_default()),
+            ),
+            _ => request,
+        }
+    }
+
+    pub fn matches(&self, event: &ScanEvent) -> bool {
+        if !self.events.is_empty() && !self.events.iter().any(|e| e == event.kind()) {
+            return false;
+        }
+        match event {
+            ScanEvent::OpenPort(open) => self.ports.is_empty() || self.ports.contains(&open.port),
+            ScanEvent::NewCountry { country, .. } => {
+                self.countries.is_empty()
+                    || self
+                        .countries
+                        .iter()
+                        .any(|c| c.eq_ignore_ascii_case(country))
+            }
+            ScanEvent::RoundComplete(_) => true,
+        }
+    }
+
+    pub fn payload(&self, event: &ScanEvent) -> Value {
+        let fields = event.fields();
+        let text = render_template(
+            self.template.as_deref().unwrap_or(event.default_template()),
+            &fields,
+        );
+        match self.kind.as_str() {
+            "slack" => json!({ "text": text }),
+            "discord" => json!({ "content": text }),
+            "telegram" => json!({
+                "chat_id": self.chat_id,
+                "text": text,
+                "disable_web_page_preview": true,
+            }),
+            "pagerduty" => json!({
+                "routing_key": self.credential(),
+                "event_action": "trigger",
+                "dedup_key": event.dedup_key(),
+                "payload": {
+                    "summary": text,
+                    "source": "ip-scan",
+                    "severity": self.severity.as_deref().unwrap_or("critical"),
+                    "class": event.kind(),
+                    "custom_details": event.to_json(),
+                },
+            }),
+            "opsgenie" => {
+                let priority = match self.severity.as_deref().unwrap_or("critical") {
+                    "critical" => "P1",
+                    "error" => "P2",
+                    "warning" => "P3",
+                    _ => "P5",
+                };
+                json!({
+                    "message": text.chars().take(OPSGENIE_MESSAGE_LEN).collect::<String>(),
+                    "alias": event.dedup_key(),
+                    "description": text,
+                    "priority": priority,
+                    "source": "ip-scan",
+                    "tags": ["ip-scan", event.kind()],
+                    "details": event.to_json(),
+                })
+            }
+            _ => {
+                let mut body = event.to_json();
+                body["text"] = Value::String(text);
+                body
+            }
+        }
+    }
+}
+
+/// Check every `[[notify]]` sink, so a typo fails at startup.
+pub fn validate_notifiers(configs: &[NotifyConfig]) -> Result<()> {
+    configs.iter().try_for_each(NotifyConfig::validate)
+}
+
+/// Validate every sink, then start one delivery task per sink.
+pub fn spawn_notifiers(configs: &[NotifyConfig], bus: &EventBus) -> Result<Vec<JoinHandle<()>>> {
+    validate_notifiers(configs)?;
+    let client = reqwest::Client::builder()
+        .timeout(SEND_TIMEOUT)
+        .user_agent(concat!("ip-scan/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    Ok(configs
+        .iter()
+        .cloned()
+        .map(|config| {
+            let client = client.clone();
+            let events = bus.subscribe();
+            tokio::spawn(run_notifier(config, client, events))
+        })
+        .collect())
+}
+
+/// Whether an open port lies in a sink's `ranges`; other events always pass.
+fn in_ranges(ranges: Option<&ExcludeList>, event: &ScanEvent) -> bool {
+    match (ranges, event) {
+        (Some(ranges), ScanEvent::OpenPort(open)) => ranges.contains(open.ip),
+        _ => true,
+    }
+}
+
+async fn run_notifier(
+    config: NotifyConfig,
+    client: reqwest::Client,
+    mut events: broadcast::Receiver<ScanEvent>,
+) {
+    let limiter = RateLimiter::new(config.rate_per_minute as usize, Duration::from_secs(60));
+    let endpoint = config.endpoint();
+    // Checked when the sinks were validated.
+    let ranges = config.owned_ranges().ok().flatten();
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("{} notifier lagged, {} events dropped", config.kind, missed);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if !config.matches(&event) || !in_ranges(ranges.as_ref(), &event) {
+            continue;
+        }
+        limiter.acquire().await;
+        let result = config
+            .request(&client, &endpoint, &event)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status());
+        match result {
+            Ok(_) => debug!("Sent {} notification for {}", config.kind, event.kind()),
+            // Webhook URLs and the Telegram endpoint carry secrets.
+            Err(e) => warn!("{} notification failed: {}", config.kind, e.without_url()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(kind: &str) -> NotifyConfig {
+        NotifyConfig {
+            kind: kind.to_string(),
+            url: "https://hooks.example.com/x".to_string(),
+            bot_token: None,
+            chat_id: None,
+            routing_key: None,
+            api_key: None,
+            severity: None,
+            events: Vec::new(),
+            ports: Vec::new(),
+            ranges: Vec::new(),
+            countries: Vec::new(),
+            template: None,
+            rate_per_minute: 30,
+        }
+    }
+
+    fn open_port(port: u16) -> ScanEvent {
+        ScanEvent::OpenPort(OpenPort {
+            ip: "192.0.2.7".parse().unwrap(),
+            port,
+            scan_round: 3,
+        })
+    }
+
+    #[test]
+    fn test_render_template_fills_placeholders() {
+        let fields = [("ip", "192.0.2.7".to_string()), ("port", "22".to_string())];
+        assert_eq!(
+            render_template("{{ip}}:{{ port }} {{missing}}!", &fields),
+            "192.0.2.7:22 !"
+        );
+        assert_eq!(render_template("abc {{x", &fields), "abc {{x");
+        assert_eq!(
+            render_template("{{ip}} {{port", &fields),
+            "192.0.2.7 {{port"
+        );
+    }
+
+    #[test]
+    fn test_filters_by_event_port_and_country() {
+        let mut slack = config("slack");
+        slack.events = vec!["open_port".to_string(), "new_country".to_string()];
+        slack.ports = vec![3389, 445];
+        slack.countries = vec!["cn".to_string()];
+        assert!(slack.validate().is_ok());
+
+        assert!(slack.matches(&open_port(445)));
+        assert!(!slack.matches(&open_port(80)));
+        let country = |c: &str| ScanEvent::NewCountry {
+            ip: "192.0.2.7".to_string(),
+            country: c.to_string(),
+        };
+        assert!(slack.matches(&country("CN")));
+        assert!(!slack.matches(&country("US")));
+        assert!(!slack.matches(&ScanEvent::RoundComplete(RoundMetrics {
+            round: 3,
+            scanned: 10,
+            open: 1,
+            errors: 0,
+            retries: 0,
+            duration_secs: 1.0,
+            avg_rate: 10.0,
+            finished_at: String::new(),
+        })));
+    }
+
+    #[test]
+    fn test_payload_per_kind() {
+        assert_eq!(
+            config("slack").payload(&open_port(22)),
+            json!({"text": "Open port 192.0.2.7:22 (round 3)"})
+        );
+        let mut discord = config("discord");
+        discord.template = Some(":warning: {{ip}}:{{port}} {{country}}!".to_string());
+        assert_eq!(
+            discord.payload(&open_port(3389)),
+            json!({"content": ":warning: 192.0.2.7:3389 !"})
+        );
+        let webhook = config("webhook").payload(&open_port(22));
+        assert_eq!(webhook["event"], "open_port");
+        assert_eq!(webhook["port"], "22");
+        assert_eq!(webhook["text"], "Open port 192.0.2.7:22 (round 3)");
+    }
+
+    #[test]
+    fn test_telegram_posts_to_the_bot_api() {
+        let mut telegram = config("telegram");
+        telegram.url = String::new();
+        telegram.bot_token = Some("123:abc".to_string());
+        telegram.chat_id = Some("@secops".to_string());
+        assert!(telegram.validate().is_ok());
+        assert_eq!(
+            telegram.endpoint(),
+            "https://api.telegram.org/bot123:abc/sendMessage"
+        );
+        assert_eq!(
+            telegram.payload(&open_port(22)),
+            json!({
+                "chat_id": "@secops",
+                "text": "Open port 192.0.2.7:22 (round 3)",
+                "disable_web_page_preview": true,
+            })
+        );
+
+        telegram.url = "http://127.0.0.1:8081/".to_string();
+        assert_eq!(
+            telegram.endpoint(),
+            "http://127.0.0.1:8081/bot123:abc/sendMessage"
+        );
+        telegram.chat_id = None;
+        assert!(telegram.validate().is_err());
+    }
+
+    #[test]
+    fn test_escalations_dedup_per_ip_and_port() {
+        let mut pagerduty = config("pagerduty");
+        pagerduty.url = String::new();
+        pagerduty.routing_key = Some("R0UT1NG".to_string());
+        pagerduty.ports = vec![3389, 445];
+        pagerduty.ranges = vec!["192.0.2.0/24".to_string()];
+        assert!(pagerduty.validate().is_ok());
+        assert_eq!(pagerduty.endpoint(), PAGERDUTY_EVENTS);
+
+        let body = pagerduty.payload(&open_port(3389));
+        assert_eq!(body["routing_key"], "R0UT1NG");
+        assert_eq!(body["dedup_key"], "ip-scan/open_port/192.0.2.7/3389");
+        assert_eq!(body["payload"]["severity"], "critical");
+        assert_eq!(body["payload"]["custom_details"]["port"], "3389");
+
+        let ranges = pagerduty.owned_ranges().unwrap();
+        assert!(in_ranges(ranges.as_ref(), &open_port(3389)));
+        let outside = ScanEvent::OpenPort(OpenPort {
+            ip: "198.51.100.7".parse().unwrap(),
+            port: 3389,
+            scan_round: 3,
+        });
+        assert!(!in_ranges(ranges.as_ref(), &outside));
+
+        let mut opsgenie = config("opsgenie");
+        opsgenie.url = String::new();
+        opsgenie.api_key = Some("g3n1e".to_string());
+        opsgenie.severity = Some("warning".to_string());
+        assert!(opsgenie.validate().is_ok());
+        let body = opsgenie.payload(&open_port(445));
+        assert_eq!(body["alias"], "ip-scan/open_port/192.0.2.7/445");
+        assert_eq!(body["priority"], "P3");
+        let request = opsgenie
+            .request(&reqwest::Client::new(), OPSGENIE_ALERTS, &open_port(445))
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["authorization"], "GenieKey g3n1e");
+
+        opsgenie.severity = Some("sev1".to_string());
+        assert!(opsgenie.validate().is_err());
+        pagerduty.ranges = vec!["not-a-range".to_string()];
+        assert!(pagerduty.validate().is_err());
+    }
+
+    #[test]
+    fn test_rejects_invalid_sinks() {
+        assert!(config("teams").validate().is_err());
+        let mut bad_event = config("slack");
+        bad_event.events = vec!["closed_port".to_string()];
+        assert!(bad_event.validate().is_err());
+        let mut bad_url = config("discord");
+        bad_url.url = "ftp://example.com".to_string();
+        assert!(bad_url.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_forwarder_drains_on_finish() {
+        let bus = EventBus::new();
+        let mut sink = bus.subscribe();
+        let (scanner_tx, scanner_rx) = broadcast::channel(8);
+        let forwarder = bus.forward(scanner_rx);
+        scanner_tx
+            .send(OpenPort {
+                ip: "192.0.2.7".parse().unwrap(),
+                port: 22,
+                scan_round: 1,
+            })
+            .unwrap();
+        // The scanner keeps its sender, as SYN receiver threads do.
+        forwarder.finish().await;
+        assert!(matches!(sink.recv().await, Ok(ScanEvent::OpenPort(open)) if open.port == 22));
+    }
+}
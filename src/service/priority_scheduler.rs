@@ -0,0 +1,161 @@
+//! Extra probes for hosts whose open ports changed recently
+//! (`--priority-weights`).
+//!
+//! In loop mode every host is scanned once per round. A host whose ports
+//! opened or closed in the round just completed is scanned `weights[0]`
+//! times in the next round, `weights[1]` times in the round after, and so on
+//! until it is back to once per round. Changes churn in a small set of hosts,
+//! so this tracks them closely without shortening the round for everyone.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::net::Ipv4Addr;
+
+/// Changed hosts taken from one round diff.
+pub const PRIORITY_HOST_LIMIT: usize = 10_000;
+
+pub struct PriorityScheduler {
+    weights: Vec<u32>,
+    /// Rounds since each boosted host last changed, as an index into
+    /// `weights`.
+    ages: HashMap<Ipv4Addr, usize>,
+}
+
+impl PriorityScheduler {
+    pub fn new(weights: Vec<u32>) -> Self {
+        PriorityScheduler {
+            weights,
+            ages: HashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.weights.is_empty()
+    }
+
+    /// Hosts currently scanned more than once per round.
+    pub fn boosted_hosts(&self) -> usize {
+        self.ages.len()
+    }
+
+    /// Age every boosted host by one round and boost the hosts that changed
+    /// in the round just completed from the start again.
+    pub fn record_round(&mut self, changed: impl IntoIterator<Item = Ipv4Addr>) {
+        let rounds = self.weights.len();
+        self.ages.retain(|_, age| {
+            *age += 1;
+            *age < rounds
+        });
+        if rounds == 0 {
+            return;
+        }
+        for host in changed {
+            self.ages.insert(host, 0);
+        }
+    }
+
+    /// Extra probes for a round over `start..=end`. A host at offset `p`
+    /// with weight `w` is probed again at offsets `p + k * len / w`, so its
+    /// scans are spread evenly across the round; repeats that would fall
+    /// past the end are dropped.
+    pub fn plan(&self, start: Ipv4Addr, end: Ipv4Addr) -> RescanQueue {
+        let (start, end) = (u32::from(start) as u64, u32::from(end) as u64);
+        let len = (end + 1).saturating_sub(start);
+        let mut due = BinaryHeap::new();
+        for (&host, &age) in &self.ages {
+            let host_index = u32::from(host) as u64;
+            if host_index < start || host_index > end {
+                continue;
+            }
+            let weight = self.weights[age].max(1) as u64;
+            let interval = (len / weight).max(1);
+            for k in 1..weight {
+                let at = host_index + k * interval;
+                if at > end {
+                    break;
+                }
+                due.push(Reverse((at as u32, host)));
+            }
+        }
+        RescanQueue { due }
+    }
+}
+
+/// Extra probes for one round, ordered by the range position they are due
+/// at.
+#[derive(Default)]
+pub struct RescanQueue {
+    due: BinaryHeap<Reverse<(u32, Ipv4Addr)>>,
+}
+
+impl RescanQueue {
+    pub fn len(&self) -> usize {
+        self.due.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.due.is_empty()
+    }
+
+    /// Next repeat due once the range has reached `current`. Every repeat
+    /// is of a host at or before `current`, so scanners that checkpoint the
+    /// last dispatched address never save a resume point past unscanned
+    /// targets.
+    pub fn pop_due(&mut self, current: Ipv4Addr) -> Option<Ipv4Addr> {
+        match self.due.peek() {
+            Some(Reverse((at, _))) if *at <= u32::from(current) => {
+                self.due.pop().map(|Reverse((_, host))| host)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> Ipv4Addr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_changed_hosts_decay_through_weights() {
+        let mut scheduler = PriorityScheduler::new(vec![4, 2]);
+        scheduler.record_round([ip("10.0.0.10")]);
+        let range = (ip("10.0.0.0"), ip("10.0.0.255"));
+        assert_eq!(scheduler.plan(range.0, range.1).len(), 3);
+
+        scheduler.record_round([]);
+        assert_eq!(scheduler.plan(range.0, range.1).len(), 1);
+
+        scheduler.record_round([]);
+        assert_eq!(scheduler.boosted_hosts(), 0);
+        assert!(scheduler.plan(range.0, range.1).is_empty());
+
+        let mut disabled = PriorityScheduler::new(Vec::new());
+        disabled.record_round([ip("10.0.0.10")]);
+        assert!(!disabled.is_enabled());
+        assert_eq!(disabled.boosted_hosts(), 0);
+    }
+
+    #[test]
+    fn test_repeats_are_spread_and_never_ahead_of_the_range() {
+        let mut scheduler = PriorityScheduler::new(vec![4]);
+        scheduler.record_round([ip("10.0.0.10"), ip("10.0.0.250"), ip("192.0.2.1")]);
+        let mut queue = scheduler.plan(ip("10.0.0.0"), ip("10.0.0.255"));
+        // 10.0.0.250 has no room left in the range; 192.0.2.1 is outside it.
+        assert_eq!(queue.len(), 3);
+
+        let mut repeats = Vec::new();
+        for last in 0..=255u8 {
+            let current = Ipv4Addr::new(10, 0, 0, last);
+            while let Some(host) = queue.pop_due(current) {
+                assert!(host <= current);
+                repeats.push((last, host));
+            }
+        }
+        let host = ip("10.0.0.10");
+        assert_eq!(repeats, [(74, host), (138, host), (202, host)]);
+    }
+}
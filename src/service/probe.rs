@@ -0,0 +1,37 @@
+//! Extension point for post-detection service probes.
+
+use crate::model::ServiceInfo;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use tokio::net::TcpStream;
+
+/// The open port a probe runs against.
+pub struct ProbeContext<'a> {
+    pub ip: &'a str,
+    pub port: u16,
+    /// A fresh connection to `ip:port`, opened by the pipeline when
+    /// [`Probe::needs_socket`] returns true.
+    pub stream: Option<TcpStream>,
+}
+
+/// A service probe run against open ports by [`super::ServiceProber`].
+///
+/// Probes run in registration order, built-in HTTP, TLS and banner probes
+/// first, and record their findings in one shared [`ServiceInfo`]; later
+/// probes can build on or skip what earlier ones found.
+pub trait Probe: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Whether to run for `port`, given the findings so far. Must not do I/O.
+    fn applies_to(&self, port: u16, info: &ServiceInfo) -> bool;
+
+    fn needs_socket(&self) -> bool {
+        false
+    }
+
+    fn probe<'a>(
+        &'a self,
+        ctx: ProbeContext<'a>,
+        info: &'a mut ServiceInfo,
+    ) -> BoxFuture<'a, Result<()>>;
+}
@@ -0,0 +1,96 @@
+//! Progress bar for scans with a known end: a single pass (no `--loop-mode`)
+//! over a range or hostname list. The producer knows how many targets it
+//! will hand out, so the bar runs to targets × ports probes and takes its
+//! position and open count from [`ScanMetrics`]. It draws on stderr and only
+//! when that is a terminal; otherwise the periodic progress log stays.
+
+use crate::model::ScanMetrics;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// How often the bar reads the scanner's counters.
+const REFRESH: Duration = Duration::from_millis(200);
+
+const TEMPLATE: &str =
+    "R{prefix} [{elapsed_precise}] {wide_bar} {pos}/{len} probes, ETA {eta} ({msg})";
+
+pub struct RoundProgressBar {
+    bar: ProgressBar,
+    ticker: JoinHandle<()>,
+}
+
+impl RoundProgressBar {
+    /// Start a bar over `probes` probes, or `None` when nobody would see it.
+    pub fn start(round: i64, probes: u64, metrics: ScanMetrics) -> Option<Self> {
+        let bar = ProgressBar::new(probes);
+        if bar.is_hidden() {
+            return None;
+        }
+        bar.set_style(
+            ProgressStyle::with_template(TEMPLATE)
+                .expect("valid progress template")
+                .progress_chars("=> "),
+        );
+        bar.set_prefix(round.to_string());
+        let ticker_bar = bar.clone();
+        let ticker = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REFRESH);
+            loop {
+                interval.tick().await;
+                update(&ticker_bar, &metrics);
+            }
+        });
+        Some(Self { bar, ticker })
+    }
+}
+
+/// Dropping the bar stops the updates and clears it from the terminal.
+impl Drop for RoundProgressBar {
+    fn drop(&mut self) {
+        self.ticker.abort();
+        self.bar.finish_and_clear();
+    }
+}
+
+fn update(bar: &ProgressBar, metrics: &ScanMetrics) {
+    // Retries and priority rescans can carry the count past the estimate.
+    let scanned = metrics.get_scanned();
+    if scanned > bar.length().unwrap_or(0) {
+        bar.set_length(scanned);
+    }
+    bar.set_position(scanned);
+    bar.set_message(format!(
+        "{:.0}/s, {} open",
+        metrics.get_scan_rate(),
+        metrics.get_open()
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indicatif::ProgressDrawTarget;
+
+    #[tokio::test]
+    async fn test_bar_follows_the_scanner_metrics() {
+        // Tests do not run on a terminal.
+        assert!(RoundProgressBar::start(1, 10, ScanMetrics::new()).is_none());
+
+        let bar = ProgressBar::with_draw_target(Some(4), ProgressDrawTarget::hidden());
+        let metrics = ScanMetrics::new();
+        let target = "192.0.2.1".parse().unwrap();
+        for port in [22, 80, 443] {
+            metrics.record_scanned(target, port);
+        }
+        metrics.record_open(target, 443);
+        update(&bar, &metrics);
+        assert_eq!(bar.position(), 3);
+        assert!(bar.message().ends_with("/s, 1 open"), "{}", bar.message());
+
+        metrics.record_scanned(target, 8080);
+        metrics.record_scanned(target, 8443);
+        update(&bar, &metrics);
+        assert_eq!((bar.position(), bar.length()), (5, Some(5)));
+    }
+}
@@ -1,74 +1,93 @@
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Semaphore;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
+/// Token bucket refilled continuously at `max_rate / window`.
+///
+/// Each `acquire()` takes a token and, when the bucket is empty, reserves
+/// the next one and sleeps until it is due, so waiters are released one
+/// refill interval apart instead of all at once when a window rolls over.
+/// Clones share the same bucket.
+#[derive(Clone)]
 pub struct RateLimiter {
-    semaphore: Arc<Semaphore>,
-    max_rate: usize,
-    window_duration: Duration,
-    last_reset_ms: Arc<AtomicU64>,
+    bucket: Arc<Mutex<Bucket>>,
+    /// Tokens added per second.
+    rate: f64,
+    /// Most tokens the bucket holds, i.e. the largest burst after idling.
+    burst: f64,
+}
+
+struct Bucket {
+    /// Negative while callers are waiting on reserved tokens.
+    tokens: f64,
+    updated: Instant,
 }
 
 impl RateLimiter {
+    /// `max_rate` per `window`, with up to a full window's worth available
+    /// at once. Suits quota-style limits such as provider requests per
+    /// minute; traffic generators should pick a burst with [`Self::with_burst`].
     pub fn new(max_rate: usize, window_duration: Duration) -> Self {
+        Self::with_burst(max_rate, window_duration, max_rate.max(1))
+    }
+
+    /// `max_rate` per `window` with at most `burst` tokens banked. A burst of
+    /// 0 selects 10 ms worth of traffic (at least one token).
+    pub fn with_burst(max_rate: usize, window_duration: Duration, burst: usize) -> Self {
+        let window = window_duration.as_secs_f64().max(0.001);
+        let rate = max_rate.max(1) as f64 / window;
+        let burst = if burst == 0 {
+            (rate / 100.0).ceil().max(1.0)
+        } else {
+            burst as f64
+        };
         RateLimiter {
-            semaphore: Arc::new(Semaphore::new(max_rate)),
-            max_rate,
-            window_duration,
-            last_reset_ms: Arc::new(AtomicU64::new(now_ms())),
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: burst,
+                updated: Instant::now(),
+            })),
+            rate,
+            burst,
         }
     }
 
     pub async fn acquire(&self) {
-        let window_ms = (self.window_duration.as_millis() as u64).max(1);
-        loop {
-            let now = now_ms();
-            let last = self.last_reset_ms.load(Ordering::Acquire);
-
-            if now.saturating_sub(last) >= window_ms
-                && self
-                    .last_reset_ms
-                    .compare_exchange(last, now, Ordering::AcqRel, Ordering::Acquire)
-                    .is_ok()
-            {
-                let current = self.semaphore.available_permits();
-                if current < self.max_rate {
-                    self.semaphore.add_permits(self.max_rate - current);
-                }
-            }
-
-            if let Ok(permit) = self.semaphore.try_acquire() {
-                // A token must stay consumed until the next fixed-window refill.
-                // Dropping the permit would return it immediately and silently
-                // disable rate limiting.
-                permit.forget();
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.updated).as_secs_f64() * self.rate;
+            bucket.tokens = (bucket.tokens + refill).min(self.burst) - 1.0;
+            bucket.updated = now;
+            if bucket.tokens >= 0.0 {
                 return;
             }
+            // The deficit covers every caller queued ahead of this one.
+            Duration::from_secs_f64(-bucket.tokens / self.rate)
+        };
+        tokio::time::sleep(wait).await;
+    }
 
-            let last = self.last_reset_ms.load(Ordering::Acquire);
-            let wait_ms = last
-                .saturating_add(window_ms)
-                .saturating_sub(now_ms())
-                .max(1);
-            tokio::time::sleep(Duration::from_millis(wait_ms)).await;
-        }
+    /// Tokens in the bucket right now; negative by the tokens already
+    /// reserved by waiting callers.
+    pub fn available(&self) -> f64 {
+        let bucket = self.bucket.lock().unwrap();
+        let refill = bucket.updated.elapsed().as_secs_f64() * self.rate;
+        (bucket.tokens + refill).min(self.burst)
     }
-}
 
-impl Clone for RateLimiter {
-    fn clone(&self) -> Self {
-        RateLimiter {
-            semaphore: self.semaphore.clone(),
-            max_rate: self.max_rate,
-            window_duration: self.window_duration,
-            last_reset_ms: self.last_reset_ms.clone(),
+    /// [`Self::acquire`] that gives up as soon as `cancel` fires. Returns
+    /// false when cancelled; the caller should not send anything then.
+    pub async fn acquire_or_cancel(&self, cancel: &CancellationToken) -> bool {
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => false,
+            _ = self.acquire() => true,
         }
     }
 }
 
 #[inline]
-fn now_ms() -> u64 {
+pub(super) fn now_ms() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
@@ -90,7 +109,8 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_rate_limiter_waits_for_next_window_and_shares_clone_budget() {
+    async fn test_rate_limiter_refills_smoothly_and_shares_clone_budget() {
+        // 2 per 80 ms refills one token every 40 ms.
         let limiter = RateLimiter::new(2, Duration::from_millis(80));
         let clone = limiter.clone();
         limiter.acquire().await;
@@ -98,6 +118,61 @@ mod tests {
 
         let start = std::time::Instant::now();
         limiter.acquire().await;
-        assert!(start.elapsed() >= Duration::from_millis(60));
+        let first = start.elapsed();
+        assert!(first >= Duration::from_millis(30), "{:?}", first);
+        assert!(first < Duration::from_millis(75), "{:?}", first);
+        clone.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(70));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_spaces_out_concurrent_waiters() {
+        // 200/s with a single banked token: waiters queue 5 ms apart rather
+        // than waking together at a window boundary.
+        let limiter = RateLimiter::with_burst(200, Duration::from_secs(1), 1);
+        let start = std::time::Instant::now();
+        let waiters: Vec<_> = (0..10)
+            .map(|_| {
+                let limiter = limiter.clone();
+                tokio::spawn(async move {
+                    limiter.acquire().await;
+                    start.elapsed()
+                })
+            })
+            .collect();
+        let mut done = Vec::new();
+        for waiter in waiters {
+            done.push(waiter.await.unwrap());
+        }
+        done.sort();
+        assert!(done[0] < Duration::from_millis(20));
+        assert!(done[9] >= Duration::from_millis(40), "{:?}", done);
+        assert!(done[9] < Duration::from_millis(500), "{:?}", done);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_or_cancel_wakes_on_cancel() {
+        let limiter = RateLimiter::with_burst(1, Duration::from_secs(60), 1);
+        let cancel = CancellationToken::new();
+        assert!(limiter.acquire_or_cancel(&cancel).await);
+
+        let waiter = {
+            let limiter = limiter.clone();
+            let cancel = cancel.clone();
+            tokio::spawn(async move { limiter.acquire_or_cancel(&cancel).await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let start = std::time::Instant::now();
+        cancel.cancel();
+        assert!(!waiter.await.unwrap());
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_default_burst_is_ten_milliseconds_of_traffic() {
+        let limiter = RateLimiter::with_burst(100_000, Duration::from_secs(1), 0);
+        assert_eq!(limiter.burst, 1000.0);
+        let slow = RateLimiter::with_burst(10, Duration::from_secs(60), 0);
+        assert_eq!(slow.burst, 1.0);
     }
 }
@@ -0,0 +1,269 @@
+//! Minimal RDAP (RFC 9082/9083) client for IP network lookups.
+//!
+//! The responsible registry is found through the IANA bootstrap registry
+//! (RFC 9224), fetched once per process and cached; a failed bootstrap fetch
+//! is retried on the next lookup instead of being cached.
+
+use crate::model::{IpGeoInfo, IpRange};
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+const BOOTSTRAP_V4_URL: &str = "https://data.iana.org/rdap/ipv4.json";
+const BOOTSTRAP_V6_URL: &str = "https://data.iana.org/rdap/ipv6.json";
+
+/// A registry's address block and its RDAP base URL.
+struct BootstrapEntry {
+    start: IpAddr,
+    end: IpAddr,
+    base_url: String,
+}
+
+/// Outcome of an RDAP request that did not produce data.
+pub enum RdapError {
+    /// HTTP 429; carries the server's `Retry-After` when present.
+    Throttled(Option<Duration>),
+    /// The registry (or the bootstrap registry) could not be reached.
+    Unavailable(anyhow::Error),
+    /// The registry answered but had nothing usable (e.g. 404).
+    NoData(anyhow::Error),
+}
+
+pub struct RdapClient {
+    client: reqwest::Client,
+    bootstrap_v4: OnceCell<Vec<BootstrapEntry>>,
+    bootstrap_v6: OnceCell<Vec<BootstrapEntry>>,
+}
+
+impl RdapClient {
+    pub fn new() -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .user_agent(concat!("ip-scan/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self {
+            client,
+            bootstrap_v4: OnceCell::new(),
+            bootstrap_v6: OnceCell::new(),
+        })
+    }
+
+    pub async fn lookup(&self, ip: &str) -> std::result::Result<IpGeoInfo, RdapError> {
+        let addr: IpAddr = ip
+            .parse()
+            .map_err(|e| RdapError::NoData(anyhow!("Invalid IP {}: {}", ip, e)))?;
+        let (cell, url) = match addr {
+            IpAddr::V4(_) => (&self.bootstrap_v4, BOOTSTRAP_V4_URL),
+            IpAddr::V6(_) => (&self.bootstrap_v6, BOOTSTRAP_V6_URL),
+        };
+        let entries = cell
+            .get_or_try_init(|| self.fetch_bootstrap(url))
+            .await
+            .map_err(RdapError::Unavailable)?;
+        let base_url = find_base_url(entries, addr)
+            .ok_or_else(|| RdapError::NoData(anyhow!("No RDAP service covers {}", ip)))?;
+
+        let response = self
+            .client
+            .get(format!("{}/ip/{}", base_url.trim_end_matches('/'), ip))
+            .header("Accept", "application/rdap+json")
+            .send()
+            .await
+            .map_err(|e| RdapError::Unavailable(e.into()))?;
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(Duration::from_secs);
+            return Err(RdapError::Throttled(retry_after));
+        }
+        let body = response
+            .error_for_status()
+            .map_err(|e| RdapError::NoData(e.into()))?
+            .json::<Value>()
+            .await
+            .map_err(|e| RdapError::NoData(e.into()))?;
+        Ok(parse_ip_network(ip, &body))
+    }
+
+    async fn fetch_bootstrap(&self, url: &str) -> Result<Vec<BootstrapEntry>> {
+        let body = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch RDAP bootstrap {}", url))?
+            .error_for_status()?
+            .json::<Value>()
+            .await
+            .context("Failed to parse RDAP bootstrap")?;
+        let entries = parse_bootstrap(&body);
+        if entries.is_empty() {
+            return Err(anyhow!("RDAP bootstrap {} lists no services", url));
+        }
+        Ok(entries)
+    }
+}
+
+/// Parse an IANA bootstrap file: `services` is a list of
+/// `[[cidr, ...], [url, ...]]` pairs. HTTPS URLs are preferred.
+fn parse_bootstrap(body: &Value) -> Vec<BootstrapEntry> {
+    let mut entries = Vec::new();
+    for service in body["services"].as_array().into_iter().flatten() {
+        let urls: Vec<&str> = service[1]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        let Some(base_url) = urls
+            .iter()
+            .find(|url| url.starts_with("https://"))
+            .or_else(|| urls.first())
+        else {
+            continue;
+        };
+        for cidr in service[0].as_array().into_iter().flatten() {
+            if let Some(range) = cidr.as_str().and_then(|c| IpRange::from_cidr(c).ok()) {
+                entries.push(BootstrapEntry {
+                    start: range.start,
+                    end: range.end,
+                    base_url: base_url.to_string(),
+                });
+            }
+        }
+    }
+    entries
+}
+
+/// Pick the most specific registry block containing `ip`.
+fn find_base_url(entries: &[BootstrapEntry], ip: IpAddr) -> Option<&str> {
+    entries
+        .iter()
+        .filter(|e| e.start <= ip && ip <= e.end)
+        .max_by_key(|e| e.start)
+        .map(|e| e.base_url.as_str())
+}
+
+/// Extract country, organisation, origin ASN and abuse contact from an RDAP ip network
+/// object. Registries differ in what they include; missing fields stay empty.
+fn parse_ip_network(ip: &str, body: &Value) -> IpGeoInfo {
+    let mut info = IpGeoInfo::new(ip.to_string(), "RDAP".to_string());
+    info.country = body["country"].as_str().map(str::to_string);
+    info.isp = registrant_name(body).or_else(|| body["name"].as_str().map(str::to_string));
+    // ARIN publishes origin ASNs through its `arin_originas0` extension.
+    info.asn = body["arin_originas0_originautnums"]
+        .as_array()
+        .and_then(|asns| asns.first())
+        .and_then(Value::as_u64)
+        .map(|asn| format!("AS{}", asn));
+    info.abuse_email = abuse_email(body);
+    info
+}
+
+/// The email of the first entity with the `abuse` role. Registries usually
+/// nest it under the registrant, so entities are searched recursively.
+fn abuse_email(object: &Value) -> Option<String> {
+    object["entities"].as_array()?.iter().find_map(|entity| {
+        let is_abuse = entity["roles"]
+            .as_array()
+            .is_some_and(|roles| roles.iter().any(|r| r == "abuse"));
+        is_abuse
+            .then(|| vcard_property(entity, "email"))
+            .flatten()
+            .or_else(|| abuse_email(entity))
+    })
+}
+
+fn vcard_property(entity: &Value, name: &str) -> Option<String> {
+    entity["vcardArray"][1]
+        .as_array()?
+        .iter()
+        .find(|prop| prop[0] == name)
+        .and_then(|prop| prop[3].as_str())
+        .map(str::to_string)
+}
+
+/// The `fn` vCard property of the first registrant entity.
+fn registrant_name(body: &Value) -> Option<String> {
+    body["entities"]
+        .as_array()?
+        .iter()
+        .filter(|entity| {
+            entity["roles"]
+                .as_array()
+                .is_some_and(|roles| roles.iter().any(|r| r == "registrant"))
+        })
+        .find_map(|entity| vcard_property(entity, "fn"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bootstrap_picks_most_specific_https_service() {
+        let body = json!({
+            "services": [
+                [["8.0.0.0/8"], ["http://rdap.arin.net/registry/", "https://rdap.arin.net/registry/"]],
+                [["8.8.0.0/16"], ["https://rdap.example.net/"]],
+                [["2001:200::/23"], ["https://rdap.apnic.net/"]]
+            ]
+        });
+        let entries = parse_bootstrap(&body);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            find_base_url(&entries, "8.8.8.8".parse().unwrap()),
+            Some("https://rdap.example.net/")
+        );
+        assert_eq!(
+            find_base_url(&entries, "8.1.1.1".parse().unwrap()),
+            Some("https://rdap.arin.net/registry/")
+        );
+        assert_eq!(
+            find_base_url(&entries, "2001:200::1".parse().unwrap()),
+            Some("https://rdap.apnic.net/")
+        );
+        assert_eq!(find_base_url(&entries, "9.9.9.9".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn test_parse_ip_network() {
+        let body = json!({
+            "objectClassName": "ip network",
+            "name": "GOGL",
+            "country": "US",
+            "arin_originas0_originautnums": [15169],
+            "entities": [
+                {"roles": ["abuse"], "vcardArray": ["vcard", [["fn", {}, "text", "Abuse Desk"]]]},
+                {"roles": ["registrant"], "vcardArray": ["vcard", [
+                    ["version", {}, "text", "4.0"],
+                    ["fn", {}, "text", "Google LLC"]
+                ]], "entities": [
+                    {"roles": ["abuse"], "vcardArray": ["vcard", [
+                        ["email", {}, "text", "network-abuse@google.com"]
+                    ]]}
+                ]}
+            ]
+        });
+        let info = parse_ip_network("8.8.8.8", &body);
+        assert_eq!(info.source, "RDAP");
+        assert_eq!(info.country.as_deref(), Some("US"));
+        assert_eq!(info.isp.as_deref(), Some("Google LLC"));
+        assert_eq!(info.asn.as_deref(), Some("AS15169"));
+        // The top-level abuse entity has no email; the nested one does.
+        assert_eq!(
+            info.abuse_email.as_deref(),
+            Some("network-abuse@google.com")
+        );
+
+        let sparse = parse_ip_network("193.0.0.1", &json!({"name": "RIPE-NCC"}));
+        assert_eq!(sparse.isp.as_deref(), Some("RIPE-NCC"));
+        assert!(sparse.country.is_none() && sparse.asn.is_none());
+    }
+}
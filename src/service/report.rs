@@ -0,0 +1,614 @@
+//! Human-readable reports rendered from the database (`ip-scan report ...`).
+
+use crate::dao::{
+    PortChange, PortStatus, ReputationFilter, RoundDiff, RoundMetrics, ScanResultDetail, SqliteDB,
+};
+use crate::model::ServiceInfo;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// A [`RoundDiff`] plus the last probed service of each newly open port.
+pub struct DiffReport {
+    pub diff: RoundDiff,
+    services: HashMap<(String, u16), ServiceInfo>,
+}
+
+impl DiffReport {
+    /// `limit` caps each listed section; counts and per-port deltas are exact.
+    pub fn collect(db: &SqliteDB, from: i64, to: i64, limit: usize) -> Result<Self> {
+        let diff = db.get_round_diff(from, to, limit)?;
+        let mut services = HashMap::new();
+        let mut ips: Vec<&str> = diff.opened.iter().map(|c| c.ip_address.as_str()).collect();
+        ips.sort_unstable();
+        ips.dedup();
+        for ip in ips {
+            for info in db.get_service_info_by_ip(ip)? {
+                services.insert((info.ip.clone(), info.port), info);
+            }
+        }
+        Ok(Self { diff, services })
+    }
+
+    fn service_label(&self, change: &PortChange) -> String {
+        let Some(info) = self.services.get(&(change.ip_address.clone(), change.port)) else {
+            return String::new();
+        };
+        let mut label = info.service_name.clone();
+        for extra in [&info.service_version, &info.http_title]
+            .into_iter()
+            .flatten()
+        {
+            label.push_str(" / ");
+            label.push_str(extra);
+        }
+        label
+    }
+
+    /// Ports whose open set changed, busiest first.
+    fn changed_ports(&self) -> Vec<&crate::dao::PortDelta> {
+        let mut ports: Vec<_> = self
+            .diff
+            .ports
+            .iter()
+            .filter(|p| p.opened + p.closed > 0)
+            .collect();
+        ports.sort_by_key(|p| (std::cmp::Reverse(p.opened + p.closed), p.port));
+        ports
+    }
+
+    fn totals(&self) -> (usize, usize, usize, usize) {
+        self.diff.ports.iter().fold((0, 0, 0, 0), |acc, p| {
+            (
+                acc.0 + p.open_before,
+                acc.1 + p.open_after,
+                acc.2 + p.opened,
+                acc.3 + p.closed,
+            )
+        })
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let d = &self.diff;
+        let (before, after, opened, closed) = self.totals();
+        let mut out = String::new();
+        let _ = writeln!(out, "# Round {} → {} changes\n", d.from_round, d.to_round);
+        let _ = writeln!(
+            out,
+            "| | Round {} | Round {} | Change |",
+            d.from_round, d.to_round
+        );
+        let _ = writeln!(out, "|---|---:|---:|---:|");
+        let _ = writeln!(
+            out,
+            "| Hosts with open ports | {} | {} | +{} / -{} |",
+            d.hosts_before, d.hosts_after, d.new_hosts, d.hosts_gone_dark_count
+        );
+        let _ = writeln!(
+            out,
+            "| Open ports | {} | {} | +{} / -{} |\n",
+            before, after, opened, closed
+        );
+
+        let _ = writeln!(out, "## Newly exposed services ({})\n", opened);
+        if d.opened.is_empty() {
+            out.push_str("None.\n\n");
+        } else {
+            out.push_str("| Host | Port | Service |\n|---|---:|---|\n");
+            for change in &d.opened {
+                let _ = writeln!(
+                    out,
+                    "| {} | {} | {} |",
+                    change.ip_address,
+                    change.port,
+                    self.service_label(change).replace('|', "\\|")
+                );
+            }
+            more_line(&mut out, opened, d.opened.len());
+        }
+
+        let _ = writeln!(out, "## Hosts gone dark ({})\n", d.hosts_gone_dark_count);
+        if d.hosts_gone_dark.is_empty() {
+            out.push_str("None.\n\n");
+        } else {
+            for ip in &d.hosts_gone_dark {
+                let _ = writeln!(out, "- {}", ip);
+            }
+            more_line(&mut out, d.hosts_gone_dark_count, d.hosts_gone_dark.len());
+        }
+
+        let _ = writeln!(out, "## Closed ports ({})\n", closed);
+        if d.closed.is_empty() {
+            out.push_str("None.\n\n");
+        } else {
+            out.push_str("| Host | Port |\n|---|---:|\n");
+            for change in &d.closed {
+                let _ = writeln!(out, "| {} | {} |", change.ip_address, change.port);
+            }
+            more_line(&mut out, closed, d.closed.len());
+        }
+
+        out.push_str("## Per-port deltas\n\n");
+        let ports = self.changed_ports();
+        if ports.is_empty() {
+            out.push_str("No port changed.\n");
+        } else {
+            out.push_str("| Port | Before | After | Opened | Closed | Net |\n");
+            out.push_str("|---:|---:|---:|---:|---:|---:|\n");
+            for p in ports {
+                let _ = writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {} | {:+} |",
+                    p.port,
+                    p.open_before,
+                    p.open_after,
+                    p.opened,
+                    p.closed,
+                    p.open_after as i64 - p.open_before as i64
+                );
+            }
+        }
+        out
+    }
+
+    /// A self-contained page (inline CSS, no scripts).
+    pub fn to_html(&self) -> String {
+        let d = &self.diff;
+        let (before, after, opened, closed) = self.totals();
+        let title = format!("Round {} → {} changes", d.from_round, d.to_round);
+        let mut body = String::new();
+        let _ = write!(
+            body,
+            "<table><tr><th></th><th>Round {}</th><th>Round {}</th><th>Change</th></tr>\
+             <tr><td>Hosts with open ports</td><td>{}</td><td>{}</td><td>+{} / -{}</td></tr>\
+             <tr><td>Open ports</td><td>{}</td><td>{}</td><td>+{} / -{}</td></tr></table>",
+            d.from_round,
+            d.to_round,
+            d.hosts_before,
+            d.hosts_after,
+            d.new_hosts,
+            d.hosts_gone_dark_count,
+            before,
+            after,
+            opened,
+            closed
+        );
+
+        let _ = write!(body, "<h2>Newly exposed services ({})</h2>", opened);
+        html_table(
+            &mut body,
+            &["Host", "Port", "Service"],
+            d.opened.iter().map(|c| {
+                vec![
+                    c.ip_address.clone(),
+                    c.port.to_string(),
+                    self.service_label(c),
+                ]
+            }),
+            opened,
+        );
+        let _ = write!(
+            body,
+            "<h2>Hosts gone dark ({})</h2>",
+            d.hosts_gone_dark_count
+        );
+        html_table(
+            &mut body,
+            &["Host"],
+            d.hosts_gone_dark.iter().map(|ip| vec![ip.clone()]),
+            d.hosts_gone_dark_count,
+        );
+        let _ = write!(body, "<h2>Closed ports ({})</h2>", closed);
+        html_table(
+            &mut body,
+            &["Host", "Port"],
+            d.closed
+                .iter()
+                .map(|c| vec![c.ip_address.clone(), c.port.to_string()]),
+            closed,
+        );
+        body.push_str("<h2>Per-port deltas</h2>");
+        let ports = self.changed_ports();
+        let changed = ports.len();
+        html_table(
+            &mut body,
+            &["Port", "Before", "After", "Opened", "Closed", "Net"],
+            ports.into_iter().map(|p| {
+                vec![
+                    p.port.to_string(),
+                    p.open_before.to_string(),
+                    p.open_after.to_string(),
+                    p.opened.to_string(),
+                    p.closed.to_string(),
+                    format!("{:+}", p.open_after as i64 - p.open_before as i64),
+                ]
+            }),
+            changed,
+        );
+        html_page(&title, &body)
+    }
+}
+
+/// Result filters shared by the HTML export endpoint and `report html`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResultsFilter {
+    /// Partial IP match
+    pub ip: Option<String>,
+    pub port: Option<u16>,
+    pub round: Option<i64>,
+    /// "IPv4" or "IPv6"
+    pub ip_type: Option<String>,
+    pub status: Option<PortStatus>,
+    /// Ports last seen by this API scan
+    pub scan_id: Option<String>,
+    /// IPs with a matching reverse DNS name or that a hostname target
+    /// resolved to; `*` is a wildcard
+    pub hostname: Option<String>,
+    /// Ports with (or without) candidate CVEs
+    pub has_cves: Option<bool>,
+    pub reputation: Option<ReputationFilter>,
+}
+
+impl ResultsFilter {
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(ip) = &self.ip {
+            parts.push(format!("ip ~ {}", ip));
+        }
+        if let Some(port) = self.port {
+            parts.push(format!("port = {}", port));
+        }
+        if let Some(round) = self.round {
+            parts.push(format!("round = {}", round));
+        }
+        if let Some(ip_type) = &self.ip_type {
+            parts.push(format!("type = {}", ip_type));
+        }
+        match self.status {
+            Some(PortStatus::Active) => parts.push("status = active".to_string()),
+            Some(PortStatus::Gone) => parts.push("status = gone".to_string()),
+            None => {}
+        }
+        if let Some(scan_id) = &self.scan_id {
+            parts.push(format!("scan = {}", scan_id));
+        }
+        if let Some(hostname) = &self.hostname {
+            parts.push(format!("hostname = {}", hostname));
+        }
+        if let Some(has_cves) = self.has_cves {
+            parts.push(format!("has CVEs = {}", has_cves));
+        }
+        match self.reputation {
+            Some(ReputationFilter::Risky) => parts.push("reputation = risky".to_string()),
+            Some(ReputationFilter::Scanner) => parts.push("reputation = scanner".to_string()),
+            Some(ReputationFilter::NotScanner) => {
+                parts.push("reputation = not-scanner".to_string())
+            }
+            None => {}
+        }
+        if parts.is_empty() {
+            "none".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+const REPORT_TOP_PORTS: usize = 15;
+const REPORT_ROUNDS: usize = 20;
+
+/// Summary statistics, charts and a filtered results table for stakeholders.
+pub struct ResultsReport {
+    filter: ResultsFilter,
+    generated_at: String,
+    total_open: usize,
+    unique_ips: usize,
+    current_round: i64,
+    last_scan_time: Option<String>,
+    top_ports: Vec<(u16, usize)>,
+    rounds: Vec<RoundMetrics>,
+    results: Vec<ScanResultDetail>,
+    total_results: usize,
+}
+
+impl ResultsReport {
+    /// Rows in the results table.
+    pub fn rows(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Lists at most `limit` matching results; the count is always complete.
+    pub fn collect(db: &SqliteDB, filter: ResultsFilter, limit: usize) -> Result<Self> {
+        let (total_open, unique_ips) = db.get_stats()?;
+        let (results, total_results) = db.get_scan_results(
+            1,
+            limit,
+            filter.ip.as_deref(),
+            filter.port,
+            filter.round,
+            filter.ip_type.as_deref(),
+            filter.status,
+            filter.scan_id.as_deref(),
+            filter.hostname.as_deref(),
+            filter.has_cves,
+            filter.reputation,
+        )?;
+        let mut rounds = db.get_round_metrics(REPORT_ROUNDS)?;
+        rounds.reverse();
+        Ok(Self {
+            filter,
+            generated_at: chrono::Utc::now()
+                .format("%Y-%m-%d %H:%M:%S UTC")
+                .to_string(),
+            total_open,
+            unique_ips,
+            current_round: db.get_current_round()?,
+            last_scan_time: db.get_last_scan_time()?,
+            top_ports: db.get_top_ports(REPORT_TOP_PORTS)?,
+            rounds,
+            results,
+            total_results,
+        })
+    }
+
+    /// A self-contained page: inline CSS bar charts, no scripts or assets.
+    pub fn to_html(&self) -> String {
+        let mut body = String::new();
+        let _ = write!(
+            body,
+            "<p>Generated {}. Filters: {}.</p>",
+            html_escape(&self.generated_at),
+            html_escape(&self.filter.describe())
+        );
+        body.push_str("<h2>Summary</h2>");
+        let summary = [
+            ("Open port records", self.total_open.to_string()),
+            ("Hosts with open ports", self.unique_ips.to_string()),
+            ("Current round", self.current_round.to_string()),
+            (
+                "Last scan",
+                self.last_scan_time
+                    .clone()
+                    .unwrap_or_else(|| "-".to_string()),
+            ),
+            ("Matching results", self.total_results.to_string()),
+        ];
+        html_table(
+            &mut body,
+            &["Metric", "Value"],
+            summary.iter().map(|(k, v)| vec![k.to_string(), v.clone()]),
+            summary.len(),
+        );
+
+        body.push_str("<h2>Top ports</h2>");
+        html_bar_chart(
+            &mut body,
+            self.top_ports
+                .iter()
+                .map(|(port, count)| (port.to_string(), *count)),
+        );
+        body.push_str("<h2>Open ports per round</h2>");
+        html_bar_chart(
+            &mut body,
+            self.rounds
+                .iter()
+                .map(|m| (format!("Round {}", m.round), m.open as usize)),
+        );
+
+        let _ = write!(body, "<h2>Results ({})</h2>", self.total_results);
+        html_table(
+            &mut body,
+            &[
+                "IP",
+                "Port",
+                "Type",
+                "Round",
+                "Country",
+                "City",
+                "Reverse DNS",
+                "First seen",
+                "Last seen",
+                "Closed",
+                "Scan",
+                "CVEs",
+            ],
+            self.results.iter().map(|r| {
+                vec![
+                    r.ip_address.clone(),
+                    r.port.to_string(),
+                    r.ip_type.clone(),
+                    r.scan_round.to_string(),
+                    r.country.clone().unwrap_or_default(),
+                    r.city.clone().unwrap_or_default(),
+                    r.reverse_dns.clone().unwrap_or_default(),
+                    r.first_seen.clone(),
+                    r.last_seen.clone(),
+                    r.closed_at.clone().unwrap_or_default(),
+                    r.scan_id.clone().unwrap_or_default(),
+                    r.cves.join(" "),
+                ]
+            }),
+            self.total_results,
+        );
+        html_page("ip-scan report", &body)
+    }
+}
+
+fn more_line(out: &mut String, total: usize, listed: usize) {
+    if total > listed {
+        let _ = writeln!(out, "\n… and {} more", total - listed);
+    }
+    out.push('\n');
+}
+
+pub(crate) fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Append a table of escaped cells, or "None." when there are no rows.
+pub(crate) fn html_table(
+    out: &mut String,
+    headers: &[&str],
+    rows: impl Iterator<Item = Vec<String>>,
+    total: usize,
+) {
+    let mut listed = 0;
+    let mut table = String::from("<table><tr>");
+    for header in headers {
+        let _ = write!(table, "<th>{}</th>", html_escape(header));
+    }
+    table.push_str("</tr>");
+    for row in rows {
+        listed += 1;
+        table.push_str("<tr>");
+        for cell in row {
+            let _ = write!(table, "<td>{}</td>", html_escape(&cell));
+        }
+        table.push_str("</tr>");
+    }
+    table.push_str("</table>");
+    if listed == 0 {
+        out.push_str("<p>None.</p>");
+        return;
+    }
+    out.push_str(&table);
+    if total > listed {
+        let _ = write!(out, "<p>… and {} more</p>", total - listed);
+    }
+}
+
+/// Horizontal bars scaled to the largest value, or "None." without data.
+fn html_bar_chart(out: &mut String, rows: impl Iterator<Item = (String, usize)>) {
+    let rows: Vec<_> = rows.collect();
+    let Some(max) = rows.iter().map(|(_, v)| *v).max().filter(|m| *m > 0) else {
+        out.push_str("<p>None.</p>");
+        return;
+    };
+    out.push_str("<div class=\"chart\">");
+    for (label, value) in &rows {
+        let _ = write!(
+            out,
+            "<div class=\"row\"><span class=\"label\">{}</span>\
+             <span class=\"bar\" style=\"width:{:.1}%\"></span>\
+             <span class=\"value\">{}</span></div>",
+            html_escape(label),
+            *value as f64 * 100.0 / max as f64,
+            value
+        );
+    }
+    out.push_str("</div>");
+}
+
+pub(crate) fn html_page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\"><head><meta charset=\"utf-8\">\
+         <title>{title}</title><style>\
+         body{{font-family:system-ui,sans-serif;margin:2em;color:#222}}\
+         table{{border-collapse:collapse;margin:.5em 0 1.5em}}\
+         th,td{{border:1px solid #ccc;padding:.25em .6em;text-align:left}}\
+         th{{background:#f3f3f3}}\
+         .chart{{max-width:48em;margin:.5em 0 1.5em}}\
+         .row{{display:flex;align-items:center;gap:.5em;margin:2px 0}}\
+         .label{{width:7em;text-align:right}}\
+         .bar{{display:inline-block;height:1em;background:#4a7bd0;min-width:1px;flex:none}}\
+         .bar+.value{{color:#555}}\
+         </style></head><body><h1>{title}</h1>{body}</body></html>\n",
+        title = html_escape(title),
+        body = body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_report_lists_exposed_services_and_dark_hosts() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 22, true),
+                ("192.0.2.2".parse().unwrap(), 22, true),
+                ("192.0.2.2".parse().unwrap(), 80, true),
+            ],
+            4,
+        )
+        .unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.2".parse().unwrap(), 22, true),
+                ("192.0.2.3".parse().unwrap(), 3389, true),
+            ],
+            5,
+        )
+        .unwrap();
+        let mut rdp = ServiceInfo::new("192.0.2.3".to_string(), 3389);
+        rdp.service_name = "rdp".to_string();
+        db.save_service_info(&rdp).unwrap();
+
+        assert!(DiffReport::collect(&db, 3, 5, 10).is_err());
+        let report = DiffReport::collect(&db, 4, 5, 10).unwrap();
+        assert_eq!(report.diff.hosts_gone_dark, vec!["192.0.2.1"]);
+        assert_eq!(report.diff.new_hosts, 1);
+
+        let md = report.to_markdown();
+        assert!(md.contains("| Hosts with open ports | 2 | 2 | +1 / -1 |"));
+        assert!(md.contains("| 192.0.2.3 | 3389 | rdp |"));
+        assert!(md.contains("## Hosts gone dark (1)\n\n- 192.0.2.1"));
+        assert!(md.contains("| 80 | 1 | 0 | 0 | 1 | -1 |"));
+        assert!(md.contains("| 22 | 2 | 1 | 0 | 1 | -1 |"));
+
+        let html = report.to_html();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<td>192.0.2.3</td><td>3389</td><td>rdp</td>"));
+    }
+
+    #[test]
+    fn test_results_report_renders_filtered_table_and_charts() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 22, true),
+                ("192.0.2.2".parse().unwrap(), 443, true),
+                ("198.51.100.9".parse().unwrap(), 443, true),
+            ],
+            1,
+        )
+        .unwrap();
+
+        let filter = ResultsFilter {
+            port: Some(443),
+            ..Default::default()
+        };
+        let html = ResultsReport::collect(&db, filter, 1).unwrap().to_html();
+        assert!(html.contains("Filters: port = 443."));
+        assert!(html.contains("<h2>Results (2)</h2>"));
+        assert!(html.contains("<p>… and 1 more</p>"));
+        assert!(!html.contains("<td>22</td>"));
+        assert!(html.contains(
+            "<span class=\"label\">443</span><span class=\"bar\" style=\"width:100.0%\">"
+        ));
+        assert!(html
+            .contains("<span class=\"label\">22</span><span class=\"bar\" style=\"width:50.0%\">"));
+    }
+
+    #[test]
+    fn test_html_escape() {
+        assert_eq!(
+            html_escape(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+}
@@ -0,0 +1,403 @@
+//! `--reputation-providers`: look up hosts with open ports in IP reputation
+//! services (AbuseIPDB, GreyNoise, or custom sources) so results can be
+//! filtered to risky hosts or stripped of known internet scanners.
+//!
+//! The worker runs beside the scan and never blocks it: every source has its
+//! own hourly budget and is paused after a failure, and each lookup is time
+//! boxed. Hosts are checked again once their reports are older than
+//! `RECHECK_AFTER`.
+
+use super::rate_limiter::now_ms;
+use super::RateLimiter;
+use crate::cli::Args;
+use crate::dao::SqliteDB;
+use crate::model::IpReputation;
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use futures::future::BoxFuture;
+use serde_json::Value;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, warn};
+
+/// Built-in sources accepted by `--reputation-providers`.
+pub const REPUTATION_PROVIDERS: &[&str] = &["abuseipdb", "greynoise"];
+
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a source is skipped after it fails or throttles us.
+const FAILURE_PAUSE: Duration = Duration::from_secs(300);
+const RECHECK_AFTER: Duration = Duration::from_secs(7 * 24 * 3600);
+const PAGE_SIZE: usize = 64;
+const IDLE_POLL: Duration = Duration::from_secs(10);
+
+/// A source of IP reputation data.
+///
+/// `Ok(None)` means the source has nothing on the IP; the host is still
+/// recorded as checked. Errors pause the source for a few minutes.
+/// Implementations should set `IpReputation::source` to identify themselves.
+pub trait ReputationProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn lookup<'a>(&'a self, ip: &'a str) -> BoxFuture<'a, Result<Option<IpReputation>>>;
+}
+
+/// A provider with its request budget.
+struct Source {
+    provider: Arc<dyn ReputationProvider>,
+    limiter: RateLimiter,
+    paused_until_ms: AtomicU64,
+}
+
+#[derive(Clone)]
+pub struct ReputationService {
+    sources: Vec<Arc<Source>>,
+    lookups_per_hour: u64,
+}
+
+impl ReputationService {
+    /// A service without sources; each one registered may start
+    /// `lookups_per_hour` lookups per hour.
+    pub fn new(lookups_per_hour: u64) -> Self {
+        Self {
+            sources: Vec::new(),
+            lookups_per_hour: lookups_per_hour.max(1),
+        }
+    }
+
+    /// The built-in sources named by `--reputation-providers`, or `None`
+    /// when none are.
+    pub fn from_args(args: &Args) -> Result<Option<Self>> {
+        if args.reputation_providers.is_empty() {
+            return Ok(None);
+        }
+        let mut service = Self::new(args.reputation_rate);
+        for name in &args.reputation_providers {
+            let provider: Arc<dyn ReputationProvider> = match name.as_str() {
+                "abuseipdb" => {
+                    Arc::new(AbuseIpDb::new(args.abuseipdb_key.clone().ok_or_else(
+                        || anyhow!("AbuseIPDB needs an API key (SCAN_ABUSEIPDB_KEY)"),
+                    )?)?)
+                }
+                "greynoise" => Arc::new(GreyNoise::new(args.greynoise_key.clone())?),
+                other => return Err(anyhow!("Unknown reputation provider {:?}", other)),
+            };
+            service.register_provider(provider);
+        }
+        Ok(Some(service))
+    }
+
+    /// Add a provider after any previously registered ones.
+    pub fn register_provider(&mut self, provider: Arc<dyn ReputationProvider>) {
+        let window = Duration::from_secs(3600);
+        self.sources.push(Arc::new(Source {
+            provider,
+            limiter: RateLimiter::new(self.lookups_per_hour as usize, window),
+            paused_until_ms: AtomicU64::new(0),
+        }));
+    }
+
+    /// Spawn the background worker. It pages through hosts with active open
+    /// ports that have no recent reputation data and exits once `stop` is
+    /// set; shutdown may also abort it while it waits for a budget.
+    pub fn spawn_worker(&self, db: SqliteDB, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+        let service = self.clone();
+        tokio::spawn(async move { service.run(db, stop).await })
+    }
+
+    async fn run(self, db: SqliteDB, stop: Arc<AtomicBool>) {
+        let mut cursor = String::new();
+        while !stop.load(Ordering::Relaxed) {
+            let checked_since = (Utc::now()
+                - chrono::Duration::from_std(RECHECK_AFTER).unwrap_or_default())
+            .to_rfc3339();
+            let ips = db
+                .get_ips_missing_reputation(&cursor, &checked_since, PAGE_SIZE)
+                .unwrap_or_else(|e| {
+                    error!("Failed to load IPs missing reputation data: {}", e);
+                    Vec::new()
+                });
+            let Some(last) = ips.last() else {
+                // Pass complete: start over so hosts whose lookups failed
+                // get another attempt.
+                cursor.clear();
+                tokio::time::sleep(IDLE_POLL).await;
+                continue;
+            };
+            cursor = last.clone();
+            for ip in ips {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let found = self.check(&ip).await;
+                if let Err(e) = db.save_ip_reputation_batch(&found) {
+                    error!("Failed to save reputation data for {}: {}", ip, e);
+                }
+            }
+        }
+    }
+
+    /// Ask every source that is not paused about `ip`. Private and other
+    /// non-public addresses are never sent to a third party.
+    pub async fn check(&self, ip: &str) -> Vec<IpReputation> {
+        let mut found = Vec::new();
+        if !ip.parse().is_ok_and(is_public) {
+            return found;
+        }
+        for source in &self.sources {
+            let name = source.provider.name();
+            if now_ms() < source.paused_until_ms.load(Ordering::Relaxed) {
+                continue;
+            }
+            source.limiter.acquire().await;
+            match tokio::time::timeout(LOOKUP_TIMEOUT, source.provider.lookup(ip)).await {
+                Ok(Ok(reputation)) => found.push(
+                    reputation.unwrap_or_else(|| IpReputation::new(ip.to_string(), name.into())),
+                ),
+                Ok(Err(e)) => {
+                    warn!(
+                        "{} lookup failed; pausing it for {:?}: {}",
+                        name, FAILURE_PAUSE, e
+                    );
+                    source.paused_until_ms.store(
+                        now_ms() + FAILURE_PAUSE.as_millis() as u64,
+                        Ordering::Relaxed,
+                    );
+                }
+                Err(_) => debug!("{} lookup for {} timed out", name, ip),
+            }
+        }
+        found
+    }
+}
+
+/// Whether `ip` is routable on the internet, i.e. worth asking about.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            !Args::is_private_ipv4(&v4.to_string()) && !v4.is_unspecified() && !v4.is_broadcast()
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            !v6.is_loopback()
+                && !v6.is_unspecified()
+                && !v6.is_multicast()
+                && first & 0xfe00 != 0xfc00
+                && first & 0xffc0 != 0xfe80
+        }
+    }
+}
+
+fn http_client() -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .timeout(LOOKUP_TIMEOUT)
+        .user_agent(concat!("ip-scan/", env!("CARGO_PKG_VERSION")))
+        .build()?)
+}
+
+/// AbuseIPDB `check` endpoint: abuse confidence score and report count.
+pub struct AbuseIpDb {
+    client: reqwest::Client,
+    key: String,
+}
+
+impl AbuseIpDb {
+    pub fn new(key: String) -> Result<Self> {
+        Ok(Self {
+            client: http_client()?,
+            key,
+        })
+    }
+}
+
+impl ReputationProvider for AbuseIpDb {
+    fn name(&self) -> &str {
+        "AbuseIPDB"
+    }
+
+    fn lookup<'a>(&'a self, ip: &'a str) -> BoxFuture<'a, Result<Option<IpReputation>>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .get("https://api.abuseipdb.com/api/v2/check")
+                .query(&[("ipAddress", ip), ("maxAgeInDays", "90")])
+                .header("Key", &self.key)
+                .header("Accept", "application/json")
+                .send()
+                .await
+                .context("Failed to call AbuseIPDB")?
+                .error_for_status()?;
+            let body: Value = response
+                .json()
+                .await
+                .context("Failed to parse AbuseIPDB response")?;
+            parse_abuseipdb(ip, &body).map(Some)
+        })
+    }
+}
+
+fn parse_abuseipdb(ip: &str, body: &Value) -> Result<IpReputation> {
+    let data = body
+        .get("data")
+        .ok_or_else(|| anyhow!("AbuseIPDB response has no data"))?;
+    let mut reputation = IpReputation::new(ip.to_string(), "AbuseIPDB".to_string());
+    reputation.score = data["abuseConfidenceScore"]
+        .as_u64()
+        .map(|score| score.min(100) as u8);
+    reputation.reports = data["totalReports"].as_u64().map(|n| n as u32);
+    Ok(reputation)
+}
+
+/// GreyNoise Community API: whether the host scans the internet and how
+/// GreyNoise classifies that activity. Works without a key at a lower
+/// daily quota.
+pub struct GreyNoise {
+    client: reqwest::Client,
+    key: Option<String>,
+}
+
+impl GreyNoise {
+    pub fn new(key: Option<String>) -> Result<Self> {
+        Ok(Self {
+            client: http_client()?,
+            key,
+        })
+    }
+}
+
+impl ReputationProvider for GreyNoise {
+    fn name(&self) -> &str {
+        "GreyNoise"
+    }
+
+    fn lookup<'a>(&'a self, ip: &'a str) -> BoxFuture<'a, Result<Option<IpReputation>>> {
+        Box::pin(async move {
+            let mut request = self
+                .client
+                .get(format!("https://api.greynoise.io/v3/community/{}", ip));
+            if let Some(key) = &self.key {
+                request = request.header("key", key);
+            }
+            let response = request.send().await.context("Failed to call GreyNoise")?;
+            // 404 is the normal answer for hosts GreyNoise has not seen.
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let body: Value = response
+                .error_for_status()?
+                .json()
+                .await
+                .context("Failed to parse GreyNoise response")?;
+            Ok(Some(parse_greynoise(ip, &body)))
+        })
+    }
+}
+
+fn parse_greynoise(ip: &str, body: &Value) -> IpReputation {
+    let mut reputation = IpReputation::new(ip.to_string(), "GreyNoise".to_string());
+    reputation.scanner = body["noise"].as_bool().unwrap_or(false);
+    reputation.classification = body["classification"].as_str().map(str::to_string);
+    reputation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dao::ReputationFilter;
+
+    struct StaticProvider(Option<IpReputation>);
+
+    impl ReputationProvider for StaticProvider {
+        fn name(&self) -> &str {
+            "static"
+        }
+
+        fn lookup<'a>(&'a self, _ip: &'a str) -> BoxFuture<'a, Result<Option<IpReputation>>> {
+            Box::pin(async move {
+                match &self.0 {
+                    Some(reputation) if reputation.source == "error" => Err(anyhow!("down")),
+                    other => Ok(other.clone()),
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn test_parse_provider_responses() {
+        let abuse = parse_abuseipdb(
+            "198.51.100.7",
+            &serde_json::json!({"data": {"abuseConfidenceScore": 87, "totalReports": 12}}),
+        )
+        .unwrap();
+        assert_eq!(abuse.score, Some(87));
+        assert_eq!(abuse.reports, Some(12));
+        assert!(abuse.is_risky());
+        assert!(parse_abuseipdb("198.51.100.7", &serde_json::json!({"errors": []})).is_err());
+
+        let noise = parse_greynoise(
+            "198.51.100.7",
+            &serde_json::json!({"noise": true, "riot": false, "classification": "benign"}),
+        );
+        assert!(noise.scanner);
+        assert!(!noise.is_risky());
+    }
+
+    #[tokio::test]
+    async fn test_checked_hosts_are_stored_and_filterable() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("198.51.100.7".parse().unwrap(), 22, true),
+                ("203.0.113.9".parse().unwrap(), 22, true),
+                ("10.0.0.1".parse().unwrap(), 22, true),
+            ],
+            1,
+        )
+        .unwrap();
+
+        let mut scanner = IpReputation::new("198.51.100.7".to_string(), "static".to_string());
+        scanner.scanner = true;
+        scanner.score = Some(90);
+        let mut service = ReputationService::new(1000);
+        service.register_provider(Arc::new(StaticProvider(Some(scanner))));
+        let found = service.check("198.51.100.7").await;
+        db.save_ip_reputation_batch(&found).unwrap();
+        assert!(service.check("10.0.0.1").await.is_empty());
+
+        let mut quiet = ReputationService::new(1000);
+        quiet.register_provider(Arc::new(StaticProvider(None)));
+        let found = quiet.check("203.0.113.9").await;
+        assert_eq!(found[0].score, None);
+        db.save_ip_reputation_batch(&found).unwrap();
+
+        let since = (Utc::now() - chrono::Duration::hours(1)).to_rfc3339();
+        assert_eq!(
+            db.get_ips_missing_reputation("", &since, 10).unwrap(),
+            ["10.0.0.1"]
+        );
+        let filtered = |reputation| {
+            db.get_scan_results(
+                1, 10, None, None, None, None, None, None, None, None, reputation,
+            )
+            .unwrap()
+            .0
+            .into_iter()
+            .map(|r| r.ip_address)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(filtered(Some(ReputationFilter::Risky)), ["198.51.100.7"]);
+        assert_eq!(filtered(Some(ReputationFilter::Scanner)), ["198.51.100.7"]);
+        assert_eq!(filtered(Some(ReputationFilter::NotScanner)).len(), 2);
+        let row = &db.get_results_by_ip("198.51.100.7").unwrap()[0];
+        assert_eq!(row.reputation_score, Some(90));
+        assert!(row.known_scanner);
+
+        let mut failing = ReputationService::new(1000);
+        let error = IpReputation::new(String::new(), "error".to_string());
+        failing.register_provider(Arc::new(StaticProvider(Some(error))));
+        assert!(failing.check("203.0.113.9").await.is_empty());
+        assert!(failing.sources[0].paused_until_ms.load(Ordering::Relaxed) > now_ms());
+    }
+}
@@ -0,0 +1,156 @@
+//! `--rescan-open`: re-verify the open ports already in the database instead
+//! of walking a target range.
+
+use super::{ConScanner, ConScannerConfig, PortState};
+use crate::dao::SqliteDB;
+use crate::model::{ExcludeList, ScanMetrics};
+use anyhow::Result;
+use futures::StreamExt;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use tracing::{error, info};
+
+/// Outcome of one verification pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RescanSummary {
+    pub checked: usize,
+    pub still_open: usize,
+    pub closed: usize,
+}
+
+/// Probe every known open (ip, port) once with a connect scan. Ports that
+/// answer get `last_seen` refreshed through the normal result writer; the
+/// rest are marked gone. Hosts are verified `concurrent_limit /
+/// host_concurrent_limit` at a time. Cancelling stops dispatching new hosts;
+/// hosts already being probed finish so no port is closed on a partial
+/// answer.
+pub async fn rescan_open_ports(
+    db: &SqliteDB,
+    round: i64,
+    config: ConScannerConfig,
+    exclude: Option<&ExcludeList>,
+) -> Result<(RescanSummary, ScanMetrics)> {
+    let mut targets: BTreeMap<IpAddr, Vec<u16>> = BTreeMap::new();
+    for (ip, port) in db.get_active_open_ports()? {
+        let Ok(ip) = ip.parse::<IpAddr>() else {
+            continue;
+        };
+        if exclude.is_some_and(|list| list.contains(ip)) {
+            continue;
+        }
+        targets.entry(ip).or_default().push(port);
+    }
+    info!(
+        "Re-verifying {} known open ports on {} hosts",
+        targets.values().map(Vec::len).sum::<usize>(),
+        targets.len()
+    );
+
+    let cancel = config.cancel.clone();
+    let parallel_hosts = config
+        .concurrent_limit
+        .div_ceil(config.host_concurrent_limit.max(1))
+        .max(1);
+    let scanner = ConScanner::new(db.clone(), round, config);
+    let scanner_ref = &scanner;
+    let results: Vec<_> =
+        futures::stream::iter(targets)
+            .take_until(cancel.cancelled())
+            .map(|(ip, ports)| async move {
+                (ip, scanner_ref.scan_ip_ports_classified(ip, &ports).await)
+            })
+            .buffer_unordered(parallel_hosts)
+            .collect()
+            .await;
+    let metrics = scanner.get_metrics().clone();
+    // Let the writer record the ports that are still open before closing
+    // the rest.
+    scanner.finish().await;
+
+    let mut summary = RescanSummary::default();
+    let mut gone = Vec::new();
+    for (ip, states) in results {
+        let states = match states {
+            Ok(states) => states,
+            Err(e) => {
+                error!("Failed to re-verify {}: {}", ip, e);
+                continue;
+            }
+        };
+        for (port, state) in states {
+            summary.checked += 1;
+            if state == PortState::Open {
+                summary.still_open += 1;
+            } else {
+                gone.push((ip.to_string(), port));
+            }
+        }
+    }
+    summary.closed = db.mark_ports_closed(&gone)?;
+    Ok((summary, metrics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_util::sync::CancellationToken;
+
+    #[tokio::test]
+    async fn test_rescan_refreshes_open_and_closes_gone_ports() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+        // Bind and release a port so nothing is listening on it.
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("127.0.0.1".parse().unwrap(), open, true),
+                ("127.0.0.1".parse().unwrap(), closed, true),
+            ],
+            1,
+        )
+        .unwrap();
+
+        let config = ConScannerConfig {
+            timeout_ms: 500,
+            concurrent_limit: 10,
+            host_concurrent_limit: 4,
+            result_buffer: 100,
+            db_batch_size: 100,
+            flush_interval_ms: 60_000,
+            adaptive_batching: false,
+            max_rate: 10_000,
+            rate_window_secs: 1,
+            rate_burst: 0,
+            hooks: None,
+            cancel: CancellationToken::new(),
+            io_uring: false,
+            source_ports: None,
+            banner_grab_ms: 0,
+        };
+        let (summary, metrics) = rescan_open_ports(&db, 2, config, None).await.unwrap();
+
+        assert_eq!(
+            summary,
+            RescanSummary {
+                checked: 2,
+                still_open: 1,
+                closed: 1
+            }
+        );
+        assert_eq!(metrics.get_open(), 1);
+        assert_eq!(
+            db.get_active_open_ports().unwrap(),
+            vec![("127.0.0.1".to_string(), open)]
+        );
+        let refreshed = db.get_results_by_ip("127.0.0.1").unwrap();
+        let row = refreshed.iter().find(|r| r.port == open).unwrap();
+        assert_eq!(row.scan_round, 2);
+    }
+}
@@ -0,0 +1,149 @@
+//! Hostname targets (`--target example.com`, the API's `hostnames`): names
+//! are resolved to A/AAAA addresses at the start of every round, and the
+//! mapping is stored so results can be filtered by the name they came from.
+
+use crate::dao::SqliteDB;
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+/// Hostnames accepted in one target list.
+pub const MAX_TARGET_HOSTNAMES: usize = 1024;
+
+/// Addresses fed to the scanner in one round.
+pub type TargetIter = Box<dyn Iterator<Item = IpAddr> + Send>;
+
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+const RESOLVE_CONCURRENCY: usize = 8;
+
+/// Resolves target hostnames through the system resolver, a few at a time
+/// and each within a timeout, so a slow DNS server delays the round start
+/// by seconds rather than stalling it.
+pub struct HostResolver {
+    timeout: Duration,
+}
+
+impl Default for HostResolver {
+    fn default() -> Self {
+        HostResolver {
+            timeout: RESOLVE_TIMEOUT,
+        }
+    }
+}
+
+impl HostResolver {
+    /// Distinct A and AAAA addresses of `hostname`.
+    pub async fn lookup(&self, hostname: &str) -> Result<Vec<IpAddr>> {
+        resolve(hostname, self.timeout).await
+    }
+
+    /// Resolve every hostname, record which addresses each one had in
+    /// `round`, and return all of them sorted and deduplicated. Names that
+    /// fail to resolve are logged and skipped; it is an error only when none
+    /// resolves.
+    pub async fn resolve_targets(
+        &self,
+        db: &SqliteDB,
+        hostnames: &[String],
+        round: i64,
+    ) -> Result<Vec<IpAddr>> {
+        let wait = self.timeout;
+        let resolved: Vec<_> = futures::stream::iter(hostnames.iter().cloned())
+            .map(|hostname| lookup(hostname, wait))
+            .buffer_unordered(RESOLVE_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut targets = Vec::new();
+        for (hostname, ips) in resolved {
+            match ips {
+                Ok(ips) if !ips.is_empty() => {
+                    db.save_hostname_resolution(&hostname, &ips, round)?;
+                    targets.extend(ips);
+                }
+                Ok(_) => warn!("{} has no A or AAAA records", hostname),
+                Err(e) => warn!("Failed to resolve {}: {}", hostname, e),
+            }
+        }
+        if targets.is_empty() {
+            return Err(anyhow!("None of the target hostnames resolved"));
+        }
+        targets.sort();
+        targets.dedup();
+        info!(
+            "Resolved {} target hostnames to {} addresses",
+            hostnames.len(),
+            targets.len()
+        );
+        Ok(targets)
+    }
+}
+
+// Takes and returns the name by value so the lookups can run concurrently
+// from a spawned task.
+async fn lookup(hostname: String, wait: Duration) -> (String, Result<Vec<IpAddr>>) {
+    let ips = resolve(&hostname, wait).await;
+    (hostname, ips)
+}
+
+async fn resolve(hostname: &str, wait: Duration) -> Result<Vec<IpAddr>> {
+    let addrs = timeout(wait, tokio::net::lookup_host((hostname, 0)))
+        .await
+        .map_err(|_| anyhow!("timed out resolving {}", hostname))??;
+    let mut ips: Vec<IpAddr> = addrs.map(|addr| addr.ip()).collect();
+    ips.sort();
+    ips.dedup();
+    Ok(ips)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolve_targets_records_the_mapping() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("127.0.0.1".parse().unwrap(), 80, true),
+                ("192.0.2.1".parse().unwrap(), 80, true),
+            ],
+            1,
+        )
+        .unwrap();
+
+        let hostnames = ["localhost".to_string(), "nonexistent.invalid".to_string()];
+        let targets = HostResolver::default()
+            .resolve_targets(&db, &hostnames, 1)
+            .await
+            .unwrap();
+        assert!(targets.contains(&"127.0.0.1".parse().unwrap()));
+
+        let (results, total) = db
+            .get_scan_results(
+                1,
+                10,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some("LOCALHOST"),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(results[0].ip_address, "127.0.0.1");
+
+        let unresolvable = ["nonexistent.invalid".to_string()];
+        assert!(HostResolver::default()
+            .resolve_targets(&db, &unresolvable, 1)
+            .await
+            .is_err());
+    }
+}
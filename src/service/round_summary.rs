@@ -0,0 +1,497 @@
+//! End-of-round summary: what a round scanned and found, per port against
+//! the round before, where the open hosts are, and where errors clustered.
+//! Printed to stdout as tables, or as one JSON line with
+//! `--summary-format json` for scripts. With `--json-summary` the rounds
+//! print nothing and [`RunSummary`] reports the whole run once at exit.
+
+use crate::dao::SqliteDB;
+use crate::model::{BreakdownEntry, LatencySummary, ReplyStats, ScanMetrics};
+use anyhow::{anyhow, Result};
+use comfy_table::presets::ASCII_MARKDOWN;
+use comfy_table::{CellAlignment, Table};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Instant;
+use tracing::error;
+
+/// Ports listed, most open first.
+const SUMMARY_PORTS: usize = 15;
+const SUMMARY_COUNTRIES: usize = 5;
+/// Ports and /8 prefixes listed by error count.
+const SUMMARY_ERROR_KEYS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryFormat {
+    Text,
+    Json,
+}
+
+impl SummaryFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "text" => Ok(SummaryFormat::Text),
+            "json" => Ok(SummaryFormat::Json),
+            other => Err(anyhow!(
+                "Summary format must be \"text\" or \"json\", got {:?}",
+                other
+            )),
+        }
+    }
+}
+
+/// Open count of one port in this round and the change since the last.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PortSummary {
+    pub port: u16,
+    pub open: usize,
+    /// `None` when the previous round has no stored bitmaps
+    pub delta: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CountrySummary {
+    pub country: String,
+    pub hosts: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorSummary {
+    pub errors: u64,
+    pub retries: u64,
+    /// Share of probes that ended in an error, in percent
+    pub error_rate: f64,
+    pub top_ports: Vec<BreakdownEntry>,
+    pub top_prefixes: Vec<BreakdownEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundSummary {
+    pub round: i64,
+    pub scanned: u64,
+    pub open: u64,
+    pub elapsed_secs: f64,
+    pub scan_rate: f64,
+    pub ports: Vec<PortSummary>,
+    /// Countries of hosts with an active open port, as far as geolocated
+    pub countries: Vec<CountrySummary>,
+    pub errors: ErrorSummary,
+    pub connect_latency: Option<LatencySummary>,
+    pub syn_rtt: Option<LatencySummary>,
+    pub replies: Option<ReplyStats>,
+}
+
+/// Open counts per port in `round`'s bitmaps, IPv4 and IPv6 together.
+fn port_counts(db: &SqliteDB, round: i64) -> Result<BTreeMap<u16, usize>> {
+    let mut counts = BTreeMap::new();
+    for (port, open) in db.get_stats_by_port(round)? {
+        *counts.entry(port).or_default() += open;
+    }
+    Ok(counts)
+}
+
+impl RoundSummary {
+    pub fn collect(db: &SqliteDB, round: i64, metrics: &ScanMetrics) -> Result<Self> {
+        let current = port_counts(db, round)?;
+        let previous = if db.get_bitmap_rounds()?.contains(&(round - 1)) {
+            Some(port_counts(db, round - 1)?)
+        } else {
+            None
+        };
+        let mut ports: Vec<PortSummary> = current
+            .keys()
+            .chain(previous.iter().flat_map(|p| p.keys()))
+            .copied()
+            .collect::<std::collections::BTreeSet<u16>>()
+            .into_iter()
+            .map(|port| {
+                let open = current.get(&port).copied().unwrap_or(0);
+                PortSummary {
+                    port,
+                    open,
+                    delta: previous.as_ref().map(|previous| {
+                        open as i64 - previous.get(&port).copied().unwrap_or(0) as i64
+                    }),
+                }
+            })
+            .collect();
+        ports.sort_by(|a, b| {
+            b.open
+                .cmp(&a.open)
+                .then(b.delta.map(i64::abs).cmp(&a.delta.map(i64::abs)))
+                .then(a.port.cmp(&b.port))
+        });
+        ports.truncate(SUMMARY_PORTS);
+
+        let scanned = metrics.get_scanned();
+        let errors = metrics.get_errors();
+        let replies = metrics.reply_stats();
+        let present = |latency: LatencySummary| (latency.count > 0).then_some(latency);
+        Ok(Self {
+            round,
+            scanned,
+            open: metrics.get_open(),
+            elapsed_secs: metrics.live_stats().elapsed_secs,
+            scan_rate: metrics.get_scan_rate(),
+            ports,
+            countries: db
+                .get_top_countries(SUMMARY_COUNTRIES)?
+                .into_iter()
+                .map(|(country, hosts)| CountrySummary { country, hosts })
+                .collect(),
+            errors: ErrorSummary {
+                errors,
+                retries: metrics.get_retries(),
+                error_rate: if scanned > 0 {
+                    errors as f64 / scanned as f64 * 100.0
+                } else {
+                    0.0
+                },
+                top_ports: error_entries(metrics.top_ports(SUMMARY_ERROR_KEYS)),
+                top_prefixes: error_entries(metrics.top_prefixes(SUMMARY_ERROR_KEYS)),
+            },
+            connect_latency: present(metrics.connect_latency()),
+            syn_rtt: present(metrics.syn_rtt()),
+            replies: (replies.syn_ack + replies.rst > 0).then_some(replies),
+        })
+    }
+
+    pub fn render(&self, format: SummaryFormat) -> String {
+        match format {
+            SummaryFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+            SummaryFormat::Text => self.render_text(),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        let mut overview = table(&["Round", &self.round.to_string()]);
+        overview.add_row(vec!["Scanned".to_string(), self.scanned.to_string()]);
+        overview.add_row(vec!["Open".to_string(), self.open.to_string()]);
+        overview.add_row(vec![
+            "Errors".to_string(),
+            format!(
+                "{} ({:.2}%), {} retries",
+                self.errors.errors, self.errors.error_rate, self.errors.retries
+            ),
+        ]);
+        overview.add_row(vec![
+            "Elapsed".to_string(),
+            format!(
+                "{:.1}s at {:.0} targets/s",
+                self.elapsed_secs, self.scan_rate
+            ),
+        ]);
+        for (name, latency) in [
+            ("Connect latency", self.connect_latency),
+            ("SYN RTT", self.syn_rtt),
+        ] {
+            if let Some(l) = latency {
+                overview.add_row(vec![
+                    name.to_string(),
+                    format!(
+                        "p50 {:.2}ms / p95 {:.2}ms / p99 {:.2}ms (n={})",
+                        l.p50_ms, l.p95_ms, l.p99_ms, l.count
+                    ),
+                ]);
+            }
+        }
+        if let Some(r) = self.replies {
+            overview.add_row(vec![
+                "Probe replies".to_string(),
+                format!(
+                    "{:.2}% SYN-ACK, {:.2}% RST, {:.2}% no answer",
+                    r.syn_ack as f64 / r.sent as f64 * 100.0,
+                    r.rst_ratio * 100.0,
+                    r.no_answer_ratio * 100.0
+                ),
+            ]);
+        }
+        let mut out = overview.to_string();
+
+        if !self.ports.is_empty() {
+            let mut ports = table(&["Port", "Open", "Change"]);
+            for p in &self.ports {
+                ports.add_row(vec![
+                    p.port.to_string(),
+                    p.open.to_string(),
+                    p.delta.map_or("-".to_string(), |d| format!("{:+}", d)),
+                ]);
+            }
+            align_right(&mut ports, &[1, 2]);
+            out.push_str("\n\n");
+            out.push_str(&ports.to_string());
+        }
+
+        if !self.countries.is_empty() {
+            let mut countries = table(&["Country", "Hosts"]);
+            for c in &self.countries {
+                countries.add_row(vec![c.country.clone(), c.hosts.to_string()]);
+            }
+            align_right(&mut countries, &[1]);
+            out.push_str("\n\n");
+            out.push_str(&countries.to_string());
+        }
+
+        for (name, entries) in [
+            ("Errors by port", &self.errors.top_ports),
+            ("Errors by /8", &self.errors.top_prefixes),
+        ] {
+            if entries.is_empty() {
+                continue;
+            }
+            let mut errors = table(&[name, "Scanned", "Open", "Errors"]);
+            for e in entries {
+                errors.add_row(vec![
+                    e.key.clone(),
+                    e.scanned.to_string(),
+                    e.open.to_string(),
+                    e.errors.to_string(),
+                ]);
+            }
+            align_right(&mut errors, &[1, 2, 3]);
+            out.push_str("\n\n");
+            out.push_str(&errors.to_string());
+        }
+        out
+    }
+
+    /// Write the summary to stdout.
+    pub fn print(&self, format: SummaryFormat) {
+        println!("{}", self.render(format));
+    }
+}
+
+/// Totals of one process run for `--json-summary`, printed as a single JSON
+/// document when the scan exits, however it exits.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    /// `completed`, `interrupted` (stopped by a signal) or `failed`
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub rounds: usize,
+    pub first_round: Option<i64>,
+    pub last_round: Option<i64>,
+    pub scanned: u64,
+    pub open: u64,
+    pub errors: u64,
+    pub retries: u64,
+    pub duration_secs: f64,
+    pub rate: f64,
+    /// Open ports seen for the first time during the run; `None` when the
+    /// database could not be queried
+    pub new_findings: Option<usize>,
+    pub database: String,
+    pub started_at: String,
+    pub finished_at: String,
+    #[serde(skip)]
+    started: Instant,
+}
+
+impl RunSummary {
+    pub fn new(database: &str) -> Self {
+        Self {
+            status: "completed",
+            error: None,
+            rounds: 0,
+            first_round: None,
+            last_round: None,
+            scanned: 0,
+            open: 0,
+            errors: 0,
+            retries: 0,
+            duration_secs: 0.0,
+            rate: 0.0,
+            new_findings: None,
+            database: database.to_string(),
+            started_at: chrono::Utc::now().to_rfc3339(),
+            finished_at: String::new(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Count a round, or the part of it this run scanned.
+    pub fn add_round(&mut self, round: i64, metrics: &ScanMetrics) {
+        self.rounds += 1;
+        self.first_round.get_or_insert(round);
+        self.last_round = Some(round);
+        self.scanned += metrics.get_scanned();
+        self.open += metrics.get_open();
+        self.errors += metrics.get_errors();
+        self.retries += metrics.get_retries();
+    }
+
+    pub fn finish(&mut self, db: &SqliteDB, result: &Result<()>, interrupted: bool) {
+        self.duration_secs = self.started.elapsed().as_secs_f64();
+        self.rate = if self.duration_secs > 0.0 {
+            self.scanned as f64 / self.duration_secs
+        } else {
+            0.0
+        };
+        self.finished_at = chrono::Utc::now().to_rfc3339();
+        self.new_findings = match db.count_open_ports_since(&self.started_at) {
+            Ok(count) => Some(count),
+            Err(e) => {
+                error!("Failed to count new findings: {}", e);
+                None
+            }
+        };
+        match result {
+            Err(e) => {
+                self.status = "failed";
+                self.error = Some(format!("{:#}", e));
+            }
+            Ok(()) if interrupted => self.status = "interrupted",
+            Ok(()) => self.status = "completed",
+        }
+    }
+
+    pub fn render(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Only the entries that had errors; the rest carry no error signal.
+fn error_entries(entries: Vec<BreakdownEntry>) -> Vec<BreakdownEntry> {
+    entries.into_iter().filter(|e| e.errors > 0).collect()
+}
+
+fn table(header: &[&str]) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(ASCII_MARKDOWN)
+        .set_header(header.to_vec());
+    table
+}
+
+fn align_right(table: &mut Table, columns: &[usize]) {
+    for &index in columns {
+        if let Some(column) = table.column_mut(index) {
+            column.set_cell_alignment(CellAlignment::Right);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::IpGeoInfo;
+
+    #[test]
+    fn test_summary_compares_ports_with_the_previous_round() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 22, true),
+                ("192.0.2.2".parse().unwrap(), 22, true),
+                ("192.0.2.3".parse().unwrap(), 3389, true),
+            ],
+            1,
+        )
+        .unwrap();
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 22, true),
+                ("192.0.2.1".parse().unwrap(), 443, true),
+                ("192.0.2.2".parse().unwrap(), 443, true),
+                ("192.0.2.4".parse().unwrap(), 443, true),
+            ],
+            2,
+        )
+        .unwrap();
+        let mut geo = IpGeoInfo::new("192.0.2.1".to_string(), "test".to_string());
+        geo.country = Some("NL".to_string());
+        db.save_ip_geo_info_batch(&[geo]).unwrap();
+
+        let metrics = ScanMetrics::new();
+        let target = "192.0.2.9".parse().unwrap();
+        for _ in 0..4 {
+            metrics.record_scanned(target, 8080);
+        }
+        metrics.record_error(target, 8080);
+
+        let summary = RoundSummary::collect(&db, 2, &metrics).unwrap();
+        assert_eq!(
+            summary.ports,
+            [
+                PortSummary {
+                    port: 443,
+                    open: 3,
+                    delta: Some(3)
+                },
+                PortSummary {
+                    port: 22,
+                    open: 1,
+                    delta: Some(-1)
+                },
+                PortSummary {
+                    port: 3389,
+                    open: 0,
+                    delta: Some(-1)
+                },
+            ]
+        );
+        assert_eq!(
+            summary.countries,
+            [CountrySummary {
+                country: "NL".to_string(),
+                hosts: 1
+            }]
+        );
+        assert_eq!(summary.errors.error_rate, 25.0);
+        assert_eq!(summary.errors.top_ports[0].key, "8080");
+
+        let text = summary.render(SummaryFormat::Text);
+        assert!(text.contains("| 443  |    3 |     +3 |"), "{}", text);
+        assert!(text.contains("Errors by /8"));
+        let json: serde_json::Value =
+            serde_json::from_str(&summary.render(SummaryFormat::Json)).unwrap();
+        assert_eq!(json["ports"][1]["delta"], -1);
+        assert_eq!(json["countries"][0]["country"], "NL");
+
+        // Round 1 has nothing to compare with.
+        let first = RoundSummary::collect(&db, 1, &metrics).unwrap();
+        assert!(first.ports.iter().all(|p| p.delta.is_none()));
+        assert!(SummaryFormat::parse("yaml").is_err());
+    }
+
+    #[test]
+    fn test_run_summary_totals_rounds_and_new_findings() {
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.bulk_update_port_status(vec![("192.0.2.1".parse().unwrap(), 22, true)], 1)
+            .unwrap();
+        let mut run = RunSummary::new("scan.db");
+        // Seen again during the run: not a new finding.
+        db.bulk_update_port_status(
+            vec![
+                ("192.0.2.1".parse().unwrap(), 22, true),
+                ("192.0.2.2".parse().unwrap(), 22, true),
+            ],
+            2,
+        )
+        .unwrap();
+
+        let target = "192.0.2.1".parse().unwrap();
+        for round in [2, 3] {
+            let metrics = ScanMetrics::new();
+            metrics.record_scanned(target, 22);
+            metrics.record_open(target, 22);
+            run.add_round(round, &metrics);
+        }
+        run.finish(&db, &Ok(()), true);
+
+        let json: serde_json::Value = serde_json::from_str(&run.render()).unwrap();
+        assert_eq!(json["status"], "interrupted");
+        assert_eq!(json["rounds"], 2);
+        assert_eq!(json["first_round"], 2);
+        assert_eq!(json["last_round"], 3);
+        assert_eq!(json["scanned"], 2);
+        assert_eq!(json["open"], 2);
+        assert_eq!(json["new_findings"], 1);
+        assert_eq!(json["database"], "scan.db");
+        assert!(json.get("error").is_none());
+
+        run.finish(&db, &Err(anyhow!("disk full")), false);
+        assert_eq!(run.status, "failed");
+        assert_eq!(run.error.as_deref(), Some("disk full"));
+    }
+}
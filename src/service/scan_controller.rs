@@ -6,14 +6,24 @@
 use crate::api::models::{ScanStatus, StartScanRequest};
 use crate::cli::Args;
 use crate::dao::SqliteDB;
-use crate::service::syn_scanner::SynScanner;
-use crate::service::ConScanner;
+use crate::model::{ExcludeList, ScanMetrics};
+use crate::service::{HostResolver, MetricsSample, MetricsTimeseries, TargetIter};
 use anyhow::{anyhow, Result};
 use chrono::Utc;
+use serde::Serialize;
+use std::net::IpAddr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
 
+/// Longest accepted scan `name` and `owner`, in characters.
+const MAX_LABEL_CHARS: usize = 128;
+/// Longest accepted scan `description`, in characters.
+const MAX_DESCRIPTION_CHARS: usize = 1024;
+
 /// Runtime state for a scanner started by the CLI rather than the API controller.
 #[derive(Debug, Clone, Default)]
 pub struct RuntimeScanState {
@@ -36,13 +46,43 @@ impl RuntimeScanState {
     }
 }
 
-/// Scan controller for managing scan operations
+/// Lifecycle state of the API-controlled scan. Kept behind one async lock so
+/// checks and transitions are atomic; the lock is never held while waiting
+/// for the scan task.
+struct ControllerState {
+    status: ScanStatus,
+    scan_id: Option<String>,
+    handle: Option<JoinHandle<Result<()>>>,
+    /// Cancels the producer, scanner and DB writer of the current scan.
+    cancel: CancellationToken,
+    /// Ends the current scan once the round in progress completes.
+    finish: CancellationToken,
+}
+
+/// Rounds of the latest API scan, for `/scan/status`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RoundProgress {
+    /// Round being scanned, or the next one while waiting between rounds.
+    pub current_round: i64,
+    pub completed_rounds: u64,
+    /// Rounds requested; `None` loops until stopped.
+    pub total_rounds: Option<u64>,
+    /// The scan ends once the round in progress completes.
+    pub stop_after_round: bool,
+}
+
+/// Scan controller for managing scan operations. Shared between actix
+/// workers as-is; every method takes `&self`.
 pub struct ScanController {
     db: SqliteDB,
-    scan_status: Arc<Mutex<ScanStatus>>,
-    scan_running: Arc<AtomicBool>,
-    scan_handle: Arc<Mutex<Option<tokio::task::JoinHandle<Result<()>>>>>,
-    scan_id: Arc<Mutex<Option<String>>>,
+    state: Arc<RwLock<ControllerState>>,
+    /// Counters of the scanner probing the current round, replaced every
+    /// round and cleared when the scan ends.
+    metrics: Arc<Mutex<Option<ScanMetrics>>>,
+    /// Round progress of the latest scan, kept after it ends.
+    rounds: Arc<Mutex<Option<RoundProgress>>>,
+    /// Metrics samples of the latest scan, kept after it ends.
+    timeseries: Arc<Mutex<MetricsTimeseries>>,
 }
 
 impl ScanController {
@@ -50,38 +90,81 @@ impl ScanController {
     pub fn new(db: SqliteDB) -> Self {
         Self {
             db,
-            scan_status: Arc::new(Mutex::new(ScanStatus::Idle)),
-            scan_running: Arc::new(AtomicBool::new(false)),
-            scan_handle: Arc::new(Mutex::new(None)),
-            scan_id: Arc::new(Mutex::new(None)),
+            state: Arc::new(RwLock::new(ControllerState {
+                status: ScanStatus::Idle,
+                scan_id: None,
+                handle: None,
+                cancel: CancellationToken::new(),
+                finish: CancellationToken::new(),
+            })),
+            metrics: Arc::new(Mutex::new(None)),
+            rounds: Arc::new(Mutex::new(None)),
+            timeseries: Arc::new(Mutex::new(MetricsTimeseries::new(0))),
         }
     }
 
-    /// Start a new scan
-    pub async fn start_scan(&self, request: StartScanRequest, base_args: &Args) -> Result<String> {
-        // Check if scan is already running
-        {
-            let status = self.scan_status.lock().unwrap();
-            match *status {
-                ScanStatus::Running | ScanStatus::Starting => {
-                    return Err(anyhow!("Scan is already running"));
-                }
-                _ => {}
-            }
+    /// Start a new scan. `base_args` is the server's configuration; the
+    /// request overrides it field by field. `principal` is the caller,
+    /// recorded on the session.
+    pub async fn start_scan(
+        &self,
+        request: StartScanRequest,
+        base_args: &Args,
+        principal: Option<&str>,
+    ) -> Result<String> {
+        let mut state = self.state.write().await;
+        if matches!(
+            state.status,
+            ScanStatus::Running | ScanStatus::Starting | ScanStatus::Stopping
+        ) {
+            return Err(anyhow!("Scan is already running"));
         }
 
-        // Update status to starting
-        {
-            let mut status = self.scan_status.lock().unwrap();
-            *status = ScanStatus::Starting;
-        }
+        let name = label(request.name.clone(), "name", MAX_LABEL_CHARS)?;
+        let description = label(
+            request.description.clone(),
+            "description",
+            MAX_DESCRIPTION_CHARS,
+        )?;
+        let owner = label(request.owner.clone(), "owner", MAX_LABEL_CHARS)?;
 
-        // Generate scan ID
-        let scan_id = format!("scan_{}", Utc::now().timestamp());
-        {
-            let mut id = self.scan_id.lock().unwrap();
-            *id = Some(scan_id.clone());
-        }
+        let resume = request.resume;
+        let total_rounds = match (request.rounds, request.loop_mode) {
+            (Some(0), _) => return Err(anyhow!("rounds must be at least 1")),
+            (Some(rounds), _) => Some(rounds),
+            (None, true) => None,
+            (None, false) => Some(1),
+        };
+
+        // Create scan arguments from request
+        let (scan_args, exclude) = self.create_scan_args(request, base_args)?;
+        let resumed = if resume {
+            self.resumable_session(&scan_args)?
+        } else {
+            None
+        };
+
+        state.status = ScanStatus::Starting;
+        let (scan_id, resume_from) = match resumed {
+            Some((scan_id, last_ip)) => {
+                info!("Resuming scan {} from {}", scan_id, last_ip);
+                self.db.reopen_scan_session(&scan_id)?;
+                (scan_id, Some(last_ip))
+            }
+            None => {
+                let scan_id = format!("scan_{}", Utc::now().timestamp());
+                self.db.create_scan_session(
+                    &scan_id,
+                    name.as_deref(),
+                    description.as_deref(),
+                    owner.as_deref(),
+                    principal,
+                    self.db.get_current_round()?,
+                )?;
+                (scan_id, None)
+            }
+        };
+        state.scan_id = Some(scan_id.clone());
 
         // Update database metadata
         self.db.save_metadata("scan_status", "starting")?;
@@ -89,84 +172,94 @@ impl ScanController {
         self.db
             .save_metadata("last_scan_start_time", &Utc::now().to_rfc3339())?;
 
-        // Create scan arguments from request
-        let scan_args = self.create_scan_args(request, base_args)?;
-
-        // Start scan in background task
-        let db_clone = self.db.clone();
-        let scan_running = self.scan_running.clone();
-        let scan_status = self.scan_status.clone();
+        // Start scan in background task. It cannot record its outcome before
+        // this method releases the lock, so a fast scan never races `Running`.
+        let db = self.db.clone();
+        let cancel = CancellationToken::new();
+        state.cancel = cancel.clone();
+        let finish = CancellationToken::new();
+        state.finish = finish.clone();
+        let task_state = self.state.clone();
+        let metrics = self.metrics.clone();
+        let rounds = self.rounds.clone();
+        *rounds.lock().unwrap() = Some(RoundProgress {
+            current_round: self.db.get_current_round()?,
+            completed_rounds: 0,
+            total_rounds,
+            stop_after_round: false,
+        });
+        let timeseries = MetricsTimeseries::new(scan_args.timeseries_minutes);
+        *self.timeseries.lock().unwrap() = timeseries.clone();
         let scan_id_clone = scan_id.clone();
 
-        let handle = tokio::spawn(async move {
-            let result =
-                Self::run_scan_task(db_clone, scan_args, scan_running, scan_status.clone()).await;
+        state.handle = Some(tokio::spawn(async move {
+            let result = Self::run_scan_task(
+                db.clone(),
+                &scan_id_clone,
+                scan_args,
+                resume_from,
+                exclude,
+                cancel,
+                finish,
+                &metrics,
+                &rounds,
+                &timeseries,
+            )
+            .await;
+            *metrics.lock().unwrap() = None;
 
-            // Update final status
-            match result {
-                Ok(_) => {
-                    info!("Scan {} completed successfully", scan_id_clone);
-                }
-                Err(ref e) => {
-                    error!("Scan {} failed: {}", scan_id_clone, e);
-                    let mut status = scan_status.lock().unwrap();
-                    *status = ScanStatus::Error(e.to_string());
+            let mut state = task_state.write().await;
+            // A scan being stopped is finalized by `stop_scan`.
+            if state.status == ScanStatus::Running {
+                state.handle = None;
+                match &result {
+                    Ok(_) => {
+                        info!("Scan {} completed successfully", scan_id_clone);
+                        state.status = ScanStatus::Idle;
+                        let _ = db.save_metadata("scan_status", "idle");
+                        let _ = db.finish_scan_session(&scan_id_clone, "completed");
+                    }
+                    Err(e) => {
+                        error!("Scan {} failed: {}", scan_id_clone, e);
+                        state.status = ScanStatus::Error(e.to_string());
+                        let _ = db.save_metadata("scan_status", "error");
+                        let _ = db.finish_scan_session(&scan_id_clone, "error");
+                    }
                 }
             }
 
             result
-        });
+        }));
 
-        // Store handle
-        {
-            let mut handle_guard = self.scan_handle.lock().unwrap();
-            *handle_guard = Some(handle);
-        }
-
-        // Update status to running
-        {
-            let mut status = self.scan_status.lock().unwrap();
-            *status = ScanStatus::Running;
-        }
+        state.status = ScanStatus::Running;
         self.db.save_metadata("scan_status", "running")?;
 
-        self.scan_running.store(true, Ordering::SeqCst);
-
         Ok(scan_id)
     }
 
     /// Stop the current scan
     pub async fn stop_scan(&self) -> Result<()> {
-        // Check if scan is running
-        {
-            let status = self.scan_status.lock().unwrap();
-            match *status {
+        let handle = {
+            let mut state = self.state.write().await;
+            match state.status {
                 ScanStatus::Running | ScanStatus::Starting => {}
                 ScanStatus::Idle => return Err(anyhow!("No scan is currently running")),
                 ScanStatus::Stopping => return Err(anyhow!("Scan is already stopping")),
                 ScanStatus::Stopped => return Err(anyhow!("Scan is already stopped")),
                 ScanStatus::Error(_) => return Err(anyhow!("Scan is in error state")),
             }
-        }
-
-        // Update status to stopping
-        {
-            let mut status = self.scan_status.lock().unwrap();
-            *status = ScanStatus::Stopping;
-        }
-        self.db.save_metadata("scan_status", "stopping")?;
-
-        // Stop scan
-        self.scan_running.store(false, Ordering::SeqCst);
+            state.status = ScanStatus::Stopping;
+            self.db.save_metadata("scan_status", "stopping")?;
 
-        // Wait for scan to stop
-        let handle = {
-            let mut handle_guard = self.scan_handle.lock().unwrap();
-            handle_guard.take()
+            // Stop scan; the scanner flushes what it has already found
+            state.cancel.cancel();
+            state.handle.take()
         };
 
-        if let Some(handle) = handle {
-            match tokio::time::timeout(tokio::time::Duration::from_secs(30), handle).await {
+        // Wait for scan to stop without holding the lock, so status requests
+        // keep answering meanwhile.
+        if let Some(mut handle) = handle {
+            match tokio::time::timeout(tokio::time::Duration::from_secs(30), &mut handle).await {
                 Ok(result) => match result {
                     Ok(_) => {
                         info!("Scan stopped successfully");
@@ -177,42 +270,146 @@ impl ScanController {
                 },
                 Err(_) => {
                     error!("Scan did not stop within 30 seconds, forcing stop");
+                    handle.abort();
                 }
             }
         }
+        *self.metrics.lock().unwrap() = None;
 
         // Update final status
-        {
-            let mut status = self.scan_status.lock().unwrap();
-            *status = ScanStatus::Stopped;
-        }
+        let scan_id = {
+            let mut state = self.state.write().await;
+            state.status = ScanStatus::Stopped;
+            state.scan_id.clone()
+        };
         self.db.save_metadata("scan_status", "stopped")?;
+        if let Some(scan_id) = scan_id {
+            self.db.finish_scan_session(&scan_id, "stopped")?;
+        }
         self.db
             .save_metadata("last_scan_stop_time", &Utc::now().to_rfc3339())?;
 
         Ok(())
     }
 
+    /// Let the round in progress complete, then end the scan as if it had
+    /// run all its rounds. Returns the round that will be the last.
+    pub async fn stop_after_round(&self) -> Result<i64> {
+        let state = self.state.read().await;
+        match state.status {
+            ScanStatus::Running | ScanStatus::Starting => {}
+            ScanStatus::Stopping => return Err(anyhow!("Scan is already stopping")),
+            _ => return Err(anyhow!("No scan is currently running")),
+        }
+        state.finish.cancel();
+        let mut rounds = self.rounds.lock().unwrap();
+        let progress = rounds
+            .as_mut()
+            .ok_or_else(|| anyhow!("No scan is currently running"))?;
+        progress.stop_after_round = true;
+        Ok(progress.current_round)
+    }
+
     /// Get current scan status
-    pub fn get_status(&self) -> ScanStatus {
-        let status = self.scan_status.lock().unwrap();
-        status.clone()
+    pub async fn get_status(&self) -> ScanStatus {
+        self.state.read().await.status.clone()
     }
 
     /// Get current scan ID
-    pub fn get_scan_id(&self) -> Option<String> {
-        let id = self.scan_id.lock().unwrap();
-        id.clone()
+    pub async fn get_scan_id(&self) -> Option<String> {
+        self.state.read().await.scan_id.clone()
+    }
+
+    /// Live counters and queue depths of the API scan in progress, if any.
+    pub fn live_metrics(&self) -> Option<ScanMetrics> {
+        self.metrics.lock().unwrap().clone()
+    }
+
+    /// Metrics samples of the latest API scan, oldest first.
+    pub fn timeseries(&self) -> Vec<MetricsSample> {
+        self.timeseries.lock().unwrap().samples()
+    }
+
+    /// Round progress of the latest API scan, if any was started.
+    pub fn round_progress(&self) -> Option<RoundProgress> {
+        *self.rounds.lock().unwrap()
+    }
+
+    /// Whether a scan task still exists, including one being stopped.
+    pub async fn has_active_task(&self) -> bool {
+        matches!(
+            self.state.read().await.status,
+            ScanStatus::Running | ScanStatus::Starting | ScanStatus::Stopping
+        )
     }
 
     /// Check if scan is running
-    pub fn is_running(&self) -> bool {
-        self.scan_running.load(Ordering::SeqCst)
+    pub async fn is_running(&self) -> bool {
+        matches!(
+            self.state.read().await.status,
+            ScanStatus::Running | ScanStatus::Starting
+        )
+    }
+
+    /// The scan ID and saved position of the latest API scan, if it did not
+    /// complete, is still on the current round and stopped inside the range
+    /// `args` scan. Otherwise the scan starts over.
+    fn resumable_session(&self, args: &Args) -> Result<Option<(String, String)>> {
+        let Some(session) = self.db.get_latest_scan_session()? else {
+            info!("No previous API scan to resume, starting fresh scan");
+            return Ok(None);
+        };
+        let Some(last_ip) = session.last_ip.filter(|_| session.status != "completed") else {
+            info!(
+                "Scan {} has no unfinished round to resume, starting fresh scan",
+                session.scan_id
+            );
+            return Ok(None);
+        };
+        if session.end_round != self.db.get_current_round()? {
+            info!(
+                "Scan {} stopped in round {}, which has since been finished, starting fresh scan",
+                session.scan_id, session.end_round
+            );
+            return Ok(None);
+        }
+        if args.target_hostnames().is_some() {
+            info!("Hostname targets are resolved afresh, starting fresh scan");
+            return Ok(None);
+        }
+        let (start_ip, end_ip) = scan_range(args);
+        let in_range = match (
+            last_ip.parse::<IpAddr>(),
+            start_ip.parse::<IpAddr>(),
+            end_ip.parse::<IpAddr>(),
+        ) {
+            (Ok(ip), Ok(start), Ok(end)) => start <= ip && ip <= end,
+            _ => false,
+        };
+        if !in_range {
+            info!(
+                "Scan {} stopped at {}, outside {} - {}, starting fresh scan",
+                session.scan_id, last_ip, start_ip, end_ip
+            );
+            return Ok(None);
+        }
+        Ok(Some((session.scan_id, last_ip)))
     }
 
-    /// Create scan arguments from request
-    fn create_scan_args(&self, request: StartScanRequest, base_args: &Args) -> Result<Args> {
+    /// Create scan arguments from request, plus the addresses to skip: the
+    /// server's `--excludefile` together with the request's `exclude`.
+    fn create_scan_args(
+        &self,
+        request: StartScanRequest,
+        base_args: &Args,
+    ) -> Result<(Args, Option<ExcludeList>)> {
         let mut args = base_args.clone();
+        // Only the CLI scanner re-verifies, reprioritizes, seeds hosts or
+        // reads an --input-file.
+        args.rescan_open = false;
+        args.priority_weights.clear();
+        args.seed_domains = None;
+        args.input_file = None;
 
         // Override with request parameters
         if let Some(start_ip) = request.start_ip {
@@ -221,36 +418,167 @@ impl ScanController {
         if let Some(end_ip) = request.end_ip {
             args.end_ip = Some(end_ip);
         }
+        // Hostnames replace the range; `validate` checks them.
+        args.target = (!request.hostnames.is_empty()).then(|| request.hostnames.join(","));
         if let Some(ports) = request.ports {
             args.ports = ports;
         }
 
         args.timeout = request.timeout;
         args.concurrency = request.concurrency;
+        if let Some(host_concurrency) = request.host_concurrency {
+            args.host_concurrency = host_concurrency;
+        }
         args.syn = request.syn;
         args.skip_private = request.skip_private;
+        args.loop_mode = request.loop_mode || request.rounds.is_some_and(|rounds| rounds > 1);
+        if let Some(max_rate) = request.max_rate {
+            args.max_rate = max_rate;
+        }
+        if let Some(rate_window_secs) = request.rate_window_secs {
+            args.rate_window_secs = rate_window_secs;
+        }
+        if let Some(rate_burst) = request.rate_burst {
+            args.rate_burst = rate_burst;
+        }
+        if let Some(pipeline_buffer) = request.pipeline_buffer {
+            args.pipeline_buffer = pipeline_buffer;
+        }
+        if let Some(result_buffer) = request.result_buffer {
+            args.result_buffer = result_buffer;
+        }
+        if let Some(db_batch_size) = request.db_batch_size {
+            args.db_batch_size = db_batch_size;
+        }
+        if let Some(flush_interval_ms) = request.flush_interval_ms {
+            args.flush_interval_ms = flush_interval_ms;
+        }
+        if let Some(adaptive_batching) = request.adaptive_batching {
+            args.adaptive_batching = adaptive_batching;
+        }
+        if let Some(io_backend) = request.io_backend {
+            args.io_backend = io_backend;
+        }
+        if request.source_port_range.is_some() {
+            args.source_port_range = request.source_port_range;
+        }
+        if let Some(round_delay_ms) = request.round_delay_ms {
+            args.round_delay_ms = round_delay_ms;
+        }
 
         // Validate arguments
         args.validate()?;
 
-        Ok(args)
+        let mut exclude = args.load_exclude_list()?;
+        if !request.exclude.is_empty() {
+            let extra = ExcludeList::parse(&request.exclude.join("\n")).map_err(|e| anyhow!(e))?;
+            exclude.get_or_insert_with(Default::default).merge(&extra);
+        }
+
+        Ok((args, exclude))
     }
 
-    /// Run scan task
+    /// Run scan task: rounds until `rounds` says they are all done, the
+    /// scan is cancelled, or `finish` asks to end after the current round.
+    /// With `resume_from`, the first round starts at that IP instead of the
+    /// start of the range.
+    #[allow(clippy::too_many_arguments)]
     async fn run_scan_task(
         db: SqliteDB,
+        scan_id: &str,
         args: Args,
-        scan_running: Arc<AtomicBool>,
-        _scan_status: Arc<Mutex<ScanStatus>>,
+        mut resume_from: Option<String>,
+        exclude: Option<ExcludeList>,
+        cancel: CancellationToken,
+        finish: CancellationToken,
+        metrics: &Mutex<Option<ScanMetrics>>,
+        rounds: &Mutex<Option<RoundProgress>>,
+        timeseries: &MetricsTimeseries,
+    ) -> Result<()> {
+        let exclude = exclude.map(Arc::new);
+        // Results found by this scan are credited to it.
+        let scan_db = db.with_scan_id(scan_id);
+        loop {
+            let round = db.get_current_round()?;
+            db.extend_scan_session(scan_id, round)?;
+            if let Some(progress) = rounds.lock().unwrap().as_mut() {
+                progress.current_round = round;
+            }
+            let resumed_args;
+            let round_args = match resume_from.take() {
+                Some(ip) => {
+                    let (_, end_ip) = scan_range(&args);
+                    resumed_args = Args {
+                        start_ip: Some(ip),
+                        end_ip: Some(end_ip),
+                        ..args.clone()
+                    };
+                    &resumed_args
+                }
+                None => &args,
+            };
+            Self::run_round(
+                &scan_db,
+                round_args,
+                round,
+                exclude.clone(),
+                cancel.clone(),
+                metrics,
+                timeseries,
+            )
+            .await?;
+            if cancel.is_cancelled() {
+                return Ok(());
+            }
+            db.save_metadata("last_scan_time", &Utc::now().to_rfc3339())?;
+            let round = db.increment_round()?;
+            let more = match rounds.lock().unwrap().as_mut() {
+                Some(progress) => {
+                    progress.completed_rounds += 1;
+                    progress.current_round = round;
+                    progress
+                        .total_rounds
+                        .is_none_or(|total| progress.completed_rounds < total)
+                }
+                None => false,
+            };
+            if !more || finish.is_cancelled() {
+                return Ok(());
+            }
+            info!("Starting API scan round {}", round);
+            tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                _ = finish.cancelled() => return Ok(()),
+                _ = tokio::time::sleep(tokio::time::Duration::from_millis(args.round_delay_ms)) => {}
+            }
+        }
+    }
+
+    /// Scan the requested range, or the addresses the requested hostnames
+    /// resolve to now, once as `current_round`.
+    async fn run_round(
+        db: &SqliteDB,
+        args: &Args,
+        current_round: i64,
+        exclude: Option<Arc<ExcludeList>>,
+        cancel: CancellationToken,
+        metrics: &Mutex<Option<ScanMetrics>>,
+        timeseries: &MetricsTimeseries,
     ) -> Result<()> {
         use crate::model::parse_port_range;
 
         // Parse port range
-        let ports = parse_port_range(&args.ports).map_err(|e| anyhow!(e))?;
+        let ports = parse_port_range(&args.ports, &args.port_groups).map_err(|e| anyhow!(e))?;
         info!("Scanning {} ports: {:?}", ports.len(), ports);
 
-        // Get current round
-        let current_round = db.get_current_round()?;
+        let resolved = match args.target_hostnames() {
+            Some(hostnames) => Some(
+                HostResolver::default()
+                    .resolve_targets(db, &hostnames, current_round)
+                    .await?,
+            ),
+            None => None,
+        };
 
         // Initialize scanner
         let (tx, rx) = tokio::sync::mpsc::channel(args.pipeline_buffer);
@@ -258,27 +586,27 @@ impl ScanController {
         // Producer task
         let producer_handle = {
             let args_clone = args.clone();
-            let scan_running_clone = scan_running.clone();
+            let cancel = cancel.clone();
             tokio::spawn(async move {
-                let (start_ip, end_ip) = args_clone
-                    .start_ip
-                    .as_ref()
-                    .zip(args_clone.end_ip.as_ref())
-                    .map(|(s, e)| (s.clone(), e.clone()))
-                    .unwrap_or_else(Args::get_default_ipv4_range);
-
-                info!("Scanning IPv4: {} - {}", start_ip, end_ip);
-
-                match crate::model::IpRange::new(&start_ip, &end_ip) {
-                    Ok(ip_range) => {
-                        for ip in ip_range.iter() {
-                            if !scan_running_clone.load(Ordering::SeqCst) {
-                                break;
-                            }
+                let targets: Result<TargetIter, String> = match resolved {
+                    Some(addrs) => Ok(Box::new(addrs.into_iter())),
+                    None => {
+                        let (start_ip, end_ip) = scan_range(&args_clone);
+                        info!("Scanning {} - {}", start_ip, end_ip);
+                        crate::model::IpRange::new(&start_ip, &end_ip)
+                            .map(|range| Box::new(range.iter()) as TargetIter)
+                    }
+                };
 
+                match targets {
+                    Ok(ip_iter) => {
+                        for ip in ip_iter {
                             if args_clone.skip_private && Args::is_private_ipv4(&ip.to_string()) {
                                 continue;
                             }
+                            if exclude.as_ref().is_some_and(|list| list.contains(ip)) {
+                                continue;
+                            }
 
                             // Skip 0.0.0.0/8 range
                             if let std::net::IpAddr::V4(ipv4) = ip {
@@ -287,8 +615,13 @@ impl ScanController {
                                 }
                             }
 
-                            if tx.send(ip).await.is_err() {
-                                break;
+                            tokio::select! {
+                                _ = cancel.cancelled() => break,
+                                sent = tx.send(ip) => {
+                                    if sent.is_err() {
+                                        break;
+                                    }
+                                }
                             }
                         }
                     }
@@ -300,57 +633,56 @@ impl ScanController {
         };
 
         // Consumer (Scanner)
-        let scanner_result = if args.syn {
-            // SYN Scan Mode
-            match SynScanner::new(
-                db.clone(),
-                current_round,
-                args.result_buffer,
-                args.db_batch_size,
-                args.flush_interval_ms,
-                args.max_rate,
-                args.rate_window_secs,
-            ) {
-                Ok(scanner) => {
-                    scanner
-                        .run_pipeline(rx, ports.clone(), |_total_scanned| {})
-                        .await
-                }
-                Err(e) => {
-                    error!("Failed to initialize SYN scanner: {}", e);
-                    Err(e)
-                }
-            }
-        } else {
-            // Connect Scan Mode
-            let config = crate::service::ConScannerConfig {
-                timeout_ms: args.timeout,
-                concurrent_limit: args.concurrency,
-                result_buffer: args.result_buffer,
-                db_batch_size: args.db_batch_size,
-                flush_interval_ms: args.flush_interval_ms,
-                max_rate: args.max_rate,
-                rate_window_secs: args.rate_window_secs,
-            };
-            let scanner = ConScanner::new(db.clone(), current_round, config);
-            scanner
-                .run_pipeline(rx, ports.clone(), |_total_scanned| {})
-                .await
-        };
+        let scanner = crate::service::scanner_from_args(
+            args,
+            db.clone(),
+            current_round,
+            None,
+            cancel.clone(),
+        )?;
+        *metrics.lock().unwrap() = Some(scanner.get_metrics().clone());
+        let _sampler = timeseries.sample(
+            current_round,
+            scanner.get_metrics().clone(),
+            scanner.rate_limiter().clone(),
+            None,
+        );
+        let scanner_result = scanner
+            .run_pipeline(rx, ports.clone(), Box::new(|_total_scanned| {}))
+            .await;
+        scanner.finish().await;
 
         // Wait for producer
         let _ = producer_handle.await;
 
-        // Update round if scan completed successfully
-        if scanner_result.is_ok() {
-            db.save_metadata("last_scan_time", &Utc::now().to_rfc3339())?;
-            let _ = db.increment_round()?;
-        }
-
         scanner_result
     }
 }
 
+/// The range `args` scan, defaulting to the whole IPv4 space.
+fn scan_range(args: &Args) -> (String, String) {
+    args.start_ip
+        .as_ref()
+        .zip(args.end_ip.as_ref())
+        .map(|(s, e)| (s.clone(), e.clone()))
+        .unwrap_or_else(Args::get_default_ipv4_range)
+}
+
+/// Trim an optional free-text field, dropping it when blank and rejecting it
+/// when longer than `max` characters.
+fn label(value: Option<String>, field: &str, max: usize) -> Result<Option<String>> {
+    let Some(value) = value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+    else {
+        return Ok(None);
+    };
+    if value.chars().count() > max {
+        return Err(anyhow!("{} must be at most {} characters", field, max));
+    }
+    Ok(Some(value))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -367,38 +699,24 @@ mod tests {
         assert!(!state.is_cli_scan_running());
     }
 
-    #[tokio::test]
-    async fn test_scan_controller() {
-        let temp_file = NamedTempFile::new().unwrap();
-        let db = SqliteDB::new(temp_file.path().to_str().unwrap()).unwrap();
-        let controller = ScanController::new(db);
-
-        // Test initial state
-        assert_eq!(controller.get_status(), ScanStatus::Idle);
-        assert!(!controller.is_running());
-
-        // Test starting scan
-        let request = StartScanRequest {
-            start_ip: Some("192.168.1.1".to_string()),
-            end_ip: Some("192.168.1.10".to_string()),
-            ports: Some("80,443".to_string()),
-            timeout: 500,
-            concurrency: 10,
-            syn: false,
-            skip_private: false,
-        };
-
-        let base_args = Args {
+    fn test_args() -> Args {
+        Args {
+            command: None,
             config_flag: None,
             config_pos: None,
             start_ip: None,
             end_ip: None,
             ports: "80".to_string(),
+            ports_file: None,
             timeout: 500,
             concurrency: 100,
+            host_concurrency: 4,
             database: "test.db".to_string(),
+            db_key: None,
             verbose: false,
+            quiet: false,
             dry_run: false,
+            rescan_open: false,
             loop_mode: false,
             ipv4: true,
             ipv6: false,
@@ -406,36 +724,533 @@ mod tests {
             skip_private: true,
             syn: false,
             geoip_db: None,
+            whois_servers: None,
+            geo_csv: Vec::new(),
             no_geo: false,
             worker_threads: None,
             pipeline_buffer: 2000,
             result_buffer: 10000,
             db_batch_size: 2000,
             flush_interval_ms: 1000,
+            adaptive_batching: false,
+            wal_checkpoint_secs: 30,
+            wal_truncate_mb: 64,
+            maintenance: Default::default(),
+            attach_db: Vec::new(),
+            api_allow_cidr: Vec::new(),
+            api_trusted_proxy: Vec::new(),
+            api_read_only: false,
+            api_max_range: 1 << 32,
+            api_tls_cert: None,
+            api_tls_key: None,
+            api_client_ca: None,
+            namespace_dir: "namespaces".to_string(),
+            api_operator: Vec::new(),
+            quotas: Default::default(),
+            port_groups: Default::default(),
             max_rate: 100000,
             rate_window_secs: 1,
+            rate_burst: 0,
+            syn_linger_secs: 1,
+            io_backend: "tokio".to_string(),
+            source_port_range: None,
             api: false,
             api_only: false,
             no_api: false,
+            coordinator: false,
+            worker: None,
+            worker_id: None,
+            lease_size: 65536,
+            lease_secs: 300,
+            cluster_token: None,
             api_host: "127.0.0.1".to_string(),
             api_port: 9090,
             swagger_ui: false,
             target: None,
+            seed_domains: None,
+            input_file: None,
             preset: None,
             output_format: "text".to_string(),
+            summary_format: "text".to_string(),
+            json_summary: false,
             probe_service: false,
             probe_timeout: 5,
             probe_concurrency: 50,
+            probe_rate: 100,
+            grab_banner_ms: 0,
+            sni_hosts: None,
+            sni_from_rdns: false,
+            cve_db: None,
+            reputation_providers: Vec::new(),
+            reputation_rate: 40,
+            abuseipdb_key: None,
+            greynoise_key: None,
             geo_concurrency: 8,
             round_delay_ms: 0,
+            stale_rounds: 3,
+            port_history: false,
+            storage_engine: "sqlite".to_string(),
+            bitmap_dir: "bitmaps".to_string(),
+            db_shards: 1,
+            timeseries_minutes: 15,
+            priority_weights: Vec::new(),
+            scan_window: None,
+            exclude_file: None,
+            exclude_country: Vec::new(),
+            script: None,
+            report_email: Vec::new(),
+            report_email_config: Default::default(),
+            notify: Vec::new(),
+            mqtt: Default::default(),
+            syslog: Default::default(),
+            metrics_push: Default::default(),
+            daemon: false,
+            pid_file: "ip-scan.pid".to_string(),
+            log_file: "ip-scan.log".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_controller() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = SqliteDB::new(temp_file.path().to_str().unwrap()).unwrap();
+        let controller = ScanController::new(db);
+
+        // Test initial state
+        assert_eq!(controller.get_status().await, ScanStatus::Idle);
+        assert!(!controller.is_running().await);
+
+        // Test starting scan
+        let request = StartScanRequest {
+            start_ip: Some("192.168.1.1".to_string()),
+            end_ip: Some("192.168.1.10".to_string()),
+            ports: Some("80,443".to_string()),
+            timeout: 500,
+            concurrency: 10,
+            host_concurrency: None,
+            syn: false,
+            skip_private: false,
+            ..Default::default()
         };
 
+        let base_args = test_args();
+
         // This will fail because we don't have proper network setup in test,
         // but it should at least validate the controller logic
-        let result = controller.start_scan(request, &base_args).await;
+        let result = controller.start_scan(request, &base_args, None).await;
         assert!(result.is_ok());
 
         // Clean up
         let _ = controller.stop_scan().await;
     }
+
+    #[test]
+    fn test_request_overrides_server_tuning() {
+        let controller = ScanController::new(SqliteDB::new(":memory:").unwrap());
+        let mut base = test_args();
+        base.loop_mode = true;
+        base.rescan_open = true;
+
+        let (args, exclude) = controller
+            .create_scan_args(
+                StartScanRequest {
+                    timeout: 500,
+                    concurrency: 10,
+                    ..Default::default()
+                },
+                &base,
+            )
+            .unwrap();
+        assert!(!args.loop_mode && !args.rescan_open);
+        assert_eq!(args.max_rate, base.max_rate);
+        assert_eq!(args.db_batch_size, base.db_batch_size);
+        assert!(exclude.is_none());
+
+        let (args, exclude) = controller
+            .create_scan_args(
+                StartScanRequest {
+                    timeout: 500,
+                    concurrency: 10,
+                    max_rate: Some(500),
+                    db_batch_size: Some(64),
+                    flush_interval_ms: Some(200),
+                    adaptive_batching: Some(true),
+                    result_buffer: Some(128),
+                    exclude: vec!["192.0.2.0/24".to_string()],
+                    loop_mode: true,
+                    round_delay_ms: Some(1000),
+                    ..Default::default()
+                },
+                &base,
+            )
+            .unwrap();
+        assert_eq!((args.max_rate, args.db_batch_size), (500, 64));
+        assert_eq!((args.flush_interval_ms, args.result_buffer), (200, 128));
+        assert!(args.adaptive_batching && args.loop_mode);
+        assert_eq!(args.round_delay_ms, 1000);
+        assert!(exclude.unwrap().contains("192.0.2.7".parse().unwrap()));
+
+        for request in [
+            StartScanRequest {
+                timeout: 500,
+                concurrency: 10,
+                db_batch_size: Some(0),
+                ..Default::default()
+            },
+            StartScanRequest {
+                timeout: 500,
+                concurrency: 10,
+                io_backend: Some("epoll".to_string()),
+                ..Default::default()
+            },
+            StartScanRequest {
+                timeout: 500,
+                concurrency: 10,
+                exclude: vec!["not-an-ip".to_string()],
+                ..Default::default()
+            },
+        ] {
+            assert!(controller.create_scan_args(request, &base).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_finished_scan_returns_to_idle_and_can_restart() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        let controller = ScanController::new(db.clone());
+        let request = || StartScanRequest {
+            start_ip: Some("127.0.0.1".to_string()),
+            end_ip: Some("127.0.0.1".to_string()),
+            ports: Some(port.to_string()),
+            timeout: 500,
+            concurrency: 10,
+            host_concurrency: None,
+            syn: false,
+            skip_private: false,
+            ..Default::default()
+        };
+
+        let scan_id = controller
+            .start_scan(
+                StartScanRequest {
+                    name: Some(" loopback ".to_string()),
+                    owner: Some("netops".to_string()),
+                    ..request()
+                },
+                &test_args(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(controller
+            .start_scan(request(), &test_args(), None)
+            .await
+            .is_err());
+        for _ in 0..100 {
+            if controller.get_status().await == ScanStatus::Idle {
+                break;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        }
+        assert_eq!(controller.get_status().await, ScanStatus::Idle);
+        assert!(!controller.is_running().await);
+        assert!(controller.live_metrics().is_none());
+        assert_eq!(db.get_total_open_ports_count().unwrap(), 1);
+        let session = db.get_scan_session(&scan_id).unwrap().unwrap();
+        assert_eq!(session.name.as_deref(), Some("loopback"));
+        assert_eq!(session.owner.as_deref(), Some("netops"));
+        assert_eq!(session.description, None);
+        assert_eq!(session.status, "completed");
+        assert_eq!((session.start_round, session.end_round), (1, 1));
+        let (results, _) = db
+            .get_scan_results(
+                1,
+                10,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&scan_id),
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].scan_id.as_deref(), Some(scan_id.as_str()));
+
+        assert!(controller
+            .start_scan(
+                StartScanRequest {
+                    name: Some("x".repeat(MAX_LABEL_CHARS + 1)),
+                    ..request()
+                },
+                &test_args(),
+                None,
+            )
+            .await
+            .is_err());
+        assert_eq!(controller.get_status().await, ScanStatus::Idle);
+
+        let scan_id = controller
+            .start_scan(
+                StartScanRequest {
+                    loop_mode: true,
+                    round_delay_ms: Some(60_000),
+                    ..request()
+                },
+                &test_args(),
+                None,
+            )
+            .await
+            .unwrap();
+        for _ in 0..100 {
+            if controller
+                .live_metrics()
+                .is_some_and(|metrics| metrics.get_scanned() == 1 && metrics.get_open() == 1)
+            {
+                break;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        }
+        // Between loop rounds the last round's counters stay visible.
+        let live = controller.live_metrics().unwrap().live_stats();
+        assert_eq!((live.scanned, live.open), (1, 1));
+        let _ = controller.stop_scan().await;
+        assert!(controller.live_metrics().is_none());
+        assert_eq!(controller.get_status().await, ScanStatus::Stopped);
+        let session = db.get_scan_session(&scan_id).unwrap().unwrap();
+        assert_eq!(session.status, "stopped");
+        assert_eq!(session.name, None);
+    }
+
+    #[tokio::test]
+    async fn test_resume_continues_an_unfinished_scan() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        db.create_scan_session("scan_old", Some("dmz"), None, None, None, 1)
+            .unwrap();
+        db.with_scan_id("scan_old")
+            .save_progress("127.0.0.2", "IPv4", 1)
+            .unwrap();
+        db.finish_scan_session("scan_old", "stopped").unwrap();
+
+        let controller = ScanController::new(db.clone());
+        let request = |resume| StartScanRequest {
+            start_ip: Some("127.0.0.1".to_string()),
+            end_ip: Some("127.0.0.3".to_string()),
+            ports: Some(port.to_string()),
+            timeout: 500,
+            concurrency: 10,
+            resume,
+            ..Default::default()
+        };
+        let wait_idle = || async {
+            for _ in 0..100 {
+                if controller.get_status().await == ScanStatus::Idle {
+                    break;
+                }
+                tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+            }
+            assert_eq!(controller.get_status().await, ScanStatus::Idle);
+        };
+
+        let scan_id = controller
+            .start_scan(request(true), &test_args(), None)
+            .await
+            .unwrap();
+        assert_eq!(scan_id, "scan_old");
+        wait_idle().await;
+        // 127.0.0.1 was before the saved position and is not scanned again.
+        assert_eq!(db.get_total_open_ports_count().unwrap(), 0);
+        let session = db.get_scan_session("scan_old").unwrap().unwrap();
+        assert_eq!(session.status, "completed");
+        assert_eq!(session.name.as_deref(), Some("dmz"));
+
+        // The completed scan is not resumed again.
+        let scan_id = controller
+            .start_scan(request(true), &test_args(), None)
+            .await
+            .unwrap();
+        assert_ne!(scan_id, "scan_old");
+        wait_idle().await;
+        assert_eq!(db.get_total_open_ports_count().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_hostname_targets_are_resolved_and_filterable() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        let controller = ScanController::new(db.clone());
+        let request = |hostnames: &[&str]| StartScanRequest {
+            hostnames: hostnames.iter().map(|name| name.to_string()).collect(),
+            ports: Some(port.to_string()),
+            timeout: 500,
+            concurrency: 10,
+            ..Default::default()
+        };
+        assert!(controller
+            .start_scan(request(&["not a host"]), &test_args(), None)
+            .await
+            .is_err());
+
+        controller
+            .start_scan(request(&["LocalHost"]), &test_args(), None)
+            .await
+            .unwrap();
+        for _ in 0..100 {
+            if controller.get_status().await == ScanStatus::Idle {
+                break;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        }
+        assert_eq!(controller.get_status().await, ScanStatus::Idle);
+        let (results, total) = db
+            .get_scan_results(
+                1,
+                10,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some("localhost"),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(
+            (results[0].ip_address.as_str(), results[0].port),
+            ("127.0.0.1", port)
+        );
+        let (_, total) = db
+            .get_scan_results(
+                1,
+                10,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some("example.com"),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(total, 0);
+    }
+
+    #[tokio::test]
+    async fn test_rounds_limit_and_stop_after_round() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        let controller = ScanController::new(db.clone());
+        let request = || StartScanRequest {
+            start_ip: Some("127.0.0.1".to_string()),
+            end_ip: Some("127.0.0.1".to_string()),
+            ports: Some(port.to_string()),
+            timeout: 500,
+            concurrency: 10,
+            ..Default::default()
+        };
+        let wait_for = |done: fn(Option<RoundProgress>) -> bool| {
+            let controller = &controller;
+            async move {
+                for _ in 0..100 {
+                    if done(controller.round_progress())
+                        && controller.get_status().await == ScanStatus::Idle
+                    {
+                        break;
+                    }
+                    tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+                }
+            }
+        };
+
+        assert!(controller
+            .start_scan(
+                StartScanRequest {
+                    rounds: Some(0),
+                    ..request()
+                },
+                &test_args(),
+                None,
+            )
+            .await
+            .is_err());
+        assert!(controller.round_progress().is_none());
+
+        let scan_id = controller
+            .start_scan(
+                StartScanRequest {
+                    rounds: Some(3),
+                    ..request()
+                },
+                &test_args(),
+                None,
+            )
+            .await
+            .unwrap();
+        wait_for(|p| p.is_some_and(|p| p.completed_rounds == 3)).await;
+        assert_eq!(controller.get_status().await, ScanStatus::Idle);
+        assert_eq!(
+            controller.round_progress(),
+            Some(RoundProgress {
+                current_round: 4,
+                completed_rounds: 3,
+                total_rounds: Some(3),
+                stop_after_round: false,
+            })
+        );
+        let session = db.get_scan_session(&scan_id).unwrap().unwrap();
+        assert_eq!((session.start_round, session.end_round), (1, 3));
+
+        // Looping until told to stop; the request comes in between rounds.
+        controller
+            .start_scan(
+                StartScanRequest {
+                    loop_mode: true,
+                    round_delay_ms: Some(60_000),
+                    ..request()
+                },
+                &test_args(),
+                None,
+            )
+            .await
+            .unwrap();
+        for _ in 0..100 {
+            if controller
+                .round_progress()
+                .is_some_and(|p| p.completed_rounds == 1)
+            {
+                break;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        }
+        assert_eq!(controller.round_progress().unwrap().total_rounds, None);
+        assert_eq!(controller.stop_after_round().await.unwrap(), 5);
+        wait_for(|_| true).await;
+        assert_eq!(controller.get_status().await, ScanStatus::Idle);
+        let progress = controller.round_progress().unwrap();
+        assert_eq!((progress.completed_rounds, progress.current_round), (1, 5));
+        assert!(progress.stop_after_round);
+        assert!(controller.stop_after_round().await.is_err());
+    }
 }
@@ -0,0 +1,197 @@
+//! The interface every probing backend implements, so the CLI loop, the API
+//! controller and cluster workers drive connect and SYN scans the same way.
+
+use super::{ConScanner, ConScannerConfig, RateLimiter, ScriptHooks, SynScanner};
+use crate::cli::Args;
+use crate::dao::SqliteDB;
+use crate::model::{OpenPort, ScanMetrics};
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Called with the number of IPs dispatched so far.
+pub type ProgressFn = Box<dyn Fn(usize) + Send + Sync>;
+
+pub trait Scanner: Send + Sync {
+    /// Probe `ports` on every IP received from `rx` until the channel closes
+    /// or the scan is cancelled.
+    fn run_pipeline(
+        &self,
+        rx: mpsc::Receiver<IpAddr>,
+        ports: Vec<u16>,
+        progress: ProgressFn,
+    ) -> BoxFuture<'_, Result<()>>;
+
+    fn get_metrics(&self) -> &ScanMetrics;
+
+    /// The `--max-rate` bucket probes are paced by.
+    fn rate_limiter(&self) -> &RateLimiter;
+
+    /// Receive every open port found from now on.
+    fn subscribe(&self) -> broadcast::Receiver<OpenPort>;
+
+    /// Wait until every result has been written and release the scanner's
+    /// sockets and threads.
+    fn finish(self: Box<Self>) -> BoxFuture<'static, ()>;
+}
+
+impl Scanner for ConScanner {
+    fn run_pipeline(
+        &self,
+        rx: mpsc::Receiver<IpAddr>,
+        ports: Vec<u16>,
+        progress: ProgressFn,
+    ) -> BoxFuture<'_, Result<()>> {
+        Box::pin(ConScanner::run_pipeline(self, rx, ports, progress))
+    }
+
+    fn get_metrics(&self) -> &ScanMetrics {
+        ConScanner::get_metrics(self)
+    }
+
+    fn rate_limiter(&self) -> &RateLimiter {
+        ConScanner::rate_limiter(self)
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<OpenPort> {
+        ConScanner::subscribe(self)
+    }
+
+    fn finish(self: Box<Self>) -> BoxFuture<'static, ()> {
+        Box::pin(ConScanner::finish(*self))
+    }
+}
+
+impl Scanner for SynScanner {
+    fn run_pipeline(
+        &self,
+        rx: mpsc::Receiver<IpAddr>,
+        ports: Vec<u16>,
+        progress: ProgressFn,
+    ) -> BoxFuture<'_, Result<()>> {
+        Box::pin(SynScanner::run_pipeline(self, rx, ports, progress))
+    }
+
+    fn get_metrics(&self) -> &ScanMetrics {
+        SynScanner::get_metrics(self)
+    }
+
+    fn rate_limiter(&self) -> &RateLimiter {
+        SynScanner::rate_limiter(self)
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<OpenPort> {
+        SynScanner::subscribe(self)
+    }
+
+    fn finish(self: Box<Self>) -> BoxFuture<'static, ()> {
+        Box::pin(SynScanner::finish(*self))
+    }
+}
+
+/// Create the scanner `args` ask for. `--syn` without raw socket access
+/// falls back to a connect scan with the same limits.
+pub fn scanner_from_args(
+    args: &Args,
+    db: SqliteDB,
+    round: i64,
+    hooks: Option<Arc<ScriptHooks>>,
+    cancel: CancellationToken,
+) -> Result<Box<dyn Scanner>> {
+    let source_ports = args.parsed_source_ports()?;
+    if args.syn {
+        match SynScanner::new(
+            db.clone(),
+            round,
+            args.result_buffer,
+            args.db_batch_size,
+            args.flush_interval_ms,
+            args.adaptive_batching,
+            args.max_rate,
+            args.rate_window_secs,
+            args.rate_burst,
+            hooks.clone(),
+            cancel.clone(),
+        ) {
+            Ok(scanner) => {
+                if args.grab_banner_ms > 0 {
+                    warn!("Banner grabbing needs a connect scan; SYN scans skip it");
+                }
+                let scanner = scanner
+                    .with_linger(Duration::from_secs(args.syn_linger_secs))
+                    .with_source_ports(source_ports.unwrap_or_default());
+                return Ok(Box::new(scanner));
+            }
+            Err(e) => {
+                warn!("SYN scanner unavailable, using connect scan: {}", e);
+                #[cfg(target_os = "windows")]
+                info!("SYN scans need Npcap and an elevated prompt");
+                #[cfg(not(target_os = "windows"))]
+                info!("SYN scans need root: sudo ./ip-scan --syn ...");
+            }
+        }
+    }
+    let config = connect_config(args, hooks, cancel)?;
+    Ok(Box::new(ConScanner::new(db, round, config)))
+}
+
+/// Connect scanner settings taken from `args`.
+pub fn connect_config(
+    args: &Args,
+    hooks: Option<Arc<ScriptHooks>>,
+    cancel: CancellationToken,
+) -> Result<ConScannerConfig> {
+    Ok(ConScannerConfig {
+        timeout_ms: args.timeout,
+        concurrent_limit: args.concurrency,
+        host_concurrent_limit: args.host_concurrency,
+        result_buffer: args.result_buffer,
+        db_batch_size: args.db_batch_size,
+        flush_interval_ms: args.flush_interval_ms,
+        adaptive_batching: args.adaptive_batching,
+        max_rate: args.max_rate,
+        rate_window_secs: args.rate_window_secs,
+        rate_burst: args.rate_burst,
+        hooks,
+        cancel,
+        io_uring: args.io_backend == "uring",
+        source_ports: args.parsed_source_ports()?,
+        banner_grab_ms: args.grab_banner_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[tokio::test]
+    async fn test_scanner_from_args_drives_connect_scan() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+
+        let args = Args::try_parse_from(["ip-scan", "--timeout", "200"]).unwrap();
+        let db = SqliteDB::new(":memory:").unwrap();
+        let scanner =
+            scanner_from_args(&args, db.clone(), 1, None, CancellationToken::new()).unwrap();
+        let mut events = scanner.subscribe();
+        let (tx, rx) = mpsc::channel(1);
+        tx.send("127.0.0.1".parse().unwrap()).await.unwrap();
+        drop(tx);
+
+        scanner
+            .run_pipeline(rx, vec![port], Box::new(|_| {}))
+            .await
+            .unwrap();
+        assert_eq!(scanner.get_metrics().get_open(), 1);
+        scanner.finish().await;
+        assert_eq!(events.recv().await.unwrap().port, port);
+        assert_eq!(db.get_total_open_ports_count().unwrap(), 1);
+    }
+}
@@ -0,0 +1,217 @@
+//! User scripting hooks (rhai) run on every open port before it is persisted.
+//!
+//! A script defines `fn on_open_port(event)`, where `event` is
+//! `#{ ip, port, round }`, and returns one of:
+//!
+//! - `()` or `true`: keep the result unchanged;
+//! - `false`: drop it, so it is neither stored, enriched nor sent to the
+//!   event stream and notifiers;
+//! - a map with optional `drop: bool`, `tags: [string]` and
+//!   `findings: [#{ kind, value }]`; tags are stored as `kind = "tag"`.
+
+use anyhow::{anyhow, Context, Result};
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use std::path::Path;
+use tracing::warn;
+
+const HOOK_FN: &str = "on_open_port";
+/// Keeps a runaway script from stalling the DB writer it runs on.
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_FINDINGS_PER_EVENT: usize = 32;
+const MAX_VALUE_LEN: usize = 256;
+
+/// A script-produced finding: `(ip, port, kind, value)`.
+pub type Finding = (String, u16, String, String);
+
+pub struct ScriptHooks {
+    engine: Engine,
+    ast: AST,
+}
+
+impl ScriptHooks {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read script {}", path.display()))?;
+        Self::compile(&source).with_context(|| format!("Invalid script {}", path.display()))
+    }
+
+    pub fn compile(source: &str) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(32)
+            // rhai's debug-build defaults are tight enough to reject ordinary
+            // nested map literals inside a function.
+            .set_max_expr_depths(64, 64)
+            .set_max_string_size(4096)
+            .set_max_array_size(MAX_FINDINGS_PER_EVENT * 4)
+            .set_max_map_size(64);
+        let ast = engine.compile(source).map_err(|e| anyhow!("{}", e))?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == HOOK_FN && f.params.len() == 1)
+        {
+            return Err(anyhow!("script must define `fn {}(event)`", HOOK_FN));
+        }
+        Ok(Self { engine, ast })
+    }
+
+    /// Run the hook for one open port, appending any findings. Returns
+    /// `false` when the script drops the result. A failing script keeps the
+    /// result, so a broken hook never loses scan data.
+    pub fn on_open_port(
+        &self,
+        ip: &str,
+        port: u16,
+        scan_round: i64,
+        findings: &mut Vec<Finding>,
+    ) -> bool {
+        let mut event = Map::new();
+        event.insert("ip".into(), ip.into());
+        event.insert("port".into(), (port as i64).into());
+        event.insert("round".into(), scan_round.into());
+
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            CallFnOptions::new().eval_ast(false),
+            &mut Scope::new(),
+            &self.ast,
+            HOOK_FN,
+            (event,),
+        );
+        let value = match result {
+            Ok(value) => value,
+            Err(e) => {
+                warn!("Script hook failed for {}:{}: {}", ip, port, e);
+                return true;
+            }
+        };
+
+        if let Ok(keep) = value.as_bool() {
+            return keep;
+        }
+        let Some(map) = value.try_cast::<Map>() else {
+            return true;
+        };
+
+        // `findings` collects a whole writer batch; the cap is per event.
+        let limit = findings.len() + MAX_FINDINGS_PER_EVENT;
+        let mut push = |kind: &str, value: String| {
+            if findings.len() < limit && !value.is_empty() {
+                let value = value.chars().take(MAX_VALUE_LEN).collect();
+                findings.push((ip.to_string(), port, kind.to_string(), value));
+            }
+        };
+        if let Some(tags) = map
+            .get("tags")
+            .and_then(|t| t.clone().try_cast::<rhai::Array>())
+        {
+            for tag in tags {
+                push("tag", tag.to_string());
+            }
+        }
+        if let Some(items) = map
+            .get("findings")
+            .and_then(|f| f.clone().try_cast::<rhai::Array>())
+        {
+            for item in items {
+                let Some(item) = item.try_cast::<Map>() else {
+                    continue;
+                };
+                let field = |name: &str| item.get(name).map(|v| v.to_string());
+                if let (Some(kind), Some(value)) = (field("kind"), field("value")) {
+                    push(&kind, value);
+                }
+            }
+        }
+        !map.get("drop")
+            .and_then(|d| d.as_bool().ok())
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_tags_drops_and_emits_findings() {
+        let hooks = ScriptHooks::compile(
+            r#"
+            fn on_open_port(event) {
+                if event.port == 23 { return false; }
+                if event.port == 22 {
+                    return #{
+                        tags: ["ssh", "round-" + event.round],
+                        findings: [#{ kind: "note", value: "ssh on " + event.ip }],
+                    };
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+        let mut findings = Vec::new();
+        assert!(!hooks.on_open_port("192.0.2.1", 23, 1, &mut findings));
+        assert!(hooks.on_open_port("192.0.2.1", 80, 1, &mut findings));
+        assert!(findings.is_empty());
+
+        assert!(hooks.on_open_port("192.0.2.1", 22, 7, &mut findings));
+        let pairs: Vec<(&str, &str)> = findings
+            .iter()
+            .map(|(_, _, kind, value)| (kind.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("tag", "ssh"),
+                ("tag", "round-7"),
+                ("note", "ssh on 192.0.2.1")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_findings_cap_applies_per_event_within_a_batch() {
+        let hooks = ScriptHooks::compile(
+            r#"
+            fn on_open_port(event) {
+                let tags = ["seen"];
+                if event.port == 0 {
+                    for i in 0..40 { tags.push("extra-" + i); }
+                }
+                #{ tags: tags }
+            }
+            "#,
+        )
+        .unwrap();
+
+        // One writer batch: the shared Vec already holds earlier events'
+        // findings when later events run.
+        let mut findings = Vec::new();
+        for port in 1..=40 {
+            assert!(hooks.on_open_port("192.0.2.1", port, 1, &mut findings));
+        }
+        assert_eq!(findings.len(), 40);
+        assert_eq!(findings[39].1, 40);
+
+        assert!(hooks.on_open_port("192.0.2.1", 0, 1, &mut findings));
+        assert_eq!(findings.len(), 40 + MAX_FINDINGS_PER_EVENT);
+    }
+
+    #[test]
+    fn test_failing_or_runaway_script_keeps_result() {
+        let hooks = ScriptHooks::compile(
+            "fn on_open_port(event) { if event.port == 1 { loop {} } throw \"boom\"; }",
+        )
+        .unwrap();
+        let mut findings = Vec::new();
+        assert!(hooks.on_open_port("192.0.2.1", 1, 1, &mut findings));
+        assert!(hooks.on_open_port("192.0.2.1", 2, 1, &mut findings));
+    }
+
+    #[test]
+    fn test_script_without_hook_is_rejected() {
+        let err = ScriptHooks::compile("fn other(x) { x }").err().unwrap();
+        assert!(err.to_string().contains("on_open_port"));
+    }
+}
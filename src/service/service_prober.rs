@@ -1,7 +1,15 @@
-use crate::model::ServiceInfo;
+use super::probe::{Probe, ProbeContext};
+use super::RateLimiter;
+use crate::model::{ServiceInfo, VirtualHostInfo};
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
 use tokio::time::timeout;
 use tracing::debug;
 
@@ -9,43 +17,77 @@ const PROBE_TIMEOUT_SECS: u64 = 5;
 const BANNER_READ_TIMEOUT_SECS: u64 = 3;
 const BANNER_MAX_BYTES: usize = 2048;
 const HTTP_BODY_PREVIEW_BYTES: usize = 512;
+const DEFAULT_PROBE_RATE: u64 = 100;
+/// Hostnames probed per IP, whatever the sources list.
+pub const MAX_HOSTNAMES_PER_IP: usize = 16;
 
+/// Post-detection pipeline: runs every applicable [`Probe`] against each open
+/// port, with one concurrency limit and start rate shared by all probes.
 #[derive(Clone)]
 pub struct ServiceProber {
-    http_client: reqwest::Client,
-    banner_timeout: Duration,
-    concurrency: usize,
+    probes: Vec<Arc<dyn Probe>>,
+    /// The built-in HTTP probe, also used for per-hostname probing.
+    http: Arc<HttpProbe>,
+    /// Upper bound for one probe's whole run, on top of its own timeouts.
+    probe_timeout: Duration,
+    semaphore: Arc<Semaphore>,
+    rate_limiter: RateLimiter,
 }
 
 impl ServiceProber {
     pub fn new(timeout_secs: u64, concurrency: usize) -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(timeout_secs.max(1)))
-            .connect_timeout(Duration::from_secs(timeout_secs.max(1)))
-            .danger_accept_invalid_certs(true)
-            .no_proxy()
-            .build()
-            .unwrap_or_default();
-
+        let timeout_secs = timeout_secs.max(1);
+        let http = Arc::new(HttpProbe::new(timeout_secs));
+        let probes: Vec<Arc<dyn Probe>> = vec![
+            http.clone(),
+            Arc::new(TlsProbe),
+            Arc::new(BannerProbe {
+                read_timeout: Duration::from_secs(BANNER_READ_TIMEOUT_SECS),
+            }),
+        ];
         Self {
-            http_client: client,
-            banner_timeout: Duration::from_secs(BANNER_READ_TIMEOUT_SECS),
-            concurrency: concurrency.max(1),
+            probes,
+            http,
+            probe_timeout: Duration::from_secs(timeout_secs * 2),
+            semaphore: Arc::new(Semaphore::new(concurrency.max(1))),
+            rate_limiter: RateLimiter::new(DEFAULT_PROBE_RATE as usize, Duration::from_secs(1)),
         }
     }
 
+    /// Limit how many probes start per second across all hosts.
+    pub fn with_rate_limit(mut self, per_second: u64) -> Self {
+        self.rate_limiter = RateLimiter::new(per_second.max(1) as usize, Duration::from_secs(1));
+        self
+    }
+
+    /// Add a probe that runs after the built-in ones.
+    pub fn register_probe(&mut self, probe: Arc<dyn Probe>) {
+        self.probes.push(probe);
+    }
+
     pub async fn probe_ip(&self, ip: &str, open_ports: &[u16]) -> Vec<ServiceInfo> {
-        let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(self.concurrency.max(1)));
+        self.probe_ip_with_banners(ip, open_ports, &HashMap::new())
+            .await
+    }
+
+    /// `probe_ip` for ports whose greeting was already read during the scan
+    /// (`--grab-banner-ms`); those skip the banner probe's connection.
+    pub async fn probe_ip_with_banners(
+        &self,
+        ip: &str,
+        open_ports: &[u16],
+        banners: &HashMap<u16, String>,
+    ) -> Vec<ServiceInfo> {
         let mut join_set = tokio::task::JoinSet::new();
 
         for &port in open_ports {
-            let sem = semaphore.clone();
             let ip_owned = ip.to_string();
             let prober = self.clone();
-
+            let banner = banners.get(&port).cloned();
             join_set.spawn(async move {
-                let _permit = sem.acquire().await.unwrap();
-                prober.probe_port(&ip_owned, port).await
+                prober
+                    .probe_port_with_banner(&ip_owned, port, banner.as_deref())
+                    .await
             });
         }
 
@@ -59,20 +101,74 @@ impl ServiceProber {
     }
 
     pub async fn probe_port(&self, ip: &str, port: u16) -> Option<ServiceInfo> {
+        self.probe_port_with_banner(ip, port, None).await
+    }
+
+    async fn probe_port_with_banner(
+        &self,
+        ip: &str,
+        port: u16,
+        banner: Option<&str>,
+    ) -> Option<ServiceInfo> {
+        let _permit = self.semaphore.acquire().await.ok()?;
         let mut info = ServiceInfo::new(ip.to_string(), port);
         info.service_name = ServiceInfo::guess_service_name(port).to_string();
+        if let Some(banner) = banner {
+            info.banner = Some(banner.lines().next().unwrap_or("").to_string());
+            Self::parse_banner_info(&mut info, banner);
+        }
 
-        if ServiceInfo::is_probable_http_port(port) || ServiceInfo::is_probable_https_port(port) {
-            self.probe_http(ip, port, &mut info).await;
-        } else {
-            self.probe_banner(ip, port, &mut info).await;
+        for probe in &self.probes {
+            if !probe.applies_to(port, &info) {
+                continue;
+            }
+            self.rate_limiter.acquire().await;
+
+            let stream = if probe.needs_socket() {
+                let start = Instant::now();
+                match timeout(
+                    Duration::from_secs(PROBE_TIMEOUT_SECS),
+                    TcpStream::connect((ip, port)),
+                )
+                .await
+                {
+                    Ok(Ok(stream)) => {
+                        info.rtt_ms
+                            .get_or_insert(start.elapsed().as_secs_f64() * 1000.0);
+                        Some(stream)
+                    }
+                    _ => {
+                        debug!(
+                            "{} probe could not connect to {}:{}",
+                            probe.name(),
+                            ip,
+                            port
+                        );
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+
+            let ctx = ProbeContext { ip, port, stream };
+            match timeout(self.probe_timeout, probe.probe(ctx, &mut info)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => debug!("{} probe failed {}:{}: {}", probe.name(), ip, port, e),
+                Err(_) => debug!("{} probe timed out {}:{}", probe.name(), ip, port),
+            }
         }
 
-        info.protocol = self.guess_protocol(&info);
+        info.protocol = Self::guess_protocol(&info);
 
-        if let Some(ref banner) = info.banner {
-            info.service_version =
-                ServiceInfo::parse_version_from_banner(&info.service_name, banner);
+        // Only overwrite with a banner-derived version; probes may already
+        // have recorded one.
+        if let Some(version) = info
+            .banner
+            .as_deref()
+            .and_then(|banner| ServiceInfo::parse_version_from_banner(&info.service_name, banner))
+        {
+            info.service_version = Some(version);
         }
         if info.service_name == "redis" {
             // Redis INFO is key/value text; retain only the version, not the full dump.
@@ -84,227 +180,92 @@ impl ServiceProber {
         Some(info)
     }
 
-    async fn probe_http(&self, ip: &str, port: u16, info: &mut ServiceInfo) {
-        let scheme = if ServiceInfo::is_probable_https_port(port) {
-            "https"
-        } else {
-            "http"
-        };
-        let url = format!("{}://{}:{}/", scheme, ip, port);
-
-        let start = Instant::now();
-        match self.http_client.get(&url).send().await {
-            Ok(resp) => {
-                let rtt = start.elapsed().as_secs_f64() * 1000.0;
-                info.rtt_ms = Some(rtt);
-
-                if let Some(server) = resp.headers().get("server") {
-                    info.http_server = Some(server.to_str().unwrap_or("").to_string());
-                }
-
-                let status = resp.status();
-                info.banner = Some(format!("HTTP {}", status.as_u16()));
-                let expected = [
-                    "content-security-policy",
-                    "strict-transport-security",
-                    "x-content-type-options",
-                    "x-frame-options",
-                    "referrer-policy",
-                ];
-                let present: Vec<&str> = expected
-                    .iter()
-                    .copied()
-                    .filter(|name| resp.headers().contains_key(*name))
-                    .collect();
-                info.http_security_headers = Some(format!(
-                    "{}/{} present: {}",
-                    present.len(),
-                    expected.len(),
-                    present.join(",")
-                ));
-
-                // Fetch the page and favicon concurrently: favicon is independent
-                // enrichment and must not add a full extra RTT to every HTTP probe.
-                let favicon_url = format!("{}://{}:{}/favicon.ico", scheme, ip, port);
-                let favicon_request = self.http_client.get(&favicon_url).send();
-                let body_request = resp.text();
-                let (body_result, favicon_result) = tokio::join!(body_request, favicon_request);
-
-                if let Ok(body) = body_result {
-                    if let Some(title) = self.extract_html_title(&body) {
-                        info.http_title = Some(title);
-                    }
-                    let preview: String = body.chars().take(HTTP_BODY_PREVIEW_BYTES).collect();
-                    let cleaned = preview
-                        .replace(['\n', '\r', '\t'], " ")
-                        .split_whitespace()
-                        .collect::<Vec<_>>()
-                        .join(" ");
-                    let trimmed = cleaned.chars().take(300).collect::<String>();
-                    info.http_body_preview = Some(trimmed);
-                    info.http_body_hash = Some(self.compute_body_hash(&body));
-                    let technologies =
-                        Self::detect_web_technologies(&body, info.http_server.as_deref());
-                    if !technologies.is_empty() {
-                        info.service_version = Some(technologies.join(", "));
-                    }
-                }
-
-                if let Ok(favicon) = favicon_result {
-                    if favicon.status().is_success() {
-                        if let Ok(bytes) = favicon.bytes().await {
-                            if !bytes.is_empty() && bytes.len() <= 1024 * 1024 {
-                                let hash = Self::compute_bytes_hash(&bytes);
-                                let marker = format!("favicon:{}", hash);
-                                info.service_version = Some(match info.service_version.take() {
-                                    Some(existing) => format!("{}, {}", existing, marker),
-                                    None => marker,
-                                });
-                            }
-                        }
-                    }
-                }
-
-                if ServiceInfo::is_probable_https_port(port) {
-                    let ip_owned = ip.to_string();
-                    let tls = tokio::task::spawn_blocking(move || {
-                        Self::extract_tls_info_blocking(&ip_owned, port)
-                    })
-                    .await
-                    .unwrap_or_default();
-                    info.tls_subject = tls.0;
-                    info.tls_issuer = tls.1;
-                    info.tls_version = tls.2;
-                    if tls.3.is_some() {
-                        info.os_guess = tls.3;
-                    }
-                }
-            }
-            Err(e) => {
-                debug!("HTTP probe failed {}:{}: {}", ip, port, e);
-                self.probe_banner(ip, port, info).await;
-            }
-        }
-    }
-
-    fn extract_tls_info_blocking(
+    /// Probe every HTTP(S) port in `open_ports` once per hostname, sending
+    /// the name as SNI and `Host` header while still connecting to `ip`.
+    /// At most [`MAX_HOSTNAMES_PER_IP`] names are used; each probe counts
+    /// against the prober's concurrency and rate limits like any other.
+    pub async fn probe_hostnames(
+        &self,
         ip: &str,
-        port: u16,
-    ) -> (
-        Option<String>,
-        Option<String>,
-        Option<String>,
-        Option<String>,
-    ) {
-        let mut info = ServiceInfo::new(ip.to_string(), port);
-        let connector = match native_tls::TlsConnector::builder()
-            .danger_accept_invalid_certs(true)
-            .build()
-        {
-            Ok(c) => c,
-            Err(_) => return (None, None, None, None),
-        };
-
-        let addr = format!("{}:{}", ip, port);
-        let sock_addr: std::net::SocketAddr = match addr.parse() {
-            Ok(a) => a,
-            Err(_) => return (None, None, None, None),
-        };
-
-        let tcp_stream = match std::net::TcpStream::connect_timeout(
-            &sock_addr,
-            Duration::from_secs(PROBE_TIMEOUT_SECS),
-        ) {
-            Ok(s) => s,
-            Err(_) => return (None, None, None, None),
-        };
-
-        Self::read_ttl_from_stream(&tcp_stream, &mut info);
-
-        if let Ok(tls_stream) = connector.connect(ip, tcp_stream) {
-            if let Ok(Some(cert)) = tls_stream.peer_certificate() {
-                if let Ok(der_bytes) = cert.to_der() {
-                    let cn = extract_cn_from_der(&der_bytes);
-                    info.tls_subject =
-                        Some(cn.unwrap_or_else(|| "(certificate present)".to_string()));
-                    info.tls_issuer = Some("present".to_string());
-                }
+        open_ports: &[u16],
+        hostnames: &[String],
+    ) -> Vec<VirtualHostInfo> {
+        let mut join_set = tokio::task::JoinSet::new();
+        let web_ports = open_ports.iter().copied().filter(|&port| {
+            ServiceInfo::is_probable_http_port(port) || ServiceInfo::is_probable_https_port(port)
+        });
+        for port in web_ports {
+            for hostname in hostnames.iter().take(MAX_HOSTNAMES_PER_IP) {
+                let ip_owned = ip.to_string();
+                let hostname = hostname.clone();
+                let prober = self.clone();
+                join_set
+                    .spawn(async move { prober.probe_hostname(&ip_owned, port, &hostname).await });
             }
-            info.tls_version = Some("TLS".to_string());
         }
 
-        (
-            info.tls_subject,
-            info.tls_issuer,
-            info.tls_version,
-            info.os_guess,
-        )
-    }
-
-    #[cfg(unix)]
-    fn read_ttl_from_stream(stream: &std::net::TcpStream, info: &mut ServiceInfo) {
-        use std::os::unix::io::AsRawFd;
-        let fd = stream.as_raw_fd();
-        let mut ttl: u32 = 0;
-        let mut ttl_len = std::mem::size_of::<u32>() as libc::socklen_t;
-        unsafe {
-            let ret = libc::getsockopt(
-                fd,
-                libc::IPPROTO_IP,
-                libc::IP_TTL,
-                &mut ttl as *mut u32 as *mut _,
-                &mut ttl_len,
-            );
-            if ret == 0 {
-                info.os_guess = ServiceInfo::guess_os_from_ttl(ttl).map(|s| s.to_string());
+        let mut results = Vec::new();
+        while let Some(res) = join_set.join_next().await {
+            if let Ok(Some(vhost)) = res {
+                results.push(vhost);
             }
         }
+        results.sort_by(|a, b| (a.port, &a.hostname).cmp(&(b.port, &b.hostname)));
+        results
     }
 
-    #[cfg(not(unix))]
-    fn read_ttl_from_stream(_stream: &std::net::TcpStream, _info: &mut ServiceInfo) {}
+    async fn probe_hostname(&self, ip: &str, port: u16, hostname: &str) -> Option<VirtualHostInfo> {
+        let _permit = self.semaphore.acquire().await.ok()?;
+        let mut info = ServiceInfo::new(ip.to_string(), port);
 
-    async fn probe_banner(&self, ip: &str, port: u16, info: &mut ServiceInfo) {
-        let addr = format!("{}:{}", ip, port);
-        let start = Instant::now();
-        let conn = timeout(
-            Duration::from_secs(PROBE_TIMEOUT_SECS),
-            TcpStream::connect(&addr),
+        self.rate_limiter.acquire().await;
+        match timeout(
+            self.probe_timeout,
+            self.http.probe_http_host(ip, port, hostname, &mut info),
         )
-        .await;
-
-        let Ok(Ok(mut stream)) = conn else {
-            return;
-        };
-
-        info.rtt_ms = Some(start.elapsed().as_secs_f64() * 1000.0);
-
-        let probe_data = match info.service_name.as_str() {
-            "ftp" => b"\r\n".to_vec(),
-            "smtp" => b"EHLO probe\r\n".to_vec(),
-            "pop3" => b"\r\n".to_vec(),
-            "imap" => b"a001 CAPABILITY\r\n".to_vec(),
-            "redis" => b"INFO\r\n".to_vec(),
-            _ => b"".to_vec(),
-        };
-
-        if !probe_data.is_empty() {
-            let _ = stream.write_all(&probe_data).await;
+        .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => debug!("http probe failed {}:{} as {}: {}", ip, port, hostname, e),
+            Err(_) => debug!("http probe timed out {}:{} as {}", ip, port, hostname),
         }
 
-        let mut buf = vec![0u8; BANNER_MAX_BYTES];
-        match timeout(self.banner_timeout, stream.read(&mut buf)).await {
-            Ok(Ok(n)) if n > 0 => {
-                let banner = String::from_utf8_lossy(&buf[..n]);
-                let first_line = banner.lines().next().unwrap_or("").to_string();
-                info.banner = Some(first_line);
-                self.parse_banner_info(info, &banner);
+        if ServiceInfo::is_probable_https_port(port) && info.banner.is_some() {
+            self.rate_limiter.acquire().await;
+            let (ip_owned, name) = (ip.to_string(), hostname.to_string());
+            let tls = tokio::task::spawn_blocking(move || {
+                TlsProbe::extract_tls_info_blocking(&ip_owned, port, Some(&name))
+            });
+            if let Ok(Ok(tls)) = timeout(self.probe_timeout, tls).await {
+                info.tls_subject = tls.0;
+                info.tls_issuer = tls.1;
+                info.tls_version = tls.2;
             }
-            _ => {}
         }
+
+        let http_status = info
+            .banner
+            .as_deref()
+            .and_then(|banner| banner.strip_prefix("HTTP "))
+            .and_then(|status| status.parse().ok());
+        if http_status.is_none() && info.tls_subject.is_none() {
+            return None;
+        }
+        Some(VirtualHostInfo {
+            ip: info.ip,
+            port,
+            hostname: hostname.to_string(),
+            http_status,
+            http_title: info.http_title,
+            http_server: info.http_server,
+            tls_subject: info.tls_subject,
+            tls_issuer: info.tls_issuer,
+            tls_version: info.tls_version,
+            detected_at: info.detected_at,
+        })
     }
 
-    fn parse_banner_info(&self, info: &mut ServiceInfo, banner: &str) {
+    fn parse_banner_info(info: &mut ServiceInfo, banner: &str) {
         match info.service_name.as_str() {
             "ssh" => {
                 if let Some(line) = banner.lines().next() {
@@ -320,12 +281,10 @@ impl ServiceProber {
                     }
                 }
             }
-            "redis" => {
-                if banner.contains("redis_version") {
-                    info.banner = Some("Redis".to_string());
-                    if let Some(version) = Self::extract_key_value_text(banner, "redis_version") {
-                        info.service_version = Some(format!("Redis {}", version));
-                    }
+            "redis" if banner.contains("redis_version") => {
+                info.banner = Some("Redis".to_string());
+                if let Some(version) = Self::extract_key_value_text(banner, "redis_version") {
+                    info.service_version = Some(format!("Redis {}", version));
                 }
             }
             "mysql" if !banner.is_empty() => {
@@ -389,7 +348,7 @@ impl ServiceProber {
         })
     }
 
-    fn extract_html_title(&self, html: &str) -> Option<String> {
+    fn extract_html_title(html: &str) -> Option<String> {
         let lower = html.to_lowercase();
         if let Some(start) = lower.find("<title>") {
             if let Some(end) = lower.find("</title>") {
@@ -403,7 +362,7 @@ impl ServiceProber {
         None
     }
 
-    fn compute_body_hash(&self, body: &str) -> String {
+    fn compute_body_hash(body: &str) -> String {
         Self::compute_bytes_hash(body.as_bytes())
     }
 
@@ -414,7 +373,7 @@ impl ServiceProber {
         format!("{:016x}", hasher.finish())
     }
 
-    fn guess_protocol(&self, info: &ServiceInfo) -> String {
+    fn guess_protocol(info: &ServiceInfo) -> String {
         if !info.protocol.is_empty() {
             return info.protocol.clone();
         }
@@ -440,6 +399,324 @@ impl ServiceProber {
     }
 }
 
+/// Fetches `/` and `/favicon.ico` on HTTP(S) ports.
+pub struct HttpProbe {
+    client: reqwest::Client,
+    timeout: Duration,
+}
+
+impl HttpProbe {
+    pub fn new(timeout_secs: u64) -> Self {
+        let timeout = Duration::from_secs(timeout_secs.max(1));
+        let client = Self::client_builder(timeout).build().unwrap_or_default();
+        Self { client, timeout }
+    }
+
+    fn client_builder(timeout: Duration) -> reqwest::ClientBuilder {
+        reqwest::Client::builder()
+            .timeout(timeout)
+            .connect_timeout(timeout)
+            .danger_accept_invalid_certs(true)
+            .no_proxy()
+    }
+
+    async fn probe_http(&self, ip: &str, port: u16, info: &mut ServiceInfo) -> Result<()> {
+        self.fetch(&self.client, ip, port, info).await
+    }
+
+    /// `probe_http` for a name-based virtual host: the connection goes to
+    /// `ip`, while SNI and the `Host` header carry `hostname`.
+    async fn probe_http_host(
+        &self,
+        ip: &str,
+        port: u16,
+        hostname: &str,
+        info: &mut ServiceInfo,
+    ) -> Result<()> {
+        let addr = SocketAddr::new(ip.parse()?, port);
+        let client = Self::client_builder(self.timeout)
+            .resolve(hostname, addr)
+            .build()?;
+        self.fetch(&client, hostname, port, info).await
+    }
+
+    async fn fetch(
+        &self,
+        client: &reqwest::Client,
+        host: &str,
+        port: u16,
+        info: &mut ServiceInfo,
+    ) -> Result<()> {
+        let scheme = if ServiceInfo::is_probable_https_port(port) {
+            "https"
+        } else {
+            "http"
+        };
+        let url = format!("{}://{}:{}/", scheme, host, port);
+
+        let start = Instant::now();
+        let resp = client.get(&url).send().await?;
+        let rtt = start.elapsed().as_secs_f64() * 1000.0;
+        info.rtt_ms = Some(rtt);
+
+        if let Some(server) = resp.headers().get("server") {
+            info.http_server = Some(server.to_str().unwrap_or("").to_string());
+        }
+
+        let status = resp.status();
+        info.banner = Some(format!("HTTP {}", status.as_u16()));
+        let expected = [
+            "content-security-policy",
+            "strict-transport-security",
+            "x-content-type-options",
+            "x-frame-options",
+            "referrer-policy",
+        ];
+        let present: Vec<&str> = expected
+            .iter()
+            .copied()
+            .filter(|name| resp.headers().contains_key(*name))
+            .collect();
+        info.http_security_headers = Some(format!(
+            "{}/{} present: {}",
+            present.len(),
+            expected.len(),
+            present.join(",")
+        ));
+
+        // Fetch the page and favicon concurrently: favicon is independent
+        // enrichment and must not add a full extra RTT to every HTTP probe.
+        let favicon_url = format!("{}://{}:{}/favicon.ico", scheme, host, port);
+        let favicon_request = client.get(&favicon_url).send();
+        let body_request = resp.text();
+        let (body_result, favicon_result) = tokio::join!(body_request, favicon_request);
+
+        if let Ok(body) = body_result {
+            if let Some(title) = ServiceProber::extract_html_title(&body) {
+                info.http_title = Some(title);
+            }
+            let preview: String = body.chars().take(HTTP_BODY_PREVIEW_BYTES).collect();
+            let cleaned = preview
+                .replace(['\n', '\r', '\t'], " ")
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            let trimmed = cleaned.chars().take(300).collect::<String>();
+            info.http_body_preview = Some(trimmed);
+            info.http_body_hash = Some(ServiceProber::compute_body_hash(&body));
+            let technologies =
+                ServiceProber::detect_web_technologies(&body, info.http_server.as_deref());
+            if !technologies.is_empty() {
+                info.service_version = Some(technologies.join(", "));
+            }
+        }
+
+        if let Ok(favicon) = favicon_result {
+            if favicon.status().is_success() {
+                if let Ok(bytes) = favicon.bytes().await {
+                    if !bytes.is_empty() && bytes.len() <= 1024 * 1024 {
+                        let hash = ServiceProber::compute_bytes_hash(&bytes);
+                        let marker = format!("favicon:{}", hash);
+                        info.service_version = Some(match info.service_version.take() {
+                            Some(existing) => format!("{}, {}", existing, marker),
+                            None => marker,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Probe for HttpProbe {
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    fn applies_to(&self, port: u16, _info: &ServiceInfo) -> bool {
+        ServiceInfo::is_probable_http_port(port) || ServiceInfo::is_probable_https_port(port)
+    }
+
+    fn probe<'a>(
+        &'a self,
+        ctx: ProbeContext<'a>,
+        info: &'a mut ServiceInfo,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.probe_http(ctx.ip, ctx.port, info))
+    }
+}
+
+/// Reads the certificate subject, issuer and protocol version once HTTPS answered.
+pub struct TlsProbe;
+
+impl TlsProbe {
+    /// Handshake with `ip:port`, sending `server_name` as SNI when given.
+    fn extract_tls_info_blocking(
+        ip: &str,
+        port: u16,
+        server_name: Option<&str>,
+    ) -> (
+        Option<String>,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+    ) {
+        let mut info = ServiceInfo::new(ip.to_string(), port);
+        let connector = match native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+        {
+            Ok(c) => c,
+            Err(_) => return (None, None, None, None),
+        };
+
+        let addr = format!("{}:{}", ip, port);
+        let sock_addr: std::net::SocketAddr = match addr.parse() {
+            Ok(a) => a,
+            Err(_) => return (None, None, None, None),
+        };
+
+        let tcp_stream = match std::net::TcpStream::connect_timeout(
+            &sock_addr,
+            Duration::from_secs(PROBE_TIMEOUT_SECS),
+        ) {
+            Ok(s) => s,
+            Err(_) => return (None, None, None, None),
+        };
+
+        Self::read_ttl_from_stream(&tcp_stream, &mut info);
+
+        if let Ok(tls_stream) = connector.connect(server_name.unwrap_or(ip), tcp_stream) {
+            if let Ok(Some(cert)) = tls_stream.peer_certificate() {
+                if let Ok(der_bytes) = cert.to_der() {
+                    let cn = extract_cn_from_der(&der_bytes);
+                    info.tls_subject =
+                        Some(cn.unwrap_or_else(|| "(certificate present)".to_string()));
+                    info.tls_issuer = Some("present".to_string());
+                }
+            }
+            info.tls_version = Some("TLS".to_string());
+        }
+
+        (
+            info.tls_subject,
+            info.tls_issuer,
+            info.tls_version,
+            info.os_guess,
+        )
+    }
+
+    #[cfg(unix)]
+    fn read_ttl_from_stream(stream: &std::net::TcpStream, info: &mut ServiceInfo) {
+        use std::os::unix::io::AsRawFd;
+        let fd = stream.as_raw_fd();
+        let mut ttl: u32 = 0;
+        let mut ttl_len = std::mem::size_of::<u32>() as libc::socklen_t;
+        unsafe {
+            let ret = libc::getsockopt(
+                fd,
+                libc::IPPROTO_IP,
+                libc::IP_TTL,
+                &mut ttl as *mut u32 as *mut _,
+                &mut ttl_len,
+            );
+            if ret == 0 {
+                info.os_guess = ServiceInfo::guess_os_from_ttl(ttl).map(|s| s.to_string());
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn read_ttl_from_stream(_stream: &std::net::TcpStream, _info: &mut ServiceInfo) {}
+}
+
+impl Probe for TlsProbe {
+    fn name(&self) -> &str {
+        "tls"
+    }
+
+    fn applies_to(&self, port: u16, info: &ServiceInfo) -> bool {
+        ServiceInfo::is_probable_https_port(port) && info.banner.is_some()
+    }
+
+    fn probe<'a>(
+        &'a self,
+        ctx: ProbeContext<'a>,
+        info: &'a mut ServiceInfo,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let ip = ctx.ip.to_string();
+            let port = ctx.port;
+            let tls = tokio::task::spawn_blocking(move || {
+                Self::extract_tls_info_blocking(&ip, port, None)
+            })
+            .await?;
+            info.tls_subject = tls.0;
+            info.tls_issuer = tls.1;
+            info.tls_version = tls.2;
+            if tls.3.is_some() {
+                info.os_guess = tls.3;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Sends a protocol-appropriate greeting and keeps the first line of the
+/// reply. Runs on every port no earlier probe produced a banner for.
+pub struct BannerProbe {
+    read_timeout: Duration,
+}
+
+impl Probe for BannerProbe {
+    fn name(&self) -> &str {
+        "banner"
+    }
+
+    fn applies_to(&self, _port: u16, info: &ServiceInfo) -> bool {
+        info.banner.is_none()
+    }
+
+    fn needs_socket(&self) -> bool {
+        true
+    }
+
+    fn probe<'a>(
+        &'a self,
+        ctx: ProbeContext<'a>,
+        info: &'a mut ServiceInfo,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut stream = ctx
+                .stream
+                .ok_or_else(|| anyhow!("banner probe needs a connection"))?;
+            let probe_data: &[u8] = match info.service_name.as_str() {
+                "ftp" => b"\r\n",
+                "smtp" => b"EHLO probe\r\n",
+                "pop3" => b"\r\n",
+                "imap" => b"a001 CAPABILITY\r\n",
+                "redis" => b"INFO\r\n",
+                _ => b"",
+            };
+            if !probe_data.is_empty() {
+                let _ = stream.write_all(probe_data).await;
+            }
+
+            let mut buf = vec![0u8; BANNER_MAX_BYTES];
+            if let Ok(Ok(n)) = timeout(self.read_timeout, stream.read(&mut buf)).await {
+                if n > 0 {
+                    let banner = String::from_utf8_lossy(&buf[..n]);
+                    let first_line = banner.lines().next().unwrap_or("").to_string();
+                    info.banner = Some(first_line);
+                    ServiceProber::parse_banner_info(info, &banner);
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
 pub async fn reverse_dns_lookup(ip: &str) -> Option<String> {
     let ip_owned = ip.to_string();
     tokio::task::spawn_blocking(move || reverse_lookup_impl(&ip_owned))
@@ -654,9 +931,14 @@ fn find_byte_sequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
 
 #[cfg(test)]
 mod tests {
-    use super::ServiceProber;
+    use super::{HttpProbe, ServiceProber};
     use crate::model::ServiceInfo;
-    use tokio::io::AsyncWriteExt;
+    use crate::service::{Probe, ProbeContext};
+    use anyhow::Result;
+    use futures::future::BoxFuture;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio::net::TcpListener;
 
     #[tokio::test]
@@ -681,13 +963,141 @@ mod tests {
             }
         });
 
-        let prober = ServiceProber::new(2, 1);
+        let probe = HttpProbe::new(2);
         let mut info = ServiceInfo::new("127.0.0.1".to_string(), port);
-        prober.probe_http("127.0.0.1", port, &mut info).await;
+        probe
+            .probe_http("127.0.0.1", port, &mut info)
+            .await
+            .unwrap();
 
         let preview = info.http_body_preview.expect("HTTP preview should exist");
         assert!(preview.chars().count() <= 300);
         assert!(info.http_body_hash.is_some());
         server.await.unwrap();
     }
+
+    /// Reads the greeting itself and tags the service, like a user probe would.
+    struct GreetingProbe;
+
+    impl Probe for GreetingProbe {
+        fn name(&self) -> &str {
+            "greeting"
+        }
+
+        fn applies_to(&self, _port: u16, info: &ServiceInfo) -> bool {
+            info.service_version.is_none()
+        }
+
+        fn needs_socket(&self) -> bool {
+            true
+        }
+
+        fn probe<'a>(
+            &'a self,
+            ctx: ProbeContext<'a>,
+            info: &'a mut ServiceInfo,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move {
+                let mut stream = ctx.stream.expect("pipeline opens the socket");
+                let mut buf = [0u8; 64];
+                let n = stream.read(&mut buf).await?;
+                if buf[..n].starts_with(b"HELLO") {
+                    info.service_version = Some("custom-greeter".to_string());
+                }
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn registered_probe_runs_after_built_in_probes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            // One connection for the banner probe, one for the custom probe.
+            for _ in 0..2 {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let _ = stream.write_all(b"HELLO v1\r\n").await;
+            }
+        });
+
+        let mut prober = ServiceProber::new(2, 4).with_rate_limit(10);
+        prober.register_probe(Arc::new(GreetingProbe));
+        let info = prober.probe_port("127.0.0.1", port).await.unwrap();
+
+        assert_eq!(info.banner.as_deref(), Some("HELLO v1"));
+        assert_eq!(info.service_version.as_deref(), Some("custom-greeter"));
+        assert!(info.rtt_ms.is_some());
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn grabbed_banner_skips_the_banner_probe_connection() {
+        // Nothing listens here, so only the seeded banner can fill it in.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let banners = HashMap::from([(port, "SSH-2.0-test\r\nsecond line".to_string())]);
+
+        let prober = ServiceProber::new(1, 4);
+        let infos = prober
+            .probe_ip_with_banners("127.0.0.1", &[port], &banners)
+            .await;
+
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].banner.as_deref(), Some("SSH-2.0-test"));
+        assert!(infos[0].rtt_ms.is_none());
+    }
+
+    #[tokio::test]
+    async fn hostname_probe_sends_the_name_as_host() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let mut hosts = Vec::new();
+            // Page and favicon.
+            for _ in 0..2 {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    break;
+                };
+                let mut buf = [0u8; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+                hosts.extend(
+                    request
+                        .lines()
+                        .filter_map(|line| line.strip_prefix("host: "))
+                        .map(str::to_string),
+                );
+                let body = "<title>App</title>";
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+            hosts
+        });
+
+        let prober = ServiceProber::new(2, 4);
+        let vhost = prober
+            .probe_hostname("127.0.0.1", port, "app.example.test")
+            .await
+            .unwrap();
+
+        assert_eq!(vhost.hostname, "app.example.test");
+        assert_eq!(vhost.http_status, Some(200));
+        assert_eq!(vhost.http_title.as_deref(), Some("App"));
+        let hosts = server.await.unwrap();
+        assert!(
+            hosts.contains(&format!("app.example.test:{}", port)),
+            "{:?}",
+            hosts
+        );
+    }
 }
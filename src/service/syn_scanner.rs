@@ -1,17 +1,18 @@
 use anyhow::{anyhow, Result};
 use pnet_packet::ip::IpNextHeaderProtocols;
-use pnet_packet::tcp::{ipv4_checksum, MutableTcpPacket, TcpFlags, TcpPacket};
+use pnet_packet::tcp::{MutableTcpPacket, TcpFlags, TcpPacket};
 use pnet_packet::Packet;
 #[cfg(target_os = "windows")]
 use pnet_transport as transport;
 #[cfg(not(target_os = "windows"))]
 use pnet_transport::{self as transport, TransportChannelType, TransportProtocol};
-use rand::Rng;
 use std::net::{IpAddr, Ipv4Addr};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::thread;
 use std::time::{Duration, Instant};
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error};
 
 #[cfg(target_os = "windows")]
@@ -21,7 +22,7 @@ use pnet_datalink::{self as datalink, Channel, MacAddr};
 use pnet_packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
 
 #[cfg(target_os = "windows")]
-use pnet_packet::ipv4::{self, Ipv4Packet, MutableIpv4Packet};
+use pnet_packet::ipv4::{Ipv4Packet, MutableIpv4Packet};
 
 #[cfg(target_os = "windows")]
 use pnet_packet::MutablePacket;
@@ -32,98 +33,396 @@ use regex::Regex;
 #[cfg(target_os = "windows")]
 use std::process::Command;
 
-use super::RateLimiter;
+use super::batch_tuner::BatchTuner;
+use super::con_scanner::{admit_result, EVENT_BUFFER};
+use super::script_hooks::Finding;
+use super::{RateLimiter, ScriptHooks};
 use crate::dao::SqliteDB;
-use crate::model::ScanMetrics;
-
-#[cfg(not(target_os = "windows"))]
-pub enum ScannerTx {
-    L4(transport::TransportSender),
+use crate::model::{OpenPort, ScanMetrics, SourcePorts};
+
+/// Default for how long `finish` keeps listening for SYN-ACKs to packets
+/// sent at the very end of the pipeline; see [`SynScanner::with_linger`].
+pub const DEFAULT_SYN_LINGER: Duration = Duration::from_secs(1);
+
+/// How often the packet threads wake from a blocking read or an empty queue
+/// to check for shutdown.
+const THREAD_POLL: Duration = Duration::from_millis(100);
+
+/// The send, receive and bridge threads of one scanner. Dropping it (or
+/// calling `stop`) signals shutdown and joins them, which releases the raw
+/// socket or pcap handle they own.
+struct ScannerThreads {
+    shutdown: Arc<AtomicBool>,
+    handles: Vec<thread::JoinHandle<()>>,
 }
 
-#[cfg(target_os = "windows")]
-#[allow(dead_code)]
-pub enum ScannerTx {
-    L4(transport::TransportSender),
-    L2 {
-        sender: Box<dyn datalink::DataLinkSender>,
-        src_mac: MacAddr,
-        dst_mac: MacAddr,
-        src_ip: Ipv4Addr,
-    },
+impl ScannerThreads {
+    fn new() -> Self {
+        ScannerThreads {
+            shutdown: Arc::new(AtomicBool::new(false)),
+            handles: Vec::new(),
+        }
+    }
+
+    fn spawn(&mut self, name: &str, f: impl FnOnce(Arc<AtomicBool>) + Send + 'static) {
+        let shutdown = self.shutdown.clone();
+        match thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || f(shutdown))
+        {
+            Ok(handle) => self.handles.push(handle),
+            Err(e) => error!("Failed to spawn {} thread: {}", name, e),
+        }
+    }
+
+    /// Blocks for up to one `THREAD_POLL` per thread.
+    fn stop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        for handle in self.handles.drain(..) {
+            if handle.join().is_err() {
+                error!("SYN scanner thread panicked");
+            }
+        }
+    }
 }
 
-unsafe impl Send for ScannerTx {}
+impl Drop for ScannerThreads {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
 
 #[derive(Clone, Copy)]
 struct SynPacket {
+    src_port: u16,
     dst_ip: Ipv4Addr,
     dst_port: u16,
 }
 
+const TCP_HEADER_LEN: usize = 20;
+
+/// Ones' complement sum of big-endian 16-bit words, unfolded.
+fn sum_words(bytes: &[u8]) -> u32 {
+    bytes
+        .chunks(2)
+        .map(|w| u32::from(w[0]) << 8 | u32::from(*w.get(1).unwrap_or(&0)))
+        .sum()
+}
+
+fn fold_checksum(mut sum: u32) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// A SYN header built once per send thread. Only the ports, the sequence
+/// number and the checksum change between probes; the checksum starts from
+/// the precomputed sum of every fixed word (including the pseudo-header
+/// protocol and length) so each probe adds just the words it patched.
+struct SynTemplate {
+    header: [u8; TCP_HEADER_LEN],
+    fixed_sum: u32,
+}
+
+impl SynTemplate {
+    fn new() -> Self {
+        let mut header = [0u8; TCP_HEADER_LEN];
+        let mut tcp = MutableTcpPacket::new(&mut header).expect("buffer fits a TCP header");
+        tcp.set_flags(TcpFlags::SYN);
+        tcp.set_window(64240);
+        tcp.set_data_offset(5);
+        let fixed_sum =
+            sum_words(&header) + u32::from(IpNextHeaderProtocols::Tcp.0) + TCP_HEADER_LEN as u32;
+        SynTemplate { header, fixed_sum }
+    }
+
+    /// Patch in one probe and return the finished header.
+    fn build(&mut self, src_ip: Ipv4Addr, pkt: &SynPacket) -> &[u8] {
+        let seq = syn_timestamp();
+        self.header[0..2].copy_from_slice(&pkt.src_port.to_be_bytes());
+        self.header[2..4].copy_from_slice(&pkt.dst_port.to_be_bytes());
+        self.header[4..8].copy_from_slice(&seq.to_be_bytes());
+        let sum = self.fixed_sum
+            + sum_words(&src_ip.octets())
+            + sum_words(&pkt.dst_ip.octets())
+            + u32::from(pkt.src_port)
+            + u32::from(pkt.dst_port)
+            + (seq >> 16)
+            + (seq & 0xffff);
+        self.header[16..18].copy_from_slice(&fold_checksum(sum).to_be_bytes());
+        &self.header
+    }
+}
+
+/// An Ethernet + IPv4 + SYN frame for the layer 2 path. The IP header only
+/// varies in its destination and checksum.
+#[cfg(target_os = "windows")]
+struct FrameTemplate {
+    frame: [u8; FRAME_LEN],
+    src_ip: Ipv4Addr,
+    ip_fixed_sum: u32,
+    tcp: SynTemplate,
+}
+
+#[cfg(target_os = "windows")]
+const FRAME_LEN: usize = 14 + 20 + TCP_HEADER_LEN;
+
+#[cfg(target_os = "windows")]
+impl FrameTemplate {
+    fn new(src_mac: MacAddr, dst_mac: MacAddr, src_ip: Ipv4Addr) -> Self {
+        let mut frame = [0u8; FRAME_LEN];
+        let mut eth = MutableEthernetPacket::new(&mut frame).expect("buffer fits a frame");
+        eth.set_destination(dst_mac);
+        eth.set_source(src_mac);
+        eth.set_ethertype(EtherTypes::Ipv4);
+        let mut ip = MutableIpv4Packet::new(eth.payload_mut()).expect("buffer fits an IP header");
+        ip.set_version(4);
+        ip.set_header_length(5);
+        ip.set_total_length((20 + TCP_HEADER_LEN) as u16);
+        ip.set_ttl(64);
+        ip.set_next_level_protocol(IpNextHeaderProtocols::Tcp);
+        ip.set_source(src_ip);
+        let ip_fixed_sum = sum_words(&frame[14..34]);
+        FrameTemplate {
+            frame,
+            src_ip,
+            ip_fixed_sum,
+            tcp: SynTemplate::new(),
+        }
+    }
+
+    fn build(&mut self, pkt: &SynPacket) -> &[u8] {
+        let dst = pkt.dst_ip.octets();
+        self.frame[30..34].copy_from_slice(&dst);
+        let ip_checksum = fold_checksum(self.ip_fixed_sum + sum_words(&dst));
+        self.frame[24..26].copy_from_slice(&ip_checksum.to_be_bytes());
+        let tcp = self.tcp.build(self.src_ip, pkt);
+        self.frame[34..].copy_from_slice(tcp);
+        &self.frame
+    }
+}
+
+/// Local IPv4 networks, read once per scanner. The raw L4 socket leaves the
+/// source address to the kernel, so the checksum has to be computed with
+/// the address it will pick for each destination.
+#[cfg(not(target_os = "windows"))]
+struct SourceRoutes {
+    /// (network, mask, interface address)
+    networks: Vec<(u32, u32, Ipv4Addr)>,
+    fallback: Option<Ipv4Addr>,
+}
+
+#[cfg(not(target_os = "windows"))]
+impl SourceRoutes {
+    fn discover() -> Self {
+        let mut routes = SourceRoutes {
+            networks: Vec::new(),
+            fallback: None,
+        };
+        for iface in pnet_datalink::interfaces() {
+            for ip_net in iface.ips {
+                if let IpAddr::V4(addr) = ip_net.ip() {
+                    let mask = u32::MAX
+                        .checked_shl(32 - u32::from(ip_net.prefix()))
+                        .unwrap_or(0);
+                    routes.networks.push((u32::from(addr) & mask, mask, addr));
+                    if !addr.is_loopback() && routes.fallback.is_none() {
+                        routes.fallback = Some(addr);
+                    }
+                }
+            }
+        }
+        routes
+    }
+
+    /// The interface address on the destination's network, else the first
+    /// non-loopback address.
+    fn source_for(&self, dst_ip: Ipv4Addr) -> Option<Ipv4Addr> {
+        let dst = u32::from(dst_ip);
+        self.networks
+            .iter()
+            .find(|(net, mask, _)| dst & mask == *net)
+            .map(|(_, _, addr)| *addr)
+            .or(self.fallback)
+    }
+}
+
+/// Number of raw sockets opened for sending. Each send thread owns one, so
+/// sends never contend on a lock; only the first socket is read from.
+#[cfg(not(target_os = "windows"))]
+fn send_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get().min(4))
+}
+
+/// Send everything queued for one send thread. Exits when the bridge hangs
+/// up, or on shutdown once the queue has gone idle.
+fn run_send_queue(
+    rx: std::sync::mpsc::Receiver<SynPacket>,
+    shutdown: &AtomicBool,
+    mut send: impl FnMut(&SynPacket),
+) {
+    loop {
+        match rx.recv_timeout(THREAD_POLL) {
+            Ok(pkt) => send(&pkt),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+/// Reference point for SYN send timestamps. The send time, in microseconds
+/// since this instant truncated to 32 bits, is used as the SYN's sequence
+/// number; the target echoes it back as `ack - 1` in its SYN-ACK, so RTTs
+/// are measured without keeping per-probe state.
+static SYN_EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// Replies slower than this are stray or unrelated SYN-ACKs.
+const MAX_SYN_RTT: Duration = Duration::from_secs(30);
+
+fn syn_timestamp() -> u32 {
+    SYN_EPOCH.get_or_init(Instant::now).elapsed().as_micros() as u32
+}
+
+fn syn_rtt(ack: u32) -> Option<Duration> {
+    let elapsed = syn_timestamp().wrapping_sub(ack.wrapping_sub(1));
+    let rtt = Duration::from_micros(elapsed as u64);
+    (rtt <= MAX_SYN_RTT).then_some(rtt)
+}
+
+/// A RST answering one of our SYNs (closed port): it acknowledges the
+/// timestamp carried in the probe's sequence number.
+fn is_probe_rst(tcp: &TcpPacket) -> bool {
+    tcp.get_flags() & TcpFlags::RST != 0 && syn_rtt(tcp.get_acknowledgement()).is_some()
+}
+
+fn save_findings(db: &SqliteDB, findings: &mut Vec<Finding>, scan_round: i64) {
+    if !findings.is_empty() {
+        if let Err(e) = db.save_script_findings(&std::mem::take(findings), scan_round) {
+            error!("Failed to save script findings: {}", e);
+        }
+    }
+}
+
 pub struct SynScanner {
-    #[allow(dead_code)]
-    tx: Arc<Mutex<ScannerTx>>,
     rate_limiter: RateLimiter,
     metrics: ScanMetrics,
+    // Declared before `threads`: dropping it first lets the bridge and send
+    // threads see a closed queue before they are joined.
     packet_tx: mpsc::Sender<SynPacket>,
+    threads: ScannerThreads,
+    linger: Duration,
+    source_ports: SourcePorts,
+    cancel: CancellationToken,
+    db: SqliteDB,
+    scan_round: i64,
+    writer: tokio::task::JoinHandle<()>,
+    writer_shutdown: oneshot::Sender<()>,
+    events: broadcast::Sender<OpenPort>,
 }
 
 impl SynScanner {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         db: SqliteDB,
         scan_round: i64,
         result_buffer: usize,
         db_batch_size: usize,
         flush_interval_ms: u64,
+        adaptive_batching: bool,
         max_rate: u64,
         rate_window_secs: u64,
+        rate_burst: usize,
+        hooks: Option<Arc<ScriptHooks>>,
+        cancel: CancellationToken,
     ) -> Result<Self> {
         let metrics = ScanMetrics::new();
-        let rate_limiter =
-            RateLimiter::new(max_rate as usize, Duration::from_secs(rate_window_secs));
-        let (result_tx, mut result_rx) = mpsc::channel(result_buffer);
+        let events = broadcast::channel(EVENT_BUFFER).0;
+        let rate_limiter = RateLimiter::with_burst(
+            max_rate as usize,
+            Duration::from_secs(rate_window_secs),
+            rate_burst,
+        );
+        let (result_tx, mut result_rx) = mpsc::channel::<(IpAddr, u16, bool)>(result_buffer);
+        let (writer_shutdown, mut shutdown_rx) = oneshot::channel::<()>();
         let db_clone = db.clone();
-
-        tokio::spawn(async move {
-            let mut buffer = Vec::with_capacity(db_batch_size);
+        let writer_cancel = cancel.clone();
+        let writer_metrics = metrics.clone();
+        let writer_events = events.clone();
+        let mut tuner = BatchTuner::new(
+            db_batch_size,
+            Duration::from_millis(flush_interval_ms),
+            adaptive_batching,
+        );
+
+        // The receiver thread holds `result_tx` until it is joined; `finish`
+        // also stops the writer explicitly through `writer_shutdown`.
+        let writer = tokio::spawn(async move {
+            let mut buffer = Vec::with_capacity(tuner.batch_size());
+            let mut findings = Vec::new();
             let mut last_flush = Instant::now();
-            let flush_interval = Duration::from_millis(flush_interval_ms);
 
             loop {
+                writer_metrics.set_result_queue(result_rx.len(), result_rx.max_capacity());
                 tokio::select! {
                     result = result_rx.recv() => {
                         match result {
                             Some(item) => {
-                                buffer.push(item);
-                                if buffer.len() >= db_batch_size {
+                                if admit_result(hooks.as_deref(), &writer_events, item, scan_round, &mut findings) {
+                                    buffer.push(item);
+                                }
+                                if buffer.len() >= tuner.batch_size() {
+                                    let started = Instant::now();
                                     if let Err(e) = db_clone
                                         .bulk_update_port_status(std::mem::take(&mut buffer), scan_round)
                                     {
                                         error!("Failed to bulk update port status: {}", e);
                                     }
+                                    save_findings(&db_clone, &mut findings, scan_round);
+                                    tuner.flushed(started, &result_rx, &writer_metrics);
                                     last_flush = Instant::now();
                                 }
                             }
                             None => break,
                         }
                     }
+                    _ = &mut shutdown_rx => {
+                        while let Ok(item) = result_rx.try_recv() {
+                            if admit_result(hooks.as_deref(), &writer_events, item, scan_round, &mut findings) {
+                                buffer.push(item);
+                            }
+                        }
+                        break;
+                    }
                     _ = tokio::time::sleep(Duration::from_millis(100)) => {}
                 }
 
-                if !buffer.is_empty() && last_flush.elapsed() >= flush_interval {
+                // Once the scan is cancelled nothing is held back for batching.
+                let due =
+                    last_flush.elapsed() >= tuner.flush_interval() || writer_cancel.is_cancelled();
+                if !buffer.is_empty() && due {
+                    let started = Instant::now();
                     if let Err(e) =
                         db_clone.bulk_update_port_status(std::mem::take(&mut buffer), scan_round)
                     {
                         error!("Failed to bulk update port status (timer): {}", e);
                     }
+                    save_findings(&db_clone, &mut findings, scan_round);
+                    tuner.flushed(started, &result_rx, &writer_metrics);
                     last_flush = Instant::now();
                 }
             }
 
             if !buffer.is_empty() {
-                let _ = db_clone.bulk_update_port_status(buffer, scan_round);
+                if let Err(e) = db_clone.bulk_update_port_status(buffer, scan_round) {
+                    error!("Failed to bulk update port status (final): {}", e);
+                }
             }
+            save_findings(&db_clone, &mut findings, scan_round);
         });
 
         #[cfg(target_os = "windows")]
@@ -163,89 +462,74 @@ impl SynScanner {
                 .ok_or(anyhow!("Interface has no MAC address"))?;
             tracing::info!("Using Interface: {} ({})", interface.name, src_mac);
 
-            let (tx, mut rx) = match datalink::channel(&interface, Default::default()) {
+            let config = datalink::Config {
+                read_timeout: Some(THREAD_POLL),
+                ..Default::default()
+            };
+            let (tx, mut rx) = match datalink::channel(&interface, config) {
                 Ok(Channel::Ethernet(tx, rx)) => (tx, rx),
                 Ok(_) => return Err(anyhow!("Unhandled channel type")),
                 Err(e) => return Err(anyhow!("Failed to create datalink channel: {}", e)),
             };
 
+            let mut threads = ScannerThreads::new();
             let (pkt_tx, pkt_rx) = std::sync::mpsc::channel::<SynPacket>();
-
-            let tx_arc = Arc::new(Mutex::new(ScannerTx::L2 {
-                sender: tx,
-                src_mac,
-                dst_mac: gateway_mac,
-                src_ip: interface_ip,
-            }));
-            let tx_for_sender = tx_arc.clone();
-
-            thread::spawn(move || {
-                let mut tx_lock = tx_for_sender.lock().unwrap();
-                if let ScannerTx::L2 {
-                    ref mut sender,
-                    src_mac,
-                    dst_mac,
-                    src_ip,
-                } = *tx_lock
-                {
-                    let mut pkt_buffer = Vec::with_capacity(64);
-                    loop {
-                        pkt_buffer.clear();
-                        match pkt_rx.recv_timeout(Duration::from_millis(100)) {
-                            Ok(pkt) => {
-                                pkt_buffer.push(pkt);
-                                while pkt_buffer.len() < 64 {
-                                    match pkt_rx.try_recv() {
-                                        Ok(p) => pkt_buffer.push(p),
-                                        Err(_) => break,
-                                    }
-                                }
-                                for pkt in &pkt_buffer {
-                                    Self::send_syn_l2_internal(
-                                        sender,
-                                        src_mac,
-                                        dst_mac,
-                                        src_ip,
-                                        pkt.dst_ip,
-                                        pkt.dst_port,
-                                    );
-                                }
-                            }
-                            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
-                            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
-                        }
+            // A single pcap handle both sends and captures, so Windows keeps
+            // one send thread; it owns the handle and the frame template.
+            let mut sender = tx;
+            threads.spawn("syn-send", move |shutdown| {
+                let mut template = FrameTemplate::new(src_mac, gateway_mac, interface_ip);
+                run_send_queue(pkt_rx, &shutdown, |pkt| {
+                    if let Some(Err(e)) = sender.send_to(template.build(pkt), None) {
+                        error!("Failed to send SYN frame: {}", e);
                     }
-                }
+                });
             });
+            let queues = vec![pkt_tx];
 
             let metrics_rx_clone = metrics.clone();
-            thread::spawn(move || loop {
-                match rx.next() {
-                    Ok(packet) => {
-                        if let Some(frame) = EthernetPacket::new(packet) {
-                            if frame.get_ethertype() == EtherTypes::Ipv4 {
-                                if let Some(ip_header) = Ipv4Packet::new(frame.payload()) {
-                                    if ip_header.get_next_level_protocol()
-                                        == IpNextHeaderProtocols::Tcp
-                                    {
-                                        if let Some(tcp) = TcpPacket::new(ip_header.payload()) {
-                                            if tcp.get_flags() & (TcpFlags::SYN | TcpFlags::ACK)
-                                                == (TcpFlags::SYN | TcpFlags::ACK)
-                                            {
-                                                let src_ip = ip_header.get_source();
-                                                let src_port = tcp.get_source();
-
-                                                if ip_header.get_destination() == interface_ip {
-                                                    metrics_rx_clone.increment_open();
-                                                    debug!(
-                                                        "Found open port: {}:{}",
-                                                        src_ip, src_port
-                                                    );
-                                                    let _ = result_tx.blocking_send((
-                                                        src_ip.to_string(),
-                                                        src_port,
-                                                        true,
-                                                    ));
+            threads.spawn("syn-recv", move |shutdown| {
+                while !shutdown.load(Ordering::SeqCst) {
+                    match rx.next() {
+                        Ok(packet) => {
+                            if let Some(frame) = EthernetPacket::new(packet) {
+                                if frame.get_ethertype() == EtherTypes::Ipv4 {
+                                    if let Some(ip_header) = Ipv4Packet::new(frame.payload()) {
+                                        if ip_header.get_next_level_protocol()
+                                            == IpNextHeaderProtocols::Tcp
+                                        {
+                                            if let Some(tcp) = TcpPacket::new(ip_header.payload()) {
+                                                if tcp.get_flags() & (TcpFlags::SYN | TcpFlags::ACK)
+                                                    == (TcpFlags::SYN | TcpFlags::ACK)
+                                                {
+                                                    let src_ip = ip_header.get_source();
+                                                    let src_port = tcp.get_source();
+
+                                                    if ip_header.get_destination() == interface_ip {
+                                                        metrics_rx_clone.record_open(
+                                                            IpAddr::V4(src_ip),
+                                                            src_port,
+                                                        );
+                                                        if let Some(rtt) =
+                                                            syn_rtt(tcp.get_acknowledgement())
+                                                        {
+                                                            metrics_rx_clone.record_syn_rtt(rtt);
+                                                            metrics_rx_clone.record_reply(false);
+                                                        }
+                                                        debug!(
+                                                            "Found open port: {}:{}",
+                                                            src_ip, src_port
+                                                        );
+                                                        let _ = result_tx.blocking_send((
+                                                            IpAddr::V4(src_ip),
+                                                            src_port,
+                                                            true,
+                                                        ));
+                                                    }
+                                                } else if is_probe_rst(&tcp)
+                                                    && ip_header.get_destination() == interface_ip
+                                                {
+                                                    metrics_rx_clone.record_reply(true);
                                                 }
                                             }
                                         }
@@ -253,18 +537,27 @@ impl SynScanner {
                                 }
                             }
                         }
-                    }
-                    Err(e) => {
-                        debug!("Datalink read error: {}", e);
+                        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+                        Err(e) => {
+                            debug!("Datalink read error: {}", e);
+                        }
                     }
                 }
             });
 
             return Ok(SynScanner {
-                tx: tx_arc,
                 rate_limiter,
                 metrics,
-                packet_tx: Self::tokio_to_std_sender(pkt_tx),
+                packet_tx: Self::tokio_to_std_sender(queues, &mut threads),
+                threads,
+                linger: DEFAULT_SYN_LINGER,
+                source_ports: SourcePorts::default(),
+                cancel,
+                db,
+                scan_round,
+                writer,
+                writer_shutdown,
+                events,
             });
         }
 
@@ -272,69 +565,63 @@ impl SynScanner {
         {
             let protocol =
                 TransportChannelType::Layer4(TransportProtocol::Ipv4(IpNextHeaderProtocols::Tcp));
-            let (tx, mut rx) = match transport::transport_channel(4096, protocol) {
-                Ok((tx, rx)) => (tx, rx),
-                Err(e) => {
-                    return Err(anyhow!(
-                        "Failed to create raw socket (Root/Admin required?): {}",
-                        e
-                    ))
-                }
+            let open = || {
+                transport::transport_channel(4096, protocol).map_err(|e| {
+                    anyhow!("Failed to create raw socket (Root/Admin required?): {}", e)
+                })
             };
+            let (tx, mut rx) = open()?;
+            let mut senders = vec![tx];
+            for _ in 1..send_threads() {
+                senders.push(open()?.0);
+            }
 
-            let (pkt_tx, pkt_rx) = std::sync::mpsc::channel::<SynPacket>();
-
-            let tx_arc = Arc::new(Mutex::new(ScannerTx::L4(tx)));
-            let tx_for_sender = tx_arc.clone();
-
-            thread::spawn(move || {
-                let mut tx_lock = tx_for_sender.lock().unwrap();
-                let ScannerTx::L4(ref mut tx) = *tx_lock;
-                let mut pkt_buffer = Vec::with_capacity(64);
-                loop {
-                    pkt_buffer.clear();
-                    match pkt_rx.recv_timeout(Duration::from_millis(100)) {
-                        Ok(pkt) => {
-                            pkt_buffer.push(pkt);
-                            while pkt_buffer.len() < 64 {
-                                match pkt_rx.try_recv() {
-                                    Ok(p) => pkt_buffer.push(p),
-                                    Err(_) => break,
-                                }
-                            }
-                            for pkt in &pkt_buffer {
-                                if let Err(e) =
-                                    Self::send_syn_l4_internal(tx, pkt.dst_ip, pkt.dst_port)
-                                {
-                                    error!("Failed to send SYN packet: {}", e);
-                                }
-                            }
+            let routes = Arc::new(SourceRoutes::discover());
+            let mut threads = ScannerThreads::new();
+            let mut queues = Vec::with_capacity(senders.len());
+
+            for (i, mut tx) in senders.into_iter().enumerate() {
+                let (pkt_tx, pkt_rx) = std::sync::mpsc::channel::<SynPacket>();
+                queues.push(pkt_tx);
+                let routes = routes.clone();
+                threads.spawn(&format!("syn-send-{}", i), move |shutdown| {
+                    let mut template = SynTemplate::new();
+                    run_send_queue(pkt_rx, &shutdown, |pkt| {
+                        if let Err(e) =
+                            Self::send_syn_l4_internal(&mut tx, &mut template, &routes, pkt)
+                        {
+                            error!("Failed to send SYN packet: {}", e);
                         }
-                        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
-                        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
-                    }
-                }
-            });
+                    });
+                });
+            }
 
             let metrics_rx_clone = metrics.clone();
-            thread::spawn(move || {
+            threads.spawn("syn-recv", move |shutdown| {
                 let mut iter = transport::ipv4_packet_iter(&mut rx);
-                loop {
-                    match iter.next() {
-                        Ok((packet, _addr)) => {
+                while !shutdown.load(Ordering::SeqCst) {
+                    match iter.next_with_timeout(THREAD_POLL) {
+                        Ok(None) => {}
+                        Ok(Some((packet, _addr))) => {
                             if let Some(tcp) = TcpPacket::new(packet.payload()) {
                                 if tcp.get_flags() & (TcpFlags::SYN | TcpFlags::ACK)
                                     == (TcpFlags::SYN | TcpFlags::ACK)
                                 {
                                     let src_ip = packet.get_source();
                                     let src_port = tcp.get_source();
-                                    metrics_rx_clone.increment_open();
+                                    metrics_rx_clone.record_open(IpAddr::V4(src_ip), src_port);
+                                    if let Some(rtt) = syn_rtt(tcp.get_acknowledgement()) {
+                                        metrics_rx_clone.record_syn_rtt(rtt);
+                                        metrics_rx_clone.record_reply(false);
+                                    }
                                     debug!("Found open port: {}:{}", src_ip, src_port);
                                     let _ = result_tx.blocking_send((
-                                        src_ip.to_string(),
+                                        IpAddr::V4(src_ip),
                                         src_port,
                                         true,
                                     ));
+                                } else if is_probe_rst(&tcp) {
+                                    metrics_rx_clone.record_reply(true);
                                 }
                             }
                         }
@@ -344,19 +631,35 @@ impl SynScanner {
             });
 
             Ok(SynScanner {
-                tx: tx_arc,
                 rate_limiter,
                 metrics,
-                packet_tx: Self::tokio_to_std_sender(pkt_tx),
+                packet_tx: Self::tokio_to_std_sender(queues, &mut threads),
+                threads,
+                linger: DEFAULT_SYN_LINGER,
+                source_ports: SourcePorts::default(),
+                cancel,
+                db,
+                scan_round,
+                writer,
+                writer_shutdown,
+                events,
             })
         }
     }
 
-    fn tokio_to_std_sender(std_tx: std::sync::mpsc::Sender<SynPacket>) -> mpsc::Sender<SynPacket> {
+    /// Bridge the async pipeline to the send threads, dealing packets out
+    /// round-robin. The bridge ends once the returned sender is dropped.
+    fn tokio_to_std_sender(
+        queues: Vec<std::sync::mpsc::Sender<SynPacket>>,
+        threads: &mut ScannerThreads,
+    ) -> mpsc::Sender<SynPacket> {
         let (tokio_tx, mut tokio_rx) = mpsc::channel::<SynPacket>(4096);
-        thread::spawn(move || {
-            while let Some(pkt) = tokio_rx.blocking_recv() {
-                if std_tx.send(pkt).is_err() {
+        threads.spawn("syn-bridge", move |_| {
+            for queue in queues.iter().cycle() {
+                let Some(pkt) = tokio_rx.blocking_recv() else {
+                    break;
+                };
+                if queue.send(pkt).is_err() {
                     break;
                 }
             }
@@ -364,8 +667,21 @@ impl SynScanner {
         tokio_tx
     }
 
+    /// Keep listening this long after the last probe before `finish` stops
+    /// the receiver; raise it for high-latency targets.
+    pub fn with_linger(mut self, linger: Duration) -> Self {
+        self.linger = linger;
+        self
+    }
+
+    /// Send probes from ports in `ports` (`--source-port-range`).
+    pub fn with_source_ports(mut self, ports: SourcePorts) -> Self {
+        self.source_ports = ports;
+        self
+    }
+
     #[cfg(target_os = "windows")]
-    fn get_gateway_info_windows() -> Result<(Ipv4Addr, MacAddr, Ipv4Addr)> {
+    pub(crate) fn get_gateway_info_windows() -> Result<(Ipv4Addr, MacAddr, Ipv4Addr)> {
         let output = Command::new("route").args(&["print", "0.0.0.0"]).output()?;
         let output_str = String::from_utf8_lossy(&output.stdout);
 
@@ -397,115 +713,35 @@ impl SynScanner {
     }
 
     #[cfg(not(target_os = "windows"))]
-    fn find_source_ip(dst_ip: Ipv4Addr) -> Option<Ipv4Addr> {
-        let interfaces = pnet_datalink::interfaces();
-        let mut best_if_ip: Option<Ipv4Addr> = None;
-        for iface in interfaces {
-            for ip_net in iface.ips {
-                if let IpAddr::V4(ipv4_addr) = ip_net.ip() {
-                    if ip_net.contains(IpAddr::V4(dst_ip)) {
-                        return Some(ipv4_addr);
-                    }
-                    if !ipv4_addr.is_loopback() && best_if_ip.is_none() {
-                        best_if_ip = Some(ipv4_addr);
-                    }
-                }
-            }
-        }
-        best_if_ip
-    }
-
-    #[cfg(not(target_os = "windows"))]
-    #[inline]
     fn send_syn_l4_internal(
         tx: &mut transport::TransportSender,
-        dst_ip: Ipv4Addr,
-        dst_port: u16,
+        template: &mut SynTemplate,
+        routes: &SourceRoutes,
+        pkt: &SynPacket,
     ) -> Result<()> {
-        let src_ip = Self::find_source_ip(dst_ip).ok_or_else(|| {
+        let src_ip = routes.source_for(pkt.dst_ip).ok_or_else(|| {
             anyhow!(
                 "Could not find suitable source IP for destination {}",
-                dst_ip
+                pkt.dst_ip
             )
         })?;
-
-        let mut vec = vec![0u8; 20];
-        let mut tcp_packet =
-            MutableTcpPacket::new(&mut vec).ok_or(anyhow!("Failed to create TCP packet"))?;
-
-        let mut rng = rand::thread_rng();
-        let src_port = rng.gen_range(1025..=65535);
-
-        tcp_packet.set_source(src_port);
-        tcp_packet.set_destination(dst_port);
-        tcp_packet.set_sequence(rng.gen());
-        tcp_packet.set_acknowledgement(0);
-        tcp_packet.set_flags(TcpFlags::SYN);
-        tcp_packet.set_window(64240);
-        tcp_packet.set_data_offset(5);
-        tcp_packet.set_urgent_ptr(0);
-        let checksum = ipv4_checksum(&tcp_packet.to_immutable(), &src_ip, &dst_ip);
-        tcp_packet.set_checksum(checksum);
-        tx.send_to(tcp_packet, IpAddr::V4(dst_ip))?;
+        let header = template.build(src_ip, pkt);
+        let tcp = TcpPacket::new(header).ok_or(anyhow!("Failed to create TCP packet"))?;
+        tx.send_to(tcp, IpAddr::V4(pkt.dst_ip))?;
         Ok(())
     }
 
-    #[cfg(target_os = "windows")]
-    fn send_syn_l2_internal(
-        sender: &mut Box<dyn datalink::DataLinkSender>,
-        src_mac: MacAddr,
-        dst_mac: MacAddr,
-        src_ip: Ipv4Addr,
-        dst_ip: Ipv4Addr,
-        dst_port: u16,
-    ) {
-        const ETH_HEADER_LEN: usize = 14;
-        const IP_HEADER_LEN: usize = 20;
-        const TCP_HEADER_LEN: usize = 20;
-        const TOTAL_LEN: usize = ETH_HEADER_LEN + IP_HEADER_LEN + TCP_HEADER_LEN;
-
-        sender.build_and_send(1, TOTAL_LEN, &mut |packet| {
-            let mut eth = MutableEthernetPacket::new(packet).unwrap();
-            eth.set_destination(dst_mac);
-            eth.set_source(src_mac);
-            eth.set_ethertype(EtherTypes::Ipv4);
-
-            let mut ip = MutableIpv4Packet::new(eth.payload_mut()).unwrap();
-            ip.set_version(4);
-            ip.set_header_length(5);
-            ip.set_total_length((IP_HEADER_LEN + TCP_HEADER_LEN) as u16);
-            ip.set_ttl(64);
-            ip.set_next_level_protocol(IpNextHeaderProtocols::Tcp);
-            ip.set_source(src_ip);
-            ip.set_destination(dst_ip);
-            let ip_checksum = ipv4::checksum(&ip.to_immutable());
-            ip.set_checksum(ip_checksum);
-
-            let mut tcp = MutableTcpPacket::new(ip.payload_mut()).unwrap();
-            let mut rng = rand::thread_rng();
-            let src_port = rng.gen_range(1025..=65535);
-
-            tcp.set_source(src_port);
-            tcp.set_destination(dst_port);
-            tcp.set_sequence(rng.gen());
-            tcp.set_acknowledgement(0);
-            tcp.set_flags(TcpFlags::SYN);
-            tcp.set_window(64240);
-            tcp.set_data_offset(5);
-            tcp.set_urgent_ptr(0);
-
-            let checksum = ipv4_checksum(&tcp.to_immutable(), &src_ip, &dst_ip);
-            tcp.set_checksum(checksum);
-        });
-    }
-
     pub async fn send_syn(&self, dst_ip: Ipv4Addr, dst_port: u16) -> Result<()> {
-        let pkt = SynPacket { dst_ip, dst_port };
+        let pkt = SynPacket {
+            src_port: self.source_ports.pick(),
+            dst_ip,
+            dst_port,
+        };
         self.packet_tx
             .send(pkt)
             .await
             .map_err(|e| anyhow!("{}", e))?;
-        self.metrics.increment_scanned();
+        self.metrics.record_scanned(IpAddr::V4(dst_ip), dst_port);
         Ok(())
     }
 
@@ -516,25 +752,165 @@ impl SynScanner {
         progress_callback: impl Fn(usize) + Send + Sync + 'static,
     ) -> Result<()> {
         let mut total_sent = 0;
-
-        while let Some(ip) = rx.recv().await {
+        let mut last_sent = None;
+
+        'ips: loop {
+            let ip = tokio::select! {
+                biased;
+                _ = self.cancel.cancelled() => break,
+                ip = rx.recv() => match ip {
+                    Some(ip) => {
+                        self.metrics.set_pipeline_queue(rx.len(), rx.max_capacity());
+                        ip
+                    }
+                    None => break,
+                },
+            };
             if let IpAddr::V4(ipv4) = ip {
                 for port in &ports {
-                    self.rate_limiter.acquire().await;
+                    // An IP cut short here is not recorded as progress.
+                    if !self.rate_limiter.acquire_or_cancel(&self.cancel).await {
+                        break 'ips;
+                    }
                     if let Err(e) = self.send_syn(ipv4, *port).await {
                         debug!(ip = %ipv4, port = port, error = %e, "Failed to send SYN");
-                        self.metrics.increment_errors();
+                        self.metrics.record_error(ip, *port);
                     }
                 }
                 total_sent += 1;
                 progress_callback(total_sent);
+                if total_sent.is_multiple_of(200) {
+                    self.save_progress(ipv4);
+                }
+                last_sent = Some(ipv4);
             }
         }
 
+        if let Some(ipv4) = last_sent {
+            self.save_progress(ipv4);
+        }
+
         Ok(())
     }
 
+    fn save_progress(&self, ip: Ipv4Addr) {
+        if let Err(e) = self
+            .db
+            .save_progress(&ip.to_string(), "IPv4", self.scan_round)
+        {
+            error!("Progress save error: {}", e);
+        }
+    }
+
+    /// Give in-flight SYN-ACKs the linger window to arrive, stop and join the
+    /// packet threads, then stop the DB writer after it has drained the
+    /// result channel and flushed its last batch. A cancelled scan skips
+    /// the linger.
+    pub async fn finish(self) {
+        tokio::select! {
+            _ = tokio::time::sleep(self.linger) => {}
+            _ = self.cancel.cancelled() => {}
+        }
+        drop(self.packet_tx);
+        let mut threads = self.threads;
+        if let Err(e) = tokio::task::spawn_blocking(move || threads.stop()).await {
+            error!("Failed to stop SYN scanner threads: {}", e);
+        }
+        let _ = self.writer_shutdown.send(());
+        if let Err(e) = self.writer.await {
+            error!("DB writer task failed: {}", e);
+        }
+    }
+
     pub fn get_metrics(&self) -> &ScanMetrics {
         &self.metrics
     }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// Receive every SYN-ACK seen from now on, with the same lagging
+    /// behaviour as [`super::ConScanner::subscribe`].
+    pub fn subscribe(&self) -> broadcast::Receiver<OpenPort> {
+        self.events.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_syn_rtt_from_echoed_sequence() {
+        let seq = syn_timestamp();
+        std::thread::sleep(Duration::from_millis(5));
+        let rtt = syn_rtt(seq.wrapping_add(1)).unwrap();
+        assert!(rtt >= Duration::from_millis(5) && rtt < MAX_SYN_RTT);
+
+        // An ack far from any recent send is not a reply to our probe.
+        assert!(syn_rtt(seq.wrapping_add(1).wrapping_sub(u32::MAX / 2)).is_none());
+    }
+
+    #[test]
+    fn test_syn_template_matches_freshly_built_header() {
+        let mut template = SynTemplate::new();
+        let src_ip = Ipv4Addr::new(192, 0, 2, 10);
+        for (src_port, dst_ip, dst_port) in [
+            (40000, Ipv4Addr::new(198, 51, 100, 7), 443),
+            (65535, Ipv4Addr::new(203, 0, 113, 255), 1),
+        ] {
+            let pkt = SynPacket {
+                src_port,
+                dst_ip,
+                dst_port,
+            };
+            let header = template.build(src_ip, &pkt).to_vec();
+            let tcp = TcpPacket::new(&header).unwrap();
+            assert_eq!(tcp.get_source(), src_port);
+            assert_eq!(tcp.get_destination(), dst_port);
+            assert_eq!(tcp.get_flags(), TcpFlags::SYN);
+            assert_eq!(
+                tcp.get_checksum(),
+                pnet_packet::tcp::ipv4_checksum(&tcp, &src_ip, &dst_ip)
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_source_routes_prefer_the_destination_network() {
+        let routes = SourceRoutes {
+            networks: vec![
+                (0x7f00_0000, 0xff00_0000, Ipv4Addr::LOCALHOST),
+                (0x0a00_0000, 0xffff_0000, Ipv4Addr::new(10, 0, 0, 5)),
+            ],
+            fallback: Some(Ipv4Addr::new(10, 0, 0, 5)),
+        };
+        assert_eq!(
+            routes.source_for(Ipv4Addr::new(127, 0, 0, 1)),
+            Some(Ipv4Addr::LOCALHOST)
+        );
+        assert_eq!(
+            routes.source_for(Ipv4Addr::new(8, 8, 8, 8)),
+            Some(Ipv4Addr::new(10, 0, 0, 5))
+        );
+    }
+
+    #[test]
+    fn test_scanner_threads_are_joined_on_drop() {
+        let exited = Arc::new(AtomicBool::new(false));
+        let mut threads = ScannerThreads::new();
+        let flag = exited.clone();
+        threads.spawn("test-recv", move |shutdown| {
+            while !shutdown.load(Ordering::SeqCst) {
+                thread::sleep(THREAD_POLL);
+            }
+            flag.store(true, Ordering::SeqCst);
+        });
+        let start = Instant::now();
+        drop(threads);
+        assert!(exited.load(Ordering::SeqCst));
+        assert!(start.elapsed() < THREAD_POLL * 3);
+    }
 }
@@ -0,0 +1,477 @@
+//! Syslog (RFC 5424) and CEF sink for scan events.
+//!
+//! Every matching [`ScanEvent`] becomes one RFC 5424 message. In `rfc5424`
+//! format the event fields travel as structured data next to a readable
+//! message; in `cef` format the message body is an ArcSight CEF record, the
+//! layout most SIEM parsers expect. UDP sends one datagram per event; TCP
+//! and TLS (RFC 5425) use octet-counting framing (RFC 6587).
+
+use super::notify::{render_template, EVENT_KINDS};
+use super::{EventBus, ScanEvent};
+use crate::cli::SyslogConfig;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_native_tls::{TlsConnector, TlsStream};
+use tracing::{info, warn};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+/// Events arriving sooner than this after a failure are dropped unsent.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Structured-data ID; 32473 is the enterprise number reserved for examples.
+const SD_ID: &str = "scan@32473";
+
+const FACILITIES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv",
+    "ftp", "ntp", "audit", "alert", "clock", "local0", "local1", "local2", "local3", "local4",
+    "local5", "local6", "local7",
+];
+const SEVERITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+/// CEF severity (0-10) for each syslog severity above.
+const CEF_SEVERITIES: [u8; 8] = [10, 10, 9, 7, 5, 3, 1, 0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Rfc5424,
+    Cef,
+}
+
+#[derive(Clone)]
+enum Transport {
+    Udp,
+    Tcp,
+    Tls(TlsConnector),
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Connection {
+    async fn send(&mut self, message: &str) -> std::io::Result<()> {
+        let framed = format!("{} {}", message.len(), message);
+        match self {
+            Connection::Udp(socket) => socket.send(message.as_bytes()).await.map(|_| ()),
+            Connection::Tcp(stream) => stream.write_all(framed.as_bytes()).await,
+            Connection::Tls(stream) => stream.write_all(framed.as_bytes()).await,
+        }
+    }
+
+    async fn shutdown(self) {
+        let _ = match self {
+            Connection::Udp(_) => return,
+            Connection::Tcp(mut stream) => {
+                tokio::time::timeout(SEND_TIMEOUT, stream.shutdown()).await
+            }
+            Connection::Tls(mut stream) => {
+                tokio::time::timeout(SEND_TIMEOUT, stream.shutdown()).await
+            }
+        };
+    }
+}
+
+pub struct SyslogSink {
+    host: String,
+    address: String,
+    transport: Transport,
+    format: Format,
+    facility: u8,
+    severity: u8,
+    hostname: String,
+    app_name: String,
+    events: Vec<String>,
+}
+
+impl SyslogSink {
+    /// `None` when no collector is configured. Errors on invalid settings,
+    /// so a typo fails at startup rather than on the first event.
+    pub fn from_config(config: &SyslogConfig) -> Result<Option<Self>> {
+        let Some(host) = config.host.as_deref() else {
+            return Ok(None);
+        };
+        let transport = match config.protocol.as_str() {
+            "udp" => Transport::Udp,
+            "tcp" => Transport::Tcp,
+            "tls" => Transport::Tls(tls_connector(config.ca_file.as_deref())?),
+            other => {
+                return Err(anyhow!(
+                    "Invalid [syslog] protocol {}; expected udp, tcp or tls",
+                    other
+                ))
+            }
+        };
+        if config.ca_file.is_some() && !matches!(transport, Transport::Tls(_)) {
+            return Err(anyhow!("[syslog] ca_file needs protocol = \"tls\""));
+        }
+        let format = match config.format.as_str() {
+            "rfc5424" => Format::Rfc5424,
+            "cef" => Format::Cef,
+            other => {
+                return Err(anyhow!(
+                    "Invalid [syslog] format {}; expected rfc5424 or cef",
+                    other
+                ))
+            }
+        };
+        let facility = FACILITIES
+            .iter()
+            .position(|f| *f == config.facility)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid [syslog] facility {}; expected one of {}",
+                    config.facility,
+                    FACILITIES.join(", ")
+                )
+            })?;
+        let severity = SEVERITIES
+            .iter()
+            .position(|s| *s == config.severity)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid [syslog] severity {}; expected one of {}",
+                    config.severity,
+                    SEVERITIES.join(", ")
+                )
+            })?;
+        if let Some(event) = config
+            .events
+            .iter()
+            .find(|e| !EVENT_KINDS.contains(&e.as_str()))
+        {
+            return Err(anyhow!(
+                "Invalid [syslog] event {}; expected one of {}",
+                event,
+                EVENT_KINDS.join(", ")
+            ));
+        }
+        let hostname = match &config.hostname {
+            Some(name) => name.clone(),
+            None => local_hostname(),
+        };
+        for (field, value) in [("hostname", &hostname), ("app_name", &config.app_name)] {
+            if value.is_empty() || !value.chars().all(|c| c.is_ascii_graphic()) {
+                return Err(anyhow!(
+                    "Invalid [syslog] {} {:?}; expected printable ASCII without spaces",
+                    field,
+                    value
+                ));
+            }
+        }
+
+        Ok(Some(Self {
+            host: host.to_string(),
+            address: format!("{}:{}", host, config.port),
+            transport,
+            format,
+            facility: facility as u8,
+            severity: severity as u8,
+            hostname,
+            app_name: config.app_name.clone(),
+            events: config.events.clone(),
+        }))
+    }
+
+    /// Forward bus events until every bus handle is dropped. While the
+    /// collector is unreachable events are dropped and a reconnect is tried
+    /// at most every few seconds, so a dead SIEM never slows the scan.
+    pub fn spawn(self, bus: &EventBus) -> JoinHandle<()> {
+        let mut events = bus.subscribe();
+        tokio::spawn(async move {
+            let mut connection: Option<Connection> = None;
+            let mut retry_at = Instant::now();
+            let mut dropped = 0u64;
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Syslog sink lagged, {} events dropped", missed);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if !self.matches(&event) {
+                    continue;
+                }
+                if connection.is_none() {
+                    if Instant::now() < retry_at {
+                        dropped += 1;
+                        continue;
+                    }
+                    match tokio::time::timeout(CONNECT_TIMEOUT, self.connect()).await {
+                        Ok(Ok(conn)) => {
+                            info!("Forwarding scan events to syslog {}", self.address);
+                            if dropped > 0 {
+                                warn!(
+                                    "{} syslog events dropped while {} was unreachable",
+                                    dropped, self.address
+                                );
+                                dropped = 0;
+                            }
+                            connection = Some(conn);
+                        }
+                        Ok(Err(e)) => {
+                            warn!("Syslog collector {} unavailable: {}", self.address, e);
+                            retry_at = Instant::now() + RECONNECT_DELAY;
+                            dropped += 1;
+                            continue;
+                        }
+                        Err(_) => {
+                            warn!("Connecting to syslog collector {} timed out", self.address);
+                            retry_at = Instant::now() + RECONNECT_DELAY;
+                            dropped += 1;
+                            continue;
+                        }
+                    }
+                }
+                let Some(conn) = connection.as_mut() else {
+                    continue;
+                };
+                let message = self.format(&event, Utc::now());
+                let error = match tokio::time::timeout(SEND_TIMEOUT, conn.send(&message)).await {
+                    Ok(Ok(())) => continue,
+                    Ok(Err(e)) => e.to_string(),
+                    Err(_) => "timed out".to_string(),
+                };
+                warn!("Syslog send to {} failed: {}", self.address, error);
+                connection = None;
+                retry_at = Instant::now() + RECONNECT_DELAY;
+                dropped += 1;
+            }
+            if let Some(connection) = connection {
+                connection.shutdown().await;
+            }
+        })
+    }
+
+    async fn connect(&self) -> std::io::Result<Connection> {
+        let addr = tokio::net::lookup_host(&self.address)
+            .await?
+            .next()
+            .ok_or_else(|| std::io::Error::other("no address resolved"))?;
+        match &self.transport {
+            Transport::Udp => {}
+            Transport::Tcp => return Ok(Connection::Tcp(TcpStream::connect(addr).await?)),
+            Transport::Tls(connector) => {
+                let stream = TcpStream::connect(addr).await?;
+                let stream = connector
+                    .connect(&self.host, stream)
+                    .await
+                    .map_err(std::io::Error::other)?;
+                return Ok(Connection::Tls(Box::new(stream)));
+            }
+        }
+        let local = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(addr).await?;
+        Ok(Connection::Udp(socket))
+    }
+
+    fn matches(&self, event: &ScanEvent) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event.kind())
+    }
+
+    /// One RFC 5424 message, without transport framing.
+    fn format(&self, event: &ScanEvent, timestamp: DateTime<Utc>) -> String {
+        let fields = event.fields();
+        let text = render_template(event.default_template(), &fields);
+        let header = format!(
+            "<{}>1 {} {} {} {} {}",
+            self.facility as u16 * 8 + self.severity as u16,
+            timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            self.hostname,
+            self.app_name,
+            std::process::id(),
+            event.kind()
+        );
+        match self.format {
+            Format::Rfc5424 => {
+                let params: Vec<String> = fields
+                    .iter()
+                    .filter(|(key, _)| *key != "event")
+                    .map(|(key, value)| format!("{}=\"{}\"", key, sd_escape(value)))
+                    .collect();
+                format!("{} [{} {}] {}", header, SD_ID, params.join(" "), text)
+            }
+            Format::Cef => format!("{} - {}", header, self.cef(event, &text, timestamp)),
+        }
+    }
+
+    fn cef(&self, event: &ScanEvent, text: &str, timestamp: DateTime<Utc>) -> String {
+        let mut extension = vec![("rt", timestamp.timestamp_millis().to_string())];
+        match event {
+            ScanEvent::OpenPort(open) => {
+                extension.push(("dst", open.ip.to_string()));
+                extension.push(("dpt", open.port.to_string()));
+                extension.push(("proto", "TCP".to_string()));
+                extension.push(("cn1", open.scan_round.to_string()));
+                extension.push(("cn1Label", "scanRound".to_string()));
+            }
+            ScanEvent::NewCountry { ip, country } => {
+                extension.push(("dst", ip.clone()));
+                extension.push(("cs1", country.clone()));
+                extension.push(("cs1Label", "country".to_string()));
+            }
+            ScanEvent::RoundComplete(m) => {
+                extension.push(("cn1", m.round.to_string()));
+                extension.push(("cn1Label", "scanRound".to_string()));
+                extension.push(("cn2", m.scanned.to_string()));
+                extension.push(("cn2Label", "scanned".to_string()));
+                extension.push(("cn3", m.open.to_string()));
+                extension.push(("cn3Label", "openPorts".to_string()));
+            }
+        }
+        let extension: Vec<String> = extension
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, cef_value_escape(value)))
+            .collect();
+        format!(
+            "CEF:0|ip-scan|ip-scan|{}|{}|{}|{}|{}",
+            env!("CARGO_PKG_VERSION"),
+            event.kind(),
+            cef_header_escape(text),
+            CEF_SEVERITIES[self.severity as usize],
+            extension.join(" ")
+        )
+    }
+}
+
+/// Verifies the collector against the system roots plus `ca_file`.
+fn tls_connector(ca_file: Option<&str>) -> Result<TlsConnector> {
+    let mut builder = native_tls::TlsConnector::builder();
+    if let Some(path) = ca_file {
+        let pem = std::fs::read(path)
+            .map_err(|e| anyhow!("Failed to read [syslog] ca_file {}: {}", path, e))?;
+        let certificate = native_tls::Certificate::from_pem(&pem)
+            .map_err(|e| anyhow!("Invalid [syslog] ca_file {}: {}", path, e))?;
+        builder.add_root_certificate(certificate);
+    }
+    Ok(TlsConnector::from(builder.build()?))
+}
+
+fn sd_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(']', "\\]")
+}
+
+fn cef_header_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn cef_value_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\n', "\\n")
+}
+
+#[cfg(unix)]
+pub(crate) fn local_hostname() -> String {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for its full length; the result is only
+    // read up to the first NUL.
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    match std::str::from_utf8(&buf[..len]) {
+        Ok(name) if rc == 0 && !name.is_empty() => name.to_string(),
+        _ => "-".to_string(),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn local_hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| "-".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::OpenPort;
+
+    #[test]
+    fn test_formats_rfc5424_and_cef() {
+        let mut config = SyslogConfig::default();
+        assert!(SyslogSink::from_config(&config).unwrap().is_none());
+
+        config.host = Some("siem.local".to_string());
+        config.facility = "local9".to_string();
+        assert!(SyslogSink::from_config(&config).is_err());
+        config.facility = "local4".to_string();
+        config.severity = "warning".to_string();
+        config.hostname = Some("scanner 1".to_string());
+        assert!(SyslogSink::from_config(&config).is_err());
+        config.hostname = Some("scanner-1".to_string());
+
+        let event = ScanEvent::OpenPort(OpenPort {
+            ip: "192.0.2.7".parse().unwrap(),
+            port: 3389,
+            scan_round: 4,
+        });
+        let timestamp = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let pid = std::process::id();
+
+        let sink = SyslogSink::from_config(&config).unwrap().unwrap();
+        assert_eq!(
+            sink.format(&event, timestamp),
+            format!(
+                "<164>1 2024-05-01T12:00:00.000Z scanner-1 ip-scan {} open_port \
+                 [scan@32473 ip=\"192.0.2.7\" port=\"3389\" round=\"4\"] \
+                 Open port 192.0.2.7:3389 (round 4)",
+                pid
+            )
+        );
+
+        config.format = "cef".to_string();
+        let sink = SyslogSink::from_config(&config).unwrap().unwrap();
+        assert_eq!(
+            sink.format(&event, timestamp),
+            format!(
+                "<164>1 2024-05-01T12:00:00.000Z scanner-1 ip-scan {} open_port - \
+                 CEF:0|ip-scan|ip-scan|{}|open_port|Open port 192.0.2.7:3389 (round 4)|5|\
+                 rt=1714564800000 dst=192.0.2.7 dpt=3389 proto=TCP cn1=4 cn1Label=scanRound",
+                pid,
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        assert_eq!(cef_value_escape("a=b\\c"), "a\\=b\\\\c");
+        assert_eq!(sd_escape("x\"]"), "x\\\"\\]");
+    }
+
+    #[test]
+    fn test_tls_transport_settings() {
+        let mut config = SyslogConfig {
+            host: Some("siem.local".to_string()),
+            protocol: "tls".to_string(),
+            port: 6514,
+            ..SyslogConfig::default()
+        };
+        let sink = SyslogSink::from_config(&config).unwrap().unwrap();
+        assert!(matches!(sink.transport, Transport::Tls(_)));
+        assert_eq!(sink.address, "siem.local:6514");
+
+        config.ca_file = Some("/nonexistent/siem-ca.pem".to_string());
+        assert!(SyslogSink::from_config(&config).is_err());
+        config.protocol = "udp".to_string();
+        assert!(SyslogSink::from_config(&config).is_err());
+        config.protocol = "dtls".to_string();
+        config.ca_file = None;
+        assert!(SyslogSink::from_config(&config).is_err());
+    }
+}
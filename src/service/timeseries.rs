@@ -0,0 +1,210 @@
+//! Recent scan metrics for live charts (`/stats/timeseries`). A sampler task
+//! reads the scanner's counters, the queue gauges the pipeline already
+//! stores and the rate limiter's bucket every [`SAMPLE_EVERY`], and keeps
+//! the last `--timeseries-minutes` of samples in a ring. Everything it reads
+//! is an atomic the send path updates anyway, apart from the bucket, which
+//! it locks once per sample. CLI scans publish the ring to
+//! `scan_metadata.metrics_timeseries` for the API process; the API's own
+//! scans are read from the controller's ring.
+
+use super::RateLimiter;
+use crate::dao::SqliteDB;
+use crate::model::ScanMetrics;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::error;
+use utoipa::ToSchema;
+
+/// Time between two samples.
+pub const SAMPLE_EVERY: Duration = Duration::from_secs(5);
+
+/// `scan_metadata` key CLI scans publish their samples under.
+pub const TIMESERIES_KEY: &str = "metrics_timeseries";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MetricsSample {
+    /// Unix time of the sample, in seconds
+    pub at: i64,
+    pub round: i64,
+    /// Probes sent this round so far
+    pub scanned: u64,
+    pub open: u64,
+    pub errors: u64,
+    /// Probes per second since the previous sample
+    pub rate: f64,
+    /// Targets waiting for a probe
+    pub pipeline_depth: u64,
+    pub pipeline_capacity: u64,
+    /// Results waiting for the DB writer
+    pub result_depth: u64,
+    pub result_capacity: u64,
+    pub db_batch_size: u64,
+    /// Tokens left in the `--max-rate` bucket; negative while probes wait
+    /// for one
+    pub rate_tokens: f64,
+}
+
+/// The last few minutes of samples. Clones share the ring.
+#[derive(Clone)]
+pub struct MetricsTimeseries {
+    samples: Arc<Mutex<VecDeque<MetricsSample>>>,
+    capacity: usize,
+}
+
+impl MetricsTimeseries {
+    /// Keep `minutes` of samples; 0 keeps none and starts no sampler.
+    pub fn new(minutes: u64) -> Self {
+        let capacity = (minutes * 60 / SAMPLE_EVERY.as_secs()) as usize;
+        Self {
+            samples: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Samples, oldest first.
+    pub fn samples(&self) -> Vec<MetricsSample> {
+        self.samples.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.samples.lock().unwrap().clear();
+    }
+
+    /// Sample the scanner of `round` until the returned sampler is dropped,
+    /// publishing the ring to `publish` after each sample.
+    pub fn sample(
+        &self,
+        round: i64,
+        metrics: ScanMetrics,
+        limiter: RateLimiter,
+        publish: Option<SqliteDB>,
+    ) -> Option<TimeseriesSampler> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let series = self.clone();
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAMPLE_EVERY);
+            // The first tick fires at once; the first sample covers a full
+            // interval.
+            interval.tick().await;
+            let mut previous = metrics.get_scanned();
+            loop {
+                interval.tick().await;
+                let sample = take_sample(round, &metrics, &limiter, previous);
+                previous = sample.scanned;
+                series.push(sample);
+                if let Some(db) = &publish {
+                    series.publish(db);
+                }
+            }
+        });
+        Some(TimeseriesSampler { task })
+    }
+
+    fn push(&self, sample: MetricsSample) {
+        let mut samples = self.samples.lock().unwrap();
+        while samples.len() >= self.capacity {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    fn publish(&self, db: &SqliteDB) {
+        let json = match serde_json::to_string(&self.samples()) {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Failed to encode metrics timeseries: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = db.save_metadata(TIMESERIES_KEY, &json) {
+            error!("Failed to save {}: {}", TIMESERIES_KEY, e);
+        }
+    }
+}
+
+/// Dropping the sampler stops it; the samples stay in the ring.
+pub struct TimeseriesSampler {
+    task: JoinHandle<()>,
+}
+
+impl Drop for TimeseriesSampler {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn take_sample(
+    round: i64,
+    metrics: &ScanMetrics,
+    limiter: &RateLimiter,
+    previous: u64,
+) -> MetricsSample {
+    let scanned = metrics.get_scanned();
+    let queues = metrics.queue_stats();
+    MetricsSample {
+        at: chrono::Utc::now().timestamp(),
+        round,
+        scanned,
+        open: metrics.get_open(),
+        errors: metrics.get_errors(),
+        rate: scanned.saturating_sub(previous) as f64 / SAMPLE_EVERY.as_secs_f64(),
+        pipeline_depth: queues.pipeline.depth,
+        pipeline_capacity: queues.pipeline.capacity,
+        result_depth: queues.results.depth,
+        result_capacity: queues.results.capacity,
+        db_batch_size: queues.db_batch_size,
+        rate_tokens: limiter.available(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ring_keeps_the_latest_samples_and_publishes_them() {
+        let series = MetricsTimeseries::new(1);
+        assert_eq!(series.capacity, 12);
+        assert!(MetricsTimeseries::new(0)
+            .sample(
+                1,
+                ScanMetrics::new(),
+                RateLimiter::new(10, SAMPLE_EVERY),
+                None
+            )
+            .is_none());
+
+        let metrics = ScanMetrics::new();
+        let limiter = RateLimiter::new(10, Duration::from_secs(1));
+        limiter.acquire().await;
+        let target = "192.0.2.1".parse().unwrap();
+        for port in 0..20 {
+            metrics.record_scanned(target, port);
+        }
+        metrics.set_pipeline_queue(3, 8);
+        for round in 0..15 {
+            series.push(take_sample(round, &metrics, &limiter, 10));
+        }
+
+        let samples = series.samples();
+        assert_eq!(samples.len(), 12);
+        assert_eq!((samples[0].round, samples[11].round), (3, 14));
+        let last = &samples[11];
+        assert_eq!((last.scanned, last.rate), (20, 2.0));
+        assert_eq!((last.pipeline_depth, last.pipeline_capacity), (3, 8));
+        assert!(last.rate_tokens >= 9.0 && last.rate_tokens <= 10.0);
+
+        let db = SqliteDB::new(":memory:").unwrap();
+        series.publish(&db);
+        let stored: Vec<MetricsSample> =
+            serde_json::from_str(&db.get_metadata(TIMESERIES_KEY).unwrap().unwrap()).unwrap();
+        assert_eq!(stored, samples);
+        series.clear();
+        assert!(series.samples().is_empty());
+    }
+}
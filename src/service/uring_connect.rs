@@ -0,0 +1,448 @@
+//! io_uring connect backend for `--io-backend uring` (Linux only).
+//!
+//! A single thread owns the ring. Each probe becomes a non-blocking socket
+//! and a linked CONNECT + LINK_TIMEOUT pair, so the kernel enforces the
+//! timeout and no per-probe task, timer or epoll registration exists.
+//! Everything queued since the last wakeup is submitted with one syscall and
+//! completions are reaped in batches. An eventfd read stays armed on the ring
+//! so new probes wake the thread while it waits for completions.
+
+use crate::model::{ScanMetrics, SourcePorts};
+use io_uring::{opcode, squeue, types, IoUring};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::mpsc as std_mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, OwnedSemaphorePermit};
+use tracing::{error, info};
+
+/// Submission queue size. Probes in flight are bounded by the scanner's
+/// semaphore, not by this; the kernel keeps overflowing completions.
+const RING_ENTRIES: u32 = 4096;
+
+const WAKE: u64 = u64::MAX;
+const LINK_TIMEOUT: u64 = u64::MAX - 1;
+const CANCEL: u64 = u64::MAX - 2;
+
+/// What the ring thread needs to report a finished probe.
+pub(super) struct RingContext {
+    pub metrics: ScanMetrics,
+    pub result_tx: mpsc::Sender<(IpAddr, u16, bool)>,
+    pub source_ports: Option<SourcePorts>,
+    pub scan_round: i64,
+}
+
+/// Held until a probe's result has been queued: the global `--concurrency`
+/// permit and the target host's `--host-concurrency` permit. Only ever
+/// dropped, never read.
+#[allow(dead_code)]
+pub(super) struct ProbePermits {
+    pub global: OwnedSemaphorePermit,
+    pub host: OwnedSemaphorePermit,
+}
+
+enum Request {
+    Probe(IpAddr, u16, ProbePermits),
+    /// Abort every probe in flight; their results are dropped.
+    Cancel,
+}
+
+struct InFlight {
+    fd: RawFd,
+    /// Read by the kernel when the CONNECT is submitted.
+    _addr: Box<libc::sockaddr_storage>,
+    ip: IpAddr,
+    port: u16,
+    started: Instant,
+    _permits: ProbePermits,
+}
+
+pub(super) struct UringConnector {
+    tx: Option<std_mpsc::Sender<Request>>,
+    wake: OwnedFd,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl UringConnector {
+    /// Fails when the kernel or a seccomp profile does not allow io_uring.
+    pub(super) fn new(ctx: RingContext, timeout: Duration) -> io::Result<Self> {
+        let ring = IoUring::new(RING_ENTRIES)?;
+        let wake = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if wake < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let wake = unsafe { OwnedFd::from_raw_fd(wake) };
+        let (tx, rx) = std_mpsc::channel();
+        let wake_fd = wake.as_raw_fd();
+        let thread = thread::Builder::new()
+            .name("uring-connect".to_string())
+            .spawn(move || {
+                if let Err(e) = run_ring(ring, wake_fd, rx, ctx, timeout) {
+                    error!("io_uring connect loop failed: {}", e);
+                }
+            })?;
+        Ok(UringConnector {
+            tx: Some(tx),
+            wake,
+            thread: Some(thread),
+        })
+    }
+
+    /// Queue a connect probe. `permits` bound the probes in flight.
+    pub(super) fn probe(&self, ip: IpAddr, port: u16, permits: ProbePermits) {
+        self.send(Request::Probe(ip, port, permits));
+    }
+
+    pub(super) fn cancel(&self) {
+        self.send(Request::Cancel);
+    }
+
+    fn send(&self, request: Request) {
+        if let Some(tx) = &self.tx {
+            if tx.send(request).is_ok() {
+                self.notify();
+            }
+        }
+    }
+
+    fn notify(&self) {
+        let one: u64 = 1;
+        unsafe {
+            libc::write(
+                self.wake.as_raw_fd(),
+                &one as *const u64 as *const libc::c_void,
+                8,
+            );
+        }
+    }
+}
+
+impl Drop for UringConnector {
+    /// Blocks until the probes in flight have completed or timed out.
+    fn drop(&mut self) {
+        drop(self.tx.take());
+        self.notify();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("io_uring connect thread panicked");
+            }
+        }
+    }
+}
+
+fn run_ring(
+    mut ring: IoUring,
+    wake_fd: RawFd,
+    rx: std_mpsc::Receiver<Request>,
+    ctx: RingContext,
+    timeout: Duration,
+) -> io::Result<()> {
+    let timespec = types::Timespec::new()
+        .sec(timeout.as_secs())
+        .nsec(timeout.subsec_nanos());
+    let mut slots: Vec<Option<InFlight>> = Vec::new();
+    let mut free: Vec<usize> = Vec::new();
+    let mut in_flight = 0usize;
+    let mut wake_buf = [0u8; 8];
+    let mut wake_armed = false;
+    let mut closed = false;
+    let mut cancelled = false;
+
+    loop {
+        if !wake_armed && !closed {
+            let read = opcode::Read::new(types::Fd(wake_fd), wake_buf.as_mut_ptr(), 8)
+                .build()
+                .user_data(WAKE);
+            push(&mut ring, &[read])?;
+            wake_armed = true;
+        }
+
+        loop {
+            match rx.try_recv() {
+                Ok(Request::Probe(ip, port, permits)) => {
+                    if cancelled {
+                        continue;
+                    }
+                    let addr = SocketAddr::new(ip, port);
+                    let (storage, len) = raw_sockaddr(addr);
+                    let domain = match ip {
+                        IpAddr::V4(_) => libc::AF_INET,
+                        IpAddr::V6(_) => libc::AF_INET6,
+                    };
+                    let fd = unsafe {
+                        libc::socket(
+                            domain,
+                            libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                            0,
+                        )
+                    };
+                    let bound = match ctx.source_ports {
+                        Some(ports) if fd >= 0 => bind_source_port(fd, ip, ports.pick()),
+                        _ => Ok(()),
+                    };
+                    if fd < 0 || bound.is_err() {
+                        if fd >= 0 {
+                            unsafe { libc::close(fd) };
+                        }
+                        ctx.metrics.record_error(ip, port);
+                        report(&ctx, ip, port, false);
+                        continue;
+                    }
+                    let idx = free.pop().unwrap_or_else(|| {
+                        slots.push(None);
+                        slots.len() - 1
+                    });
+                    let connect = opcode::Connect::new(
+                        types::Fd(fd),
+                        &*storage as *const libc::sockaddr_storage as *const libc::sockaddr,
+                        len,
+                    )
+                    .build()
+                    .flags(squeue::Flags::IO_LINK)
+                    .user_data((idx as u64) << 1);
+                    let link_timeout = opcode::LinkTimeout::new(&timespec)
+                        .build()
+                        .user_data(LINK_TIMEOUT);
+                    slots[idx] = Some(InFlight {
+                        fd,
+                        _addr: storage,
+                        ip,
+                        port,
+                        started: Instant::now(),
+                        _permits: permits,
+                    });
+                    in_flight += 1;
+                    push(&mut ring, &[connect, link_timeout])?;
+                }
+                Ok(Request::Cancel) => {
+                    cancelled = true;
+                    for (idx, slot) in slots.iter().enumerate() {
+                        if slot.is_some() {
+                            let cancel = opcode::AsyncCancel::new((idx as u64) << 1)
+                                .build()
+                                .user_data(CANCEL);
+                            push(&mut ring, &[cancel])?;
+                        }
+                    }
+                }
+                Err(std_mpsc::TryRecvError::Empty) => break,
+                Err(std_mpsc::TryRecvError::Disconnected) => {
+                    closed = true;
+                    break;
+                }
+            }
+        }
+
+        if closed && in_flight == 0 {
+            return Ok(());
+        }
+
+        match ring.submit_and_wait(1) {
+            Ok(_) => {}
+            Err(e) if e.raw_os_error() == Some(libc::EINTR) => continue,
+            Err(e) => return Err(e),
+        }
+
+        let completions: Vec<(u64, i32)> = ring
+            .completion()
+            .map(|cqe| (cqe.user_data(), cqe.result()))
+            .collect();
+        for (user_data, result) in completions {
+            match user_data {
+                WAKE => wake_armed = false,
+                LINK_TIMEOUT | CANCEL => {}
+                _ => {
+                    let idx = (user_data >> 1) as usize;
+                    let Some(probe) = slots[idx].take() else {
+                        continue;
+                    };
+                    free.push(idx);
+                    in_flight -= 1;
+                    complete(&ctx, probe, result, cancelled);
+                }
+            }
+        }
+    }
+}
+
+/// Same accounting as the tokio backend's `try_connect`.
+fn complete(ctx: &RingContext, probe: InFlight, result: i32, cancelled: bool) {
+    unsafe { libc::close(probe.fd) };
+    let (ip, port) = (probe.ip, probe.port);
+    let open = match -result {
+        0 => {
+            ctx.metrics.record_connect_latency(probe.started.elapsed());
+            ctx.metrics.record_reply(false);
+            true
+        }
+        libc::ECONNREFUSED => {
+            ctx.metrics.record_connect_latency(probe.started.elapsed());
+            ctx.metrics.record_reply(true);
+            false
+        }
+        // Timed out, or aborted by a cancel.
+        libc::ECANCELED => {
+            if cancelled {
+                return;
+            }
+            false
+        }
+        _ => {
+            ctx.metrics.record_error(ip, port);
+            false
+        }
+    };
+    report(ctx, ip, port, open);
+}
+
+fn report(ctx: &RingContext, ip: IpAddr, port: u16, open: bool) {
+    if open {
+        ctx.metrics.record_open(ip, port);
+        info!(ip = %ip, port, round = ctx.scan_round, "Found open port");
+    }
+    if let Err(e) = ctx.result_tx.blocking_send((ip, port, open)) {
+        error!("Result channel send error: {}", e);
+    }
+}
+
+/// Queue entries, flushing the submission queue first if they do not fit.
+fn push(ring: &mut IoUring, entries: &[squeue::Entry]) -> io::Result<()> {
+    let free = {
+        let sq = ring.submission();
+        sq.capacity() - sq.len()
+    };
+    if free < entries.len() {
+        ring.submit()?;
+    }
+    unsafe { ring.submission().push_multiple(entries) }
+        .map_err(|_| io::Error::other("io_uring submission queue full"))
+}
+
+/// Bind `fd` to `port` on the wildcard address, sharing it with other
+/// probes like the tokio backend does.
+fn bind_source_port(fd: RawFd, ip: IpAddr, port: u16) -> io::Result<()> {
+    let one: libc::c_int = 1;
+    for option in [libc::SO_REUSEADDR, libc::SO_REUSEPORT] {
+        let rc = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                option,
+                &one as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    let any = match ip {
+        IpAddr::V4(_) => IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED),
+    };
+    let (storage, len) = raw_sockaddr(SocketAddr::new(any, port));
+    let rc = unsafe {
+        libc::bind(
+            fd,
+            &*storage as *const libc::sockaddr_storage as *const libc::sockaddr,
+            len,
+        )
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn raw_sockaddr(addr: SocketAddr) -> (Box<libc::sockaddr_storage>, libc::socklen_t) {
+    let mut storage: Box<libc::sockaddr_storage> = Box::new(unsafe { std::mem::zeroed() });
+    let len = match addr {
+        SocketAddr::V4(v4) => {
+            let sin = libc::sockaddr_in {
+                sin_family: libc::AF_INET as libc::sa_family_t,
+                sin_port: v4.port().to_be(),
+                sin_addr: libc::in_addr {
+                    s_addr: u32::from_ne_bytes(v4.ip().octets()),
+                },
+                sin_zero: [0; 8],
+            };
+            unsafe { std::ptr::write(&mut *storage as *mut _ as *mut libc::sockaddr_in, sin) };
+            std::mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(v6) => {
+            let sin6 = libc::sockaddr_in6 {
+                sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                sin6_port: v6.port().to_be(),
+                sin6_flowinfo: v6.flowinfo(),
+                sin6_addr: libc::in6_addr {
+                    s6_addr: v6.ip().octets(),
+                },
+                sin6_scope_id: v6.scope_id(),
+            };
+            unsafe { std::ptr::write(&mut *storage as *mut _ as *mut libc::sockaddr_in6, sin6) };
+            std::mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (storage, len as libc::socklen_t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::sync::Semaphore;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_uring_connect_reports_open_and_closed_ports() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open_port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { while (listener.accept().await).is_ok() {} });
+        let closed_port = {
+            let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            l.local_addr().unwrap().port()
+        };
+
+        let (result_tx, mut result_rx) = mpsc::channel(16);
+        let ctx = RingContext {
+            metrics: ScanMetrics::new(),
+            result_tx,
+            source_ports: None,
+            scan_round: 1,
+        };
+        let connector = match UringConnector::new(ctx, Duration::from_millis(500)) {
+            Ok(connector) => connector,
+            Err(e) => {
+                eprintln!("io_uring unavailable, skipping: {}", e);
+                return;
+            }
+        };
+        let semaphore = Arc::new(Semaphore::new(4));
+        let host = Arc::new(Semaphore::new(2));
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        for port in [open_port, closed_port] {
+            let permits = ProbePermits {
+                global: semaphore.clone().acquire_owned().await.unwrap(),
+                host: host.clone().acquire_owned().await.unwrap(),
+            };
+            connector.probe(ip, port, permits);
+        }
+        let _all_done = semaphore.acquire_many(4).await.unwrap();
+        tokio::task::spawn_blocking(move || drop(connector))
+            .await
+            .unwrap();
+
+        let mut results = Vec::new();
+        while let Some(result) = result_rx.recv().await {
+            results.push(result);
+        }
+        results.sort();
+        let mut expected = vec![
+            (IpAddr::from([127, 0, 0, 1]), open_port, true),
+            (IpAddr::from([127, 0, 0, 1]), closed_port, false),
+        ];
+        expected.sort();
+        assert_eq!(results, expected);
+    }
+}
@@ -0,0 +1,136 @@
+//! `--wal-checkpoint-secs`: checkpoint the WAL in the background so long
+//! loop-mode runs do not let it grow without bound.
+//!
+//! SQLite's autocheckpoint only runs when a commit crosses the threshold and
+//! never shrinks the file, and a checkpoint cannot reset the WAL while
+//! frames are still being appended. Every interval the task copies back
+//! what it can (PASSIVE); once the WAL file passes `--wal-truncate-mb`, or
+//! no frames were written since the previous tick, it truncates the file
+//! to zero bytes. Checkpoints share the connection lock with the DB writer,
+//! so they land between its batches rather than inside one.
+
+use crate::cli::Args;
+use crate::dao::SqliteDB;
+use anyhow::Result;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+#[derive(Debug, Clone, Copy)]
+pub struct WalCheckpointer {
+    interval: Duration,
+    truncate_bytes: u64,
+}
+
+impl WalCheckpointer {
+    pub fn new(interval: Duration, truncate_bytes: u64) -> Self {
+        Self {
+            interval,
+            truncate_bytes,
+        }
+    }
+
+    pub fn from_args(args: &Args) -> Self {
+        Self::new(
+            Duration::from_secs(args.wal_checkpoint_secs),
+            args.wal_truncate_mb * 1024 * 1024,
+        )
+    }
+
+    /// Checkpoint `db` every interval until the task is aborted.
+    pub fn spawn(self, db: SqliteDB) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick fires immediately; the startup already truncated
+            // a stale WAL.
+            ticker.tick().await;
+            let mut last_frames = None;
+            loop {
+                ticker.tick().await;
+                let db = db.clone();
+                // A TRUNCATE may wait out the busy timeout; keep it off the
+                // async workers.
+                let checkpointed =
+                    tokio::task::spawn_blocking(move || self.tick(&db, last_frames)).await;
+                match checkpointed {
+                    Ok(Ok(frames)) => last_frames = frames,
+                    Ok(Err(e)) => error!("WAL checkpoint failed: {}", e),
+                    Err(e) => error!("WAL checkpoint task failed: {}", e),
+                }
+            }
+        })
+    }
+
+    /// One checkpoint pass. `last_frames` is the WAL frame count the
+    /// previous pass left behind; returns the count for the next one.
+    fn tick(&self, db: &SqliteDB, last_frames: Option<i64>) -> Result<Option<i64>> {
+        let wal_bytes = db.wal_bytes()?.unwrap_or(0);
+        if wal_bytes == 0 {
+            return Ok(None);
+        }
+        let passive = db.checkpoint_wal_frames(false)?;
+        let idle = !passive.busy
+            && passive.checkpointed_frames == passive.log_frames
+            && last_frames == Some(passive.log_frames);
+        let oversized = wal_bytes >= self.truncate_bytes;
+        if !idle && !oversized {
+            debug!(
+                "WAL checkpoint: {} of {} frames copied back",
+                passive.checkpointed_frames, passive.log_frames
+            );
+            return Ok(Some(passive.log_frames));
+        }
+
+        let truncated = db.checkpoint_wal_frames(true)?;
+        if truncated.busy {
+            warn!(
+                "WAL truncation blocked by another connection; WAL is {:.1} MiB",
+                wal_bytes as f64 / (1024.0 * 1024.0)
+            );
+        } else if oversized {
+            info!(
+                "Truncated WAL of {:.1} MiB",
+                wal_bytes as f64 / (1024.0 * 1024.0)
+            );
+        } else {
+            debug!("Truncated idle WAL of {} bytes", wal_bytes);
+        }
+        Ok(Some(truncated.log_frames))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tick_truncates_idle_and_oversized_wal() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SqliteDB::new(dir.path().join("wal.db").to_str().unwrap()).unwrap();
+        let write = |port| {
+            db.bulk_update_port_status(vec![("192.0.2.1".parse().unwrap(), port, true)], 1)
+                .unwrap()
+        };
+        let checkpointer = WalCheckpointer::new(Duration::from_secs(1), u64::MAX);
+
+        write(80);
+        let frames = checkpointer.tick(&db, None).unwrap();
+        assert!(frames.unwrap() > 0);
+        assert!(db.wal_bytes().unwrap().unwrap() > 0);
+
+        // Written to since the last pass: copied back, not truncated.
+        write(443);
+        let frames = checkpointer.tick(&db, frames).unwrap();
+        assert!(db.wal_bytes().unwrap().unwrap() > 0);
+
+        // Nothing written since: the WAL is reset.
+        assert_eq!(checkpointer.tick(&db, frames).unwrap(), Some(0));
+        assert_eq!(db.wal_bytes().unwrap(), Some(0));
+
+        write(8080);
+        let oversized = WalCheckpointer::new(Duration::from_secs(1), 1);
+        assert_eq!(oversized.tick(&db, None).unwrap(), Some(0));
+        assert_eq!(db.wal_bytes().unwrap(), Some(0));
+    }
+}
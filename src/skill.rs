@@ -1,11 +1,11 @@
 use crate::dao::SqliteDB;
 use crate::model::{IpRange, IpServiceSummary, ServiceInfo};
-use crate::service::optimized_scanner::{OptimizedScanner, OptimizedScannerConfig, PortState};
-use crate::service::ServiceProber;
+use crate::service::{ConScanner, ConScannerConfig, PortState, ServiceProber};
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
 use std::time::Instant;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PortResult {
@@ -44,7 +44,6 @@ pub struct ScanConfig {
     pub timeout_ms: u64,
     pub concurrency: usize,
     pub max_rate: u64,
-    pub adaptive_timeout: bool,
 }
 
 impl Default for ScanConfig {
@@ -53,7 +52,6 @@ impl Default for ScanConfig {
             timeout_ms: 500,
             concurrency: 500,
             max_rate: 50000,
-            adaptive_timeout: true,
         }
     }
 }
@@ -63,6 +61,9 @@ pub const COMMON_PORTS: &[u16] = &[
     5900, 6379, 8080, 8443, 9200, 27017,
 ];
 
+/// Ports probed at once per host in range scans, as `--host-concurrency`.
+const RANGE_HOST_CONCURRENCY: usize = 4;
+
 pub struct IpScanSkill {
     config: ScanConfig,
 }
@@ -78,13 +79,24 @@ impl IpScanSkill {
         Ok(Self { config })
     }
 
-    fn make_scanner_config(&self) -> OptimizedScannerConfig {
-        OptimizedScannerConfig {
+    /// A single host is probed up to `concurrency` ports at a time.
+    fn make_scanner_config(&self, host_limit: usize) -> ConScannerConfig {
+        ConScannerConfig {
             timeout_ms: self.config.timeout_ms,
             concurrent_limit: self.config.concurrency,
+            host_concurrent_limit: host_limit,
+            result_buffer: 10000,
+            db_batch_size: 500,
+            flush_interval_ms: 500,
+            adaptive_batching: false,
             max_rate: self.config.max_rate,
-            adaptive_timeout: self.config.adaptive_timeout,
-            ..Default::default()
+            rate_window_secs: 1,
+            rate_burst: 0,
+            hooks: None,
+            cancel: CancellationToken::new(),
+            io_uring: false,
+            source_ports: None,
+            banner_grab_ms: 0,
         }
     }
 
@@ -96,7 +108,7 @@ impl IpScanSkill {
     }
 
     fn parse_ports(ports_str: &str) -> Result<Vec<u16>> {
-        crate::model::parse_port_range(ports_str).map_err(|e| anyhow!(e))
+        crate::model::parse_port_range(ports_str, &Default::default()).map_err(|e| anyhow!(e))
     }
 
     pub async fn scan_single(&self, target: &str, ports: &str) -> Result<HostScanResult> {
@@ -105,8 +117,10 @@ impl IpScanSkill {
         let ports_vec = Self::parse_ports(ports)?;
         let db = SqliteDB::new(":memory:")?;
 
-        let scanner = OptimizedScanner::new(db, 1, self.make_scanner_config());
-        let results = scanner.scan_batch_ports_classified(ip, &ports_vec).await?;
+        let scanner = ConScanner::new(db, 1, self.make_scanner_config(self.config.concurrency));
+        // States come back directly and the in-memory database is thrown
+        // away, so the scanner is dropped without waiting for its writer.
+        let results = scanner.scan_ip_ports_classified(ip, &ports_vec).await?;
 
         let mut open_ports = Vec::new();
         let mut closed_ports = Vec::new();
@@ -156,7 +170,11 @@ impl IpScanSkill {
         let ports_vec = Self::parse_ports(ports)?;
         let db = SqliteDB::new(":memory:")?;
 
-        let scanner = OptimizedScanner::new(db.clone(), 1, self.make_scanner_config());
+        let scanner = ConScanner::new(
+            db.clone(),
+            1,
+            self.make_scanner_config(RANGE_HOST_CONCURRENCY),
+        );
 
         let (tx, rx) = tokio::sync::mpsc::channel(self.config.concurrency * 2);
 
@@ -177,11 +195,12 @@ impl IpScanSkill {
         };
 
         scanner
-            .run_high_performance(rx, ports_vec, progress_callback)
+            .run_pipeline(rx, ports_vec, progress_callback)
             .await?;
+        scanner.finish().await;
 
         let scan_results = db
-            .get_results_by_round(1)
+            .get_all_results_by_round(1)
             .map_err(|e| anyhow!("Failed to query scan results: {}", e))?;
 
         let mut host_map: std::collections::HashMap<String, HostScanResult> =
@@ -250,7 +269,6 @@ impl IpScanSkill {
             timeout_ms: 200,
             concurrency: 1000,
             max_rate: 200000,
-            adaptive_timeout: true,
         };
         let skill = Self::with_config(quick_config)?;
         let ports_str = COMMON_PORTS
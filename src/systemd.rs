@@ -0,0 +1,94 @@
+//! systemd `sd_notify` integration for `Type=notify` units.
+//!
+//! Every function is a cheap no-op when the process was not started by
+//! systemd (no `NOTIFY_SOCKET`) or on non-Unix platforms, so call sites do not
+//! need to guard on the environment.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+struct Watchdog {
+    started: Instant,
+    interval: Duration,
+    last_ping_ms: AtomicU64,
+}
+
+static WATCHDOG: OnceLock<Option<Watchdog>> = OnceLock::new();
+
+fn watchdog() -> Option<&'static Watchdog> {
+    WATCHDOG
+        .get_or_init(|| {
+            watchdog_usec().map(|usec| Watchdog {
+                started: Instant::now(),
+                interval: Duration::from_micros(usec),
+                last_ping_ms: AtomicU64::new(0),
+            })
+        })
+        .as_ref()
+}
+
+#[cfg(unix)]
+fn watchdog_usec() -> Option<u64> {
+    let mut usec = 0;
+    (sd_notify::watchdog_enabled(false, &mut usec) && usec > 0).then_some(usec)
+}
+
+#[cfg(not(unix))]
+fn watchdog_usec() -> Option<u64> {
+    None
+}
+
+/// Tell systemd the service finished starting (database open, API bound).
+pub fn notify_ready() {
+    #[cfg(unix)]
+    send(&[sd_notify::NotifyState::Ready]);
+}
+
+/// Tell systemd a graceful shutdown has begun.
+pub fn notify_stopping() {
+    #[cfg(unix)]
+    send(&[sd_notify::NotifyState::Stopping]);
+}
+
+/// Update the free-form status line shown by `systemctl status`.
+pub fn notify_status(status: &str) {
+    #[cfg(unix)]
+    send(&[sd_notify::NotifyState::Status(status)]);
+    #[cfg(not(unix))]
+    let _ = status;
+}
+
+/// The `WatchdogSec=` configured for this unit, if any.
+pub fn watchdog_interval() -> Option<Duration> {
+    watchdog().map(|wd| wd.interval)
+}
+
+/// Record liveness. Safe to call from hot paths: a `WATCHDOG=1` datagram is
+/// only sent once half of the watchdog interval has elapsed since the last one.
+pub fn heartbeat() {
+    let Some(wd) = watchdog() else {
+        return;
+    };
+    let now_ms = wd.started.elapsed().as_millis() as u64;
+    let last_ms = wd.last_ping_ms.load(Ordering::Relaxed);
+    let half_interval_ms = (wd.interval.as_millis() as u64 / 2).max(1);
+    if now_ms.saturating_sub(last_ms) < half_interval_ms {
+        return;
+    }
+    if wd
+        .last_ping_ms
+        .compare_exchange(last_ms, now_ms, Ordering::Relaxed, Ordering::Relaxed)
+        .is_ok()
+    {
+        #[cfg(unix)]
+        send(&[sd_notify::NotifyState::Watchdog]);
+    }
+}
+
+#[cfg(unix)]
+fn send(state: &[sd_notify::NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        tracing::debug!("sd_notify failed: {}", e);
+    }
+}